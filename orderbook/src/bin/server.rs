@@ -0,0 +1,39 @@
+//! Market data and order entry over one websocket:
+//! `cargo run -p orderbook --features server --bin server -- [config.toml]`.
+//!
+//! Hosts the markets of the config, see `ExchangeConfig` for the format.
+//! Clients connect to the feed address, get a snapshot of every market and
+//! then its depth updates and trades, and send orders as text frames:
+//!
+//! ```text
+//! {"id": 1, "method": "POST", "path": "/orders",
+//!  "body": {"account": 1, "market": "BTC-USD", "side": "bid", "price": "100", "qty": "1"}}
+//! {"id": 2, "method": "DELETE", "path": "/orders/<order_id>?market=BTC-USD"}
+//! ```
+//!
+//! each answered by `{"reply": <id>, "status": .., "body": ..}`.
+
+use std::env;
+use std::error::Error;
+
+use orderbook::net::config::ExchangeConfig;
+use orderbook::net::exchange::Exchange;
+use orderbook::net::server::Server;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let path = env::args()
+        .nth(1)
+        .unwrap_or_else(|| String::from("paper-net/exchange.toml"));
+    let config = ExchangeConfig::load(&path)?;
+
+    let server = Server::bind(&config.server)?;
+    let exchange = Exchange::new(&config)?;
+    eprintln!(
+        "{} markets on ws://{}, REST on http://{}",
+        config.markets.len(),
+        server.feed_addr()?,
+        server.rest_addr()?
+    );
+    server.run(exchange)?;
+    Ok(())
+}
@@ -1,8 +0,0 @@
-
-pub mod domain;
-pub mod orderbook;
-pub mod order_queues;
-pub mod orders;
-
-// private
-mod validation;
\ No newline at end of file
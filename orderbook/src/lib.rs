@@ -1,3 +1,13 @@
+//! Facade over the workspace crates, keeping the original module paths.
+//!
+//! Embedders needing only matching can depend on `paper-core` directly.
 
-pub mod guid;
-pub mod sequential;
\ No newline at end of file
+pub use paper_core::sequential;
+
+#[cfg(feature = "server")]
+pub use paper_net as net;
+
+pub mod guid {
+    pub use paper_core::guid::*;
+    pub use paper_serde::capture;
+}
@@ -0,0 +1,399 @@
+use bigdecimal::BigDecimal;
+use criterion::{criterion_group, criterion_main, Criterion};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::BTreeMap;
+use std::hint::black_box;
+use std::time::{Duration, Instant, SystemTime};
+use uuid::Uuid;
+
+use paper_core::guid;
+use paper_core::sequential;
+
+/// touch prices sit around 100.00, prices are in cents
+const MID_TICKS: i64 = 10_000;
+/// mean distance of resting orders from the mid price, in ticks
+const MEAN_DISTANCE: f64 = 25.0;
+/// mean order size past the minimum of one
+const MEAN_EXTRA_QTY: f64 = 4.0;
+/// resting orders a market sweep takes out
+const SWEEP_ORDERS: usize = 50;
+const SEED: u64 = 7;
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+enum Asset {
+    Usd,
+    Btc,
+}
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+enum Side {
+    Bid,
+    Ask,
+}
+
+/// Limit order that rests on arrival
+#[derive(Debug, Copy, Clone)]
+struct Quote {
+    side: Side,
+    ticks: i64,
+    qty: u32,
+}
+
+impl Quote {
+    /// Order sizes and distances from the mid price are exponential: most
+    /// orders are small and close to the touch, a few are large or deep.
+    fn random(rng: &mut StdRng, side: Option<Side>) -> Self {
+        let side = side.unwrap_or_else(|| if rng.gen() { Side::Bid } else { Side::Ask });
+        let distance = 1 + exponential(rng, MEAN_DISTANCE) as i64;
+        let ticks = match side {
+            Side::Bid => MID_TICKS - distance,
+            Side::Ask => MID_TICKS + distance,
+        };
+        let qty = 1 + exponential(rng, MEAN_EXTRA_QTY) as u32;
+        Quote { side, ticks, qty }
+    }
+}
+
+fn exponential(rng: &mut StdRng, mean: f64) -> f64 {
+    -(1.0 - rng.gen::<f64>()).ln() * mean
+}
+
+/// Book under test, the same flows run against every implementation
+trait Book {
+    type Id: Copy;
+    /// resting orders the flows run against
+    const DEPTH: usize;
+    /// new orders a book takes before it runs out of IDs
+    const ID_CAPACITY: usize;
+
+    fn new() -> Self;
+    fn limit(&mut self, quote: &Quote, ts: SystemTime) -> Self::Id;
+    fn cancel(&mut self, id: Self::Id, side: Side);
+    fn amend(&mut self, id: Self::Id, quote: &Quote, ts: SystemTime);
+    fn market(&mut self, side: Side, qty: u32, ts: SystemTime);
+}
+
+struct GuidBook(guid::orderbook::Orderbook<Asset>);
+
+fn guid_side(side: Side) -> guid::domain::OrderSide {
+    match side {
+        Side::Bid => guid::domain::OrderSide::Bid,
+        Side::Ask => guid::domain::OrderSide::Ask,
+    }
+}
+
+impl Book for GuidBook {
+    type Id = Uuid;
+    const DEPTH: usize = 10_000;
+    const ID_CAPACITY: usize = usize::MAX;
+
+    fn new() -> Self {
+        GuidBook(guid::orderbook::Orderbook::new(Asset::Btc, Asset::Usd))
+    }
+
+    fn limit(&mut self, quote: &Quote, ts: SystemTime) -> Uuid {
+        let request = guid::orders::new_limit_order_request(
+            Asset::Btc,
+            Asset::Usd,
+            guid_side(quote.side),
+            BigDecimal::new(quote.ticks.into(), 2),
+            BigDecimal::from(quote.qty),
+            ts,
+        );
+        let order_id = request.order_id();
+        black_box(self.0.process_order(request));
+        order_id
+    }
+
+    fn cancel(&mut self, id: Uuid, side: Side) {
+        let request = guid::orders::limit_order_cancel_request(id, guid_side(side));
+        black_box(self.0.process_order(request));
+    }
+
+    fn amend(&mut self, id: Uuid, quote: &Quote, ts: SystemTime) {
+        let request = guid::orders::amend_order_request(
+            id,
+            guid_side(quote.side),
+            BigDecimal::new(quote.ticks.into(), 2),
+            BigDecimal::from(quote.qty),
+            ts,
+        );
+        black_box(self.0.process_order(request));
+    }
+
+    fn market(&mut self, side: Side, qty: u32, ts: SystemTime) {
+        let request = guid::orders::new_market_order_request(
+            Asset::Btc,
+            Asset::Usd,
+            guid_side(side),
+            BigDecimal::from(qty),
+            ts,
+        );
+        black_box(self.0.process_order(request));
+    }
+}
+
+struct SequentialBook(sequential::orderbook::Orderbook<Asset>);
+
+fn sequential_side(side: Side) -> sequential::domain::OrderSide {
+    match side {
+        Side::Bid => sequential::domain::OrderSide::Bid,
+        Side::Ask => sequential::domain::OrderSide::Ask,
+    }
+}
+
+impl Book for SequentialBook {
+    type Id = u64;
+    const DEPTH: usize = 500;
+    // IDs run from 1 to 1000 and then wrap around
+    const ID_CAPACITY: usize = 999;
+
+    fn new() -> Self {
+        SequentialBook(sequential::orderbook::Orderbook::new(
+            Asset::Btc,
+            Asset::Usd,
+        ))
+    }
+
+    fn limit(&mut self, quote: &Quote, ts: SystemTime) -> u64 {
+        let request = sequential::orders::new_limit_order_request(
+            Asset::Btc,
+            Asset::Usd,
+            sequential_side(quote.side),
+            quote.ticks as f64 / 100.0,
+            quote.qty as f64,
+            ts,
+        );
+        match self.0.process_order(request).first() {
+            Some(Ok(sequential::orderbook::Success::Accepted { id, .. })) => *id,
+            _ => panic!("limit order not accepted"),
+        }
+    }
+
+    fn cancel(&mut self, id: u64, side: Side) {
+        let request = sequential::orders::limit_order_cancel_request(id, sequential_side(side));
+        black_box(self.0.process_order(request));
+    }
+
+    fn amend(&mut self, id: u64, quote: &Quote, ts: SystemTime) {
+        let request = sequential::orders::amend_order_request(
+            id,
+            sequential_side(quote.side),
+            quote.ticks as f64 / 100.0,
+            quote.qty as f64,
+            ts,
+        );
+        black_box(self.0.process_order(request));
+    }
+
+    fn market(&mut self, side: Side, qty: u32, ts: SystemTime) {
+        let request = sequential::orders::new_market_order_request(
+            Asset::Btc,
+            Asset::Usd,
+            sequential_side(side),
+            qty as f64,
+            ts,
+        );
+        black_box(self.0.process_order(request));
+    }
+}
+
+/// Book with resting orders on both sides kept at the same depth: only the
+/// measured request is timed, the upkeep around it is not
+struct Resting<B: Book> {
+    rng: StdRng,
+    book: B,
+    orders: Vec<(B::Id, Quote)>,
+    /// new orders taken since the book was built
+    taken: usize,
+    /// timestamps increase one microsecond per request so that orders at the
+    /// same price queue in arrival order
+    clock: u64,
+}
+
+impl<B: Book> Resting<B> {
+    fn new() -> Self {
+        let mut resting = Resting {
+            rng: StdRng::seed_from_u64(SEED),
+            book: B::new(),
+            orders: vec![],
+            taken: 0,
+            clock: 0,
+        };
+        resting.rebuild();
+        resting
+    }
+
+    fn tick(&mut self) -> SystemTime {
+        self.clock += 1;
+        SystemTime::UNIX_EPOCH + Duration::from_micros(self.clock)
+    }
+
+    fn rebuild(&mut self) {
+        self.book = B::new();
+        self.orders.clear();
+        self.taken = 0;
+        for _ in 0..B::DEPTH {
+            let quote = Quote::random(&mut self.rng, None);
+            let ts = self.tick();
+            let id = self.book.limit(&quote, ts);
+            self.orders.push((id, quote));
+        }
+        self.taken = B::DEPTH;
+    }
+
+    /// Make room for `orders` more new orders
+    fn reserve(&mut self, orders: usize) {
+        if self.taken + orders > B::ID_CAPACITY {
+            self.rebuild();
+        }
+        self.taken += orders;
+    }
+
+    /// New limit order resting in the book, cancelled again untimed
+    fn limit_insert(&mut self, iters: u64) -> Duration {
+        let mut elapsed = Duration::ZERO;
+        for _ in 0..iters {
+            self.reserve(1);
+            let quote = Quote::random(&mut self.rng, None);
+            let ts = self.tick();
+            let start = Instant::now();
+            let id = self.book.limit(&quote, ts);
+            elapsed += start.elapsed();
+            self.book.cancel(id, quote.side);
+        }
+        elapsed
+    }
+
+    /// Cancel of a random resting order, replaced untimed
+    fn cancel(&mut self, iters: u64) -> Duration {
+        let mut elapsed = Duration::ZERO;
+        for _ in 0..iters {
+            self.reserve(1);
+            let i = self.rng.gen_range(0..self.orders.len());
+            let (id, quote) = self.orders[i];
+            let start = Instant::now();
+            self.book.cancel(id, quote.side);
+            elapsed += start.elapsed();
+
+            let quote = Quote::random(&mut self.rng, None);
+            let ts = self.tick();
+            self.orders[i] = (self.book.limit(&quote, ts), quote);
+        }
+        elapsed
+    }
+
+    /// Random resting order moved to another price and size on its side
+    fn amend(&mut self, iters: u64) -> Duration {
+        let mut elapsed = Duration::ZERO;
+        for _ in 0..iters {
+            let i = self.rng.gen_range(0..self.orders.len());
+            let (id, quote) = self.orders[i];
+            let amended = Quote::random(&mut self.rng, Some(quote.side));
+            let ts = self.tick();
+            let start = Instant::now();
+            self.book.amend(id, &amended, ts);
+            elapsed += start.elapsed();
+            self.orders[i] = (id, amended);
+        }
+        elapsed
+    }
+}
+
+/// Asks a market bid sweeps the best of, replaced untimed after each sweep.
+///
+/// Mirrors the ask queue in price-time order to size every sweep to take
+/// out exactly `SWEEP_ORDERS` orders.
+struct AskLadder<B: Book> {
+    rng: StdRng,
+    book: B,
+    /// (ticks, arrival) to the order
+    asks: BTreeMap<(i64, u64), (B::Id, Quote)>,
+    taken: usize,
+    clock: u64,
+}
+
+impl<B: Book> AskLadder<B> {
+    fn new() -> Self {
+        let mut ladder = AskLadder {
+            rng: StdRng::seed_from_u64(SEED),
+            book: B::new(),
+            asks: BTreeMap::new(),
+            taken: 0,
+            clock: 0,
+        };
+        ladder.rebuild();
+        ladder
+    }
+
+    fn tick(&mut self) -> SystemTime {
+        self.clock += 1;
+        SystemTime::UNIX_EPOCH + Duration::from_micros(self.clock)
+    }
+
+    fn rest(&mut self, quote: Quote) {
+        let ts = self.tick();
+        let id = self.book.limit(&quote, ts);
+        self.asks.insert((quote.ticks, self.clock), (id, quote));
+        self.taken += 1;
+    }
+
+    fn rebuild(&mut self) {
+        self.book = B::new();
+        self.asks.clear();
+        self.taken = 0;
+        for _ in 0..B::DEPTH {
+            let quote = Quote::random(&mut self.rng, Some(Side::Ask));
+            self.rest(quote);
+        }
+    }
+
+    fn sweep(&mut self, iters: u64) -> Duration {
+        let mut elapsed = Duration::ZERO;
+        for _ in 0..iters {
+            if self.taken + SWEEP_ORDERS + 1 > B::ID_CAPACITY {
+                self.rebuild();
+            }
+            let swept: Vec<(i64, u64)> = self.asks.keys().take(SWEEP_ORDERS).cloned().collect();
+            let qty: u32 = swept.iter().map(|key| self.asks[key].1.qty).sum();
+            let ts = self.tick();
+            self.taken += 1;
+            let start = Instant::now();
+            self.book.market(Side::Bid, qty, ts);
+            elapsed += start.elapsed();
+
+            for key in swept {
+                let (_, quote) = self.asks.remove(&key).unwrap();
+                self.rest(quote);
+            }
+        }
+        elapsed
+    }
+}
+
+fn matching<B: Book>(c: &mut Criterion, name: &str) {
+    let mut group = c.benchmark_group(name);
+    let mut resting = Resting::<B>::new();
+    group.bench_function("limit_insert", |b| {
+        b.iter_custom(|iters| resting.limit_insert(iters))
+    });
+    group.bench_function("cancel", |b| b.iter_custom(|iters| resting.cancel(iters)));
+    group.bench_function("amend", |b| b.iter_custom(|iters| resting.amend(iters)));
+    let mut ladder = AskLadder::<B>::new();
+    group.bench_function("market_sweep", |b| {
+        b.iter_custom(|iters| ladder.sweep(iters))
+    });
+    group.finish();
+}
+
+fn guid_book(c: &mut Criterion) {
+    matching::<GuidBook>(c, "guid");
+}
+
+fn sequential_book(c: &mut Criterion) {
+    matching::<SequentialBook>(c, "sequential");
+}
+
+criterion_group!(benches, guid_book, sequential_book);
+criterion_main!(benches);
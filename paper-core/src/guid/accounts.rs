@@ -0,0 +1,580 @@
+use bigdecimal::{BigDecimal, Zero};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+use uuid::Uuid;
+
+use super::domain::{AccountId, OrderSide};
+use super::engine::Symbol;
+use super::orderbook::{OrderProcessingResult, Success};
+
+/// Resting order of an account
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpenOrder<Asset> {
+    pub order_id: Uuid,
+    pub symbol: Symbol<Asset>,
+    pub side: OrderSide,
+    pub price: BigDecimal,
+    pub qty: BigDecimal,
+}
+
+/// Net position of an account in one market and its P&L, in the price asset
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstrumentPnl<Asset> {
+    pub symbol: Symbol<Asset>,
+    /// order asset bought (positive) or sold (negative)
+    pub qty: BigDecimal,
+    /// average entry price of the open position, None when flat
+    pub avg_price: Option<BigDecimal>,
+    /// gains of closing fills against the average entry price
+    pub realized: BigDecimal,
+    /// gains of the open position at the mark price, None when flat or
+    /// the market has no mark
+    pub unrealized: Option<BigDecimal>,
+}
+
+/// Per-account view for risk and margin checks
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccountSummary<Asset>
+where
+    Asset: Eq + Hash,
+{
+    pub account: AccountId,
+    pub open_orders: Vec<OpenOrder<Asset>>,
+    /// price times quantity of open orders, by price asset
+    pub resting_notional: HashMap<Asset, BigDecimal>,
+    /// net amount bought (positive) or sold (negative) of every asset traded
+    pub positions: HashMap<Asset, BigDecimal>,
+    /// notional of fills since the day started, by price asset
+    pub fill_volume: HashMap<Asset, BigDecimal>,
+    /// deposits plus positions less fees, of every asset the account holds
+    pub balances: HashMap<Asset, BigDecimal>,
+}
+
+/// Attributes orders to accounts and follows their fills.
+///
+/// Like other result consumers it sees fills only, so orders have to be
+/// registered up front. Attribution is dropped once an order is done.
+#[derive(Debug)]
+pub struct AccountTracker<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq + Hash,
+{
+    owners: HashMap<Uuid, (AccountId, Symbol<Asset>)>,
+    positions: HashMap<(AccountId, Asset), BigDecimal>,
+    fill_volume: HashMap<(AccountId, Asset), BigDecimal>,
+    balances: HashMap<(AccountId, Asset), BigDecimal>,
+    /// net order asset quantity per market, in order of the first fill
+    instruments: HashMap<AccountId, Vec<(Symbol<Asset>, BigDecimal)>>,
+    /// average entry price and realized P&L per market
+    costs: HashMap<(AccountId, Symbol<Asset>), (BigDecimal, BigDecimal)>,
+    /// amount of an asset each resting order holds, see `set_hold`
+    holds: HashMap<Uuid, (AccountId, Asset, BigDecimal)>,
+    /// sum of the holds per account and asset
+    held: HashMap<(AccountId, Asset), BigDecimal>,
+}
+
+impl<Asset> Default for AccountTracker<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq + Hash,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Asset> AccountTracker<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq + Hash,
+{
+    pub fn new() -> Self {
+        AccountTracker {
+            owners: HashMap::new(),
+            positions: HashMap::new(),
+            fill_volume: HashMap::new(),
+            balances: HashMap::new(),
+            instruments: HashMap::new(),
+            costs: HashMap::new(),
+            holds: HashMap::new(),
+            held: HashMap::new(),
+        }
+    }
+
+    /// Add `amount` of the asset to the balance of the account, negative to
+    /// take it away
+    pub fn credit(&mut self, account: AccountId, asset: Asset, amount: &BigDecimal) {
+        *self
+            .balances
+            .entry((account, asset))
+            .or_insert_with(BigDecimal::zero) += amount;
+    }
+
+    /// Amount of the asset the account holds, open orders included
+    pub fn balance(&self, account: AccountId, asset: Asset) -> BigDecimal {
+        self.balances
+            .get(&(account, asset))
+            .cloned()
+            .unwrap_or_else(BigDecimal::zero)
+    }
+
+    pub fn register_order(&mut self, order_id: Uuid, account: AccountId, symbol: Symbol<Asset>) {
+        self.owners.insert(order_id, (account, symbol));
+    }
+
+    /// Drop attribution of an order not resting anywhere anymore
+    pub fn forget_order(&mut self, order_id: Uuid) {
+        self.owners.remove(&order_id);
+        self.set_hold(order_id, None);
+    }
+
+    /// Set the amount of an asset the registered order holds while it
+    /// rests, None once it holds nothing
+    pub fn set_hold(&mut self, order_id: Uuid, hold: Option<(Asset, BigDecimal)>) {
+        if let Some((account, asset, amount)) = self.holds.remove(&order_id) {
+            let held = self.held.get_mut(&(account, asset)).unwrap();
+            *held -= amount;
+            if held.is_zero() {
+                self.held.remove(&(account, asset));
+            }
+        }
+        let account = match self.owners.get(&order_id) {
+            Some((account, _)) => *account,
+            None => return,
+        };
+        if let Some((asset, amount)) = hold {
+            *self
+                .held
+                .entry((account, asset))
+                .or_insert_with(BigDecimal::zero) += &amount;
+            self.holds.insert(order_id, (account, asset, amount));
+        }
+    }
+
+    /// Amount of the asset the open orders of the account hold
+    pub fn held(&self, account: AccountId, asset: Asset) -> BigDecimal {
+        self.held
+            .get(&(account, asset))
+            .cloned()
+            .unwrap_or_else(BigDecimal::zero)
+    }
+
+    /// Account, asset and amount the order holds
+    pub fn hold(&self, order_id: Uuid) -> Option<(AccountId, Asset, &BigDecimal)> {
+        self.holds
+            .get(&order_id)
+            .map(|(account, asset, amount)| (*account, *asset, amount))
+    }
+
+    pub fn owner(&self, order_id: Uuid) -> Option<(AccountId, Symbol<Asset>)> {
+        self.owners.get(&order_id).copied()
+    }
+
+    /// Registered orders of the account, resting or not yet processed
+    pub fn orders_of(
+        &self,
+        account: AccountId,
+    ) -> impl Iterator<Item = (Uuid, Symbol<Asset>)> + '_ {
+        self.owners
+            .iter()
+            .filter(move |(_, (owner, _))| *owner == account)
+            .map(|(order_id, (_, symbol))| (*order_id, *symbol))
+    }
+
+    pub fn on_results(&mut self, results: &OrderProcessingResult<Asset>) {
+        for result in results {
+            match result {
+                Ok(Success::Filled {
+                    order_id,
+                    side,
+                    price,
+                    qty,
+                    fee,
+                    fee_asset,
+                    ..
+                }) => {
+                    self.record_fill(*order_id, *side, price, qty);
+                    self.charge_fee(*order_id, fee.as_ref().zip(*fee_asset));
+                    self.owners.remove(order_id);
+                }
+                Ok(Success::PartiallyFilled {
+                    order_id,
+                    side,
+                    price,
+                    qty,
+                    fee,
+                    fee_asset,
+                    ..
+                }) => {
+                    self.record_fill(*order_id, *side, price, qty);
+                    self.charge_fee(*order_id, fee.as_ref().zip(*fee_asset));
+                }
+                Ok(Success::Cancelled { order_id, .. })
+                | Ok(Success::Expired { order_id, .. })
+                | Ok(Success::DustCancelled { order_id, .. }) => {
+                    self.owners.remove(order_id);
+                }
+                Ok(Success::SelfTradePrevented {
+                    order_id,
+                    remaining,
+                    ..
+                }) if remaining.is_zero() => {
+                    self.owners.remove(order_id);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Net quantity bought (positive) or sold (negative) in every market the
+    /// account traded, in order of its first fill there
+    pub fn instrument_positions(&self, account: AccountId) -> &[(Symbol<Asset>, BigDecimal)] {
+        self.instruments
+            .get(&account)
+            .map_or(&[], |positions| positions.as_slice())
+    }
+
+    /// Positions of the account per market with their P&L, unrealized P&L
+    /// taken at the price `mark` gives for the market
+    pub fn instrument_pnl<F>(&self, account: AccountId, mark: F) -> Vec<InstrumentPnl<Asset>>
+    where
+        F: Fn(Symbol<Asset>) -> Option<BigDecimal>,
+    {
+        self.instrument_positions(account)
+            .iter()
+            .map(|(symbol, qty)| {
+                let (avg_price, realized) = self.costs[&(account, *symbol)].clone();
+                let avg_price = Some(avg_price).filter(|_| !qty.is_zero());
+                let unrealized = avg_price
+                    .as_ref()
+                    .zip(mark(*symbol))
+                    .map(|(avg_price, mark)| (mark - avg_price) * qty);
+                InstrumentPnl {
+                    symbol: *symbol,
+                    qty: qty.clone(),
+                    avg_price,
+                    realized,
+                    unrealized,
+                }
+            })
+            .collect()
+    }
+
+    /// Start a new trading day, resetting fill volumes
+    pub fn roll_day(&mut self) {
+        self.fill_volume.clear();
+    }
+
+    /// Summary of the account with `open_orders` looked up by the caller
+    pub fn summary(
+        &self,
+        account: AccountId,
+        open_orders: Vec<OpenOrder<Asset>>,
+    ) -> AccountSummary<Asset> {
+        let mut resting_notional: HashMap<Asset, BigDecimal> = HashMap::new();
+        for order in &open_orders {
+            *resting_notional
+                .entry(order.symbol.1)
+                .or_insert_with(BigDecimal::zero) += &order.price * &order.qty;
+        }
+
+        AccountSummary {
+            account,
+            open_orders,
+            resting_notional,
+            positions: collect_for(&self.positions, account),
+            fill_volume: collect_for(&self.fill_volume, account),
+            balances: collect_for(&self.balances, account),
+        }
+    }
+
+    /* Helpers */
+
+    fn record_fill(
+        &mut self,
+        order_id: Uuid,
+        side: OrderSide,
+        price: &BigDecimal,
+        qty: &BigDecimal,
+    ) {
+        let (account, symbol) = match self.owners.get(&order_id) {
+            Some(owner) => *owner,
+            None => return,
+        };
+
+        let (order_asset, price_asset) = symbol;
+        let notional = price * qty;
+        let (order_delta, price_delta) = match side {
+            OrderSide::Bid => (qty.clone(), -notional.clone()),
+            OrderSide::Ask => (-qty, notional.clone()),
+        };
+        *self
+            .positions
+            .entry((account, order_asset))
+            .or_insert_with(BigDecimal::zero) += &order_delta;
+        *self
+            .positions
+            .entry((account, price_asset))
+            .or_insert_with(BigDecimal::zero) += &price_delta;
+        *self
+            .fill_volume
+            .entry((account, price_asset))
+            .or_insert_with(BigDecimal::zero) += notional;
+        self.credit(account, order_asset, &order_delta);
+        self.credit(account, price_asset, &price_delta);
+
+        let instruments = self.instruments.entry(account).or_default();
+        let position = match instruments.iter().position(|(traded, _)| *traded == symbol) {
+            Some(index) => &mut instruments[index].1,
+            None => {
+                instruments.push((symbol, BigDecimal::zero()));
+                &mut instruments.last_mut().unwrap().1
+            }
+        };
+        let (avg_price, realized) = self
+            .costs
+            .entry((account, symbol))
+            .or_insert_with(|| (BigDecimal::zero(), BigDecimal::zero()));
+        let held = position.abs();
+        let extends =
+            position.is_zero() || (*position > BigDecimal::zero()) == (side == OrderSide::Bid);
+        if extends {
+            *avg_price = (&*avg_price * &held + price * qty) / (&held + qty);
+        } else {
+            // closing fill, realizes P&L on the quantity it closes
+            let closed = held.clone().min(qty.clone());
+            let gain = match side {
+                OrderSide::Bid => &*avg_price - price,
+                OrderSide::Ask => price - &*avg_price,
+            };
+            *realized += gain * closed;
+            if *qty > held {
+                // flipped, the rest opens a position at the fill price
+                *avg_price = price.clone();
+            }
+        }
+        *position += &order_delta;
+    }
+
+    fn charge_fee(&mut self, order_id: Uuid, fee: Option<(&BigDecimal, Asset)>) {
+        if let (Some((account, _)), Some((fee, asset))) = (self.owners.get(&order_id), fee) {
+            let account = *account;
+            self.credit(account, asset, &-fee);
+        }
+    }
+}
+
+fn collect_for<Asset>(
+    amounts: &HashMap<(AccountId, Asset), BigDecimal>,
+    account: AccountId,
+) -> HashMap<Asset, BigDecimal>
+where
+    Asset: Copy + Eq + Hash,
+{
+    amounts
+        .iter()
+        .filter(|((owner, _), _)| *owner == account)
+        .map(|((_, asset), amount)| (*asset, amount.clone()))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::engine::MatchingEngine;
+    use super::super::errors::CODE_INSUFFICIENT_BALANCE;
+    use super::super::fees::{FeeCurrency, FeeEngine, FeeRates};
+    use super::super::orders;
+    use super::*;
+    use std::str::FromStr;
+    use std::time::SystemTime;
+
+    #[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
+    pub enum Asset {
+        USD,
+        BTC,
+    }
+
+    fn bigdec(num: &str) -> BigDecimal {
+        BigDecimal::from_str(num).unwrap()
+    }
+
+    fn limit(side: OrderSide, price: &str, qty: &str) -> orders::OrderRequest<Asset> {
+        orders::new_limit_order_request(
+            Asset::BTC,
+            Asset::USD,
+            side,
+            bigdec(price),
+            bigdec(qty),
+            SystemTime::now(),
+        )
+    }
+
+    #[test]
+    fn account_summary() {
+        let symbol = (Asset::BTC, Asset::USD);
+        let mut engine = MatchingEngine::new();
+        engine.add_book(Asset::BTC, Asset::USD);
+
+        engine.submit_for(1, symbol, limit(OrderSide::Ask, "100", "2"));
+        engine.submit_for(1, symbol, limit(OrderSide::Bid, "90", "1"));
+        // account 2 takes half of the ask
+        engine.submit_for(2, symbol, limit(OrderSide::Bid, "100", "1"));
+
+        let summary = engine.account_summary(1);
+        assert_eq!(summary.open_orders.len(), 2);
+        assert_eq!(summary.resting_notional[&Asset::USD], bigdec("190"));
+        assert_eq!(summary.positions[&Asset::BTC], bigdec("-1"));
+        assert_eq!(summary.positions[&Asset::USD], bigdec("100"));
+        assert_eq!(summary.fill_volume[&Asset::USD], bigdec("100"));
+
+        let summary = engine.account_summary(2);
+        assert!(summary.open_orders.is_empty());
+        assert_eq!(summary.positions[&Asset::BTC], bigdec("1"));
+
+        engine.roll_day();
+        assert!(engine.account_summary(1).fill_volume.is_empty());
+        assert!(engine.account_summary(3).positions.is_empty());
+    }
+
+    #[test]
+    fn pnl() {
+        let symbol = (Asset::BTC, Asset::USD);
+        let mut engine = MatchingEngine::new();
+        engine.add_book(Asset::BTC, Asset::USD);
+        let trade =
+            |engine: &mut MatchingEngine<Asset>, side: OrderSide, price: &str, qty: &str| {
+                engine.submit_for(9, symbol, limit(side.opposite(), price, qty));
+                engine.submit_for(1, symbol, limit(side, price, qty));
+            };
+
+        trade(&mut engine, OrderSide::Bid, "100", "2");
+        trade(&mut engine, OrderSide::Bid, "110", "2");
+        // closes 3 of 4 bought at 105 on average
+        trade(&mut engine, OrderSide::Ask, "120", "3");
+        engine.submit_for(9, symbol, limit(OrderSide::Bid, "114", "1"));
+        engine.submit_for(9, symbol, limit(OrderSide::Ask, "116", "1"));
+
+        let pnl = engine.pnl(1);
+        assert_eq!(pnl.len(), 1);
+        assert_eq!(pnl[0].symbol, symbol);
+        assert_eq!(pnl[0].qty, bigdec("1"));
+        assert_eq!(pnl[0].avg_price, Some(bigdec("105")));
+        assert_eq!(pnl[0].realized, bigdec("45"));
+        assert_eq!(pnl[0].unrealized, Some(bigdec("10")));
+
+        // sells 1 at 114 and 2 at 100, flipping to short 2 at 100
+        engine.submit_for(9, symbol, limit(OrderSide::Bid, "100", "3"));
+        engine.submit_for(1, symbol, limit(OrderSide::Ask, "100", "3"));
+        let pnl = engine.pnl(1);
+        assert_eq!(pnl[0].qty, bigdec("-2"));
+        assert_eq!(pnl[0].avg_price, Some(bigdec("100")));
+        assert_eq!(pnl[0].realized, bigdec("54"));
+        assert_eq!(pnl[0].unrealized, Some(bigdec("-16")));
+
+        // closing the short leaves no position to mark
+        trade(&mut engine, OrderSide::Bid, "116", "2");
+        let pnl = engine.pnl(1);
+        assert_eq!(pnl[0].qty, bigdec("0"));
+        assert_eq!(pnl[0].avg_price, None);
+        assert_eq!(pnl[0].realized, bigdec("22"));
+        assert_eq!(pnl[0].unrealized, None);
+
+        // the counterparty is flat as well
+        assert_eq!(engine.pnl(9)[0].qty, bigdec("0"));
+        assert!(engine.pnl(3).is_empty());
+    }
+
+    #[test]
+    fn balances() {
+        let symbol = (Asset::BTC, Asset::USD);
+        let mut engine = MatchingEngine::new();
+        engine.add_book(Asset::BTC, Asset::USD);
+        engine.set_balance_checks(true);
+        engine.set_fee_engine(Some(FeeEngine::flat(FeeRates::from_bps(0, 10))));
+        engine.deposit(1, Asset::BTC, &bigdec("2"));
+        engine.deposit(2, Asset::USD, &bigdec("250"));
+
+        let rejected = |results: &OrderProcessingResult<Asset>| matches!(results.as_slice(), [Err(failed)] if failed.code() == CODE_INSUFFICIENT_BALANCE);
+        assert!(rejected(&engine.submit_for(
+            1,
+            symbol,
+            limit(OrderSide::Ask, "100", "3")
+        )));
+        assert!(engine.submit_for(1, symbol, limit(OrderSide::Ask, "100", "2"))[0].is_ok());
+        // both BTC are held by the ask
+        assert_eq!(engine.balance_available(1, Asset::BTC), bigdec("0"));
+        assert!(!engine.withdraw(1, Asset::BTC, &bigdec("1")));
+
+        engine.submit_for(2, symbol, limit(OrderSide::Bid, "90", "1"));
+        assert_eq!(engine.balance_available(2, Asset::USD), bigdec("160"));
+        // 200 for the market bid on top of the 90 held
+        let market = orders::new_market_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Bid,
+            bigdec("2"),
+            SystemTime::now(),
+        );
+        assert!(rejected(&engine.submit_for(2, symbol, market)));
+        let market = orders::new_market_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Bid,
+            bigdec("1"),
+            SystemTime::now(),
+        );
+        assert!(engine.submit_for(2, symbol, market)[0].is_ok());
+
+        let summary = engine.account_summary(2);
+        assert_eq!(summary.balances[&Asset::BTC], bigdec("1"));
+        // 100 paid and a taker fee of 0.1
+        assert_eq!(summary.balances[&Asset::USD], bigdec("149.9"));
+        let summary = engine.account_summary(1);
+        assert_eq!(summary.balances[&Asset::BTC], bigdec("1"));
+        assert_eq!(summary.balances[&Asset::USD], bigdec("100"));
+
+        assert!(engine.withdraw(1, Asset::USD, &bigdec("40")));
+        assert_eq!(engine.balance_available(1, Asset::USD), bigdec("60"));
+    }
+
+    #[test]
+    fn balance_covers_taker_fee() {
+        let symbol = (Asset::BTC, Asset::USD);
+        let mut engine = MatchingEngine::new();
+        engine.add_book(Asset::BTC, Asset::USD);
+        engine.set_balance_checks(true);
+        let mut fees = FeeEngine::flat(FeeRates::from_bps(0, 10));
+        fees.set_fee_currency(FeeCurrency::OrderAsset);
+        engine.set_fee_engine(Some(fees));
+        engine.deposit(1, Asset::BTC, &bigdec("2"));
+        engine.deposit(2, Asset::USD, &bigdec("1000"));
+
+        let rejected = |results: &OrderProcessingResult<Asset>| matches!(results.as_slice(), [Err(failed)] if failed.code() == CODE_INSUFFICIENT_BALANCE);
+        // 2 BTC to sell and 0.002 BTC of fees
+        assert!(rejected(&engine.submit_for(
+            1,
+            symbol,
+            limit(OrderSide::Ask, "100", "2")
+        )));
+        assert!(engine.submit_for(1, symbol, limit(OrderSide::Ask, "100", "1.5"))[0].is_ok());
+        assert_eq!(engine.balance_available(1, Asset::BTC), bigdec("0.5"));
+
+        // fees of a bid come out of the BTC it buys
+        engine.submit_for(2, symbol, limit(OrderSide::Bid, "100", "1"));
+        // the fill takes the BTC it sold off the balance and off the hold
+        assert_eq!(engine.balance_available(1, Asset::BTC), bigdec("0.5"));
+        assert_eq!(engine.balance_available(2, Asset::USD), bigdec("900"));
+        engine.submit_for(2, symbol, limit(OrderSide::Bid, "100", "0.5"));
+        assert_eq!(engine.balance_available(1, Asset::BTC), bigdec("0.5"));
+        assert!(engine.account_summary(1).open_orders.is_empty());
+
+        engine.set_fee_engine(Some(FeeEngine::flat(FeeRates::from_bps(0, 10))));
+        engine.deposit(3, Asset::USD, &bigdec("100"));
+        assert!(rejected(&engine.submit_for(
+            3,
+            symbol,
+            limit(OrderSide::Bid, "100", "1")
+        )));
+        engine.deposit(3, Asset::USD, &bigdec("0.1"));
+        assert!(engine.submit_for(3, symbol, limit(OrderSide::Bid, "100", "1"))[0].is_ok());
+    }
+}
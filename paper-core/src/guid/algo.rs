@@ -0,0 +1,710 @@
+use bigdecimal::{BigDecimal, Zero};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::time::SystemTime;
+use uuid::Uuid;
+
+use super::clock::Clock;
+use super::domain::OrderSide;
+use super::engine::{MatchingEngine, Symbol};
+use super::orderbook::{OrderProcessingResult, Success};
+use super::orders;
+use super::trade_ids::TradeId;
+
+const ERR_BAD_QTY: &str = "parent quantity must be positive";
+const ERR_BAD_WINDOW: &str = "parent order must end after it starts";
+const ERR_BAD_SCHEDULE: &str = "empty execution schedule";
+
+/// How a parent order is spread over its execution window
+#[derive(Debug, Clone)]
+pub enum AlgoStrategy {
+    /// equal slices at regular intervals
+    Twap { slices: u32 },
+    /// slices follow the expected volume, one weight per equal interval
+    Vwap { profile: Vec<BigDecimal> },
+    /// children keep own executions at `rate` (between 0 and 1 exclusive)
+    /// of the volume traded in the market since the parent started
+    Pov { rate: BigDecimal },
+}
+
+/// Large order executed by the scheduler as a series of child orders
+#[derive(Debug, Clone)]
+pub struct ParentOrder<Asset> {
+    pub symbol: Symbol<Asset>,
+    pub side: OrderSide,
+    pub qty: BigDecimal,
+    /// children are limit orders at this price, market orders if none
+    pub limit_price: Option<BigDecimal>,
+    pub start: SystemTime,
+    pub end: SystemTime,
+    pub strategy: AlgoStrategy,
+}
+
+/// Parent level view of the execution
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParentProgress {
+    pub parent_id: Uuid,
+    /// quantity the schedule wants executed by now
+    pub target: BigDecimal,
+    pub executed: BigDecimal,
+    /// quantity of children resting in the book
+    pub working: BigDecimal,
+    pub remaining: BigDecimal,
+    /// volume weighted price of executions so far
+    pub avg_price: Option<BigDecimal>,
+    pub done: bool,
+}
+
+/// Volume traded in one market, as observed by the scheduler
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TapeStats {
+    pub trades: u64,
+    pub volume: BigDecimal,
+    pub notional: BigDecimal,
+}
+
+impl TapeStats {
+    /// Volume weighted average trade price
+    pub fn vwap(&self) -> Option<BigDecimal> {
+        if self.volume.is_zero() {
+            None
+        } else {
+            Some(&self.notional / &self.volume)
+        }
+    }
+
+    fn record<Asset>(&mut self, results: &OrderProcessingResult<Asset>) {
+        let mut seen: HashSet<TradeId> = HashSet::new();
+        let fills = results.iter().filter_map(|result| match result {
+            Ok(Success::Filled {
+                trade_id,
+                price,
+                qty,
+                ..
+            })
+            | Ok(Success::PartiallyFilled {
+                trade_id,
+                price,
+                qty,
+                ..
+            }) => Some((trade_id, price, qty)),
+            _ => None,
+        });
+        for (_, price, qty) in fills.filter(|(trade_id, ..)| seen.insert(**trade_id)) {
+            self.trades += 1;
+            self.volume += qty;
+            self.notional += price * qty;
+        }
+    }
+}
+
+/// Outcome of a scheduler poll
+#[derive(Debug)]
+pub struct AlgoReport<Asset> {
+    /// processing results of child orders and their cancels
+    pub child_results: Vec<OrderProcessingResult<Asset>>,
+    /// progress of every parent that was active during the poll
+    pub progress: Vec<ParentProgress>,
+}
+
+struct ParentState<Asset> {
+    order: ParentOrder<Asset>,
+    target: BigDecimal,
+    executed: BigDecimal,
+    notional: BigDecimal,
+    working: BigDecimal,
+    /// market volume on the tape when the parent started
+    volume_at_start: Option<BigDecimal>,
+    done: bool,
+}
+
+struct ChildState {
+    parent_id: Uuid,
+    side: OrderSide,
+    remaining: BigDecimal,
+}
+
+/// Slices parent orders into child orders over time.
+///
+/// Children are sent to the engine on `poll`, sized by the parent strategy
+/// and the injected clock. Fills of children resting in the book arrive with
+/// other participants' requests, so all processing results of the markets
+/// involved have to be fed back through `on_results`, which also keeps the
+/// tape statistics volume tracking strategies rely on.
+pub struct AlgoScheduler<Asset, C>
+where
+    Asset: Debug + Clone + Copy + Eq + Hash,
+    C: Clock,
+{
+    clock: C,
+    parents: HashMap<Uuid, ParentState<Asset>>,
+    /// parent ids in submission order
+    active: Vec<Uuid>,
+    children: HashMap<Uuid, ChildState>,
+    tapes: HashMap<Symbol<Asset>, TapeStats>,
+}
+
+impl<Asset, C> AlgoScheduler<Asset, C>
+where
+    Asset: Debug + Clone + Copy + Eq + Hash,
+    C: Clock,
+{
+    pub fn new(clock: C) -> Self {
+        AlgoScheduler {
+            clock,
+            parents: HashMap::new(),
+            active: vec![],
+            children: HashMap::new(),
+            tapes: HashMap::new(),
+        }
+    }
+
+    /// Accept parent order for execution, returns its id
+    pub fn submit(&mut self, order: ParentOrder<Asset>) -> Result<Uuid, &'static str> {
+        if order.qty <= BigDecimal::zero() {
+            return Err(ERR_BAD_QTY);
+        }
+        if order.end <= order.start {
+            return Err(ERR_BAD_WINDOW);
+        }
+        let valid_schedule = match &order.strategy {
+            AlgoStrategy::Twap { slices } => *slices > 0,
+            AlgoStrategy::Vwap { profile } => {
+                !profile.is_empty()
+                    && profile.iter().all(|weight| weight >= &BigDecimal::zero())
+                    && profile.iter().sum::<BigDecimal>() > BigDecimal::zero()
+            }
+            AlgoStrategy::Pov { rate } => rate > &BigDecimal::zero() && rate < &BigDecimal::from(1),
+        };
+        if !valid_schedule {
+            return Err(ERR_BAD_SCHEDULE);
+        }
+
+        let parent_id = Uuid::new_v4();
+        self.parents.insert(
+            parent_id,
+            ParentState {
+                order,
+                target: BigDecimal::zero(),
+                executed: BigDecimal::zero(),
+                notional: BigDecimal::zero(),
+                working: BigDecimal::zero(),
+                volume_at_start: None,
+                done: false,
+            },
+        );
+        self.active.push(parent_id);
+        Ok(parent_id)
+    }
+
+    pub fn progress(&self, parent_id: Uuid) -> Option<ParentProgress> {
+        self.parents
+            .get(&parent_id)
+            .map(|parent| parent.progress(parent_id))
+    }
+
+    /// Trading observed so far in the market
+    pub fn tape(&self, symbol: Symbol<Asset>) -> Option<&TapeStats> {
+        self.tapes.get(&symbol)
+    }
+
+    /// Stop the parent and pull its resting children from the book
+    pub fn cancel(
+        &mut self,
+        parent_id: Uuid,
+        engine: &mut MatchingEngine<Asset>,
+    ) -> Option<Vec<OrderProcessingResult<Asset>>> {
+        if !self.parents.contains_key(&parent_id) {
+            return None;
+        }
+        let results = self.cancel_children(parent_id, engine);
+        self.finish(parent_id);
+        Some(results)
+    }
+
+    /// Send child orders due by now
+    pub fn poll(&mut self, engine: &mut MatchingEngine<Asset>) -> AlgoReport<Asset> {
+        let now = self.clock.now();
+        let mut child_results = vec![];
+        let mut progress = vec![];
+
+        for parent_id in self.active.clone() {
+            let parent = &self.parents[&parent_id];
+            if now < parent.order.start {
+                continue;
+            }
+
+            if now >= parent.order.end {
+                child_results.extend(self.cancel_children(parent_id, engine));
+                self.finish(parent_id);
+            } else {
+                let parent = self.parents.get_mut(&parent_id).unwrap();
+                let volume = self
+                    .tapes
+                    .get(&parent.order.symbol)
+                    .map(|tape| tape.volume.clone())
+                    .unwrap_or_else(BigDecimal::zero);
+                let volume_at_start = parent
+                    .volume_at_start
+                    .get_or_insert_with(|| volume.clone())
+                    .clone();
+                parent.target = parent.scheduled(now, &(volume - volume_at_start));
+                let child_qty = &parent.target - &parent.executed - &parent.working;
+                if child_qty > BigDecimal::zero() {
+                    child_results.push(self.send_child(parent_id, child_qty, now, engine));
+                }
+            }
+
+            progress.push(self.parents[&parent_id].progress(parent_id));
+        }
+
+        let parents = &self.parents;
+        self.active.retain(|parent_id| !parents[parent_id].done);
+        AlgoReport {
+            child_results,
+            progress,
+        }
+    }
+
+    /// Record trades of the market and account fills and cancels of child
+    /// orders found in its processing results
+    pub fn on_results(&mut self, symbol: Symbol<Asset>, results: &OrderProcessingResult<Asset>) {
+        self.tapes.entry(symbol).or_default().record(results);
+        for result in results {
+            match result {
+                Ok(Success::Filled {
+                    order_id,
+                    price,
+                    qty,
+                    ..
+                })
+                | Ok(Success::PartiallyFilled {
+                    order_id,
+                    price,
+                    qty,
+                    ..
+                }) => self.record_fill(*order_id, price, qty),
+                Ok(Success::Cancelled { order_id, .. })
+                | Ok(Success::Expired { order_id, .. })
+                | Ok(Success::DustCancelled { order_id, .. }) => self.drop_child(*order_id),
+                Ok(Success::SelfTradePrevented {
+                    order_id,
+                    remaining,
+                    ..
+                }) if remaining.is_zero() => self.drop_child(*order_id),
+                _ => (),
+            }
+        }
+    }
+
+    /* Helpers */
+
+    fn send_child(
+        &mut self,
+        parent_id: Uuid,
+        qty: BigDecimal,
+        now: SystemTime,
+        engine: &mut MatchingEngine<Asset>,
+    ) -> OrderProcessingResult<Asset> {
+        let parent = &self.parents[&parent_id].order;
+        let (order_asset, price_asset) = parent.symbol;
+        let request = match &parent.limit_price {
+            Some(price) => orders::new_limit_order_request(
+                order_asset,
+                price_asset,
+                parent.side,
+                price.clone(),
+                qty.clone(),
+                now,
+            ),
+            None => orders::new_market_order_request(
+                order_asset,
+                price_asset,
+                parent.side,
+                qty.clone(),
+                now,
+            ),
+        };
+        let child_id = request.order_id();
+        let symbol = parent.symbol;
+        let side = parent.side;
+
+        self.children.insert(
+            child_id,
+            ChildState {
+                parent_id,
+                side,
+                remaining: qty.clone(),
+            },
+        );
+        self.parents.get_mut(&parent_id).unwrap().working += qty;
+
+        let results = engine.submit(symbol, request);
+        self.on_results(symbol, &results);
+
+        let resting = results
+            .iter()
+            .any(|result| matches!(result, Ok(Success::Accepted { .. })))
+            && self.parents[&parent_id].order.limit_price.is_some();
+        if !resting {
+            // market remainder or rejected child never reaches the book
+            self.drop_child(child_id);
+        }
+        results
+    }
+
+    fn cancel_children(
+        &mut self,
+        parent_id: Uuid,
+        engine: &mut MatchingEngine<Asset>,
+    ) -> Vec<OrderProcessingResult<Asset>> {
+        let symbol = self.parents[&parent_id].order.symbol;
+        let working: Vec<(Uuid, OrderSide)> = self
+            .children
+            .iter()
+            .filter(|(_, child)| child.parent_id == parent_id)
+            .map(|(child_id, child)| (*child_id, child.side))
+            .collect();
+
+        working
+            .into_iter()
+            .map(|(child_id, side)| {
+                let results =
+                    engine.submit(symbol, orders::limit_order_cancel_request(child_id, side));
+                self.on_results(symbol, &results);
+                self.drop_child(child_id);
+                results
+            })
+            .collect()
+    }
+
+    fn finish(&mut self, parent_id: Uuid) {
+        if let Some(parent) = self.parents.get_mut(&parent_id) {
+            parent.done = true;
+        }
+        self.active.retain(|id| *id != parent_id);
+    }
+
+    fn record_fill(&mut self, child_id: Uuid, price: &BigDecimal, qty: &BigDecimal) {
+        let child = match self.children.get_mut(&child_id) {
+            Some(child) => child,
+            None => return,
+        };
+        child.remaining -= qty;
+        let parent_id = child.parent_id;
+        if child.remaining.is_zero() {
+            self.children.remove(&child_id);
+        }
+
+        let parent = self.parents.get_mut(&parent_id).unwrap();
+        parent.executed += qty;
+        parent.notional += price * qty;
+        parent.working -= qty;
+        if parent.executed >= parent.order.qty {
+            parent.done = true;
+        }
+    }
+
+    fn drop_child(&mut self, child_id: Uuid) {
+        if let Some(child) = self.children.remove(&child_id) {
+            if let Some(parent) = self.parents.get_mut(&child.parent_id) {
+                parent.working -= child.remaining;
+            }
+        }
+    }
+}
+
+impl<Asset> ParentState<Asset> {
+    /// Quantity the strategy wants executed by `now`, given the volume
+    /// traded since start, rounded down to the precision of the parent quantity
+    fn scheduled(&self, now: SystemTime, market_volume: &BigDecimal) -> BigDecimal {
+        let order = &self.order;
+        let scale = order.qty.as_bigint_and_exponent().1;
+        let buckets = match &order.strategy {
+            AlgoStrategy::Twap { slices } => *slices as u128,
+            AlgoStrategy::Vwap { profile } => profile.len() as u128,
+            AlgoStrategy::Pov { rate } => {
+                // own share of the total volume, own executions included
+                let others = market_volume - &self.executed;
+                let target = (others * rate / (BigDecimal::from(1) - rate)).with_scale(scale);
+                return target.min(order.qty.clone());
+            }
+        };
+
+        let window = order
+            .end
+            .duration_since(order.start)
+            .unwrap_or_default()
+            .as_nanos();
+        let elapsed = now
+            .duration_since(order.start)
+            .unwrap_or_default()
+            .as_nanos();
+        // intervals started so far, each one releases its slice at once
+        let started = (elapsed * buckets / window + 1).min(buckets);
+        if started == buckets {
+            return order.qty.clone();
+        }
+
+        // equal TWAP slices need no weights, however many there are
+        let (released, total) = match &order.strategy {
+            AlgoStrategy::Vwap { profile } => (
+                profile[..started as usize].iter().sum(),
+                profile.iter().sum(),
+            ),
+            _ => (BigDecimal::from(started), BigDecimal::from(buckets)),
+        };
+        (&order.qty * released / total).with_scale(scale)
+    }
+
+    fn progress(&self, parent_id: Uuid) -> ParentProgress {
+        ParentProgress {
+            parent_id,
+            target: self.target.clone(),
+            executed: self.executed.clone(),
+            working: self.working.clone(),
+            remaining: &self.order.qty - &self.executed,
+            avg_price: if self.executed.is_zero() {
+                None
+            } else {
+                Some(&self.notional / &self.executed)
+            },
+            done: self.done,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::clock::ManualClock;
+    use super::*;
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    #[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
+    pub enum Asset {
+        USD,
+        BTC,
+    }
+
+    const SYMBOL: Symbol<Asset> = (Asset::BTC, Asset::USD);
+
+    fn bigdec(num: &str) -> BigDecimal {
+        BigDecimal::from_str(num).unwrap()
+    }
+
+    fn limit(side: OrderSide, price: &str, qty: &str) -> orders::OrderRequest<Asset> {
+        orders::new_limit_order_request(
+            Asset::BTC,
+            Asset::USD,
+            side,
+            bigdec(price),
+            bigdec(qty),
+            SystemTime::now(),
+        )
+    }
+
+    fn get_engine() -> MatchingEngine<Asset> {
+        let mut engine = MatchingEngine::new();
+        engine.add_book(Asset::BTC, Asset::USD);
+        engine
+    }
+
+    #[test]
+    fn tape_counts_trades_once() {
+        let mut engine = get_engine();
+        engine.submit(SYMBOL, limit(OrderSide::Ask, "100", "2"));
+        engine.submit(SYMBOL, limit(OrderSide::Ask, "101", "2"));
+        let mut results = engine.submit(SYMBOL, limit(OrderSide::Bid, "101", "3"));
+        // no reliance on the order fills are reported in
+        results.reverse();
+
+        let mut tape = TapeStats::default();
+        tape.record(&results);
+        assert_eq!(tape.trades, 2);
+        assert_eq!(tape.volume, bigdec("3"));
+        assert_eq!(tape.notional, bigdec("301"));
+    }
+
+    #[test]
+    fn twap_market_slices() {
+        let start = SystemTime::now();
+        let clock = ManualClock::new(start);
+        let mut engine = get_engine();
+        engine.submit(SYMBOL, limit(OrderSide::Ask, "100", "100"));
+
+        let mut scheduler = AlgoScheduler::new(&clock);
+        let parent_id = scheduler
+            .submit(ParentOrder {
+                symbol: SYMBOL,
+                side: OrderSide::Bid,
+                qty: bigdec("10"),
+                limit_price: None,
+                start,
+                end: start + Duration::from_secs(100),
+                strategy: AlgoStrategy::Twap { slices: 5 },
+            })
+            .unwrap();
+
+        let report = scheduler.poll(&mut engine);
+        assert_eq!(report.child_results.len(), 1);
+        assert_eq!(report.progress[0].executed, bigdec("2"));
+        assert_eq!(report.progress[0].avg_price, Some(bigdec("100")));
+
+        // still within the first interval
+        clock.advance(Duration::from_secs(10));
+        assert!(scheduler.poll(&mut engine).child_results.is_empty());
+
+        clock.advance(Duration::from_secs(50));
+        let report = scheduler.poll(&mut engine);
+        assert_eq!(report.child_results.len(), 1);
+        assert_eq!(report.progress[0].target, bigdec("8"));
+        assert_eq!(report.progress[0].executed, bigdec("8"));
+
+        clock.advance(Duration::from_secs(30));
+        scheduler.poll(&mut engine);
+        let progress = scheduler.progress(parent_id).unwrap();
+        assert_eq!(progress.executed, bigdec("10"));
+        assert_eq!(progress.remaining, bigdec("0"));
+        assert!(progress.done);
+
+        // a huge slice count is scheduled without a weight per slice
+        let parent_id = scheduler
+            .submit(ParentOrder {
+                symbol: SYMBOL,
+                side: OrderSide::Bid,
+                qty: bigdec("10"),
+                limit_price: None,
+                start,
+                end: start + Duration::from_secs(1000),
+                strategy: AlgoStrategy::Twap { slices: u32::MAX },
+            })
+            .unwrap();
+        clock.advance(Duration::from_secs(410));
+        scheduler.poll(&mut engine);
+        let progress = scheduler.progress(parent_id).unwrap();
+        assert_eq!(progress.target, bigdec("5"));
+        assert_eq!(progress.executed, bigdec("5"));
+    }
+
+    #[test]
+    fn vwap_limit_slices() {
+        let start = SystemTime::now();
+        let clock = ManualClock::new(start);
+        let mut engine = get_engine();
+
+        let mut scheduler = AlgoScheduler::new(&clock);
+        let parent_id = scheduler
+            .submit(ParentOrder {
+                symbol: SYMBOL,
+                side: OrderSide::Bid,
+                qty: bigdec("10.0"),
+                limit_price: Some(bigdec("99")),
+                start,
+                end: start + Duration::from_secs(60),
+                strategy: AlgoStrategy::Vwap {
+                    profile: vec![bigdec("1"), bigdec("3")],
+                },
+            })
+            .unwrap();
+
+        let report = scheduler.poll(&mut engine);
+        assert_eq!(report.progress[0].working, bigdec("2.5"));
+
+        // someone sells into the resting child
+        let results = engine.submit(SYMBOL, limit(OrderSide::Ask, "99", "1"));
+        scheduler.on_results(SYMBOL, &results);
+        let progress = scheduler.progress(parent_id).unwrap();
+        assert_eq!(progress.executed, bigdec("1"));
+        assert_eq!(progress.working, bigdec("1.5"));
+
+        clock.advance(Duration::from_secs(30));
+        let report = scheduler.poll(&mut engine);
+        assert_eq!(report.progress[0].target, bigdec("10.0"));
+        assert_eq!(report.progress[0].working, bigdec("9.0"));
+
+        // window closes, resting children are pulled
+        clock.advance(Duration::from_secs(30));
+        let report = scheduler.poll(&mut engine);
+        assert_eq!(report.child_results.len(), 2);
+        assert!(report.progress[0].done);
+        assert_eq!(report.progress[0].working, bigdec("0"));
+        assert_eq!(report.progress[0].remaining, bigdec("9.0"));
+        assert_eq!(engine.book_mut(SYMBOL).unwrap().current_spread(), None);
+    }
+
+    #[test]
+    fn pov_tracks_volume() {
+        let start = SystemTime::now();
+        let clock = ManualClock::new(start);
+        let mut engine = get_engine();
+        engine.submit(SYMBOL, limit(OrderSide::Ask, "100", "100"));
+
+        let mut scheduler = AlgoScheduler::new(&clock);
+        let parent_id = scheduler
+            .submit(ParentOrder {
+                symbol: SYMBOL,
+                side: OrderSide::Bid,
+                qty: bigdec("5.0"),
+                limit_price: None,
+                start,
+                end: start + Duration::from_secs(60),
+                strategy: AlgoStrategy::Pov {
+                    rate: bigdec("0.2"),
+                },
+            })
+            .unwrap();
+
+        // nothing traded yet
+        assert!(scheduler.poll(&mut engine).child_results.is_empty());
+
+        for _ in 0..2 {
+            let results = engine.submit(SYMBOL, limit(OrderSide::Bid, "100", "4"));
+            scheduler.on_results(SYMBOL, &results);
+        }
+        assert_eq!(scheduler.tape(SYMBOL).unwrap().trades, 2);
+
+        let report = scheduler.poll(&mut engine);
+        assert_eq!(report.progress[0].executed, bigdec("2.0"));
+        let tape = scheduler.tape(SYMBOL).unwrap();
+        assert_eq!(tape.volume, bigdec("10"));
+        assert_eq!(tape.vwap(), Some(bigdec("100")));
+
+        // own executions alone do not drive further participation
+        clock.advance(Duration::from_secs(1));
+        assert!(scheduler.poll(&mut engine).child_results.is_empty());
+
+        for _ in 0..10 {
+            let results = engine.submit(SYMBOL, limit(OrderSide::Bid, "100", "4"));
+            scheduler.on_results(SYMBOL, &results);
+        }
+        scheduler.poll(&mut engine);
+        let progress = scheduler.progress(parent_id).unwrap();
+        assert_eq!(progress.executed, bigdec("5.0"));
+        assert!(progress.done);
+    }
+
+    #[test]
+    fn invalid_parent() {
+        let start = SystemTime::now();
+        let mut scheduler: AlgoScheduler<Asset, _> = AlgoScheduler::new(ManualClock::new(start));
+        let parent = ParentOrder {
+            symbol: SYMBOL,
+            side: OrderSide::Ask,
+            qty: bigdec("1"),
+            limit_price: None,
+            start,
+            end: start,
+            strategy: AlgoStrategy::Twap { slices: 1 },
+        };
+        assert_eq!(scheduler.submit(parent.clone()), Err(ERR_BAD_WINDOW));
+
+        let parent = ParentOrder {
+            end: start + Duration::from_secs(1),
+            strategy: AlgoStrategy::Pov { rate: bigdec("1") },
+            ..parent
+        };
+        assert_eq!(scheduler.submit(parent), Err(ERR_BAD_SCHEDULE));
+    }
+}
@@ -0,0 +1,176 @@
+use bigdecimal::{BigDecimal, Zero};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use super::domain::OrderSide;
+
+/// Price a call auction would uncross at right now
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndicativeUncross {
+    pub price: BigDecimal,
+    /// quantity changing hands at the price
+    pub volume: BigDecimal,
+    /// quantity left on the heavier side at the price, zero when balanced
+    pub surplus: BigDecimal,
+    /// side of the surplus, none when balanced
+    pub surplus_side: Option<OrderSide>,
+}
+
+/// Uncross price of crossing limit orders given as `(price, qty)`.
+///
+/// The price maximizes the volume executed; ties go to the smallest
+/// surplus, then to the price nearest the reference price, then to the
+/// lowest price. None if the orders do not cross.
+pub fn indicative_uncross<'a, B, A>(
+    bids: B,
+    asks: A,
+    reference: Option<&BigDecimal>,
+) -> Option<IndicativeUncross>
+where
+    B: IntoIterator<Item = (&'a BigDecimal, &'a BigDecimal)>,
+    A: IntoIterator<Item = (&'a BigDecimal, &'a BigDecimal)>,
+{
+    let (bids, asks) = (levels(bids), levels(asks));
+
+    let mut best: Option<IndicativeUncross> = None;
+    for &price in bids.keys().chain(asks.keys()) {
+        // bids willing to pay the price, asks willing to take it
+        let demand = bids
+            .range::<&BigDecimal, _>(price..)
+            .fold(BigDecimal::zero(), |total, (_, qty)| total + qty);
+        let supply = asks
+            .range::<&BigDecimal, _>(..=price)
+            .fold(BigDecimal::zero(), |total, (_, qty)| total + qty);
+        let (volume, surplus, surplus_side) = if demand > supply {
+            (supply.clone(), &demand - &supply, Some(OrderSide::Bid))
+        } else if supply > demand {
+            (demand.clone(), &supply - &demand, Some(OrderSide::Ask))
+        } else {
+            (demand, BigDecimal::zero(), None)
+        };
+        if volume.is_zero() {
+            continue;
+        }
+
+        let candidate = IndicativeUncross {
+            price: price.clone(),
+            volume,
+            surplus,
+            surplus_side,
+        };
+        if best
+            .as_ref()
+            .is_none_or(|best| better(&candidate, best, reference))
+        {
+            best = Some(candidate);
+        }
+    }
+    best
+}
+
+/// Quantity at each price
+fn levels<'a, I>(orders: I) -> BTreeMap<&'a BigDecimal, BigDecimal>
+where
+    I: IntoIterator<Item = (&'a BigDecimal, &'a BigDecimal)>,
+{
+    let mut levels = BTreeMap::new();
+    for (price, qty) in orders {
+        *levels.entry(price).or_insert_with(BigDecimal::zero) += qty;
+    }
+    levels
+}
+
+fn better(
+    candidate: &IndicativeUncross,
+    best: &IndicativeUncross,
+    reference: Option<&BigDecimal>,
+) -> bool {
+    if candidate.volume != best.volume {
+        return candidate.volume > best.volume;
+    }
+    if candidate.surplus != best.surplus {
+        return candidate.surplus < best.surplus;
+    }
+    if let Some(reference) = reference {
+        let distance = |price: &BigDecimal| (price - reference).abs();
+        let (candidate_distance, best_distance) =
+            (distance(&candidate.price), distance(&best.price));
+        if candidate_distance != best_distance {
+            return candidate_distance < best_distance;
+        }
+    }
+    candidate.price < best.price
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+
+    fn bigdec(num: &str) -> BigDecimal {
+        BigDecimal::from_str(num).unwrap()
+    }
+
+    fn orders(orders: &[(&str, &str)]) -> Vec<(BigDecimal, BigDecimal)> {
+        orders
+            .iter()
+            .map(|(price, qty)| (bigdec(price), bigdec(qty)))
+            .collect()
+    }
+
+    fn uncross(
+        bids: &[(BigDecimal, BigDecimal)],
+        asks: &[(BigDecimal, BigDecimal)],
+        reference: Option<&BigDecimal>,
+    ) -> Option<IndicativeUncross> {
+        indicative_uncross(
+            bids.iter().map(|(price, qty)| (price, qty)),
+            asks.iter().map(|(price, qty)| (price, qty)),
+            reference,
+        )
+    }
+
+    #[test]
+    fn maximum_volume() {
+        let bids = orders(&[("103", "2"), ("102", "3"), ("100", "5")]);
+        let asks = orders(&[("99", "1"), ("101", "4"), ("104", "5")]);
+        // at 101: demand 5, supply 5
+        let indicative = uncross(&bids, &asks, None).unwrap();
+        assert_eq!(indicative.price, bigdec("101"));
+        assert_eq!(indicative.volume, bigdec("5"));
+        assert_eq!(indicative.surplus, bigdec("0"));
+        assert_eq!(indicative.surplus_side, None);
+
+        // orders at the same price count together
+        let asks = orders(&[("99", "1"), ("101", "2"), ("101", "4")]);
+        let indicative = uncross(&bids, &asks, None).unwrap();
+        assert_eq!(indicative.price, bigdec("101"));
+        assert_eq!(indicative.volume, bigdec("5"));
+        assert_eq!(indicative.surplus, bigdec("2"));
+        assert_eq!(indicative.surplus_side, Some(OrderSide::Ask));
+
+        assert_eq!(uncross(&orders(&[("98", "1")]), &asks, None), None);
+        assert_eq!(uncross(&[], &asks, None), None);
+    }
+
+    #[test]
+    fn ties() {
+        // 2 change hands anywhere from 100 to 102 with no surplus
+        let bids = orders(&[("102", "2")]);
+        let asks = orders(&[("100", "2")]);
+        assert_eq!(uncross(&bids, &asks, None).unwrap().price, bigdec("100"));
+        let reference = bigdec("101.5");
+        assert_eq!(
+            uncross(&bids, &asks, Some(&reference)).unwrap().price,
+            bigdec("102")
+        );
+
+        // the smaller surplus wins before the reference price
+        let bids = orders(&[("102", "2"), ("101", "1")]);
+        let asks = orders(&[("100", "2")]);
+        let reference = bigdec("100");
+        let indicative = uncross(&bids, &asks, Some(&reference)).unwrap();
+        assert_eq!(indicative.price, bigdec("102"));
+        assert_eq!(indicative.volume, bigdec("2"));
+    }
+}
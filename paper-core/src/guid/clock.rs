@@ -0,0 +1,141 @@
+use serde::{Deserialize, Serialize};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Source of the current time for components acting on a schedule
+pub trait Clock {
+    fn now(&self) -> SystemTime;
+}
+
+/// Wall clock
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// Clock moved only explicitly, for tests and simulations. Share it behind
+/// an `Arc` to move it from another thread than the one reading it.
+#[derive(Debug)]
+pub struct ManualClock {
+    now: Mutex<SystemTime>,
+}
+
+impl ManualClock {
+    pub fn new(start: SystemTime) -> Self {
+        ManualClock {
+            now: Mutex::new(start),
+        }
+    }
+
+    pub fn set(&self, ts: SystemTime) {
+        *self.now.lock().unwrap() = ts;
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap() += by;
+    }
+}
+
+impl Clone for ManualClock {
+    fn clone(&self) -> Self {
+        ManualClock::new(self.now())
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().unwrap()
+    }
+}
+
+/// Hands out event timestamps of a book.
+///
+/// The clock is read once per request, events of the request get that time
+/// plus one nanosecond per event emitted before them. Timestamps never go
+/// back, even if the clock does, so events order strictly by time.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct EventStamper {
+    now: SystemTime,
+    last: Option<SystemTime>,
+}
+
+impl Default for EventStamper {
+    fn default() -> Self {
+        EventStamper {
+            now: UNIX_EPOCH,
+            last: None,
+        }
+    }
+}
+
+impl EventStamper {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Begin new request observed at `now`
+    pub fn start(&mut self, now: SystemTime) {
+        self.now = now;
+    }
+
+    /// Timestamp of the next event
+    pub fn stamp(&mut self) -> SystemTime {
+        let ts = match self.last {
+            Some(last) if last >= self.now => last + Duration::from_nanos(1),
+            _ => self.now,
+        };
+        self.last = Some(ts);
+        ts
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for &C {
+    fn now(&self) -> SystemTime {
+        (**self).now()
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for Rc<C> {
+    fn now(&self) -> SystemTime {
+        (**self).now()
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for Arc<C> {
+    fn now(&self) -> SystemTime {
+        (**self).now()
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for Box<C> {
+    fn now(&self) -> SystemTime {
+        (**self).now()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn stamps_strictly_increase() {
+        let start = SystemTime::now();
+        let mut stamper = EventStamper::new();
+
+        stamper.start(start);
+        assert_eq!(stamper.stamp(), start);
+        assert_eq!(stamper.stamp(), start + Duration::from_nanos(1));
+
+        // clock going back does not move events back
+        stamper.start(start - Duration::from_secs(1));
+        assert_eq!(stamper.stamp(), start + Duration::from_nanos(2));
+
+        stamper.start(start + Duration::from_secs(1));
+        assert_eq!(stamper.stamp(), start + Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,163 @@
+use bigdecimal::Zero;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Debug;
+use std::time::SystemTime;
+use uuid::Uuid;
+
+use super::orderbook::{OrderProcessingResult, Success};
+
+/// Orders remembered by default once they left the book
+pub const DEFAULT_COMPLETED_CAPACITY: usize = 10_000;
+
+/// Where an order stands, as far as its book knows
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OrderStatus {
+    /// in the book, possibly partly filled
+    Resting,
+    /// stop order waiting for its trigger
+    Waiting,
+    Filled,
+    /// cancelled on request or by the book, e.g. an unfilled remainder
+    Cancelled,
+    Expired,
+}
+
+/// Final state of an order that left the book
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompletedOrder {
+    pub status: OrderStatus,
+    pub ts: SystemTime,
+}
+
+/// Bounded memory of orders that were filled, cancelled or expired, built
+/// from processing results.
+///
+/// Lets late cancels and status queries tell a finished order from one that
+/// never existed. Once full the least recently completed order is dropped.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CompletedOrders {
+    capacity: usize,
+    orders: HashMap<Uuid, (CompletedOrder, u64)>,
+    /// in completion order, with the sequence number of the completion; an
+    /// entry whose number differs from the map's is left from an earlier
+    /// completion of a reused ID
+    order: VecDeque<(Uuid, u64)>,
+    seq: u64,
+}
+
+impl Default for CompletedOrders {
+    fn default() -> Self {
+        Self::new(DEFAULT_COMPLETED_CAPACITY)
+    }
+}
+
+impl CompletedOrders {
+    /// Remember up to `capacity` orders, none at all with 0
+    pub fn new(capacity: usize) -> Self {
+        CompletedOrders {
+            capacity,
+            orders: HashMap::new(),
+            order: VecDeque::new(),
+            seq: 0,
+        }
+    }
+
+    pub fn on_results<Asset>(&mut self, results: &OrderProcessingResult<Asset>)
+    where
+        Asset: Debug + Clone,
+    {
+        for result in results {
+            let (order_id, status, ts) = match result {
+                Ok(Success::Filled { order_id, ts, .. }) => (order_id, OrderStatus::Filled, ts),
+                Ok(Success::Cancelled { order_id, ts })
+                | Ok(Success::CancelledRemainder { order_id, ts, .. })
+                | Ok(Success::DustCancelled { order_id, ts, .. }) => {
+                    (order_id, OrderStatus::Cancelled, ts)
+                }
+                Ok(Success::SelfTradePrevented {
+                    order_id,
+                    remaining,
+                    ts,
+                    ..
+                }) if remaining.is_zero() => (order_id, OrderStatus::Cancelled, ts),
+                Ok(Success::Expired { order_id, ts }) => (order_id, OrderStatus::Expired, ts),
+                _ => continue,
+            };
+            self.insert(*order_id, CompletedOrder { status, ts: *ts });
+        }
+    }
+
+    pub fn get(&self, order_id: Uuid) -> Option<&CompletedOrder> {
+        self.orders.get(&order_id).map(|(completed, _)| completed)
+    }
+
+    pub fn len(&self) -> usize {
+        self.orders.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.orders.is_empty()
+    }
+
+    fn insert(&mut self, order_id: Uuid, completed: CompletedOrder) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.seq += 1;
+        self.orders.insert(order_id, (completed, self.seq));
+        self.order.push_back((order_id, self.seq));
+
+        while self.orders.len() > self.capacity || self.order.len() > 2 * self.capacity {
+            let (oldest, seq) = match self.order.pop_front() {
+                Some(entry) => entry,
+                None => break,
+            };
+            if self
+                .orders
+                .get(&oldest)
+                .is_some_and(|(_, last)| *last == seq)
+            {
+                self.orders.remove(&oldest);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn cancelled(order_id: Uuid) -> OrderProcessingResult<u8> {
+        vec![Ok(Success::Cancelled {
+            order_id,
+            ts: SystemTime::now(),
+        })]
+    }
+
+    #[test]
+    fn least_recently_completed_dropped() {
+        let mut completed = CompletedOrders::new(2);
+        let ids: Vec<Uuid> = (0..3).map(|_| Uuid::new_v4()).collect();
+        for id in &ids {
+            completed.on_results(&cancelled(*id));
+        }
+        assert_eq!(completed.len(), 2);
+        assert!(completed.get(ids[0]).is_none());
+        assert_eq!(
+            completed.get(ids[2]).unwrap().status,
+            OrderStatus::Cancelled
+        );
+
+        // completing a reused ID again refreshes it
+        completed.on_results(&cancelled(ids[1]));
+        completed.on_results(&cancelled(ids[0]));
+        assert!(completed.get(ids[1]).is_some());
+        assert!(completed.get(ids[2]).is_none());
+
+        let mut none = CompletedOrders::new(0);
+        none.on_results(&cancelled(ids[0]));
+        assert!(none.is_empty());
+    }
+}
@@ -0,0 +1,137 @@
+use bigdecimal::{BigDecimal, One, Zero};
+use std::error::Error;
+use std::fmt;
+use std::time::SystemTime;
+
+use super::engine::Symbol;
+use super::orderbook::{DepthLimit, DustThreshold, PriceBand, QtyLimits, TradingStatus};
+use super::price_key::PriceScale;
+use super::surveillance::StackingLimits;
+
+/// Parameters of a market that can be changed while it trades
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MarketConfig {
+    pub price_scale: PriceScale,
+    pub depth_limit: Option<DepthLimit>,
+    pub dust_threshold: Option<DustThreshold>,
+    pub qty_limits: Option<QtyLimits>,
+    pub price_band: Option<PriceBand>,
+    pub stacking_limits: Option<StackingLimits>,
+    pub trading_status: TradingStatus,
+}
+
+/// Why a market config was refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigError {
+    UnknownMarket,
+    /// resting orders are priced off the new tick size
+    OffTick,
+    /// parameter out of range, named by the message
+    Invalid(&'static str),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::UnknownMarket => write!(f, "unknown market"),
+            ConfigError::OffTick => write!(f, "resting orders off the new tick size"),
+            ConfigError::Invalid(reason) => write!(f, "invalid market config: {}", reason),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+/// Config of a market replaced on the fly
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarketConfigChanged<Asset> {
+    pub symbol: Symbol<Asset>,
+    pub previous: MarketConfig,
+    pub config: MarketConfig,
+    pub ts: SystemTime,
+}
+
+impl MarketConfig {
+    /// Check parameters that make no sense on their own, whatever the book
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if let Some(limit) = &self.depth_limit {
+            if limit.max_levels == Some(0) || limit.max_orders == Some(0) {
+                return Err(ConfigError::Invalid("depth limit of zero"));
+            }
+        }
+        if let Some(threshold) = &self.dust_threshold {
+            let negative = |min: &Option<BigDecimal>| {
+                min.as_ref().is_some_and(|min| *min < BigDecimal::zero())
+            };
+            if negative(&threshold.min_qty) || negative(&threshold.min_notional) {
+                return Err(ConfigError::Invalid("negative dust threshold"));
+            }
+        }
+        if let Some(limits) = &self.qty_limits {
+            limits.validate().map_err(ConfigError::Invalid)?;
+        }
+        if let Some(band) = &self.price_band {
+            if band.width <= BigDecimal::zero() || band.width >= BigDecimal::one() {
+                return Err(ConfigError::Invalid("band width not within 0 and 1"));
+            }
+        }
+        if let Some(limits) = &self.stacking_limits {
+            if limits.max_per_level == Some(0) {
+                return Err(ConfigError::Invalid("stacking limit of zero"));
+            }
+            if let Some((width, max)) = &limits.max_in_range {
+                if *max == 0 || *width < BigDecimal::zero() {
+                    return Err(ConfigError::Invalid("bad stacking range"));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::orderbook::DepthLimitPolicy;
+    use super::*;
+    use std::str::FromStr;
+
+    fn bigdec(num: &str) -> BigDecimal {
+        BigDecimal::from_str(num).unwrap()
+    }
+
+    #[test]
+    fn validate_parameters() {
+        assert_eq!(MarketConfig::default().validate(), Ok(()));
+
+        let config = MarketConfig {
+            depth_limit: Some(DepthLimit {
+                max_levels: Some(0),
+                max_orders: None,
+                policy: DepthLimitPolicy::RejectNew,
+            }),
+            ..MarketConfig::default()
+        };
+        assert_eq!(
+            config.validate(),
+            Err(ConfigError::Invalid("depth limit of zero"))
+        );
+
+        let config = MarketConfig {
+            dust_threshold: Some(DustThreshold {
+                min_qty: Some(bigdec("-1")),
+                min_notional: None,
+            }),
+            ..MarketConfig::default()
+        };
+        assert!(config.validate().is_err());
+
+        let config = MarketConfig {
+            stacking_limits: Some(StackingLimits {
+                max_per_level: Some(3),
+                max_in_range: Some((bigdec("0.5"), 5)),
+            }),
+            ..MarketConfig::default()
+        };
+        assert_eq!(config.validate(), Ok(()));
+    }
+}
@@ -0,0 +1,529 @@
+use bigdecimal::{BigDecimal, Zero};
+use std::fmt::Debug;
+use uuid::Uuid;
+
+use super::clock::EventStamper;
+use super::trade_ids::{SequentialTradeIds, TradeIdGenerator};
+use super::domain::{OrderSide, OrderType};
+use super::fees::LiquidityRole;
+use super::orderbook::{Failed, OrderProcessingResult, Orderbook, Success};
+use super::orders::OrderRequest;
+use super::validation::OrderRequestValidator;
+
+pub(super) const ERR_UNLIMITED_AMEND: &str = "amending unlimited peg needs a price";
+pub(super) const ERR_DARK_STOP: &str = "stop orders not supported in the dark book";
+
+/// Non-displayed order pegged to the lit midpoint
+#[derive(Debug, Clone)]
+struct DarkOrder {
+    order_id: Uuid,
+    side: OrderSide,
+    order_type: OrderType,
+    /// worst acceptable midpoint, none for unlimited peg
+    limit: Option<BigDecimal>,
+    qty: BigDecimal,
+    filled: BigDecimal,
+    liquidation: bool,
+    /// arrival sequence, defines time priority
+    seq: u64,
+}
+
+impl DarkOrder {
+    fn accepts(&self, mid: &BigDecimal) -> bool {
+        match (&self.limit, self.side) {
+            (None, _) => true,
+            (Some(limit), OrderSide::Bid) => limit >= mid,
+            (Some(limit), OrderSide::Ask) => limit <= mid,
+        }
+    }
+}
+
+/// Dark book executing hidden orders at the midpoint of a lit book.
+///
+/// Limit orders carry the worst midpoint they accept, market orders are
+/// pegged without limit and rest until matched. Nothing about resting orders
+/// is published: only acceptance, fill and cancel events are produced, in
+/// the same format as the lit `Orderbook` emits.
+pub struct MidpointBook<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    pub order_asset: Asset,
+    pub price_asset: Asset,
+    bids: Vec<DarkOrder>,
+    asks: Vec<DarkOrder>,
+    next_seq: u64,
+    order_validator: OrderRequestValidator<Asset>,
+    stamper: EventStamper,
+    trade_ids: Box<dyn TradeIdGenerator + Send>,
+}
+
+impl<Asset> MidpointBook<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    pub fn new(order_asset: Asset, price_asset: Asset) -> Self {
+        MidpointBook {
+            order_asset,
+            price_asset,
+            bids: vec![],
+            asks: vec![],
+            next_seq: 0,
+            order_validator: OrderRequestValidator::new(order_asset, price_asset),
+            stamper: EventStamper::new(),
+            trade_ids: Box::new(SequentialTradeIds::new()),
+        }
+    }
+
+    /// Source of trade IDs, consecutive numbers by default
+    pub fn set_trade_id_generator(&mut self, trade_ids: Box<dyn TradeIdGenerator + Send>) {
+        self.trade_ids = trade_ids;
+    }
+
+    /// Process request and cross the dark book at the current lit midpoint
+    pub fn process_order(
+        &mut self,
+        order: OrderRequest<Asset>,
+        lit: &mut Orderbook<Asset>,
+    ) -> OrderProcessingResult<Asset> {
+        let mut proc_result: OrderProcessingResult<Asset> = vec![];
+        // events are timed by the clock of the lit book
+        self.stamper.start(lit.clock().now());
+
+        if let Err(reason) = self.order_validator.validate(&order) {
+            proc_result.push(Err(Failed::ValidationFailed(String::from(reason))));
+            return proc_result;
+        }
+
+        match order {
+            OrderRequest::NewMarketOrder {
+                order_id,
+                order_asset,
+                price_asset,
+                side,
+                qty,
+                liquidation,
+                ..
+            } => {
+                proc_result.push(Ok(Success::Accepted {
+                    order_id,
+                    order_asset,
+                    price_asset,
+                    price: None,
+                    collared_from: None,
+                    order_type: OrderType::Market,
+                    qty: qty.clone(),
+                    side,
+                    ts: self.stamper.stamp(),
+                    liquidation,
+                }));
+                self.store(
+                    &mut proc_result,
+                    order_id,
+                    side,
+                    OrderType::Market,
+                    None,
+                    qty,
+                    liquidation,
+                );
+            }
+
+            OrderRequest::NewLimitOrder {
+                order_id,
+                order_asset,
+                price_asset,
+                side,
+                price,
+                qty,
+                liquidation,
+                ..
+            } => {
+                proc_result.push(Ok(Success::Accepted {
+                    order_id,
+                    order_asset,
+                    price_asset,
+                    price: Some(price.clone()),
+                    collared_from: None,
+                    order_type: OrderType::Limit,
+                    qty: qty.clone(),
+                    side,
+                    ts: self.stamper.stamp(),
+                    liquidation,
+                }));
+                self.store(
+                    &mut proc_result,
+                    order_id,
+                    side,
+                    OrderType::Limit,
+                    Some(price),
+                    qty,
+                    liquidation,
+                );
+            }
+
+            OrderRequest::NewStopOrder { .. } | OrderRequest::NewStopLimitOrder { .. } => {
+                proc_result.push(Err(Failed::ValidationFailed(String::from(ERR_DARK_STOP))));
+            }
+
+            OrderRequest::AmendOrder {
+                id,
+                side,
+                price,
+                qty,
+                ..
+            } => {
+                let seq = self.next_seq();
+                let queue = self.queue_mut(side);
+                match queue.iter_mut().find(|order| order.order_id == id) {
+                    Some(order) => match price.or_else(|| order.limit.clone()) {
+                        Some(price) => {
+                            // the request names the total, fills included
+                            let qty = match qty {
+                                Some(total) if total <= order.filled => {
+                                    proc_result.push(Err(Failed::QtyBelowFilled(id)));
+                                    return proc_result;
+                                }
+                                Some(total) => total - &order.filled,
+                                None => order.qty.clone(),
+                            };
+                            // only reducing quantity keeps time priority
+                            let priority_kept =
+                                Some(&price) == order.limit.as_ref() && qty <= order.qty;
+                            if !priority_kept {
+                                order.seq = seq;
+                            }
+                            let prev_price = order.limit.replace(price.clone());
+                            let prev_qty = std::mem::replace(&mut order.qty, qty.clone());
+                            order.order_type = OrderType::Limit;
+                            proc_result.push(Ok(Success::Amended {
+                                order_id: id,
+                                price,
+                                qty,
+                                prev_price,
+                                prev_qty,
+                                priority_kept,
+                                ts: self.stamper.stamp(),
+                            }));
+                        }
+                        None => proc_result.push(Err(Failed::ValidationFailed(String::from(
+                            ERR_UNLIMITED_AMEND,
+                        )))),
+                    },
+                    None => proc_result.push(Err(Failed::OrderNotFound(id))),
+                }
+            }
+
+            OrderRequest::CancelOrder { id, side } => {
+                let sides = match side {
+                    Some(side) => vec![side],
+                    None => vec![OrderSide::Bid, OrderSide::Ask],
+                };
+                let mut cancelled = false;
+                for side in sides {
+                    let queue = self.queue_mut(side);
+                    let before = queue.len();
+                    queue.retain(|order| order.order_id != id);
+                    cancelled |= queue.len() < before;
+                }
+                if cancelled {
+                    proc_result.push(Ok(Success::Cancelled {
+                        order_id: id,
+                        ts: self.stamper.stamp(),
+                    }));
+                } else {
+                    proc_result.push(Err(Failed::OrderNotFound(id)));
+                }
+            }
+        }
+
+        self.cross(&mut proc_result, lit);
+        proc_result
+    }
+
+    /// Re-check crossing after the lit book changed
+    pub fn on_lit_update(&mut self, lit: &mut Orderbook<Asset>) -> OrderProcessingResult<Asset> {
+        let mut proc_result: OrderProcessingResult<Asset> = vec![];
+        self.stamper.start(lit.clock().now());
+        self.cross(&mut proc_result, lit);
+        proc_result
+    }
+
+    /// Quantity resting on the side. Meant for internal and surveillance
+    /// views only, publishing it would defeat the dark book.
+    pub fn hidden_qty(&self, side: OrderSide) -> BigDecimal {
+        let queue = match side {
+            OrderSide::Bid => &self.bids,
+            OrderSide::Ask => &self.asks,
+        };
+        queue
+            .iter()
+            .fold(BigDecimal::zero(), |total, order| total + &order.qty)
+    }
+
+    /* Helpers */
+
+    fn next_seq(&mut self) -> u64 {
+        self.next_seq += 1;
+        self.next_seq
+    }
+
+    fn queue_mut(&mut self, side: OrderSide) -> &mut Vec<DarkOrder> {
+        match side {
+            OrderSide::Bid => &mut self.bids,
+            OrderSide::Ask => &mut self.asks,
+        }
+    }
+
+    fn store(
+        &mut self,
+        results: &mut OrderProcessingResult<Asset>,
+        order_id: Uuid,
+        side: OrderSide,
+        order_type: OrderType,
+        limit: Option<BigDecimal>,
+        qty: BigDecimal,
+        liquidation: bool,
+    ) {
+        let exists = self
+            .bids
+            .iter()
+            .chain(self.asks.iter())
+            .any(|order| order.order_id == order_id);
+        if exists {
+            results.push(Err(Failed::DuplicateOrderID(order_id)));
+            return;
+        }
+
+        let seq = self.next_seq();
+        self.queue_mut(side).push(DarkOrder {
+            order_id,
+            side,
+            order_type,
+            limit,
+            qty,
+            filled: BigDecimal::zero(),
+            liquidation,
+            seq,
+        });
+    }
+
+    /// Match eligible bids against eligible asks in time priority at the midpoint
+    fn cross(&mut self, results: &mut OrderProcessingResult<Asset>, lit: &mut Orderbook<Asset>) {
+        let mid = match lit.mid_price() {
+            Some(mid) => mid,
+            None => return,
+        };
+
+        loop {
+            let bid_idx = best_eligible(&self.bids, &mid);
+            let ask_idx = best_eligible(&self.asks, &mid);
+            let (bid_idx, ask_idx) = match (bid_idx, ask_idx) {
+                (Some(bid_idx), Some(ask_idx)) => (bid_idx, ask_idx),
+                _ => break,
+            };
+
+            let qty = if self.bids[bid_idx].qty < self.asks[ask_idx].qty {
+                self.bids[bid_idx].qty.clone()
+            } else {
+                self.asks[ask_idx].qty.clone()
+            };
+            let trade_id = self.trade_ids.next_id();
+
+            // the later order triggered the cross and is reported first
+            let mut parties = [(OrderSide::Bid, bid_idx), (OrderSide::Ask, ask_idx)];
+            if self.bids[bid_idx].seq < self.asks[ask_idx].seq {
+                parties.reverse();
+            }
+            let aggressor = parties[0].0;
+            let (bid_id, ask_id) = (self.bids[bid_idx].order_id, self.asks[ask_idx].order_id);
+            for (side, idx) in parties {
+                let counterparty = match side {
+                    OrderSide::Bid => ask_id,
+                    OrderSide::Ask => bid_id,
+                };
+                let role = if side == aggressor {
+                    LiquidityRole::Taker
+                } else {
+                    LiquidityRole::Maker
+                };
+                let deal_time = self.stamper.stamp();
+                let order = &mut self.queue_mut(side)[idx];
+                order.qty -= &qty;
+                order.filled += &qty;
+                let event = if order.qty.is_zero() {
+                    Success::Filled {
+                        order_id: order.order_id,
+                        trade_id,
+                        counterparty,
+                        side,
+                        aggressor,
+                        role,
+                        order_type: order.order_type,
+                        price: mid.clone(),
+                        qty: qty.clone(),
+                        ts: deal_time,
+                        liquidation: order.liquidation,
+                        fee: None,
+                        fee_asset: None,
+                    }
+                } else {
+                    Success::PartiallyFilled {
+                        order_id: order.order_id,
+                        trade_id,
+                        counterparty,
+                        side,
+                        aggressor,
+                        role,
+                        order_type: order.order_type,
+                        price: mid.clone(),
+                        qty: qty.clone(),
+                        ts: deal_time,
+                        liquidation: order.liquidation,
+                        fee: None,
+                        fee_asset: None,
+                    }
+                };
+                results.push(Ok(event));
+            }
+
+            self.bids.retain(|order| !order.qty.is_zero());
+            self.asks.retain(|order| !order.qty.is_zero());
+        }
+    }
+}
+
+fn best_eligible(queue: &[DarkOrder], mid: &BigDecimal) -> Option<usize> {
+    queue
+        .iter()
+        .enumerate()
+        .filter(|(_, order)| order.accepts(mid))
+        .min_by_key(|(_, order)| order.seq)
+        .map(|(idx, _)| idx)
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::orders;
+    use super::*;
+    use std::str::FromStr;
+    use std::time::SystemTime;
+
+    #[derive(PartialEq, Eq, Debug, Copy, Clone)]
+    pub enum Asset {
+        USD,
+        BTC,
+    }
+
+    fn bigdec(num: &str) -> BigDecimal {
+        BigDecimal::from_str(num).unwrap()
+    }
+
+    fn limit(side: OrderSide, price: &str, qty: &str) -> OrderRequest<Asset> {
+        orders::new_limit_order_request(
+            Asset::BTC,
+            Asset::USD,
+            side,
+            bigdec(price),
+            bigdec(qty),
+            SystemTime::now(),
+        )
+    }
+
+    fn get_lit_book() -> Orderbook<Asset> {
+        let mut lit = Orderbook::new(Asset::BTC, Asset::USD);
+        lit.process_order(limit(OrderSide::Bid, "99", "1"));
+        lit.process_order(limit(OrderSide::Ask, "101", "1"));
+        lit
+    }
+
+    #[test]
+    fn cross_at_midpoint() {
+        let mut lit = get_lit_book();
+        let mut dark = MidpointBook::new(Asset::BTC, Asset::USD);
+
+        let sell = limit(OrderSide::Ask, "99.5", "2");
+        let sell_id = sell.order_id();
+        assert_eq!(dark.process_order(sell, &mut lit).len(), 1);
+
+        let buy = orders::new_market_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Bid,
+            bigdec("1.5"),
+            SystemTime::now(),
+        );
+        let buy_id = buy.order_id();
+        let results = dark.process_order(buy, &mut lit);
+        assert_eq!(results.len(), 3);
+        match &results[1] {
+            Ok(Success::Filled {
+                order_id,
+                price,
+                role,
+                ..
+            }) => {
+                assert_eq!(*order_id, buy_id);
+                assert_eq!(*price, bigdec("100"));
+                assert_eq!(*role, LiquidityRole::Taker);
+            }
+            _ => panic!("unexpected events"),
+        }
+        match &results[2] {
+            Ok(Success::PartiallyFilled {
+                order_id,
+                qty,
+                role,
+                ..
+            }) => {
+                assert_eq!(*order_id, sell_id);
+                assert_eq!(*qty, bigdec("1.5"));
+                assert_eq!(*role, LiquidityRole::Maker);
+            }
+            _ => panic!("unexpected events"),
+        }
+
+        // lit book is never touched
+        assert_eq!(lit.current_spread(), Some((bigdec("99"), bigdec("101"))));
+    }
+
+    #[test]
+    fn limit_outside_midpoint_waits() {
+        let mut lit = get_lit_book();
+        let mut dark = MidpointBook::new(Asset::BTC, Asset::USD);
+
+        dark.process_order(limit(OrderSide::Ask, "100.5", "1"), &mut lit);
+        let results = dark.process_order(limit(OrderSide::Bid, "101", "1"), &mut lit);
+        assert_eq!(results.len(), 1);
+
+        // lit market moves up, midpoint reaches the ask limit
+        lit.process_order(limit(OrderSide::Bid, "100", "1"));
+        let results = dark.on_lit_update(&mut lit);
+        assert_eq!(results.len(), 2);
+        for result in results {
+            match result {
+                Ok(Success::Filled { price, .. }) => assert_eq!(price, bigdec("100.5")),
+                _ => panic!("unexpected events"),
+            }
+        }
+    }
+
+    #[test]
+    fn cancel_dark_order() {
+        let mut lit = get_lit_book();
+        let mut dark = MidpointBook::new(Asset::BTC, Asset::USD);
+
+        let sell = limit(OrderSide::Ask, "105", "1");
+        let sell_id = sell.order_id();
+        dark.process_order(sell, &mut lit);
+
+        let request = orders::limit_order_cancel_request(sell_id, OrderSide::Ask);
+        match dark.process_order(request.clone(), &mut lit).as_slice() {
+            [Ok(Success::Cancelled { .. })] => (),
+            _ => panic!("unexpected events"),
+        }
+        match dark.process_order(request, &mut lit).as_slice() {
+            [Err(Failed::OrderNotFound(_))] => (),
+            _ => panic!("unexpected events"),
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use bigdecimal::{BigDecimal, Zero};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::time::SystemTime;
+
+use super::engine::{MatchingEngine, Symbol};
+use super::orderbook::{DepthLevel, TradingStatus};
+
+/// Resting orders of one book side, summed up
+#[derive(Debug, Clone, PartialEq)]
+pub struct SideSummary {
+    pub levels: usize,
+    pub orders: usize,
+    pub qty: BigDecimal,
+}
+
+/// State of one market at a refresh
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarketStatus<Asset> {
+    pub symbol: Symbol<Asset>,
+    pub halted: bool,
+    pub trading_status: TradingStatus,
+    pub best_bid: Option<BigDecimal>,
+    pub best_ask: Option<BigDecimal>,
+    pub bids: SideSummary,
+    pub asks: SideSummary,
+    pub last_trade_price: Option<BigDecimal>,
+    /// stop orders waiting for their trigger
+    pub stops: usize,
+    /// `Orderbook::change_seq`
+    pub change_seq: u64,
+    /// book changes per second since the previous refresh, None on the first
+    pub update_rate: Option<f64>,
+}
+
+/// State of the whole engine at a refresh
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExchangeStatus<Asset> {
+    pub ts: SystemTime,
+    /// in listing order
+    pub markets: Vec<MarketStatus<Asset>>,
+    /// requests waiting in the engine queue
+    pub queued: usize,
+    /// requests shed since the engine started
+    pub shed: u64,
+    pub shut_down: bool,
+}
+
+/// Aggregated status of an engine for operations dashboards.
+///
+/// Every refresh reads the engine as it is, only the change sequence of each
+/// market is kept in between to tell its update rate. Dashboards polling the
+/// same instance share those rates, each refresh measuring from the last.
+#[derive(Debug)]
+pub struct Dashboard<Asset> {
+    /// change sequence of each market at the previous refresh and its time
+    previous: HashMap<Symbol<Asset>, (u64, SystemTime)>,
+}
+
+impl<Asset> Default for Dashboard<Asset> {
+    fn default() -> Self {
+        Dashboard {
+            previous: HashMap::new(),
+        }
+    }
+}
+
+impl<Asset> Dashboard<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq + Hash,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn refresh(
+        &mut self,
+        engine: &MatchingEngine<Asset>,
+        ts: SystemTime,
+    ) -> ExchangeStatus<Asset> {
+        let mut markets = vec![];
+        for symbol in engine.markets() {
+            let book = match engine.book(*symbol) {
+                Some(book) => book,
+                None => continue,
+            };
+            let change_seq = book.change_seq();
+            let update_rate = self
+                .previous
+                .insert(*symbol, (change_seq, ts))
+                .and_then(|previous| rate(previous, (change_seq, ts)));
+
+            let depth = book.depth(usize::MAX);
+            markets.push(MarketStatus {
+                symbol: *symbol,
+                halted: engine.is_halted(*symbol),
+                trading_status: book.trading_status(),
+                best_bid: book.best_bid().cloned(),
+                best_ask: book.best_ask().cloned(),
+                bids: summarize(&depth.bids),
+                asks: summarize(&depth.asks),
+                last_trade_price: book.last_trade_price().cloned(),
+                stops: book.stop_count(),
+                change_seq,
+                update_rate,
+            });
+        }
+
+        ExchangeStatus {
+            ts,
+            markets,
+            queued: engine.queued(),
+            shed: engine.shed(),
+            shut_down: engine.is_shut_down(),
+        }
+    }
+}
+
+/* Helpers */
+
+/// Changes per second between two (change sequence, time) samples
+fn rate((seq, at): (u64, SystemTime), (now_seq, now): (u64, SystemTime)) -> Option<f64> {
+    let elapsed = now.duration_since(at).ok()?.as_secs_f64();
+    (elapsed > 0.0).then(|| now_seq.saturating_sub(seq) as f64 / elapsed)
+}
+
+fn summarize(levels: &[DepthLevel]) -> SideSummary {
+    SideSummary {
+        levels: levels.len(),
+        orders: levels.iter().map(|level| level.orders).sum(),
+        qty: levels
+            .iter()
+            .fold(BigDecimal::zero(), |total, level| total + &level.qty),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::domain::OrderSide;
+    use super::super::orders;
+    use super::super::session::HaltPolicy;
+    use super::*;
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    #[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
+    pub enum Asset {
+        USD,
+        BTC,
+        ETH,
+    }
+
+    fn bigdec(num: &str) -> BigDecimal {
+        BigDecimal::from_str(num).unwrap()
+    }
+
+    fn limit(side: OrderSide, price: &str, qty: &str) -> orders::OrderRequest<Asset> {
+        orders::new_limit_order_request(
+            Asset::BTC,
+            Asset::USD,
+            side,
+            bigdec(price),
+            bigdec(qty),
+            SystemTime::now(),
+        )
+    }
+
+    #[test]
+    fn refreshed_status() {
+        let btc = (Asset::BTC, Asset::USD);
+        let mut engine = MatchingEngine::new();
+        engine.add_book(Asset::BTC, Asset::USD);
+        engine.add_book(Asset::ETH, Asset::USD);
+        let mut dashboard = Dashboard::new();
+        let start = SystemTime::now();
+
+        let status = dashboard.refresh(&engine, start);
+        assert_eq!(status.markets.len(), 2);
+        assert_eq!(status.markets[0].update_rate, None);
+
+        engine.submit(btc, limit(OrderSide::Bid, "99", "1"));
+        engine.submit(btc, limit(OrderSide::Bid, "99", "2"));
+        engine.submit(btc, limit(OrderSide::Ask, "101", "1"));
+        engine.submit(btc, limit(OrderSide::Bid, "101", "0.5"));
+        engine.halt((Asset::ETH, Asset::USD), HaltPolicy::LeaveIntact);
+
+        let status = dashboard.refresh(&engine, start + Duration::from_secs(2));
+        let market = &status.markets[0];
+        assert_eq!(market.best_bid, Some(bigdec("99")));
+        assert_eq!(market.best_ask, Some(bigdec("101")));
+        assert_eq!(
+            market.bids,
+            SideSummary {
+                levels: 1,
+                orders: 2,
+                qty: bigdec("3"),
+            }
+        );
+        assert_eq!(market.asks.qty, bigdec("0.5"));
+        assert_eq!(market.last_trade_price, Some(bigdec("101")));
+        assert_eq!(market.update_rate, Some(2.0));
+        assert!(!market.halted);
+        assert!(status.markets[1].halted);
+        assert_eq!(status.markets[1].update_rate, Some(0.0));
+    }
+}
@@ -0,0 +1,103 @@
+
+use std::fmt::{self, Debug};
+use std::time::SystemTime;
+use serde::{Deserialize, Serialize};
+use bigdecimal::BigDecimal;
+use uuid::Uuid;
+
+/// Identifier of the trading account owning orders
+pub type AccountId = u64;
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OrderSide {
+    Bid,
+    Ask,
+}
+
+impl OrderSide {
+    pub fn from_string(side: &str) -> Option<OrderSide> {
+        let lower = side.to_lowercase();
+        match lower.as_str() {
+            "bid" => Some(OrderSide::Bid),
+            "ask" => Some(OrderSide::Ask),
+            _ => None,
+        }
+    }
+
+    /// Side an order of this side matches against
+    pub fn opposite(&self) -> OrderSide {
+        match self {
+            OrderSide::Bid => OrderSide::Ask,
+            OrderSide::Ask => OrderSide::Bid,
+        }
+    }
+}
+
+impl fmt::Display for OrderSide {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OrderSide::Bid => write!(f, "bid"),
+            OrderSide::Ask => write!(f, "ask"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Order<Asset>
+where
+    Asset: Debug + Clone,
+{
+    pub order_id: Uuid,
+    pub order_asset: Asset,
+    pub price_asset: Asset,
+    pub side: OrderSide,
+    pub price: BigDecimal,
+    /// quantity still open
+    pub qty: BigDecimal,
+    /// quantity traded so far, the order's total is `filled + qty`
+    #[serde(default)]
+    pub filled: BigDecimal,
+    pub liquidation: bool,
+    pub time_in_force: TimeInForce,
+    /// account the order belongs to, orders of one owner never trade with
+    /// each other while the book prevents self-trades
+    #[serde(default)]
+    pub owner: Option<AccountId>,
+}
+
+
+/// How long a limit order may rest in the book
+#[derive(Eq, PartialEq, Debug, Copy, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeInForce {
+    /// good till cancelled, carried over session close
+    #[default]
+    GTC,
+    /// good for the day, expires at session close
+    Day,
+    /// immediate or cancel, the unfilled remainder is cancelled instead of
+    /// resting
+    IOC,
+    /// fill or kill, rejected unless it can be filled in full right away
+    FOK,
+    /// good till date, expires on the first expiry sweep at or past the time
+    GTD(SystemTime),
+}
+
+
+#[derive(Eq, PartialEq, Debug, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OrderType {
+    Market,
+    Limit,
+}
+
+impl fmt::Display for OrderType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OrderType::Market => write!(f, "market"),
+            OrderType::Limit => write!(f, "limit"),
+        }
+    }
+}
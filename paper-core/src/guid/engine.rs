@@ -0,0 +1,2535 @@
+use bigdecimal::{BigDecimal, One, Zero};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::io;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use uuid::Uuid;
+
+use super::auction::IndicativeUncross;
+use super::accounts::{AccountSummary, AccountTracker, InstrumentPnl, OpenOrder};
+use super::clock::{Clock, ManualClock, SystemClock};
+use super::config::{ConfigError, MarketConfig, MarketConfigChanged};
+use super::domain::{AccountId, OrderSide};
+use super::execution::{ExecutionQuality, ImprovementStats};
+use super::fees::FeeEngine;
+use super::journal::Journal;
+use super::order_queues::OrderQueue;
+use super::orderbook::{
+    BookConfig, Failed, OrderProcessingResult, Orderbook, Success, TradingStatus,
+};
+use super::orders::{self, OrderRequest};
+use super::session::HaltPolicy;
+use super::surveillance::{ComplianceEvent, StackingLimits};
+use super::tape::{PageRequest, TradePage, TradeTape};
+use super::trade_ids::{
+    SequentialTradeIds, SnowflakeTradeIds, TradeIdGenerator, TradeIdScheme, UuidTradeIds,
+};
+
+pub(super) const ERR_UNKNOWN_MARKET: &str = "unknown market";
+pub(super) const ERR_MARKET_HALTED: &str = "market halted";
+pub(super) const ERR_STACKING_LIMIT: &str = "too many orders stacked by account";
+pub(super) const ERR_ENGINE_SHUT_DOWN: &str = "engine shut down";
+pub(super) const ERR_INSUFFICIENT_BALANCE: &str = "insufficient balance";
+
+/// Trading pair addressing a book inside the engine: (order asset, price asset)
+pub type Symbol<Asset> = (Asset, Asset);
+
+/// Single leg of a basket: target market and the request for it
+pub type BasketLeg<Asset> = (Symbol<Asset>, OrderRequest<Asset>);
+
+/// Grouped outcome of a basket submission
+#[derive(Debug)]
+pub struct BasketResult<Asset> {
+    /// false when the whole basket was rejected by the all-or-nothing check
+    pub accepted: bool,
+    /// processing results of every leg, in submission order
+    pub legs: Vec<OrderProcessingResult<Asset>>,
+}
+
+/// Liquidation of the account's position in a single market
+#[derive(Debug)]
+pub struct StopOutLeg<Asset> {
+    pub symbol: Symbol<Asset>,
+    pub side: OrderSide,
+    /// size of the position to close
+    pub position: BigDecimal,
+    pub filled: BigDecimal,
+    /// price times quantity of the fills
+    pub notional: BigDecimal,
+    /// results of the liquidation order, empty if none could be sent
+    pub results: OrderProcessingResult<Asset>,
+}
+
+impl<Asset> StopOutLeg<Asset> {
+    /// Part of the position left open, for lack of liquidity within protection
+    pub fn unfilled(&self) -> BigDecimal {
+        &self.position - &self.filled
+    }
+}
+
+/// Consolidated execution report of a stop-out sweep
+#[derive(Debug)]
+pub struct StopOutReport<Asset> {
+    pub account: AccountId,
+    /// resting orders of the account pulled before liquidating
+    pub cancelled: OrderProcessingResult<Asset>,
+    pub legs: Vec<StopOutLeg<Asset>>,
+}
+
+impl<Asset> StopOutReport<Asset> {
+    /// Whether every position got closed
+    pub fn is_flat(&self) -> bool {
+        self.legs.iter().all(|leg| leg.unfilled().is_zero())
+    }
+}
+
+/// Bounds on the request queue, past them requests are shed with
+/// `Failed::Overloaded` instead of building a backlog. Cancels are never
+/// shed, so participants can always pull their orders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LoadShedding {
+    /// requests refused by `enqueue` while this many are waiting
+    pub max_queued: Option<usize>,
+    /// requests waiting longer are dropped by `process_queued` unprocessed
+    pub max_wait: Option<Duration>,
+}
+
+/// Housekeeping of `process_queued` once it has emptied the request queue,
+/// sweeping book sides left with many dangling indices by cancel traffic
+/// instead of waiting for the automatic sweep of the next operations
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdleMaintenance {
+    /// compact a book side once this many of its indices are stalled
+    pub min_stalled: usize,
+    /// also release memory of the side, see `OrderQueue::maintain`
+    pub shrink: bool,
+}
+
+struct QueuedRequest<Asset>
+where
+    Asset: Debug + Clone,
+{
+    symbol: Symbol<Asset>,
+    order: OrderRequest<Asset>,
+    arrived: SystemTime,
+}
+
+/// Where a market stands against its last snapshot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SnapshotState {
+    /// change sequence of the book, see `Orderbook::change_seq`
+    pub seq: u64,
+    /// change sequence the last snapshot was taken at, None before the first
+    pub snapshot_seq: Option<u64>,
+    pub snapshot_at: Option<SystemTime>,
+}
+
+impl SnapshotState {
+    /// Whether the book changed since the last snapshot
+    pub fn is_dirty(&self) -> bool {
+        self.snapshot_seq != Some(self.seq)
+    }
+
+    /// Changes not covered by the last snapshot
+    pub fn pending_changes(&self) -> u64 {
+        self.seq - self.snapshot_seq.unwrap_or(0)
+    }
+}
+
+/// Book of one market in an `EngineSnapshot`
+#[derive(Serialize)]
+#[serde(bound = "Asset: Debug + Clone + Copy + Eq + Serialize")]
+pub struct BookSnapshot<'a, Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    pub symbol: Symbol<Asset>,
+    pub halted: bool,
+    /// `Orderbook::change_seq` of the book when taken
+    pub change_seq: u64,
+    /// `Orderbook::event_seq` of the book when taken
+    pub event_seq: u64,
+    pub book: &'a Orderbook<Asset>,
+}
+
+/// Books of every market at one point in time, see
+/// `MatchingEngine::snapshot_all`
+#[derive(Serialize)]
+#[serde(bound = "Asset: Debug + Clone + Copy + Eq + Serialize")]
+pub struct EngineSnapshot<'a, Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    pub ts: SystemTime,
+    /// in listing order
+    pub books: Vec<BookSnapshot<'a, Asset>>,
+}
+
+/// Book of one market read back from an `EngineSnapshot`
+#[derive(Deserialize)]
+#[serde(bound = "Asset: Debug + Clone + Copy + Eq + DeserializeOwned")]
+pub struct RestoredBook<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    pub symbol: Symbol<Asset>,
+    pub halted: bool,
+    pub change_seq: u64,
+    pub event_seq: u64,
+    pub book: Orderbook<Asset>,
+}
+
+/// `EngineSnapshot` read back, for `MatchingEngine::restore_all`
+#[derive(Deserialize)]
+#[serde(bound = "Asset: Debug + Clone + Copy + Eq + DeserializeOwned")]
+pub struct RestoredSnapshot<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    pub ts: SystemTime,
+    pub books: Vec<RestoredBook<Asset>>,
+}
+
+/// Terminal event of an engine, nothing is accepted after it
+#[derive(Debug)]
+pub struct EngineShutdown<Asset> {
+    /// requests still queued at shutdown and what the books made of them
+    pub drained: Vec<(Symbol<Asset>, OrderProcessingResult<Asset>)>,
+    /// markets snapshotted, every book of the engine
+    pub snapshots: Vec<Symbol<Asset>>,
+    pub ts: SystemTime,
+}
+
+/// Set of orderbooks keyed by trading pair
+pub struct MatchingEngine<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq + Hash,
+{
+    books: HashMap<Symbol<Asset>, Orderbook<Asset>>,
+    /// markets in listing order, sweeps over every book follow it so they
+    /// report the same events on every run
+    listed: Vec<Symbol<Asset>>,
+    trade_id_scheme: TradeIdScheme,
+    /// generator shared by all books under the snowflake scheme
+    snowflake: Option<Arc<Mutex<SnowflakeTradeIds<SystemClock>>>>,
+    accounts: AccountTracker<Asset>,
+    execution: ExecutionQuality<Asset>,
+    tape: TradeTape<Asset>,
+    market_makers: HashSet<AccountId>,
+    halted: HashSet<Symbol<Asset>>,
+    stacking_limits: HashMap<Symbol<Asset>, StackingLimits>,
+    compliance_events: Vec<ComplianceEvent<Asset>>,
+    /// change sequence and time of the last snapshot of each market
+    snapshots: HashMap<Symbol<Asset>, (u64, SystemTime)>,
+    journal: Option<Box<dyn Journal<Asset> + Send>>,
+    /// charges fills of attributed orders, writing the fees on the events
+    fees: Option<FeeEngine<Asset>>,
+    /// reject orders of accounts that cannot afford them
+    balance_checks: bool,
+    queued: VecDeque<QueuedRequest<Asset>>,
+    load_shedding: Option<LoadShedding>,
+    /// requests shed so far
+    shed: u64,
+    idle_maintenance: Option<IdleMaintenance>,
+    /// stalled indices dropped by idle maintenance so far
+    compacted: u64,
+    /// times requests joining the queue
+    clock: Box<dyn Clock + Send>,
+    /// set in simulation mode, drives the engine and every book
+    logical_clock: Option<Arc<ManualClock>>,
+    shut_down: bool,
+}
+
+impl<Asset> Default for MatchingEngine<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq + Hash,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Asset> MatchingEngine<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq + Hash,
+{
+    /// Create engine without any markets
+    pub fn new() -> Self {
+        Self::with_trade_ids(TradeIdScheme::default())
+    }
+
+    /// Create engine whose books issue trade IDs by `scheme`
+    pub fn with_trade_ids(scheme: TradeIdScheme) -> Self {
+        let snowflake = match scheme {
+            TradeIdScheme::Snowflake { node } => {
+                Some(Arc::new(Mutex::new(SnowflakeTradeIds::new(node))))
+            }
+            _ => None,
+        };
+        MatchingEngine {
+            books: HashMap::new(),
+            listed: Vec::new(),
+            trade_id_scheme: scheme,
+            snowflake,
+            accounts: AccountTracker::new(),
+            execution: ExecutionQuality::new(),
+            tape: TradeTape::new(),
+            market_makers: HashSet::new(),
+            halted: HashSet::new(),
+            stacking_limits: HashMap::new(),
+            compliance_events: Vec::new(),
+            snapshots: HashMap::new(),
+            journal: None,
+            fees: None,
+            balance_checks: false,
+            queued: VecDeque::new(),
+            load_shedding: None,
+            shed: 0,
+            idle_maintenance: None,
+            compacted: 0,
+            clock: Box::new(SystemClock),
+            logical_clock: None,
+            shut_down: false,
+        }
+    }
+
+    /// Create engine in simulation mode, fully deterministic and replayable.
+    ///
+    /// All time comes from `clock`, which only moves when the caller moves
+    /// it: event timestamps, the request queue and the books of every market,
+    /// including those added later. Trade IDs are sequential and sweeps over
+    /// all markets run in listing order, so the same requests fed at the same
+    /// logical times produce the same events. Hand the clock to a
+    /// `TradingCalendar` to drive sessions the same way, and give orders
+    /// fixed IDs with `OrderRequest::with_order_id`.
+    pub fn with_logical_clock(clock: Arc<ManualClock>) -> Self {
+        let mut engine = Self::with_trade_ids(TradeIdScheme::Sequential);
+        engine.clock = Box::new(clock.clone());
+        engine.logical_clock = Some(clock);
+        engine
+    }
+
+    pub fn is_simulation(&self) -> bool {
+        self.logical_clock.is_some()
+    }
+
+    /// Move the logical clock forward and expire GTD orders that ran out by
+    /// then, None outside simulation mode
+    pub fn advance_time(&mut self, by: Duration) -> Option<OrderProcessingResult<Asset>> {
+        let clock = self.logical_clock.as_ref()?;
+        clock.advance(by);
+        let now = clock.now();
+        Some(self.expire_orders(now))
+    }
+
+    /// Open new market for the pair of assets.
+    ///
+    /// Returns false if the market already exists.
+    pub fn add_book(&mut self, order_asset: Asset, price_asset: Asset) -> bool {
+        self.add_book_with_config(order_asset, price_asset, BookConfig::default())
+    }
+
+    /// Open new market tuned with its own book config
+    pub fn add_book_with_config(
+        &mut self,
+        order_asset: Asset,
+        price_asset: Asset,
+        config: BookConfig,
+    ) -> bool {
+        let symbol = (order_asset, price_asset);
+        if self.books.contains_key(&symbol) {
+            return false;
+        }
+        let mut book = Orderbook::new_with_config(order_asset, price_asset, config);
+        book.set_trade_id_generator(self.trade_id_generator());
+        if let Some(clock) = &self.logical_clock {
+            book.set_clock(Box::new(clock.clone()));
+        }
+        self.books.insert(symbol, book);
+        self.listed.push(symbol);
+        true
+    }
+
+    /// Delist the market, cancelling its resting orders and dropping its
+    /// halt, snapshot and stacking state.
+    ///
+    /// Returns the cancellations, None if the market is unknown. Requests
+    /// still queued for it are rejected as for any unknown market, and
+    /// adding it again starts a fresh book.
+    pub fn remove_book(&mut self, symbol: Symbol<Asset>) -> Option<OrderProcessingResult<Asset>> {
+        let mut book = self.books.remove(&symbol)?;
+        self.listed.retain(|listed| *listed != symbol);
+        self.halted.remove(&symbol);
+        self.stacking_limits.remove(&symbol);
+        self.snapshots.remove(&symbol);
+
+        let results = book.cancel_resting(|_| true);
+        self.track_results(&results);
+        Some(results)
+    }
+
+    pub fn book(&self, symbol: Symbol<Asset>) -> Option<&Orderbook<Asset>> {
+        self.books.get(&symbol)
+    }
+
+    /// Markets in listing order
+    pub fn markets(&self) -> &[Symbol<Asset>] {
+        &self.listed
+    }
+
+    pub fn book_mut(&mut self, symbol: Symbol<Asset>) -> Option<&mut Orderbook<Asset>> {
+        self.books.get_mut(&symbol)
+    }
+
+    /// Journal every request that passes the engine checks before processing
+    /// it, requests the journal fails to take are rejected
+    pub fn set_journal<J>(&mut self, journal: J)
+    where
+        J: Journal<Asset> + Send + 'static,
+    {
+        self.journal = Some(Box::new(journal));
+    }
+
+    /// Charge maker/taker fees on fills of orders attributed with
+    /// `submit_for`, filling in `fee` and `fee_asset` of their events.
+    /// Fills of other orders carry no fee.
+    pub fn set_fee_engine(&mut self, fees: Option<FeeEngine<Asset>>) {
+        self.fees = fees;
+    }
+
+    /// Fee engine in use, e.g. to look up the tier of an account
+    pub fn fee_engine_mut(&mut self) -> Option<&mut FeeEngine<Asset>> {
+        self.fees.as_mut()
+    }
+
+    /// Route request to the book of the given market
+    pub fn submit(
+        &mut self,
+        symbol: Symbol<Asset>,
+        order: OrderRequest<Asset>,
+    ) -> OrderProcessingResult<Asset> {
+        if self.shut_down {
+            return self.reject(&order, ERR_ENGINE_SHUT_DOWN);
+        }
+        self.route(symbol, order)
+    }
+
+    /// Route request of the account, attributing the order to it, also as
+    /// its owner for self-trade prevention
+    pub fn submit_for(
+        &mut self,
+        account: AccountId,
+        symbol: Symbol<Asset>,
+        order: OrderRequest<Asset>,
+    ) -> OrderProcessingResult<Asset> {
+        if order.is_new_order() {
+            self.accounts
+                .register_order(order.order_id(), account, symbol);
+        }
+        self.submit(symbol, order.with_owner(account))
+    }
+
+    /// Open orders, resting notional, positions and today's fill volume of the account
+    pub fn account_summary(&self, account: AccountId) -> AccountSummary<Asset> {
+        let mut open_orders: Vec<OpenOrder<Asset>> = self
+            .accounts
+            .orders_of(account)
+            .filter_map(|(order_id, symbol)| {
+                let book = self.books.get(&symbol)?;
+                let order = book
+                    .bid_queue
+                    .get(order_id)
+                    .or_else(|| book.ask_queue.get(order_id))?;
+                Some(OpenOrder {
+                    order_id,
+                    symbol,
+                    side: order.side,
+                    price: order.price.clone(),
+                    qty: order.qty.clone(),
+                })
+            })
+            .collect();
+        open_orders.sort_by_key(|order| order.order_id);
+
+        self.accounts.summary(account, open_orders)
+    }
+
+    /// Net position of the account in every market it traded, in order of
+    /// its first fill there, with realized P&L and unrealized P&L at the mid
+    /// price of the book
+    pub fn pnl(&self, account: AccountId) -> Vec<InstrumentPnl<Asset>> {
+        self.accounts.instrument_pnl(account, |symbol| {
+            self.books.get(&symbol).and_then(Orderbook::mid_price)
+        })
+    }
+
+    /// Credit the account with `amount` of the asset
+    pub fn deposit(&mut self, account: AccountId, asset: Asset, amount: &BigDecimal) {
+        self.accounts.credit(account, asset, amount);
+    }
+
+    /// Debit the account, false if less than `amount` of the asset is
+    /// available to it
+    pub fn withdraw(&mut self, account: AccountId, asset: Asset, amount: &BigDecimal) -> bool {
+        if self.available(account, asset, None) < *amount {
+            return false;
+        }
+        self.accounts.credit(account, asset, &-amount);
+        true
+    }
+
+    /// Balance of the account not held by its open orders
+    pub fn balance_available(&self, account: AccountId, asset: Asset) -> BigDecimal {
+        self.available(account, asset, None)
+    }
+
+    /// Reject new orders and amends of attributed orders the account cannot
+    /// afford with its available balance, off by default.
+    ///
+    /// Bids need the price asset, limit price times quantity or, for market
+    /// bids, the cost of the levels they would take. Asks need the quantity
+    /// of the order asset. Stops are checked at their limit or trigger price
+    /// when submitted, and hold nothing until they trigger. With a fee engine
+    /// set, orders also need the highest taker fee of the schedule, unless it
+    /// is charged in the asset the fill pays out.
+    pub fn set_balance_checks(&mut self, enabled: bool) {
+        self.balance_checks = enabled;
+    }
+
+    /// Price improvement of taker fills in the market
+    pub fn price_improvement(&self, symbol: Symbol<Asset>) -> Option<&ImprovementStats> {
+        self.execution.market(symbol)
+    }
+
+    /// Price improvement of taker fills of the account in the market
+    pub fn account_price_improvement(
+        &self,
+        account: AccountId,
+        symbol: Symbol<Asset>,
+    ) -> Option<&ImprovementStats> {
+        self.execution.account(account, symbol)
+    }
+
+    /// Fills of the account in every market timed within `range`, a page at
+    /// a time, see `TradeTape::trades_for_account`
+    pub fn trades_for_account(
+        &self,
+        account: AccountId,
+        range: Range<SystemTime>,
+        page: PageRequest,
+    ) -> TradePage<'_, Asset> {
+        self.tape.trades_for_account(account, range, page)
+    }
+
+    /// Flag the account as market maker, its orders count as quotes on halt
+    pub fn set_market_maker(&mut self, account: AccountId, market_maker: bool) {
+        if market_maker {
+            self.market_makers.insert(account);
+        } else {
+            self.market_makers.remove(&account);
+        }
+    }
+
+    pub fn is_halted(&self, symbol: Symbol<Asset>) -> bool {
+        self.halted.contains(&symbol)
+    }
+
+    /// Stop trading in the market and clear resting orders as `policy` says.
+    ///
+    /// Until resumed only cancels are accepted. Returns the cancellations, or
+    /// nothing if the market is unknown or already halted.
+    pub fn halt(
+        &mut self,
+        symbol: Symbol<Asset>,
+        policy: HaltPolicy,
+    ) -> OrderProcessingResult<Asset> {
+        let book = match self.books.get_mut(&symbol) {
+            Some(book) if self.halted.insert(symbol) => book,
+            _ => return vec![],
+        };
+
+        let accounts = &self.accounts;
+        let market_makers = &self.market_makers;
+        let results = match policy {
+            HaltPolicy::LeaveIntact => vec![],
+            HaltPolicy::CancelAll => book.cancel_resting(|_| true),
+            HaltPolicy::CancelQuotes => book.cancel_resting(|order| {
+                accounts
+                    .owner(order.order_id)
+                    .is_some_and(|(account, _)| market_makers.contains(&account))
+            }),
+        };
+        self.track_results(&results);
+        results
+    }
+
+    /// Reopen halted market, returns false if it was not halted
+    pub fn resume(&mut self, symbol: Symbol<Asset>) -> bool {
+        self.halted.remove(&symbol)
+    }
+
+    /// Phase the market is in, None if it is unknown
+    pub fn trading_status(&self, symbol: Symbol<Asset>) -> Option<TradingStatus> {
+        self.books.get(&symbol).map(Orderbook::trading_status)
+    }
+
+    /// Move the market to another phase, e.g. from post-only to trading
+    /// once a new listing opens. Independent of halts, a halted market only
+    /// takes cancels whatever its status. Returns false if the market is
+    /// unknown.
+    pub fn set_trading_status(&mut self, symbol: Symbol<Asset>, status: TradingStatus) -> bool {
+        match self.books.get_mut(&symbol) {
+            Some(book) => {
+                book.set_trading_status(status);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Price the market would uncross at, see `Orderbook::indicative_price`
+    pub fn indicative_price(&self, symbol: Symbol<Asset>) -> Option<IndicativeUncross> {
+        self.books.get(&symbol)?.indicative_price()
+    }
+
+    /// End the auction call of the market, see `Orderbook::uncross`. Auction
+    /// trades pay fees and count for accounts like any other. None if the
+    /// market is unknown, no events if it is halted or not in an auction.
+    pub fn uncross(&mut self, symbol: Symbol<Asset>) -> Option<OrderProcessingResult<Asset>> {
+        if self.halted.contains(&symbol) {
+            return self.books.contains_key(&symbol).then(Vec::new);
+        }
+        let mut results = self.books.get_mut(&symbol)?.uncross();
+        let accounts = &self.accounts;
+        if let Some(fees) = self.fees.as_mut() {
+            fees.apply(&mut results, |order_id| accounts.owner(order_id));
+        }
+        self.tape.record(
+            symbol,
+            |order_id| accounts.owner(order_id).map(|(account, _)| account),
+            &results,
+        );
+        self.track_results(&results);
+        Some(results)
+    }
+
+    /// Limit orders accounts may stack in the market, see `StackingLimits`.
+    ///
+    /// Applies to orders attributed with `submit_for`, new ones and amends
+    /// moving their price. Violations are rejected and logged for compliance.
+    pub fn set_stacking_limits(&mut self, symbol: Symbol<Asset>, limits: Option<StackingLimits>) {
+        match limits {
+            Some(limits) => self.stacking_limits.insert(symbol, limits),
+            None => self.stacking_limits.remove(&symbol),
+        };
+    }
+
+    /// Current parameters of the market
+    pub fn market_config(&self, symbol: Symbol<Asset>) -> Option<MarketConfig> {
+        let book = self.books.get(&symbol)?;
+        Some(MarketConfig {
+            price_scale: book.config().price_scale,
+            depth_limit: book.depth_limit(),
+            dust_threshold: book.dust_threshold().cloned(),
+            qty_limits: book.qty_limits().cloned(),
+            price_band: book.price_band().cloned(),
+            stacking_limits: self.stacking_limits.get(&symbol).cloned(),
+            trading_status: book.trading_status(),
+        })
+    }
+
+    /// Replace the parameters of a live market without a restart.
+    ///
+    /// The config is validated and applied as a whole or not at all, a new
+    /// tick size is refused while resting orders are off it. Limits apply to
+    /// requests from now on, resting orders are left alone.
+    pub fn update_market_config(
+        &mut self,
+        symbol: Symbol<Asset>,
+        config: MarketConfig,
+    ) -> Result<MarketConfigChanged<Asset>, ConfigError> {
+        config.validate()?;
+        let previous = self
+            .market_config(symbol)
+            .ok_or(ConfigError::UnknownMarket)?;
+
+        let book = self.books.get_mut(&symbol).unwrap();
+        if !book.set_price_scale(config.price_scale) {
+            return Err(ConfigError::OffTick);
+        }
+        book.set_depth_limit(config.depth_limit);
+        book.set_dust_threshold(config.dust_threshold.clone());
+        book.set_qty_limits(config.qty_limits.clone())
+            .map_err(ConfigError::Invalid)?;
+        book.set_price_band(config.price_band.clone());
+        book.set_trading_status(config.trading_status);
+        let ts = book.clock().now();
+        self.set_stacking_limits(symbol, config.stacking_limits.clone());
+
+        Ok(MarketConfigChanged {
+            symbol,
+            previous,
+            config,
+            ts,
+        })
+    }
+
+    /// Take compliance events logged since the last call
+    pub fn drain_compliance_events(&mut self) -> Vec<ComplianceEvent<Asset>> {
+        std::mem::take(&mut self.compliance_events)
+    }
+
+    /// Liquidate every position of the account in one go, as a margin engine
+    /// does when the account falls below maintenance.
+    ///
+    /// Resting and stop orders of the account are cancelled first, then each
+    /// market position is closed by a liquidation market order. The order is
+    /// protected: it only takes liquidity priced within `max_slippage`
+    /// (a fraction, 0.05 for 5%) of the touch, the rest stays open and is
+    /// reported as unfilled. Halted markets are left alone.
+    pub fn stop_out(
+        &mut self,
+        account: AccountId,
+        max_slippage: &BigDecimal,
+    ) -> StopOutReport<Asset> {
+        let mut cancelled: OrderProcessingResult<Asset> = vec![];
+        let mut resting: Vec<(Uuid, Symbol<Asset>)> = self.accounts.orders_of(account).collect();
+        resting.sort_by_key(|(order_id, _)| *order_id);
+        for (order_id, symbol) in resting {
+            let cancel = match self.books.get(&symbol) {
+                Some(book) if book.bid_queue.get(order_id).is_some() => {
+                    orders::limit_order_cancel_request(order_id, OrderSide::Bid)
+                }
+                Some(book) if book.ask_queue.get(order_id).is_some() => {
+                    orders::limit_order_cancel_request(order_id, OrderSide::Ask)
+                }
+                // parked stops would trigger after the stop-out
+                Some(book) if book.has_stop(order_id) => orders::cancel_order_request(order_id),
+                _ => continue,
+            };
+            cancelled.extend(self.submit(symbol, cancel));
+        }
+
+        let positions = self.accounts.instrument_positions(account).to_vec();
+        let mut legs = vec![];
+        for (symbol, position) in positions {
+            if position.is_zero() || self.is_halted(symbol) {
+                continue;
+            }
+            let book = match self.books.get(&symbol) {
+                Some(book) => book,
+                None => continue,
+            };
+
+            let side = if position > BigDecimal::zero() {
+                OrderSide::Ask
+            } else {
+                OrderSide::Bid
+            };
+            let position = position.abs();
+            let qty = protected_qty(book, side, &position, max_slippage);
+
+            let mut leg = StopOutLeg {
+                symbol,
+                side,
+                position,
+                filled: BigDecimal::zero(),
+                notional: BigDecimal::zero(),
+                results: vec![],
+            };
+            if !qty.is_zero() {
+                let order = orders::new_market_order_request(
+                    symbol.0,
+                    symbol.1,
+                    side,
+                    qty,
+                    book.clock().now(),
+                )
+                .with_liquidation_flag();
+                let order_id = order.order_id();
+                leg.results = self.submit_for(account, symbol, order);
+
+                for result in &leg.results {
+                    if let Ok(Success::Filled {
+                        order_id: filled_id,
+                        price,
+                        qty,
+                        ..
+                    })
+                    | Ok(Success::PartiallyFilled {
+                        order_id: filled_id,
+                        price,
+                        qty,
+                        ..
+                    }) = result
+                    {
+                        if *filled_id == order_id {
+                            leg.filled += qty;
+                            leg.notional += price * qty;
+                        }
+                    }
+                }
+            }
+            legs.push(leg);
+        }
+
+        StopOutReport {
+            account,
+            cancelled,
+            legs,
+        }
+    }
+
+    /// Start a new trading day for account statistics
+    pub fn roll_day(&mut self) {
+        self.accounts.roll_day();
+    }
+
+    /// Submit several orders, possibly across different markets, as one request.
+    ///
+    /// With `all_or_nothing` every leg goes through the checks of `submit` up
+    /// front, balances covering all legs together, and nothing is processed
+    /// if any of them fails: failing legs report the reason, the rest report
+    /// `Failed::BasketRejected`.
+    /// Otherwise legs are processed one by one independently.
+    pub fn submit_basket(
+        &mut self,
+        basket: Vec<BasketLeg<Asset>>,
+        all_or_nothing: bool,
+    ) -> BasketResult<Asset> {
+        self.process_basket(None, basket, all_or_nothing)
+    }
+
+    /// Submit basket of the account like `submit_basket`, legs go through
+    /// `submit_for`
+    pub fn submit_basket_for(
+        &mut self,
+        account: AccountId,
+        basket: Vec<BasketLeg<Asset>>,
+        all_or_nothing: bool,
+    ) -> BasketResult<Asset> {
+        self.process_basket(Some(account), basket, all_or_nothing)
+    }
+
+    /// Expire resting DAY orders in every market, see `Orderbook::expire_day_orders`
+    pub fn expire_day_orders(&mut self, ts: SystemTime) -> OrderProcessingResult<Asset> {
+        let mut results: OrderProcessingResult<Asset> = vec![];
+        for symbol in &self.listed {
+            results.extend(self.books.get_mut(symbol).unwrap().expire_day_orders(ts));
+        }
+        self.track_results(&results);
+        results
+    }
+
+    /// Expire resting GTD orders in every market, see `Orderbook::expire_orders`
+    pub fn expire_orders(&mut self, ts: SystemTime) -> OrderProcessingResult<Asset> {
+        let mut results: OrderProcessingResult<Asset> = vec![];
+        for symbol in &self.listed {
+            results.extend(self.books.get_mut(symbol).unwrap().expire_orders(ts));
+        }
+        self.track_results(&results);
+        results
+    }
+
+    /// Run housekeeping of every book
+    pub fn maintain(&mut self) -> usize {
+        self.books.values_mut().map(Orderbook::maintain).sum()
+    }
+
+    /// Sweep book sides whenever `process_queued` leaves the queue empty,
+    /// never by default
+    pub fn set_idle_maintenance(&mut self, maintenance: Option<IdleMaintenance>) {
+        self.idle_maintenance = maintenance;
+    }
+
+    /// Stalled indices dropped by idle maintenance since the engine started
+    pub fn compacted(&self) -> u64 {
+        self.compacted
+    }
+
+    /// Clock timing queued requests against the latency budget
+    pub fn set_clock(&mut self, clock: Box<dyn Clock + Send>) {
+        self.clock = clock;
+    }
+
+    /// Bound the request queue, unbounded by default
+    pub fn set_load_shedding(&mut self, load_shedding: Option<LoadShedding>) {
+        self.load_shedding = load_shedding;
+    }
+
+    pub fn load_shedding(&self) -> Option<LoadShedding> {
+        self.load_shedding
+    }
+
+    /// Requests shed since the engine started
+    pub fn shed(&self) -> u64 {
+        self.shed
+    }
+
+    /// Queue request for `process_queued`.
+    ///
+    /// Refused requests come back with the reason: the engine is shut down or
+    /// the queue is full.
+    pub fn enqueue(
+        &mut self,
+        symbol: Symbol<Asset>,
+        order: OrderRequest<Asset>,
+    ) -> Result<(), OrderProcessingResult<Asset>> {
+        if self.shut_down {
+            return Err(self.reject(&order, ERR_ENGINE_SHUT_DOWN));
+        }
+        let full = self
+            .load_shedding
+            .and_then(|load_shedding| load_shedding.max_queued)
+            .is_some_and(|max_queued| self.queued.len() >= max_queued);
+        if full && !matches!(order, OrderRequest::CancelOrder { .. }) {
+            return Err(self.shed_request(&order));
+        }
+        self.queued.push_back(QueuedRequest {
+            symbol,
+            order,
+            arrived: self.clock.now(),
+        });
+        Ok(())
+    }
+
+    /// Number of requests waiting in the queue
+    pub fn queued(&self) -> usize {
+        self.queued.len()
+    }
+
+    /// Submit queued requests in arrival order, shedding those that waited
+    /// past the latency budget
+    pub fn process_queued(&mut self) -> Vec<(Symbol<Asset>, OrderProcessingResult<Asset>)> {
+        let max_wait = self
+            .load_shedding
+            .and_then(|load_shedding| load_shedding.max_wait);
+        let mut processed = vec![];
+        while let Some(QueuedRequest {
+            symbol,
+            order,
+            arrived,
+        }) = self.queued.pop_front()
+        {
+            let late = max_wait.is_some_and(|max_wait| {
+                self.clock
+                    .now()
+                    .duration_since(arrived)
+                    .is_ok_and(|waited| waited > max_wait)
+            });
+            let results = if late && !matches!(order, OrderRequest::CancelOrder { .. }) {
+                self.shed_request(&order)
+            } else {
+                self.route(symbol, order)
+            };
+            processed.push((symbol, results));
+        }
+        self.maintain_idle();
+        processed
+    }
+
+    /// Compact book sides with enough stalled indices, in listing order
+    fn maintain_idle(&mut self) {
+        let maintenance = match self.idle_maintenance {
+            Some(maintenance) => maintenance,
+            None => return,
+        };
+        let compact = |queue: &mut OrderQueue<_>| {
+            if queue.stats().stalled < maintenance.min_stalled {
+                0
+            } else if maintenance.shrink {
+                queue.maintain()
+            } else {
+                queue.compact()
+            }
+        };
+        for symbol in &self.listed {
+            let book = self.books.get_mut(symbol).unwrap();
+            let dropped = compact(&mut book.bid_queue) + compact(&mut book.ask_queue);
+            self.compacted += dropped as u64;
+        }
+    }
+
+    pub fn is_shut_down(&self) -> bool {
+        self.shut_down
+    }
+
+    pub fn snapshot_state(&self, symbol: Symbol<Asset>) -> Option<SnapshotState> {
+        let book = self.books.get(&symbol)?;
+        let last = self.snapshots.get(&symbol);
+        Some(SnapshotState {
+            seq: book.change_seq(),
+            snapshot_seq: last.map(|(seq, _)| *seq),
+            snapshot_at: last.map(|(_, ts)| *ts),
+        })
+    }
+
+    /// Call `snapshot` with every book changed since its last snapshot,
+    /// meant to run periodically so quiet markets are not rewritten.
+    ///
+    /// Returns the markets snapshotted. A failed snapshot leaves its market
+    /// dirty and stops the round, markets after it are tried next round.
+    pub fn snapshot_changed<F>(
+        &mut self,
+        ts: SystemTime,
+        mut snapshot: F,
+    ) -> io::Result<Vec<Symbol<Asset>>>
+    where
+        F: FnMut(Symbol<Asset>, &Orderbook<Asset>) -> io::Result<()>,
+    {
+        let mut taken = vec![];
+        for symbol in &self.listed {
+            let book = &self.books[symbol];
+            let seq = book.change_seq();
+            if self.snapshots.get(symbol).map(|(last, _)| *last) == Some(seq) {
+                continue;
+            }
+            snapshot(*symbol, book)?;
+            self.snapshots.insert(*symbol, (seq, ts));
+            taken.push(*symbol);
+        }
+        Ok(taken)
+    }
+
+    /// Every book of the engine as it stands, ready to be serialized as one
+    /// document.
+    ///
+    /// The snapshot borrows the books, so no request can reach any of them
+    /// until it is dropped and all markets are seen at the same point, each
+    /// with its sequence numbers. Requests still queued are not in it, call
+    /// `process_queued` first to cover them. Account positions are left to
+    /// the caller.
+    pub fn snapshot_all(&self, ts: SystemTime) -> EngineSnapshot<'_, Asset> {
+        let books = self
+            .listed
+            .iter()
+            .map(|symbol| {
+                let book = &self.books[symbol];
+                BookSnapshot {
+                    symbol: *symbol,
+                    halted: self.halted.contains(symbol),
+                    change_seq: book.change_seq(),
+                    event_seq: book.event_seq(),
+                    book,
+                }
+            })
+            .collect();
+        EngineSnapshot { ts, books }
+    }
+
+    /// Put back the books of a snapshot taken by `snapshot_all`, returning
+    /// how many were restored.
+    ///
+    /// Books of markets already listed are replaced, others are listed after
+    /// them in snapshot order, halted as they were. Nothing changes unless
+    /// every book has the sequence numbers recorded with it. Books keep the
+    /// trade IDs they went on with under the sequential scheme and take the
+    /// generator of the engine otherwise.
+    pub fn restore_all(&mut self, snapshot: RestoredSnapshot<Asset>) -> io::Result<usize> {
+        for restored in &snapshot.books {
+            if restored.book.change_seq() != restored.change_seq
+                || restored.book.event_seq() != restored.event_seq
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "book of {:?} does not match its sequence numbers",
+                        restored.symbol
+                    ),
+                ));
+            }
+        }
+
+        let restored = snapshot.books.len();
+        for RestoredBook {
+            symbol,
+            halted,
+            change_seq,
+            mut book,
+            ..
+        } in snapshot.books
+        {
+            if self.trade_id_scheme != TradeIdScheme::Sequential {
+                book.set_trade_id_generator(self.trade_id_generator());
+            }
+            if let Some(clock) = &self.logical_clock {
+                book.set_clock(Box::new(clock.clone()));
+            }
+            if self.books.insert(symbol, book).is_none() {
+                self.listed.push(symbol);
+            }
+            if halted {
+                self.halted.insert(symbol);
+            } else {
+                self.halted.remove(&symbol);
+            }
+            self.snapshots.insert(symbol, (change_seq, snapshot.ts));
+        }
+        Ok(restored)
+    }
+
+    /// Stop the engine for a clean restart.
+    ///
+    /// From now on requests are rejected and nothing is queued. Requests
+    /// already queued are still processed, then the journal is flushed and
+    /// `snapshot` is called with every book. Should flushing or a snapshot
+    /// fail, the engine stays shut down and calling again retries both.
+    pub fn shutdown<F>(
+        &mut self,
+        ts: SystemTime,
+        mut snapshot: F,
+    ) -> io::Result<EngineShutdown<Asset>>
+    where
+        F: FnMut(Symbol<Asset>, &Orderbook<Asset>) -> io::Result<()>,
+    {
+        self.shut_down = true;
+        let drained = self.process_queued();
+
+        if let Some(journal) = self.journal.as_mut() {
+            journal.flush()?;
+        }
+
+        let mut snapshots = Vec::with_capacity(self.books.len());
+        for symbol in &self.listed {
+            let book = &self.books[symbol];
+            snapshot(*symbol, book)?;
+            self.snapshots.insert(*symbol, (book.change_seq(), ts));
+            snapshots.push(*symbol);
+        }
+
+        Ok(EngineShutdown {
+            drained,
+            snapshots,
+            ts,
+        })
+    }
+
+    /* Helpers */
+
+    fn route(
+        &mut self,
+        symbol: Symbol<Asset>,
+        order: OrderRequest<Asset>,
+    ) -> OrderProcessingResult<Asset> {
+        if !self.books.contains_key(&symbol) {
+            return vec![Err(Failed::ValidationFailed(String::from(
+                ERR_UNKNOWN_MARKET,
+            )))];
+        }
+
+        let halted = self.halted.contains(&symbol);
+        if halted && !matches!(order, OrderRequest::CancelOrder { .. }) {
+            return vec![Err(Failed::ValidationFailed(String::from(
+                ERR_MARKET_HALTED,
+            )))];
+        }
+
+        if let Err(reason) = self.check_stacking(symbol, &order) {
+            return vec![Err(Failed::ValidationFailed(String::from(reason)))];
+        }
+
+        if let Err(reason) = self.check_balance(symbol, &order) {
+            return self.reject(&order, reason);
+        }
+
+        if let Some(journal) = self.journal.as_mut() {
+            if journal.append(symbol, &order).is_err() {
+                let failed = Failed::JournalFailed(order.order_id());
+                return self.fail(&order, failed);
+            }
+        }
+
+        let book = self.books.get_mut(&symbol).unwrap();
+        let order_id = order.order_id();
+        // limit and pre-trade opposite best of takers, for price improvement
+        let taker = match &order {
+            OrderRequest::NewMarketOrder { side, .. } => Some((*side, None)),
+            OrderRequest::NewLimitOrder { side, price, .. } => Some((*side, Some(price.clone()))),
+            _ => None,
+        }
+        .map(|(side, limit)| {
+            let opposite = match side {
+                OrderSide::Bid => &mut book.ask_queue,
+                OrderSide::Ask => &mut book.bid_queue,
+            };
+            (limit, opposite.peek().map(|best| best.price.clone()))
+        });
+        let account = self.accounts.owner(order_id).map(|(account, _)| account);
+        let mut results = book.process_order(order);
+        let live = book.bid_queue.get(order_id).is_some()
+            || book.ask_queue.get(order_id).is_some()
+            || book.has_stop(order_id);
+        // fills release the owners of filled orders, take them first
+        let accounts = &self.accounts;
+        if let Some(fees) = self.fees.as_mut() {
+            fees.apply(&mut results, |order_id| accounts.owner(order_id));
+        }
+        self.tape.record(
+            symbol,
+            |order_id| accounts.owner(order_id).map(|(account, _)| account),
+            &results,
+        );
+        self.track_results(&results);
+        if let Some((limit, bbo)) = taker {
+            self.execution.record(
+                symbol,
+                account,
+                order_id,
+                limit.as_ref(),
+                bbo.as_ref(),
+                &results,
+            );
+        }
+        if !live {
+            self.accounts.forget_order(order_id);
+        }
+        results
+    }
+
+    fn trade_id_generator(&self) -> Box<dyn TradeIdGenerator + Send> {
+        match (self.trade_id_scheme, &self.snowflake) {
+            (TradeIdScheme::Snowflake { .. }, Some(shared)) => Box::new(shared.clone()),
+            (TradeIdScheme::Uuid, _) => Box::new(UuidTradeIds),
+            _ => Box::new(SequentialTradeIds::new()),
+        }
+    }
+
+    /// Fail a request that never reaches the book, forgetting the order the
+    /// account registered for it
+    fn reject(
+        &mut self,
+        order: &OrderRequest<Asset>,
+        reason: &str,
+    ) -> OrderProcessingResult<Asset> {
+        self.fail(order, Failed::ValidationFailed(String::from(reason)))
+    }
+
+    /// Like `reject`, with a failure other than a validation one
+    fn fail(
+        &mut self,
+        order: &OrderRequest<Asset>,
+        failed: Failed,
+    ) -> OrderProcessingResult<Asset> {
+        if order.is_new_order() {
+            self.accounts.forget_order(order.order_id());
+        }
+        vec![Err(failed)]
+    }
+
+    fn shed_request(&mut self, order: &OrderRequest<Asset>) -> OrderProcessingResult<Asset> {
+        self.shed += 1;
+        if order.is_new_order() {
+            self.accounts.forget_order(order.order_id());
+        }
+        vec![Err(Failed::Overloaded(order.order_id()))]
+    }
+
+    fn check_stacking(
+        &mut self,
+        symbol: Symbol<Asset>,
+        order: &OrderRequest<Asset>,
+    ) -> Result<(), &'static str> {
+        let account = match self.accounts.owner(order.order_id()) {
+            Some((account, _)) => account,
+            None => return Ok(()),
+        };
+        match self.stacking_breach(account, symbol, order) {
+            Some(event) => {
+                self.compliance_events.push(event);
+                // the order never reached the book
+                if let OrderRequest::NewLimitOrder { order_id, .. } = order {
+                    self.accounts.forget_order(*order_id);
+                }
+                Err(ERR_STACKING_LIMIT)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Event recorded if the order of the account breaks a stacking limit
+    fn stacking_breach(
+        &self,
+        account: AccountId,
+        symbol: Symbol<Asset>,
+        order: &OrderRequest<Asset>,
+    ) -> Option<ComplianceEvent<Asset>> {
+        let (order_id, side, price, ts) = match order {
+            OrderRequest::NewLimitOrder {
+                order_id,
+                side,
+                price,
+                ts,
+                ..
+            } => (*order_id, *side, price, *ts),
+            OrderRequest::AmendOrder {
+                id,
+                side,
+                price: Some(price),
+                ts,
+                ..
+            } => (*id, *side, price, *ts),
+            _ => return None,
+        };
+        let (limits, book) = match (self.stacking_limits.get(&symbol), self.books.get(&symbol)) {
+            (Some(limits), Some(book)) => (limits, book),
+            _ => return None,
+        };
+
+        let order_queue = match side {
+            OrderSide::Bid => &book.bid_queue,
+            OrderSide::Ask => &book.ask_queue,
+        };
+        let resting = self
+            .accounts
+            .orders_of(account)
+            .filter(|(other_id, other_symbol)| *other_id != order_id && *other_symbol == symbol)
+            .filter_map(|(other_id, _)| order_queue.get(other_id))
+            .map(|other| &other.price);
+
+        let (rule, count) = limits.check(price, resting).err()?;
+        Some(ComplianceEvent {
+            account,
+            symbol,
+            order_id,
+            side,
+            price: price.clone(),
+            rule,
+            count,
+            ts,
+        })
+    }
+
+    fn process_basket(
+        &mut self,
+        owner: Option<AccountId>,
+        basket: Vec<BasketLeg<Asset>>,
+        all_or_nothing: bool,
+    ) -> BasketResult<Asset> {
+        if all_or_nothing {
+            let mut reserved = HashMap::new();
+            let verdicts: Vec<Result<(), String>> = basket
+                .iter()
+                .map(|(symbol, order)| self.check_leg(owner, *symbol, order, &mut reserved))
+                .collect();
+
+            if verdicts.iter().any(|verdict| verdict.is_err()) {
+                let legs = basket
+                    .iter()
+                    .zip(verdicts)
+                    .map(|((_, order), verdict)| match verdict {
+                        Err(reason) => vec![Err(Failed::ValidationFailed(reason))],
+                        Ok(()) => vec![Err(Failed::BasketRejected(order.order_id()))],
+                    })
+                    .collect();
+
+                return BasketResult {
+                    accepted: false,
+                    legs,
+                };
+            }
+        }
+
+        let legs = basket
+            .into_iter()
+            .map(|(symbol, order)| match owner {
+                Some(account) => self.submit_for(account, symbol, order),
+                None => self.submit(symbol, order),
+            })
+            .collect();
+
+        BasketResult {
+            accepted: true,
+            legs,
+        }
+    }
+
+    fn check_balance(
+        &self,
+        symbol: Symbol<Asset>,
+        order: &OrderRequest<Asset>,
+    ) -> Result<(), &'static str> {
+        let account = match self.accounts.owner(order.order_id()) {
+            Some((account, _)) => account,
+            None => return Ok(()),
+        };
+        for (asset, amount) in self.balance_needed(symbol, order) {
+            if self.available(account, asset, Some(order.order_id())) < amount {
+                return Err(ERR_INSUFFICIENT_BALANCE);
+            }
+        }
+        Ok(())
+    }
+
+    /// Assets the order has to be covered by, none when balances are not
+    /// checked
+    fn balance_needed(
+        &self,
+        symbol: Symbol<Asset>,
+        order: &OrderRequest<Asset>,
+    ) -> Vec<(Asset, BigDecimal)> {
+        let book = match self.books.get(&symbol) {
+            Some(book) if self.balance_checks => book,
+            _ => return vec![],
+        };
+
+        let (side, price, qty) = match order {
+            OrderRequest::NewLimitOrder {
+                side, price, qty, ..
+            }
+            | OrderRequest::NewStopLimitOrder {
+                side, price, qty, ..
+            } => (*side, Some(price.clone()), qty.clone()),
+            OrderRequest::NewStopOrder {
+                side,
+                trigger_price,
+                qty,
+                ..
+            } => (*side, Some(trigger_price.clone()), qty.clone()),
+            OrderRequest::NewMarketOrder { side, qty, .. } => (*side, None, qty.clone()),
+            OrderRequest::AmendOrder {
+                id,
+                side,
+                price,
+                qty,
+                ..
+            } => {
+                let resting = match side {
+                    OrderSide::Bid => book.bid_queue.get(*id),
+                    OrderSide::Ask => book.ask_queue.get(*id),
+                };
+                let resting = match resting {
+                    Some(resting) => resting,
+                    None => return vec![],
+                };
+                // the amendment names the total, fills so far hold nothing
+                let qty = match qty {
+                    Some(total) if *total <= resting.filled => return vec![],
+                    Some(total) => total - &resting.filled,
+                    None => resting.qty.clone(),
+                };
+                (
+                    *side,
+                    Some(price.clone().unwrap_or_else(|| resting.price.clone())),
+                    qty,
+                )
+            }
+            OrderRequest::CancelOrder { .. } => return vec![],
+        };
+
+        let (order_asset, price_asset) = symbol;
+        let notional = match &price {
+            Some(price) => price * &qty,
+            None => market_notional(book, side, &qty),
+        };
+        let mut needed = match side {
+            OrderSide::Ask => vec![(order_asset, qty.clone())],
+            OrderSide::Bid => vec![(price_asset, notional.clone())],
+        };
+        // the order may take liquidity at the highest rate, fees charged in
+        // the asset the fill pays out come out of the proceeds
+        if let Some(fees) = &self.fees {
+            let (fee_asset, fee) = fees.worst_taker_fee(symbol, &notional, &qty);
+            let received = match side {
+                OrderSide::Bid => order_asset,
+                OrderSide::Ask => price_asset,
+            };
+            if fee_asset != received {
+                match needed.iter_mut().find(|(asset, _)| *asset == fee_asset) {
+                    Some((_, amount)) => *amount += fee,
+                    None => needed.push((fee_asset, fee)),
+                }
+            }
+        }
+        needed
+    }
+
+    /// Balance of the account less what its open orders other than `except`
+    /// hold: price asset of bids, order asset of asks
+    fn available(&self, account: AccountId, asset: Asset, except: Option<Uuid>) -> BigDecimal {
+        let mut held = self.accounts.held(account, asset);
+        if let Some((owner, held_asset, amount)) = except.and_then(|id| self.accounts.hold(id)) {
+            if owner == account && held_asset == asset {
+                held -= amount;
+            }
+        }
+        self.accounts.balance(account, asset) - held
+    }
+
+    /// Pass results to the account tracker and update what the resting
+    /// orders they name hold
+    fn track_results(&mut self, results: &OrderProcessingResult<Asset>) {
+        self.accounts.on_results(results);
+        for event in results.iter().flatten() {
+            let order_id = event.order_id();
+            let hold = self.accounts.owner(order_id).and_then(|(_, symbol)| {
+                let book = self.books.get(&symbol)?;
+                let order = book
+                    .bid_queue
+                    .get(order_id)
+                    .or_else(|| book.ask_queue.get(order_id))?;
+                Some(match order.side {
+                    OrderSide::Bid => (symbol.1, &order.price * &order.qty),
+                    OrderSide::Ask => (symbol.0, order.qty.clone()),
+                })
+            });
+            self.accounts.set_hold(order_id, hold);
+        }
+    }
+
+    /// Every check `submit` runs before the book takes the leg, without
+    /// recording anything. Legs checked before hold their share of the
+    /// balance in `reserved`.
+    fn check_leg(
+        &self,
+        owner: Option<AccountId>,
+        symbol: Symbol<Asset>,
+        order: &OrderRequest<Asset>,
+        reserved: &mut HashMap<(AccountId, Asset), BigDecimal>,
+    ) -> Result<(), String> {
+        if self.shut_down {
+            return Err(String::from(ERR_ENGINE_SHUT_DOWN));
+        }
+        let book = match self.books.get(&symbol) {
+            Some(book) => book,
+            None => return Err(String::from(ERR_UNKNOWN_MARKET)),
+        };
+        if self.halted.contains(&symbol) && !matches!(order, OrderRequest::CancelOrder { .. }) {
+            return Err(String::from(ERR_MARKET_HALTED));
+        }
+        book.validate(order).map_err(String::from)?;
+
+        let owner = owner.or_else(|| {
+            self.accounts
+                .owner(order.order_id())
+                .map(|(account, _)| account)
+        });
+        let account = match owner {
+            Some(account) => account,
+            None => return Ok(()),
+        };
+        if self.stacking_breach(account, symbol, order).is_some() {
+            return Err(String::from(ERR_STACKING_LIMIT));
+        }
+        for (asset, amount) in self.balance_needed(symbol, order) {
+            let held = reserved
+                .entry((account, asset))
+                .or_insert_with(BigDecimal::zero);
+            *held += amount;
+            if self.available(account, asset, Some(order.order_id())) < *held {
+                return Err(String::from(ERR_INSUFFICIENT_BALANCE));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Price asset a market order on `side` for `qty` trades on the resting
+/// orders of the other side
+fn market_notional<Asset>(book: &Orderbook<Asset>, side: OrderSide, qty: &BigDecimal) -> BigDecimal
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    let opposite = match side {
+        OrderSide::Bid => &book.ask_queue,
+        OrderSide::Ask => &book.bid_queue,
+    };
+    let mut cost = BigDecimal::zero();
+    let mut left = qty.clone();
+    for (order, _, _) in opposite.ranked() {
+        if left.is_zero() {
+            break;
+        }
+        let taken = left.clone().min(order.qty.clone());
+        cost += &order.price * &taken;
+        left -= taken;
+    }
+    cost
+}
+
+/// Quantity up to `wanted` a market order on `side` can take from levels
+/// priced within `max_slippage` of the best opposite price
+fn protected_qty<Asset>(
+    book: &Orderbook<Asset>,
+    side: OrderSide,
+    wanted: &BigDecimal,
+    max_slippage: &BigDecimal,
+) -> BigDecimal
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    let (opposite, band) = match side {
+        OrderSide::Bid => (&book.ask_queue, BigDecimal::one() + max_slippage),
+        OrderSide::Ask => (&book.bid_queue, BigDecimal::one() - max_slippage),
+    };
+
+    let mut available = BigDecimal::zero();
+    let mut limit = None;
+    for (order, _, _) in opposite.ranked() {
+        let limit = limit.get_or_insert_with(|| &order.price * &band);
+        let within = match side {
+            OrderSide::Bid => order.price <= *limit,
+            OrderSide::Ask => order.price >= *limit,
+        };
+        if !within || available >= *wanted {
+            break;
+        }
+        available += &order.qty;
+    }
+
+    available.min(wanted.clone())
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::clock::ManualClock;
+    use super::super::domain::{OrderSide, TimeInForce};
+    use super::super::errors::{
+        CODE_ENGINE_SHUT_DOWN, CODE_OFF_TICK, CODE_OVERLOADED, CODE_UNKNOWN_MARKET,
+    };
+    use super::super::fees::{FeeRates, FeeTier, LiquidityRole};
+    use super::super::orderbook::Success;
+    use super::super::orders;
+    use super::super::price_key::PriceScale;
+    use super::super::session::{Session, TradingCalendar};
+    use super::super::surveillance::StackingRule;
+    use super::*;
+    use bigdecimal::BigDecimal;
+    use std::str::FromStr;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    #[derive(PartialEq, Eq, Hash, Debug, Copy, Clone, Serialize, Deserialize)]
+    pub enum Asset {
+        USD,
+        BTC,
+        ETH,
+    }
+
+    fn bigdec(num: &str) -> BigDecimal {
+        BigDecimal::from_str(num).unwrap()
+    }
+
+    fn get_engine() -> MatchingEngine<Asset> {
+        let mut engine = MatchingEngine::new();
+        assert!(engine.add_book(Asset::BTC, Asset::USD));
+        assert!(engine.add_book(Asset::ETH, Asset::USD));
+        assert!(!engine.add_book(Asset::ETH, Asset::USD));
+        engine
+    }
+
+    fn limit(order_asset: Asset, price: &str, qty: &str) -> BasketLeg<Asset> {
+        (
+            (order_asset, Asset::USD),
+            orders::new_limit_order_request(
+                order_asset,
+                Asset::USD,
+                OrderSide::Bid,
+                bigdec(price),
+                bigdec(qty),
+                SystemTime::now(),
+            ),
+        )
+    }
+
+    #[test]
+    fn send_across_threads() {
+        fn assert_send<T: Send>() {}
+        assert_send::<MatchingEngine<Asset>>();
+        assert_send::<Orderbook<Asset>>();
+        assert_send::<FeeEngine<Asset>>();
+    }
+
+    #[test]
+    fn remove_book() {
+        let mut engine = get_engine();
+        let (btc, eth) = ((Asset::BTC, Asset::USD), (Asset::ETH, Asset::USD));
+        let (_, order) = limit(Asset::BTC, "41000.0", "0.1");
+        engine.submit_for(1, btc, order);
+        engine.halt(btc, HaltPolicy::LeaveIntact);
+
+        let cancelled = engine.remove_book(btc).unwrap();
+        assert!(matches!(
+            cancelled.as_slice(),
+            [Ok(Success::Cancelled { .. })]
+        ));
+        assert!(engine.remove_book(btc).is_none());
+        assert_eq!(engine.markets(), &[eth]);
+        assert!(engine.account_summary(1).open_orders.is_empty());
+
+        let (_, order) = limit(Asset::BTC, "41000.0", "0.1");
+        match engine.submit(btc, order).as_slice() {
+            [Err(failed)] => assert_eq!(failed.code(), CODE_UNKNOWN_MARKET),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        // listed again, the market starts over
+        assert!(engine.add_book(Asset::BTC, Asset::USD));
+        assert_eq!(engine.markets(), &[eth, btc]);
+        assert!(!engine.is_halted(btc));
+        assert_eq!(engine.book(btc).unwrap().change_seq(), 0);
+        let (_, order) = limit(Asset::BTC, "41000.0", "0.1");
+        assert!(matches!(
+            engine.submit(btc, order).as_slice(),
+            [Ok(Success::Accepted { .. })]
+        ));
+    }
+
+    #[test]
+    fn basket_across_markets() {
+        let mut engine = get_engine();
+        let result = engine.submit_basket(
+            vec![
+                limit(Asset::BTC, "41000.0", "0.1"),
+                limit(Asset::ETH, "1600.0", "2.0"),
+            ],
+            true,
+        );
+
+        assert!(result.accepted);
+        assert_eq!(result.legs.len(), 2);
+        for leg in result.legs {
+            match leg.as_slice() {
+                [Ok(Success::Accepted { .. })] => (),
+                _ => panic!("unexpected events"),
+            }
+        }
+
+        let book = engine.book_mut((Asset::ETH, Asset::USD)).unwrap();
+        assert_eq!(book.bid_queue.peek().unwrap().price, bigdec("1600.0"));
+    }
+
+    #[test]
+    fn basket_all_or_nothing_rejected() {
+        let mut engine = get_engine();
+        let result = engine.submit_basket(
+            vec![
+                limit(Asset::BTC, "41000.0", "0.1"),
+                limit(Asset::ETH, "1600.0", "0"),
+            ],
+            true,
+        );
+
+        assert!(!result.accepted);
+        match result.legs[0].as_slice() {
+            [Err(Failed::BasketRejected(_))] => (),
+            _ => panic!("unexpected events"),
+        }
+        match result.legs[1].as_slice() {
+            [Err(Failed::ValidationFailed(_))] => (),
+            _ => panic!("unexpected events"),
+        }
+
+        // nothing reached the books
+        let book = engine.book_mut((Asset::BTC, Asset::USD)).unwrap();
+        assert!(book.bid_queue.peek().is_none());
+    }
+
+    #[test]
+    fn basket_all_or_nothing_engine_checks() {
+        let (btc, eth) = ((Asset::BTC, Asset::USD), (Asset::ETH, Asset::USD));
+        let mut engine = get_engine();
+        engine.set_balance_checks(true);
+        engine.deposit(1, Asset::USD, &bigdec("5000"));
+
+        // either leg is covered on its own, not both together
+        let result = engine.submit_basket_for(
+            1,
+            vec![
+                limit(Asset::BTC, "4000", "1"),
+                limit(Asset::ETH, "1600", "1"),
+            ],
+            true,
+        );
+        assert!(!result.accepted);
+        assert!(matches!(
+            result.legs[0].as_slice(),
+            [Err(Failed::BasketRejected(_))]
+        ));
+        match result.legs[1].as_slice() {
+            [Err(Failed::ValidationFailed(reason))] => assert_eq!(reason, ERR_INSUFFICIENT_BALANCE),
+            _ => panic!("unexpected events"),
+        }
+        assert_eq!(engine.balance_available(1, Asset::USD), bigdec("5000"));
+
+        engine.halt(eth, HaltPolicy::LeaveIntact);
+        let result = engine.submit_basket_for(
+            1,
+            vec![limit(Asset::BTC, "100", "1"), limit(Asset::ETH, "100", "1")],
+            true,
+        );
+        assert!(!result.accepted);
+        match result.legs[1].as_slice() {
+            [Err(Failed::ValidationFailed(reason))] => assert_eq!(reason, ERR_MARKET_HALTED),
+            _ => panic!("unexpected events"),
+        }
+
+        // nothing reached the books
+        for symbol in [btc, eth] {
+            assert!(engine.book_mut(symbol).unwrap().bid_queue.peek().is_none());
+        }
+        assert_eq!(engine.balance_available(1, Asset::USD), bigdec("5000"));
+    }
+
+    #[test]
+    fn basket_partial_unknown_market() {
+        let mut engine = get_engine();
+        let result = engine.submit_basket(
+            vec![
+                limit(Asset::BTC, "41000.0", "0.1"),
+                (
+                    (Asset::USD, Asset::BTC),
+                    orders::new_market_order_request(
+                        Asset::USD,
+                        Asset::BTC,
+                        OrderSide::Bid,
+                        bigdec("1.0"),
+                        SystemTime::now(),
+                    ),
+                ),
+            ],
+            false,
+        );
+
+        assert!(result.accepted);
+        match result.legs[0].as_slice() {
+            [Ok(Success::Accepted { .. })] => (),
+            _ => panic!("unexpected events"),
+        }
+        match result.legs[1].as_slice() {
+            [Err(Failed::ValidationFailed(_))] => (),
+            _ => panic!("unexpected events"),
+        }
+    }
+
+    #[test]
+    fn trade_ids_unique_across_books() {
+        let mut engine = MatchingEngine::with_trade_ids(TradeIdScheme::Snowflake { node: 7 });
+        engine.add_book(Asset::BTC, Asset::USD);
+        engine.add_book(Asset::ETH, Asset::USD);
+
+        let mut trade_ids = vec![];
+        for order_asset in [Asset::BTC, Asset::ETH] {
+            let symbol = (order_asset, Asset::USD);
+            let (_, bid) = limit(order_asset, "100", "1");
+            engine.submit(symbol, bid);
+            let ask = orders::new_market_order_request(
+                order_asset,
+                Asset::USD,
+                OrderSide::Ask,
+                bigdec("1"),
+                SystemTime::now(),
+            );
+            let fills: Vec<_> = engine
+                .submit(symbol, ask)
+                .into_iter()
+                .filter_map(|result| match result {
+                    Ok(Success::Filled { trade_id, .. }) => Some(trade_id),
+                    _ => None,
+                })
+                .collect();
+            // both sides of the trade share the ID
+            assert_eq!(fills.len(), 2);
+            assert_eq!(fills[0], fills[1]);
+            trade_ids.push(fills[0]);
+        }
+        assert_ne!(trade_ids[0], trade_ids[1]);
+    }
+
+    #[test]
+    fn stacking_limit_rejects() {
+        let mut engine = get_engine();
+        let symbol = (Asset::BTC, Asset::USD);
+        engine.set_stacking_limits(
+            symbol,
+            Some(StackingLimits {
+                max_per_level: Some(2),
+                max_in_range: None,
+            }),
+        );
+
+        for _ in 0..2 {
+            let (_, bid) = limit(Asset::BTC, "100", "1");
+            engine.submit_for(1, symbol, bid);
+        }
+        let (_, stacked) = limit(Asset::BTC, "100", "1");
+        let stacked_id = stacked.order_id();
+        match engine.submit_for(1, symbol, stacked).as_slice() {
+            [Err(Failed::ValidationFailed(reason))] => assert_eq!(reason, ERR_STACKING_LIMIT),
+            _ => panic!("unexpected events"),
+        }
+        // other accounts and other levels are unaffected
+        let (_, other) = limit(Asset::BTC, "100", "1");
+        assert!(engine.submit_for(2, symbol, other)[0].is_ok());
+        let (_, lower) = limit(Asset::BTC, "99", "1");
+        let lower_id = lower.order_id();
+        assert!(engine.submit_for(1, symbol, lower)[0].is_ok());
+
+        // nor can an amend move an order into the full level
+        let amend = orders::amend_order_price_request(
+            lower_id,
+            OrderSide::Bid,
+            bigdec("100"),
+            SystemTime::now(),
+        );
+        assert!(engine.submit(symbol, amend)[0].is_err());
+
+        let events = engine.drain_compliance_events();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].order_id, stacked_id);
+        assert_eq!(events[0].rule, StackingRule::PriceLevel);
+        assert_eq!(events[0].count, 3);
+        assert_eq!(events[1].order_id, lower_id);
+        assert!(engine.drain_compliance_events().is_empty());
+        assert_eq!(engine.account_summary(1).open_orders.len(), 3);
+    }
+    #[test]
+    fn fees_on_fills() {
+        let mut engine = get_engine();
+        let symbol = (Asset::BTC, Asset::USD);
+        let fees = FeeEngine::new(
+            vec![
+                FeeTier {
+                    min_volume: bigdec("0"),
+                    rates: FeeRates::from_bps(2, 7),
+                },
+                FeeTier {
+                    min_volume: bigdec("300"),
+                    rates: FeeRates::from_bps(-1, 5),
+                },
+            ],
+            Duration::from_secs(3600),
+        )
+        .unwrap();
+        engine.set_fee_engine(Some(fees));
+        let order = |side, price: &str, qty: &str| {
+            orders::new_limit_order_request(
+                Asset::BTC,
+                Asset::USD,
+                side,
+                bigdec(price),
+                bigdec(qty),
+                SystemTime::now(),
+            )
+        };
+        let fees = |results: &OrderProcessingResult<Asset>| -> Vec<(LiquidityRole, BigDecimal)> {
+            results
+                .iter()
+                .filter_map(|result| match result {
+                    Ok(Success::Filled {
+                        role,
+                        fee,
+                        fee_asset,
+                        ..
+                    })
+                    | Ok(Success::PartiallyFilled {
+                        role,
+                        fee,
+                        fee_asset,
+                        ..
+                    }) => {
+                        assert_eq!(*fee_asset, fee.as_ref().map(|_| Asset::USD));
+                        fee.clone().map(|fee| (*role, fee))
+                    }
+                    _ => None,
+                })
+                .collect()
+        };
+
+        engine.submit_for(2, symbol, order(OrderSide::Ask, "100", "4"));
+        let results = engine.submit_for(1, symbol, order(OrderSide::Bid, "100", "3"));
+        assert_eq!(
+            fees(&results),
+            vec![
+                (LiquidityRole::Taker, bigdec("0.21")),
+                (LiquidityRole::Maker, bigdec("0.06"))
+            ]
+        );
+
+        // past 300 of volume, the maker earns a rebate; the taker is not attributed
+        let results = engine.submit(symbol, order(OrderSide::Bid, "100", "1"));
+        assert_eq!(
+            fees(&results),
+            vec![(LiquidityRole::Maker, bigdec("-0.01"))]
+        );
+        assert_eq!(
+            engine
+                .fee_engine_mut()
+                .unwrap()
+                .account_tier(2, SystemTime::now())
+                .tier,
+            1
+        );
+    }
+
+    #[test]
+    fn stop_out_protected() {
+        let mut engine = get_engine();
+        let symbol = (Asset::BTC, Asset::USD);
+        let order = |side, price: &str, qty: &str| {
+            orders::new_limit_order_request(
+                Asset::BTC,
+                Asset::USD,
+                side,
+                bigdec(price),
+                bigdec(qty),
+                SystemTime::now(),
+            )
+        };
+
+        // account 1 long 3 BTC with a bid still resting
+        engine.submit_for(2, symbol, order(OrderSide::Ask, "100", "3"));
+        engine.submit_for(1, symbol, order(OrderSide::Bid, "100", "3"));
+        engine.submit_for(1, symbol, order(OrderSide::Bid, "95", "1"));
+        // and a stop parked below the market
+        let stop = orders::new_stop_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Ask,
+            bigdec("90"),
+            bigdec("1"),
+            SystemTime::now(),
+        );
+        let stop_id = stop.order_id();
+        engine.submit_for(1, symbol, stop);
+        engine.submit_for(3, symbol, order(OrderSide::Bid, "99", "1"));
+        engine.submit_for(3, symbol, order(OrderSide::Bid, "97", "5"));
+
+        let report = engine.stop_out(1, &bigdec("0.02"));
+        assert_eq!(report.cancelled.len(), 2);
+        assert!(!engine.book_mut(symbol).unwrap().has_stop(stop_id));
+        assert_eq!(report.legs.len(), 1);
+        let leg = &report.legs[0];
+        assert_eq!(leg.side, OrderSide::Ask);
+        assert_eq!(leg.position, bigdec("3"));
+        // 97 is beyond 2% below the touch of 99
+        assert_eq!(leg.filled, bigdec("1"));
+        assert_eq!(leg.notional, bigdec("99"));
+        assert_eq!(leg.unfilled(), bigdec("2"));
+        assert!(!report.is_flat());
+
+        let summary = engine.account_summary(1);
+        assert!(summary.open_orders.is_empty());
+        assert_eq!(summary.positions[&Asset::BTC], bigdec("2"));
+
+        let report = engine.stop_out(1, &bigdec("0.05"));
+        assert!(report.is_flat());
+        assert_eq!(report.legs[0].notional, bigdec("194"));
+        assert!(engine.stop_out(1, &bigdec("0.05")).legs.is_empty());
+    }
+
+    #[test]
+    fn hot_config_reload() {
+        let mut engine = get_engine();
+        let symbol = (Asset::BTC, Asset::USD);
+        let (_, bid) = limit(Asset::BTC, "100.5", "1");
+        engine.submit(symbol, bid);
+
+        let halves = PriceScale::from_tick(&bigdec("0.5")).unwrap();
+        let config = MarketConfig {
+            price_scale: halves,
+            stacking_limits: Some(StackingLimits {
+                max_per_level: Some(1),
+                max_in_range: None,
+            }),
+            ..MarketConfig::default()
+        };
+        let changed = engine.update_market_config(symbol, config.clone()).unwrap();
+        assert_eq!(changed.previous, MarketConfig::default());
+        assert_eq!(changed.config, config);
+        assert_eq!(engine.market_config(symbol), Some(config));
+
+        // new tick applies to new orders
+        let (_, off_tick) = limit(Asset::BTC, "100.25", "1");
+        assert_eq!(
+            engine.submit(symbol, off_tick)[0]
+                .as_ref()
+                .unwrap_err()
+                .code(),
+            CODE_OFF_TICK
+        );
+        let (_, on_tick) = limit(Asset::BTC, "101", "1");
+        assert!(engine.submit(symbol, on_tick)[0].is_ok());
+
+        // whole units would strand the resting 100.5 bid
+        let whole = MarketConfig {
+            price_scale: PriceScale::from_tick(&bigdec("1")).unwrap(),
+            ..MarketConfig::default()
+        };
+        assert_eq!(
+            engine.update_market_config(symbol, whole),
+            Err(ConfigError::OffTick)
+        );
+        assert_eq!(engine.market_config(symbol).unwrap().price_scale, halves);
+        assert_eq!(
+            engine.update_market_config((Asset::USD, Asset::BTC), MarketConfig::default()),
+            Err(ConfigError::UnknownMarket)
+        );
+    }
+
+    #[test]
+    fn shutdown_drains_and_snapshots() {
+        let mut engine = get_engine();
+        let journal = Arc::new(Mutex::new(vec![]));
+        engine.set_journal(journal.clone());
+        let symbol = (Asset::BTC, Asset::USD);
+
+        let (_, bid) = limit(Asset::BTC, "100", "1");
+        engine.submit(symbol, bid);
+        let (_, queued) = limit(Asset::BTC, "101", "1");
+        assert!(engine.enqueue(symbol, queued).is_ok());
+        assert_eq!(engine.queued(), 1);
+
+        // a failed snapshot leaves the engine shut down, the retry completes
+        let error = engine
+            .shutdown(SystemTime::now(), |_, _| Err(io::ErrorKind::Other.into()))
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::Other);
+        assert!(engine.is_shut_down());
+        assert_eq!(engine.queued(), 0);
+
+        let mut best_bids = HashMap::new();
+        let shutdown = engine
+            .shutdown(SystemTime::now(), |symbol, book| {
+                best_bids.insert(
+                    symbol,
+                    book.bid_queue
+                        .ranked()
+                        .first()
+                        .map(|(order, _, _)| order.price.clone()),
+                );
+                Ok(())
+            })
+            .unwrap();
+        assert!(shutdown.drained.is_empty());
+        assert_eq!(shutdown.snapshots.len(), 2);
+        assert_eq!(best_bids[&symbol], Some(bigdec("101")));
+        assert_eq!(best_bids[&(Asset::ETH, Asset::USD)], None);
+        assert_eq!(journal.lock().unwrap().len(), 2);
+
+        let (_, late) = limit(Asset::BTC, "102", "1");
+        assert!(engine.enqueue(symbol, late.clone()).is_err());
+        match engine.submit_for(1, symbol, late).as_slice() {
+            [Err(failed)] => assert_eq!(failed.code(), CODE_ENGINE_SHUT_DOWN),
+            _ => panic!("unexpected events"),
+        }
+        assert!(engine.account_summary(1).open_orders.is_empty());
+        assert_eq!(journal.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn snapshot_all_markets() {
+        let mut engine = get_engine();
+        let (btc, eth) = ((Asset::BTC, Asset::USD), (Asset::ETH, Asset::USD));
+        for (order_asset, price) in [(Asset::BTC, "100"), (Asset::BTC, "99"), (Asset::ETH, "10")] {
+            let (symbol, bid) = limit(order_asset, price, "1");
+            engine.submit(symbol, bid);
+        }
+        let ask = orders::new_limit_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Ask,
+            bigdec("100"),
+            bigdec("0.5"),
+            SystemTime::now(),
+        );
+        engine.submit(btc, ask);
+        engine.halt(eth, HaltPolicy::LeaveIntact);
+
+        let json = serde_json::to_string(&engine.snapshot_all(SystemTime::now())).unwrap();
+        let snapshot: RestoredSnapshot<Asset> = serde_json::from_str(&json).unwrap();
+        assert_eq!(snapshot.books.len(), 2);
+        assert_eq!(snapshot.books[0].symbol, btc);
+        assert_eq!(snapshot.books[0].change_seq, 3);
+
+        let mut restored = MatchingEngine::new();
+        assert_eq!(restored.restore_all(snapshot).unwrap(), 2);
+        assert_eq!(restored.markets(), engine.markets());
+        assert!(restored.is_halted(eth));
+        for symbol in [btc, eth] {
+            let (book, original) = (restored.book(symbol).unwrap(), engine.book(symbol).unwrap());
+            assert_eq!(book.depth(10), original.depth(10));
+            assert_eq!(book.change_seq(), original.change_seq());
+            assert!(!restored.snapshot_state(symbol).unwrap().is_dirty());
+        }
+
+        // both go on with the same trade IDs
+        let sell = |engine: &mut MatchingEngine<Asset>| {
+            let ask = orders::new_limit_order_request(
+                Asset::BTC,
+                Asset::USD,
+                OrderSide::Ask,
+                bigdec("99"),
+                bigdec("1"),
+                SystemTime::now(),
+            );
+            engine
+                .submit(btc, ask)
+                .into_iter()
+                .filter_map(|result| match result {
+                    Ok(Success::Filled { trade_id, .. }) => Some(trade_id),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(sell(&mut restored), sell(&mut engine));
+
+        // a book not matching its recorded sequence numbers is refused
+        let json = json.replacen(r#""change_seq":3"#, r#""change_seq":4"#, 1);
+        let snapshot: RestoredSnapshot<Asset> = serde_json::from_str(&json).unwrap();
+        let error = restored.restore_all(snapshot).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn load_shedding() {
+        let mut engine = get_engine();
+        let clock = Arc::new(ManualClock::new(SystemTime::now()));
+        engine.set_clock(Box::new(clock.clone()));
+        engine.set_load_shedding(Some(LoadShedding {
+            max_queued: Some(2),
+            max_wait: Some(Duration::from_millis(10)),
+        }));
+        let symbol = (Asset::BTC, Asset::USD);
+
+        let (_, first) = limit(Asset::BTC, "100", "1");
+        let first_id = first.order_id();
+        assert!(engine.enqueue(symbol, first).is_ok());
+        let (_, second) = limit(Asset::BTC, "101", "1");
+        assert!(engine.enqueue(symbol, second).is_ok());
+
+        // the queue is full, only cancels still get in
+        let (_, third) = limit(Asset::BTC, "102", "1");
+        match engine.enqueue(symbol, third).unwrap_err().as_slice() {
+            [Err(failed @ Failed::Overloaded(_))] => assert_eq!(failed.code(), CODE_OVERLOADED),
+            _ => panic!("unexpected events"),
+        }
+        let cancel = orders::limit_order_cancel_request(first_id, OrderSide::Bid);
+        assert!(engine.enqueue(symbol, cancel).is_ok());
+
+        // past the latency budget new orders are dropped, the cancel still runs
+        clock.advance(Duration::from_millis(20));
+        let processed = engine.process_queued();
+        assert!(matches!(
+            processed[0].1.as_slice(),
+            [Err(Failed::Overloaded(_))]
+        ));
+        assert!(matches!(
+            processed[2].1.as_slice(),
+            [Err(Failed::OrderNotFound(_))]
+        ));
+        assert_eq!(engine.shed(), 3);
+
+        let (_, fresh) = limit(Asset::BTC, "100", "1");
+        assert!(engine.enqueue(symbol, fresh).is_ok());
+        match engine.process_queued()[0].1.as_slice() {
+            [Ok(Success::Accepted { .. })] => (),
+            _ => panic!("unexpected events"),
+        }
+    }
+
+    #[test]
+    fn idle_maintenance() {
+        let mut engine = MatchingEngine::new();
+        let config = BookConfig {
+            max_stalled_indices: 1000,
+            ..BookConfig::default()
+        };
+        engine.add_book_with_config(Asset::BTC, Asset::USD, config);
+        engine.set_idle_maintenance(Some(IdleMaintenance {
+            min_stalled: 5,
+            shrink: false,
+        }));
+        let symbol = (Asset::BTC, Asset::USD);
+        let mut ids = vec![];
+        for _ in 0..10 {
+            let (_, order) = limit(Asset::BTC, "100", "1");
+            ids.push(order.order_id());
+            engine.submit(symbol, order);
+        }
+
+        // cancels behind the best bids leave their indices
+        let mut cancel = |ids: &[Uuid]| {
+            for id in ids {
+                let cancel = orders::limit_order_cancel_request(*id, OrderSide::Bid);
+                assert!(engine.enqueue(symbol, cancel).is_ok());
+            }
+            engine.process_queued();
+            engine.book(symbol).unwrap().bid_queue.stats().stalled
+        };
+        assert_eq!(cancel(&ids[7..]), 3);
+        assert_eq!(cancel(&ids[4..7]), 0);
+        assert_eq!(engine.compacted(), 6);
+        assert_eq!(engine.book(symbol).unwrap().bid_queue.len(), 4);
+    }
+
+    #[test]
+    fn price_improvement() {
+        let mut engine = get_engine();
+        let symbol = (Asset::BTC, Asset::USD);
+        for price in ["100", "101"] {
+            engine.submit(
+                symbol,
+                orders::new_limit_order_request(
+                    Asset::BTC,
+                    Asset::USD,
+                    OrderSide::Ask,
+                    bigdec(price),
+                    bigdec("1"),
+                    SystemTime::now(),
+                ),
+            );
+        }
+        // the resting asks did not take, nothing to measure yet
+        assert!(engine.price_improvement(symbol).is_none());
+
+        let bid = orders::new_limit_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Bid,
+            bigdec("102"),
+            bigdec("2"),
+            SystemTime::now(),
+        );
+        engine.submit_for(7, symbol, bid);
+
+        let stats = engine.account_price_improvement(7, symbol).unwrap();
+        assert_eq!(stats.fills, 2);
+        assert_eq!(stats.qty, bigdec("2"));
+        // 2 below the limit on the first fill, 1 on the second
+        assert_eq!(stats.avg_vs_limit(), Some(bigdec("1.5")));
+        // the second fill walked 1 past the best ask of 100
+        assert_eq!(stats.avg_vs_bbo(), Some(bigdec("-0.5")));
+        assert_eq!(engine.price_improvement(symbol), Some(stats));
+        assert!(engine.account_price_improvement(8, symbol).is_none());
+    }
+
+    #[test]
+    fn snapshots_only_changed_books() {
+        let mut engine = get_engine();
+        let btc = (Asset::BTC, Asset::USD);
+        let eth = (Asset::ETH, Asset::USD);
+        let ts = SystemTime::now();
+        let mut snapshot = |_, _: &Orderbook<Asset>| Ok(());
+
+        // never snapshotted books are dirty even without changes
+        assert!(engine.snapshot_state(btc).unwrap().is_dirty());
+        assert_eq!(engine.snapshot_changed(ts, &mut snapshot).unwrap().len(), 2);
+        assert!(engine
+            .snapshot_changed(ts, &mut snapshot)
+            .unwrap()
+            .is_empty());
+
+        engine.submit(btc, limit(Asset::BTC, "100", "1").1);
+        // rejected requests do not change the book
+        engine.submit(btc, limit(Asset::BTC, "-1", "1").1);
+        let state = engine.snapshot_state(btc).unwrap();
+        assert!(state.is_dirty());
+        assert_eq!(state.pending_changes(), 1);
+        assert!(!engine.snapshot_state(eth).unwrap().is_dirty());
+
+        assert_eq!(
+            engine.snapshot_changed(ts, &mut snapshot).unwrap(),
+            vec![btc]
+        );
+        let state = engine.snapshot_state(btc).unwrap();
+        assert_eq!(state.snapshot_seq, Some(state.seq));
+        assert_eq!(state.snapshot_at, Some(ts));
+
+        let failing = |_, _: &Orderbook<Asset>| Err(io::Error::other("disk full"));
+        engine.submit(eth, limit(Asset::ETH, "10", "1").1);
+        assert!(engine.snapshot_changed(ts, failing).is_err());
+        assert!(engine.snapshot_state(eth).unwrap().is_dirty());
+        assert!(engine
+            .snapshot_state(btc)
+            .is_some_and(|state| !state.is_dirty()));
+        assert!(engine.snapshot_state((Asset::USD, Asset::BTC)).is_none());
+    }
+
+    #[test]
+    fn account_trade_history() {
+        let mut engine = get_engine();
+        let symbol = (Asset::BTC, Asset::USD);
+        let start = SystemTime::now();
+        for price in ["100", "101", "102"] {
+            let ask = orders::new_limit_order_request(
+                Asset::BTC,
+                Asset::USD,
+                OrderSide::Ask,
+                bigdec(price),
+                bigdec("1"),
+                SystemTime::now(),
+            );
+            engine.submit_for(1, symbol, ask);
+        }
+        for _ in 0..3 {
+            let bid = orders::new_market_order_request(
+                Asset::BTC,
+                Asset::USD,
+                OrderSide::Bid,
+                bigdec("1"),
+                SystemTime::now(),
+            );
+            engine.submit_for(2, symbol, bid);
+        }
+        let range = start..SystemTime::now() + Duration::from_secs(1);
+
+        let first = engine.trades_for_account(
+            1,
+            range.clone(),
+            PageRequest {
+                cursor: None,
+                limit: 2,
+            },
+        );
+        assert_eq!(first.fills.len(), 2);
+        assert!(first
+            .fills
+            .iter()
+            .all(|fill| fill.account == Some(1) && fill.side == OrderSide::Ask));
+        assert_eq!(first.fills[1].price, bigdec("101"));
+        let second = engine.trades_for_account(
+            1,
+            range.clone(),
+            PageRequest {
+                cursor: first.next,
+                limit: 2,
+            },
+        );
+        assert_eq!(second.fills.len(), 1);
+        assert_eq!(second.fills[0].price, bigdec("102"));
+        assert_eq!(second.next, None);
+
+        let buyer = engine.trades_for_account(
+            2,
+            range,
+            PageRequest {
+                cursor: None,
+                limit: 10,
+            },
+        );
+        assert_eq!(buyer.fills.len(), 3);
+        assert!(buyer
+            .fills
+            .iter()
+            .all(|fill| fill.role == LiquidityRole::Taker));
+
+        // nothing before the first trade
+        let before = start - Duration::from_secs(60)..start;
+        assert!(engine
+            .trades_for_account(
+                1,
+                before,
+                PageRequest {
+                    cursor: None,
+                    limit: 10
+                }
+            )
+            .fills
+            .is_empty());
+    }
+
+    /// Events of a small two market session, all in logical time
+    fn simulate() -> Vec<String> {
+        let start = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let clock = Arc::new(ManualClock::new(start));
+        let mut engine = MatchingEngine::with_logical_clock(clock.clone());
+        engine.add_book(Asset::BTC, Asset::USD);
+        engine.add_book(Asset::ETH, Asset::USD);
+        let mut calendar = TradingCalendar::new(
+            clock.clone(),
+            vec![Session {
+                open: start,
+                close: start + Duration::from_secs(60),
+            }],
+        );
+        let mut events = vec![];
+        events.extend(
+            calendar
+                .poll(&mut engine)
+                .iter()
+                .map(|event| format!("{:?}", event)),
+        );
+
+        let mut next_id = 0;
+        let mut order = |order_asset, side, price: &str, tif| {
+            next_id += 1;
+            orders::new_limit_order_request(
+                order_asset,
+                Asset::USD,
+                side,
+                bigdec(price),
+                bigdec("1"),
+                clock.now(),
+            )
+            .with_time_in_force(tif)
+            .with_order_id(Uuid::from_u128(next_id))
+        };
+        let requests = vec![
+            (
+                Asset::BTC,
+                order(Asset::BTC, OrderSide::Ask, "100", TimeInForce::Day),
+            ),
+            (
+                Asset::BTC,
+                order(Asset::BTC, OrderSide::Ask, "101", TimeInForce::Day),
+            ),
+            (
+                Asset::ETH,
+                order(
+                    Asset::ETH,
+                    OrderSide::Ask,
+                    "10",
+                    TimeInForce::GTD(start + Duration::from_secs(30)),
+                ),
+            ),
+            (
+                Asset::ETH,
+                order(Asset::ETH, OrderSide::Bid, "9", TimeInForce::Day),
+            ),
+            (
+                Asset::BTC,
+                order(Asset::BTC, OrderSide::Bid, "100", TimeInForce::GTC),
+            ),
+        ];
+        for (order_asset, request) in requests {
+            clock.advance(Duration::from_millis(250));
+            let results = engine.submit((order_asset, Asset::USD), request);
+            events.push(format!("{:?}", results));
+        }
+
+        // the GTD ask runs out, then the session closes
+        let expired = engine.advance_time(Duration::from_secs(30)).unwrap();
+        assert_eq!(expired.len(), 1);
+        events.push(format!("{:?}", expired));
+        clock.advance(Duration::from_secs(30));
+        events.extend(
+            calendar
+                .poll(&mut engine)
+                .iter()
+                .map(|event| format!("{:?}", event)),
+        );
+        assert_eq!(
+            engine.book((Asset::BTC, Asset::USD)).unwrap().clock().now(),
+            start + Duration::from_millis(61_250)
+        );
+        events
+    }
+
+    #[test]
+    fn deterministic_simulation() {
+        let first = simulate();
+        assert_eq!(first.len(), 8);
+        assert!(first[5].contains("trade_id: TradeId(1)"));
+        assert!(first[7].contains("Closed"));
+        assert_eq!(first, simulate());
+
+        assert!(!MatchingEngine::<Asset>::new().is_simulation());
+        assert!(MatchingEngine::<Asset>::new()
+            .advance_time(Duration::from_secs(1))
+            .is_none());
+    }
+}
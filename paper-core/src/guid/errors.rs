@@ -0,0 +1,284 @@
+use serde::de::{self, Deserializer};
+use serde::ser::{SerializeStruct, Serializer};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::dark_pool::{ERR_DARK_STOP, ERR_UNLIMITED_AMEND};
+use super::engine::{
+    ERR_ENGINE_SHUT_DOWN, ERR_INSUFFICIENT_BALANCE, ERR_MARKET_HALTED, ERR_STACKING_LIMIT,
+    ERR_UNKNOWN_MARKET,
+};
+use super::orderbook::{
+    Failed, ERR_CANCEL_ONLY, ERR_IN_AUCTION, ERR_MARKET_SUSPENDED, ERR_OUTSIDE_BAND, ERR_POST_ONLY,
+};
+use super::risk::ERR_NOT_RISK_CHECKED;
+use super::scheduled::ERR_NOT_NEW_ORDER;
+use super::validation::{
+    ERR_BAD_EXPIRY, ERR_BAD_ORDER_ASSET, ERR_BAD_ORDER_ID, ERR_BAD_PRICE_ASSET,
+    ERR_BAD_PRICE_VALUE, ERR_BAD_PROTECTION, ERR_BAD_QUANTITY_VALUE, ERR_EMPTY_AMEND, ERR_OFF_LOT,
+    ERR_OFF_TICK, ERR_QTY_ABOVE_MAX, ERR_QTY_BELOW_MIN,
+};
+
+/// Reasons of requests refused before they reach a book, e.g. by a gateway
+/// normalizing them to the precision of the market
+pub const ERR_QTY_TOO_PRECISE: &str = "quantity has too many decimals";
+pub const ERR_QTY_ROUNDS_TO_ZERO: &str = "quantity rounds to zero";
+
+/* Error codes
+ *
+ * Codes are part of the public API: once assigned they never change and are
+ * never reused, new failures get new codes. 1xxx are validation failures,
+ * 2xxx are failures of otherwise valid requests.
+ */
+
+/// validation failure with a reason not listed below
+pub const CODE_VALIDATION_FAILED: u16 = 1000;
+pub const CODE_BAD_ORDER_ASSET: u16 = 1001;
+pub const CODE_BAD_PRICE_ASSET: u16 = 1002;
+pub const CODE_BAD_PRICE_VALUE: u16 = 1003;
+pub const CODE_BAD_QUANTITY_VALUE: u16 = 1004;
+pub const CODE_BAD_ORDER_ID: u16 = 1005;
+pub const CODE_UNKNOWN_MARKET: u16 = 1006;
+pub const CODE_NOT_NEW_ORDER: u16 = 1007;
+pub const CODE_EMPTY_AMEND: u16 = 1008;
+pub const CODE_UNLIMITED_AMEND: u16 = 1009;
+pub const CODE_MARKET_HALTED: u16 = 1010;
+pub const CODE_STACKING_LIMIT: u16 = 1011;
+pub const CODE_OFF_TICK: u16 = 1012;
+pub const CODE_ENGINE_SHUT_DOWN: u16 = 1013;
+pub const CODE_OUTSIDE_BAND: u16 = 1014;
+pub const CODE_DARK_STOP: u16 = 1015;
+pub const CODE_BAD_EXPIRY: u16 = 1016;
+pub const CODE_BAD_PROTECTION: u16 = 1017;
+/// kept from when the mismatch was a validation failure
+pub const CODE_SIDE_MISMATCH: u16 = 1018;
+pub const CODE_POST_ONLY: u16 = 1019;
+pub const CODE_CANCEL_ONLY: u16 = 1020;
+pub const CODE_MARKET_SUSPENDED: u16 = 1021;
+pub const CODE_INSUFFICIENT_BALANCE: u16 = 1022;
+pub const CODE_OFF_LOT: u16 = 1023;
+pub const CODE_QTY_BELOW_MIN: u16 = 1024;
+pub const CODE_QTY_ABOVE_MAX: u16 = 1025;
+pub const CODE_IN_AUCTION: u16 = 1026;
+pub const CODE_QTY_TOO_PRECISE: u16 = 1027;
+pub const CODE_QTY_ROUNDS_TO_ZERO: u16 = 1028;
+pub const CODE_NOT_RISK_CHECKED: u16 = 1029;
+
+pub const CODE_DUPLICATE_ORDER_ID: u16 = 2001;
+pub const CODE_NO_MATCH: u16 = 2002;
+pub const CODE_ORDER_NOT_FOUND: u16 = 2003;
+pub const CODE_BASKET_REJECTED: u16 = 2004;
+pub const CODE_DEPTH_LIMIT_EXCEEDED: u16 = 2005;
+pub const CODE_JOURNAL_FAILED: u16 = 2006;
+pub const CODE_OVERLOADED: u16 = 2007;
+pub const CODE_RISK_REJECTED: u16 = 2008;
+pub const CODE_TOO_LATE_TO_CANCEL: u16 = 2009;
+pub const CODE_TOO_LATE_TO_AMEND: u16 = 2010;
+pub const CODE_QTY_BELOW_FILLED: u16 = 2011;
+
+const VALIDATION_CODES: &[(&str, u16)] = &[
+    (ERR_BAD_ORDER_ASSET, CODE_BAD_ORDER_ASSET),
+    (ERR_BAD_PRICE_ASSET, CODE_BAD_PRICE_ASSET),
+    (ERR_BAD_PRICE_VALUE, CODE_BAD_PRICE_VALUE),
+    (ERR_BAD_QUANTITY_VALUE, CODE_BAD_QUANTITY_VALUE),
+    (ERR_BAD_ORDER_ID, CODE_BAD_ORDER_ID),
+    (ERR_UNKNOWN_MARKET, CODE_UNKNOWN_MARKET),
+    (ERR_NOT_NEW_ORDER, CODE_NOT_NEW_ORDER),
+    (ERR_EMPTY_AMEND, CODE_EMPTY_AMEND),
+    (ERR_UNLIMITED_AMEND, CODE_UNLIMITED_AMEND),
+    (ERR_MARKET_HALTED, CODE_MARKET_HALTED),
+    (ERR_STACKING_LIMIT, CODE_STACKING_LIMIT),
+    (ERR_OFF_TICK, CODE_OFF_TICK),
+    (ERR_ENGINE_SHUT_DOWN, CODE_ENGINE_SHUT_DOWN),
+    (ERR_OUTSIDE_BAND, CODE_OUTSIDE_BAND),
+    (ERR_DARK_STOP, CODE_DARK_STOP),
+    (ERR_BAD_EXPIRY, CODE_BAD_EXPIRY),
+    (ERR_BAD_PROTECTION, CODE_BAD_PROTECTION),
+    (ERR_POST_ONLY, CODE_POST_ONLY),
+    (ERR_CANCEL_ONLY, CODE_CANCEL_ONLY),
+    (ERR_MARKET_SUSPENDED, CODE_MARKET_SUSPENDED),
+    (ERR_INSUFFICIENT_BALANCE, CODE_INSUFFICIENT_BALANCE),
+    (ERR_OFF_LOT, CODE_OFF_LOT),
+    (ERR_QTY_BELOW_MIN, CODE_QTY_BELOW_MIN),
+    (ERR_QTY_ABOVE_MAX, CODE_QTY_ABOVE_MAX),
+    (ERR_IN_AUCTION, CODE_IN_AUCTION),
+    (ERR_QTY_TOO_PRECISE, CODE_QTY_TOO_PRECISE),
+    (ERR_QTY_ROUNDS_TO_ZERO, CODE_QTY_ROUNDS_TO_ZERO),
+    (ERR_NOT_RISK_CHECKED, CODE_NOT_RISK_CHECKED),
+];
+
+impl Failed {
+    /// Stable numeric code clients can branch on instead of the message
+    pub fn code(&self) -> u16 {
+        match self {
+            Failed::ValidationFailed(reason) => VALIDATION_CODES
+                .iter()
+                .find(|(known, _)| known == reason)
+                .map_or(CODE_VALIDATION_FAILED, |(_, code)| *code),
+            Failed::DuplicateOrderID(_) => CODE_DUPLICATE_ORDER_ID,
+            Failed::NoMatch(_) => CODE_NO_MATCH,
+            Failed::OrderNotFound(_) => CODE_ORDER_NOT_FOUND,
+            Failed::BasketRejected(_) => CODE_BASKET_REJECTED,
+            Failed::DepthLimitExceeded(_) => CODE_DEPTH_LIMIT_EXCEEDED,
+            Failed::Overloaded(_) => CODE_OVERLOADED,
+            Failed::RiskRejected(_) => CODE_RISK_REJECTED,
+            Failed::TooLateToCancel(_) => CODE_TOO_LATE_TO_CANCEL,
+            Failed::TooLateToAmend(_) => CODE_TOO_LATE_TO_AMEND,
+            Failed::SideMismatch(_) => CODE_SIDE_MISMATCH,
+            Failed::QtyBelowFilled(_) => CODE_QTY_BELOW_FILLED,
+            Failed::JournalFailed(_) => CODE_JOURNAL_FAILED,
+        }
+    }
+
+    /// Variant name, as the `error` field of the serialized failure
+    pub fn name(&self) -> &'static str {
+        match self {
+            Failed::ValidationFailed(_) => "ValidationFailed",
+            Failed::DuplicateOrderID(_) => "DuplicateOrderID",
+            Failed::NoMatch(_) => "NoMatch",
+            Failed::OrderNotFound(_) => "OrderNotFound",
+            Failed::BasketRejected(_) => "BasketRejected",
+            Failed::DepthLimitExceeded(_) => "DepthLimitExceeded",
+            Failed::Overloaded(_) => "Overloaded",
+            Failed::RiskRejected(_) => "RiskRejected",
+            Failed::TooLateToCancel(_) => "TooLateToCancel",
+            Failed::TooLateToAmend(_) => "TooLateToAmend",
+            Failed::SideMismatch(_) => "SideMismatch",
+            Failed::QtyBelowFilled(_) => "QtyBelowFilled",
+            Failed::JournalFailed(_) => "JournalFailed",
+        }
+    }
+
+    /// Validation reason or the ID of the order the failure is about
+    pub fn detail(&self) -> String {
+        match self {
+            Failed::ValidationFailed(reason) => reason.clone(),
+            Failed::DuplicateOrderID(order_id)
+            | Failed::NoMatch(order_id)
+            | Failed::OrderNotFound(order_id)
+            | Failed::BasketRejected(order_id)
+            | Failed::DepthLimitExceeded(order_id)
+            | Failed::Overloaded(order_id)
+            | Failed::RiskRejected(order_id)
+            | Failed::TooLateToCancel(order_id)
+            | Failed::TooLateToAmend(order_id)
+            | Failed::SideMismatch(order_id)
+            | Failed::QtyBelowFilled(order_id)
+            | Failed::JournalFailed(order_id) => order_id.to_string(),
+        }
+    }
+}
+
+/// Serialized as:
+/// ```json
+/// {"code": 2002, "error": "NoMatch", "detail": "67e55044-10b1-426f-9247-bb680e5fe0c8"}
+/// ```
+/// where `detail` is the validation reason or the order ID.
+impl Serialize for Failed {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Failed", 3)?;
+        state.serialize_field("code", &self.code())?;
+        state.serialize_field("error", self.name())?;
+        state.serialize_field("detail", &self.detail())?;
+        state.end()
+    }
+}
+
+#[derive(Deserialize)]
+struct FailedWire {
+    error: String,
+    detail: String,
+}
+
+impl<'de> Deserialize<'de> for Failed {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let wire = FailedWire::deserialize(deserializer)?;
+        let order_id = || Uuid::parse_str(&wire.detail).map_err(de::Error::custom);
+        Ok(match wire.error.as_str() {
+            "ValidationFailed" => Failed::ValidationFailed(wire.detail.clone()),
+            "DuplicateOrderID" => Failed::DuplicateOrderID(order_id()?),
+            "NoMatch" => Failed::NoMatch(order_id()?),
+            "OrderNotFound" => Failed::OrderNotFound(order_id()?),
+            "BasketRejected" => Failed::BasketRejected(order_id()?),
+            "DepthLimitExceeded" => Failed::DepthLimitExceeded(order_id()?),
+            "Overloaded" => Failed::Overloaded(order_id()?),
+            "RiskRejected" => Failed::RiskRejected(order_id()?),
+            "TooLateToCancel" => Failed::TooLateToCancel(order_id()?),
+            "TooLateToAmend" => Failed::TooLateToAmend(order_id()?),
+            "SideMismatch" => Failed::SideMismatch(order_id()?),
+            "QtyBelowFilled" => Failed::QtyBelowFilled(order_id()?),
+            "JournalFailed" => Failed::JournalFailed(order_id()?),
+            other => return Err(de::Error::unknown_variant(other, &[])),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn codes_are_unique() {
+        let codes: HashSet<u16> = VALIDATION_CODES.iter().map(|(_, code)| *code).collect();
+        assert_eq!(codes.len(), VALIDATION_CODES.len());
+
+        let failures = [
+            Failed::ValidationFailed(String::from("something new")),
+            Failed::ValidationFailed(String::from(ERR_UNKNOWN_MARKET)),
+            Failed::DuplicateOrderID(Uuid::nil()),
+            Failed::NoMatch(Uuid::nil()),
+            Failed::OrderNotFound(Uuid::nil()),
+            Failed::BasketRejected(Uuid::nil()),
+            Failed::Overloaded(Uuid::nil()),
+            Failed::RiskRejected(Uuid::nil()),
+            Failed::TooLateToCancel(Uuid::nil()),
+            Failed::TooLateToAmend(Uuid::nil()),
+            Failed::SideMismatch(Uuid::nil()),
+            Failed::QtyBelowFilled(Uuid::nil()),
+            Failed::JournalFailed(Uuid::nil()),
+        ];
+        let codes: Vec<u16> = failures.iter().map(Failed::code).collect();
+        assert_eq!(
+            codes,
+            vec![
+                CODE_VALIDATION_FAILED,
+                CODE_UNKNOWN_MARKET,
+                CODE_DUPLICATE_ORDER_ID,
+                CODE_NO_MATCH,
+                CODE_ORDER_NOT_FOUND,
+                CODE_BASKET_REJECTED,
+                CODE_OVERLOADED,
+                CODE_RISK_REJECTED,
+                CODE_TOO_LATE_TO_CANCEL,
+                CODE_TOO_LATE_TO_AMEND,
+                CODE_SIDE_MISMATCH,
+                CODE_QTY_BELOW_FILLED,
+                CODE_JOURNAL_FAILED
+            ]
+        );
+    }
+
+    #[test]
+    fn serialized_with_code() {
+        let order_id = Uuid::new_v4();
+        let json = serde_json::to_string(&Failed::NoMatch(order_id)).unwrap();
+        assert_eq!(
+            json,
+            format!(
+                r#"{{"code":2002,"error":"NoMatch","detail":"{}"}}"#,
+                order_id
+            )
+        );
+
+        match serde_json::from_str::<Failed>(&json).unwrap() {
+            Failed::NoMatch(parsed) => assert_eq!(parsed, order_id),
+            other => panic!("unexpected failure {:?}", other),
+        }
+
+        let json =
+            serde_json::to_string(&Failed::ValidationFailed(String::from(ERR_BAD_PRICE_VALUE)))
+                .unwrap();
+        assert!(json.starts_with(r#"{"code":1003,"#));
+    }
+}
@@ -0,0 +1,177 @@
+use bigdecimal::{BigDecimal, Zero};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+use uuid::Uuid;
+
+use super::domain::{AccountId, OrderSide};
+use super::engine::Symbol;
+use super::orderbook::{OrderProcessingResult, Success};
+
+/// Price improvement takers got on their fills.
+///
+/// Improvement is counted in the price asset per unit filled, positive when
+/// the taker did better than the reference: bought below or sold above it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImprovementStats {
+    /// taker fills seen
+    pub fills: u64,
+    pub qty: BigDecimal,
+    /// improvement over the limit price times quantity, market orders have
+    /// no limit and are left out
+    pub vs_limit: BigDecimal,
+    /// quantity filled by orders with a limit price
+    pub limit_qty: BigDecimal,
+    /// improvement over the opposite best price before the trade times
+    /// quantity, fills into an empty book are left out
+    pub vs_bbo: BigDecimal,
+    /// quantity filled against a quoted opposite side
+    pub bbo_qty: BigDecimal,
+}
+
+impl Default for ImprovementStats {
+    fn default() -> Self {
+        ImprovementStats {
+            fills: 0,
+            qty: BigDecimal::zero(),
+            vs_limit: BigDecimal::zero(),
+            limit_qty: BigDecimal::zero(),
+            vs_bbo: BigDecimal::zero(),
+            bbo_qty: BigDecimal::zero(),
+        }
+    }
+}
+
+impl ImprovementStats {
+    /// Average improvement per unit over the limit price
+    pub fn avg_vs_limit(&self) -> Option<BigDecimal> {
+        average(&self.vs_limit, &self.limit_qty)
+    }
+
+    /// Average improvement per unit over the pre-trade best opposite price,
+    /// negative when orders walked the book
+    pub fn avg_vs_bbo(&self) -> Option<BigDecimal> {
+        average(&self.vs_bbo, &self.bbo_qty)
+    }
+
+    fn record(
+        &mut self,
+        side: OrderSide,
+        price: &BigDecimal,
+        qty: &BigDecimal,
+        limit: Option<&BigDecimal>,
+        bbo: Option<&BigDecimal>,
+    ) {
+        self.fills += 1;
+        self.qty += qty;
+        if let Some(limit) = limit {
+            self.vs_limit += improvement(side, price, limit) * qty;
+            self.limit_qty += qty;
+        }
+        if let Some(bbo) = bbo {
+            self.vs_bbo += improvement(side, price, bbo) * qty;
+            self.bbo_qty += qty;
+        }
+    }
+}
+
+/// Best execution statistics of taker fills, per market and per account.
+///
+/// Fed with each incoming order along with the opposite best price seen
+/// before it was processed, fills of resting orders do not count.
+#[derive(Debug)]
+pub struct ExecutionQuality<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq + Hash,
+{
+    markets: HashMap<Symbol<Asset>, ImprovementStats>,
+    accounts: HashMap<(AccountId, Symbol<Asset>), ImprovementStats>,
+}
+
+impl<Asset> Default for ExecutionQuality<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq + Hash,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Asset> ExecutionQuality<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq + Hash,
+{
+    pub fn new() -> Self {
+        ExecutionQuality {
+            markets: HashMap::new(),
+            accounts: HashMap::new(),
+        }
+    }
+
+    /// Record fills of the taker order found in the processing results of
+    /// its request, `limit` is None for market orders
+    pub fn record(
+        &mut self,
+        symbol: Symbol<Asset>,
+        account: Option<AccountId>,
+        taker: Uuid,
+        limit: Option<&BigDecimal>,
+        bbo: Option<&BigDecimal>,
+        results: &OrderProcessingResult<Asset>,
+    ) {
+        for result in results {
+            let (side, price, qty) = match result {
+                Ok(Success::Filled {
+                    order_id,
+                    side,
+                    price,
+                    qty,
+                    ..
+                })
+                | Ok(Success::PartiallyFilled {
+                    order_id,
+                    side,
+                    price,
+                    qty,
+                    ..
+                }) if *order_id == taker => (*side, price, qty),
+                _ => continue,
+            };
+            self.markets
+                .entry(symbol)
+                .or_default()
+                .record(side, price, qty, limit, bbo);
+            if let Some(account) = account {
+                self.accounts
+                    .entry((account, symbol))
+                    .or_default()
+                    .record(side, price, qty, limit, bbo);
+            }
+        }
+    }
+
+    pub fn market(&self, symbol: Symbol<Asset>) -> Option<&ImprovementStats> {
+        self.markets.get(&symbol)
+    }
+
+    pub fn account(&self, account: AccountId, symbol: Symbol<Asset>) -> Option<&ImprovementStats> {
+        self.accounts.get(&(account, symbol))
+    }
+}
+
+/* Helpers */
+
+fn improvement(side: OrderSide, price: &BigDecimal, reference: &BigDecimal) -> BigDecimal {
+    match side {
+        OrderSide::Bid => reference - price,
+        OrderSide::Ask => price - reference,
+    }
+}
+
+fn average(total: &BigDecimal, qty: &BigDecimal) -> Option<BigDecimal> {
+    if qty.is_zero() {
+        None
+    } else {
+        Some(total / qty)
+    }
+}
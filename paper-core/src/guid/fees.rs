@@ -0,0 +1,560 @@
+use bigdecimal::{BigDecimal, Zero};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::time::{Duration, SystemTime};
+use uuid::Uuid;
+
+use super::config::ConfigError;
+use super::domain::AccountId;
+use super::engine::Symbol;
+use super::orderbook::{OrderProcessingResult, Success};
+
+/// Volume window tiers are computed over
+pub const DEFAULT_VOLUME_WINDOW: Duration = Duration::from_secs(30 * 24 * 3600);
+
+/// Side of the trade an order took
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LiquidityRole {
+    /// resting order
+    Maker,
+    /// incoming order
+    Taker,
+}
+
+/// Fee rates as fractions of notional, negative for rebates
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeeRates {
+    pub maker: BigDecimal,
+    pub taker: BigDecimal,
+}
+
+impl FeeRates {
+    /// Rates given in basis points, e.g. 2 and 7 for 0.02% and 0.07%
+    pub fn from_bps(maker: i64, taker: i64) -> Self {
+        FeeRates {
+            maker: BigDecimal::new(maker.into(), 4),
+            taker: BigDecimal::new(taker.into(), 4),
+        }
+    }
+
+    pub fn rate(&self, role: LiquidityRole) -> &BigDecimal {
+        match role {
+            LiquidityRole::Maker => &self.maker,
+            LiquidityRole::Taker => &self.taker,
+        }
+    }
+}
+
+/// Rates applying from the given traded volume up
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeeTier {
+    pub min_volume: BigDecimal,
+    pub rates: FeeRates,
+}
+
+/// Asset fees are charged in
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FeeCurrency<Asset> {
+    /// price asset of the traded pair, the default
+    PriceAsset,
+    /// order asset of the traded pair
+    OrderAsset,
+    /// fixed asset, e.g. the venue token, converted by the rate source
+    Designated(Asset),
+}
+
+/// Conversion rates between assets
+pub trait RateSource<Asset> {
+    /// Amount of `to` worth one unit of `from`
+    fn rate(&self, from: Asset, to: Asset) -> Option<BigDecimal>;
+}
+
+/// Rates set by hand, the reverse direction is derived
+#[derive(Debug, Clone)]
+pub struct FixedRates<Asset>
+where
+    Asset: Eq + Hash,
+{
+    rates: HashMap<(Asset, Asset), BigDecimal>,
+}
+
+impl<Asset> Default for FixedRates<Asset>
+where
+    Asset: Eq + Hash,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Asset> FixedRates<Asset>
+where
+    Asset: Eq + Hash,
+{
+    pub fn new() -> Self {
+        FixedRates {
+            rates: HashMap::new(),
+        }
+    }
+
+    pub fn set(&mut self, from: Asset, to: Asset, rate: BigDecimal) {
+        self.rates.insert((from, to), rate);
+    }
+}
+
+impl<Asset> RateSource<Asset> for FixedRates<Asset>
+where
+    Asset: Copy + Eq + Hash,
+{
+    fn rate(&self, from: Asset, to: Asset) -> Option<BigDecimal> {
+        if from == to {
+            return Some(BigDecimal::from(1));
+        }
+        if let Some(rate) = self.rates.get(&(from, to)) {
+            return Some(rate.clone());
+        }
+        self.rates
+            .get(&(to, from))
+            .filter(|rate| !rate.is_zero())
+            .map(|rate| BigDecimal::from(1) / rate)
+    }
+}
+
+/// Account standing under the fee schedule
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccountTier {
+    /// position of the tier in the schedule, starting from 0
+    pub tier: usize,
+    /// notional traded within the volume window
+    pub volume: BigDecimal,
+    pub rates: FeeRates,
+}
+
+/// Fee charged for a single execution
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeeRecord<Asset> {
+    pub order_id: Uuid,
+    pub account: AccountId,
+    pub role: LiquidityRole,
+    /// execution value in the price asset
+    pub notional: BigDecimal,
+    pub rate: BigDecimal,
+    pub fee: BigDecimal,
+    /// asset the fee is charged in
+    pub asset: Asset,
+    pub ts: SystemTime,
+}
+
+/// Charges maker/taker fees on executions.
+///
+/// Rates depend on the account's tier, picked by the notional it traded
+/// within the rolling volume window, in price asset notional. Executions are
+/// taken from processing results, so accounts have to register their orders
+/// up front.
+///
+/// Fees are computed in the price asset and charged in the configured fee
+/// currency. When no conversion rate to a designated asset is available,
+/// the fee stays in the price asset; the record always names the asset used.
+pub struct FeeEngine<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    tiers: Vec<FeeTier>,
+    window: Duration,
+    fee_currency: FeeCurrency<Asset>,
+    rate_source: Option<Box<dyn RateSource<Asset> + Send>>,
+    owners: HashMap<Uuid, (AccountId, Symbol<Asset>)>,
+    /// executions per account within the window: (time, notional)
+    volumes: HashMap<AccountId, VecDeque<(SystemTime, BigDecimal)>>,
+}
+
+impl<Asset> FeeEngine<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    /// Create engine with the tiers in any order.
+    ///
+    /// Accounts below the lowest tier pay its rates.
+    pub fn new(mut tiers: Vec<FeeTier>, window: Duration) -> Result<Self, ConfigError> {
+        if tiers.is_empty() {
+            return Err(ConfigError::Invalid("fee schedule without tiers"));
+        }
+        tiers.sort_by(|a, b| a.min_volume.cmp(&b.min_volume));
+        Ok(Self::with_tiers(tiers, window))
+    }
+
+    /// Single tier schedule
+    pub fn flat(rates: FeeRates) -> Self {
+        Self::with_tiers(
+            vec![FeeTier {
+                min_volume: BigDecimal::zero(),
+                rates,
+            }],
+            DEFAULT_VOLUME_WINDOW,
+        )
+    }
+
+    /// Engine over tiers already sorted by volume, at least one
+    fn with_tiers(tiers: Vec<FeeTier>, window: Duration) -> Self {
+        FeeEngine {
+            tiers,
+            window,
+            fee_currency: FeeCurrency::PriceAsset,
+            rate_source: None,
+            owners: HashMap::new(),
+            volumes: HashMap::new(),
+        }
+    }
+
+    pub fn set_fee_currency(&mut self, currency: FeeCurrency<Asset>) {
+        self.fee_currency = currency;
+    }
+
+    /// Conversion rates used for a designated fee asset
+    pub fn set_rate_source(&mut self, source: Box<dyn RateSource<Asset> + Send>) {
+        self.rate_source = Some(source);
+    }
+
+    pub fn register_order(&mut self, order_id: Uuid, account: AccountId, symbol: Symbol<Asset>) {
+        self.owners.insert(order_id, (account, symbol));
+    }
+
+    /// Highest taker fee of any tier on an execution of `qty` for `notional`
+    /// in the market, with the asset it is charged in
+    pub fn worst_taker_fee(
+        &self,
+        (order_asset, price_asset): Symbol<Asset>,
+        notional: &BigDecimal,
+        qty: &BigDecimal,
+    ) -> (Asset, BigDecimal) {
+        let rate = self
+            .tiers
+            .iter()
+            .map(|tier| &tier.rates.taker)
+            .max()
+            .filter(|rate| **rate > BigDecimal::zero())
+            .cloned()
+            .unwrap_or_else(BigDecimal::zero);
+        match self.fee_currency {
+            FeeCurrency::PriceAsset => (price_asset, notional * rate),
+            FeeCurrency::OrderAsset => (order_asset, qty * rate),
+            FeeCurrency::Designated(asset) => match self
+                .rate_source
+                .as_ref()
+                .and_then(|source| source.rate(price_asset, asset))
+            {
+                Some(conversion) => (asset, notional * rate * conversion),
+                None => (price_asset, notional * rate),
+            },
+        }
+    }
+
+    /// Current tier and effective rates of the account
+    pub fn account_tier(&mut self, account: AccountId, now: SystemTime) -> AccountTier {
+        let volume = self.rolling_volume(account, now);
+        let tier = self
+            .tiers
+            .iter()
+            .rposition(|tier| tier.min_volume <= volume)
+            .unwrap_or(0);
+        AccountTier {
+            tier,
+            volume,
+            rates: self.tiers[tier].rates.clone(),
+        }
+    }
+
+    /// Charge fees for executions of registered orders found in results
+    pub fn on_results(
+        &mut self,
+        results: &OrderProcessingResult<Asset>,
+        ts: SystemTime,
+    ) -> Vec<FeeRecord<Asset>> {
+        let mut records = vec![];
+        for result in results {
+            let (order_id, role, price, qty) = match result {
+                Ok(Success::Filled {
+                    order_id,
+                    role,
+                    price,
+                    qty,
+                    ..
+                })
+                | Ok(Success::PartiallyFilled {
+                    order_id,
+                    role,
+                    price,
+                    qty,
+                    ..
+                }) => (order_id, role, price, qty),
+                _ => continue,
+            };
+            let owner = match self.owners.get(order_id) {
+                Some(owner) => *owner,
+                None => continue,
+            };
+            records.push(self.charge(*order_id, owner, *role, price, qty, ts));
+        }
+        records
+    }
+
+    /// Charge fees for executions of orders `owner` attributes, writing them
+    /// on the fill events as well.
+    ///
+    /// Used by the matching engine, which knows the owners itself. Executions
+    /// count towards the tier at the time of their event.
+    pub fn apply<F>(
+        &mut self,
+        results: &mut OrderProcessingResult<Asset>,
+        owner: F,
+    ) -> Vec<FeeRecord<Asset>>
+    where
+        F: Fn(Uuid) -> Option<(AccountId, Symbol<Asset>)>,
+    {
+        let mut records = vec![];
+        for result in results.iter_mut() {
+            let (order_id, role, price, qty, ts, fee, fee_asset) = match result {
+                Ok(Success::Filled {
+                    order_id,
+                    role,
+                    price,
+                    qty,
+                    ts,
+                    fee,
+                    fee_asset,
+                    ..
+                })
+                | Ok(Success::PartiallyFilled {
+                    order_id,
+                    role,
+                    price,
+                    qty,
+                    ts,
+                    fee,
+                    fee_asset,
+                    ..
+                }) => (order_id, role, price, qty, ts, fee, fee_asset),
+                _ => continue,
+            };
+            let owner = match owner(*order_id) {
+                Some(owner) => owner,
+                None => continue,
+            };
+            let record = self.charge(*order_id, owner, *role, price, qty, *ts);
+            *fee = Some(record.fee.clone());
+            *fee_asset = Some(record.asset);
+            records.push(record);
+        }
+        records
+    }
+
+    /* Helpers */
+
+    fn charge(
+        &mut self,
+        order_id: Uuid,
+        (account, (order_asset, price_asset)): (AccountId, Symbol<Asset>),
+        role: LiquidityRole,
+        price: &BigDecimal,
+        qty: &BigDecimal,
+        ts: SystemTime,
+    ) -> FeeRecord<Asset> {
+        let notional = price * qty;
+        // the execution counts towards the tier from the next one on
+        let rate = self.account_tier(account, ts).rates.rate(role).clone();
+        self.volumes
+            .entry(account)
+            .or_default()
+            .push_back((ts, notional.clone()));
+
+        let fee = &notional * &rate;
+        let (fee, asset) = match self.fee_currency {
+            FeeCurrency::PriceAsset => (fee, price_asset),
+            FeeCurrency::OrderAsset => (qty * &rate, order_asset),
+            FeeCurrency::Designated(asset) => match self
+                .rate_source
+                .as_ref()
+                .and_then(|source| source.rate(price_asset, asset))
+            {
+                Some(conversion) => (fee * conversion, asset),
+                None => (fee, price_asset),
+            },
+        };
+
+        FeeRecord {
+            order_id,
+            account,
+            role,
+            notional,
+            rate,
+            fee,
+            asset,
+            ts,
+        }
+    }
+
+    fn rolling_volume(&mut self, account: AccountId, now: SystemTime) -> BigDecimal {
+        let executions = match self.volumes.get_mut(&account) {
+            Some(executions) => executions,
+            None => return BigDecimal::zero(),
+        };
+        let window = self.window;
+        while executions
+            .front()
+            .is_some_and(|(ts, _)| now.duration_since(*ts).unwrap_or_default() > window)
+        {
+            executions.pop_front();
+        }
+        executions.iter().map(|(_, notional)| notional).sum()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::domain::OrderSide;
+    use super::super::orderbook::Orderbook;
+    use super::super::orders;
+    use super::*;
+    use std::str::FromStr;
+
+    #[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
+    pub enum Asset {
+        USD,
+        BTC,
+        FEE,
+    }
+
+    const SYMBOL: Symbol<Asset> = (Asset::BTC, Asset::USD);
+
+    fn bigdec(num: &str) -> BigDecimal {
+        BigDecimal::from_str(num).unwrap()
+    }
+
+    fn rates(maker: &str, taker: &str) -> FeeRates {
+        FeeRates {
+            maker: bigdec(maker),
+            taker: bigdec(taker),
+        }
+    }
+
+    fn get_fee_engine() -> FeeEngine<Asset> {
+        FeeEngine::new(
+            vec![
+                FeeTier {
+                    min_volume: bigdec("1000"),
+                    rates: rates("-0.0001", "0.0005"),
+                },
+                FeeTier {
+                    min_volume: bigdec("0"),
+                    rates: rates("0.001", "0.002"),
+                },
+            ],
+            DEFAULT_VOLUME_WINDOW,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn reject_empty_schedule() {
+        assert!(matches!(
+            FeeEngine::<Asset>::new(vec![], DEFAULT_VOLUME_WINDOW),
+            Err(ConfigError::Invalid("fee schedule without tiers"))
+        ));
+    }
+
+    #[test]
+    fn maker_taker_fees() {
+        let mut book = Orderbook::new(Asset::BTC, Asset::USD);
+        let mut fees = get_fee_engine();
+        let ts = SystemTime::now();
+
+        let ask = orders::new_limit_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Ask,
+            bigdec("100"),
+            bigdec("20"),
+            ts,
+        );
+        fees.register_order(ask.order_id(), 1, SYMBOL);
+        book.process_order(ask);
+
+        let bid = orders::new_market_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Bid,
+            bigdec("10"),
+            ts,
+        );
+        fees.register_order(bid.order_id(), 2, SYMBOL);
+        let records = fees.on_results(&book.process_order(bid), ts);
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].account, 2);
+        assert_eq!(records[0].role, LiquidityRole::Taker);
+        assert_eq!(records[0].fee, bigdec("2"));
+        assert_eq!(records[0].asset, Asset::USD);
+        assert_eq!(records[1].account, 1);
+        assert_eq!(records[1].role, LiquidityRole::Maker);
+        assert_eq!(records[1].fee, bigdec("1"));
+
+        // both accounts reached the upper tier
+        let tier = fees.account_tier(1, ts);
+        assert_eq!(tier.tier, 1);
+        assert_eq!(tier.volume, bigdec("1000"));
+        assert_eq!(tier.rates, rates("-0.0001", "0.0005"));
+    }
+
+    #[test]
+    fn volume_window_rolls() {
+        let mut fees = get_fee_engine();
+        let start = SystemTime::now();
+        fees.charge(
+            Uuid::nil(),
+            (7, SYMBOL),
+            LiquidityRole::Taker,
+            &bigdec("5000"),
+            &bigdec("1"),
+            start,
+        );
+        assert_eq!(fees.account_tier(7, start).tier, 1);
+
+        let later = start + DEFAULT_VOLUME_WINDOW + Duration::from_secs(1);
+        let tier = fees.account_tier(7, later);
+        assert_eq!(tier.tier, 0);
+        assert_eq!(tier.volume, bigdec("0"));
+        assert_eq!(fees.account_tier(8, later).tier, 0);
+    }
+
+    #[test]
+    fn fee_currencies() {
+        let mut fees = FeeEngine::flat(rates("0.001", "0.002"));
+        let ts = SystemTime::now();
+        let charge = |fees: &mut FeeEngine<Asset>| {
+            let record = fees.charge(
+                Uuid::nil(),
+                (1, SYMBOL),
+                LiquidityRole::Taker,
+                &bigdec("100"),
+                &bigdec("5"),
+                ts,
+            );
+            (record.fee, record.asset)
+        };
+
+        fees.set_fee_currency(FeeCurrency::OrderAsset);
+        assert_eq!(charge(&mut fees), (bigdec("0.010"), Asset::BTC));
+
+        // no conversion rate known yet
+        fees.set_fee_currency(FeeCurrency::Designated(Asset::FEE));
+        assert_eq!(charge(&mut fees), (bigdec("1.000"), Asset::USD));
+
+        let mut conversion = FixedRates::new();
+        conversion.set(Asset::FEE, Asset::USD, bigdec("0.5"));
+        fees.set_rate_source(Box::new(conversion));
+        assert_eq!(charge(&mut fees), (bigdec("2"), Asset::FEE));
+    }
+}
@@ -0,0 +1,277 @@
+use bigdecimal::{BigDecimal, One, Zero};
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+use std::time::{Duration, SystemTime};
+
+use super::config::ConfigError;
+use super::engine::Symbol;
+use super::orderbook::Orderbook;
+
+/// Decimals the premium EMA is kept at, it would grow a few with every sample
+const PREMIUM_SCALE: i64 = 12;
+
+/// Parameters of mark price smoothing and funding
+#[derive(Debug, Clone)]
+pub struct FundingConfig {
+    /// weight of the newest premium sample in the EMA, in (0, 1]
+    pub ema_alpha: BigDecimal,
+    /// time between funding events
+    pub interval: Duration,
+    /// interest component added to the premium every interval
+    pub interest_rate: BigDecimal,
+    /// absolute cap of the funding rate
+    pub max_rate: BigDecimal,
+}
+
+impl FundingConfig {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.ema_alpha <= BigDecimal::zero() || self.ema_alpha > BigDecimal::one() {
+            return Err(ConfigError::Invalid("EMA weight outside (0, 1]"));
+        }
+        if self.interval.is_zero() {
+            return Err(ConfigError::Invalid("funding interval not positive"));
+        }
+        if self.max_rate < BigDecimal::zero() {
+            return Err(ConfigError::Invalid("maximum funding rate negative"));
+        }
+        Ok(())
+    }
+}
+
+/// Periodic funding payment to be settled between longs and shorts.
+///
+/// Positive rate: longs pay shorts `position * mark_price * rate`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FundingEvent<Asset> {
+    pub symbol: Symbol<Asset>,
+    pub mark_price: BigDecimal,
+    pub index_price: BigDecimal,
+    pub rate: BigDecimal,
+    pub ts: SystemTime,
+}
+
+/// Mark price and funding rate of a perpetual-style market.
+///
+/// Mark price is the index price adjusted by the EMA of the book premium
+/// `(mid - index) / index`. The funding rate is the average premium over the
+/// interval plus interest, clamped to `max_rate`.
+pub struct FundingTracker<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    symbol: Symbol<Asset>,
+    config: FundingConfig,
+    premium_ema: Option<BigDecimal>,
+    premium_sum: BigDecimal,
+    premium_samples: u64,
+    next_funding: SystemTime,
+}
+
+impl<Asset> FundingTracker<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    /// Create tracker with the first funding due one interval after `start`
+    pub fn new(
+        symbol: Symbol<Asset>,
+        config: FundingConfig,
+        start: SystemTime,
+    ) -> Result<Self, ConfigError> {
+        config.validate()?;
+        let next_funding = start + config.interval;
+        Ok(FundingTracker {
+            symbol,
+            config,
+            premium_ema: None,
+            premium_sum: BigDecimal::zero(),
+            premium_samples: 0,
+            next_funding,
+        })
+    }
+
+    /// Feed book mid price together with the index price at the same moment
+    pub fn update(&mut self, mid: &BigDecimal, index_price: &BigDecimal) {
+        if index_price.is_zero() {
+            return;
+        }
+        let premium = (mid - index_price) / index_price;
+
+        let ema = match self.premium_ema.take() {
+            Some(ema) => {
+                &self.config.ema_alpha * &premium
+                    + (BigDecimal::one() - &self.config.ema_alpha) * ema
+            }
+            None => premium.clone(),
+        };
+        self.premium_ema = Some(ema.round(PREMIUM_SCALE));
+        self.premium_sum += premium;
+        self.premium_samples += 1;
+    }
+
+    /// Take mid price from the book, skipped when the book is one-sided
+    pub fn sample(&mut self, book: &Orderbook<Asset>, index_price: &BigDecimal) {
+        if let Some(mid) = book.mid_price() {
+            self.update(&mid, index_price);
+        }
+    }
+
+    pub fn mark_price(&self, index_price: &BigDecimal) -> BigDecimal {
+        match &self.premium_ema {
+            Some(ema) => index_price * (BigDecimal::one() + ema),
+            None => index_price.clone(),
+        }
+    }
+
+    /// Produce funding event if the interval elapsed by `now`
+    pub fn funding(
+        &mut self,
+        index_price: &BigDecimal,
+        now: SystemTime,
+    ) -> Option<FundingEvent<Asset>> {
+        if now < self.next_funding {
+            return None;
+        }
+
+        let avg_premium = if self.premium_samples == 0 {
+            BigDecimal::zero()
+        } else {
+            &self.premium_sum / BigDecimal::from(self.premium_samples)
+        };
+        let rate = clamp(
+            avg_premium + &self.config.interest_rate,
+            &self.config.max_rate,
+        );
+
+        let event = FundingEvent {
+            symbol: self.symbol,
+            mark_price: self.mark_price(index_price),
+            index_price: index_price.clone(),
+            rate,
+            ts: now,
+        };
+
+        self.premium_sum = BigDecimal::zero();
+        self.premium_samples = 0;
+        while self.next_funding <= now {
+            self.next_funding += self.config.interval;
+        }
+
+        Some(event)
+    }
+}
+
+fn clamp(value: BigDecimal, limit: &BigDecimal) -> BigDecimal {
+    if &value > limit {
+        limit.clone()
+    } else if value < -limit {
+        -limit
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+
+    #[derive(PartialEq, Eq, Debug, Copy, Clone)]
+    pub enum Asset {
+        USD,
+        BTC,
+    }
+
+    fn bigdec(num: &str) -> BigDecimal {
+        BigDecimal::from_str(num).unwrap()
+    }
+
+    fn get_config() -> FundingConfig {
+        FundingConfig {
+            ema_alpha: bigdec("0.5"),
+            interval: Duration::from_secs(8 * 3600),
+            interest_rate: bigdec("0.0001"),
+            max_rate: bigdec("0.0075"),
+        }
+    }
+
+    fn get_tracker(start: SystemTime) -> FundingTracker<Asset> {
+        FundingTracker::new((Asset::BTC, Asset::USD), get_config(), start).unwrap()
+    }
+
+    #[test]
+    fn reject_config() {
+        let symbol = (Asset::BTC, Asset::USD);
+        let zero_interval = FundingConfig {
+            interval: Duration::ZERO,
+            ..get_config()
+        };
+        assert!(matches!(
+            FundingTracker::new(symbol, zero_interval, SystemTime::now()),
+            Err(ConfigError::Invalid("funding interval not positive"))
+        ));
+        let no_weight = FundingConfig {
+            ema_alpha: bigdec("0"),
+            ..get_config()
+        };
+        assert!(FundingTracker::new(symbol, no_weight, SystemTime::now()).is_err());
+    }
+
+    #[test]
+    fn mark_price_follows_premium_ema() {
+        let mut tracker = get_tracker(SystemTime::now());
+        let index = bigdec("100");
+        assert_eq!(tracker.mark_price(&index), index);
+
+        tracker.update(&bigdec("102"), &index);
+        assert_eq!(tracker.mark_price(&index), bigdec("102"));
+
+        tracker.update(&bigdec("100"), &index);
+        assert_eq!(tracker.mark_price(&index), bigdec("101"));
+
+        // the EMA keeps a fixed number of decimals however many samples it takes
+        let mut tracker = FundingTracker::new(
+            (Asset::BTC, Asset::USD),
+            FundingConfig {
+                ema_alpha: bigdec("0.3"),
+                ..get_config()
+            },
+            SystemTime::now(),
+        )
+        .unwrap();
+        for i in 0..200 {
+            tracker.update(
+                &(bigdec("100.07") + BigDecimal::from(i % 7)),
+                &bigdec("99.3"),
+            );
+        }
+        let (_, scale) = tracker
+            .premium_ema
+            .as_ref()
+            .unwrap()
+            .as_bigint_and_exponent();
+        assert!(scale <= PREMIUM_SCALE);
+    }
+
+    #[test]
+    fn funding_every_interval() {
+        let start = SystemTime::now();
+        let mut tracker = get_tracker(start);
+        let index = bigdec("100");
+
+        tracker.update(&bigdec("100.2"), &index);
+        tracker.update(&bigdec("100.4"), &index);
+        assert!(tracker.funding(&index, start).is_none());
+
+        let due = start + Duration::from_secs(8 * 3600);
+        let event = tracker.funding(&index, due).unwrap();
+        assert_eq!(event.rate, bigdec("0.0031"));
+        assert!(tracker.funding(&index, due).is_none());
+
+        // clamped rate
+        tracker.update(&bigdec("110"), &index);
+        let event = tracker
+            .funding(&index, due + Duration::from_secs(8 * 3600))
+            .unwrap();
+        assert_eq!(event.rate, bigdec("0.0075"));
+    }
+}
@@ -0,0 +1,202 @@
+use bigdecimal::BigDecimal;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+use super::orderbook::{OrderProcessingResult, Success};
+
+/// Effect of an amendment on the order's place in the queue
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PriorityImpact {
+    Kept,
+    Lost,
+}
+
+/// Single link of an order's amendment chain
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AmendRecord {
+    pub order_id: Uuid,
+    /// None when the order was an unlimited peg
+    pub prev_price: Option<BigDecimal>,
+    pub prev_qty: BigDecimal,
+    pub price: BigDecimal,
+    pub qty: BigDecimal,
+    pub priority: PriorityImpact,
+    pub ts: SystemTime,
+}
+
+/// Amendment chains of orders, built from processing results.
+///
+/// Chains outlive the orders themselves, call `forget` once an order's
+/// history is no longer needed.
+#[derive(Debug, Default)]
+pub struct OrderHistory {
+    amendments: HashMap<Uuid, Vec<AmendRecord>>,
+}
+
+impl OrderHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn on_results<Asset>(&mut self, results: &OrderProcessingResult<Asset>)
+    where
+        Asset: Debug + Clone,
+    {
+        for result in results {
+            if let Ok(Success::Amended {
+                order_id,
+                price,
+                qty,
+                prev_price,
+                prev_qty,
+                priority_kept,
+                ts,
+            }) = result
+            {
+                self.amendments
+                    .entry(*order_id)
+                    .or_default()
+                    .push(AmendRecord {
+                        order_id: *order_id,
+                        prev_price: prev_price.clone(),
+                        prev_qty: prev_qty.clone(),
+                        price: price.clone(),
+                        qty: qty.clone(),
+                        priority: if *priority_kept {
+                            PriorityImpact::Kept
+                        } else {
+                            PriorityImpact::Lost
+                        },
+                        ts: *ts,
+                    });
+            }
+        }
+    }
+
+    /// Amendments of the order, oldest first
+    pub fn amendments(&self, order_id: Uuid) -> &[AmendRecord] {
+        self.amendments
+            .get(&order_id)
+            .map_or(&[], |chain| chain.as_slice())
+    }
+
+    pub fn forget(&mut self, order_id: Uuid) {
+        self.amendments.remove(&order_id);
+    }
+
+    /// Write every amendment as CSV, ordered by time, for regulatory reporting.
+    ///
+    /// Timestamps are nanoseconds since the Unix epoch.
+    pub fn export_amendments<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let mut records: Vec<&AmendRecord> = self.amendments.values().flatten().collect();
+        records.sort_by_key(|record| record.ts);
+
+        writeln!(writer, "ts,order_id,prev_price,prev_qty,price,qty,priority")?;
+        for record in records {
+            let ts = record
+                .ts
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_nanos());
+            let prev_price = record
+                .prev_price
+                .as_ref()
+                .map_or(String::new(), BigDecimal::to_string);
+            let priority = match record.priority {
+                PriorityImpact::Kept => "kept",
+                PriorityImpact::Lost => "lost",
+            };
+            writeln!(
+                writer,
+                "{},{},{},{},{},{},{}",
+                ts,
+                record.order_id,
+                prev_price,
+                record.prev_qty,
+                record.price,
+                record.qty,
+                priority
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::domain::OrderSide;
+    use super::super::orderbook::Orderbook;
+    use super::super::orders;
+    use super::*;
+    use std::str::FromStr;
+
+    #[derive(PartialEq, Eq, Debug, Copy, Clone)]
+    pub enum Asset {
+        USD,
+        BTC,
+    }
+
+    fn bigdec(num: &str) -> BigDecimal {
+        BigDecimal::from_str(num).unwrap()
+    }
+
+    #[test]
+    fn amendment_chain() {
+        let mut book = Orderbook::new(Asset::BTC, Asset::USD);
+        let mut history = OrderHistory::new();
+
+        let order = orders::new_limit_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Ask,
+            bigdec("101"),
+            bigdec("2"),
+            SystemTime::now(),
+        );
+        let order_id = order.order_id();
+        history.on_results(&book.process_order(order));
+        assert!(history.amendments(order_id).is_empty());
+
+        let amends = [
+            orders::amend_order_qty_request(
+                order_id,
+                OrderSide::Ask,
+                bigdec("1"),
+                SystemTime::now(),
+            ),
+            orders::amend_order_price_request(
+                order_id,
+                OrderSide::Ask,
+                bigdec("102"),
+                SystemTime::now(),
+            ),
+        ];
+        for amend in amends {
+            history.on_results(&book.process_order(amend));
+        }
+
+        let chain = history.amendments(order_id);
+        assert_eq!(chain.len(), 2);
+        assert_eq!(chain[0].prev_price, Some(bigdec("101")));
+        assert_eq!(chain[0].prev_qty, bigdec("2"));
+        assert_eq!(chain[0].qty, bigdec("1"));
+        assert_eq!(chain[0].priority, PriorityImpact::Kept);
+        assert_eq!(chain[1].prev_price, Some(bigdec("101")));
+        assert_eq!(chain[1].price, bigdec("102"));
+        assert_eq!(chain[1].priority, PriorityImpact::Lost);
+
+        let mut csv = vec![];
+        history.export_amendments(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[2].ends_with(&format!("{},101,1,102,1,lost", order_id)));
+
+        history.forget(order_id);
+        assert!(history.amendments(order_id).is_empty());
+    }
+}
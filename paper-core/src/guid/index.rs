@@ -0,0 +1,233 @@
+use bigdecimal::{BigDecimal, Zero};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::time::{Duration, SystemTime};
+
+use super::engine::{MatchingEngine, Symbol};
+use super::orderbook::Orderbook;
+
+/// Market contributing to the index with its relative weight
+#[derive(Debug, Clone)]
+pub struct IndexComponent<Asset> {
+    pub symbol: Symbol<Asset>,
+    pub weight: BigDecimal,
+}
+
+/// Weighted index price over the mid prices of several books.
+///
+/// Mids older than `max_staleness` are ignored, as well as mids deviating
+/// from the median of the fresh ones by more than `max_deviation` (fraction,
+/// e.g. 0.05 for 5%). Weights of the remaining components are renormalized.
+pub struct IndexPriceCalculator<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq + Hash,
+{
+    components: Vec<IndexComponent<Asset>>,
+    max_staleness: Duration,
+    max_deviation: BigDecimal,
+    observations: HashMap<Symbol<Asset>, (BigDecimal, SystemTime)>,
+}
+
+impl<Asset> IndexPriceCalculator<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq + Hash,
+{
+    pub fn new(
+        components: Vec<IndexComponent<Asset>>,
+        max_staleness: Duration,
+        max_deviation: BigDecimal,
+    ) -> Self {
+        IndexPriceCalculator {
+            components,
+            max_staleness,
+            max_deviation,
+            observations: HashMap::new(),
+        }
+    }
+
+    /// Record mid price of the component market observed at `ts`, mids not
+    /// positive are ignored
+    pub fn observe(&mut self, symbol: Symbol<Asset>, mid: BigDecimal, ts: SystemTime) {
+        if mid <= BigDecimal::zero() {
+            return;
+        }
+        if self.components.iter().any(|c| c.symbol == symbol) {
+            self.observations.insert(symbol, (mid, ts));
+        }
+    }
+
+    /// Take current mid prices of all component books of the engine.
+    ///
+    /// Books without two-sided quotes keep their previous observation,
+    /// which eventually becomes stale.
+    pub fn sample(&mut self, engine: &MatchingEngine<Asset>, ts: SystemTime) {
+        for i in 0..self.components.len() {
+            let symbol = self.components[i].symbol;
+            if let Some(mid) = engine.book(symbol).and_then(Orderbook::mid_price) {
+                self.observe(symbol, mid, ts);
+            }
+        }
+    }
+
+    /// Calculate index price at `now` from the usable observations
+    pub fn index_price(&self, now: SystemTime) -> Option<BigDecimal> {
+        let fresh: Vec<(&BigDecimal, &BigDecimal)> = self
+            .components
+            .iter()
+            .filter_map(|component| {
+                let (mid, ts) = self.observations.get(&component.symbol)?;
+                let age = now.duration_since(*ts).unwrap_or_default();
+                if age > self.max_staleness {
+                    None
+                } else {
+                    Some((mid, &component.weight))
+                }
+            })
+            .collect();
+
+        let median = median(fresh.iter().map(|(mid, _)| (*mid).clone()).collect())?;
+        if median.is_zero() {
+            return None;
+        }
+
+        let mut weighted_sum = BigDecimal::zero();
+        let mut total_weight = BigDecimal::zero();
+        for (mid, weight) in fresh {
+            let deviation = (mid - &median).abs() / &median;
+            if deviation <= self.max_deviation {
+                weighted_sum += mid * weight;
+                total_weight += weight;
+            }
+        }
+
+        if total_weight.is_zero() {
+            None
+        } else {
+            Some(weighted_sum / total_weight)
+        }
+    }
+}
+
+fn median(mut values: Vec<BigDecimal>) -> Option<BigDecimal> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort();
+    let mid = values.len() / 2;
+    if values.len() % 2 == 1 {
+        Some(values[mid].clone())
+    } else {
+        Some((&values[mid - 1] + &values[mid]) / BigDecimal::from(2))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::domain::OrderSide;
+    use super::super::orders;
+    use super::*;
+    use std::str::FromStr;
+
+    #[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
+    pub enum Asset {
+        USD,
+        EUR,
+        USDT,
+        BTC,
+    }
+
+    fn bigdec(num: &str) -> BigDecimal {
+        BigDecimal::from_str(num).unwrap()
+    }
+
+    fn component(price_asset: Asset, weight: &str) -> IndexComponent<Asset> {
+        IndexComponent {
+            symbol: (Asset::BTC, price_asset),
+            weight: bigdec(weight),
+        }
+    }
+
+    fn get_calculator() -> IndexPriceCalculator<Asset> {
+        IndexPriceCalculator::new(
+            vec![
+                component(Asset::USD, "2"),
+                component(Asset::USDT, "1"),
+                component(Asset::EUR, "1"),
+            ],
+            Duration::from_secs(10),
+            bigdec("0.05"),
+        )
+    }
+
+    #[test]
+    fn weighted_index() {
+        let now = SystemTime::now();
+        let mut calculator = get_calculator();
+        assert_eq!(calculator.index_price(now), None);
+
+        calculator.observe((Asset::BTC, Asset::USD), bigdec("100"), now);
+        calculator.observe((Asset::BTC, Asset::USDT), bigdec("103"), now);
+        assert_eq!(calculator.index_price(now), Some(bigdec("101")));
+    }
+
+    #[test]
+    fn stale_and_outlier_ignored() {
+        let now = SystemTime::now();
+        let mut calculator = get_calculator();
+
+        calculator.observe((Asset::BTC, Asset::USD), bigdec("100"), now);
+        calculator.observe((Asset::BTC, Asset::USDT), bigdec("101"), now);
+        // outlier
+        calculator.observe((Asset::BTC, Asset::EUR), bigdec("150"), now);
+        assert_eq!(
+            calculator.index_price(now),
+            Some(bigdec("301") / bigdec("3"))
+        );
+
+        // only the latest observation is fresh
+        let later = now + Duration::from_secs(30);
+        calculator.observe((Asset::BTC, Asset::USDT), bigdec("102"), later);
+        assert_eq!(calculator.index_price(later), Some(bigdec("102")));
+    }
+
+    #[test]
+    fn non_positive_mid_ignored() {
+        let now = SystemTime::now();
+        let mut calculator = get_calculator();
+
+        calculator.observe((Asset::BTC, Asset::USD), bigdec("0"), now);
+        assert_eq!(calculator.index_price(now), None);
+        calculator.observe((Asset::BTC, Asset::USDT), bigdec("-5"), now);
+        assert_eq!(calculator.index_price(now), None);
+
+        // nor do they replace a good observation
+        calculator.observe((Asset::BTC, Asset::USD), bigdec("100"), now);
+        calculator.observe((Asset::BTC, Asset::USD), bigdec("0"), now);
+        assert_eq!(calculator.index_price(now), Some(bigdec("100")));
+    }
+
+    #[test]
+    fn sample_engine_books() {
+        let now = SystemTime::now();
+        let mut engine = MatchingEngine::new();
+        engine.add_book(Asset::BTC, Asset::USD);
+        for (side, price) in [(OrderSide::Bid, "99"), (OrderSide::Ask, "101")] {
+            engine.submit(
+                (Asset::BTC, Asset::USD),
+                orders::new_limit_order_request(
+                    Asset::BTC,
+                    Asset::USD,
+                    side,
+                    bigdec(price),
+                    bigdec("1"),
+                    now,
+                ),
+            );
+        }
+
+        let mut calculator = get_calculator();
+        calculator.sample(&engine, now);
+        assert_eq!(calculator.index_price(now), Some(bigdec("100")));
+    }
+}
@@ -0,0 +1,50 @@
+use std::fmt::Debug;
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use super::engine::Symbol;
+use super::orders::OrderRequest;
+
+/// Write-ahead log of the requests an engine accepted.
+///
+/// Requests are appended before they reach a book, so replaying the journal
+/// into fresh books (see `reconcile`) rebuilds the markets after a restart.
+pub trait Journal<Asset>
+where
+    Asset: Debug + Clone,
+{
+    fn append(&mut self, symbol: Symbol<Asset>, request: &OrderRequest<Asset>) -> io::Result<()>;
+
+    /// Make appended requests durable
+    fn flush(&mut self) -> io::Result<()>;
+}
+
+/// In-memory journal, flushing is a no-op
+impl<Asset> Journal<Asset> for Vec<(Symbol<Asset>, OrderRequest<Asset>)>
+where
+    Asset: Debug + Clone,
+{
+    fn append(&mut self, symbol: Symbol<Asset>, request: &OrderRequest<Asset>) -> io::Result<()> {
+        self.push((symbol, request.clone()));
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Journal kept by the caller while the engine writes to it
+impl<Asset, J> Journal<Asset> for Arc<Mutex<J>>
+where
+    Asset: Debug + Clone,
+    J: Journal<Asset> + ?Sized,
+{
+    fn append(&mut self, symbol: Symbol<Asset>, request: &OrderRequest<Asset>) -> io::Result<()> {
+        self.lock().unwrap().append(symbol, request)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.lock().unwrap().flush()
+    }
+}
@@ -0,0 +1,393 @@
+use bigdecimal::{BigDecimal, RoundingMode, Zero};
+use std::fmt::Debug;
+use std::hash::Hash;
+use uuid::Uuid;
+
+use super::clock::Clock;
+use super::domain::{AccountId, OrderSide};
+use super::engine::{MatchingEngine, Symbol};
+use super::orderbook::{OrderProcessingResult, Success};
+use super::orders::{self, OrderRequest};
+
+const ERR_BAD_SPREAD: &str = "spread must be positive";
+const ERR_BAD_SIZE: &str = "quote size must be positive";
+const ERR_BAD_SKEW: &str = "skew must not be negative";
+const ERR_BAD_INVENTORY: &str = "inventory limit must be positive";
+const ERR_BAD_PRICE: &str = "initial price must be positive";
+const ERR_UNKNOWN_MARKET: &str = "unknown market";
+
+/// How the liquidity provider quotes
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuoteParams {
+    /// distance between bid and ask as a fraction of the fair price
+    pub spread: BigDecimal,
+    /// quantity quoted on each side
+    pub size: BigDecimal,
+    /// shift of both quotes per unit of inventory, as a fraction of the fair
+    /// price, long inventory moves the quotes down so they sell it off
+    pub skew: BigDecimal,
+    /// the bid is pulled once long this much, the ask once short this much
+    pub max_inventory: BigDecimal,
+    /// fair price until the market trades, the last trade price after
+    pub initial_price: BigDecimal,
+}
+
+impl QuoteParams {
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.spread <= BigDecimal::zero() {
+            return Err(ERR_BAD_SPREAD);
+        }
+        if self.size <= BigDecimal::zero() {
+            return Err(ERR_BAD_SIZE);
+        }
+        if self.skew < BigDecimal::zero() {
+            return Err(ERR_BAD_SKEW);
+        }
+        if self.max_inventory <= BigDecimal::zero() {
+            return Err(ERR_BAD_INVENTORY);
+        }
+        if self.initial_price <= BigDecimal::zero() {
+            return Err(ERR_BAD_PRICE);
+        }
+        Ok(())
+    }
+}
+
+/// Order of the liquidity provider resting in the book
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quote {
+    pub order_id: Uuid,
+    pub side: OrderSide,
+    pub price: BigDecimal,
+    pub remaining: BigDecimal,
+}
+
+/// Market maker keeping a bid and an ask around the fair price of a market.
+///
+/// Quotes are plain limit orders sent through the engine, so they are
+/// journaled, checked and matched like those of any other participant. On
+/// every `poll` quotes whose price moved are replaced, unchanged ones keep
+/// their place in the queue. Fills arrive with other participants' requests,
+/// so all processing results of the market have to be fed back through
+/// `on_results` to keep the inventory the quotes are skewed by.
+pub struct LiquidityProvider<Asset, C>
+where
+    Asset: Debug + Clone + Copy + Eq + Hash,
+    C: Clock,
+{
+    clock: C,
+    symbol: Symbol<Asset>,
+    params: QuoteParams,
+    account: Option<AccountId>,
+    /// base asset bought minus sold
+    inventory: BigDecimal,
+    quotes: Vec<Quote>,
+}
+
+impl<Asset, C> LiquidityProvider<Asset, C>
+where
+    Asset: Debug + Clone + Copy + Eq + Hash,
+    C: Clock,
+{
+    pub fn new(clock: C, symbol: Symbol<Asset>, params: QuoteParams) -> Result<Self, &'static str> {
+        params.validate()?;
+        Ok(LiquidityProvider {
+            clock,
+            symbol,
+            params,
+            account: None,
+            inventory: BigDecimal::zero(),
+            quotes: vec![],
+        })
+    }
+
+    /// Attribute quotes to the account, positions and fees then show up in
+    /// the engine's account views
+    pub fn set_account(&mut self, account: Option<AccountId>) {
+        self.account = account;
+    }
+
+    pub fn set_params(&mut self, params: QuoteParams) -> Result<(), &'static str> {
+        params.validate()?;
+        self.params = params;
+        Ok(())
+    }
+
+    pub fn params(&self) -> &QuoteParams {
+        &self.params
+    }
+
+    pub fn inventory(&self) -> &BigDecimal {
+        &self.inventory
+    }
+
+    pub fn quotes(&self) -> &[Quote] {
+        &self.quotes
+    }
+
+    /// Bid and ask the provider wants in the book now, None for a side it
+    /// does not quote
+    pub fn target_quotes(
+        &self,
+        engine: &MatchingEngine<Asset>,
+    ) -> Result<(Option<BigDecimal>, Option<BigDecimal>), &'static str> {
+        let book = engine.book(self.symbol).ok_or(ERR_UNKNOWN_MARKET)?;
+        let scale = book.config().price_scale;
+        let fair = book.reference_price().unwrap_or(&self.params.initial_price);
+
+        let center = fair - fair * &self.params.skew * &self.inventory;
+        let half_spread = fair * &self.params.spread / BigDecimal::from(2);
+        let bid = scale.round(&(&center - &half_spread), RoundingMode::Floor);
+        let mut ask = scale.round(&(&center + &half_spread), RoundingMode::Ceiling);
+        if ask <= bid {
+            ask = &bid + scale.tick();
+        }
+
+        let bid = Some(bid)
+            .filter(|bid| *bid > BigDecimal::zero() && self.inventory < self.params.max_inventory);
+        let ask = Some(ask).filter(|_| -&self.inventory < self.params.max_inventory);
+        Ok((bid, ask))
+    }
+
+    /// Replace quotes off the target prices and fill in missing ones
+    pub fn poll(
+        &mut self,
+        engine: &mut MatchingEngine<Asset>,
+    ) -> Result<Vec<OrderProcessingResult<Asset>>, &'static str> {
+        let (bid, ask) = self.target_quotes(engine)?;
+        let mut results = vec![];
+        for (side, target) in [(OrderSide::Bid, bid), (OrderSide::Ask, ask)] {
+            let current = self.quotes.iter().find(|quote| quote.side == side).cloned();
+            if let Some(quote) = &current {
+                if Some(&quote.price) == target.as_ref() {
+                    continue;
+                }
+                results.push(self.send(
+                    engine,
+                    orders::limit_order_cancel_request(quote.order_id, side),
+                ));
+                self.drop_quote(quote.order_id);
+            }
+
+            if let Some(price) = target {
+                let (order_asset, price_asset) = self.symbol;
+                let request = orders::new_limit_order_request(
+                    order_asset,
+                    price_asset,
+                    side,
+                    price.clone(),
+                    self.params.size.clone(),
+                    self.clock.now(),
+                );
+                let order_id = request.order_id();
+                self.quotes.push(Quote {
+                    order_id,
+                    side,
+                    price,
+                    remaining: self.params.size.clone(),
+                });
+                let sent = self.send(engine, request);
+                let resting = sent
+                    .iter()
+                    .any(|result| matches!(result, Ok(Success::Accepted { .. })));
+                if !resting {
+                    self.drop_quote(order_id);
+                }
+                results.push(sent);
+            }
+        }
+        Ok(results)
+    }
+
+    /// Pull every quote from the book
+    pub fn withdraw(
+        &mut self,
+        engine: &mut MatchingEngine<Asset>,
+    ) -> Vec<OrderProcessingResult<Asset>> {
+        let quotes: Vec<(Uuid, OrderSide)> = self
+            .quotes
+            .iter()
+            .map(|quote| (quote.order_id, quote.side))
+            .collect();
+        quotes
+            .into_iter()
+            .map(|(order_id, side)| {
+                let results = self.send(engine, orders::limit_order_cancel_request(order_id, side));
+                self.drop_quote(order_id);
+                results
+            })
+            .collect()
+    }
+
+    /// Account fills and cancels of quotes found in processing results of
+    /// the market
+    pub fn on_results(&mut self, results: &OrderProcessingResult<Asset>) {
+        for result in results {
+            match result {
+                Ok(Success::Filled { order_id, qty, .. })
+                | Ok(Success::PartiallyFilled { order_id, qty, .. }) => {
+                    self.record_fill(*order_id, qty)
+                }
+                Ok(Success::Cancelled { order_id, .. })
+                | Ok(Success::Expired { order_id, .. })
+                | Ok(Success::DustCancelled { order_id, .. }) => self.drop_quote(*order_id),
+                Ok(Success::SelfTradePrevented {
+                    order_id,
+                    remaining,
+                    ..
+                }) => match self
+                    .quotes
+                    .iter_mut()
+                    .find(|quote| quote.order_id == *order_id)
+                {
+                    Some(_) if remaining.is_zero() => self.drop_quote(*order_id),
+                    Some(quote) => quote.remaining = remaining.clone(),
+                    None => (),
+                },
+                _ => (),
+            }
+        }
+    }
+
+    /* Helpers */
+
+    fn send(
+        &mut self,
+        engine: &mut MatchingEngine<Asset>,
+        request: OrderRequest<Asset>,
+    ) -> OrderProcessingResult<Asset> {
+        let results = match self.account {
+            Some(account) => engine.submit_for(account, self.symbol, request),
+            None => engine.submit(self.symbol, request),
+        };
+        self.on_results(&results);
+        results
+    }
+
+    fn record_fill(&mut self, order_id: Uuid, qty: &BigDecimal) {
+        let quote = match self
+            .quotes
+            .iter_mut()
+            .find(|quote| quote.order_id == order_id)
+        {
+            Some(quote) => quote,
+            None => return,
+        };
+        quote.remaining -= qty;
+        match quote.side {
+            OrderSide::Bid => self.inventory += qty,
+            OrderSide::Ask => self.inventory -= qty,
+        }
+        if quote.remaining.is_zero() {
+            self.drop_quote(order_id);
+        }
+    }
+
+    fn drop_quote(&mut self, order_id: Uuid) {
+        self.quotes.retain(|quote| quote.order_id != order_id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::clock::ManualClock;
+    use super::*;
+    use std::str::FromStr;
+    use std::time::SystemTime;
+
+    #[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
+    pub enum Asset {
+        USD,
+        BTC,
+    }
+
+    const SYMBOL: Symbol<Asset> = (Asset::BTC, Asset::USD);
+
+    fn bigdec(num: &str) -> BigDecimal {
+        BigDecimal::from_str(num).unwrap()
+    }
+
+    fn prices(provider: &LiquidityProvider<Asset, &ManualClock>) -> Vec<(OrderSide, BigDecimal)> {
+        provider
+            .quotes()
+            .iter()
+            .map(|quote| (quote.side, quote.price.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn quotes_skewed_by_inventory() {
+        let clock = ManualClock::new(SystemTime::now());
+        let mut engine = MatchingEngine::new();
+        engine.add_book(Asset::BTC, Asset::USD);
+        let params = QuoteParams {
+            spread: bigdec("0.02"),
+            size: bigdec("1"),
+            skew: bigdec("0.01"),
+            max_inventory: bigdec("2"),
+            initial_price: bigdec("100"),
+        };
+        let mut provider = LiquidityProvider::new(&clock, SYMBOL, params.clone()).unwrap();
+
+        provider.poll(&mut engine).unwrap();
+        assert_eq!(
+            prices(&provider),
+            vec![
+                (OrderSide::Bid, bigdec("99")),
+                (OrderSide::Ask, bigdec("101"))
+            ]
+        );
+        assert_eq!(engine.book(SYMBOL).unwrap().bid_queue.len(), 1);
+
+        // nothing moved, quotes stay in place
+        let first = provider.quotes().to_vec();
+        assert!(provider.poll(&mut engine).unwrap().is_empty());
+        assert_eq!(provider.quotes(), first.as_slice());
+
+        // a seller hits the bid, long one unit the quotes move down by 1%
+        let sell = orders::new_market_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Ask,
+            bigdec("1"),
+            SystemTime::now(),
+        );
+        let results = engine.submit(SYMBOL, sell);
+        provider.on_results(&results);
+        assert_eq!(provider.inventory(), &bigdec("1"));
+
+        provider.poll(&mut engine).unwrap();
+        assert_eq!(
+            prices(&provider),
+            vec![
+                (OrderSide::Bid, bigdec("97.02")),
+                (OrderSide::Ask, bigdec("99"))
+            ]
+        );
+
+        // at the inventory limit only the ask is quoted
+        let sell = orders::new_market_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Ask,
+            bigdec("1"),
+            SystemTime::now(),
+        );
+        provider.on_results(&engine.submit(SYMBOL, sell));
+        provider.poll(&mut engine).unwrap();
+        assert!(provider
+            .quotes()
+            .iter()
+            .all(|quote| quote.side == OrderSide::Ask));
+
+        provider.withdraw(&mut engine);
+        assert!(provider.quotes().is_empty());
+        assert_eq!(engine.book(SYMBOL).unwrap().ask_queue.len(), 0);
+
+        let bad = QuoteParams {
+            size: bigdec("0"),
+            ..params
+        };
+        assert!(LiquidityProvider::new(&clock, SYMBOL, bad).is_err());
+    }
+}
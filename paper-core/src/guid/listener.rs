@@ -0,0 +1,84 @@
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+
+use super::orderbook::{Failed, OrderProcessingResult, Success};
+
+/// Callbacks for the events of a book, registered with
+/// `Orderbook::add_listener`.
+///
+/// Events are delivered synchronously before `process_order` and the other
+/// processing calls return, in the order of their processing results. Every
+/// callback does nothing by default, so listeners implement only what they
+/// care about.
+pub trait OrderbookListener<Asset>
+where
+    Asset: Debug + Clone,
+{
+    /// `Accepted` and `StopAccepted`
+    fn on_accept(&mut self, _event: &Success<Asset>) {}
+
+    /// `Filled` and `PartiallyFilled`
+    fn on_fill(&mut self, _event: &Success<Asset>) {}
+
+    /// Quantity taken off the book without trading: `Cancelled`,
+    /// `CancelledRemainder`, `DustCancelled`, `SelfTradePrevented` and
+    /// `Expired`
+    fn on_cancel(&mut self, _event: &Success<Asset>) {}
+
+    fn on_reject(&mut self, _failure: &Failed) {}
+
+    /// Events of no other callback, e.g. `Amended` or `Triggered`
+    fn on_other(&mut self, _event: &Success<Asset>) {}
+}
+
+/// Listener kept by the caller while the book calls it
+impl<Asset, L> OrderbookListener<Asset> for Arc<Mutex<L>>
+where
+    Asset: Debug + Clone,
+    L: OrderbookListener<Asset> + ?Sized,
+{
+    fn on_accept(&mut self, event: &Success<Asset>) {
+        self.lock().unwrap().on_accept(event)
+    }
+
+    fn on_fill(&mut self, event: &Success<Asset>) {
+        self.lock().unwrap().on_fill(event)
+    }
+
+    fn on_cancel(&mut self, event: &Success<Asset>) {
+        self.lock().unwrap().on_cancel(event)
+    }
+
+    fn on_reject(&mut self, failure: &Failed) {
+        self.lock().unwrap().on_reject(failure)
+    }
+
+    fn on_other(&mut self, event: &Success<Asset>) {
+        self.lock().unwrap().on_other(event)
+    }
+}
+
+/// Hand every processing result to its callback
+pub fn dispatch<Asset, L>(listener: &mut L, results: &OrderProcessingResult<Asset>)
+where
+    Asset: Debug + Clone,
+    L: OrderbookListener<Asset> + ?Sized,
+{
+    for result in results {
+        match result {
+            Ok(event @ Success::Accepted { .. }) | Ok(event @ Success::StopAccepted { .. }) => {
+                listener.on_accept(event)
+            }
+            Ok(event @ Success::Filled { .. }) | Ok(event @ Success::PartiallyFilled { .. }) => {
+                listener.on_fill(event)
+            }
+            Ok(event @ Success::Cancelled { .. })
+            | Ok(event @ Success::CancelledRemainder { .. })
+            | Ok(event @ Success::DustCancelled { .. })
+            | Ok(event @ Success::SelfTradePrevented { .. })
+            | Ok(event @ Success::Expired { .. }) => listener.on_cancel(event),
+            Ok(event) => listener.on_other(event),
+            Err(failure) => listener.on_reject(failure),
+        }
+    }
+}
@@ -0,0 +1,295 @@
+use bigdecimal::{BigDecimal, Zero};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+
+use super::domain::Order;
+
+/// Allocation of incoming quantity among resting orders of one price level.
+///
+/// Used by the orderbook for every opposite price level it matches against,
+/// so venues can pick the execution rule per market.
+pub trait MatchPolicy<Asset>
+where
+    Asset: Debug + Clone,
+{
+    /// Split `qty` among the `level` orders, given in time priority.
+    ///
+    /// Returns quantity allocated to each order, in the same order as `level`.
+    /// No allocation may exceed the order quantity, and all of them together
+    /// may not exceed `qty`.
+    fn allocate(&mut self, level: &[Order<Asset>], qty: &BigDecimal) -> Vec<BigDecimal>;
+}
+
+/// Classic price-time priority: earlier orders are filled first
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PriceTimeFifo;
+
+impl<Asset> MatchPolicy<Asset> for PriceTimeFifo
+where
+    Asset: Debug + Clone,
+{
+    fn allocate(&mut self, level: &[Order<Asset>], qty: &BigDecimal) -> Vec<BigDecimal> {
+        fill_in_sequence(level.iter().map(|order| &order.qty), qty)
+    }
+}
+
+/// Larger orders are filled first, time breaks ties
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SizePriority;
+
+impl<Asset> MatchPolicy<Asset> for SizePriority
+where
+    Asset: Debug + Clone,
+{
+    fn allocate(&mut self, level: &[Order<Asset>], qty: &BigDecimal) -> Vec<BigDecimal> {
+        let mut by_size: Vec<usize> = (0..level.len()).collect();
+        // stable sort keeps time priority among equal sizes
+        by_size.sort_by(|a, b| level[*b].qty.cmp(&level[*a].qty));
+
+        let sized = fill_in_sequence(by_size.iter().map(|i| &level[*i].qty), qty);
+        let mut allocations = vec![BigDecimal::zero(); level.len()];
+        for (i, allocated) in by_size.into_iter().zip(sized) {
+            allocations[i] = allocated;
+        }
+        allocations
+    }
+}
+
+/// Quantity is split proportionally to order sizes.
+///
+/// Shares are rounded down to the finest precision present in the level or
+/// the incoming quantity, and the rounding leftover is filled in time priority.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ProRata;
+
+impl<Asset> MatchPolicy<Asset> for ProRata
+where
+    Asset: Debug + Clone,
+{
+    fn allocate(&mut self, level: &[Order<Asset>], qty: &BigDecimal) -> Vec<BigDecimal> {
+        let total: BigDecimal = level.iter().map(|order| &order.qty).sum();
+        if &total <= qty {
+            // whole level is taken
+            return level.iter().map(|order| order.qty.clone()).collect();
+        }
+
+        let scale = level
+            .iter()
+            .map(|order| order.qty.as_bigint_and_exponent().1)
+            .chain(std::iter::once(qty.as_bigint_and_exponent().1))
+            .max()
+            .unwrap_or(0);
+
+        let mut allocations: Vec<BigDecimal> = level
+            .iter()
+            .map(|order| (qty * &order.qty / &total).with_scale(scale))
+            .collect();
+
+        let allocated: BigDecimal = allocations.iter().sum();
+        let leftovers: Vec<BigDecimal> = level
+            .iter()
+            .zip(allocations.iter())
+            .map(|(order, share)| &order.qty - share)
+            .collect();
+        let extra = fill_in_sequence(leftovers.iter(), &(qty - allocated));
+        for (share, extra) in allocations.iter_mut().zip(extra) {
+            *share += extra;
+        }
+        allocations
+    }
+}
+
+/// Orders of the level are filled in random order.
+///
+/// Discourages racing for queue position. Seed the generator to get
+/// reproducible allocations in tests and simulations.
+pub struct RandomLottery {
+    rng: StdRng,
+}
+
+impl RandomLottery {
+    pub fn new() -> Self {
+        RandomLottery {
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    pub fn with_seed(seed: u64) -> Self {
+        RandomLottery {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Default for RandomLottery {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Asset> MatchPolicy<Asset> for RandomLottery
+where
+    Asset: Debug + Clone,
+{
+    fn allocate(&mut self, level: &[Order<Asset>], qty: &BigDecimal) -> Vec<BigDecimal> {
+        let mut draw: Vec<usize> = (0..level.len()).collect();
+        draw.shuffle(&mut self.rng);
+
+        let drawn = fill_in_sequence(draw.iter().map(|i| &level[*i].qty), qty);
+        let mut allocations = vec![BigDecimal::zero(); level.len()];
+        for (i, allocated) in draw.into_iter().zip(drawn) {
+            allocations[i] = allocated;
+        }
+        allocations
+    }
+}
+
+/// Built-in match policy a book is created with, see `BookConfig`.
+///
+/// Policies with state of their own, like a seeded `RandomLottery`, are
+/// set with `Orderbook::set_match_policy` instead.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchAlgorithm {
+    /// `PriceTimeFifo`
+    #[default]
+    PriceTime,
+    /// `SizePriority`
+    SizePriority,
+    /// `ProRata`, as futures markets often use
+    ProRata,
+}
+
+impl MatchAlgorithm {
+    pub fn policy<Asset>(&self) -> Box<dyn MatchPolicy<Asset> + Send>
+    where
+        Asset: Debug + Clone,
+    {
+        match self {
+            MatchAlgorithm::PriceTime => Box::new(PriceTimeFifo),
+            MatchAlgorithm::SizePriority => Box::new(SizePriority),
+            MatchAlgorithm::ProRata => Box::new(ProRata),
+        }
+    }
+}
+
+/// Fill sizes one after another until `qty` runs out
+pub(super) fn fill_in_sequence<'a, I>(sizes: I, qty: &BigDecimal) -> Vec<BigDecimal>
+where
+    I: Iterator<Item = &'a BigDecimal>,
+{
+    let mut remaining = qty.clone();
+    sizes
+        .map(|size| {
+            let allocated = if size < &remaining {
+                size.clone()
+            } else {
+                remaining.clone()
+            };
+            remaining -= &allocated;
+            allocated
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::domain::{OrderSide, TimeInForce};
+    use super::*;
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    #[derive(PartialEq, Eq, Debug, Copy, Clone)]
+    pub enum Asset {
+        USD,
+        BTC,
+    }
+
+    fn bigdec(num: &str) -> BigDecimal {
+        BigDecimal::from_str(num).unwrap()
+    }
+
+    fn level(sizes: &[&str]) -> Vec<Order<Asset>> {
+        sizes
+            .iter()
+            .map(|qty| Order {
+                order_id: Uuid::new_v4(),
+                order_asset: Asset::BTC,
+                price_asset: Asset::USD,
+                side: OrderSide::Ask,
+                price: bigdec("100"),
+                qty: bigdec(qty),
+                filled: BigDecimal::zero(),
+                liquidation: false,
+                time_in_force: TimeInForce::GTC,
+                owner: None,
+            })
+            .collect()
+    }
+
+    fn allocate<P: MatchPolicy<Asset>>(mut policy: P, sizes: &[&str], qty: &str) -> Vec<BigDecimal> {
+        policy.allocate(&level(sizes), &bigdec(qty))
+    }
+
+    fn expected(allocations: &[&str]) -> Vec<BigDecimal> {
+        allocations.iter().map(|qty| bigdec(qty)).collect()
+    }
+
+    #[test]
+    fn fifo_allocation() {
+        assert_eq!(
+            allocate(PriceTimeFifo, &["1", "2", "3"], "2.5"),
+            expected(&["1", "1.5", "0"])
+        );
+    }
+
+    #[test]
+    fn size_priority_allocation() {
+        assert_eq!(
+            allocate(SizePriority, &["1", "3", "3"], "4"),
+            expected(&["0", "3", "1"])
+        );
+    }
+
+    #[test]
+    fn lottery_allocation() {
+        let sizes = ["1", "1", "1", "1", "1", "1", "1", "1"];
+        let seeded = allocate(RandomLottery::with_seed(7), &sizes, "3");
+        assert_eq!(seeded, allocate(RandomLottery::with_seed(7), &sizes, "3"));
+
+        let total: BigDecimal = seeded.iter().sum();
+        assert_eq!(total, bigdec("3"));
+        assert_eq!(seeded.iter().filter(|qty| qty.is_zero()).count(), 5);
+
+        let mut lottery = RandomLottery::with_seed(7);
+        let level = level(&sizes);
+        let draws: Vec<Vec<BigDecimal>> = (0..10)
+            .map(|_| lottery.allocate(&level, &bigdec("1")))
+            .collect();
+        assert!(draws.iter().any(|draw| draw != &draws[0]));
+    }
+
+    #[test]
+    fn pro_rata_allocation() {
+        assert_eq!(
+            allocate(ProRata, &["1", "3"], "2.0"),
+            expected(&["0.5", "1.5"])
+        );
+        // rounding leftover is filled in time priority
+        assert_eq!(
+            allocate(ProRata, &["1", "1", "1"], "2"),
+            expected(&["1", "1", "0"])
+        );
+        assert_eq!(
+            allocate(ProRata, &["1", "1", "1"], "2.0"),
+            expected(&["0.8", "0.6", "0.6"])
+        );
+        assert_eq!(
+            allocate(ProRata, &["1", "1"], "5"),
+            expected(&["1", "1"])
+        );
+    }
+}
@@ -0,0 +1,38 @@
+
+pub mod accounts;
+pub mod algo;
+pub mod auction;
+pub mod clock;
+pub mod completed;
+pub mod config;
+pub mod dashboard;
+pub mod dark_pool;
+pub mod domain;
+pub mod engine;
+pub mod errors;
+pub mod execution;
+pub mod fees;
+pub mod funding;
+pub mod history;
+pub mod index;
+pub mod journal;
+pub mod liquidity;
+pub mod listener;
+pub mod matching;
+pub mod orderbook;
+pub mod order_queues;
+pub mod orders;
+pub mod price_key;
+pub mod reconcile;
+pub mod replay;
+pub mod risk;
+pub mod scheduled;
+pub mod session;
+pub mod settlement;
+pub mod shadow;
+pub mod surveillance;
+pub mod tape;
+pub mod trade_ids;
+
+// private
+mod validation;
\ No newline at end of file
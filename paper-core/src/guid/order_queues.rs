@@ -0,0 +1,1111 @@
+use bigdecimal::BigDecimal;
+use serde::de::{self, Deserializer};
+use serde::{Deserialize, Serialize, Serializer};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::time;
+use uuid::Uuid;
+
+use super::domain::OrderSide;
+use super::price_key::{PriceKey, PriceScale};
+
+/// Containers double once full unless configured otherwise
+pub const DEFAULT_GROWTH_FACTOR: f64 = 2.0;
+
+#[derive(Clone)]
+struct OrderIndex {
+    id: Uuid,
+    price: PriceKey,
+    timestamp: time::SystemTime,
+    order_side: OrderSide,
+    priority: bool,
+    /// handle of the order when indexed, an index whose handle is no longer
+    /// the order's own is dangling
+    handle: u64,
+}
+
+/// Resting order with the handle of its current index
+struct Slot<T> {
+    order: T,
+    handle: u64,
+    priority: bool,
+    /// level of the current index
+    price: PriceKey,
+}
+
+// Arrange at first by price, then priority orders and after that by time
+impl Ord for OrderIndex {
+    fn cmp(&self, other: &Self) -> Ordering {
+        if self.price < other.price {
+            match self.order_side {
+                OrderSide::Bid => Ordering::Less,
+                OrderSide::Ask => Ordering::Greater,
+            }
+        } else if self.price > other.price {
+            match self.order_side {
+                OrderSide::Bid => Ordering::Greater,
+                OrderSide::Ask => Ordering::Less,
+            }
+        } else if self.priority != other.priority {
+            self.priority.cmp(&other.priority)
+        } else {
+            // FIFO
+            other.timestamp.cmp(&self.timestamp)
+        }
+    }
+}
+
+impl PartialOrd for OrderIndex {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for OrderIndex {
+    fn eq(&self, other: &Self) -> bool {
+        self.price == other.price
+            && self.priority == other.priority
+            && self.timestamp == other.timestamp
+    }
+}
+
+impl Eq for OrderIndex {}
+
+/// Index health of a queue, see `OrderQueue::stats`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct QueueStats {
+    /// resting orders, each with one live index
+    pub live: usize,
+    /// indices left by cancels and amends, not swept yet
+    pub stalled: usize,
+    /// operations since the last sweep, the next one is due past the
+    /// queue's `max_stalled`
+    pub ops_since_sweep: u64,
+}
+
+/// Resting order as written by `Serialize`
+#[derive(Serialize, Deserialize)]
+struct StoredOrder<T> {
+    id: Uuid,
+    price: BigDecimal,
+    timestamp: time::SystemTime,
+    priority: bool,
+    order: T,
+}
+
+/// Settings and resting orders of a queue, best first
+#[derive(Serialize, Deserialize)]
+struct StoredQueue<T> {
+    side: OrderSide,
+    max_stalled: u64,
+    min_capacity: usize,
+    growth_factor: f64,
+    price_scale: PriceScale,
+    orders: Vec<StoredOrder<T>>,
+}
+
+/// Public methods
+pub struct OrderQueue<T> {
+    // use Option in order to replace heap in mutable borrow
+    idx_queue: Option<BinaryHeap<OrderIndex>>,
+    orders: HashMap<Uuid, Slot<T>>,
+    /// resting orders per price level, levels without orders left out
+    level_sizes: HashMap<PriceKey, usize>,
+    /// handle of the next index pushed
+    next_handle: u64,
+    op_counter: u64,
+    max_stalled: u64,
+    queue_side: OrderSide,
+    /// containers are never shrunk below it
+    min_capacity: usize,
+    /// factor containers grow by once full
+    growth_factor: f64,
+    /// converts prices to the integer keys levels are ordered by
+    price_scale: PriceScale,
+}
+
+impl<T> OrderQueue<T> {
+    /// Create new order queue
+    ///
+    /// Queue is universal and could be used for both asks and bids
+    pub fn new(side: OrderSide, max_stalled: u64, capacity: usize) -> Self {
+        Self::with_growth(side, max_stalled, capacity, DEFAULT_GROWTH_FACTOR)
+    }
+
+    /// Create queue growing by `growth_factor` whenever full.
+    ///
+    /// Factors closer to 1 save memory at the cost of more frequent
+    /// reallocations on the insert path. Factors below 1 are treated as 1,
+    /// i.e. growing by a single slot.
+    pub fn with_growth(
+        side: OrderSide,
+        max_stalled: u64,
+        capacity: usize,
+        growth_factor: f64,
+    ) -> Self {
+        OrderQueue {
+            idx_queue: Some(BinaryHeap::with_capacity(capacity)),
+            orders: HashMap::with_capacity(capacity),
+            level_sizes: HashMap::new(),
+            next_handle: 0,
+            op_counter: 0,
+            max_stalled,
+            queue_side: side,
+            min_capacity: capacity,
+            growth_factor,
+            price_scale: PriceScale::default(),
+        }
+    }
+
+    /// Key price levels by the tick size of the market.
+    ///
+    /// Orders priced off the tick grid are refused by `insert` and `amend`.
+    pub fn with_price_scale(mut self, price_scale: PriceScale) -> Self {
+        self.price_scale = price_scale;
+        self
+    }
+
+    pub fn price_scale(&self) -> PriceScale {
+        self.price_scale
+    }
+
+    /// Whether every resting order is priced on the tick grid of the scale
+    pub fn fits_price_scale(&self, price_scale: PriceScale) -> bool {
+        self.live_indices().all(|order_ptr| {
+            price_scale
+                .key(&self.price_scale.price(order_ptr.price))
+                .is_some()
+        })
+    }
+
+    /// Switch to another tick size, re-keying the levels of resting orders.
+    ///
+    /// Refused, returning false, if an order is off the new tick grid.
+    pub fn set_price_scale(&mut self, price_scale: PriceScale) -> bool {
+        let rekeyed: Option<Vec<OrderIndex>> = self
+            .live_indices()
+            .map(|order_ptr| {
+                let price = price_scale.key(&self.price_scale.price(order_ptr.price))?;
+                Some(OrderIndex {
+                    price,
+                    ..order_ptr.clone()
+                })
+            })
+            .collect();
+        match rekeyed {
+            Some(active_orders) => {
+                self.level_sizes.clear();
+                for order_ptr in &active_orders {
+                    self.add_to_level(order_ptr.price);
+                    if let Some(slot) = self.orders.get_mut(&order_ptr.id) {
+                        slot.price = order_ptr.price;
+                    }
+                }
+                self.idx_queue = Some(BinaryHeap::from(active_orders));
+                self.price_scale = price_scale;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Best order, read without touching the queue: every removal drops
+    /// the dangling indices it leaves on top, so the top index is live
+    pub fn peek(&self) -> Option<&T> {
+        let order_ptr = self.idx_queue.as_ref()?.peek()?;
+        self.orders.get(&order_ptr.id).map(|slot| &slot.order)
+    }
+
+    /// Get resting order by its ID
+    pub fn get(&self, id: Uuid) -> Option<&T> {
+        self.orders.get(&id).map(|slot| &slot.order)
+    }
+
+    /// Iterate over resting orders in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.orders.values().map(|slot| &slot.order)
+    }
+
+    pub fn len(&self) -> usize {
+        self.orders.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.orders.is_empty()
+    }
+
+    /// Number of orders the queue can hold without reallocating
+    pub fn capacity(&self) -> usize {
+        self.orders.capacity()
+    }
+
+    pub fn stats(&self) -> QueueStats {
+        QueueStats {
+            live: self.orders.len(),
+            stalled: self.index_len().saturating_sub(self.orders.len()),
+            ops_since_sweep: self.op_counter,
+        }
+    }
+
+    /// Drop dangling indices now rather than at the next automatic sweep.
+    ///
+    /// Returns the number of indices dropped.
+    pub fn compact(&mut self) -> usize {
+        let before = self.index_len();
+        self.op_counter = 0;
+        self.remove_stalled();
+        before - self.index_len()
+    }
+
+    /// Drop dangling indices and release memory left from busier periods.
+    ///
+    /// Containers more than twice as large as needed are shrunk, but never
+    /// below the initial capacity. Returns the number of indices dropped.
+    pub fn maintain(&mut self) -> usize {
+        let dropped = self.compact();
+
+        let target = self.orders.len().max(self.min_capacity);
+        if self.orders.capacity() > 2 * target {
+            self.orders.shrink_to(target);
+        }
+        if let Some(idx_queue) = self.idx_queue.as_mut() {
+            if idx_queue.capacity() > 2 * target {
+                idx_queue.shrink_to(target);
+            }
+        }
+        dropped
+    }
+
+    /// Number of distinct prices with resting orders
+    pub fn level_count(&self) -> usize {
+        self.level_sizes.len()
+    }
+
+    /// Resting orders best first, with their queue time and priority flag
+    pub fn ranked(&self) -> Vec<(&T, time::SystemTime, bool)> {
+        let mut indices: Vec<&OrderIndex> = self.live_indices().collect();
+        indices.sort_by(|a, b| b.cmp(a));
+        indices
+            .into_iter()
+            .map(|order_ptr| {
+                (
+                    &self.orders[&order_ptr.id].order,
+                    order_ptr.timestamp,
+                    order_ptr.priority,
+                )
+            })
+            .collect()
+    }
+
+    /// ID of the order last in the queue priority, the farthest from the touch
+    pub fn last_id(&self) -> Option<Uuid> {
+        self.live_indices().min().map(|order_ptr| order_ptr.id)
+    }
+
+    /// Get all orders at the best price, in the queue priority.
+    ///
+    /// Nothing ranks above an index of the best price, so in the heap all
+    /// of them sit in the subtree under the top one and only that subtree
+    /// is walked.
+    pub fn peek_level(&self) -> Vec<&T> {
+        let heap = match self.idx_queue.as_ref() {
+            Some(idx_queue) => idx_queue.as_slice(),
+            None => return vec![],
+        };
+        let best = match heap.first() {
+            Some(order_ptr) => order_ptr.price,
+            None => return vec![],
+        };
+
+        let mut level: Vec<&OrderIndex> = vec![];
+        let mut pending = vec![0];
+        while let Some(i) = pending.pop() {
+            let order_ptr = match heap.get(i) {
+                Some(order_ptr) if order_ptr.price == best => order_ptr,
+                _ => continue,
+            };
+            if self.is_live(order_ptr) {
+                level.push(order_ptr);
+            }
+            pending.extend([2 * i + 1, 2 * i + 2]);
+        }
+        level.sort_by(|a, b| b.cmp(a));
+        level
+            .into_iter()
+            .map(|order_ptr| &self.orders[&order_ptr.id].order)
+            .collect()
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        // remove order index from queue in any case
+        let order_ptr = self.idx_queue.as_mut()?.pop()?;
+
+        if self.is_live(&order_ptr) {
+            let order = self.remove_slot(order_ptr.id);
+            self.prune_top();
+            order
+        } else {
+            self.pop()
+        }
+    }
+
+    // Add new limit order to the queue, false if it exists or is off tick
+    pub fn insert(&mut self, id: Uuid, price: BigDecimal, ts: time::SystemTime, order: T) -> bool {
+        self.insert_with_priority(id, price, ts, false, order)
+    }
+
+    // Add new limit order ahead of regular orders with the same price
+    pub fn insert_with_priority(
+        &mut self,
+        id: Uuid,
+        price: BigDecimal,
+        ts: time::SystemTime,
+        priority: bool,
+        order: T,
+    ) -> bool {
+        if self.orders.contains_key(&id) {
+            // do not update existing order
+            return false;
+        }
+        let price = match self.price_scale.key(&price) {
+            Some(key) => key,
+            None => return false,
+        };
+
+        self.reserve_slot();
+
+        // store new order
+        let handle = self.push_index(id, price, ts, priority);
+        self.orders.insert(
+            id,
+            Slot {
+                order,
+                handle,
+                priority,
+                price,
+            },
+        );
+        self.add_to_level(price);
+        true
+    }
+
+    // use it when price was changed
+    pub fn amend(&mut self, id: Uuid, price: BigDecimal, ts: time::SystemTime, order: T) -> bool {
+        if self.price_scale.key(&price).is_none() {
+            return false;
+        }
+        if let Some(stored) = self.orders.get_mut(&id) {
+            // store new order data
+            stored.order = order;
+            self.reindex(vec![(id, price, ts)]);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Move orders to new prices and queue times, e.g. after a batch of
+    /// amendments.
+    ///
+    /// Each order gets a new index and a new handle, the old index is left
+    /// dangling, so a move costs the same as an insert however deep the
+    /// queue. Order data has to be updated with `update` beforehand. The
+    /// last move of an order wins, unknown orders and prices off tick are
+    /// skipped. Returns the number of orders moved.
+    pub fn reindex(&mut self, moves: Vec<(Uuid, BigDecimal, time::SystemTime)>) -> usize {
+        let mut moved = HashSet::new();
+        for (id, price, ts) in moves {
+            let (priority, key) = match (self.orders.get(&id), self.price_scale.key(&price)) {
+                (Some(slot), Some(key)) => (slot.priority, key),
+                _ => continue,
+            };
+            // a later move of the order leaves this index dangling in turn
+            let handle = self.push_index(id, key, ts, priority);
+            let previous = match self.orders.get_mut(&id) {
+                Some(slot) => {
+                    slot.handle = handle;
+                    std::mem::replace(&mut slot.price, key)
+                }
+                None => continue,
+            };
+            self.remove_from_level(previous);
+            self.add_to_level(key);
+            self.clean_check();
+            moved.insert(id);
+        }
+        self.prune_top();
+        moved.len()
+    }
+
+    pub fn cancel(&mut self, id: Uuid) -> bool {
+        match self.remove_slot(id) {
+            Some(_) => {
+                self.prune_top();
+                self.clean_check();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Replace order data keeping its place in the queue.
+    ///
+    /// Note: do not modify price or time, cause index doesn't change!
+    pub fn update(&mut self, id: Uuid, order: T) -> bool {
+        match self.orders.get_mut(&id) {
+            Some(stored) => {
+                stored.order = order;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /* Internal methods */
+
+    /// Used internally when current order is partially matched.
+    ///
+    /// Note: do not modify price or time, cause index doesn't change!
+    pub fn modify_current_order(&mut self, new_order: T) -> bool {
+        if let Some(order_id) = self.get_current_order_id() {
+            if let Some(stored) = self.orders.get_mut(&order_id) {
+                stored.order = new_order;
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Grow containers by the growth factor when there is no room for an order
+    fn reserve_slot(&mut self) {
+        let len = self.orders.len();
+        if len < self.orders.capacity() {
+            return;
+        }
+        let extra = ((len as f64) * (self.growth_factor - 1.0)).ceil().max(1.0) as usize;
+        self.orders.reserve(extra);
+        if let Some(idx_queue) = self.idx_queue.as_mut() {
+            let missing = (len + extra).saturating_sub(idx_queue.len());
+            idx_queue.reserve(missing);
+        }
+    }
+
+    /// Take order out of the slots and its level, leaving its index dangling
+    fn remove_slot(&mut self, id: Uuid) -> Option<T> {
+        let slot = self.orders.remove(&id)?;
+        self.remove_from_level(slot.price);
+        Some(slot.order)
+    }
+
+    fn add_to_level(&mut self, price: PriceKey) {
+        *self.level_sizes.entry(price).or_insert(0) += 1;
+    }
+
+    fn remove_from_level(&mut self, price: PriceKey) {
+        if let Some(size) = self.level_sizes.get_mut(&price) {
+            *size -= 1;
+            if *size == 0 {
+                self.level_sizes.remove(&price);
+            }
+        }
+    }
+
+    /// Drop dangling indices on top of the queue, keeping `peek` exact
+    fn prune_top(&mut self) {
+        let orders = &self.orders;
+        if let Some(idx_queue) = self.idx_queue.as_mut() {
+            while idx_queue
+                .peek()
+                .is_some_and(|order_ptr| !is_live(orders, order_ptr))
+            {
+                idx_queue.pop();
+            }
+        }
+    }
+
+    /// Verify if queue should be cleaned
+    fn clean_check(&mut self) {
+        if self.op_counter > self.max_stalled {
+            self.op_counter = 0;
+            self.remove_stalled()
+        } else {
+            self.op_counter += 1;
+        }
+    }
+
+    /// Remove dangling indices without orders from queue
+    fn remove_stalled(&mut self) {
+        if let Some(idx_queue) = self.idx_queue.take() {
+            let mut active_orders = idx_queue.into_vec();
+            let orders = &self.orders;
+            active_orders.retain(|order_ptr| is_live(orders, order_ptr));
+            self.idx_queue = Some(BinaryHeap::from(active_orders));
+        }
+    }
+
+    fn live_indices(&self) -> impl Iterator<Item = &OrderIndex> {
+        let orders = &self.orders;
+        self.idx_queue
+            .iter()
+            .flat_map(|idx_queue| idx_queue.iter())
+            .filter(move |order_ptr| is_live(orders, order_ptr))
+    }
+
+    fn index_len(&self) -> usize {
+        self.idx_queue.as_ref().map_or(0, BinaryHeap::len)
+    }
+
+    fn is_live(&self, order_ptr: &OrderIndex) -> bool {
+        is_live(&self.orders, order_ptr)
+    }
+
+    /// Index order under a new handle, returned
+    fn push_index(
+        &mut self,
+        id: Uuid,
+        price: PriceKey,
+        ts: time::SystemTime,
+        priority: bool,
+    ) -> u64 {
+        let handle = self.next_handle;
+        self.next_handle += 1;
+        self.idx_queue.as_mut().unwrap().push(OrderIndex {
+            id,
+            price,
+            timestamp: ts,
+            order_side: self.queue_side,
+            priority,
+            handle,
+        });
+        handle
+    }
+
+    /// Return ID of current order in queue
+    fn get_current_order_id(&self) -> Option<Uuid> {
+        let order_id = self.idx_queue.as_ref()?.peek()?;
+        Some(order_id.id)
+    }
+}
+
+/// Live orders with their queue priority, dangling indices left out
+impl<T: Serialize> Serialize for OrderQueue<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut indices: Vec<&OrderIndex> = self.live_indices().collect();
+        indices.sort_by(|a, b| b.cmp(a));
+        let orders = indices
+            .into_iter()
+            .map(|order_ptr| StoredOrder {
+                id: order_ptr.id,
+                price: self.price_scale.price(order_ptr.price),
+                timestamp: order_ptr.timestamp,
+                priority: order_ptr.priority,
+                order: &self.orders[&order_ptr.id].order,
+            })
+            .collect();
+        StoredQueue {
+            side: self.queue_side,
+            max_stalled: self.max_stalled,
+            min_capacity: self.min_capacity,
+            growth_factor: self.growth_factor,
+            price_scale: self.price_scale,
+            orders,
+        }
+        .serialize(serializer)
+    }
+}
+
+/// Index rebuilt from the stored orders, ranking them as before
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for OrderQueue<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let stored = StoredQueue::<T>::deserialize(deserializer)?;
+        let mut queue = OrderQueue::with_growth(
+            stored.side,
+            stored.max_stalled,
+            stored.min_capacity,
+            stored.growth_factor,
+        )
+        .with_price_scale(stored.price_scale);
+        for order in stored.orders {
+            let inserted = queue.insert_with_priority(
+                order.id,
+                order.price,
+                order.timestamp,
+                order.priority,
+                order.order,
+            );
+            if !inserted {
+                return Err(de::Error::custom("duplicate or off-tick order in queue"));
+            }
+        }
+        Ok(queue)
+    }
+}
+
+/// Whether the index is the current one of a resting order
+fn is_live<T>(orders: &HashMap<Uuid, Slot<T>>, order_ptr: &OrderIndex) -> bool {
+    orders
+        .get(&order_ptr.id)
+        .is_some_and(|slot| slot.handle == order_ptr.handle)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bigdecimal::BigDecimal;
+    use std::str::FromStr;
+
+    #[derive(Debug, Eq, PartialEq)]
+    struct TestOrder {
+        pub name: &'static str,
+    }
+
+    fn get_queue_empty(side: OrderSide) -> OrderQueue<TestOrder> {
+        OrderQueue::new(side, 5, 10)
+    }
+
+    fn get_queue_bids() -> OrderQueue<TestOrder> {
+        let mut bid_queue = get_queue_empty(OrderSide::Bid);
+        let o1: Uuid = Uuid::parse_str("00000000-0000-0000-0000-000000000000").unwrap();
+        let o2: Uuid = Uuid::parse_str("00000000-0000-0000-0000-000000000001").unwrap();
+        let o3: Uuid = Uuid::parse_str("00000000-0000-0000-0000-000000000002").unwrap();
+
+        assert!(bid_queue.insert(
+            o1,
+            BigDecimal::from_str("1.01").unwrap(),
+            time::SystemTime::now(),
+            TestOrder { name: "low bid" },
+        ));
+        assert!(bid_queue.insert(
+            o2,
+            BigDecimal::from_str("1.02").unwrap(),
+            time::SystemTime::now(),
+            TestOrder {
+                name: "high bid first"
+            },
+        ));
+        // same price but later
+        assert!(bid_queue.insert(
+            o3,
+            BigDecimal::from_str("1.02").unwrap(),
+            time::SystemTime::now(),
+            TestOrder {
+                name: "high bid second"
+            },
+        ));
+        assert_eq!(bid_queue.peek().unwrap().name, "high bid first");
+
+        bid_queue
+    }
+
+    fn get_queue_asks() -> OrderQueue<TestOrder> {
+        let mut ask_queue = get_queue_empty(OrderSide::Ask);
+        let o4: Uuid = Uuid::parse_str("00000000-0000-0000-0000-000000000003").unwrap();
+        let o5: Uuid = Uuid::parse_str("00000000-0000-0000-0000-000000000004").unwrap();
+        let o6: Uuid = Uuid::parse_str("00000000-0000-0000-0000-000000000005").unwrap();
+
+        assert!(ask_queue.insert(
+            o4,
+            BigDecimal::from_str("1.01").unwrap(),
+            time::SystemTime::now(),
+            TestOrder {
+                name: "low ask first"
+            },
+        ));
+        assert!(ask_queue.insert(
+            o5,
+            BigDecimal::from_str("1.02").unwrap(),
+            time::SystemTime::now(),
+            TestOrder { name: "high ask" },
+        ));
+        assert!(ask_queue.insert(
+            o6,
+            BigDecimal::from_str("1.01").unwrap(),
+            time::SystemTime::now(),
+            TestOrder {
+                name: "low ask second"
+            },
+        ));
+        assert_eq!(ask_queue.peek().unwrap().name, "low ask first");
+
+        ask_queue
+    }
+
+    #[test]
+    fn queue_operations_insert_unique() {
+        let mut bid_queue = get_queue_empty(OrderSide::Bid);
+        assert_eq!(bid_queue.peek(), None);
+        let id: Uuid = Uuid::parse_str("00000000-0000-0000-0000-000000000001").unwrap();
+
+        // insert unique
+        assert!(bid_queue.insert(
+            id,
+            BigDecimal::from_str("1.01").unwrap(),
+            time::SystemTime::now(),
+            TestOrder { name: "first bid" },
+        ));
+
+        // discard order with existing ID
+        assert!(!bid_queue.insert(
+            id,
+            BigDecimal::from_str("1.02").unwrap(),
+            time::SystemTime::now(),
+            TestOrder {
+                name: "another first bid"
+            },
+        ));
+    }
+
+    #[test]
+    fn queue_operations_ordering_bid() {
+        let mut bid_queue = get_queue_bids();
+
+        assert_eq!(bid_queue.pop().unwrap().name, "high bid first");
+        assert_eq!(bid_queue.pop().unwrap().name, "high bid second");
+        assert_eq!(bid_queue.pop().unwrap().name, "low bid");
+    }
+
+    #[test]
+    fn queue_operations_ordering_ask() {
+        let mut ask_queue = get_queue_asks();
+
+        assert_eq!(ask_queue.pop().unwrap().name, "low ask first");
+        assert_eq!(ask_queue.pop().unwrap().name, "low ask second");
+        assert_eq!(ask_queue.pop().unwrap().name, "high ask");
+    }
+
+    #[test]
+    fn queue_operations_modify_order() {
+        let mut bid_queue = get_queue_bids();
+
+        assert!(bid_queue.modify_current_order(TestOrder {
+            name: "current bid partially matched"
+        },));
+
+        assert_eq!(
+            bid_queue.pop().unwrap().name,
+            "current bid partially matched"
+        );
+        assert_eq!(bid_queue.pop().unwrap().name, "high bid second");
+        assert_eq!(bid_queue.pop().unwrap().name, "low bid");
+    }
+
+    #[test]
+    fn queue_operations_amend() {
+        let mut ask_queue = get_queue_asks();
+        let o4: Uuid = Uuid::parse_str("00000000-0000-0000-0000-000000000003").unwrap();
+        let o5: Uuid = Uuid::parse_str("00000000-0000-0000-0000-000000000004").unwrap();
+
+        // amend two orders in the queue
+        assert!(ask_queue.amend(
+            o5,
+            BigDecimal::from_str("0.99").unwrap(),
+            time::SystemTime::now(),
+            TestOrder { name: "new first" },
+        ));
+        assert!(ask_queue.amend(
+            o4,
+            BigDecimal::from_str("1.01").unwrap(),
+            time::SystemTime::now(),
+            TestOrder { name: "new last" },
+        ));
+        // non-exist order
+        assert!(!ask_queue.amend(
+            Uuid::new_v4(),
+            BigDecimal::from_str("3.03").unwrap(),
+            time::SystemTime::now(),
+            TestOrder {
+                name: "nonexistent"
+            },
+        ));
+
+        assert_eq!(ask_queue.pop().unwrap().name, "new first");
+        assert_eq!(ask_queue.pop().unwrap().name, "low ask second");
+        assert_eq!(ask_queue.pop().unwrap().name, "new last");
+    }
+
+    #[test]
+    fn queue_operations_amend_lazily() {
+        let mut ask_queue = get_queue_empty(OrderSide::Ask);
+        let id = Uuid::new_v4();
+        ask_queue.insert(
+            id,
+            BigDecimal::from_str("1.01").unwrap(),
+            time::SystemTime::now(),
+            TestOrder { name: "moving" },
+        );
+        ask_queue.insert(
+            Uuid::new_v4(),
+            BigDecimal::from_str("1.02").unwrap(),
+            time::SystemTime::now(),
+            TestOrder { name: "still" },
+        );
+
+        // every move leaves the previous index behind instead of rebuilding
+        for price in ["1.03", "1.00", "1.04"] {
+            assert!(ask_queue.amend(
+                id,
+                BigDecimal::from_str(price).unwrap(),
+                time::SystemTime::now(),
+                TestOrder { name: "moving" },
+            ));
+        }
+        assert_eq!(ask_queue.len(), 2);
+        assert_eq!(ask_queue.level_count(), 2);
+        // indices left on top are dropped right away, the one at 1.03 waits
+        assert_eq!(ask_queue.maintain(), 1);
+
+        assert_eq!(ask_queue.pop().unwrap().name, "still");
+        assert_eq!(ask_queue.pop().unwrap().name, "moving");
+        assert!(ask_queue.pop().is_none());
+    }
+
+    #[test]
+    fn queue_operations_level_count() {
+        let mut bid_queue = get_queue_empty(OrderSide::Bid);
+        let ids: Vec<Uuid> = ["1.01", "1.01", "1.02"]
+            .iter()
+            .map(|price| {
+                let id = Uuid::new_v4();
+                bid_queue.insert(
+                    id,
+                    BigDecimal::from_str(price).unwrap(),
+                    time::SystemTime::now(),
+                    TestOrder { name: "level" },
+                );
+                id
+            })
+            .collect();
+        assert_eq!(bid_queue.level_count(), 2);
+
+        // joining a level and emptying another
+        assert!(bid_queue.amend(
+            ids[2],
+            BigDecimal::from_str("1.01").unwrap(),
+            time::SystemTime::now(),
+            TestOrder { name: "level" },
+        ));
+        assert_eq!(bid_queue.level_count(), 1);
+        assert!(bid_queue.cancel(ids[0]));
+        assert_eq!(bid_queue.level_count(), 1);
+        bid_queue.pop();
+        bid_queue.pop();
+        assert_eq!(bid_queue.level_count(), 0);
+    }
+
+    #[test]
+    fn queue_operations_priority() {
+        let mut ask_queue = get_queue_asks();
+        let id: Uuid = Uuid::parse_str("00000000-0000-0000-0000-000000000006").unwrap();
+
+        assert!(ask_queue.insert_with_priority(
+            id,
+            BigDecimal::from_str("1.01").unwrap(),
+            time::SystemTime::now(),
+            true,
+            TestOrder { name: "priority ask" },
+        ));
+        // amended order keeps its priority
+        assert!(ask_queue.amend(
+            id,
+            BigDecimal::from_str("1.01").unwrap(),
+            time::SystemTime::now(),
+            TestOrder {
+                name: "amended priority ask"
+            },
+        ));
+
+        assert_eq!(ask_queue.pop().unwrap().name, "amended priority ask");
+        assert_eq!(ask_queue.pop().unwrap().name, "low ask first");
+        assert_eq!(ask_queue.pop().unwrap().name, "low ask second");
+        assert_eq!(ask_queue.pop().unwrap().name, "high ask");
+    }
+
+    #[test]
+    fn queue_operations_peek_level() {
+        let mut ask_queue = get_queue_asks();
+        let o4: Uuid = Uuid::parse_str("00000000-0000-0000-0000-000000000003").unwrap();
+        ask_queue.cancel(o4);
+
+        let level: Vec<&str> = ask_queue.peek_level().iter().map(|order| order.name).collect();
+        assert_eq!(level, vec!["low ask second"]);
+
+        // level is left in the queue
+        assert_eq!(ask_queue.pop().unwrap().name, "low ask second");
+        let level: Vec<&str> = ask_queue.peek_level().iter().map(|order| order.name).collect();
+        assert_eq!(level, vec!["high ask"]);
+
+        // deep level with orders cancelled and moved away inside it
+        let mut bid_queue = get_queue_empty(OrderSide::Bid);
+        let start = time::SystemTime::now();
+        let ids: Vec<Uuid> = (0..50).map(|_| Uuid::new_v4()).collect();
+        for (i, id) in ids.iter().enumerate() {
+            let price = if i % 2 == 0 { "1.02" } else { "1.01" };
+            bid_queue.insert(
+                *id,
+                BigDecimal::from_str(price).unwrap(),
+                start + time::Duration::from_millis(i as u64),
+                TestOrder { name: "bid" },
+            );
+        }
+        bid_queue.cancel(ids[10]);
+        bid_queue.amend(
+            ids[20],
+            BigDecimal::from_str("1.01").unwrap(),
+            start,
+            TestOrder { name: "moved" },
+        );
+        let level = bid_queue.peek_level();
+        assert_eq!(level.len(), 23);
+        assert!(level.iter().all(|order| order.name == "bid"));
+        // dangling indices are left for the next sweep
+        assert_eq!(bid_queue.stats().stalled, 2);
+    }
+
+    #[test]
+    fn queue_operations_maintain() {
+        let mut ask_queue = get_queue_empty(OrderSide::Ask);
+        let ids: Vec<Uuid> = (0..1000).map(|_| Uuid::new_v4()).collect();
+        for id in &ids {
+            ask_queue.insert(
+                *id,
+                BigDecimal::from_str("1.01").unwrap(),
+                time::SystemTime::now(),
+                TestOrder { name: "burst" },
+            );
+        }
+        // cancel most orders, leaving their indices behind
+        for id in &ids[..995] {
+            ask_queue.cancel(*id);
+        }
+        assert!(ask_queue.capacity() >= 1000);
+
+        // the cancelled ones were on top, only memory is left to release
+        assert_eq!(ask_queue.stats().stalled, 0);
+        ask_queue.maintain();
+        assert_eq!(ask_queue.len(), 5);
+        assert!(ask_queue.capacity() < 100);
+        assert_eq!(ask_queue.maintain(), 0);
+        assert_eq!(ask_queue.pop().unwrap().name, "burst");
+    }
+
+    #[test]
+    fn queue_operations_prune_top() {
+        let mut ask_queue = get_queue_asks();
+        let first: Uuid = Uuid::parse_str("00000000-0000-0000-0000-000000000003").unwrap();
+        let high: Uuid = Uuid::parse_str("00000000-0000-0000-0000-000000000004").unwrap();
+        let second: Uuid = Uuid::parse_str("00000000-0000-0000-0000-000000000005").unwrap();
+
+        // cancelled behind the best order, the index stays
+        assert!(ask_queue.cancel(high));
+        assert_eq!(ask_queue.stats().stalled, 1);
+
+        // cancelled on top, the next order is the best right away
+        assert!(ask_queue.cancel(first));
+        assert_eq!(ask_queue.stats().stalled, 1);
+        assert_eq!(ask_queue.peek().unwrap().name, "low ask second");
+
+        // moved away from the top, every dangling index above it goes
+        assert!(ask_queue.amend(
+            second,
+            BigDecimal::from_str("1.03").unwrap(),
+            time::SystemTime::now(),
+            TestOrder { name: "moved" },
+        ));
+        assert_eq!(ask_queue.stats().stalled, 0);
+        assert_eq!(ask_queue.peek().unwrap().name, "moved");
+
+        assert_eq!(ask_queue.pop().unwrap().name, "moved");
+        assert_eq!(ask_queue.stats().stalled, 0);
+        assert!(ask_queue.peek().is_none());
+    }
+
+    #[test]
+    fn queue_stats_and_compact() {
+        // sweeps left to the caller
+        let mut bid_queue = OrderQueue::new(OrderSide::Bid, 1000, 10);
+        let ids: Vec<Uuid> = (0..10).map(|_| Uuid::new_v4()).collect();
+        for id in &ids {
+            bid_queue.insert(
+                *id,
+                BigDecimal::from_str("1.01").unwrap(),
+                time::SystemTime::now(),
+                TestOrder { name: "quote" },
+            );
+        }
+        // behind the best ones, cancels at the top are pruned right away
+        for id in &ids[4..] {
+            bid_queue.cancel(*id);
+        }
+        let stats = bid_queue.stats();
+        assert_eq!((stats.live, stats.stalled), (4, 6));
+        assert!(stats.ops_since_sweep > 0);
+
+        assert_eq!(bid_queue.compact(), 6);
+        assert_eq!(
+            bid_queue.stats(),
+            QueueStats {
+                live: 4,
+                stalled: 0,
+                ops_since_sweep: 0,
+            }
+        );
+        assert_eq!(bid_queue.len(), 4);
+    }
+
+    #[test]
+    fn queue_operations_price_scale() {
+        let mut bid_queue = get_queue_bids();
+        let cents = PriceScale::from_tick(&BigDecimal::from_str("0.01").unwrap()).unwrap();
+        let even_cents = PriceScale::from_tick(&BigDecimal::from_str("0.02").unwrap()).unwrap();
+
+        // 1.01 is off the coarser grid
+        assert!(!bid_queue.fits_price_scale(even_cents));
+        assert!(!bid_queue.set_price_scale(even_cents));
+        assert_eq!(bid_queue.price_scale(), PriceScale::default());
+
+        assert!(bid_queue.set_price_scale(cents));
+        assert_eq!(bid_queue.price_scale(), cents);
+        assert_eq!(bid_queue.level_count(), 2);
+        assert_eq!(bid_queue.pop().unwrap().name, "high bid first");
+        assert_eq!(bid_queue.pop().unwrap().name, "high bid second");
+        assert_eq!(bid_queue.pop().unwrap().name, "low bid");
+    }
+
+    #[test]
+    fn queue_operations_growth() {
+        let mut bid_queue = OrderQueue::with_growth(OrderSide::Bid, 5, 16, 1.25);
+        for _ in 0..100 {
+            bid_queue.insert(
+                Uuid::new_v4(),
+                BigDecimal::from_str("1.01").unwrap(),
+                time::SystemTime::now(),
+                TestOrder { name: "bid" },
+            );
+        }
+        assert_eq!(bid_queue.len(), 100);
+        // grown by a quarter at a time instead of doubling
+        assert!(bid_queue.capacity() < 200);
+    }
+
+    #[test]
+    fn queue_operations_cancel_order1() {
+        let mut bid_queue = get_queue_bids();
+
+        let o2: Uuid = Uuid::parse_str("00000000-0000-0000-0000-000000000001").unwrap();
+        bid_queue.cancel(o2);
+
+        assert_eq!(bid_queue.pop().unwrap().name, "high bid second");
+        assert_eq!(bid_queue.pop().unwrap().name, "low bid");
+    }
+
+    #[test]
+    fn queue_operations_cancel_order2() {
+        let mut ask_queue = get_queue_asks();
+
+        let o6: Uuid = Uuid::parse_str("00000000-0000-0000-0000-000000000005").unwrap();
+        ask_queue.cancel(o6);
+
+        assert_eq!(ask_queue.pop().unwrap().name, "low ask first");
+        assert_eq!(ask_queue.pop().unwrap().name, "high ask");
+    }
+}
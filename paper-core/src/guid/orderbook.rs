@@ -0,0 +1,4893 @@
+// use library::utils::{serialize_bigdecimal, serialize_bigdecimal_opt};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::SystemTime;
+use uuid::Uuid;
+use bigdecimal::{BigDecimal, One, RoundingMode, ToPrimitive, Zero};
+use serde::de::Deserializer;
+use serde::ser::Serializer;
+
+
+use super::auction::{self, IndicativeUncross};
+use super::clock::{Clock, EventStamper, ManualClock, SystemClock};
+use super::completed::{CompletedOrder, CompletedOrders, OrderStatus, DEFAULT_COMPLETED_CAPACITY};
+use super::domain::{AccountId, Order, OrderSide, OrderType, TimeInForce};
+use super::engine::ERR_MARKET_HALTED;
+use super::fees::LiquidityRole;
+use super::journal::Journal;
+use super::listener::{self, OrderbookListener};
+use super::matching::{MatchAlgorithm, MatchPolicy};
+use super::order_queues::{OrderQueue, DEFAULT_GROWTH_FACTOR};
+use super::price_key::PriceScale;
+use super::trade_ids::{SequentialTradeIds, TradeId, TradeIdGenerator};
+use super::orders::{AmendOrder, OrderRequest};
+use super::validation::OrderRequestValidator;
+
+const MAX_STALLED_INDICES_IN_QUEUE: u64 = 10;
+const ORDER_QUEUE_INIT_CAPACITY: usize = 500;
+
+pub(super) const ERR_OUTSIDE_BAND: &str = "price outside band";
+pub(super) const ERR_POST_ONLY: &str = "market is post-only";
+pub(super) const ERR_CANCEL_ONLY: &str = "market is cancel-only";
+pub(super) const ERR_MARKET_SUSPENDED: &str = "market suspended";
+pub(super) const ERR_IN_AUCTION: &str = "market in auction call";
+
+pub type OrderProcessingResult<Asset> = Vec<Result<Success<Asset>, Failed>>;
+
+fn serialize_bigdecimal_opt<S>(bg: &Option<BigDecimal>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match bg {
+     Some(b) => serializer.serialize_f64(b.to_f64().unwrap()),
+     None => serializer.serialize_none(),
+    }
+}
+
+fn serialize_bigdecimal<S>(bg: &BigDecimal, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_f64(bg.to_f64().unwrap())
+}
+
+/// Fills read back from before fees were charged in the engine
+fn no_fee_asset<Asset>() -> Option<Asset> {
+    None
+}
+
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Success<Asset> {
+    Accepted {
+        order_id: Uuid,
+        order_asset: Asset,
+        order_type: OrderType,
+        price_asset: Asset,
+        #[serde(serialize_with = "serialize_bigdecimal_opt")]
+        price: Option<BigDecimal>,
+        /// price asked for when the band collar re-priced the order
+        #[serde(serialize_with = "serialize_bigdecimal_opt")]
+        collared_from: Option<BigDecimal>,
+        #[serde(serialize_with = "serialize_bigdecimal")]
+        qty: BigDecimal,
+        side: OrderSide,
+        ts: SystemTime,
+        liquidation: bool,
+    },
+
+    Filled {
+        order_id: Uuid,
+        /// same for both fills of the trade
+        trade_id: TradeId,
+        /// order on the other side of the trade
+        counterparty: Uuid,
+        side: OrderSide,
+        /// side of the order that took liquidity, same for both fills
+        aggressor: OrderSide,
+        /// whether this order rested or took liquidity
+        role: LiquidityRole,
+        order_type: OrderType,
+        #[serde(serialize_with = "serialize_bigdecimal")]
+        price: BigDecimal,
+        #[serde(serialize_with = "serialize_bigdecimal")]
+        qty: BigDecimal,
+        ts: SystemTime,
+        liquidation: bool,
+        /// charged by the fee engine of the matching engine, if it has one
+        #[serde(serialize_with = "serialize_bigdecimal_opt", default)]
+        fee: Option<BigDecimal>,
+        #[serde(default = "no_fee_asset")]
+        fee_asset: Option<Asset>,
+    },
+
+    PartiallyFilled {
+        order_id: Uuid,
+        /// same for both fills of the trade
+        trade_id: TradeId,
+        /// order on the other side of the trade
+        counterparty: Uuid,
+        side: OrderSide,
+        /// side of the order that took liquidity, same for both fills
+        aggressor: OrderSide,
+        /// whether this order rested or took liquidity
+        role: LiquidityRole,
+        order_type: OrderType,
+        #[serde(serialize_with = "serialize_bigdecimal")]
+        price: BigDecimal,
+        #[serde(serialize_with = "serialize_bigdecimal")]
+        qty: BigDecimal,
+        ts: SystemTime,
+        liquidation: bool,
+        /// charged by the fee engine of the matching engine, if it has one
+        #[serde(serialize_with = "serialize_bigdecimal_opt", default)]
+        fee: Option<BigDecimal>,
+        #[serde(default = "no_fee_asset")]
+        fee_asset: Option<Asset>,
+    },
+
+    Amended {
+        order_id: Uuid,
+        #[serde(serialize_with = "serialize_bigdecimal")]
+        price: BigDecimal,
+        #[serde(serialize_with = "serialize_bigdecimal")]
+        qty: BigDecimal,
+        /// price before the amendment, None for an unlimited peg
+        #[serde(serialize_with = "serialize_bigdecimal_opt")]
+        prev_price: Option<BigDecimal>,
+        #[serde(serialize_with = "serialize_bigdecimal")]
+        prev_qty: BigDecimal,
+        /// false when the order moved to the back of its level
+        priority_kept: bool,
+        ts: SystemTime,
+    },
+
+    Cancelled {
+        order_id: Uuid,
+        ts: SystemTime,
+    },
+
+    /// stop order parked until a trade reaches its trigger price
+    StopAccepted {
+        order_id: Uuid,
+        order_asset: Asset,
+        price_asset: Asset,
+        side: OrderSide,
+        #[serde(serialize_with = "serialize_bigdecimal")]
+        trigger_price: BigDecimal,
+        /// limit price of stop-limit orders
+        #[serde(serialize_with = "serialize_bigdecimal_opt")]
+        price: Option<BigDecimal>,
+        #[serde(serialize_with = "serialize_bigdecimal")]
+        qty: BigDecimal,
+        ts: SystemTime,
+        liquidation: bool,
+    },
+
+    /// stop order released into the book as a market or limit order,
+    /// followed by the usual processing events
+    Triggered {
+        order_id: Uuid,
+        #[serde(serialize_with = "serialize_bigdecimal")]
+        trigger_price: BigDecimal,
+        /// price of the trade that triggered it
+        #[serde(serialize_with = "serialize_bigdecimal")]
+        last_price: BigDecimal,
+        ts: SystemTime,
+    },
+
+    /// order held back until the pre-trade risk check decides on it
+    PendingNew {
+        order_id: Uuid,
+        ts: SystemTime,
+    },
+
+    /// order parked until its activation time
+    Scheduled {
+        order_id: Uuid,
+        activate_at: SystemTime,
+        ts: SystemTime,
+    },
+
+    /// scheduled order released into the book
+    Activated {
+        order_id: Uuid,
+        ts: SystemTime,
+    },
+
+    /// resting order removed at the end of its validity
+    Expired {
+        order_id: Uuid,
+        ts: SystemTime,
+    },
+
+    /// unfilled part of a market order that ran out of opposite orders
+    CancelledRemainder {
+        order_id: Uuid,
+        #[serde(serialize_with = "serialize_bigdecimal")]
+        unfilled_qty: BigDecimal,
+        ts: SystemTime,
+    },
+
+    /// quantity taken off an order so its owner does not trade with itself,
+    /// the order is gone once nothing remains
+    SelfTradePrevented {
+        order_id: Uuid,
+        owner: AccountId,
+        #[serde(serialize_with = "serialize_bigdecimal")]
+        qty: BigDecimal,
+        #[serde(serialize_with = "serialize_bigdecimal")]
+        remaining: BigDecimal,
+        ts: SystemTime,
+    },
+
+    /// partially filled resting order removed with an unmatchable remainder
+    DustCancelled {
+        order_id: Uuid,
+        side: OrderSide,
+        #[serde(serialize_with = "serialize_bigdecimal")]
+        qty: BigDecimal,
+        ts: SystemTime,
+    },
+}
+
+impl<Asset> Success<Asset> {
+    /// Order the event is about
+    pub fn order_id(&self) -> Uuid {
+        match self {
+            Success::Accepted { order_id, .. }
+            | Success::Filled { order_id, .. }
+            | Success::PartiallyFilled { order_id, .. }
+            | Success::Amended { order_id, .. }
+            | Success::Cancelled { order_id, .. }
+            | Success::StopAccepted { order_id, .. }
+            | Success::Triggered { order_id, .. }
+            | Success::PendingNew { order_id, .. }
+            | Success::Scheduled { order_id, .. }
+            | Success::Activated { order_id, .. }
+            | Success::Expired { order_id, .. }
+            | Success::CancelledRemainder { order_id, .. }
+            | Success::SelfTradePrevented { order_id, .. }
+            | Success::DustCancelled { order_id, .. } => *order_id,
+        }
+    }
+}
+
+/// Serialized with a stable numeric code, see `Failed::code`
+#[derive(Debug)]
+pub enum Failed {
+    ValidationFailed(String),
+    DuplicateOrderID(Uuid),
+    NoMatch(Uuid),
+    OrderNotFound(Uuid),
+    BasketRejected(Uuid),
+    DepthLimitExceeded(Uuid),
+    /// shed by an engine under load, never reached the book
+    Overloaded(Uuid),
+    /// refused by the pre-trade risk check, never reached the book
+    RiskRejected(Uuid),
+    /// cancel of an order that was already filled, cancelled or expired
+    TooLateToCancel(Uuid),
+    /// amendment of an order that was already filled, cancelled or expired
+    TooLateToAmend(Uuid),
+    /// amendment naming the side the order does not rest on
+    SideMismatch(Uuid),
+    /// amendment to a total quantity the order already filled
+    QtyBelowFilled(Uuid),
+    /// request could not be written to the journal, never reached the book
+    JournalFailed(Uuid),
+}
+
+/// Processing result numbered by its book, see `Orderbook::event_seq`
+#[derive(Debug)]
+pub struct SequencedEvent<Asset> {
+    pub seq: u64,
+    pub event: Result<Success<Asset>, Failed>,
+}
+
+impl<Asset> SequencedEvent<Asset> {
+    /// Number results of a single processing call, the last one `last_seq`
+    pub fn number(results: OrderProcessingResult<Asset>, last_seq: u64) -> Vec<Self> {
+        let first = last_seq + 1 - results.len() as u64;
+        results
+            .into_iter()
+            .zip(first..)
+            .map(|(event, seq)| SequencedEvent { seq, event })
+            .collect()
+    }
+}
+
+/// Status of an order with the quantity still open, see `Orderbook::reconcile`
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderState {
+    pub order_id: Uuid,
+    /// None for orders the book does not know or has forgotten
+    pub status: Option<OrderStatus>,
+    /// quantity still to fill, zero once the order completed or if unknown
+    pub remaining: BigDecimal,
+}
+
+/// Resting orders at one price, aggregated
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DepthLevel {
+    #[serde(serialize_with = "serialize_bigdecimal")]
+    pub price: BigDecimal,
+    #[serde(serialize_with = "serialize_bigdecimal")]
+    pub qty: BigDecimal,
+    pub orders: usize,
+}
+
+/// Best price levels of both sides, see `Orderbook::depth`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DepthSnapshot {
+    /// best first, highest price on top
+    pub bids: Vec<DepthLevel>,
+    /// best first, lowest price on top
+    pub asks: Vec<DepthLevel>,
+    /// `Orderbook::change_seq` at the time of the snapshot
+    pub seq: u64,
+}
+
+/// What to do when a new order takes a book side past its depth limit
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DepthLimitPolicy {
+    /// reject the rest of the new order
+    RejectNew,
+    /// cancel orders farthest from the touch until the side fits again,
+    /// rejecting the new order instead if it is the farthest itself
+    EvictFarthest,
+}
+
+/// What to do with a market order the opposite side cannot fill in full
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum MarketRemainderPolicy {
+    /// fill what is there and cancel the rest with `Success::CancelledRemainder`
+    #[default]
+    CancelRemainder,
+    /// refuse the whole order with `Failed::NoMatch` before any fill
+    RejectOrder,
+}
+
+/// How a new order meeting a resting order of its own owner is handled,
+/// both are reported with `Success::SelfTradePrevented`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SelfTradePrevention {
+    /// cancel the rest of the new order
+    CancelNewest,
+    /// cancel the resting order and go on matching
+    CancelOldest,
+    /// take the smaller quantity off both, cancelling the one used up
+    DecrementAndCancel,
+}
+
+/// Cap on resting orders of each book side
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DepthLimit {
+    pub max_levels: Option<usize>,
+    pub max_orders: Option<usize>,
+    pub policy: DepthLimitPolicy,
+}
+
+/// Remainders below either minimum are cancelled after partial fills
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DustThreshold {
+    pub min_qty: Option<BigDecimal>,
+    pub min_notional: Option<BigDecimal>,
+}
+
+impl DustThreshold {
+    fn is_dust(&self, price: &BigDecimal, qty: &BigDecimal) -> bool {
+        self.min_qty.as_ref().is_some_and(|min| qty < min)
+            || self
+                .min_notional
+                .as_ref()
+                .is_some_and(|min| &(price * qty) < min)
+    }
+}
+
+/// Quantities new orders and amends must have, unchecked when None
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct QtyLimits {
+    /// step quantities must be a multiple of
+    pub lot_size: Option<BigDecimal>,
+    pub min_qty: Option<BigDecimal>,
+    pub max_qty: Option<BigDecimal>,
+}
+
+impl QtyLimits {
+    /// Limits no quantity could be checked against, e.g. a lot size of zero
+    pub fn validate(&self) -> Result<(), &'static str> {
+        let positive = |limit: &Option<BigDecimal>| {
+            limit.as_ref().is_none_or(|limit| *limit > BigDecimal::zero())
+        };
+        if !positive(&self.lot_size) {
+            return Err("lot size not positive");
+        }
+        if !positive(&self.min_qty) || !positive(&self.max_qty) {
+            return Err("quantity limit not positive");
+        }
+        if let (Some(min), Some(max)) = (&self.min_qty, &self.max_qty) {
+            if min > max {
+                return Err("minimum quantity above maximum");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What happens to limit orders priced through the band
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum BandMode {
+    #[default]
+    Reject,
+    /// re-price aggressive orders to the band edge
+    Collar,
+}
+
+/// Where the reference price of the band comes from
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BandReference {
+    /// set once, then moved by every trade of the book
+    #[default]
+    LastTrade,
+    /// mark price set from outside, e.g. an index, trades leave it alone
+    Mark,
+}
+
+/// Limit on how far through the reference price new limit orders may go
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PriceBand {
+    /// fraction of the reference price either way, 0.05 for 5%
+    pub width: BigDecimal,
+    pub mode: BandMode,
+    #[serde(default)]
+    pub reference: BandReference,
+}
+
+/// Phase of a market's life deciding which requests its book takes, e.g.
+/// post-only while a listing builds its book and cancel-only while a
+/// delisting winds down. Session phases map onto it: pre-open is post-only,
+/// open is trading and closed is suspended, the names are accepted as
+/// aliases when read back.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TradingStatus {
+    /// every request
+    #[default]
+    #[serde(alias = "open")]
+    Trading,
+    /// limit orders that would rest without matching, amends and cancels
+    #[serde(alias = "pre_open")]
+    PostOnly,
+    /// cancels only
+    CancelOnly,
+    /// nothing at all
+    #[serde(alias = "closed")]
+    Suspended,
+    /// call auction: limit orders rest even when they cross, amends and
+    /// cancels; `Orderbook::uncross` ends it
+    Auction,
+}
+
+impl TradingStatus {
+    /// Reason the request may not enter a book in this status, `crosses`
+    /// telling whether a new limit order would match on arrival
+    pub fn permits<Asset>(
+        &self,
+        request: &OrderRequest<Asset>,
+        crosses: bool,
+    ) -> Result<(), &'static str>
+    where
+        Asset: Debug + Clone,
+    {
+        match (self, request) {
+            (TradingStatus::Trading, _) => Ok(()),
+            (TradingStatus::Suspended, _) => Err(ERR_MARKET_SUSPENDED),
+            (_, OrderRequest::CancelOrder { .. }) => Ok(()),
+            (TradingStatus::CancelOnly, _) => Err(ERR_CANCEL_ONLY),
+            (
+                TradingStatus::Auction,
+                OrderRequest::NewLimitOrder { .. } | OrderRequest::AmendOrder { .. },
+            ) => Ok(()),
+            (TradingStatus::Auction, _) => Err(ERR_IN_AUCTION),
+            (TradingStatus::PostOnly, OrderRequest::AmendOrder { .. }) => Ok(()),
+            (TradingStatus::PostOnly, OrderRequest::NewLimitOrder { .. }) if !crosses => Ok(()),
+            (TradingStatus::PostOnly, _) => Err(ERR_POST_ONLY),
+        }
+    }
+}
+
+impl DepthLimit {
+    fn exceeded<T>(&self, queue: &OrderQueue<T>) -> bool {
+        self.max_orders.is_some_and(|max| queue.len() > max)
+            || self.max_levels.is_some_and(|max| queue.level_count() > max)
+    }
+}
+
+/// Tick size and memory tuning of a book, trading memory for latency
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BookConfig {
+    /// orders preallocated on each side
+    pub bid_capacity: usize,
+    pub ask_capacity: usize,
+    /// factor the side containers grow by once full
+    pub growth_factor: f64,
+    /// cancellations tolerated before dangling queue indices are swept
+    pub max_stalled_indices: u64,
+    /// tick size, prices off it are rejected
+    pub price_scale: PriceScale,
+    /// finished orders remembered for late cancels and status queries
+    pub completed_capacity: usize,
+    /// allocation within a price level, price-time unless configured
+    #[serde(default)]
+    pub match_algorithm: MatchAlgorithm,
+}
+
+impl Default for BookConfig {
+    fn default() -> Self {
+        BookConfig {
+            bid_capacity: ORDER_QUEUE_INIT_CAPACITY,
+            ask_capacity: ORDER_QUEUE_INIT_CAPACITY,
+            growth_factor: DEFAULT_GROWTH_FACTOR,
+            max_stalled_indices: MAX_STALLED_INDICES_IN_QUEUE,
+            price_scale: PriceScale::default(),
+            completed_capacity: DEFAULT_COMPLETED_CAPACITY,
+            match_algorithm: MatchAlgorithm::default(),
+        }
+    }
+}
+
+/// Stop order waiting for its trigger
+#[derive(Serialize, Deserialize)]
+struct StopOrder<Asset>
+where
+    Asset: Debug + Clone,
+{
+    side: OrderSide,
+    trigger_price: BigDecimal,
+    /// market or limit order entering the book once triggered
+    order: OrderRequest<Asset>,
+}
+
+impl<Asset> StopOrder<Asset>
+where
+    Asset: Debug + Clone,
+{
+    fn is_triggered(&self, last_price: &BigDecimal) -> bool {
+        match self.side {
+            OrderSide::Bid => *last_price >= self.trigger_price,
+            OrderSide::Ask => *last_price <= self.trigger_price,
+        }
+    }
+}
+
+pub struct Orderbook<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    pub order_asset: Asset,
+    pub price_asset: Asset,
+    pub bid_queue: OrderQueue<Order<Asset>>,
+    pub ask_queue: OrderQueue<Order<Asset>>,
+    order_validator: OrderRequestValidator<Asset>,
+    liquidation_priority: bool,
+    match_policy: Box<dyn MatchPolicy<Asset> + Send>,
+    depth_limit: Option<DepthLimit>,
+    market_remainder: MarketRemainderPolicy,
+    self_trade_prevention: Option<SelfTradePrevention>,
+    dust_threshold: Option<DustThreshold>,
+    price_band: Option<PriceBand>,
+    reference_price: Option<BigDecimal>,
+    last_trade_price: Option<BigDecimal>,
+    trading_status: TradingStatus,
+    /// only cancels pass until resumed, whatever the trading status
+    halted: bool,
+    /// in arrival order
+    stops: Vec<StopOrder<Asset>>,
+    /// requests and sweeps that changed the book so far
+    change_seq: u64,
+    /// events of every processing call so far, successes and failures
+    event_seq: u64,
+    completed: CompletedOrders,
+    config: BookConfig,
+    clock: Box<dyn Clock + Send>,
+    stamper: EventStamper,
+    trade_ids: Box<dyn TradeIdGenerator + Send>,
+    /// last issued, consecutive IDs go on from it after a restore
+    last_trade_id: Option<TradeId>,
+    /// in registration order
+    listeners: Vec<Box<dyn OrderbookListener<Asset> + Send>>,
+    /// requests that passed validation, written before they are processed
+    journal: Option<Box<dyn Journal<Asset> + Send>>,
+}
+
+impl<Asset> Orderbook<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    /// Create new orderbook for pair of assets
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    //let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+    //let result = orderbook.process_order(OrderRequest::MarketOrder{  });
+    //assert_eq!(orderbook)
+    /// ```
+    // todo fix doc test!
+    pub fn new(order_asset: Asset, price_asset: Asset) -> Self {
+        Self::new_with_config(order_asset, price_asset, BookConfig::default())
+    }
+
+    /// Create new orderbook with custom capacities and queue maintenance
+    pub fn new_with_config(order_asset: Asset, price_asset: Asset, config: BookConfig) -> Self {
+        Orderbook {
+            order_asset,
+            price_asset,
+            bid_queue: OrderQueue::with_growth(
+                OrderSide::Bid,
+                config.max_stalled_indices,
+                config.bid_capacity,
+                config.growth_factor,
+            )
+            .with_price_scale(config.price_scale),
+            ask_queue: OrderQueue::with_growth(
+                OrderSide::Ask,
+                config.max_stalled_indices,
+                config.ask_capacity,
+                config.growth_factor,
+            )
+            .with_price_scale(config.price_scale),
+            order_validator: OrderRequestValidator::new(order_asset, price_asset)
+                .with_price_scale(config.price_scale),
+            liquidation_priority: false,
+            match_policy: config.match_algorithm.policy(),
+            depth_limit: None,
+            market_remainder: MarketRemainderPolicy::default(),
+            self_trade_prevention: None,
+            dust_threshold: None,
+            price_band: None,
+            reference_price: None,
+            last_trade_price: None,
+            trading_status: TradingStatus::default(),
+            halted: false,
+            stops: vec![],
+            change_seq: 0,
+            event_seq: 0,
+            completed: CompletedOrders::new(config.completed_capacity),
+            config,
+            clock: Box::new(SystemClock),
+            stamper: EventStamper::new(),
+            trade_ids: Box::new(SequentialTradeIds::new()),
+            last_trade_id: None,
+            listeners: vec![],
+            journal: None,
+        }
+    }
+
+    /// Source of trade IDs, consecutive numbers by default
+    pub fn set_trade_id_generator(&mut self, trade_ids: Box<dyn TradeIdGenerator + Send>) {
+        self.trade_ids = trade_ids;
+    }
+
+    /// Clock event timestamps are taken from, the wall clock by default
+    pub fn set_clock(&mut self, clock: Box<dyn Clock + Send>) {
+        self.clock = clock;
+    }
+
+    pub fn clock(&self) -> &dyn Clock {
+        self.clock.as_ref()
+    }
+
+    /// Call the listener back with the events of every request processed
+    /// from now on, after the listeners added before it
+    pub fn add_listener<L>(&mut self, listener: L)
+    where
+        L: OrderbookListener<Asset> + Send + 'static,
+    {
+        self.listeners.push(Box::new(listener));
+    }
+
+    /// Append every request passing validation to the journal before it is
+    /// processed, amends of `process_amends` included, so `replay` can rebuild
+    /// the book after a crash. Requests the journal fails to take are rejected
+    /// unprocessed. Books of an engine are journaled by the engine instead, see
+    /// `MatchingEngine::set_journal`.
+    pub fn set_journal<J>(&mut self, journal: J)
+    where
+        J: Journal<Asset> + Send + 'static,
+    {
+        self.journal = Some(Box::new(journal));
+    }
+
+    /// Rebuild the book from the requests of its journal, returning how many
+    /// were processed.
+    ///
+    /// Meant for a fresh book with the settings of the journaled one. Every
+    /// request is processed at its own timestamp, cancels at the one before,
+    /// so the rebuilt book holds the same orders in the same queue priority,
+    /// with the same trade IDs and change sequence. Sweeps driven by time
+    /// alone and rejected requests are not journaled, event numbers may
+    /// differ by the latter. Nothing is journaled again and the clock is
+    /// restored afterwards.
+    pub fn replay<I>(&mut self, journal: I) -> usize
+    where
+        I: IntoIterator<Item = OrderRequest<Asset>>,
+    {
+        let manual = Arc::new(ManualClock::new(self.clock.now()));
+        let clock = std::mem::replace(&mut self.clock, Box::new(manual.clone()));
+        let writer = self.journal.take();
+
+        let mut last_ts: Option<SystemTime> = None;
+        let mut replayed = 0;
+        for request in journal {
+            if let Some(ts) = request.ts() {
+                if last_ts.is_none_or(|last| ts > last) {
+                    last_ts = Some(ts);
+                    manual.set(ts);
+                }
+            }
+            self.process_order(request);
+            replayed += 1;
+        }
+
+        self.clock = clock;
+        self.journal = writer;
+        replayed
+    }
+
+    pub fn config(&self) -> BookConfig {
+        self.config
+    }
+
+    /// Change the tick size of the live book.
+    ///
+    /// Refused, returning false, while any resting order is off the new tick.
+    pub fn set_price_scale(&mut self, price_scale: PriceScale) -> bool {
+        if !self.bid_queue.fits_price_scale(price_scale)
+            || !self.ask_queue.fits_price_scale(price_scale)
+        {
+            return false;
+        }
+        self.bid_queue.set_price_scale(price_scale);
+        self.ask_queue.set_price_scale(price_scale);
+        self.order_validator.set_price_scale(price_scale);
+        self.config.price_scale = price_scale;
+        true
+    }
+
+    /// Choose how incoming quantity is allocated within a price level.
+    ///
+    /// Replaces the policy of the book config, which snapshots restore.
+    pub fn set_match_policy(&mut self, policy: Box<dyn MatchPolicy<Asset> + Send>) {
+        self.match_policy = policy;
+    }
+
+    /// Limit depth of each book side, none by default
+    pub fn set_depth_limit(&mut self, limit: Option<DepthLimit>) {
+        self.depth_limit = limit;
+    }
+
+    pub fn depth_limit(&self) -> Option<DepthLimit> {
+        self.depth_limit
+    }
+
+    /// Handling of market orders larger than the opposite side, remainders
+    /// are cancelled by default
+    pub fn set_market_remainder_policy(&mut self, policy: MarketRemainderPolicy) {
+        self.market_remainder = policy;
+    }
+
+    pub fn market_remainder_policy(&self) -> MarketRemainderPolicy {
+        self.market_remainder
+    }
+
+    /// Keep orders of the same owner from trading with each other, off by
+    /// default. Orders without an owner are never affected.
+    pub fn set_self_trade_prevention(&mut self, mode: Option<SelfTradePrevention>) {
+        self.self_trade_prevention = mode;
+    }
+
+    pub fn self_trade_prevention(&self) -> Option<SelfTradePrevention> {
+        self.self_trade_prevention
+    }
+
+    /// Cancel tiny remainders of partially filled resting orders, off by default
+    pub fn set_dust_threshold(&mut self, threshold: Option<DustThreshold>) {
+        self.dust_threshold = threshold;
+    }
+
+    pub fn dust_threshold(&self) -> Option<&DustThreshold> {
+        self.dust_threshold.as_ref()
+    }
+
+    /// Reject orders off the lot size or outside the quantity range, off by
+    /// default. Limits that are not positive are refused.
+    pub fn set_qty_limits(&mut self, limits: Option<QtyLimits>) -> Result<(), &'static str> {
+        if let Some(limits) = &limits {
+            limits.validate()?;
+        }
+        self.order_validator.set_qty_limits(limits);
+        Ok(())
+    }
+
+    pub fn qty_limits(&self) -> Option<&QtyLimits> {
+        self.order_validator.qty_limits()
+    }
+
+    /// Keep new limit orders within the band around the reference price, off
+    /// by default
+    pub fn set_price_band(&mut self, band: Option<PriceBand>) {
+        self.price_band = band;
+    }
+
+    pub fn price_band(&self) -> Option<&PriceBand> {
+        self.price_band.as_ref()
+    }
+
+    /// Requests the book takes from now on, resting orders are left alone
+    pub fn set_trading_status(&mut self, status: TradingStatus) {
+        self.trading_status = status;
+    }
+
+    pub fn trading_status(&self) -> TradingStatus {
+        self.trading_status
+    }
+
+    /// Stops everything but cancels until `resume`, keeping the trading
+    /// status to return to. False if already halted.
+    pub fn halt(&mut self) -> bool {
+        !std::mem::replace(&mut self.halted, true)
+    }
+
+    /// Lifts a halt, the book takes what its trading status permits again.
+    /// False if not halted.
+    pub fn resume(&mut self) -> bool {
+        std::mem::replace(&mut self.halted, false)
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Shorthand for `set_trading_status(TradingStatus::CancelOnly)`
+    pub fn set_cancel_only(&mut self) {
+        self.set_trading_status(TradingStatus::CancelOnly);
+    }
+
+    /// Price and volume the book would uncross at, none unless resting
+    /// orders cross as they may during an auction call
+    pub fn indicative_price(&self) -> Option<IndicativeUncross> {
+        auction::indicative_uncross(
+            self.bid_queue.iter().map(|order| (&order.price, &order.qty)),
+            self.ask_queue.iter().map(|order| (&order.price, &order.qty)),
+            self.reference_price.as_ref(),
+        )
+    }
+
+    /// End an auction call: crossing orders trade in queue priority at the
+    /// indicative price and the book moves on to continuous trading.
+    ///
+    /// Both sides of an auction trade are makers, the aggressor reported is
+    /// the side with surplus volume, bids when balanced. Self-trade
+    /// prevention does not apply. Does nothing unless the book is in an
+    /// auction call and not halted.
+    pub fn uncross(&mut self) -> OrderProcessingResult<Asset> {
+        let mut results: OrderProcessingResult<Asset> = vec![];
+        if self.trading_status != TradingStatus::Auction || self.halted {
+            return results;
+        }
+        self.stamper.start(self.clock.now());
+
+        if let Some(indicative) = self.indicative_price() {
+            let price = indicative.price;
+            let aggressor = indicative.surplus_side.unwrap_or(OrderSide::Bid);
+            loop {
+                let (bid, ask) = match (self.bid_queue.peek(), self.ask_queue.peek()) {
+                    (Some(bid), Some(ask)) if bid.price >= price && ask.price <= price => {
+                        (bid.clone(), ask.clone())
+                    }
+                    _ => break,
+                };
+                let qty = bid.qty.clone().min(ask.qty.clone());
+                let trade_id = self.trade_ids.next_id();
+                self.last_trade_id = Some(trade_id);
+                self.auction_fill(&mut results, &bid, &ask, trade_id, &price, &qty, aggressor);
+                self.auction_fill(&mut results, &ask, &bid, trade_id, &price, &qty, aggressor);
+            }
+
+            let marked = self
+                .price_band
+                .as_ref()
+                .is_some_and(|band| band.reference == BandReference::Mark);
+            if !marked {
+                self.reference_price = Some(price.clone());
+            }
+            self.last_trade_price = Some(price);
+        }
+
+        self.trading_status = TradingStatus::Trading;
+        self.trigger_stops(&mut results);
+        self.record_change(&results);
+        results
+    }
+
+    /// Reason the request may not enter the book while halted or in its
+    /// trading status
+    fn permits(&self, request: &OrderRequest<Asset>, crosses: bool) -> Result<(), &'static str> {
+        if self.halted && !matches!(request, OrderRequest::CancelOrder { .. }) {
+            return Err(ERR_MARKET_HALTED);
+        }
+        self.trading_status.permits(request, crosses)
+    }
+
+    /// Centre of the price band, follows the last trade price once trading
+    /// unless the band is centred on a mark price
+    pub fn set_reference_price(&mut self, price: Option<BigDecimal>) {
+        self.reference_price = price;
+    }
+
+    pub fn reference_price(&self) -> Option<&BigDecimal> {
+        self.reference_price.as_ref()
+    }
+
+    /// Price of the last trade, stop orders trigger on it
+    pub fn last_trade_price(&self) -> Option<&BigDecimal> {
+        self.last_trade_price.as_ref()
+    }
+
+    /// Number of stop orders waiting for their trigger
+    pub fn stop_count(&self) -> usize {
+        self.stops.len()
+    }
+
+    pub fn has_stop(&self, order_id: Uuid) -> bool {
+        self.stops.iter().any(|stop| stop.order.order_id() == order_id)
+    }
+
+    /// Status of a live order or one that completed recently, None for
+    /// orders the book does not know or has forgotten
+    pub fn order_status(&self, order_id: Uuid) -> Option<OrderStatus> {
+        if self.bid_queue.get(order_id).is_some() || self.ask_queue.get(order_id).is_some() {
+            Some(OrderStatus::Resting)
+        } else if self.has_stop(order_id) {
+            Some(OrderStatus::Waiting)
+        } else {
+            self.completed(order_id).map(|completed| completed.status)
+        }
+    }
+
+    /// Status and open quantity of each order, in the order asked.
+    ///
+    /// Meant for gateways resynchronizing client sessions after a
+    /// reconnect, a single call answers for all orders of the session.
+    pub fn reconcile(&self, ids: &[Uuid]) -> Vec<OrderState> {
+        ids.iter()
+            .map(|order_id| {
+                let resting = self
+                    .bid_queue
+                    .get(*order_id)
+                    .or_else(|| self.ask_queue.get(*order_id))
+                    .map(|order| order.qty.clone());
+                let waiting = || {
+                    self.stops
+                        .iter()
+                        .find(|stop| stop.order.order_id() == *order_id)
+                        .and_then(|stop| stop.order.qty().cloned())
+                };
+                let (status, remaining) = match resting {
+                    Some(qty) => (Some(OrderStatus::Resting), qty),
+                    None => match waiting() {
+                        Some(qty) => (Some(OrderStatus::Waiting), qty),
+                        None => (
+                            self.completed(*order_id).map(|completed| completed.status),
+                            BigDecimal::zero(),
+                        ),
+                    },
+                };
+                OrderState {
+                    order_id: *order_id,
+                    status,
+                    remaining,
+                }
+            })
+            .collect()
+    }
+
+    /// Final state of a recently filled, cancelled or expired order
+    pub fn completed(&self, order_id: Uuid) -> Option<&CompletedOrder> {
+        self.completed.get(order_id)
+    }
+
+    /// Resting bids in price-time priority, best first
+    pub fn bids(&self) -> impl Iterator<Item = &Order<Asset>> {
+        self.bid_queue.ranked().into_iter().map(|(order, _, _)| order)
+    }
+
+    /// Resting asks in price-time priority, best first
+    pub fn asks(&self) -> impl Iterator<Item = &Order<Asset>> {
+        self.ask_queue.ranked().into_iter().map(|(order, _, _)| order)
+    }
+
+    /// Up to `levels` best price levels of each side, leaving the book as is
+    pub fn depth(&self, levels: usize) -> DepthSnapshot {
+        DepthSnapshot {
+            bids: aggregate_levels(&self.bid_queue)
+                .into_iter()
+                .rev()
+                .take(levels)
+                .collect(),
+            asks: aggregate_levels(&self.ask_queue)
+                .into_iter()
+                .take(levels)
+                .collect(),
+            seq: self.change_seq,
+        }
+    }
+
+    /// Sequence number of the last change to the book, bumped by every
+    /// request or sweep that produced events. Books with an unchanged
+    /// sequence need no new snapshot.
+    pub fn change_seq(&self) -> u64 {
+        self.change_seq
+    }
+
+    /// Number of the last event the book emitted.
+    ///
+    /// Every success and failure out of the processing calls counts, so
+    /// events are numbered from 1 in the order they were returned and a
+    /// consumer missing a number knows it lost an event, which timestamps
+    /// cannot tell.
+    pub fn event_seq(&self) -> u64 {
+        self.event_seq
+    }
+
+    /// Let resting liquidation orders go ahead of regular orders at the same price
+    pub fn set_liquidation_priority(&mut self, enabled: bool) {
+        self.liquidation_priority = enabled;
+    }
+
+    pub fn liquidation_priority(&self) -> bool {
+        self.liquidation_priority
+    }
+
+    /// Process the request, then release stop orders its trades triggered
+    pub fn process_order(&mut self, order: OrderRequest<Asset>) -> OrderProcessingResult<Asset> {
+        // processing result accumulator
+        let mut proc_result: OrderProcessingResult<Asset> = vec![];
+        // single clock reading for all events of the request
+        self.stamper.start(self.clock.now());
+
+        if self.journal_request(&order) {
+            self.process_request(&mut proc_result, order);
+            self.trigger_stops(&mut proc_result);
+        } else {
+            proc_result.push(Err(Failed::JournalFailed(order.order_id())));
+        }
+        self.record_change(&proc_result);
+
+        // return collected processing results
+        proc_result
+    }
+
+    /// Process the request like `process_order`, numbering its events
+    pub fn process_order_sequenced(
+        &mut self,
+        order: OrderRequest<Asset>,
+    ) -> Vec<SequencedEvent<Asset>> {
+        let results = self.process_order(order);
+        SequencedEvent::number(results, self.event_seq)
+    }
+
+    /// Amend many resting orders at once, e.g. a market maker refreshing quotes.
+    ///
+    /// Cheaper than amending one by one: the clock is read once, every amend
+    /// is validated in a single pass and the price index of each side is
+    /// rebuilt once after the batch instead of after every amend, so the top
+    /// of book moves only when the batch completes. Events follow the order
+    /// of the amends, invalid or unknown ones fail alone.
+    pub fn process_amends(&mut self, amends: Vec<AmendOrder>) -> OrderProcessingResult<Asset> {
+        let mut proc_result: OrderProcessingResult<Asset> = vec![];
+        self.stamper.start(self.clock.now());
+
+        let verdicts: Vec<Option<String>> = amends
+            .iter()
+            .map(|amend| {
+                self.permits(&OrderRequest::<Asset>::from(amend.clone()), false)
+                    .and(self.order_validator.validate_amend(
+                        amend.id,
+                        amend.price.as_ref(),
+                        amend.qty.as_ref(),
+                    ))
+                    .err()
+                    .map(String::from)
+            })
+            .collect();
+
+        let (mut bid_moves, mut ask_moves) = (vec![], vec![]);
+        for (amend, verdict) in amends.into_iter().zip(verdicts) {
+            if let Some(reason) = verdict {
+                proc_result.push(Err(Failed::ValidationFailed(reason)));
+                continue;
+            }
+            if !self.journal_request(&OrderRequest::from(amend.clone())) {
+                proc_result.push(Err(Failed::JournalFailed(amend.id)));
+                continue;
+            }
+            let moved = self.apply_amend(
+                &mut proc_result,
+                amend.id,
+                amend.side,
+                amend.price,
+                amend.qty,
+                amend.ts,
+            );
+            match (moved, amend.side) {
+                (Some(moved), OrderSide::Bid) => bid_moves.push(moved),
+                (Some(moved), OrderSide::Ask) => ask_moves.push(moved),
+                (None, _) => (),
+            }
+        }
+
+        self.bid_queue.reindex(bid_moves);
+        self.ask_queue.reindex(ask_moves);
+        self.record_change(&proc_result);
+        proc_result
+    }
+
+    /// Bring the book up to date with an event of another book, without
+    /// matching anything.
+    ///
+    /// Meant for read replicas mirroring a matcher from its events alone:
+    /// accepted limit orders are inserted, fills, amends and the various
+    /// cancellations applied to the orders they name, stops parked and
+    /// released. Events of orders the book does not hold are ignored, e.g.
+    /// fills of market orders. Orders of the replica carry no owner and rest
+    /// good till cancelled, their queue times are those of the events, so
+    /// levels keep the order of the matcher unless requests reached it with
+    /// timestamps out of order.
+    pub fn apply_event(&mut self, event: &Success<Asset>) {
+        match event {
+            Success::Accepted {
+                order_id,
+                order_asset,
+                price_asset,
+                price: Some(price),
+                qty,
+                side,
+                ts,
+                liquidation,
+                ..
+            } => {
+                let priority = *liquidation && self.liquidation_priority;
+                let order = Order {
+                    order_id: *order_id,
+                    order_asset: *order_asset,
+                    price_asset: *price_asset,
+                    side: *side,
+                    price: price.clone(),
+                    qty: qty.clone(),
+                    filled: BigDecimal::zero(),
+                    liquidation: *liquidation,
+                    time_in_force: TimeInForce::GTC,
+                    owner: None,
+                };
+                let order_queue = match side {
+                    OrderSide::Bid => &mut self.bid_queue,
+                    OrderSide::Ask => &mut self.ask_queue,
+                };
+                order_queue.insert_with_priority(*order_id, price.clone(), *ts, priority, order);
+            }
+            Success::Filled { order_id, trade_id, price, .. } => {
+                self.remove_resting(*order_id);
+                self.last_trade_id = Some(*trade_id);
+                self.last_trade_price = Some(price.clone());
+            }
+            Success::PartiallyFilled { order_id, trade_id, price, qty, .. } => {
+                self.fill_resting(*order_id, qty);
+                self.last_trade_id = Some(*trade_id);
+                self.last_trade_price = Some(price.clone());
+            }
+            Success::Amended { order_id, price, qty, priority_kept, ts, .. } => {
+                let resting = match self.resting(*order_id) {
+                    Some(resting) => resting.clone(),
+                    None => return,
+                };
+                let amended = Order {
+                    price: price.clone(),
+                    qty: qty.clone(),
+                    ..resting
+                };
+                let order_queue = match resting.side {
+                    OrderSide::Bid => &mut self.bid_queue,
+                    OrderSide::Ask => &mut self.ask_queue,
+                };
+                if *priority_kept {
+                    order_queue.update(*order_id, amended);
+                } else {
+                    order_queue.amend(*order_id, price.clone(), *ts, amended);
+                }
+            }
+            Success::StopAccepted {
+                order_id,
+                order_asset,
+                price_asset,
+                side,
+                trigger_price,
+                price,
+                qty,
+                ts,
+                liquidation,
+            } => {
+                let order = match price {
+                    Some(price) => OrderRequest::NewLimitOrder {
+                        order_id: *order_id,
+                        order_asset: *order_asset,
+                        price_asset: *price_asset,
+                        side: *side,
+                        price: price.clone(),
+                        qty: qty.clone(),
+                        ts: *ts,
+                        liquidation: *liquidation,
+                        time_in_force: TimeInForce::GTC,
+                        owner: None,
+                    },
+                    None => OrderRequest::NewMarketOrder {
+                        order_id: *order_id,
+                        order_asset: *order_asset,
+                        price_asset: *price_asset,
+                        side: *side,
+                        qty: qty.clone(),
+                        ts: *ts,
+                        liquidation: *liquidation,
+                        protection: None,
+                        owner: None,
+                    },
+                };
+                self.stops.push(StopOrder {
+                    side: *side,
+                    trigger_price: trigger_price.clone(),
+                    order,
+                });
+            }
+            Success::SelfTradePrevented { order_id, remaining, .. } => {
+                self.set_resting_qty(*order_id, remaining.clone());
+            }
+            Success::Triggered { order_id, .. }
+            | Success::Cancelled { order_id, .. }
+            | Success::Expired { order_id, .. }
+            | Success::CancelledRemainder { order_id, .. }
+            | Success::DustCancelled { order_id, .. } => {
+                self.stops.retain(|stop| stop.order.order_id() != *order_id);
+                self.remove_resting(*order_id);
+            }
+            Success::Accepted { price: None, .. }
+            | Success::PendingNew { .. }
+            | Success::Scheduled { .. }
+            | Success::Activated { .. } => (),
+        }
+    }
+
+    /// Apply the results of one request of another book, see `apply_event`.
+    ///
+    /// Also drops orders the matcher accepted and then refused over its
+    /// depth limit, the only failures taking something off a book, and
+    /// counts the request like `process_order` does: change and event
+    /// sequences, order statuses and listeners follow the matcher.
+    pub fn apply_results(&mut self, results: &OrderProcessingResult<Asset>) {
+        for result in results {
+            match result {
+                Ok(event) => self.apply_event(event),
+                Err(Failed::DepthLimitExceeded(order_id)) => self.remove_resting(*order_id),
+                Err(_) => (),
+            }
+        }
+        self.record_change(results);
+    }
+
+    /// Check request against the book rules and its trading status without
+    /// processing it
+    pub fn validate(&self, order: &OrderRequest<Asset>) -> Result<(), &str> {
+        let crosses = match order {
+            OrderRequest::NewLimitOrder { side, price, .. } => match side {
+                OrderSide::Bid => self.best_ask().is_some_and(|ask| price >= ask),
+                OrderSide::Ask => self.best_bid().is_some_and(|bid| price <= bid),
+            },
+            _ => false,
+        };
+        self.permits(order, crosses)?;
+        self.order_validator.validate(order)
+    }
+
+    /// Get current spread as a tuple: (bid, ask)
+    pub fn current_spread(&self) -> Option<(BigDecimal, BigDecimal)> {
+        Some((self.best_bid()?.clone(), self.best_ask()?.clone()))
+    }
+
+    pub fn best_bid(&self) -> Option<&BigDecimal> {
+        self.bid_queue.peek().map(|order| &order.price)
+    }
+
+    pub fn best_ask(&self) -> Option<&BigDecimal> {
+        self.ask_queue.peek().map(|order| &order.price)
+    }
+
+    /// Halfway between the best bid and ask, None unless both sides quote
+    pub fn mid_price(&self) -> Option<BigDecimal> {
+        Some((self.best_bid()? + self.best_ask()?) / BigDecimal::from(2))
+    }
+
+    /// Remove all resting DAY orders, e.g. at session close.
+    ///
+    /// GTC orders stay in the book for the next session.
+    pub fn expire_day_orders(&mut self, ts: SystemTime) -> OrderProcessingResult<Asset> {
+        let mut proc_result: OrderProcessingResult<Asset> = vec![];
+
+        for order_queue in [&mut self.bid_queue, &mut self.ask_queue] {
+            let mut expiring: Vec<Uuid> = order_queue
+                .iter()
+                .filter(|order| order.time_in_force == TimeInForce::Day)
+                .map(|order| order.order_id)
+                .collect();
+            // queues do not iterate in a stable order, events must
+            expiring.sort();
+
+            for order_id in expiring {
+                order_queue.cancel(order_id);
+                proc_result.push(Ok(Success::Expired { order_id, ts }));
+            }
+        }
+
+        self.record_change(&proc_result);
+        proc_result
+    }
+
+    /// Remove resting GTD orders whose expiry is at or before `ts`
+    pub fn expire_orders(&mut self, ts: SystemTime) -> OrderProcessingResult<Asset> {
+        let mut proc_result: OrderProcessingResult<Asset> = vec![];
+
+        for order_queue in [&mut self.bid_queue, &mut self.ask_queue] {
+            let mut expiring: Vec<Uuid> = order_queue
+                .iter()
+                .filter(|order| match order.time_in_force {
+                    TimeInForce::GTD(expiry) => expiry <= ts,
+                    _ => false,
+                })
+                .map(|order| order.order_id)
+                .collect();
+            expiring.sort();
+
+            for order_id in expiring {
+                order_queue.cancel(order_id);
+                proc_result.push(Ok(Success::Expired { order_id, ts }));
+            }
+        }
+
+        self.record_change(&proc_result);
+        proc_result
+    }
+
+    /// Cancel every resting order matching `filter`, reporting each one
+    pub fn cancel_resting<F>(&mut self, filter: F) -> OrderProcessingResult<Asset>
+    where
+        F: Fn(&Order<Asset>) -> bool,
+    {
+        let mut proc_result: OrderProcessingResult<Asset> = vec![];
+        self.stamper.start(self.clock.now());
+
+        for order_queue in [&mut self.bid_queue, &mut self.ask_queue] {
+            let mut cancelled: Vec<Uuid> = order_queue
+                .iter()
+                .filter(|order| filter(order))
+                .map(|order| order.order_id)
+                .collect();
+            cancelled.sort();
+
+            for order_id in cancelled {
+                order_queue.cancel(order_id);
+                proc_result.push(Ok(Success::Cancelled {
+                    order_id,
+                    ts: self.stamper.stamp(),
+                }));
+            }
+        }
+
+        self.record_change(&proc_result);
+        proc_result
+    }
+
+    /// Periodic housekeeping of both sides, see `OrderQueue::maintain`.
+    ///
+    /// Returns the number of dangling queue indices dropped.
+    pub fn maintain(&mut self) -> usize {
+        self.bid_queue.maintain() + self.ask_queue.maintain()
+    }
+
+    /* Processing logic */
+
+    fn process_request(
+        &mut self,
+        proc_result: &mut OrderProcessingResult<Asset>,
+        order: OrderRequest<Asset>,
+    ) {
+        // validate request
+        if let Err(reason) = self.validate(&order) {
+            proc_result.push(Err(Failed::ValidationFailed(String::from(reason))));
+            return;
+        }
+
+        match order {
+            OrderRequest::NewMarketOrder {
+                order_id,
+                order_asset,
+                price_asset,
+                side,
+                qty,
+                ts: _ts,
+                liquidation,
+                protection,
+                owner,
+            } => {
+                // the protection limit is fixed against the touch on arrival
+                let touch = match side {
+                    OrderSide::Bid => self.ask_queue.peek(),
+                    OrderSide::Ask => self.bid_queue.peek(),
+                }
+                .map(|order| order.price.clone());
+                let limit = protection
+                    .zip(touch)
+                    .map(|(protection, touch)| protection.limit(side, &touch));
+
+                if self.market_remainder == MarketRemainderPolicy::RejectOrder
+                    && !self.fillable(side, limit.as_ref(), &qty, owner)
+                {
+                    proc_result.push(Err(Failed::NoMatch(order_id)));
+                    return;
+                }
+
+                proc_result.push(Ok(Success::Accepted {
+                    order_id,
+                    order_asset,
+                    price_asset,
+                    price: None,
+                    collared_from: None,
+                    order_type: OrderType::Market,
+                    qty: qty.clone(),
+                    side,
+                    ts: self.stamper.stamp(),
+                    liquidation,
+                }));
+
+                self.process_market_order(
+                    proc_result,
+                    order_id,
+                    order_asset,
+                    price_asset,
+                    side,
+                    qty,
+                    liquidation,
+                    limit.as_ref(),
+                    owner,
+                );
+            }
+
+            OrderRequest::NewLimitOrder {
+                order_id,
+                order_asset,
+                price_asset,
+                side,
+                price,
+                qty,
+                ts,
+                liquidation,
+                time_in_force,
+                owner,
+            } => {
+                let (price, collared_from) = match self.band_limit(side, price) {
+                    Ok(banded) => banded,
+                    Err(reason) => {
+                        proc_result.push(Err(Failed::ValidationFailed(String::from(reason))));
+                        return;
+                    }
+                };
+
+                if time_in_force == TimeInForce::FOK
+                    && !self.fillable(side, Some(&price), &qty, owner)
+                {
+                    proc_result.push(Err(Failed::NoMatch(order_id)));
+                    return;
+                }
+
+                proc_result.push(Ok(Success::Accepted {
+                    order_id,
+                    order_asset,
+                    price_asset,
+                    price: Some(price.clone()),
+                    collared_from,
+                    order_type: OrderType::Limit,
+                    side,
+                    qty: qty.clone(),
+                    ts: self.stamper.stamp(),
+                    liquidation,
+                }));
+
+                if self.trading_status == TradingStatus::Auction {
+                    // orders accumulate until the uncross
+                    self.store_new_limit_order(
+                        proc_result,
+                        order_id,
+                        order_asset,
+                        price_asset,
+                        side,
+                        price,
+                        qty,
+                        ts,
+                        liquidation,
+                        time_in_force,
+                        owner,
+                    );
+                    return;
+                }
+
+                self.process_limit_order(
+                    proc_result,
+                    order_id,
+                    order_asset,
+                    price_asset,
+                    side,
+                    price,
+                    qty,
+                    ts,
+                    liquidation,
+                    time_in_force,
+                    owner,
+                );
+            }
+
+            stop @ (OrderRequest::NewStopOrder { .. } | OrderRequest::NewStopLimitOrder { .. }) => {
+                self.process_stop_order(proc_result, stop);
+            }
+
+            OrderRequest::AmendOrder {
+                id,
+                side,
+                price,
+                qty,
+                ts,
+            } => {
+                self.process_order_amend(proc_result, id, side, price, qty, ts);
+            }
+
+            OrderRequest::CancelOrder { id, side } => {
+                self.process_order_cancel(proc_result, id, side);
+            }
+        }
+    }
+
+    fn process_stop_order(
+        &mut self,
+        results: &mut OrderProcessingResult<Asset>,
+        stop: OrderRequest<Asset>,
+    ) {
+        let order_id = stop.order_id();
+        let known = self.has_stop(order_id)
+            || self.bid_queue.get(order_id).is_some()
+            || self.ask_queue.get(order_id).is_some();
+        if known {
+            results.push(Err(Failed::DuplicateOrderID(order_id)));
+            return;
+        }
+
+        let (order_asset, price_asset, side, trigger_price, price, qty, liquidation) = match &stop {
+            OrderRequest::NewStopOrder {
+                order_asset,
+                price_asset,
+                side,
+                trigger_price,
+                qty,
+                liquidation,
+                ..
+            } => (order_asset, price_asset, side, trigger_price, None, qty, liquidation),
+            OrderRequest::NewStopLimitOrder {
+                order_asset,
+                price_asset,
+                side,
+                trigger_price,
+                price,
+                qty,
+                liquidation,
+                ..
+            } => (order_asset, price_asset, side, trigger_price, Some(price), qty, liquidation),
+            _ => return,
+        };
+        results.push(Ok(Success::StopAccepted {
+            order_id,
+            order_asset: *order_asset,
+            price_asset: *price_asset,
+            side: *side,
+            trigger_price: trigger_price.clone(),
+            price: price.cloned(),
+            qty: qty.clone(),
+            ts: self.stamper.stamp(),
+            liquidation: *liquidation,
+        }));
+
+        let stop = StopOrder {
+            side: *side,
+            trigger_price: trigger_price.clone(),
+            order: stop.triggered().unwrap(),
+        };
+        self.stops.push(stop);
+    }
+
+    fn process_market_order(
+        &mut self,
+        results: &mut OrderProcessingResult<Asset>,
+        order_id: Uuid,
+        order_asset: Asset,
+        price_asset: Asset,
+        side: OrderSide,
+        qty: BigDecimal,
+        liquidation: bool,
+        limit: Option<&BigDecimal>,
+        owner: Option<AccountId>,
+    ) {
+        // one opposite price level at a time
+        let mut qty = qty;
+        loop {
+            // best opposite price, none past the protection limit
+            let opposite_price = match side {
+                OrderSide::Bid => self.ask_queue.peek(),
+                OrderSide::Ask => self.bid_queue.peek(),
+            }
+            .map(|order| &order.price)
+            .filter(|price| within_limit(side, price, limit));
+
+            if opposite_price.is_none() {
+                break;
+            }
+            qty = match self.level_matching(
+                results,
+                order_id,
+                order_asset,
+                price_asset,
+                OrderType::Market,
+                side,
+                qty,
+                liquidation,
+                owner,
+            ) {
+                Ok(remaining) => remaining,
+                Err(unmatched) => {
+                    qty = unmatched;
+                    break;
+                }
+            };
+            if qty.is_zero() {
+                return;
+            }
+        }
+
+        if results.iter().any(|result| is_fill_of(result, order_id)) {
+            // opposite side exhausted or protection reached part way
+            results.push(Ok(Success::CancelledRemainder {
+                order_id,
+                unfilled_qty: qty,
+                ts: self.stamper.stamp(),
+            }));
+        } else {
+            // no limit orders found
+            results.push(Err(Failed::NoMatch(order_id)));
+        }
+    }
+
+    fn process_limit_order(
+        &mut self,
+        results: &mut OrderProcessingResult<Asset>,
+        order_id: Uuid,
+        order_asset: Asset,
+        price_asset: Asset,
+        side: OrderSide,
+        price: BigDecimal,
+        qty: BigDecimal,
+        ts: SystemTime,
+        liquidation: bool,
+        time_in_force: TimeInForce,
+        owner: Option<AccountId>,
+    ) {
+        // match immediately one opposite price level at a time
+        let mut qty = qty;
+        loop {
+            let could_be_matched = match side {
+                // verify bid/ask price overlap
+                OrderSide::Bid => self.ask_queue.peek().is_some_and(|ask| price >= ask.price),
+                OrderSide::Ask => self.bid_queue.peek().is_some_and(|bid| price <= bid.price),
+            };
+
+            if !could_be_matched {
+                break;
+            }
+            qty = match self.level_matching(
+                results,
+                order_id,
+                order_asset,
+                price_asset,
+                OrderType::Limit,
+                side,
+                qty,
+                liquidation,
+                owner,
+            ) {
+                Ok(remaining) => remaining,
+                Err(_) => {
+                    // the rest would cross the level it cannot trade with
+                    results.push(Ok(Success::Cancelled {
+                        order_id,
+                        ts: self.stamper.stamp(),
+                    }));
+                    return;
+                }
+            };
+            if qty.is_zero() {
+                return;
+            }
+        }
+
+        // just insert the rest of new order in queue
+        self.store_new_limit_order(
+            results,
+            order_id,
+            order_asset,
+            price_asset,
+            side,
+            price,
+            qty,
+            ts,
+            liquidation,
+            time_in_force,
+            owner,
+        );
+    }
+
+    /// Amend resting order, unchanged fields are taken from it.
+    ///
+    /// Reducing quantity at the same price keeps queue priority,
+    /// any other change moves the order to the back of its new level.
+    fn process_order_amend(
+        &mut self,
+        results: &mut OrderProcessingResult<Asset>,
+        order_id: Uuid,
+        side: OrderSide,
+        price: Option<BigDecimal>,
+        qty: Option<BigDecimal>,
+        ts: SystemTime,
+    ) {
+        if let Some(moved) = self.apply_amend(results, order_id, side, price, qty, ts) {
+            match side {
+                OrderSide::Bid => self.bid_queue.reindex(vec![moved]),
+                OrderSide::Ask => self.ask_queue.reindex(vec![moved]),
+            };
+        }
+    }
+
+    fn process_order_cancel(
+        &mut self,
+        results: &mut OrderProcessingResult<Asset>,
+        order_id: Uuid,
+        side: Option<OrderSide>,
+    ) {
+        let side = side.unwrap_or_else(|| self.side_of(order_id));
+        let order_queue = match side {
+            OrderSide::Bid => &mut self.bid_queue,
+            OrderSide::Ask => &mut self.ask_queue,
+        };
+
+        // resting orders are found by ID, only the few stops are scanned
+        let cancelled = order_queue.cancel(order_id)
+            || match self
+                .stops
+                .iter()
+                .position(|stop| stop.order.order_id() == order_id)
+            {
+                Some(position) => {
+                    self.stops.remove(position);
+                    true
+                }
+                None => false,
+            };
+
+        if cancelled {
+            results.push(Ok(Success::Cancelled {
+                order_id,
+                ts: self.stamper.stamp(),
+            }));
+        } else if self.completed.get(order_id).is_some() {
+            results.push(Err(Failed::TooLateToCancel(order_id)));
+        } else {
+            results.push(Err(Failed::OrderNotFound(order_id)));
+        }
+    }
+
+    /* Helpers */
+
+    /// Why an amendment found no resting order on its side
+    fn amend_failure(&self, order_id: Uuid) -> Failed {
+        if self.bid_queue.get(order_id).is_some() || self.ask_queue.get(order_id).is_some() {
+            Failed::SideMismatch(order_id)
+        } else if self.completed.get(order_id).is_some() {
+            Failed::TooLateToAmend(order_id)
+        } else {
+            Failed::OrderNotFound(order_id)
+        }
+    }
+
+    /// Side the order rests on, bid if it rests on neither
+    fn side_of(&self, order_id: Uuid) -> OrderSide {
+        if self.ask_queue.get(order_id).is_some() {
+            OrderSide::Ask
+        } else {
+            OrderSide::Bid
+        }
+    }
+
+    /// Resting order of either side
+    fn resting(&self, order_id: Uuid) -> Option<&Order<Asset>> {
+        self.bid_queue
+            .get(order_id)
+            .or_else(|| self.ask_queue.get(order_id))
+    }
+
+    fn remove_resting(&mut self, order_id: Uuid) {
+        if !self.bid_queue.cancel(order_id) {
+            self.ask_queue.cancel(order_id);
+        }
+    }
+
+    /// Change quantity of a resting order in place, removing it once nothing
+    /// is left
+    fn set_resting_qty(&mut self, order_id: Uuid, qty: BigDecimal) {
+        let resting = match self.resting(order_id) {
+            Some(resting) => resting.clone(),
+            None => return,
+        };
+        let order_queue = match resting.side {
+            OrderSide::Bid => &mut self.bid_queue,
+            OrderSide::Ask => &mut self.ask_queue,
+        };
+        if qty <= BigDecimal::zero() {
+            order_queue.cancel(order_id);
+        } else {
+            order_queue.update(order_id, Order { qty, ..resting });
+        }
+    }
+
+    /// Take a fill off a resting order, removing it once nothing is left
+    fn fill_resting(&mut self, order_id: Uuid, qty: &BigDecimal) {
+        let resting = match self.resting(order_id) {
+            Some(resting) => resting.clone(),
+            None => return,
+        };
+        let order_queue = match resting.side {
+            OrderSide::Bid => &mut self.bid_queue,
+            OrderSide::Ask => &mut self.ask_queue,
+        };
+        if *qty >= resting.qty {
+            order_queue.cancel(order_id);
+        } else {
+            let filled = &resting.filled + qty;
+            let qty = &resting.qty - qty;
+            order_queue.update(order_id, Order { qty, filled, ..resting });
+        }
+    }
+
+    /// Append the request to the journal if it passes validation, false if the
+    /// journal failed to take it
+    fn journal_request(&mut self, order: &OrderRequest<Asset>) -> bool {
+        if self.journal.is_none() || self.validate(order).is_err() {
+            return true;
+        }
+        let journal = self.journal.as_mut().unwrap();
+        journal.append((self.order_asset, self.price_asset), order).is_ok()
+    }
+
+    fn record_change(&mut self, results: &OrderProcessingResult<Asset>) {
+        if results.iter().any(Result::is_ok) {
+            self.change_seq += 1;
+        }
+        self.event_seq += results.len() as u64;
+        self.completed.on_results(results);
+        for listener in self.listeners.iter_mut() {
+            listener::dispatch(listener.as_mut(), results);
+        }
+    }
+
+    /// Whether opposite orders at or better than the limit price add up to
+    /// the quantity, any price goes without limit.
+    ///
+    /// Under self-trade prevention orders of the owner do not count. With
+    /// `CancelNewest` and `DecrementAndCancel` the first level holding one
+    /// takes away from the new order before it fills, so only better levels
+    /// count.
+    fn fillable(
+        &self,
+        side: OrderSide,
+        price: Option<&BigDecimal>,
+        qty: &BigDecimal,
+        owner: Option<AccountId>,
+    ) -> bool {
+        let (opposite_queue, crosses): (_, fn(&BigDecimal, &BigDecimal) -> bool) = match side {
+            OrderSide::Bid => (&self.ask_queue, |resting, limit| resting <= limit),
+            OrderSide::Ask => (&self.bid_queue, |resting, limit| resting >= limit),
+        };
+        let crossing = || {
+            opposite_queue
+                .iter()
+                .filter(move |order| price.is_none_or(|price| crosses(&order.price, price)))
+        };
+
+        let stp = self.self_trade_prevention.zip(owner);
+        let own = |order: &Order<Asset>| stp.is_some_and(|(_, owner)| order.owner == Some(owner));
+        // best price of an own order, where the new order stops filling
+        let barrier = match stp {
+            Some((SelfTradePrevention::CancelOldest, _)) | None => None,
+            Some(_) => crossing()
+                .filter(|order| own(order))
+                .map(|order| &order.price)
+                .reduce(|best, price| match side {
+                    OrderSide::Bid => best.min(price),
+                    OrderSide::Ask => best.max(price),
+                }),
+        };
+
+        let available = crossing()
+            .filter(|order| !own(order))
+            .filter(|order| {
+                barrier.is_none_or(|barrier| match side {
+                    OrderSide::Bid => order.price < *barrier,
+                    OrderSide::Ask => order.price > *barrier,
+                })
+            })
+            .fold(BigDecimal::zero(), |total, order| total + &order.qty);
+        available >= *qty
+    }
+
+    /// Release stops the last trade reached, oldest first, until none is
+    /// left triggered; trades of released stops may trigger further ones
+    fn trigger_stops(&mut self, results: &mut OrderProcessingResult<Asset>) {
+        while let Some(last_price) = self.last_trade_price.clone() {
+            let position = match self
+                .stops
+                .iter()
+                .position(|stop| stop.is_triggered(&last_price))
+            {
+                Some(position) => position,
+                None => return,
+            };
+            let stop = self.stops.remove(position);
+            results.push(Ok(Success::Triggered {
+                order_id: stop.order.order_id(),
+                trigger_price: stop.trigger_price,
+                last_price,
+                ts: self.stamper.stamp(),
+            }));
+            self.process_request(results, stop.order);
+        }
+    }
+
+    /// Report one side of an auction trade, taking the quantity off the order
+    fn auction_fill(
+        &mut self,
+        results: &mut OrderProcessingResult<Asset>,
+        order: &Order<Asset>,
+        counterparty: &Order<Asset>,
+        trade_id: TradeId,
+        price: &BigDecimal,
+        qty: &BigDecimal,
+        aggressor: OrderSide,
+    ) {
+        let ts = self.stamper.stamp();
+        results.push(Ok(if *qty == order.qty {
+            Success::Filled {
+                order_id: order.order_id,
+                trade_id,
+                counterparty: counterparty.order_id,
+                side: order.side,
+                aggressor,
+                role: LiquidityRole::Maker,
+                order_type: OrderType::Limit,
+                price: price.clone(),
+                qty: qty.clone(),
+                ts,
+                liquidation: order.liquidation,
+                fee: None,
+                fee_asset: None,
+            }
+        } else {
+            Success::PartiallyFilled {
+                order_id: order.order_id,
+                trade_id,
+                counterparty: counterparty.order_id,
+                side: order.side,
+                aggressor,
+                role: LiquidityRole::Maker,
+                order_type: OrderType::Limit,
+                price: price.clone(),
+                qty: qty.clone(),
+                ts,
+                liquidation: order.liquidation,
+                fee: None,
+                fee_asset: None,
+            }
+        }));
+        self.fill_resting(order.order_id, qty);
+    }
+
+    /// Update the resting order's data, returning its new place in the queue
+    /// when it loses priority; the caller moves it there
+    fn apply_amend(
+        &mut self,
+        results: &mut OrderProcessingResult<Asset>,
+        order_id: Uuid,
+        side: OrderSide,
+        price: Option<BigDecimal>,
+        qty: Option<BigDecimal>,
+        ts: SystemTime,
+    ) -> Option<(Uuid, BigDecimal, SystemTime)> {
+        let order_queue = match side {
+            OrderSide::Bid => &mut self.bid_queue,
+            OrderSide::Ask => &mut self.ask_queue,
+        };
+        let resting = match order_queue.get(order_id) {
+            Some(order) => order.clone(),
+            None => {
+                results.push(Err(self.amend_failure(order_id)));
+                return None;
+            }
+        };
+
+        let price = price.unwrap_or_else(|| resting.price.clone());
+        // the request names the total, only what is not filled yet stays open
+        let qty = match qty {
+            Some(total) if total <= resting.filled => {
+                results.push(Err(Failed::QtyBelowFilled(order_id)));
+                return None;
+            }
+            Some(total) => total - &resting.filled,
+            None => resting.qty.clone(),
+        };
+        let keeps_priority = price == resting.price && qty <= resting.qty;
+        let prev_price = resting.price.clone();
+        let prev_qty = resting.qty.clone();
+        let amended = Order {
+            price: price.clone(),
+            qty: qty.clone(),
+            ..resting
+        };
+
+        order_queue.update(order_id, amended);
+        results.push(Ok(Success::Amended {
+            order_id,
+            price: price.clone(),
+            qty,
+            prev_price: Some(prev_price),
+            prev_qty,
+            priority_kept: keeps_priority,
+            ts: self.stamper.stamp(),
+        }));
+
+        if keeps_priority {
+            None
+        } else {
+            Some((order_id, price, ts))
+        }
+    }
+
+    fn store_new_limit_order(
+        &mut self,
+        results: &mut OrderProcessingResult<Asset>,
+        order_id: Uuid,
+        order_asset: Asset,
+        price_asset: Asset,
+        side: OrderSide,
+        price: BigDecimal,
+        qty: BigDecimal,
+        ts: SystemTime,
+        liquidation: bool,
+        time_in_force: TimeInForce,
+        owner: Option<AccountId>,
+    ) {
+        // only the remainder of a partly filled order gets here
+        if matches!(time_in_force, TimeInForce::IOC | TimeInForce::FOK) {
+            results.push(Ok(Success::Cancelled {
+                order_id,
+                ts: self.stamper.stamp(),
+            }));
+            return;
+        }
+
+        let priority = liquidation && self.liquidation_priority;
+        let filled = results
+            .iter()
+            .filter_map(|result| fill_of(result, order_id))
+            .sum();
+        let order_queue = match side {
+            OrderSide::Bid => &mut self.bid_queue,
+            OrderSide::Ask => &mut self.ask_queue,
+        };
+        if !order_queue.insert_with_priority(
+            order_id,
+            price.clone(),
+            ts,
+            priority,
+            Order {
+                order_id,
+                order_asset,
+                price_asset,
+                side,
+                price,
+                qty,
+                filled,
+                liquidation,
+                time_in_force,
+                owner,
+            },
+        ) {
+            results.push(Err(Failed::DuplicateOrderID(order_id)))
+        } else {
+            self.enforce_depth_limit(results, order_id, side);
+        }
+    }
+
+    fn enforce_depth_limit(
+        &mut self,
+        results: &mut OrderProcessingResult<Asset>,
+        order_id: Uuid,
+        side: OrderSide,
+    ) {
+        let limit = match self.depth_limit {
+            Some(limit) => limit,
+            None => return,
+        };
+        let order_queue = match side {
+            OrderSide::Bid => &mut self.bid_queue,
+            OrderSide::Ask => &mut self.ask_queue,
+        };
+
+        while limit.exceeded(order_queue) {
+            let farthest = match limit.policy {
+                DepthLimitPolicy::RejectNew => order_id,
+                DepthLimitPolicy::EvictFarthest => match order_queue.last_id() {
+                    Some(farthest) => farthest,
+                    None => return,
+                },
+            };
+            order_queue.cancel(farthest);
+            if farthest == order_id {
+                results.push(Err(Failed::DepthLimitExceeded(order_id)));
+                return;
+            }
+            results.push(Ok(Success::Cancelled {
+                order_id: farthest,
+                ts: self.stamper.stamp(),
+            }));
+        }
+    }
+
+    /// Match new order against the best opposite price level,
+    /// split among resting orders by the match policy.
+    ///
+    /// Returns quantity left unmatched, as an error if the policy allocated
+    /// nothing to the level, so matching it again would not either.
+    fn level_matching(
+        &mut self,
+        results: &mut OrderProcessingResult<Asset>,
+        order_id: Uuid,
+        order_asset: Asset,
+        price_asset: Asset,
+        order_type: OrderType,
+        side: OrderSide,
+        qty: BigDecimal,
+        liquidation: bool,
+        owner: Option<AccountId>,
+    ) -> Result<BigDecimal, BigDecimal> {
+        // get copy of the current opposite price level
+        let mut level: Vec<Order<Asset>> = {
+            let opposite_queue = match side {
+                OrderSide::Bid => &mut self.ask_queue,
+                OrderSide::Ask => &mut self.bid_queue,
+            };
+            opposite_queue.peek_level().into_iter().cloned().collect()
+        };
+
+        let mut qty = qty;
+        if let (Some(mode), Some(owner)) = (self.self_trade_prevention, owner) {
+            if level.iter().any(|order| order.owner == Some(owner)) {
+                qty = self.prevent_self_trade(results, mode, order_id, owner, side, qty, &level);
+                if qty.is_zero() {
+                    return Ok(qty);
+                }
+                level.retain(|order| order.owner != Some(owner));
+            }
+        }
+        let allocations = self.match_policy.allocate(&level, &qty);
+        if !level.is_empty() && allocations.iter().all(|deal_qty| deal_qty <= &BigDecimal::zero()) {
+            return Err(qty);
+        }
+
+        let mut remaining = qty;
+        for (opposite_order, deal_qty) in level.iter().zip(allocations) {
+            if deal_qty <= BigDecimal::zero() {
+                continue;
+            }
+            remaining -= &deal_qty;
+            self.order_matching(
+                results,
+                opposite_order,
+                order_id,
+                order_asset,
+                price_asset,
+                order_type,
+                side,
+                deal_qty,
+                remaining.is_zero(),
+                liquidation,
+            );
+        }
+
+        Ok(remaining)
+    }
+
+    /// Keep new order of the owner from trading with the owner's orders
+    /// resting at the level, returns what is left of the new order.
+    ///
+    /// The whole level is checked before any of it trades, so under
+    /// `CancelNewest` the new order does not trade at the level at all.
+    fn prevent_self_trade(
+        &mut self,
+        results: &mut OrderProcessingResult<Asset>,
+        mode: SelfTradePrevention,
+        order_id: Uuid,
+        owner: AccountId,
+        side: OrderSide,
+        qty: BigDecimal,
+        level: &[Order<Asset>],
+    ) -> BigDecimal {
+        if mode == SelfTradePrevention::CancelNewest {
+            results.push(Ok(Success::SelfTradePrevented {
+                order_id,
+                owner,
+                qty,
+                remaining: BigDecimal::zero(),
+                ts: self.stamper.stamp(),
+            }));
+            return BigDecimal::zero();
+        }
+
+        let mut remaining = qty;
+        for resting in level.iter().filter(|order| order.owner == Some(owner)) {
+            let taken = match mode {
+                SelfTradePrevention::DecrementAndCancel => {
+                    resting.qty.clone().min(remaining.clone())
+                }
+                _ => resting.qty.clone(),
+            };
+            let left = &resting.qty - &taken;
+            let opposite_queue = match side {
+                OrderSide::Bid => &mut self.ask_queue,
+                OrderSide::Ask => &mut self.bid_queue,
+            };
+            if left.is_zero() {
+                opposite_queue.cancel(resting.order_id);
+            } else {
+                opposite_queue.update(
+                    resting.order_id,
+                    Order {
+                        qty: left.clone(),
+                        ..resting.clone()
+                    },
+                );
+            }
+            results.push(Ok(Success::SelfTradePrevented {
+                order_id: resting.order_id,
+                owner,
+                qty: taken.clone(),
+                remaining: left,
+                ts: self.stamper.stamp(),
+            }));
+
+            if mode == SelfTradePrevention::DecrementAndCancel {
+                remaining -= &taken;
+                results.push(Ok(Success::SelfTradePrevented {
+                    order_id,
+                    owner,
+                    qty: taken,
+                    remaining: remaining.clone(),
+                    ts: self.stamper.stamp(),
+                }));
+                if remaining.is_zero() {
+                    break;
+                }
+            }
+        }
+        remaining
+    }
+
+    /// Price of a new limit order after applying the band, along with the
+    /// price asked for if the collar moved it
+    fn band_limit(
+        &self,
+        side: OrderSide,
+        price: BigDecimal,
+    ) -> Result<(BigDecimal, Option<BigDecimal>), &'static str> {
+        let (band, reference) = match (&self.price_band, &self.reference_price) {
+            (Some(band), Some(reference)) => (band, reference),
+            _ => return Ok((price, None)),
+        };
+
+        // edges rounded inwards onto the tick grid
+        let scale = self.config.price_scale;
+        let (edge, through) = match side {
+            OrderSide::Bid => {
+                let edge = scale.round(
+                    &(reference * (BigDecimal::one() + &band.width)),
+                    RoundingMode::Floor,
+                );
+                let through = price > edge;
+                (edge, through)
+            }
+            OrderSide::Ask => {
+                let edge = scale.round(
+                    &(reference * (BigDecimal::one() - &band.width)),
+                    RoundingMode::Ceiling,
+                );
+                let through = price < edge;
+                (edge, through)
+            }
+        };
+
+        match (through, band.mode) {
+            (false, _) => Ok((price, None)),
+            (true, BandMode::Reject) => Err(ERR_OUTSIDE_BAND),
+            (true, BandMode::Collar) => Ok((edge, Some(price))),
+        }
+    }
+
+    /// Execute single deal of `qty` between new order and resting opposite order
+    fn order_matching(
+        &mut self,
+        results: &mut OrderProcessingResult<Asset>,
+        opposite_order: &Order<Asset>,
+        order_id: Uuid,
+        order_asset: Asset,
+        price_asset: Asset,
+        order_type: OrderType,
+        side: OrderSide,
+        qty: BigDecimal,
+        complete: bool,
+        liquidation: bool,
+    ) {
+        let trade_id = self.trade_ids.next_id();
+        self.last_trade_id = Some(trade_id);
+        let marked = self
+            .price_band
+            .as_ref()
+            .is_some_and(|band| band.reference == BandReference::Mark);
+        if !marked {
+            self.reference_price = Some(opposite_order.price.clone());
+        }
+        self.last_trade_price = Some(opposite_order.price.clone());
+
+        if complete {
+            // report filled new order
+            results.push(Ok(Success::Filled {
+                order_id,
+                trade_id,
+                counterparty: opposite_order.order_id,
+                side,
+                aggressor: side,
+                role: LiquidityRole::Taker,
+                order_type,
+                price: opposite_order.price.clone(),
+                qty: qty.clone(),
+                ts: self.stamper.stamp(),
+                liquidation,
+                fee: None,
+                fee_asset: None,
+            }));
+        } else {
+            // report new order partially filled
+            results.push(Ok(Success::PartiallyFilled {
+                order_id,
+                trade_id,
+                counterparty: opposite_order.order_id,
+                side,
+                aggressor: side,
+                role: LiquidityRole::Taker,
+                order_type,
+                price: opposite_order.price.clone(),
+                qty: qty.clone(),
+                ts: self.stamper.stamp(),
+                liquidation,
+                fee: None,
+                fee_asset: None,
+            }));
+        }
+
+        let remaining = &opposite_order.qty - &qty;
+        let dust = self
+            .dust_threshold
+            .as_ref()
+            .is_some_and(|threshold| threshold.is_dust(&opposite_order.price, &remaining));
+
+        let opposite_queue = match side {
+            OrderSide::Bid => &mut self.ask_queue,
+            OrderSide::Ask => &mut self.bid_queue,
+        };
+
+        if qty < opposite_order.qty {
+            // report partially filled opposite limit order
+            results.push(Ok(Success::PartiallyFilled {
+                order_id: opposite_order.order_id,
+                trade_id,
+                counterparty: order_id,
+                side: opposite_order.side,
+                aggressor: side,
+                role: LiquidityRole::Maker,
+                order_type: OrderType::Limit,
+                price: opposite_order.price.clone(),
+                qty: qty.clone(),
+                ts: self.stamper.stamp(),
+                liquidation: opposite_order.liquidation,
+                fee: None,
+                fee_asset: None,
+            }));
+
+            // modify unmatched part of the opposite limit order
+            opposite_queue.update(
+                opposite_order.order_id,
+                Order {
+                    order_id: opposite_order.order_id,
+                    order_asset,
+                    price_asset,
+                    side: opposite_order.side,
+                    price: opposite_order.price.clone(),
+                    qty: remaining.clone(),
+                    filled: &opposite_order.filled + &qty,
+                    liquidation: opposite_order.liquidation,
+                    time_in_force: opposite_order.time_in_force,
+                    owner: opposite_order.owner,
+                },
+            );
+
+            if dust {
+                opposite_queue.cancel(opposite_order.order_id);
+                results.push(Ok(Success::DustCancelled {
+                    order_id: opposite_order.order_id,
+                    side: opposite_order.side,
+                    qty: remaining,
+                    ts: self.stamper.stamp(),
+                }));
+            }
+        } else {
+            // report filled opposite limit order
+            results.push(Ok(Success::Filled {
+                order_id: opposite_order.order_id,
+                trade_id,
+                counterparty: order_id,
+                side: opposite_order.side,
+                aggressor: side,
+                role: LiquidityRole::Maker,
+                order_type: OrderType::Limit,
+                price: opposite_order.price.clone(),
+                qty,
+                ts: self.stamper.stamp(),
+                liquidation: opposite_order.liquidation,
+                fee: None,
+                fee_asset: None,
+            }));
+
+            // remove filled limit order from the queue
+            let is_current = opposite_queue
+                .peek()
+                .is_some_and(|order| order.order_id == opposite_order.order_id);
+            if is_current {
+                opposite_queue.pop();
+            } else {
+                opposite_queue.cancel(opposite_order.order_id);
+            }
+        }
+    }
+}
+
+/// State of a book as written by `Serialize`, borrowed or owned
+#[derive(Serialize, Deserialize)]
+struct StoredBook<Asset, Queue, Stops, Completed> {
+    order_asset: Asset,
+    price_asset: Asset,
+    bid_queue: Queue,
+    ask_queue: Queue,
+    liquidation_priority: bool,
+    depth_limit: Option<DepthLimit>,
+    market_remainder: MarketRemainderPolicy,
+    self_trade_prevention: Option<SelfTradePrevention>,
+    dust_threshold: Option<DustThreshold>,
+    #[serde(default)]
+    qty_limits: Option<QtyLimits>,
+    price_band: Option<PriceBand>,
+    reference_price: Option<BigDecimal>,
+    last_trade_price: Option<BigDecimal>,
+    #[serde(default)]
+    trading_status: TradingStatus,
+    #[serde(default)]
+    halted: bool,
+    stops: Stops,
+    change_seq: u64,
+    event_seq: u64,
+    completed: Completed,
+    config: BookConfig,
+    stamper: EventStamper,
+    last_trade_id: Option<TradeId>,
+}
+
+/// Checkpoint of the whole book: resting orders in their queue priority,
+/// waiting stops, settings, sequence numbers and recently completed orders.
+///
+/// Match policies set on the book, clock and listeners are not part of it,
+/// a restored book has the match algorithm of its config, the defaults of
+/// `Orderbook::new` otherwise and trade IDs going on from the last one
+/// issued.
+impl<Asset> Serialize for Orderbook<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq + Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        StoredBook {
+            order_asset: self.order_asset,
+            price_asset: self.price_asset,
+            bid_queue: &self.bid_queue,
+            ask_queue: &self.ask_queue,
+            liquidation_priority: self.liquidation_priority,
+            depth_limit: self.depth_limit,
+            market_remainder: self.market_remainder,
+            self_trade_prevention: self.self_trade_prevention,
+            dust_threshold: self.dust_threshold.clone(),
+            qty_limits: self.qty_limits().cloned(),
+            price_band: self.price_band.clone(),
+            reference_price: self.reference_price.clone(),
+            last_trade_price: self.last_trade_price.clone(),
+            trading_status: self.trading_status,
+            halted: self.halted,
+            stops: &self.stops,
+            change_seq: self.change_seq,
+            event_seq: self.event_seq,
+            completed: &self.completed,
+            config: self.config,
+            stamper: self.stamper,
+            last_trade_id: self.last_trade_id,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, Asset> Deserialize<'de> for Orderbook<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq + Deserialize<'de>,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let stored: StoredBook<
+            Asset,
+            OrderQueue<Order<Asset>>,
+            Vec<StopOrder<Asset>>,
+            CompletedOrders,
+        > = StoredBook::deserialize(deserializer)?;
+        let mut book = Orderbook::new_with_config(
+            stored.order_asset,
+            stored.price_asset,
+            stored.config,
+        );
+        book.bid_queue = stored.bid_queue;
+        book.ask_queue = stored.ask_queue;
+        book.liquidation_priority = stored.liquidation_priority;
+        book.depth_limit = stored.depth_limit;
+        book.market_remainder = stored.market_remainder;
+        book.self_trade_prevention = stored.self_trade_prevention;
+        book.dust_threshold = stored.dust_threshold;
+        book.set_qty_limits(stored.qty_limits)
+            .map_err(serde::de::Error::custom)?;
+        book.price_band = stored.price_band;
+        book.reference_price = stored.reference_price;
+        book.last_trade_price = stored.last_trade_price;
+        book.trading_status = stored.trading_status;
+        book.halted = stored.halted;
+        book.stops = stored.stops;
+        book.change_seq = stored.change_seq;
+        book.event_seq = stored.event_seq;
+        book.completed = stored.completed;
+        book.stamper = stored.stamper;
+        if let Some(last) = stored.last_trade_id {
+            book.trade_ids = Box::new(SequentialTradeIds::starting_after(last));
+            book.last_trade_id = Some(last);
+        }
+        Ok(book)
+    }
+}
+
+/// Whether an order of the side may trade at the price, no limit means any
+fn within_limit(side: OrderSide, price: &BigDecimal, limit: Option<&BigDecimal>) -> bool {
+    match (side, limit) {
+        (_, None) => true,
+        (OrderSide::Bid, Some(limit)) => price <= limit,
+        (OrderSide::Ask, Some(limit)) => price >= limit,
+    }
+}
+
+fn is_fill_of<Asset>(result: &Result<Success<Asset>, Failed>, order_id: Uuid) -> bool {
+    match result {
+        Ok(Success::Filled { order_id: id, .. })
+        | Ok(Success::PartiallyFilled { order_id: id, .. }) => *id == order_id,
+        _ => false,
+    }
+}
+
+/// Quantity of the result if it fills the order
+fn fill_of<Asset>(result: &Result<Success<Asset>, Failed>, order_id: Uuid) -> Option<&BigDecimal> {
+    match result {
+        Ok(Success::Filled { order_id: id, qty, .. })
+        | Ok(Success::PartiallyFilled { order_id: id, qty, .. })
+            if *id == order_id =>
+        {
+            Some(qty)
+        }
+        _ => None,
+    }
+}
+
+/// Price levels of the queue, lowest price first
+fn aggregate_levels<Asset>(queue: &OrderQueue<Order<Asset>>) -> Vec<DepthLevel>
+where
+    Asset: Debug + Clone,
+{
+    let mut levels: BTreeMap<&BigDecimal, (BigDecimal, usize)> = BTreeMap::new();
+    for order in queue.iter() {
+        let (qty, orders) = levels
+            .entry(&order.price)
+            .or_insert_with(|| (BigDecimal::zero(), 0));
+        *qty += &order.qty;
+        *orders += 1;
+    }
+    levels
+        .into_iter()
+        .map(|(price, (qty, orders))| DepthLevel {
+            price: price.clone(),
+            qty,
+            orders,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::super::errors::{
+        CODE_QTY_BELOW_FILLED, CODE_SIDE_MISMATCH, CODE_TOO_LATE_TO_AMEND, CODE_TOO_LATE_TO_CANCEL,
+    };
+    use std::sync::Mutex;
+    use super::super::matching::ProRata;
+    use super::super::orders::{self, AmendOrder, MarketProtection};
+    use super::super::validation::{
+        ERR_BAD_EXPIRY, ERR_BAD_PROTECTION, ERR_OFF_LOT, ERR_OFF_TICK, ERR_QTY_ABOVE_MAX,
+        ERR_QTY_BELOW_MIN,
+    };
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[derive(PartialEq, Eq, Debug, Copy, Clone, Serialize, Deserialize)]
+    pub enum Asset {
+        USD,
+        BTC,
+    }
+
+    fn bigdec(num: &str) -> BigDecimal {
+        match BigDecimal::from_str(num) {
+            Ok(dec) => dec,
+            Err(_) => BigDecimal::zero(),
+        }
+    }
+
+    #[test]
+    fn cancel_nonexisting() {
+        let id = Uuid::parse_str("00000000-0000-0000-0000-000000000000").unwrap();
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        let request = orders::limit_order_cancel_request(id, OrderSide::Bid);
+        let mut result = orderbook.process_order(request);
+
+        assert_eq!(result.len(), 1);
+        match result.pop().unwrap() {
+            Err(_) => (),
+            _ => panic!("unexpected events"),
+        }
+    }
+
+    #[test]
+    fn cancel_without_side() {
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        let mut ids = vec![];
+        for (side, price) in [(OrderSide::Bid, "99"), (OrderSide::Ask, "101")] {
+            let order = orders::new_limit_order_request(
+                Asset::BTC,
+                Asset::USD,
+                side,
+                bigdec(price),
+                bigdec("1"),
+                SystemTime::now(),
+            );
+            ids.push(order.order_id());
+            orderbook.process_order(order);
+        }
+
+        for id in &ids {
+            match orderbook.process_order(orders::cancel_order_request(*id)).as_slice() {
+                [Ok(Success::Cancelled { order_id, .. })] => assert_eq!(order_id, id),
+                _ => panic!("unexpected events"),
+            }
+        }
+        assert!(orderbook.bid_queue.is_empty());
+        assert!(orderbook.ask_queue.is_empty());
+
+        // known on neither side
+        assert!(matches!(
+            orderbook.process_order(orders::cancel_order_request(Uuid::new_v4())).as_slice(),
+            [Err(Failed::OrderNotFound(_))]
+        ));
+    }
+
+    #[derive(Default)]
+    struct EventCounts {
+        accepted: usize,
+        fills: usize,
+        cancels: usize,
+        rejects: usize,
+    }
+
+    impl OrderbookListener<Asset> for EventCounts {
+        fn on_accept(&mut self, _event: &Success<Asset>) {
+            self.accepted += 1;
+        }
+
+        fn on_fill(&mut self, _event: &Success<Asset>) {
+            self.fills += 1;
+        }
+
+        fn on_cancel(&mut self, _event: &Success<Asset>) {
+            self.cancels += 1;
+        }
+
+        fn on_reject(&mut self, _failure: &Failed) {
+            self.rejects += 1;
+        }
+    }
+
+    #[test]
+    fn listener_callbacks() {
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        let counts = Arc::new(Mutex::new(EventCounts::default()));
+        orderbook.add_listener(counts.clone());
+
+        let resting = orders::new_limit_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Ask,
+            bigdec("100"),
+            bigdec("2"),
+            SystemTime::now(),
+        );
+        let resting_id = resting.order_id();
+        orderbook.process_order(resting);
+        orderbook.process_order(orders::new_limit_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Bid,
+            bigdec("100"),
+            bigdec("1"),
+            SystemTime::now(),
+        ));
+        assert_eq!(counts.lock().unwrap().accepted, 2);
+        // both sides of the trade
+        assert_eq!(counts.lock().unwrap().fills, 2);
+
+        orderbook.process_order(orders::limit_order_cancel_request(resting_id, OrderSide::Ask));
+        orderbook.process_order(orders::limit_order_cancel_request(resting_id, OrderSide::Ask));
+        let counts = counts.lock().unwrap();
+        assert_eq!(counts.cancels, 1);
+        assert_eq!(counts.rejects, 1);
+    }
+
+    #[test]
+    fn event_sequence() {
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        let limit = |side, price: &str| {
+            orders::new_limit_order_request(
+                Asset::BTC,
+                Asset::USD,
+                side,
+                bigdec(price),
+                bigdec("1"),
+                SystemTime::now(),
+            )
+        };
+
+        let resting = orderbook.process_order_sequenced(limit(OrderSide::Ask, "100"));
+        assert_eq!(resting[0].seq, 1);
+        let crossing = orderbook.process_order_sequenced(limit(OrderSide::Bid, "100"));
+        let seqs: Vec<u64> = crossing.iter().map(|event| event.seq).collect();
+        assert_eq!(seqs, vec![2, 3, 4]);
+
+        // failures and results of plain calls are numbered too
+        let failed = orderbook.process_order_sequenced(limit(OrderSide::Bid, "-1"));
+        assert!(failed[0].event.is_err());
+        assert_eq!(failed[0].seq, 5);
+        orderbook.process_order(limit(OrderSide::Bid, "99"));
+        assert_eq!(orderbook.event_seq(), 6);
+        let cancelled = orderbook.cancel_resting(|_| true);
+        assert_eq!(SequencedEvent::number(cancelled, orderbook.event_seq())[0].seq, 7);
+    }
+
+    #[test]
+    fn serde_checkpoint() {
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        orderbook.set_self_trade_prevention(Some(SelfTradePrevention::CancelOldest));
+        let limit = |side, price: &str, qty: &str| {
+            orders::new_limit_order_request(
+                Asset::BTC,
+                Asset::USD,
+                side,
+                bigdec(price),
+                bigdec(qty),
+                SystemTime::now(),
+            )
+        };
+        for (price, qty) in [("101", "1"), ("101", "2"), ("102", "1"), ("101", "3")] {
+            orderbook.process_order(limit(OrderSide::Ask, price, qty));
+        }
+        orderbook.process_order(limit(OrderSide::Bid, "99", "1"));
+        let filled = limit(OrderSide::Bid, "101", "0.5");
+        let filled_id = filled.order_id();
+        orderbook.process_order(filled);
+        orderbook.process_order(orders::new_stop_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Bid,
+            bigdec("105"),
+            bigdec("1"),
+            SystemTime::now(),
+        ));
+
+        let json = serde_json::to_string(&orderbook).unwrap();
+        let mut restored: Orderbook<Asset> = serde_json::from_str(&json).unwrap();
+        let ranked = |book: &Orderbook<Asset>| -> Vec<(Uuid, bool)> {
+            book.ask_queue
+                .ranked()
+                .into_iter()
+                .map(|(order, _, priority)| (order.order_id, priority))
+                .collect()
+        };
+        assert_eq!(ranked(&restored), ranked(&orderbook));
+        assert_eq!(restored.depth(10), orderbook.depth(10));
+        assert_eq!(restored.stop_count(), 1);
+        assert_eq!(restored.change_seq(), orderbook.change_seq());
+        assert_eq!(restored.event_seq(), orderbook.event_seq());
+        assert_eq!(restored.last_trade_price(), Some(&bigdec("101")));
+        assert_eq!(restored.order_status(filled_id), Some(OrderStatus::Filled));
+        assert_eq!(restored.self_trade_prevention, orderbook.self_trade_prevention);
+
+        // both go on the same way, trade IDs included
+        let fills = |book: &mut Orderbook<Asset>| -> Vec<(Uuid, TradeId)> {
+            book.process_order(limit(OrderSide::Bid, "101", "2"))
+                .into_iter()
+                .filter_map(|result| match result {
+                    Ok(Success::Filled {
+                        order_id,
+                        trade_id,
+                        ..
+                    })
+                    | Ok(Success::PartiallyFilled {
+                        order_id,
+                        trade_id,
+                        ..
+                    }) => Some((order_id, trade_id)),
+                    _ => None,
+                })
+                .collect()
+        };
+        let expected = fills(&mut orderbook);
+        let fills = fills(&mut restored);
+        assert_eq!(fills.len(), 4);
+        assert_eq!(fills[1], expected[1]);
+        assert_eq!(fills[3], expected[3]);
+        assert_eq!(fills[0].1, expected[0].1);
+    }
+
+    #[test]
+    fn journal_replay() {
+        type Entries = Vec<((Asset, Asset), OrderRequest<Asset>)>;
+        let journal: Arc<Mutex<Entries>> = Arc::new(Mutex::new(vec![]));
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        orderbook.set_journal(journal.clone());
+        let limit = |side, price: &str, qty: &str| {
+            orders::new_limit_order_request(
+                Asset::BTC,
+                Asset::USD,
+                side,
+                bigdec(price),
+                bigdec(qty),
+                SystemTime::now(),
+            )
+        };
+
+        let mut ids = vec![];
+        for (price, qty) in [("101", "1"), ("101", "2"), ("102", "1"), ("100", "3")] {
+            let order = limit(OrderSide::Ask, price, qty);
+            ids.push(order.order_id());
+            orderbook.process_order(order);
+        }
+        // rejected by validation, left out of the journal
+        orderbook.process_order(limit(OrderSide::Bid, "-1", "1"));
+        orderbook.process_order(limit(OrderSide::Bid, "101", "1.5"));
+        orderbook.process_amends(vec![AmendOrder {
+            id: ids[2],
+            side: OrderSide::Ask,
+            price: Some(bigdec("101")),
+            qty: None,
+            ts: SystemTime::now(),
+        }]);
+        orderbook.process_order(OrderRequest::CancelOrder {
+            id: ids[1],
+            side: None,
+        });
+        orderbook.process_order(orders::new_stop_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Bid,
+            bigdec("105"),
+            bigdec("1"),
+            SystemTime::now(),
+        ));
+        assert_eq!(journal.lock().unwrap().len(), 8);
+
+        let mut rebuilt = Orderbook::new(Asset::BTC, Asset::USD);
+        let requests: Vec<_> = journal.lock().unwrap().iter().map(|(_, r)| r.clone()).collect();
+        assert_eq!(rebuilt.replay(requests), 8);
+        let ranked = |book: &Orderbook<Asset>| -> Vec<(Uuid, BigDecimal, bool)> {
+            book.ask_queue
+                .ranked()
+                .into_iter()
+                .map(|(order, _, priority)| (order.order_id, order.qty.clone(), priority))
+                .collect()
+        };
+        assert_eq!(ranked(&rebuilt), ranked(&orderbook));
+        assert_eq!(rebuilt.depth(10), orderbook.depth(10));
+        assert_eq!(rebuilt.stop_count(), 1);
+        assert_eq!(rebuilt.change_seq(), orderbook.change_seq());
+        assert_eq!(rebuilt.last_trade_id, orderbook.last_trade_id);
+        assert_eq!(rebuilt.last_trade_price(), orderbook.last_trade_price());
+
+        // a journal failing to write rejects the request before it rests
+        struct Broken;
+        impl Journal<Asset> for Broken {
+            fn append(
+                &mut self,
+                _symbol: (Asset, Asset),
+                _request: &OrderRequest<Asset>,
+            ) -> std::io::Result<()> {
+                Err(std::io::Error::other("disk full"))
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        rebuilt.set_journal(Broken);
+        let results = rebuilt.process_order(limit(OrderSide::Bid, "90", "1"));
+        match results.as_slice() {
+            [Err(Failed::JournalFailed(_))] => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(rebuilt.best_bid(), orderbook.best_bid());
+        assert_eq!(rebuilt.change_seq(), orderbook.change_seq());
+    }
+
+    #[test]
+    fn mirror_from_events() {
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        let mut mirror = Orderbook::new(Asset::BTC, Asset::USD);
+        orderbook.set_self_trade_prevention(Some(SelfTradePrevention::CancelOldest));
+        let limit = |side, price: &str, qty: &str| {
+            orders::new_limit_order_request(
+                Asset::BTC,
+                Asset::USD,
+                side,
+                bigdec(price),
+                bigdec(qty),
+                SystemTime::now(),
+            )
+        };
+
+        let mut ids = vec![];
+        let mut requests = vec![];
+        for (price, qty) in [("101", "1"), ("101", "2"), ("102", "1"), ("103", "1")] {
+            let order = limit(OrderSide::Ask, price, qty);
+            ids.push(order.order_id());
+            requests.push(order);
+        }
+        requests.push(limit(OrderSide::Bid, "99", "1"));
+        requests.push(orders::new_stop_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Bid,
+            bigdec("102"),
+            bigdec("1.5"),
+            SystemTime::now(),
+        ));
+        // takes 101 and rests the remainder, then moves an ask to the back
+        requests.push(limit(OrderSide::Bid, "101", "3.5"));
+        requests.push(OrderRequest::AmendOrder {
+            id: ids[2],
+            side: OrderSide::Ask,
+            price: Some(bigdec("103")),
+            qty: None,
+            ts: SystemTime::now(),
+        });
+        requests.push(OrderRequest::CancelOrder {
+            id: ids[3],
+            side: None,
+        });
+        // trades at 103 and releases the stop, a market order
+        requests.push(orders::new_market_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Bid,
+            bigdec("0.5"),
+            SystemTime::now(),
+        ));
+        requests.push(limit(OrderSide::Ask, "100", "0.25"));
+
+        for request in requests {
+            let results = orderbook.process_order(request);
+            mirror.apply_results(&results);
+        }
+
+        type Ranked = Vec<(Uuid, BigDecimal, BigDecimal, BigDecimal)>;
+        let ranked = |book: &Orderbook<Asset>, side| -> Ranked {
+            let queue = match side {
+                OrderSide::Bid => &book.bid_queue,
+                OrderSide::Ask => &book.ask_queue,
+            };
+            queue
+                .ranked()
+                .into_iter()
+                .map(|(order, _, _)| {
+                    (order.order_id, order.price.clone(), order.qty.clone(), order.filled.clone())
+                })
+                .collect()
+        };
+        assert_eq!(ranked(&mirror, OrderSide::Bid), ranked(&orderbook, OrderSide::Bid));
+        assert_eq!(ranked(&mirror, OrderSide::Ask), ranked(&orderbook, OrderSide::Ask));
+        assert_eq!(mirror.stop_count(), orderbook.stop_count());
+        assert_eq!(mirror.last_trade_price(), orderbook.last_trade_price());
+        assert_eq!(mirror.last_trade_id, orderbook.last_trade_id);
+        assert_eq!(mirror.change_seq(), orderbook.change_seq());
+        assert_eq!(mirror.event_seq(), orderbook.event_seq());
+        for id in &ids {
+            assert_eq!(mirror.order_status(*id), orderbook.order_status(*id));
+        }
+    }
+
+    #[test]
+    fn late_cancel() {
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        let limit = |side, qty: &str| {
+            orders::new_limit_order_request(
+                Asset::BTC,
+                Asset::USD,
+                side,
+                bigdec("100"),
+                bigdec(qty),
+                SystemTime::now(),
+            )
+        };
+        let ask = limit(OrderSide::Ask, "1");
+        let ask_id = ask.order_id();
+        orderbook.process_order(ask);
+        assert_eq!(orderbook.order_status(ask_id), Some(OrderStatus::Resting));
+
+        let bid = limit(OrderSide::Bid, "2");
+        let bid_id = bid.order_id();
+        orderbook.process_order(bid);
+        assert_eq!(orderbook.order_status(ask_id), Some(OrderStatus::Filled));
+        match orderbook.process_order(orders::cancel_order_request(ask_id)).as_slice() {
+            [Err(failed @ Failed::TooLateToCancel(_))] => {
+                assert_eq!(failed.code(), CODE_TOO_LATE_TO_CANCEL)
+            }
+            _ => panic!("unexpected events"),
+        }
+
+        // the partly filled remainder is cancelled for good
+        orderbook.process_order(orders::cancel_order_request(bid_id));
+        assert_eq!(orderbook.order_status(bid_id), Some(OrderStatus::Cancelled));
+        assert!(matches!(
+            orderbook.process_order(orders::cancel_order_request(bid_id)).as_slice(),
+            [Err(Failed::TooLateToCancel(_))]
+        ));
+        assert_eq!(orderbook.order_status(Uuid::new_v4()), None);
+    }
+
+    #[test]
+    fn amend_failures() {
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        let limit = |side, qty: &str| {
+            orders::new_limit_order_request(
+                Asset::BTC,
+                Asset::USD,
+                side,
+                bigdec("100"),
+                bigdec(qty),
+                SystemTime::now(),
+            )
+        };
+        let amend = |id, side, qty: &str| {
+            orders::amend_order_qty_request(id, side, bigdec(qty), SystemTime::now())
+        };
+        let ask = limit(OrderSide::Ask, "1");
+        let ask_id = ask.order_id();
+        orderbook.process_order(ask);
+        let bid = limit(OrderSide::Bid, "3");
+        let bid_id = bid.order_id();
+        orderbook.process_order(bid);
+
+        // the ask is filled, the bid rests with 2 of its 3 left
+        match orderbook.process_order(amend(ask_id, OrderSide::Ask, "5")).as_slice() {
+            [Err(failed @ Failed::TooLateToAmend(_))] => {
+                assert_eq!(failed.code(), CODE_TOO_LATE_TO_AMEND)
+            }
+            _ => panic!("unexpected events"),
+        }
+        match orderbook.process_order(amend(bid_id, OrderSide::Ask, "5")).as_slice() {
+            [Err(failed @ Failed::SideMismatch(_))] => {
+                assert_eq!(failed.code(), CODE_SIDE_MISMATCH)
+            }
+            _ => panic!("unexpected events"),
+        }
+        assert!(matches!(
+            orderbook
+                .process_order(amend(Uuid::new_v4(), OrderSide::Bid, "5"))
+                .as_slice(),
+            [Err(Failed::OrderNotFound(_))]
+        ));
+
+        // the quantity is the new total, it cannot go down to what is filled
+        for total in &["0.5", "1"] {
+            match orderbook.process_order(amend(bid_id, OrderSide::Bid, total)).as_slice() {
+                [Err(failed @ Failed::QtyBelowFilled(_))] => {
+                    assert_eq!(failed.code(), CODE_QTY_BELOW_FILLED)
+                }
+                _ => panic!("unexpected events"),
+            }
+        }
+        match orderbook.process_order(amend(bid_id, OrderSide::Bid, "2.5")).as_slice() {
+            [Ok(Success::Amended {
+                qty,
+                prev_qty,
+                priority_kept,
+                ..
+            })] => {
+                assert_eq!(*qty, bigdec("1.5"));
+                assert_eq!(*prev_qty, bigdec("2"));
+                assert!(priority_kept);
+            }
+            _ => panic!("unexpected events"),
+        }
+        let resting = orderbook.bid_queue.get(bid_id).unwrap();
+        assert_eq!((&resting.qty, &resting.filled), (&bigdec("1.5"), &bigdec("1")));
+    }
+
+    #[test]
+    fn reconcile_session() {
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        let order = |side, qty: &str| {
+            orders::new_limit_order_request(
+                Asset::BTC,
+                Asset::USD,
+                side,
+                bigdec("100"),
+                bigdec(qty),
+                SystemTime::now(),
+            )
+        };
+        let ask = order(OrderSide::Ask, "1");
+        let bid = order(OrderSide::Bid, "3");
+        let stop = orders::new_stop_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Bid,
+            bigdec("110"),
+            bigdec("2"),
+            SystemTime::now(),
+        );
+        let unknown = Uuid::new_v4();
+        let ids = [ask.order_id(), bid.order_id(), stop.order_id(), unknown];
+        for request in [ask, bid, stop] {
+            orderbook.process_order(request);
+        }
+
+        let states: Vec<(Option<OrderStatus>, BigDecimal)> = orderbook
+            .reconcile(&ids)
+            .into_iter()
+            .map(|state| (state.status, state.remaining))
+            .collect();
+        assert_eq!(
+            states,
+            vec![
+                (Some(OrderStatus::Filled), bigdec("0")),
+                (Some(OrderStatus::Resting), bigdec("2")),
+                (Some(OrderStatus::Waiting), bigdec("2")),
+                (None, bigdec("0")),
+            ]
+        );
+    }
+
+    #[test]
+    fn top_of_book() {
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        let mut best_ids = vec![];
+        for (side, price) in [
+            (OrderSide::Bid, "98"),
+            (OrderSide::Bid, "99"),
+            (OrderSide::Ask, "102"),
+        ] {
+            let order = orders::new_limit_order_request(
+                Asset::BTC,
+                Asset::USD,
+                side,
+                bigdec(price),
+                bigdec("1"),
+                SystemTime::now(),
+            );
+            best_ids.push(order.order_id());
+            orderbook.process_order(order);
+        }
+        // the cancelled best bid must not show through a shared reference
+        orderbook.process_order(orders::cancel_order_request(best_ids[1]));
+
+        let book = &orderbook;
+        assert_eq!(book.best_bid(), Some(&bigdec("98")));
+        assert_eq!(book.best_ask(), Some(&bigdec("102")));
+        assert_eq!(book.mid_price(), Some(bigdec("100")));
+        assert_eq!(Orderbook::new(Asset::BTC, Asset::USD).mid_price(), None);
+    }
+
+    #[test]
+    fn depth_snapshot() {
+        use std::time::Duration;
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        let start = SystemTime::now();
+        for (secs, (side, price, qty)) in [
+            (OrderSide::Bid, "99", "1"),
+            (OrderSide::Bid, "99", "2"),
+            (OrderSide::Bid, "98", "1"),
+            (OrderSide::Bid, "97", "1"),
+            (OrderSide::Ask, "101", "4"),
+        ]
+        .iter()
+        .enumerate()
+        {
+            orderbook.process_order(orders::new_limit_order_request(
+                Asset::BTC,
+                Asset::USD,
+                *side,
+                bigdec(price),
+                bigdec(qty),
+                start + Duration::from_secs(secs as u64),
+            ));
+        }
+
+        let depth = orderbook.depth(2);
+        let level = |price: &str, qty: &str, orders| DepthLevel {
+            price: bigdec(price),
+            qty: bigdec(qty),
+            orders,
+        };
+        assert_eq!(depth.bids, vec![level("99", "3", 2), level("98", "1", 1)]);
+        assert_eq!(depth.asks, vec![level("101", "4", 1)]);
+        assert_eq!(depth.seq, orderbook.change_seq());
+        // nothing was taken out of the book
+        assert_eq!(orderbook.bid_queue.len(), 4);
+
+        // order by order, in priority
+        let bids: Vec<(String, String)> = orderbook
+            .bids()
+            .map(|order| (order.price.to_string(), order.qty.to_string()))
+            .collect();
+        let expected = [("99", "1"), ("99", "2"), ("98", "1"), ("97", "1")];
+        assert_eq!(
+            bids,
+            expected
+                .iter()
+                .map(|(price, qty)| (price.to_string(), qty.to_string()))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(orderbook.asks().count(), 1);
+    }
+
+    #[test]
+    fn amend_order() {
+        let btc_asset = Asset::BTC;
+        let usd_asset = Asset::USD;
+        let mut orderbook = Orderbook::new(btc_asset, usd_asset);
+        let limit_order = orders::new_limit_order_request(
+            btc_asset,
+            usd_asset,
+            OrderSide::Bid,
+            bigdec("41711.760112"),
+            bigdec("0.15"),
+            SystemTime::now(),
+        );
+
+        let mut results = orderbook.process_order(limit_order);
+        assert_eq!(results.len(), 1);
+
+        if let Success::Accepted {
+            order_id,
+            order_asset: _,
+            price_asset: _,
+            price: _,
+            collared_from: _,
+            order_type: _,
+            side: _,
+            qty: _,
+            ts: _,
+            liquidation: _,
+        } = results
+            .pop()
+            .expect("expected a Result")
+            .expect("this should be Success")
+        {
+            let amend_order = orders::amend_order_request(
+                order_id,
+                OrderSide::Bid,
+                bigdec("40000.00"),
+                bigdec("0.16"),
+                SystemTime::now(),
+            );
+
+            let mut results2 = orderbook.process_order(amend_order);
+            assert_eq!(results2.len(), 1);
+
+            let order = orderbook.bid_queue.peek().unwrap();
+            assert_eq!(order.order_id, order_id);
+            assert_eq!(order.price, bigdec("40000.00"));
+            assert_eq!(order.qty, bigdec("0.16"));
+
+            if let Success::Amended {
+                order_id: _,
+                price,
+                qty,
+                prev_price: _,
+                prev_qty: _,
+                priority_kept: _,
+                ts: _,
+            } = results2
+                .pop()
+                .expect("expected a Result")
+                .expect("this should be Success")
+            {
+                assert_eq!(price, bigdec("40000.00"));
+                assert_eq!(qty, bigdec("0.16"));
+            }
+        }
+    }
+
+    #[test]
+    fn amend_single_field() {
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        let mut bid = |price: &str| {
+            let order = orders::new_limit_order_request(
+                Asset::BTC,
+                Asset::USD,
+                OrderSide::Bid,
+                bigdec(price),
+                bigdec("1"),
+                SystemTime::now(),
+            );
+            let order_id = order.order_id();
+            orderbook.process_order(order);
+            order_id
+        };
+        let first = bid("100");
+        let second = bid("100");
+
+        // reducing size keeps the place in the queue
+        let results = orderbook.process_order(orders::amend_order_qty_request(
+            first,
+            OrderSide::Bid,
+            bigdec("0.5"),
+            SystemTime::now(),
+        ));
+        match results.as_slice() {
+            [Ok(Success::Amended { price, qty, .. })] => {
+                assert_eq!(*price, bigdec("100"));
+                assert_eq!(*qty, bigdec("0.5"));
+            }
+            _ => panic!("unexpected events"),
+        }
+        assert_eq!(orderbook.bid_queue.peek().unwrap().order_id, first);
+
+        // increasing size moves the order behind the level
+        orderbook.process_order(orders::amend_order_qty_request(
+            first,
+            OrderSide::Bid,
+            bigdec("2"),
+            SystemTime::now(),
+        ));
+        assert_eq!(orderbook.bid_queue.peek().unwrap().order_id, second);
+
+        let results = orderbook.process_order(orders::amend_order_price_request(
+            first,
+            OrderSide::Bid,
+            bigdec("101"),
+            SystemTime::now(),
+        ));
+        match results.as_slice() {
+            [Ok(Success::Amended { price, qty, .. })] => {
+                assert_eq!(*price, bigdec("101"));
+                assert_eq!(*qty, bigdec("2"));
+            }
+            _ => panic!("unexpected events"),
+        }
+        assert_eq!(orderbook.bid_queue.peek().unwrap().order_id, first);
+
+        let empty = OrderRequest::AmendOrder {
+            id: first,
+            side: OrderSide::Bid,
+            price: None,
+            qty: None,
+            ts: SystemTime::now(),
+        };
+        match orderbook.process_order(empty).as_slice() {
+            [Err(Failed::ValidationFailed(_))] => (),
+            _ => panic!("unexpected events"),
+        }
+    }
+
+    #[test]
+    fn event_timestamps() {
+        use super::super::clock::ManualClock;
+        use std::time::Duration;
+
+        let start = SystemTime::now();
+        let clock = Arc::new(ManualClock::new(start));
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        orderbook.set_clock(Box::new(clock.clone()));
+        let order = |side| {
+            orders::new_limit_order_request(
+                Asset::BTC,
+                Asset::USD,
+                side,
+                bigdec("100"),
+                bigdec("1"),
+                SystemTime::now(),
+            )
+        };
+
+        orderbook.process_order(order(OrderSide::Ask));
+        clock.advance(Duration::from_secs(1));
+        let stamps: Vec<SystemTime> = orderbook
+            .process_order(order(OrderSide::Bid))
+            .into_iter()
+            .map(|result| match result {
+                Ok(Success::Accepted { ts, .. }) | Ok(Success::Filled { ts, .. }) => ts,
+                _ => panic!("unexpected events"),
+            })
+            .collect();
+
+        // accepted, filled, filled, one clock reading apart by a nanosecond each
+        let now = start + Duration::from_secs(1);
+        assert_eq!(
+            stamps,
+            vec![
+                now,
+                now + Duration::from_nanos(1),
+                now + Duration::from_nanos(2)
+            ]
+        );
+    }
+
+    #[test]
+    fn liquidation_priority() {
+        let btc_asset = Asset::BTC;
+        let usd_asset = Asset::USD;
+        let mut orderbook = Orderbook::new(btc_asset, usd_asset);
+        orderbook.set_liquidation_priority(true);
+
+        let regular = orders::new_limit_order_request(
+            btc_asset,
+            usd_asset,
+            OrderSide::Ask,
+            bigdec("1.01"),
+            bigdec("0.5"),
+            SystemTime::now(),
+        );
+        let liquidation = orders::new_limit_order_request(
+            btc_asset,
+            usd_asset,
+            OrderSide::Ask,
+            bigdec("1.01"),
+            bigdec("0.5"),
+            SystemTime::now(),
+        )
+        .with_liquidation_flag();
+        let liquidation_id = liquidation.order_id();
+        orderbook.process_order(regular);
+        orderbook.process_order(liquidation);
+
+        let results = orderbook.process_order(orders::new_market_order_request(
+            btc_asset,
+            usd_asset,
+            OrderSide::Bid,
+            bigdec("0.5"),
+            SystemTime::now(),
+        ));
+        assert_eq!(results.len(), 3);
+        match &results[2] {
+            Ok(Success::Filled {
+                order_id,
+                liquidation: true,
+                ..
+            }) => assert_eq!(*order_id, liquidation_id),
+            _ => panic!("unexpected events"),
+        }
+    }
+
+    #[test]
+    fn fills_of_a_trade_linked() {
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        let limit = |side| {
+            orders::new_limit_order_request(
+                Asset::BTC,
+                Asset::USD,
+                side,
+                bigdec("100"),
+                bigdec("1"),
+                SystemTime::now(),
+            )
+        };
+        let ask = limit(OrderSide::Ask);
+        let bid = limit(OrderSide::Bid);
+        let (ask_id, bid_id) = (ask.order_id(), bid.order_id());
+        orderbook.process_order(ask);
+
+        let results = orderbook.process_order(bid);
+        match results.as_slice() {
+            [Ok(Success::Accepted { .. }), Ok(Success::Filled {
+                order_id: taker,
+                trade_id,
+                counterparty: taker_counterparty,
+                ..
+            }), Ok(Success::Filled {
+                order_id: maker,
+                trade_id: maker_trade_id,
+                counterparty: maker_counterparty,
+                ..
+            })] => {
+                assert_eq!((*taker, *taker_counterparty), (bid_id, ask_id));
+                assert_eq!((*maker, *maker_counterparty), (ask_id, bid_id));
+                assert_eq!(trade_id, maker_trade_id);
+            }
+            _ => panic!("unexpected events"),
+        }
+    }
+
+    #[test]
+    fn pro_rata_matching() {
+        let btc_asset = Asset::BTC;
+        let usd_asset = Asset::USD;
+        let mut orderbook = Orderbook::new(btc_asset, usd_asset);
+        orderbook.set_match_policy(Box::new(ProRata));
+
+        for qty in ["1.0", "3.0"] {
+            orderbook.process_order(orders::new_limit_order_request(
+                btc_asset,
+                usd_asset,
+                OrderSide::Ask,
+                bigdec("1.01"),
+                bigdec(qty),
+                SystemTime::now(),
+            ));
+        }
+
+        let results = orderbook.process_order(orders::new_market_order_request(
+            btc_asset,
+            usd_asset,
+            OrderSide::Bid,
+            bigdec("2.0"),
+            SystemTime::now(),
+        ));
+        let filled: Vec<(OrderSide, BigDecimal)> = results
+            .iter()
+            .filter_map(|result| match result {
+                Ok(Success::PartiallyFilled { side, qty, .. })
+                | Ok(Success::Filled { side, qty, .. }) => Some((*side, qty.clone())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            filled,
+            vec![
+                (OrderSide::Bid, bigdec("0.5")),
+                (OrderSide::Ask, bigdec("0.5")),
+                (OrderSide::Bid, bigdec("1.5")),
+                (OrderSide::Ask, bigdec("1.5")),
+            ]
+        );
+        assert_eq!(orderbook.ask_queue.len(), 2);
+    }
+
+    #[test]
+    fn match_policy_allocating_nothing() {
+        struct Stingy;
+        impl MatchPolicy<Asset> for Stingy {
+            fn allocate(&mut self, level: &[Order<Asset>], _qty: &BigDecimal) -> Vec<BigDecimal> {
+                vec![BigDecimal::zero(); level.len()]
+            }
+        }
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        orderbook.set_match_policy(Box::new(Stingy));
+        orderbook.process_order(orders::new_limit_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Ask,
+            bigdec("100"),
+            bigdec("1"),
+            SystemTime::now(),
+        ));
+
+        let market = orders::new_market_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Bid,
+            bigdec("1"),
+            SystemTime::now(),
+        );
+        assert!(matches!(
+            orderbook.process_order(market).as_slice(),
+            [Ok(Success::Accepted { .. }), Err(Failed::NoMatch(_))]
+        ));
+
+        // the crossing rest neither trades nor rests
+        let limit = orders::new_limit_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Bid,
+            bigdec("101"),
+            bigdec("1"),
+            SystemTime::now(),
+        );
+        assert!(matches!(
+            orderbook.process_order(limit).as_slice(),
+            [Ok(Success::Accepted { .. }), Ok(Success::Cancelled { .. })]
+        ));
+        assert_eq!(orderbook.best_bid(), None);
+        assert_eq!(orderbook.ask_queue.len(), 1);
+    }
+
+    #[test]
+    fn match_algorithm_config() {
+        let config = BookConfig {
+            match_algorithm: MatchAlgorithm::ProRata,
+            ..BookConfig::default()
+        };
+        let mut orderbook = Orderbook::new_with_config(Asset::BTC, Asset::USD, config);
+        for qty in ["1.0", "3.0"] {
+            orderbook.process_order(orders::new_limit_order_request(
+                Asset::BTC,
+                Asset::USD,
+                OrderSide::Ask,
+                bigdec("1.01"),
+                bigdec(qty),
+                SystemTime::now(),
+            ));
+        }
+
+        // kept by snapshots
+        let json = serde_json::to_string(&orderbook).unwrap();
+        let mut restored: Orderbook<Asset> = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.config().match_algorithm, MatchAlgorithm::ProRata);
+        let results = restored.process_order(orders::new_market_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Bid,
+            bigdec("2.0"),
+            SystemTime::now(),
+        ));
+        let makers: Vec<BigDecimal> = results
+            .iter()
+            .filter_map(|result| match result {
+                Ok(Success::PartiallyFilled {
+                    role: LiquidityRole::Maker,
+                    qty,
+                    ..
+                }) => Some(qty.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(makers, vec![bigdec("0.5"), bigdec("1.5")]);
+    }
+
+    #[test]
+    fn deep_sweep() {
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        let levels = 20_000;
+        for price in 1..=levels {
+            orderbook.process_order(orders::new_limit_order_request(
+                Asset::BTC,
+                Asset::USD,
+                OrderSide::Ask,
+                BigDecimal::from(price),
+                bigdec("1"),
+                SystemTime::now(),
+            ));
+        }
+
+        // every level in one request, as a limit order resting the rest
+        let results = orderbook.process_order(orders::new_limit_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Bid,
+            BigDecimal::from(levels),
+            BigDecimal::from(levels + 1),
+            SystemTime::now(),
+        ));
+        assert_eq!(results.len(), 1 + 2 * levels as usize);
+        assert_eq!(orderbook.best_ask(), None);
+        assert_eq!(orderbook.best_bid(), Some(&BigDecimal::from(levels)));
+        assert_eq!(orderbook.last_trade_price(), Some(&BigDecimal::from(levels)));
+    }
+
+    #[test]
+    fn depth_limit() {
+        let ask = |price: &str| {
+            orders::new_limit_order_request(
+                Asset::BTC,
+                Asset::USD,
+                OrderSide::Ask,
+                bigdec(price),
+                bigdec("1"),
+                SystemTime::now(),
+            )
+        };
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        orderbook.set_depth_limit(Some(DepthLimit {
+            max_levels: Some(2),
+            max_orders: None,
+            policy: DepthLimitPolicy::RejectNew,
+        }));
+        orderbook.process_order(ask("101"));
+        orderbook.process_order(ask("102"));
+        // joining an existing level is fine
+        let last = ask("102");
+        let last_id = last.order_id();
+        assert_eq!(orderbook.process_order(last).len(), 1);
+
+        let far = ask("103");
+        let far_id = far.order_id();
+        match orderbook.process_order(far).as_slice() {
+            [Ok(Success::Accepted { .. }), Err(Failed::DepthLimitExceeded(order_id))] => {
+                assert_eq!(*order_id, far_id)
+            }
+            _ => panic!("unexpected events"),
+        }
+        assert_eq!(orderbook.ask_queue.len(), 3);
+
+        orderbook.set_depth_limit(Some(DepthLimit {
+            max_levels: None,
+            max_orders: Some(3),
+            policy: DepthLimitPolicy::EvictFarthest,
+        }));
+        match orderbook.process_order(ask("100")).as_slice() {
+            [Ok(Success::Accepted { .. }), Ok(Success::Cancelled { order_id, .. })] => {
+                assert_eq!(*order_id, last_id)
+            }
+            _ => panic!("unexpected events"),
+        }
+        assert_eq!(orderbook.ask_queue.len(), 3);
+        assert_eq!(orderbook.ask_queue.level_count(), 3);
+
+        // no room made by evicting the new order itself
+        let farther = ask("104");
+        let farther_id = farther.order_id();
+        match orderbook.process_order(farther).as_slice() {
+            [Ok(Success::Accepted { .. }), Err(Failed::DepthLimitExceeded(order_id))] => {
+                assert_eq!(*order_id, farther_id)
+            }
+            _ => panic!("unexpected events"),
+        }
+        assert!(orderbook.ask_queue.get(farther_id).is_none());
+        assert_eq!(orderbook.ask_queue.len(), 3);
+    }
+
+    #[test]
+    fn dust_remainder_cancelled() {
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        orderbook.set_dust_threshold(Some(DustThreshold {
+            min_qty: None,
+            min_notional: Some(bigdec("1")),
+        }));
+        let ask = orders::new_limit_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Ask,
+            bigdec("100"),
+            bigdec("1"),
+            SystemTime::now(),
+        );
+        let ask_id = ask.order_id();
+        orderbook.process_order(ask);
+
+        let bid = |qty: &str| {
+            orders::new_market_order_request(
+                Asset::BTC,
+                Asset::USD,
+                OrderSide::Bid,
+                bigdec(qty),
+                SystemTime::now(),
+            )
+        };
+        // remainder worth 10 stays
+        assert_eq!(orderbook.process_order(bid("0.9")).len(), 3);
+        assert_eq!(orderbook.ask_queue.len(), 1);
+
+        match orderbook.process_order(bid("0.095")).as_slice() {
+            [Ok(Success::Accepted { .. }), Ok(Success::Filled { .. }), Ok(Success::PartiallyFilled { .. }), Ok(Success::DustCancelled { order_id, qty, .. })] =>
+            {
+                assert_eq!(*order_id, ask_id);
+                assert_eq!(*qty, bigdec("0.005"));
+            }
+            _ => panic!("unexpected events"),
+        }
+        assert!(orderbook.ask_queue.is_empty());
+    }
+
+    #[test]
+    fn aggressor_side() {
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        orderbook.process_order(orders::new_limit_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Bid,
+            bigdec("100"),
+            bigdec("2"),
+            SystemTime::now(),
+        ));
+        let results = orderbook.process_order(orders::new_market_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Ask,
+            bigdec("1"),
+            SystemTime::now(),
+        ));
+
+        let fills: Vec<(OrderSide, OrderSide, LiquidityRole)> = results
+            .iter()
+            .filter_map(|result| match result {
+                Ok(Success::Filled {
+                    side,
+                    aggressor,
+                    role,
+                    ..
+                })
+                | Ok(Success::PartiallyFilled {
+                    side,
+                    aggressor,
+                    role,
+                    ..
+                }) => Some((*side, *aggressor, *role)),
+                _ => None,
+            })
+            .collect();
+        // seller initiated trade
+        assert_eq!(
+            fills,
+            vec![
+                (OrderSide::Ask, OrderSide::Ask, LiquidityRole::Taker),
+                (OrderSide::Bid, OrderSide::Ask, LiquidityRole::Maker)
+            ]
+        );
+    }
+
+    #[test]
+    fn batch_amends() {
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        let mut quote_ids = vec![];
+        for price in ["99", "98", "97"] {
+            let quote = orders::new_limit_order_request(
+                Asset::BTC,
+                Asset::USD,
+                OrderSide::Bid,
+                bigdec(price),
+                bigdec("1"),
+                SystemTime::now(),
+            );
+            quote_ids.push(quote.order_id());
+            orderbook.process_order(quote);
+        }
+
+        let amend = |id, price: Option<&str>, qty: Option<&str>| AmendOrder {
+            id,
+            side: OrderSide::Bid,
+            price: price.map(bigdec),
+            qty: qty.map(bigdec),
+            ts: SystemTime::now(),
+        };
+        let results = orderbook.process_amends(vec![
+            amend(quote_ids[2], Some("100"), None),
+            amend(Uuid::new_v4(), Some("100"), None),
+            amend(quote_ids[0], None, Some("0.5")),
+            amend(quote_ids[1], None, None),
+            amend(quote_ids[1], Some("100.5"), Some("2")),
+        ]);
+
+        match results.as_slice() {
+            [Ok(Success::Amended {
+                priority_kept: false,
+                ..
+            }), Err(Failed::OrderNotFound(_)), Ok(Success::Amended {
+                priority_kept: true,
+                ..
+            }), Err(Failed::ValidationFailed(_)), Ok(Success::Amended { .. })] => (),
+            _ => panic!("unexpected events"),
+        }
+        let prices: Vec<BigDecimal> = orderbook
+            .bid_queue
+            .ranked()
+            .into_iter()
+            .map(|(order, _, _)| order.price.clone())
+            .collect();
+        assert_eq!(prices, vec![bigdec("100.5"), bigdec("100"), bigdec("99")]);
+        assert_eq!(orderbook.bid_queue.peek().unwrap().qty, bigdec("2"));
+    }
+
+    #[test]
+    fn tick_size() {
+        let config = BookConfig {
+            price_scale: PriceScale::from_tick(&bigdec("0.25")).unwrap(),
+            ..BookConfig::default()
+        };
+        let mut orderbook = Orderbook::new_with_config(Asset::BTC, Asset::USD, config);
+        let bid = |price: &str| {
+            orders::new_limit_order_request(
+                Asset::BTC,
+                Asset::USD,
+                OrderSide::Bid,
+                bigdec(price),
+                bigdec("1"),
+                SystemTime::now(),
+            )
+        };
+
+        match orderbook.process_order(bid("100.1")).as_slice() {
+            [Err(Failed::ValidationFailed(reason))] => assert_eq!(reason, ERR_OFF_TICK),
+            _ => panic!("unexpected events"),
+        }
+        let resting = bid("100.25");
+        let resting_id = resting.order_id();
+        orderbook.process_order(resting);
+        // same level however the price is written
+        orderbook.process_order(bid("100.250"));
+        assert_eq!(orderbook.bid_queue.level_count(), 1);
+        assert_eq!(orderbook.bid_queue.peek_level().len(), 2);
+
+        let amend = orders::amend_order_price_request(
+            resting_id,
+            OrderSide::Bid,
+            bigdec("100.3"),
+            SystemTime::now(),
+        );
+        assert!(orderbook.process_order(amend)[0].is_err());
+    }
+
+    #[test]
+    fn qty_limits() {
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        orderbook
+            .set_qty_limits(Some(QtyLimits {
+                lot_size: Some(bigdec("0.01")),
+                min_qty: Some(bigdec("0.1")),
+                max_qty: Some(bigdec("10")),
+            }))
+            .unwrap();
+        let bid = |qty: &str| {
+            orders::new_limit_order_request(
+                Asset::BTC,
+                Asset::USD,
+                OrderSide::Bid,
+                bigdec("100"),
+                bigdec(qty),
+                SystemTime::now(),
+            )
+        };
+        let reason = |results: OrderProcessingResult<Asset>| match results.as_slice() {
+            [Err(Failed::ValidationFailed(reason))] => reason.clone(),
+            _ => panic!("unexpected events"),
+        };
+
+        assert_eq!(reason(orderbook.process_order(bid("0.125"))), ERR_OFF_LOT);
+        assert_eq!(reason(orderbook.process_order(bid("0.05"))), ERR_QTY_BELOW_MIN);
+        assert_eq!(reason(orderbook.process_order(bid("10.01"))), ERR_QTY_ABOVE_MAX);
+        let market = orders::new_market_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Ask,
+            bigdec("0.333"),
+            SystemTime::now(),
+        );
+        assert_eq!(reason(orderbook.process_order(market)), ERR_OFF_LOT);
+
+        let resting = bid("0.12");
+        let resting_id = resting.order_id();
+        assert!(orderbook.process_order(resting)[0].is_ok());
+        let amend = orders::amend_order_qty_request(
+            resting_id,
+            OrderSide::Bid,
+            bigdec("0.123"),
+            SystemTime::now(),
+        );
+        assert_eq!(reason(orderbook.process_order(amend)), ERR_OFF_LOT);
+
+        // the limits are part of the checkpoint
+        let restored: Orderbook<Asset> =
+            serde_json::from_str(&serde_json::to_string(&orderbook).unwrap()).unwrap();
+        assert_eq!(restored.qty_limits(), orderbook.qty_limits());
+    }
+
+    #[test]
+    fn qty_limits_not_positive() {
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        let lot = |lot: &str| QtyLimits {
+            lot_size: Some(bigdec(lot)),
+            ..QtyLimits::default()
+        };
+        assert_eq!(orderbook.set_qty_limits(Some(lot("0"))), Err("lot size not positive"));
+        assert_eq!(orderbook.set_qty_limits(Some(lot("-1"))), Err("lot size not positive"));
+        let min = QtyLimits {
+            min_qty: Some(bigdec("0")),
+            ..QtyLimits::default()
+        };
+        assert_eq!(orderbook.set_qty_limits(Some(min)), Err("quantity limit not positive"));
+        assert_eq!(orderbook.qty_limits(), None);
+
+        // orders still go through unchecked
+        let bid = orders::new_limit_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Bid,
+            bigdec("100"),
+            bigdec("0.3"),
+            SystemTime::now(),
+        );
+        assert!(orderbook.process_order(bid)[0].is_ok());
+
+        // nor do snapshots bring them in
+        let mut json = serde_json::to_value(&orderbook).unwrap();
+        json["qty_limits"] = serde_json::to_value(lot("0")).unwrap();
+        assert!(serde_json::from_value::<Orderbook<Asset>>(json).is_err());
+    }
+
+    #[test]
+    fn price_band_collar() {
+        let config = BookConfig {
+            price_scale: PriceScale::from_tick(&bigdec("0.25")).unwrap(),
+            ..BookConfig::default()
+        };
+        let mut orderbook = Orderbook::new_with_config(Asset::BTC, Asset::USD, config);
+        orderbook.set_reference_price(Some(bigdec("100")));
+        orderbook.set_price_band(Some(PriceBand {
+            width: bigdec("0.05"),
+            mode: BandMode::Collar,
+            reference: BandReference::LastTrade,
+        }));
+        let limit = |side, price: &str| {
+            orders::new_limit_order_request(
+                Asset::BTC,
+                Asset::USD,
+                side,
+                bigdec(price),
+                bigdec("1"),
+                SystemTime::now(),
+            )
+        };
+
+        match orderbook.process_order(limit(OrderSide::Bid, "110")).as_slice() {
+            [Ok(Success::Accepted {
+                price,
+                collared_from,
+                ..
+            })] => {
+                assert_eq!(*price, Some(bigdec("105")));
+                assert_eq!(*collared_from, Some(bigdec("110")));
+            }
+            _ => panic!("unexpected events"),
+        }
+        // within the band, untouched
+        match orderbook.process_order(limit(OrderSide::Ask, "96")).as_slice() {
+            [
+                Ok(Success::Accepted { collared_from, .. }),
+                Ok(Success::Filled { price, .. }),
+                ..
+            ] => {
+                assert_eq!(*collared_from, None);
+                assert_eq!(*price, bigdec("105"));
+            }
+            _ => panic!("unexpected events"),
+        }
+        assert_eq!(orderbook.reference_price(), Some(&bigdec("105")));
+
+        // rejected outright in reject mode, edge rounded down onto the tick
+        orderbook.set_price_band(Some(PriceBand {
+            width: bigdec("0.05"),
+            mode: BandMode::Reject,
+            reference: BandReference::LastTrade,
+        }));
+        match orderbook.process_order(limit(OrderSide::Bid, "110.5")).as_slice() {
+            [Err(Failed::ValidationFailed(reason))] => assert_eq!(reason, ERR_OUTSIDE_BAND),
+            _ => panic!("unexpected events"),
+        }
+        assert!(orderbook.process_order(limit(OrderSide::Bid, "110.25"))[0].is_ok());
+    }
+
+    #[test]
+    fn price_band_on_mark() {
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        orderbook.set_price_band(Some(PriceBand {
+            width: bigdec("0.1"),
+            mode: BandMode::Reject,
+            reference: BandReference::Mark,
+        }));
+        let limit = |side, price: &str| {
+            orders::new_limit_order_request(
+                Asset::BTC,
+                Asset::USD,
+                side,
+                bigdec(price),
+                bigdec("1"),
+                SystemTime::now(),
+            )
+        };
+
+        // no mark yet, nothing to check against
+        assert!(orderbook.process_order(limit(OrderSide::Ask, "150"))[0].is_ok());
+        orderbook.set_reference_price(Some(bigdec("100")));
+        match orderbook.process_order(limit(OrderSide::Bid, "111")).as_slice() {
+            [Err(Failed::ValidationFailed(reason))] => assert_eq!(reason, ERR_OUTSIDE_BAND),
+            _ => panic!("unexpected events"),
+        }
+        assert!(orderbook.process_order(limit(OrderSide::Ask, "109"))[0].is_ok());
+        assert!(orderbook.process_order(limit(OrderSide::Bid, "109"))[1].is_ok());
+        // the trade at 109 left the mark where it was
+        assert_eq!(orderbook.last_trade_price(), Some(&bigdec("109")));
+        assert_eq!(orderbook.reference_price(), Some(&bigdec("100")));
+        match orderbook.process_order(limit(OrderSide::Ask, "89")).as_slice() {
+            [Err(Failed::ValidationFailed(reason))] => assert_eq!(reason, ERR_OUTSIDE_BAND),
+            _ => panic!("unexpected events"),
+        }
+
+        orderbook.set_reference_price(Some(bigdec("90")));
+        assert!(orderbook.process_order(limit(OrderSide::Ask, "89"))[0].is_ok());
+    }
+
+    #[test]
+    fn trading_status_phases() {
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        let limit = |side, price: &str| {
+            orders::new_limit_order_request(
+                Asset::BTC,
+                Asset::USD,
+                side,
+                bigdec(price),
+                bigdec("1"),
+                SystemTime::now(),
+            )
+        };
+        let rejection = |results: OrderProcessingResult<Asset>| match results.as_slice() {
+            [Err(Failed::ValidationFailed(reason))] => reason.clone(),
+            _ => panic!("unexpected events"),
+        };
+
+        // a new listing builds its book without trading
+        orderbook.set_trading_status(TradingStatus::PostOnly);
+        let ask = limit(OrderSide::Ask, "101");
+        let ask_id = ask.order_id();
+        assert!(orderbook.process_order(ask)[0].is_ok());
+        assert!(orderbook.process_order(limit(OrderSide::Bid, "100"))[0].is_ok());
+        assert_eq!(
+            rejection(orderbook.process_order(limit(OrderSide::Bid, "101"))),
+            ERR_POST_ONLY
+        );
+        let market = orders::new_market_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Bid,
+            bigdec("1"),
+            SystemTime::now(),
+        );
+        assert_eq!(rejection(orderbook.process_order(market)), ERR_POST_ONLY);
+        let amend = orders::amend_order_qty_request(
+            ask_id,
+            OrderSide::Ask,
+            bigdec("2"),
+            SystemTime::now(),
+        );
+        assert!(orderbook.process_order(amend)[0].is_ok());
+
+        // a delisting winds down
+        orderbook.set_trading_status(TradingStatus::CancelOnly);
+        assert_eq!(
+            rejection(orderbook.process_order(limit(OrderSide::Bid, "99"))),
+            ERR_CANCEL_ONLY
+        );
+        let amend = AmendOrder {
+            id: ask_id,
+            side: OrderSide::Ask,
+            price: None,
+            qty: Some(bigdec("1")),
+            ts: SystemTime::now(),
+        };
+        assert_eq!(rejection(orderbook.process_amends(vec![amend])), ERR_CANCEL_ONLY);
+
+        orderbook.set_trading_status(TradingStatus::Suspended);
+        let cancel = orders::limit_order_cancel_request(ask_id, OrderSide::Ask);
+        assert_eq!(
+            rejection(orderbook.process_order(cancel.clone())),
+            ERR_MARKET_SUSPENDED
+        );
+
+        // carried by snapshots
+        let json = serde_json::to_string(&orderbook).unwrap();
+        let mut restored: Orderbook<Asset> = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.trading_status(), TradingStatus::Suspended);
+        restored.set_trading_status(TradingStatus::CancelOnly);
+        assert!(matches!(
+            restored.process_order(cancel).as_slice(),
+            [Ok(Success::Cancelled { .. })]
+        ));
+    }
+
+    #[test]
+    fn halt_and_resume() {
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        let limit = |price: &str| {
+            orders::new_limit_order_request(
+                Asset::BTC,
+                Asset::USD,
+                OrderSide::Bid,
+                bigdec(price),
+                bigdec("1"),
+                SystemTime::now(),
+            )
+        };
+        let resting = limit("100");
+        let resting_id = resting.order_id();
+        assert!(orderbook.process_order(resting)[0].is_ok());
+        orderbook.set_trading_status(TradingStatus::PostOnly);
+
+        assert!(orderbook.halt());
+        assert!(!orderbook.halt());
+        assert!(orderbook.is_halted());
+        assert!(matches!(
+            orderbook.process_order(limit("99")).as_slice(),
+            [Err(Failed::ValidationFailed(reason))] if reason == ERR_MARKET_HALTED
+        ));
+        let amend = orders::amend_order_qty_request(
+            resting_id,
+            OrderSide::Bid,
+            bigdec("2"),
+            SystemTime::now(),
+        );
+        assert_eq!(orderbook.validate(&amend), Err(ERR_MARKET_HALTED));
+
+        // carried by snapshots and back to the status it had
+        let json = serde_json::to_string(&orderbook).unwrap();
+        let mut restored: Orderbook<Asset> = serde_json::from_str(&json).unwrap();
+        assert!(restored.is_halted());
+        assert!(restored.resume());
+        assert!(!restored.resume());
+        assert_eq!(restored.trading_status(), TradingStatus::PostOnly);
+        assert!(restored.process_order(amend)[0].is_ok());
+
+        restored.set_cancel_only();
+        assert_eq!(restored.validate(&limit("98")), Err(ERR_CANCEL_ONLY));
+        let cancel = orders::limit_order_cancel_request(resting_id, OrderSide::Bid);
+        assert!(restored.halt());
+        assert!(matches!(
+            restored.process_order(cancel).as_slice(),
+            [Ok(Success::Cancelled { .. })]
+        ));
+
+        // session phase names
+        let phases: Vec<TradingStatus> =
+            serde_json::from_str(r#"["pre_open", "open", "closed"]"#).unwrap();
+        assert_eq!(
+            phases,
+            vec![TradingStatus::PostOnly, TradingStatus::Trading, TradingStatus::Suspended]
+        );
+    }
+
+    #[test]
+    fn call_auction() {
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        orderbook.set_trading_status(TradingStatus::Auction);
+        let mut submit = |side, price: &str, qty: &str| {
+            let order = orders::new_limit_order_request(
+                Asset::BTC,
+                Asset::USD,
+                side,
+                bigdec(price),
+                bigdec(qty),
+                SystemTime::now(),
+            );
+            let order_id = order.order_id();
+            // crossing orders rest without matching
+            assert!(matches!(
+                orderbook.process_order(order).as_slice(),
+                [Ok(Success::Accepted { .. })]
+            ));
+            order_id
+        };
+        let bid_103 = submit(OrderSide::Bid, "103", "2");
+        let bid_102 = submit(OrderSide::Bid, "102", "3");
+        submit(OrderSide::Bid, "100", "5");
+        let ask_99 = submit(OrderSide::Ask, "99", "1");
+        let ask_101 = submit(OrderSide::Ask, "101", "4");
+        submit(OrderSide::Ask, "104", "5");
+
+        let market = orders::new_market_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Bid,
+            bigdec("1"),
+            SystemTime::now(),
+        );
+        assert_eq!(orderbook.validate(&market), Err(ERR_IN_AUCTION));
+
+        let indicative = orderbook.indicative_price().unwrap();
+        assert_eq!(indicative.price, bigdec("101"));
+        assert_eq!(indicative.volume, bigdec("5"));
+
+        let results = orderbook.uncross();
+        let mut filled: Vec<(Uuid, BigDecimal)> = vec![];
+        for result in &results {
+            match result {
+                Ok(Success::PartiallyFilled {
+                    order_id,
+                    price,
+                    qty,
+                    role,
+                    ..
+                })
+                | Ok(Success::Filled {
+                    order_id,
+                    price,
+                    qty,
+                    role,
+                    ..
+                }) => {
+                    assert_eq!(*price, bigdec("101"));
+                    assert_eq!(*role, LiquidityRole::Maker);
+                    match filled.iter_mut().find(|(id, _)| id == order_id) {
+                        Some((_, total)) => *total += qty,
+                        None => filled.push((*order_id, qty.clone())),
+                    }
+                }
+                _ => panic!("unexpected event"),
+            }
+        }
+        assert_eq!(
+            filled,
+            vec![
+                (bid_103, bigdec("2")),
+                (ask_99, bigdec("1")),
+                (ask_101, bigdec("4")),
+                (bid_102, bigdec("3")),
+            ]
+        );
+        assert!(matches!(results[1], Ok(Success::Filled { .. })));
+
+        // continuous trading from there on
+        assert_eq!(orderbook.trading_status(), TradingStatus::Trading);
+        assert_eq!(orderbook.current_spread(), Some((bigdec("100"), bigdec("104"))));
+        assert_eq!(orderbook.last_trade_price(), Some(&bigdec("101")));
+        assert_eq!(orderbook.indicative_price(), None);
+        assert!(orderbook.uncross().is_empty());
+    }
+
+    #[test]
+    fn stop_orders() {
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        for (price, qty) in [("101", "1"), ("102", "1"), ("103", "2")] {
+            orderbook.process_order(orders::new_limit_order_request(
+                Asset::BTC,
+                Asset::USD,
+                OrderSide::Ask,
+                bigdec(price),
+                bigdec(qty),
+                SystemTime::now(),
+            ));
+        }
+        let market_bid = || {
+            orders::new_market_order_request(
+                Asset::BTC,
+                Asset::USD,
+                OrderSide::Bid,
+                bigdec("1"),
+                SystemTime::now(),
+            )
+        };
+
+        let stop = orders::new_stop_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Bid,
+            bigdec("102"),
+            bigdec("1"),
+            SystemTime::now(),
+        );
+        let stop_id = stop.order_id();
+        match orderbook.process_order(stop).as_slice() {
+            [Ok(Success::StopAccepted { price: None, .. })] => (),
+            _ => panic!("unexpected events"),
+        }
+        let stop_limit = orders::new_stop_limit_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Bid,
+            bigdec("101"),
+            bigdec("101"),
+            bigdec("1"),
+            SystemTime::now(),
+        );
+        let stop_limit_id = stop_limit.order_id();
+        orderbook.process_order(stop_limit);
+        assert_eq!(orderbook.stop_count(), 2);
+
+        // trade at 101 releases the stop-limit, left resting at its limit
+        let results = orderbook.process_order(market_bid());
+        match &results[3..] {
+            [Ok(Success::Triggered {
+                order_id,
+                last_price,
+                ..
+            }), Ok(Success::Accepted { .. })] => {
+                assert_eq!(*order_id, stop_limit_id);
+                assert_eq!(*last_price, bigdec("101"));
+            }
+            _ => panic!("unexpected events"),
+        }
+        assert!(orderbook.bid_queue.get(stop_limit_id).is_some());
+        assert!(orderbook.has_stop(stop_id));
+
+        // trade at 102 releases the stop, which takes the next level
+        let results = orderbook.process_order(market_bid());
+        match &results[3..] {
+            [Ok(Success::Triggered { order_id, .. }), Ok(Success::Accepted { .. }), Ok(Success::Filled { price, .. }), Ok(Success::PartiallyFilled { .. })] =>
+            {
+                assert_eq!(*order_id, stop_id);
+                assert_eq!(*price, bigdec("103"));
+            }
+            _ => panic!("unexpected events"),
+        }
+        assert_eq!(orderbook.stop_count(), 0);
+        assert_eq!(orderbook.last_trade_price(), Some(&bigdec("103")));
+
+        // pending stops can be cancelled, bad triggers are refused
+        let stop = orders::new_stop_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Ask,
+            bigdec("90"),
+            bigdec("1"),
+            SystemTime::now(),
+        );
+        let stop_id = stop.order_id();
+        orderbook.process_order(stop);
+        match orderbook
+            .process_order(orders::limit_order_cancel_request(stop_id, OrderSide::Ask))
+            .as_slice()
+        {
+            [Ok(Success::Cancelled { order_id, .. })] => assert_eq!(*order_id, stop_id),
+            _ => panic!("unexpected events"),
+        }
+        let bad = orders::new_stop_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Ask,
+            bigdec("0"),
+            bigdec("1"),
+            SystemTime::now(),
+        );
+        assert!(orderbook.process_order(bad)[0].is_err());
+    }
+
+    #[test]
+    fn time_in_force() {
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        let now = SystemTime::now();
+        let bid = |price: &str, qty: &str, tif: TimeInForce| {
+            orders::new_limit_order_request(
+                Asset::BTC,
+                Asset::USD,
+                OrderSide::Bid,
+                bigdec(price),
+                bigdec(qty),
+                now,
+            )
+            .with_time_in_force(tif)
+        };
+        orderbook.process_order(orders::new_limit_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Ask,
+            bigdec("101"),
+            bigdec("2"),
+            now,
+        ));
+
+        // FOK needing more than there is at its price is refused untouched
+        let fok = bid("101", "3", TimeInForce::FOK);
+        let fok_id = fok.order_id();
+        match orderbook.process_order(fok).as_slice() {
+            [Err(Failed::NoMatch(order_id))] => assert_eq!(*order_id, fok_id),
+            _ => panic!("unexpected events"),
+        }
+        assert_eq!(orderbook.ask_queue.len(), 1);
+
+        match orderbook.process_order(bid("101", "1.5", TimeInForce::FOK)).as_slice() {
+            [Ok(Success::Accepted { .. }), Ok(Success::Filled { .. }), Ok(Success::PartiallyFilled { .. })] => (),
+            _ => panic!("unexpected events"),
+        }
+
+        // IOC takes what is there and cancels the rest
+        let ioc = bid("101", "1.5", TimeInForce::IOC);
+        let ioc_id = ioc.order_id();
+        match orderbook.process_order(ioc).as_slice() {
+            [Ok(Success::Accepted { .. }), Ok(Success::PartiallyFilled { .. }), Ok(Success::Filled { .. }), Ok(Success::Cancelled { order_id, .. })] => {
+                assert_eq!(*order_id, ioc_id)
+            }
+            _ => panic!("unexpected events"),
+        }
+        assert!(orderbook.bid_queue.is_empty());
+
+        // GTD rests until an expiry sweep reaches its time
+        let expiry = now + std::time::Duration::from_secs(60);
+        let gtd = bid("100", "1", TimeInForce::GTD(expiry));
+        let gtd_id = gtd.order_id();
+        orderbook.process_order(gtd);
+        orderbook.process_order(bid("99", "1", TimeInForce::GTC));
+        assert!(orderbook.expire_orders(now).is_empty());
+        match orderbook.expire_orders(expiry).as_slice() {
+            [Ok(Success::Expired { order_id, .. })] => assert_eq!(*order_id, gtd_id),
+            _ => panic!("unexpected events"),
+        }
+        assert_eq!(orderbook.bid_queue.len(), 1);
+
+        match orderbook.process_order(bid("100", "1", TimeInForce::GTD(now))).as_slice() {
+            [Err(Failed::ValidationFailed(reason))] => assert_eq!(reason, ERR_BAD_EXPIRY),
+            _ => panic!("unexpected events"),
+        }
+    }
+
+    #[test]
+    fn market_order_remainder() {
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        orderbook.process_order(orders::new_limit_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Ask,
+            bigdec("101"),
+            bigdec("1"),
+            SystemTime::now(),
+        ));
+        let market_bid = || {
+            orders::new_market_order_request(
+                Asset::BTC,
+                Asset::USD,
+                OrderSide::Bid,
+                bigdec("3"),
+                SystemTime::now(),
+            )
+        };
+
+        orderbook.set_market_remainder_policy(MarketRemainderPolicy::RejectOrder);
+        match orderbook.process_order(market_bid()).as_slice() {
+            [Err(Failed::NoMatch(_))] => (),
+            _ => panic!("unexpected events"),
+        }
+        assert_eq!(orderbook.ask_queue.len(), 1);
+
+        orderbook.set_market_remainder_policy(MarketRemainderPolicy::CancelRemainder);
+        match orderbook.process_order(market_bid()).as_slice() {
+            [Ok(Success::Accepted { .. }), Ok(Success::PartiallyFilled { .. }), Ok(Success::Filled { .. }), Ok(Success::CancelledRemainder { unfilled_qty, .. })] => {
+                assert_eq!(*unfilled_qty, bigdec("2"))
+            }
+            _ => panic!("unexpected events"),
+        }
+
+        // nothing filled, nothing to cancel
+        match orderbook.process_order(market_bid()).as_slice() {
+            [Ok(Success::Accepted { .. }), Err(Failed::NoMatch(_))] => (),
+            _ => panic!("unexpected events"),
+        }
+    }
+
+    #[test]
+    fn market_order_protection() {
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        for price in ["100", "102", "110"].iter() {
+            orderbook.process_order(orders::new_limit_order_request(
+                Asset::BTC,
+                Asset::USD,
+                OrderSide::Ask,
+                bigdec(price),
+                bigdec("1"),
+                SystemTime::now(),
+            ));
+        }
+        let market_bid = |protection| {
+            orders::new_market_order_request(
+                Asset::BTC,
+                Asset::USD,
+                OrderSide::Bid,
+                bigdec("3"),
+                SystemTime::now(),
+            )
+            .with_protection(protection)
+        };
+
+        // 5% off the touch reaches 105, the ask at 110 is left alone
+        let results =
+            orderbook.process_order(market_bid(MarketProtection::Slippage(bigdec("0.05"))));
+        match results.last() {
+            Some(Ok(Success::CancelledRemainder { unfilled_qty, .. })) => {
+                assert_eq!(*unfilled_qty, bigdec("1"))
+            }
+            _ => panic!("unexpected events"),
+        }
+        assert_eq!(orderbook.ask_queue.len(), 1);
+
+        // nothing within the price, nothing fills
+        match orderbook
+            .process_order(market_bid(MarketProtection::Price(bigdec("105"))))
+            .as_slice()
+        {
+            [Ok(Success::Accepted { .. }), Err(Failed::NoMatch(_))] => (),
+            _ => panic!("unexpected events"),
+        }
+        assert_eq!(orderbook.ask_queue.len(), 1);
+
+        match orderbook
+            .process_order(market_bid(MarketProtection::Slippage(bigdec("-0.01"))))
+            .as_slice()
+        {
+            [Err(Failed::ValidationFailed(reason))] => assert_eq!(reason, ERR_BAD_PROTECTION),
+            _ => panic!("unexpected events"),
+        }
+    }
+
+    #[test]
+    fn fill_or_kill_with_self_trade_prevention() {
+        let order = |side, price: &str, qty: &str, owner| {
+            orders::new_limit_order_request(
+                Asset::BTC,
+                Asset::USD,
+                side,
+                bigdec(price),
+                bigdec(qty),
+                SystemTime::now(),
+            )
+            .with_owner(owner)
+        };
+        let book = |mode| {
+            let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+            orderbook.set_self_trade_prevention(Some(mode));
+            orderbook.process_order(order(OrderSide::Ask, "100", "1", 1));
+            orderbook.process_order(order(OrderSide::Ask, "100", "1", 2));
+            orderbook.process_order(order(OrderSide::Ask, "101", "1", 2));
+            orderbook
+        };
+        let fok = |qty: &str| {
+            order(OrderSide::Bid, "101", qty, 1).with_time_in_force(TimeInForce::FOK)
+        };
+
+        // the own ask goes, two of the others are left for the order
+        let mut orderbook = book(SelfTradePrevention::CancelOldest);
+        let request = fok("3");
+        let fok_id = request.order_id();
+        assert!(matches!(
+            orderbook.process_order(request).as_slice(),
+            [Err(Failed::NoMatch(id))] if *id == fok_id
+        ));
+        assert_eq!(orderbook.ask_queue.len(), 3);
+        let results = orderbook.process_order(fok("2"));
+        assert!(matches!(results.last(), Some(Ok(Success::Filled { .. }))));
+        assert!(!results
+            .iter()
+            .any(|result| matches!(result, Ok(Success::Cancelled { .. }))));
+
+        // the level of the own ask stops the order, nothing better is left
+        for mode in [
+            SelfTradePrevention::CancelNewest,
+            SelfTradePrevention::DecrementAndCancel,
+        ] {
+            let mut orderbook = book(mode);
+            let request = fok("1");
+            let fok_id = request.order_id();
+            assert!(matches!(
+                orderbook.process_order(request).as_slice(),
+                [Err(Failed::NoMatch(id))] if *id == fok_id
+            ));
+            assert_eq!(orderbook.ask_queue.len(), 3);
+        }
+    }
+
+    #[test]
+    fn self_trade_prevention() {
+        let order = |side, price: &str, qty: &str, owner| {
+            orders::new_limit_order_request(
+                Asset::BTC,
+                Asset::USD,
+                side,
+                bigdec(price),
+                bigdec(qty),
+                SystemTime::now(),
+            )
+            .with_owner(owner)
+        };
+
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        orderbook.set_self_trade_prevention(Some(SelfTradePrevention::CancelNewest));
+        orderbook.process_order(order(OrderSide::Ask, "100", "2", 1));
+        match orderbook
+            .process_order(order(OrderSide::Bid, "100", "1", 1))
+            .as_slice()
+        {
+            [Ok(Success::Accepted { .. }), Ok(Success::SelfTradePrevented {
+                owner,
+                qty,
+                remaining,
+                ..
+            })] => {
+                assert_eq!(*owner, 1);
+                assert_eq!((qty, remaining), (&bigdec("1"), &bigdec("0")));
+            }
+            _ => panic!("unexpected events"),
+        }
+        assert!(orderbook.bid_queue.is_empty());
+        assert_eq!(orderbook.ask_queue.len(), 1);
+
+        // the own order goes, the next level trades
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        orderbook.set_self_trade_prevention(Some(SelfTradePrevention::CancelOldest));
+        let own = order(OrderSide::Ask, "100", "1", 1);
+        let own_id = own.order_id();
+        orderbook.process_order(own);
+        orderbook.process_order(order(OrderSide::Ask, "101", "1", 2));
+        match orderbook
+            .process_order(order(OrderSide::Bid, "101", "1", 1))
+            .as_slice()
+        {
+            [Ok(Success::Accepted { .. }), Ok(Success::SelfTradePrevented { order_id, .. }),
+             Ok(Success::Filled { .. }), Ok(Success::Filled { .. })] => {
+                assert_eq!(*order_id, own_id)
+            }
+            _ => panic!("unexpected events"),
+        }
+        assert!(orderbook.ask_queue.is_empty());
+
+        // both lose the smaller quantity
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        orderbook.set_self_trade_prevention(Some(SelfTradePrevention::DecrementAndCancel));
+        orderbook.process_order(order(OrderSide::Ask, "100", "3", 1));
+        let results = orderbook.process_order(order(OrderSide::Bid, "100", "1", 1));
+        let remaining: Vec<&BigDecimal> = results
+            .iter()
+            .filter_map(|result| match result {
+                Ok(Success::SelfTradePrevented { remaining, .. }) => Some(remaining),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(remaining, vec![&bigdec("2"), &bigdec("0")]);
+        assert_eq!(orderbook.ask_queue.peek().unwrap().qty, bigdec("2"));
+
+        // orders of other owners or none at all trade as usual
+        assert!(orderbook
+            .process_order(order(OrderSide::Bid, "100", "1", 2))
+            .iter()
+            .any(|result| matches!(result, Ok(Success::Filled { .. }))));
+    }
+
+    #[test]
+    fn request_list() {
+        let btc_asset = Asset::BTC;
+        let usd_asset = Asset::USD;
+        let mut orderbook = Orderbook::new(btc_asset, usd_asset);
+        let request_list = vec![
+            orders::new_limit_order_request(
+                btc_asset,
+                usd_asset,
+                OrderSide::Bid,
+                bigdec("41711.760112"),
+                bigdec("0.15"),
+                SystemTime::now(),
+            ),
+            orders::new_limit_order_request(
+                btc_asset,
+                usd_asset,
+                OrderSide::Ask,
+                bigdec("41712.60777901"),
+                bigdec("1.0223"),
+                SystemTime::now(),
+            ),
+            orders::new_limit_order_request(
+                btc_asset,
+                usd_asset,
+                OrderSide::Bid,
+                bigdec("1.01"),
+                bigdec("0.4"),
+                SystemTime::now(),
+            ),
+            orders::new_limit_order_request(
+                btc_asset,
+                usd_asset,
+                OrderSide::Ask,
+                bigdec("1.03"),
+                bigdec("0.5"),
+                SystemTime::now(),
+            ),
+            orders::new_market_order_request(
+                btc_asset,
+                usd_asset,
+                OrderSide::Bid,
+                bigdec("0.90"),
+                SystemTime::now(),
+            ),
+            orders::new_limit_order_request(
+                btc_asset,
+                usd_asset,
+                OrderSide::Ask,
+                bigdec("1.05"),
+                bigdec("0.5"),
+                SystemTime::now(),
+            ),
+            orders::new_limit_order_request(
+                btc_asset,
+                usd_asset,
+                OrderSide::Bid,
+                bigdec("1.06"),
+                bigdec("0.6"),
+                SystemTime::now(),
+            ),
+        ];
+        for order in request_list {
+            let results = orderbook.process_order(order);
+            for result in results {
+                println!("\tResult => {:?}", result);
+            }
+
+            if let Some((bid, ask)) = orderbook.current_spread() {
+                println!("Spread => bid: {}, ask: {}\n", bid, ask);
+            }
+        }
+    }
+}
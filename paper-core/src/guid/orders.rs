@@ -0,0 +1,548 @@
+
+use std::time::SystemTime;
+use std::fmt::Debug;
+use bigdecimal::BigDecimal;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::domain::{AccountId, OrderSide, TimeInForce};
+
+
+/// How far a market order may walk the book, the rest is cancelled
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum MarketProtection {
+    /// fraction of the opposite best price at arrival, 0.05 for 5%
+    Slippage(BigDecimal),
+    /// worst price the order may trade at
+    Price(BigDecimal),
+}
+
+impl MarketProtection {
+    /// Worst price for an order of the side arriving with `touch` as the
+    /// opposite best price
+    pub fn limit(&self, side: OrderSide, touch: &BigDecimal) -> BigDecimal {
+        match (self, side) {
+            (MarketProtection::Slippage(slippage), OrderSide::Bid) => touch + touch * slippage,
+            (MarketProtection::Slippage(slippage), OrderSide::Ask) => touch - touch * slippage,
+            (MarketProtection::Price(price), _) => price.clone(),
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum OrderRequest<Asset>
+where
+    Asset: Debug + Clone,
+{
+    NewMarketOrder {
+        order_id: Uuid,
+        order_asset: Asset,
+        price_asset: Asset,
+        side: OrderSide,
+        qty: BigDecimal,
+        ts: SystemTime,
+        liquidation: bool,
+        /// stop matching beyond this, None sweeps the book
+        #[serde(default)]
+        protection: Option<MarketProtection>,
+        #[serde(default)]
+        owner: Option<AccountId>,
+    },
+
+    NewLimitOrder {
+        order_id: Uuid,
+        order_asset: Asset,
+        price_asset: Asset,
+        side: OrderSide,
+        price: BigDecimal,
+        qty: BigDecimal,
+        ts: SystemTime,
+        liquidation: bool,
+        time_in_force: TimeInForce,
+        #[serde(default)]
+        owner: Option<AccountId>,
+    },
+
+    /// Market order entering the book once a trade prints at or through the
+    /// trigger price: at or above it for bids, at or below it for asks
+    NewStopOrder {
+        order_id: Uuid,
+        order_asset: Asset,
+        price_asset: Asset,
+        side: OrderSide,
+        trigger_price: BigDecimal,
+        qty: BigDecimal,
+        ts: SystemTime,
+        liquidation: bool,
+        #[serde(default)]
+        owner: Option<AccountId>,
+    },
+
+    /// Limit order entering the book once a trade prints at or through the
+    /// trigger price, see `NewStopOrder`
+    NewStopLimitOrder {
+        order_id: Uuid,
+        order_asset: Asset,
+        price_asset: Asset,
+        side: OrderSide,
+        trigger_price: BigDecimal,
+        price: BigDecimal,
+        qty: BigDecimal,
+        ts: SystemTime,
+        liquidation: bool,
+        time_in_force: TimeInForce,
+        #[serde(default)]
+        owner: Option<AccountId>,
+    },
+
+    /// Fields left as None keep the resting order's value
+    AmendOrder {
+        id: Uuid,
+        side: OrderSide,
+        price: Option<BigDecimal>,
+        /// new total quantity, fills so far included
+        qty: Option<BigDecimal>,
+        ts: SystemTime,
+    },
+
+    CancelOrder {
+        id: Uuid,
+        /// side the order rests on, looked up by ID when None
+        #[serde(default)]
+        side: Option<OrderSide>,
+        //ts: SystemTime,
+    },
+}
+
+
+impl<Asset> OrderRequest<Asset>
+where
+    Asset: Debug + Clone,
+{
+    /// ID of the order this request refers to
+    pub fn order_id(&self) -> Uuid {
+        match self {
+            OrderRequest::NewMarketOrder { order_id, .. } => *order_id,
+            OrderRequest::NewLimitOrder { order_id, .. } => *order_id,
+            OrderRequest::NewStopOrder { order_id, .. } => *order_id,
+            OrderRequest::NewStopLimitOrder { order_id, .. } => *order_id,
+            OrderRequest::AmendOrder { id, .. } => *id,
+            OrderRequest::CancelOrder { id, .. } => *id,
+        }
+    }
+
+    /// Time the request was issued, cancels carry none
+    pub fn ts(&self) -> Option<SystemTime> {
+        match self {
+            OrderRequest::NewMarketOrder { ts, .. }
+            | OrderRequest::NewLimitOrder { ts, .. }
+            | OrderRequest::NewStopOrder { ts, .. }
+            | OrderRequest::NewStopLimitOrder { ts, .. }
+            | OrderRequest::AmendOrder { ts, .. } => Some(*ts),
+            OrderRequest::CancelOrder { .. } => None,
+        }
+    }
+
+    /// Mark new order as liquidation flow.
+    ///
+    /// Liquidation orders are reported distinctly in all events and, when
+    /// enabled on the book, rest ahead of regular orders at the same price.
+    /// Amend and cancel requests are left untouched.
+    pub fn with_liquidation_flag(mut self) -> Self {
+        match &mut self {
+            OrderRequest::NewMarketOrder { liquidation, .. }
+            | OrderRequest::NewLimitOrder { liquidation, .. }
+            | OrderRequest::NewStopOrder { liquidation, .. }
+            | OrderRequest::NewStopLimitOrder { liquidation, .. } => *liquidation = true,
+            _ => (),
+        }
+        self
+    }
+
+    /// Set how long new limit order may rest in the book, GTC by default.
+    ///
+    /// Other requests are left untouched.
+    pub fn with_time_in_force(mut self, tif: TimeInForce) -> Self {
+        match &mut self {
+            OrderRequest::NewLimitOrder { time_in_force, .. }
+            | OrderRequest::NewStopLimitOrder { time_in_force, .. } => *time_in_force = tif,
+            _ => (),
+        }
+        self
+    }
+
+    /// Give new order a chosen ID in place of the random one, so simulations
+    /// can be run again with the very same orders.
+    ///
+    /// Amend and cancel requests are left untouched.
+    pub fn with_order_id(mut self, id: Uuid) -> Self {
+        match &mut self {
+            OrderRequest::NewMarketOrder { order_id, .. }
+            | OrderRequest::NewLimitOrder { order_id, .. }
+            | OrderRequest::NewStopOrder { order_id, .. }
+            | OrderRequest::NewStopLimitOrder { order_id, .. } => *order_id = id,
+            _ => (),
+        }
+        self
+    }
+
+    /// Restamp request with the time it reaches the book, e.g. an order
+    /// released from the pending area.
+    ///
+    /// Cancel requests carry no timestamp and are left untouched.
+    pub fn with_ts(mut self, at: SystemTime) -> Self {
+        match &mut self {
+            OrderRequest::NewMarketOrder { ts, .. }
+            | OrderRequest::NewLimitOrder { ts, .. }
+            | OrderRequest::NewStopOrder { ts, .. }
+            | OrderRequest::NewStopLimitOrder { ts, .. }
+            | OrderRequest::AmendOrder { ts, .. } => *ts = at,
+            OrderRequest::CancelOrder { .. } => (),
+        }
+        self
+    }
+
+    /// Limit how far new market order may walk the book.
+    ///
+    /// Other requests are left untouched.
+    pub fn with_protection(mut self, limit: MarketProtection) -> Self {
+        if let OrderRequest::NewMarketOrder { protection, .. } = &mut self {
+            *protection = Some(limit);
+        }
+        self
+    }
+
+    /// Attribute new order to the account, for self-trade prevention.
+    ///
+    /// Amend and cancel requests are left untouched.
+    pub fn with_owner(mut self, account: AccountId) -> Self {
+        match &mut self {
+            OrderRequest::NewMarketOrder { owner, .. }
+            | OrderRequest::NewLimitOrder { owner, .. }
+            | OrderRequest::NewStopOrder { owner, .. }
+            | OrderRequest::NewStopLimitOrder { owner, .. } => *owner = Some(account),
+            _ => (),
+        }
+        self
+    }
+
+    /// Account owning new order, None for other requests
+    pub fn owner(&self) -> Option<AccountId> {
+        match self {
+            OrderRequest::NewMarketOrder { owner, .. }
+            | OrderRequest::NewLimitOrder { owner, .. }
+            | OrderRequest::NewStopOrder { owner, .. }
+            | OrderRequest::NewStopLimitOrder { owner, .. } => *owner,
+            _ => None,
+        }
+    }
+
+    /// Quantity of new order, None for other requests
+    pub fn qty(&self) -> Option<&BigDecimal> {
+        match self {
+            OrderRequest::NewMarketOrder { qty, .. }
+            | OrderRequest::NewLimitOrder { qty, .. }
+            | OrderRequest::NewStopOrder { qty, .. }
+            | OrderRequest::NewStopLimitOrder { qty, .. } => Some(qty),
+            _ => None,
+        }
+    }
+
+    /// Whether the request is for a new order of any type
+    pub fn is_new_order(&self) -> bool {
+        !matches!(
+            self,
+            OrderRequest::AmendOrder { .. } | OrderRequest::CancelOrder { .. }
+        )
+    }
+
+    /// Market or limit order a triggered stop turns into, None for other
+    /// requests
+    pub fn triggered(&self) -> Option<OrderRequest<Asset>> {
+        match self {
+            OrderRequest::NewStopOrder {
+                order_id,
+                order_asset,
+                price_asset,
+                side,
+                qty,
+                ts,
+                liquidation,
+                owner,
+                ..
+            } => Some(OrderRequest::NewMarketOrder {
+                order_id: *order_id,
+                order_asset: order_asset.clone(),
+                price_asset: price_asset.clone(),
+                side: *side,
+                qty: qty.clone(),
+                ts: *ts,
+                liquidation: *liquidation,
+                protection: None,
+                owner: *owner,
+            }),
+            OrderRequest::NewStopLimitOrder {
+                order_id,
+                order_asset,
+                price_asset,
+                side,
+                price,
+                qty,
+                ts,
+                liquidation,
+                time_in_force,
+                owner,
+                ..
+            } => Some(OrderRequest::NewLimitOrder {
+                order_id: *order_id,
+                order_asset: order_asset.clone(),
+                price_asset: price_asset.clone(),
+                side: *side,
+                price: price.clone(),
+                qty: qty.clone(),
+                ts: *ts,
+                liquidation: *liquidation,
+                time_in_force: *time_in_force,
+                owner: *owner,
+            }),
+            _ => None,
+        }
+    }
+}
+
+
+/// Amendment of a resting order on its own, for batches of amends.
+///
+/// Fields left as None keep the resting order's value.
+#[derive(Debug, Clone)]
+pub struct AmendOrder {
+    pub id: Uuid,
+    pub side: OrderSide,
+    pub price: Option<BigDecimal>,
+    /// new total quantity, fills so far included
+    pub qty: Option<BigDecimal>,
+    pub ts: SystemTime,
+}
+
+
+impl<Asset> From<AmendOrder> for OrderRequest<Asset>
+where
+    Asset: Debug + Clone,
+{
+    fn from(amend: AmendOrder) -> Self {
+        OrderRequest::AmendOrder {
+            id: amend.id,
+            side: amend.side,
+            price: amend.price,
+            qty: amend.qty,
+            ts: amend.ts,
+        }
+    }
+}
+
+
+/* Constructors */
+
+
+/// Create request for the new market order
+pub fn new_market_order_request<Asset>(
+    order_asset: Asset,
+    price_asset: Asset,
+    side: OrderSide,
+    qty: BigDecimal,
+    ts: SystemTime,
+) -> OrderRequest<Asset>
+where
+    Asset: Debug + Clone,
+{
+    let order_id = Uuid::new_v4();
+    OrderRequest::NewMarketOrder {
+        order_id,
+        order_asset,
+        price_asset,
+        qty,
+        side,
+        ts,
+        liquidation: false,
+        owner: None,
+        protection: None,
+    }
+}
+
+
+/// Create request for the new limit order
+pub fn new_limit_order_request<Asset>(
+    order_asset: Asset,
+    price_asset: Asset,
+    side: OrderSide,
+    price: BigDecimal,
+    qty: BigDecimal,
+    ts: SystemTime,
+) -> OrderRequest<Asset>
+where
+    Asset: Debug + Clone,
+{
+    let order_id = Uuid::new_v4();
+    OrderRequest::NewLimitOrder {
+        order_id,
+        order_asset,
+        price_asset,
+        side,
+        price,
+        qty,
+        ts,
+        liquidation: false,
+        owner: None,
+        time_in_force: TimeInForce::GTC,
+    }
+}
+
+
+/// Create request for the new stop order, a market order once triggered
+pub fn new_stop_order_request<Asset>(
+    order_asset: Asset,
+    price_asset: Asset,
+    side: OrderSide,
+    trigger_price: BigDecimal,
+    qty: BigDecimal,
+    ts: SystemTime,
+) -> OrderRequest<Asset>
+where
+    Asset: Debug + Clone,
+{
+    let order_id = Uuid::new_v4();
+    OrderRequest::NewStopOrder {
+        order_id,
+        order_asset,
+        price_asset,
+        side,
+        trigger_price,
+        qty,
+        ts,
+        liquidation: false,
+        owner: None,
+    }
+}
+
+
+/// Create request for the new stop-limit order, a limit order once triggered
+pub fn new_stop_limit_order_request<Asset>(
+    order_asset: Asset,
+    price_asset: Asset,
+    side: OrderSide,
+    trigger_price: BigDecimal,
+    price: BigDecimal,
+    qty: BigDecimal,
+    ts: SystemTime,
+) -> OrderRequest<Asset>
+where
+    Asset: Debug + Clone,
+{
+    let order_id = Uuid::new_v4();
+    OrderRequest::NewStopLimitOrder {
+        order_id,
+        order_asset,
+        price_asset,
+        side,
+        trigger_price,
+        price,
+        qty,
+        ts,
+        liquidation: false,
+        owner: None,
+        time_in_force: TimeInForce::GTC,
+    }
+}
+
+
+/// Create request for changing price/qty for the active limit order.
+///
+/// Note: do not change order side!
+/// Instead cancel existing order and create a new one.
+pub fn amend_order_request<Asset>(
+    id: Uuid,
+    side: OrderSide,
+    price: BigDecimal,
+    qty: BigDecimal,
+    ts: SystemTime,
+) -> OrderRequest<Asset>
+where
+    Asset: Debug + Clone,
+{
+
+    OrderRequest::AmendOrder {
+        id,
+        side,
+        price: Some(price),
+        qty: Some(qty),
+        ts,
+    }
+}
+
+
+/// Create request for changing only the price of the active limit order
+pub fn amend_order_price_request<Asset>(
+    id: Uuid,
+    side: OrderSide,
+    price: BigDecimal,
+    ts: SystemTime,
+) -> OrderRequest<Asset>
+where
+    Asset: Debug + Clone,
+{
+    OrderRequest::AmendOrder {
+        id,
+        side,
+        price: Some(price),
+        qty: None,
+        ts,
+    }
+}
+
+
+/// Create request for changing only the quantity of the active limit order.
+///
+/// The quantity is the order's new total, it has to exceed what the order
+/// already filled. Reducing quantity keeps the order's place in the queue.
+pub fn amend_order_qty_request<Asset>(
+    id: Uuid,
+    side: OrderSide,
+    qty: BigDecimal,
+    ts: SystemTime,
+) -> OrderRequest<Asset>
+where
+    Asset: Debug + Clone,
+{
+    OrderRequest::AmendOrder {
+        id,
+        side,
+        price: None,
+        qty: Some(qty),
+        ts,
+    }
+}
+
+
+/// Create request for cancelling active limit order
+pub fn limit_order_cancel_request<Asset>(order_id: Uuid, side: OrderSide) -> OrderRequest<Asset>
+where
+    Asset: Debug + Clone,
+{
+    OrderRequest::CancelOrder {
+        id: order_id,
+        side: Some(side),
+    }
+}
+
+
+/// Create request for cancelling order on whichever side it rests
+pub fn cancel_order_request<Asset>(order_id: Uuid) -> OrderRequest<Asset>
+where
+    Asset: Debug + Clone,
+{
+    OrderRequest::CancelOrder {
+        id: order_id,
+        side: None,
+    }
+}
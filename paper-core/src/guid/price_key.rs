@@ -0,0 +1,130 @@
+use bigdecimal::num_bigint::BigInt;
+use bigdecimal::{BigDecimal, RoundingMode, ToPrimitive, Zero};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+
+/// Finest tick of books not configured otherwise, fine enough for any
+/// asset quoted to nano units
+pub const DEFAULT_PRICE_DECIMALS: u32 = 9;
+
+/// Price level as a whole number of ticks.
+///
+/// Compared and hashed as a plain integer, so equal prices written with
+/// different scales ("1.5" and "1.50") always land on the same level.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PriceKey(pub i64);
+
+/// Tick size of a market, converting prices to level keys and back.
+///
+/// The tick is `units` times 10 to the power of minus `decimals`, e.g. 25
+/// units with 2 decimals for a quarter tick.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PriceScale {
+    units: i64,
+    decimals: u32,
+}
+
+impl Default for PriceScale {
+    fn default() -> Self {
+        PriceScale {
+            units: 1,
+            decimals: DEFAULT_PRICE_DECIMALS,
+        }
+    }
+}
+
+impl PriceScale {
+    /// Scale for the tick size, None unless it is positive and fits 64 bits
+    pub fn from_tick(tick: &BigDecimal) -> Option<Self> {
+        if *tick <= BigDecimal::zero() {
+            return None;
+        }
+        let (mantissa, exponent) = tick.normalized().as_bigint_and_exponent();
+        let (mantissa, decimals) = if exponent < 0 {
+            (mantissa * BigInt::from(10).pow(exponent.unsigned_abs() as u32), 0)
+        } else {
+            (mantissa, exponent as u32)
+        };
+        Some(PriceScale {
+            units: mantissa.to_i64()?,
+            decimals,
+        })
+    }
+
+    pub fn tick(&self) -> BigDecimal {
+        BigDecimal::new(BigInt::from(self.units), self.decimals as i64)
+    }
+
+    /// Key of the price level, None for prices off the tick grid or too
+    /// large to key
+    pub fn key(&self, price: &BigDecimal) -> Option<PriceKey> {
+        let scaled = price.with_scale(self.decimals as i64);
+        if scaled != *price {
+            return None;
+        }
+        let (mantissa, _) = scaled.as_bigint_and_exponent();
+        let units = BigInt::from(self.units);
+        if mantissa.clone() % &units != BigInt::zero() {
+            return None;
+        }
+        i64::try_from(mantissa / units).ok().map(PriceKey)
+    }
+
+    /// Nearest price on the tick grid in the direction of `mode`
+    pub fn round(&self, price: &BigDecimal, mode: RoundingMode) -> BigDecimal {
+        let tick = self.tick();
+        let ticks = (price / &tick).with_scale_round(0, mode);
+        (ticks * tick).with_scale(self.decimals as i64)
+    }
+
+    /// Price of the level
+    pub fn price(&self, key: PriceKey) -> BigDecimal {
+        BigDecimal::new(
+            BigInt::from(key.0) * BigInt::from(self.units),
+            self.decimals as i64,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+
+    fn bigdec(num: &str) -> BigDecimal {
+        BigDecimal::from_str(num).unwrap()
+    }
+
+    #[test]
+    fn keys_on_tick_grid() {
+        let quarter = PriceScale::from_tick(&bigdec("0.25")).unwrap();
+        assert_eq!(quarter.tick(), bigdec("0.25"));
+        assert_eq!(quarter.key(&bigdec("100.5")), Some(PriceKey(402)));
+        assert_eq!(quarter.key(&bigdec("100.50")), quarter.key(&bigdec("100.5")));
+        assert_eq!(quarter.key(&bigdec("100.1")), None);
+        assert_eq!(quarter.price(PriceKey(402)), bigdec("100.5"));
+
+        let hundreds = PriceScale::from_tick(&bigdec("100")).unwrap();
+        assert_eq!(hundreds.key(&bigdec("4200")), Some(PriceKey(42)));
+        assert_eq!(hundreds.key(&bigdec("4250")), None);
+
+        let default = PriceScale::default();
+        assert_eq!(default.key(&bigdec("0.000000001")), Some(PriceKey(1)));
+        assert_eq!(default.key(&bigdec("0.0000000001")), None);
+        assert_eq!(default.key(&bigdec("1e20")), None);
+        assert!(PriceScale::from_tick(&bigdec("0")).is_none());
+
+        assert_eq!(
+            quarter.round(&bigdec("100.6"), RoundingMode::Floor),
+            bigdec("100.5")
+        );
+        assert_eq!(
+            quarter.round(&bigdec("100.6"), RoundingMode::Ceiling),
+            bigdec("100.75")
+        );
+        assert_eq!(
+            hundreds.round(&bigdec("4250"), RoundingMode::Floor),
+            bigdec("4200")
+        );
+    }
+}
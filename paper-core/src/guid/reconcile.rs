@@ -0,0 +1,226 @@
+use bigdecimal::BigDecimal;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use uuid::Uuid;
+
+use super::domain::{Order, OrderSide};
+use super::order_queues::OrderQueue;
+use super::orderbook::Orderbook;
+use super::orders::OrderRequest;
+
+/// Difference between the live book and the book rebuilt from the journal
+#[derive(Debug, Clone, PartialEq)]
+pub enum Discrepancy {
+    /// order rests in the rebuilt book only
+    MissingInLive(Uuid),
+    /// order rests in the live book only
+    MissingInReplay(Uuid),
+    SideMismatch {
+        order_id: Uuid,
+        live: OrderSide,
+        replayed: OrderSide,
+    },
+    PriceMismatch {
+        order_id: Uuid,
+        live: BigDecimal,
+        replayed: BigDecimal,
+    },
+    QtyMismatch {
+        order_id: Uuid,
+        live: BigDecimal,
+        replayed: BigDecimal,
+    },
+}
+
+#[derive(Debug)]
+pub struct ReconciliationReport {
+    /// number of journal requests replayed into the fresh book
+    pub replayed: usize,
+    pub discrepancies: Vec<Discrepancy>,
+}
+
+impl ReconciliationReport {
+    pub fn is_consistent(&self) -> bool {
+        self.discrepancies.is_empty()
+    }
+}
+
+/// Replay journaled requests into a fresh book and diff it against the live one.
+///
+/// The fresh book gets the live book's assets and settings, so both are
+/// expected to hold exactly the same resting orders.
+pub fn reconcile<Asset, I>(live: &Orderbook<Asset>, journal: I) -> ReconciliationReport
+where
+    Asset: Debug + Clone + Copy + Eq,
+    I: IntoIterator<Item = OrderRequest<Asset>>,
+{
+    let mut replay = Orderbook::new_with_config(live.order_asset, live.price_asset, live.config());
+    replay.set_liquidation_priority(live.liquidation_priority());
+    replay.set_depth_limit(live.depth_limit());
+    replay.set_dust_threshold(live.dust_threshold().cloned());
+    replay
+        .set_qty_limits(live.qty_limits().cloned())
+        .expect("limits of the live book are valid");
+
+    let mut replayed = 0;
+    for request in journal {
+        replay.process_order(request);
+        replayed += 1;
+    }
+
+    let mut discrepancies = vec![];
+    diff_orders(
+        collect_orders(&live.bid_queue, &live.ask_queue),
+        collect_orders(&replay.bid_queue, &replay.ask_queue),
+        &mut discrepancies,
+    );
+
+    ReconciliationReport {
+        replayed,
+        discrepancies,
+    }
+}
+
+/* Helpers */
+
+fn collect_orders<'a, Asset>(
+    bids: &'a OrderQueue<Order<Asset>>,
+    asks: &'a OrderQueue<Order<Asset>>,
+) -> HashMap<Uuid, &'a Order<Asset>>
+where
+    Asset: Debug + Clone,
+{
+    bids.iter()
+        .chain(asks.iter())
+        .map(|order| (order.order_id, order))
+        .collect()
+}
+
+fn diff_orders<Asset>(
+    live: HashMap<Uuid, &Order<Asset>>,
+    mut replayed: HashMap<Uuid, &Order<Asset>>,
+    discrepancies: &mut Vec<Discrepancy>,
+) where
+    Asset: Debug + Clone,
+{
+    for (order_id, live_order) in live {
+        let replayed_order = match replayed.remove(&order_id) {
+            Some(order) => order,
+            None => {
+                discrepancies.push(Discrepancy::MissingInReplay(order_id));
+                continue;
+            }
+        };
+
+        if live_order.side != replayed_order.side {
+            discrepancies.push(Discrepancy::SideMismatch {
+                order_id,
+                live: live_order.side,
+                replayed: replayed_order.side,
+            });
+        }
+        if live_order.price != replayed_order.price {
+            discrepancies.push(Discrepancy::PriceMismatch {
+                order_id,
+                live: live_order.price.clone(),
+                replayed: replayed_order.price.clone(),
+            });
+        }
+        if live_order.qty != replayed_order.qty {
+            discrepancies.push(Discrepancy::QtyMismatch {
+                order_id,
+                live: live_order.qty.clone(),
+                replayed: replayed_order.qty.clone(),
+            });
+        }
+    }
+
+    discrepancies.extend(replayed.into_keys().map(Discrepancy::MissingInLive));
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::orders;
+    use super::*;
+    use std::str::FromStr;
+    use std::time::SystemTime;
+
+    #[derive(PartialEq, Eq, Debug, Copy, Clone)]
+    pub enum Asset {
+        USD,
+        BTC,
+    }
+
+    fn bigdec(num: &str) -> BigDecimal {
+        BigDecimal::from_str(num).unwrap()
+    }
+
+    fn limit(side: OrderSide, price: &str, qty: &str, ts: SystemTime) -> OrderRequest<Asset> {
+        orders::new_limit_order_request(
+            Asset::BTC,
+            Asset::USD,
+            side,
+            bigdec(price),
+            bigdec(qty),
+            ts,
+        )
+    }
+
+    #[test]
+    fn consistent_book() {
+        let ts = SystemTime::now();
+        let requests = vec![
+            limit(OrderSide::Bid, "99", "1", ts),
+            limit(OrderSide::Ask, "101", "1", ts),
+            limit(OrderSide::Ask, "99", "0.4", ts),
+        ];
+        let journal = requests.clone();
+
+        let mut live = Orderbook::new(Asset::BTC, Asset::USD);
+        for request in requests {
+            live.process_order(request);
+        }
+
+        let report = reconcile(&live, journal);
+        assert_eq!(report.replayed, 3);
+        assert!(report.is_consistent());
+    }
+
+    #[test]
+    fn diverged_book() {
+        let ts = SystemTime::now();
+        let requests = vec![
+            limit(OrderSide::Bid, "99", "1", ts),
+            limit(OrderSide::Ask, "101", "1", ts),
+        ];
+        let bid_id = requests[0].order_id();
+        let ask_id = requests[1].order_id();
+        let journal = requests.clone();
+
+        let mut live = Orderbook::new(Asset::BTC, Asset::USD);
+        for request in requests {
+            live.process_order(request);
+        }
+        // requests applied to the live book but lost from the journal
+        live.process_order(orders::limit_order_cancel_request(ask_id, OrderSide::Ask));
+        live.process_order(orders::amend_order_request(
+            bid_id,
+            OrderSide::Bid,
+            bigdec("99"),
+            bigdec("2"),
+            ts,
+        ));
+
+        let report = reconcile(&live, journal);
+        assert!(!report.is_consistent());
+        assert_eq!(report.discrepancies.len(), 2);
+        assert!(report
+            .discrepancies
+            .contains(&Discrepancy::MissingInLive(ask_id)));
+        assert!(report.discrepancies.contains(&Discrepancy::QtyMismatch {
+            order_id: bid_id,
+            live: bigdec("2"),
+            replayed: bigdec("1"),
+        }));
+    }
+}
@@ -0,0 +1,306 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use super::clock::ManualClock;
+use super::orderbook::{OrderProcessingResult, Orderbook};
+use super::orders::OrderRequest;
+
+/// Pace of a replay relative to the original request timestamps
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ReplaySpeed {
+    /// no waiting between requests
+    #[default]
+    Unpaced,
+    /// gaps between requests as recorded
+    RealTime,
+    /// gaps between requests divided by the factor, 2.0 plays twice as fast
+    Times(f64),
+}
+
+/// Answer of a hook whether the replay goes on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayControl {
+    Continue,
+    Pause,
+}
+
+/// Why `run` returned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayStatus {
+    /// a hook asked to stop, `run` or `step` resume after the last request
+    Paused,
+    Finished,
+}
+
+/// Single replayed request and what the book made of it
+#[derive(Debug)]
+pub struct ReplayStep<Asset>
+where
+    Asset: Debug + Clone,
+{
+    /// position of the request in the recording
+    pub index: usize,
+    pub request: OrderRequest<Asset>,
+    pub results: OrderProcessingResult<Asset>,
+}
+
+type Hook<Asset> = Box<dyn FnMut(&ReplayStep<Asset>, &Orderbook<Asset>) -> ReplayControl>;
+
+/// Feeds recorded requests into a book, paced or one at a time.
+///
+/// The book clock follows the recording, every request is processed at its
+/// own timestamp (cancels at the one before), so events carry historical
+/// times whatever the pace. Hooks see each request with its results and the
+/// book right after it, and can pause the replay to inspect it.
+pub struct Replayer<Asset, I>
+where
+    Asset: Debug + Clone + Copy + Eq,
+    I: Iterator<Item = OrderRequest<Asset>>,
+{
+    book: Orderbook<Asset>,
+    requests: I,
+    clock: Arc<ManualClock>,
+    speed: ReplaySpeed,
+    sleep: Box<dyn FnMut(Duration)>,
+    hooks: Vec<Hook<Asset>>,
+    position: usize,
+    last_ts: Option<SystemTime>,
+}
+
+impl<Asset, I> Replayer<Asset, I>
+where
+    Asset: Debug + Clone + Copy + Eq,
+    I: Iterator<Item = OrderRequest<Asset>>,
+{
+    pub fn new<R>(mut book: Orderbook<Asset>, requests: R) -> Self
+    where
+        R: IntoIterator<Item = OrderRequest<Asset>, IntoIter = I>,
+    {
+        let clock = Arc::new(ManualClock::new(book.clock().now()));
+        book.set_clock(Box::new(clock.clone()));
+
+        Replayer {
+            book,
+            requests: requests.into_iter(),
+            clock,
+            speed: ReplaySpeed::default(),
+            sleep: Box::new(thread::sleep),
+            hooks: Vec::new(),
+            position: 0,
+            last_ts: None,
+        }
+    }
+
+    pub fn set_speed(&mut self, speed: ReplaySpeed) {
+        self.speed = speed;
+    }
+
+    pub fn speed(&self) -> ReplaySpeed {
+        self.speed
+    }
+
+    /// Replace the thread sleep used for pacing, e.g. to drive it from a UI
+    pub fn set_sleep<F>(&mut self, sleep: F)
+    where
+        F: FnMut(Duration) + 'static,
+    {
+        self.sleep = Box::new(sleep);
+    }
+
+    /// Call `hook` after every replayed request, in the order added
+    pub fn add_hook<F>(&mut self, hook: F)
+    where
+        F: FnMut(&ReplayStep<Asset>, &Orderbook<Asset>) -> ReplayControl + 'static,
+    {
+        self.hooks.push(Box::new(hook));
+    }
+
+    /// Number of requests replayed so far
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn book(&self) -> &Orderbook<Asset> {
+        &self.book
+    }
+
+    pub fn into_book(self) -> Orderbook<Asset> {
+        self.book
+    }
+
+    /// Replay the next request right away, ignoring the pace.
+    ///
+    /// Hooks are called, but cannot pause what is already a single step.
+    pub fn step(&mut self) -> Option<ReplayStep<Asset>> {
+        self.advance(false).map(|(step, _)| step)
+    }
+
+    /// Replay paced until the recording ends or a hook pauses
+    pub fn run(&mut self) -> ReplayStatus {
+        loop {
+            match self.advance(true) {
+                None => return ReplayStatus::Finished,
+                Some((_, ReplayControl::Pause)) => return ReplayStatus::Paused,
+                Some((_, ReplayControl::Continue)) => (),
+            }
+        }
+    }
+
+    /* Helpers */
+
+    fn advance(&mut self, paced: bool) -> Option<(ReplayStep<Asset>, ReplayControl)> {
+        let request = self.requests.next()?;
+
+        if let Some(ts) = request.ts() {
+            if paced {
+                self.wait_for(ts);
+            }
+            if self.last_ts.is_none_or(|last| ts > last) {
+                self.last_ts = Some(ts);
+                self.clock.set(ts);
+            }
+        }
+
+        let results = self.book.process_order(request.clone());
+        let step = ReplayStep {
+            index: self.position,
+            request,
+            results,
+        };
+        self.position += 1;
+
+        let mut control = ReplayControl::Continue;
+        for hook in self.hooks.iter_mut() {
+            if hook(&step, &self.book) == ReplayControl::Pause {
+                control = ReplayControl::Pause;
+            }
+        }
+
+        Some((step, control))
+    }
+
+    fn wait_for(&mut self, ts: SystemTime) {
+        let factor = match self.speed {
+            ReplaySpeed::Unpaced => return,
+            ReplaySpeed::RealTime => 1.0,
+            ReplaySpeed::Times(factor) if factor > 0.0 => factor,
+            ReplaySpeed::Times(_) => return,
+        };
+
+        let gap = match self.last_ts.map(|last| ts.duration_since(last)) {
+            Some(Ok(gap)) => gap,
+            // first request or out of order timestamp
+            _ => return,
+        };
+        let delay = gap.div_f64(factor);
+        if delay > Duration::ZERO {
+            (self.sleep)(delay);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::domain::OrderSide;
+    use super::super::orderbook::Success;
+    use super::super::orders;
+    use super::*;
+    use bigdecimal::BigDecimal;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::str::FromStr;
+
+    #[derive(PartialEq, Debug, Copy, Clone, Eq)]
+    pub enum Asset {
+        USD,
+        BTC,
+    }
+
+    fn bigdec(num: &str) -> BigDecimal {
+        BigDecimal::from_str(num).unwrap()
+    }
+
+    fn recording(start: SystemTime) -> Vec<orders::OrderRequest<Asset>> {
+        let limit = |side, price: &str, secs| {
+            orders::new_limit_order_request(
+                Asset::BTC,
+                Asset::USD,
+                side,
+                bigdec(price),
+                bigdec("1"),
+                start + Duration::from_secs(secs),
+            )
+        };
+        vec![
+            limit(OrderSide::Ask, "100", 0),
+            limit(OrderSide::Ask, "101", 2),
+            limit(OrderSide::Bid, "100", 6),
+            limit(OrderSide::Bid, "101", 10),
+        ]
+    }
+
+    #[test]
+    fn paced_replay() {
+        let start = SystemTime::now();
+        let mut replayer = Replayer::new(Orderbook::new(Asset::BTC, Asset::USD), recording(start));
+        let slept = Rc::new(RefCell::new(vec![]));
+        let sink = slept.clone();
+        replayer.set_sleep(move |delay| sink.borrow_mut().push(delay));
+        replayer.set_speed(ReplaySpeed::Times(2.0));
+
+        assert_eq!(replayer.run(), ReplayStatus::Finished);
+        assert_eq!(
+            *slept.borrow(),
+            vec![
+                Duration::from_secs(1),
+                Duration::from_secs(2),
+                Duration::from_secs(2)
+            ]
+        );
+        assert_eq!(replayer.position(), 4);
+        // historical time despite the faster pace
+        assert_eq!(
+            replayer.book().clock().now(),
+            start + Duration::from_secs(10)
+        );
+        assert_eq!(replayer.into_book().current_spread(), None);
+    }
+
+    #[test]
+    fn pause_and_step() {
+        let start = SystemTime::now();
+        let mut replayer = Replayer::new(Orderbook::new(Asset::BTC, Asset::USD), recording(start));
+        replayer.set_sleep(|_| panic!("unpaced replay must not sleep"));
+        let seen = Rc::new(RefCell::new(vec![]));
+        let sink = seen.clone();
+        replayer.add_hook(move |step, _| {
+            sink.borrow_mut().push(step.index);
+            let filled = step
+                .results
+                .iter()
+                .any(|result| matches!(result, Ok(Success::Filled { .. })));
+            if filled {
+                ReplayControl::Pause
+            } else {
+                ReplayControl::Continue
+            }
+        });
+
+        // pauses on the first trade
+        assert_eq!(replayer.run(), ReplayStatus::Paused);
+        assert_eq!(replayer.position(), 3);
+
+        let step = replayer.step().unwrap();
+        assert_eq!(step.index, 3);
+        assert!(step.results.iter().any(|result| matches!(
+            result,
+            Ok(Success::Filled { price, .. }) if *price == bigdec("101")
+        )));
+        assert!(replayer.step().is_none());
+        assert_eq!(replayer.run(), ReplayStatus::Finished);
+        assert_eq!(*seen.borrow(), vec![0, 1, 2, 3]);
+        assert_eq!(replayer.into_book().current_spread(), None);
+    }
+}
@@ -0,0 +1,301 @@
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::time::{Duration, SystemTime};
+use uuid::Uuid;
+
+use super::clock::Clock;
+use super::domain::AccountId;
+use super::engine::{MatchingEngine, Symbol, ERR_UNKNOWN_MARKET};
+use super::orderbook::{Failed, OrderProcessingResult, Success};
+use super::orders::OrderRequest;
+
+pub(super) const ERR_NOT_RISK_CHECKED: &str = "only new orders go through the risk check";
+
+/// What happens to an order whose risk verdict did not arrive in time
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum TimeoutPolicy {
+    /// fail it with `Failed::RiskRejected`, the safe choice
+    #[default]
+    Reject,
+    /// let it through, for checks that only guard against rare blowups
+    Accept,
+}
+
+/// Answer of the risk service on a pending order
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RiskVerdict {
+    Approve,
+    Reject,
+}
+
+struct PendingCheck<Asset>
+where
+    Asset: Debug + Clone,
+{
+    account: AccountId,
+    symbol: Symbol<Asset>,
+    order: OrderRequest<Asset>,
+    deadline: SystemTime,
+}
+
+/// Two-phase acceptance of new orders for pre-trade risk checks run by an
+/// external service.
+///
+/// `submit` answers with `Success::PendingNew` and holds the order back, the
+/// caller then asks the risk service, usually without blocking, and hands
+/// its answer to `verdict`. Approved orders go to the engine and produce the
+/// usual `Success::Accepted` and following events, rejected ones fail with
+/// `Failed::RiskRejected`. Orders without a verdict past the timeout are
+/// settled by `expire` as the timeout policy says.
+pub struct RiskCheckedOrders<Asset, C>
+where
+    Asset: Debug + Clone + Copy + Eq + Hash,
+    C: Clock,
+{
+    clock: C,
+    timeout: Duration,
+    on_timeout: TimeoutPolicy,
+    /// in submission order, so deadlines are ascending
+    pending: VecDeque<PendingCheck<Asset>>,
+}
+
+impl<Asset, C> RiskCheckedOrders<Asset, C>
+where
+    Asset: Debug + Clone + Copy + Eq + Hash,
+    C: Clock,
+{
+    pub fn new(clock: C, timeout: Duration, on_timeout: TimeoutPolicy) -> Self {
+        RiskCheckedOrders {
+            clock,
+            timeout,
+            on_timeout,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Hold back new order of the account until its risk verdict arrives.
+    ///
+    /// Orders the book would refuse anyway fail right away, the risk
+    /// service is not bothered with them.
+    pub fn submit(
+        &mut self,
+        engine: &MatchingEngine<Asset>,
+        account: AccountId,
+        symbol: Symbol<Asset>,
+        order: OrderRequest<Asset>,
+    ) -> OrderProcessingResult<Asset> {
+        if !order.is_new_order() {
+            return vec![Err(Failed::ValidationFailed(String::from(
+                ERR_NOT_RISK_CHECKED,
+            )))];
+        }
+        let book = match engine.book(symbol) {
+            Some(book) => book,
+            None => {
+                return vec![Err(Failed::ValidationFailed(String::from(
+                    ERR_UNKNOWN_MARKET,
+                )))]
+            }
+        };
+        if let Err(reason) = book.validate(&order) {
+            return vec![Err(Failed::ValidationFailed(String::from(reason)))];
+        }
+
+        let order_id = order.order_id();
+        if self.contains(order_id) {
+            return vec![Err(Failed::DuplicateOrderID(order_id))];
+        }
+
+        let now = self.clock.now();
+        self.pending.push_back(PendingCheck {
+            account,
+            symbol,
+            order,
+            deadline: now + self.timeout,
+        });
+        vec![Ok(Success::PendingNew { order_id, ts: now })]
+    }
+
+    /// Act on the risk service's answer. Verdicts arriving after the order
+    /// timed out or got cancelled fail with `Failed::OrderNotFound`.
+    pub fn verdict(
+        &mut self,
+        engine: &mut MatchingEngine<Asset>,
+        order_id: Uuid,
+        verdict: RiskVerdict,
+    ) -> OrderProcessingResult<Asset> {
+        match self.take(order_id) {
+            Some(check) => self.settle(engine, check, verdict),
+            None => vec![Err(Failed::OrderNotFound(order_id))],
+        }
+    }
+
+    /// Withdraw order still waiting for its verdict
+    pub fn cancel(&mut self, order_id: Uuid) -> OrderProcessingResult<Asset> {
+        match self.take(order_id) {
+            Some(_) => vec![Ok(Success::Cancelled {
+                order_id,
+                ts: self.clock.now(),
+            })],
+            None => vec![Err(Failed::OrderNotFound(order_id))],
+        }
+    }
+
+    /// Settle orders past their deadline by the timeout policy, oldest first
+    pub fn expire(
+        &mut self,
+        engine: &mut MatchingEngine<Asset>,
+    ) -> Vec<(Symbol<Asset>, OrderProcessingResult<Asset>)> {
+        let now = self.clock.now();
+        let verdict = match self.on_timeout {
+            TimeoutPolicy::Reject => RiskVerdict::Reject,
+            TimeoutPolicy::Accept => RiskVerdict::Approve,
+        };
+
+        let mut settled = vec![];
+        while self
+            .pending
+            .front()
+            .is_some_and(|check| check.deadline <= now)
+        {
+            let check = self.pending.pop_front().unwrap();
+            let symbol = check.symbol;
+            settled.push((symbol, self.settle(engine, check, verdict)));
+        }
+        settled
+    }
+
+    pub fn contains(&self, order_id: Uuid) -> bool {
+        self.pending
+            .iter()
+            .any(|check| check.order.order_id() == order_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /* Helpers */
+
+    fn take(&mut self, order_id: Uuid) -> Option<PendingCheck<Asset>> {
+        let position = self
+            .pending
+            .iter()
+            .position(|check| check.order.order_id() == order_id)?;
+        self.pending.remove(position)
+    }
+
+    fn settle(
+        &mut self,
+        engine: &mut MatchingEngine<Asset>,
+        check: PendingCheck<Asset>,
+        verdict: RiskVerdict,
+    ) -> OrderProcessingResult<Asset> {
+        match verdict {
+            RiskVerdict::Approve => engine.submit_for(check.account, check.symbol, check.order),
+            RiskVerdict::Reject => vec![Err(Failed::RiskRejected(check.order.order_id()))],
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::clock::ManualClock;
+    use super::super::domain::OrderSide;
+    use super::super::errors::CODE_NOT_RISK_CHECKED;
+    use super::super::orders;
+    use super::*;
+    use bigdecimal::BigDecimal;
+    use std::str::FromStr;
+
+    #[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
+    pub enum Asset {
+        USD,
+        BTC,
+    }
+
+    const SYMBOL: Symbol<Asset> = (Asset::BTC, Asset::USD);
+
+    fn bigdec(num: &str) -> BigDecimal {
+        BigDecimal::from_str(num).unwrap()
+    }
+
+    fn limit(price: &str) -> OrderRequest<Asset> {
+        orders::new_limit_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Bid,
+            bigdec(price),
+            bigdec("1"),
+            SystemTime::now(),
+        )
+    }
+
+    #[test]
+    fn two_phase_acceptance() {
+        let clock = ManualClock::new(SystemTime::now());
+        let mut engine = MatchingEngine::new();
+        engine.add_book(Asset::BTC, Asset::USD);
+        let mut checks =
+            RiskCheckedOrders::new(&clock, Duration::from_secs(1), TimeoutPolicy::Reject);
+
+        let approved = limit("100");
+        let approved_id = approved.order_id();
+        match checks.submit(&engine, 1, SYMBOL, approved).as_slice() {
+            [Ok(Success::PendingNew { order_id, .. })] => assert_eq!(*order_id, approved_id),
+            _ => panic!("unexpected events"),
+        }
+        let rejected = limit("99");
+        let rejected_id = rejected.order_id();
+        checks.submit(&engine, 1, SYMBOL, rejected);
+        // invalid orders never wait for a verdict
+        assert!(checks.submit(&engine, 1, SYMBOL, limit("-1"))[0].is_err());
+        let cancel = orders::limit_order_cancel_request(approved_id, OrderSide::Bid);
+        match checks.submit(&engine, 1, SYMBOL, cancel).as_slice() {
+            [Err(failed)] => assert_eq!(failed.code(), CODE_NOT_RISK_CHECKED),
+            _ => panic!("unexpected events"),
+        }
+        assert_eq!(checks.len(), 2);
+        assert!(engine.book(SYMBOL).unwrap().bid_queue.is_empty());
+
+        match checks
+            .verdict(&mut engine, approved_id, RiskVerdict::Approve)
+            .as_slice()
+        {
+            [Ok(Success::Accepted { order_id, .. })] => assert_eq!(*order_id, approved_id),
+            _ => panic!("unexpected events"),
+        }
+        assert_eq!(engine.account_summary(1).open_orders.len(), 1);
+        match checks
+            .verdict(&mut engine, rejected_id, RiskVerdict::Reject)
+            .as_slice()
+        {
+            [Err(Failed::RiskRejected(order_id))] => assert_eq!(*order_id, rejected_id),
+            _ => panic!("unexpected events"),
+        }
+
+        // the service did not answer in time
+        let late = limit("98");
+        let late_id = late.order_id();
+        checks.submit(&engine, 1, SYMBOL, late);
+        clock.advance(Duration::from_millis(500));
+        assert!(checks.expire(&mut engine).is_empty());
+        clock.advance(Duration::from_millis(500));
+        match checks.expire(&mut engine).as_slice() {
+            [(_, results)] => assert!(matches!(results.as_slice(), [Err(Failed::RiskRejected(_))])),
+            _ => panic!("unexpected events"),
+        }
+        assert!(matches!(
+            checks
+                .verdict(&mut engine, late_id, RiskVerdict::Approve)
+                .as_slice(),
+            [Err(Failed::OrderNotFound(_))]
+        ));
+        assert_eq!(engine.book(SYMBOL).unwrap().bid_queue.len(), 1);
+    }
+}
@@ -0,0 +1,247 @@
+use std::fmt::Debug;
+use std::time::SystemTime;
+use uuid::Uuid;
+
+use super::clock::Clock;
+use super::orderbook::{Failed, OrderProcessingResult, Orderbook, Success};
+use super::orders::OrderRequest;
+use super::validation::OrderRequestValidator;
+
+pub(super) const ERR_NOT_NEW_ORDER: &str = "only new orders can be scheduled";
+
+/// Pending area for orders entering the book at a given time.
+///
+/// Orders wait here, invisible to matching, until the clock passes their
+/// activation time. They are released by `activate_due`, in activation
+/// order, with `Success::Activated` followed by the usual processing events.
+pub struct PendingOrders<Asset, C>
+where
+    Asset: Debug + Clone + Copy + Eq,
+    C: Clock,
+{
+    clock: C,
+    /// kept sorted by activation time, scheduling order breaks ties
+    pending: Vec<(SystemTime, OrderRequest<Asset>)>,
+    order_validator: OrderRequestValidator<Asset>,
+}
+
+impl<Asset, C> PendingOrders<Asset, C>
+where
+    Asset: Debug + Clone + Copy + Eq,
+    C: Clock,
+{
+    pub fn new(order_asset: Asset, price_asset: Asset, clock: C) -> Self {
+        PendingOrders {
+            clock,
+            pending: vec![],
+            order_validator: OrderRequestValidator::new(order_asset, price_asset),
+        }
+    }
+
+    /// Park new market or limit order until `activate_at`
+    pub fn schedule(
+        &mut self,
+        order: OrderRequest<Asset>,
+        activate_at: SystemTime,
+    ) -> OrderProcessingResult<Asset> {
+        if !order.is_new_order() {
+            return vec![Err(Failed::ValidationFailed(String::from(
+                ERR_NOT_NEW_ORDER,
+            )))];
+        }
+        if let Err(reason) = self.order_validator.validate(&order) {
+            return vec![Err(Failed::ValidationFailed(String::from(reason)))];
+        }
+
+        let order_id = order.order_id();
+        if self.contains(order_id) {
+            return vec![Err(Failed::DuplicateOrderID(order_id))];
+        }
+
+        let position = self
+            .pending
+            .iter()
+            .position(|(ts, _)| *ts > activate_at)
+            .unwrap_or(self.pending.len());
+        self.pending.insert(position, (activate_at, order));
+
+        vec![Ok(Success::Scheduled {
+            order_id,
+            activate_at,
+            ts: self.clock.now(),
+        })]
+    }
+
+    /// Withdraw order before it becomes active
+    pub fn cancel(&mut self, order_id: Uuid) -> OrderProcessingResult<Asset> {
+        let before = self.pending.len();
+        self.pending
+            .retain(|(_, order)| order.order_id() != order_id);
+
+        if self.pending.len() < before {
+            vec![Ok(Success::Cancelled {
+                order_id,
+                ts: self.clock.now(),
+            })]
+        } else {
+            vec![Err(Failed::OrderNotFound(order_id))]
+        }
+    }
+
+    pub fn contains(&self, order_id: Uuid) -> bool {
+        self.pending
+            .iter()
+            .any(|(_, order)| order.order_id() == order_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Release orders whose activation time has come into the book, stamped
+    /// with the time of release so they queue behind orders already resting
+    pub fn activate_due(&mut self, book: &mut Orderbook<Asset>) -> OrderProcessingResult<Asset> {
+        let now = self.clock.now();
+        let due = self
+            .pending
+            .iter()
+            .take_while(|(activate_at, _)| *activate_at <= now)
+            .count();
+
+        let mut proc_result: OrderProcessingResult<Asset> = vec![];
+        for (_, order) in self.pending.drain(..due) {
+            proc_result.push(Ok(Success::Activated {
+                order_id: order.order_id(),
+                ts: now,
+            }));
+            proc_result.extend(book.process_order(order.with_ts(now)));
+        }
+        proc_result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::clock::ManualClock;
+    use super::super::domain::OrderSide;
+    use super::super::orders;
+    use super::*;
+    use bigdecimal::BigDecimal;
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    #[derive(PartialEq, Eq, Debug, Copy, Clone)]
+    pub enum Asset {
+        USD,
+        BTC,
+    }
+
+    fn bigdec(num: &str) -> BigDecimal {
+        BigDecimal::from_str(num).unwrap()
+    }
+
+    fn limit(side: OrderSide, price: &str) -> OrderRequest<Asset> {
+        orders::new_limit_order_request(
+            Asset::BTC,
+            Asset::USD,
+            side,
+            bigdec(price),
+            bigdec("1"),
+            SystemTime::now(),
+        )
+    }
+
+    #[test]
+    fn activation_order() {
+        let start = SystemTime::now();
+        let clock = ManualClock::new(start);
+        let mut book = Orderbook::new(Asset::BTC, Asset::USD);
+        let mut pending = PendingOrders::new(Asset::BTC, Asset::USD, &clock);
+
+        let late = limit(OrderSide::Bid, "100");
+        let early = limit(OrderSide::Ask, "100");
+        let early_id = early.order_id();
+        pending.schedule(late, start + Duration::from_secs(20));
+        match pending
+            .schedule(early, start + Duration::from_secs(10))
+            .as_slice()
+        {
+            [Ok(Success::Scheduled { order_id, .. })] => assert_eq!(*order_id, early_id),
+            _ => panic!("unexpected events"),
+        }
+
+        assert!(pending.activate_due(&mut book).is_empty());
+
+        clock.advance(Duration::from_secs(30));
+        let results = pending.activate_due(&mut book);
+        // activated, accepted; activated, accepted, filled, filled
+        assert_eq!(results.len(), 6);
+        match &results[0] {
+            Ok(Success::Activated { order_id, .. }) => assert_eq!(*order_id, early_id),
+            _ => panic!("unexpected events"),
+        }
+        assert!(pending.is_empty());
+        assert_eq!(book.current_spread(), None);
+    }
+
+    #[test]
+    fn activated_behind_resting() {
+        let start = SystemTime::now();
+        let clock = ManualClock::new(start);
+        let mut book = Orderbook::new(Asset::BTC, Asset::USD);
+        let mut pending = PendingOrders::new(Asset::BTC, Asset::USD, &clock);
+
+        let scheduled = limit(OrderSide::Bid, "100").with_ts(start);
+        let scheduled_id = scheduled.order_id();
+        pending.schedule(scheduled, start + Duration::from_secs(10));
+
+        // rests while the scheduled order waits
+        clock.advance(Duration::from_secs(5));
+        let resting = limit(OrderSide::Bid, "100").with_ts(clock.now());
+        let resting_id = resting.order_id();
+        book.process_order(resting);
+
+        clock.advance(Duration::from_secs(5));
+        pending.activate_due(&mut book);
+        assert!(book.bid_queue.get(scheduled_id).is_some());
+
+        let results = book.process_order(limit(OrderSide::Ask, "100"));
+        match &results[1] {
+            Ok(Success::Filled { counterparty, .. }) => assert_eq!(*counterparty, resting_id),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn cancel_pending() {
+        let clock = ManualClock::new(SystemTime::now());
+        let mut book = Orderbook::new(Asset::BTC, Asset::USD);
+        let mut pending = PendingOrders::new(Asset::BTC, Asset::USD, &clock);
+
+        let order = limit(OrderSide::Bid, "100");
+        let order_id = order.order_id();
+        pending.schedule(order, clock.now() + Duration::from_secs(1));
+
+        match pending.cancel(order_id).as_slice() {
+            [Ok(Success::Cancelled { .. })] => (),
+            _ => panic!("unexpected events"),
+        }
+        match pending.cancel(order_id).as_slice() {
+            [Err(Failed::OrderNotFound(_))] => (),
+            _ => panic!("unexpected events"),
+        }
+
+        clock.advance(Duration::from_secs(2));
+        assert!(pending.activate_due(&mut book).is_empty());
+
+        let request = orders::limit_order_cancel_request(order_id, OrderSide::Bid);
+        match pending.schedule(request, clock.now()).as_slice() {
+            [Err(Failed::ValidationFailed(_))] => (),
+            _ => panic!("unexpected events"),
+        }
+    }
+}
@@ -0,0 +1,290 @@
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::time::SystemTime;
+
+use super::clock::Clock;
+use super::engine::{MatchingEngine, Symbol};
+use super::orderbook::OrderProcessingResult;
+
+/// Trading hours of a single session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Session {
+    pub open: SystemTime,
+    pub close: SystemTime,
+}
+
+/// What happens to resting orders when a market is halted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HaltPolicy {
+    /// keep the book as is for the resumption
+    #[default]
+    LeaveIntact,
+    CancelAll,
+    /// cancel orders of accounts flagged as market makers only
+    CancelQuotes,
+}
+
+/// Session transition observed by the calendar
+#[derive(Debug)]
+pub enum SessionEvent<Asset> {
+    Opened {
+        session: Session,
+    },
+    /// carries expiry events of DAY orders removed at the close
+    Closed {
+        session: Session,
+        expired: OrderProcessingResult<Asset>,
+    },
+    /// carries cancellations made by the halt policy
+    Halted {
+        symbol: Symbol<Asset>,
+        cancelled: OrderProcessingResult<Asset>,
+    },
+    Resumed {
+        symbol: Symbol<Asset>,
+    },
+}
+
+/// Opens and closes sessions following the injected clock.
+///
+/// On close every DAY order resting in the engine expires, GTC orders are
+/// carried into the next session untouched. Halting a single market applies
+/// the calendar's halt policy to its book.
+pub struct TradingCalendar<C>
+where
+    C: Clock,
+{
+    clock: C,
+    upcoming: VecDeque<Session>,
+    current: Option<Session>,
+    halt_policy: HaltPolicy,
+}
+
+impl<C> TradingCalendar<C>
+where
+    C: Clock,
+{
+    /// Create calendar for the given sessions, in any order
+    pub fn new(clock: C, mut sessions: Vec<Session>) -> Self {
+        sessions.sort_by_key(|session| session.open);
+        TradingCalendar {
+            clock,
+            upcoming: sessions.into(),
+            current: None,
+            halt_policy: HaltPolicy::default(),
+        }
+    }
+
+    pub fn set_halt_policy(&mut self, policy: HaltPolicy) {
+        self.halt_policy = policy;
+    }
+
+    pub fn halt_policy(&self) -> HaltPolicy {
+        self.halt_policy
+    }
+
+    /// Session open as of the last poll
+    pub fn current(&self) -> Option<Session> {
+        self.current
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.current.is_some()
+    }
+
+    /// Apply session transitions due by now to the engine
+    pub fn poll<Asset>(&mut self, engine: &mut MatchingEngine<Asset>) -> Vec<SessionEvent<Asset>>
+    where
+        Asset: Debug + Clone + Copy + Eq + Hash,
+    {
+        let now = self.clock.now();
+        let mut events = vec![];
+
+        loop {
+            match self.current {
+                Some(session) if now >= session.close => {
+                    self.current = None;
+                    events.push(SessionEvent::Closed {
+                        session,
+                        expired: engine.expire_day_orders(session.close),
+                    });
+                }
+                Some(_) => break,
+                None => match self.upcoming.front() {
+                    Some(next) if now >= next.open => {
+                        let session = self.upcoming.pop_front().unwrap();
+                        // a session missed entirely is still opened and closed
+                        self.current = Some(session);
+                        engine.roll_day();
+                        events.push(SessionEvent::Opened { session });
+                    }
+                    _ => break,
+                },
+            }
+        }
+
+        events
+    }
+
+    /// Halt the market, None if it is unknown or halted already
+    pub fn halt<Asset>(
+        &mut self,
+        engine: &mut MatchingEngine<Asset>,
+        symbol: Symbol<Asset>,
+    ) -> Option<SessionEvent<Asset>>
+    where
+        Asset: Debug + Clone + Copy + Eq + Hash,
+    {
+        if engine.book(symbol).is_none() || engine.is_halted(symbol) {
+            return None;
+        }
+        let cancelled = engine.halt(symbol, self.halt_policy);
+        Some(SessionEvent::Halted { symbol, cancelled })
+    }
+
+    /// Resume the halted market, None if it was not halted
+    pub fn resume<Asset>(
+        &mut self,
+        engine: &mut MatchingEngine<Asset>,
+        symbol: Symbol<Asset>,
+    ) -> Option<SessionEvent<Asset>>
+    where
+        Asset: Debug + Clone + Copy + Eq + Hash,
+    {
+        if engine.resume(symbol) {
+            Some(SessionEvent::Resumed { symbol })
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::clock::ManualClock;
+    use super::super::domain::{OrderSide, TimeInForce};
+    use super::super::orderbook::{Failed, Success};
+    use super::super::orders;
+    use super::*;
+    use bigdecimal::BigDecimal;
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    #[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
+    pub enum Asset {
+        USD,
+        BTC,
+    }
+
+    fn bigdec(num: &str) -> BigDecimal {
+        BigDecimal::from_str(num).unwrap()
+    }
+
+    fn bid(price: &str, tif: TimeInForce) -> orders::OrderRequest<Asset> {
+        orders::new_limit_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Bid,
+            bigdec(price),
+            bigdec("1"),
+            SystemTime::now(),
+        )
+        .with_time_in_force(tif)
+    }
+
+    #[test]
+    fn day_orders_expire_at_close() {
+        let start = SystemTime::now();
+        let hour = Duration::from_secs(3600);
+        let clock = ManualClock::new(start);
+        let mut engine = MatchingEngine::new();
+        engine.add_book(Asset::BTC, Asset::USD);
+        let symbol = (Asset::BTC, Asset::USD);
+
+        let mut calendar = TradingCalendar::new(
+            &clock,
+            vec![
+                Session {
+                    open: start + hour * 24,
+                    close: start + hour * 32,
+                },
+                Session {
+                    open: start,
+                    close: start + hour * 8,
+                },
+            ],
+        );
+
+        match calendar.poll(&mut engine).as_slice() {
+            [SessionEvent::Opened { session }] => assert_eq!(session.open, start),
+            _ => panic!("unexpected events"),
+        }
+        assert!(calendar.is_open());
+
+        let day = bid("99", TimeInForce::Day);
+        let day_id = day.order_id();
+        engine.submit(symbol, day);
+        engine.submit(symbol, bid("98", TimeInForce::GTC));
+
+        clock.advance(hour * 9);
+        match calendar.poll(&mut engine).as_slice() {
+            [SessionEvent::Closed { expired, .. }] => match expired.as_slice() {
+                [Ok(Success::Expired { order_id, ts })] => {
+                    assert_eq!(*order_id, day_id);
+                    assert_eq!(*ts, start + hour * 8);
+                }
+                _ => panic!("unexpected events"),
+            },
+            _ => panic!("unexpected events"),
+        }
+        assert!(!calendar.is_open());
+
+        // GTC order carried into the next session
+        clock.advance(hour * 16);
+        assert_eq!(calendar.poll(&mut engine).len(), 1);
+        let book = engine.book(symbol).unwrap();
+        assert_eq!(book.bid_queue.len(), 1);
+        assert_eq!(book.bid_queue.iter().next().unwrap().price, bigdec("98"));
+    }
+
+    #[test]
+    fn halt_cancels_quotes() {
+        let clock = ManualClock::new(SystemTime::now());
+        let mut engine = MatchingEngine::new();
+        engine.add_book(Asset::BTC, Asset::USD);
+        let symbol = (Asset::BTC, Asset::USD);
+        engine.set_market_maker(1, true);
+
+        let quote = bid("99", TimeInForce::GTC);
+        let quote_id = quote.order_id();
+        engine.submit_for(1, symbol, quote);
+        engine.submit_for(2, symbol, bid("98", TimeInForce::GTC));
+
+        let mut calendar = TradingCalendar::new(&clock, vec![]);
+        calendar.set_halt_policy(HaltPolicy::CancelQuotes);
+        match calendar.halt(&mut engine, symbol) {
+            Some(SessionEvent::Halted { cancelled, .. }) => match cancelled.as_slice() {
+                [Ok(Success::Cancelled { order_id, .. })] => assert_eq!(*order_id, quote_id),
+                _ => panic!("unexpected events"),
+            },
+            _ => panic!("unexpected events"),
+        }
+        assert!(calendar.halt(&mut engine, symbol).is_none());
+
+        // only cancels get through while halted
+        match engine.submit(symbol, bid("97", TimeInForce::GTC)).as_slice() {
+            [Err(Failed::ValidationFailed(_))] => (),
+            _ => panic!("unexpected events"),
+        }
+        assert!(engine.account_summary(1).open_orders.is_empty());
+        assert_eq!(engine.account_summary(2).open_orders.len(), 1);
+
+        assert!(calendar.resume(&mut engine, symbol).is_some());
+        assert!(calendar.resume(&mut engine, symbol).is_none());
+        match engine.submit(symbol, bid("97", TimeInForce::GTC)).as_slice() {
+            [Ok(Success::Accepted { .. })] => (),
+            _ => panic!("unexpected events"),
+        }
+    }
+}
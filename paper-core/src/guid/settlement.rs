@@ -0,0 +1,264 @@
+use bigdecimal::{BigDecimal, Zero};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::time::SystemTime;
+use uuid::Uuid;
+
+use super::domain::{AccountId, OrderSide};
+use super::engine::Symbol;
+use super::orderbook::{OrderProcessingResult, Success};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SettlementDirection {
+    /// account has to hand over the amount
+    Deliver,
+    /// account is due to get the amount
+    Receive,
+}
+
+/// Net movement of one asset for one account over the session.
+///
+/// Serialized as:
+/// ```json
+/// {"account": 7, "asset": "BTC", "direction": "receive", "amount": "0.25", "ts": {...}}
+/// ```
+/// where `amount` is always positive and `ts` is the session close time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SettlementInstruction<Asset> {
+    pub account: AccountId,
+    pub asset: Asset,
+    pub direction: SettlementDirection,
+    pub amount: BigDecimal,
+    pub ts: SystemTime,
+}
+
+/// Accumulates the day's fills and nets them per account and asset
+pub struct SettlementBook<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq + Hash,
+{
+    owners: HashMap<Uuid, (AccountId, Symbol<Asset>)>,
+    balances: HashMap<(AccountId, Asset), BigDecimal>,
+}
+
+impl<Asset> Default for SettlementBook<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq + Hash,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Asset> SettlementBook<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq + Hash,
+{
+    pub fn new() -> Self {
+        SettlementBook {
+            owners: HashMap::new(),
+            balances: HashMap::new(),
+        }
+    }
+
+    /// Attribute order to the account so its fills can be settled
+    pub fn register_order(&mut self, order_id: Uuid, account: AccountId, symbol: Symbol<Asset>) {
+        self.owners.insert(order_id, (account, symbol));
+    }
+
+    /// Accumulate fills of registered orders from processing results,
+    /// dropping attribution of orders that are done
+    pub fn apply(&mut self, results: &OrderProcessingResult<Asset>) {
+        for result in results {
+            match result {
+                Ok(Success::Filled {
+                    order_id,
+                    side,
+                    price,
+                    qty,
+                    ..
+                }) => {
+                    self.record_fill(*order_id, *side, price, qty);
+                    self.owners.remove(order_id);
+                }
+                Ok(Success::PartiallyFilled {
+                    order_id,
+                    side,
+                    price,
+                    qty,
+                    ..
+                }) => self.record_fill(*order_id, *side, price, qty),
+                Ok(Success::Cancelled { order_id, .. })
+                | Ok(Success::Expired { order_id, .. })
+                | Ok(Success::CancelledRemainder { order_id, .. })
+                | Ok(Success::DustCancelled { order_id, .. }) => {
+                    self.owners.remove(order_id);
+                }
+                Ok(Success::SelfTradePrevented {
+                    order_id,
+                    remaining,
+                    ..
+                }) if remaining.is_zero() => {
+                    self.owners.remove(order_id);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Orders whose fills are still attributed to an account
+    pub fn registered(&self) -> usize {
+        self.owners.len()
+    }
+
+    /// Net the session into settlement instructions and start a new session.
+    ///
+    /// Instructions are ordered by account. Order attribution is kept, so
+    /// orders resting over the close settle in the session they fill.
+    pub fn close_session(&mut self, ts: SystemTime) -> Vec<SettlementInstruction<Asset>> {
+        let mut instructions: Vec<SettlementInstruction<Asset>> = self
+            .balances
+            .drain()
+            .filter(|(_, amount)| !amount.is_zero())
+            .map(|((account, asset), amount)| SettlementInstruction {
+                account,
+                asset,
+                direction: if amount > BigDecimal::zero() {
+                    SettlementDirection::Receive
+                } else {
+                    SettlementDirection::Deliver
+                },
+                amount: amount.abs(),
+                ts,
+            })
+            .collect();
+        instructions.sort_by_key(|instruction| instruction.account);
+        instructions
+    }
+
+    /* Helpers */
+
+    fn record_fill(
+        &mut self,
+        order_id: Uuid,
+        side: OrderSide,
+        price: &BigDecimal,
+        qty: &BigDecimal,
+    ) {
+        let (account, (order_asset, price_asset)) = match self.owners.get(&order_id) {
+            Some(owner) => *owner,
+            None => return,
+        };
+
+        let notional = price * qty;
+        let (order_delta, price_delta) = match side {
+            OrderSide::Bid => (qty.clone(), -notional),
+            OrderSide::Ask => (-qty, notional),
+        };
+
+        *self
+            .balances
+            .entry((account, order_asset))
+            .or_insert_with(BigDecimal::zero) += order_delta;
+        *self
+            .balances
+            .entry((account, price_asset))
+            .or_insert_with(BigDecimal::zero) += price_delta;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::orderbook::Orderbook;
+    use super::super::orders;
+    use super::*;
+    use std::str::FromStr;
+
+    #[derive(PartialEq, Eq, Hash, Debug, Copy, Clone, Serialize, Deserialize)]
+    pub enum Asset {
+        USD,
+        BTC,
+    }
+
+    fn bigdec(num: &str) -> BigDecimal {
+        BigDecimal::from_str(num).unwrap()
+    }
+
+    #[test]
+    fn net_session_fills() {
+        let symbol = (Asset::BTC, Asset::USD);
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        let mut settlement = SettlementBook::new();
+
+        let requests = vec![
+            (
+                1,
+                orders::new_limit_order_request(
+                    Asset::BTC,
+                    Asset::USD,
+                    OrderSide::Ask,
+                    bigdec("100"),
+                    bigdec("1"),
+                    SystemTime::now(),
+                ),
+            ),
+            (
+                2,
+                orders::new_market_order_request(
+                    Asset::BTC,
+                    Asset::USD,
+                    OrderSide::Bid,
+                    bigdec("0.25"),
+                    SystemTime::now(),
+                ),
+            ),
+            (
+                2,
+                orders::new_market_order_request(
+                    Asset::BTC,
+                    Asset::USD,
+                    OrderSide::Bid,
+                    bigdec("0.25"),
+                    SystemTime::now(),
+                ),
+            ),
+        ];
+        for (account, request) in requests {
+            settlement.register_order(request.order_id(), account, symbol);
+            let results = orderbook.process_order(request);
+            settlement.apply(&results);
+        }
+
+        let close = SystemTime::now();
+        let mut instructions = settlement.close_session(close);
+        instructions.sort_by_key(|i| (i.account, i.asset == Asset::USD));
+        let expected = vec![
+            (1, Asset::BTC, SettlementDirection::Deliver, "0.5"),
+            (1, Asset::USD, SettlementDirection::Receive, "50"),
+            (2, Asset::BTC, SettlementDirection::Receive, "0.5"),
+            (2, Asset::USD, SettlementDirection::Deliver, "50"),
+        ];
+        assert_eq!(instructions.len(), expected.len());
+        for (instruction, (account, asset, direction, amount)) in instructions.iter().zip(expected)
+        {
+            assert_eq!(instruction.account, account);
+            assert_eq!(instruction.asset, asset);
+            assert_eq!(instruction.direction, direction);
+            assert_eq!(instruction.amount, bigdec(amount));
+            assert_eq!(instruction.ts, close);
+        }
+
+        // new session starts empty
+        assert!(settlement.close_session(close).is_empty());
+        // only the ask resting with 0.5 left is still attributed
+        assert_eq!(settlement.registered(), 1);
+        let ask_id = *settlement.owners.keys().next().unwrap();
+        settlement.apply(
+            &orderbook.process_order(orders::limit_order_cancel_request(ask_id, OrderSide::Ask)),
+        );
+        assert_eq!(settlement.registered(), 0);
+    }
+}
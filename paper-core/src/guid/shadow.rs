@@ -0,0 +1,295 @@
+use std::fmt::Debug;
+
+use super::orderbook::{Failed, OrderProcessingResult, Orderbook, Success};
+use super::orders::OrderRequest;
+
+/// Anything able to process order requests into orderbook events
+pub trait MatchingBook<Asset>
+where
+    Asset: Debug + Clone,
+{
+    fn process_order(&mut self, order: OrderRequest<Asset>) -> OrderProcessingResult<Asset>;
+}
+
+impl<Asset> MatchingBook<Asset> for Orderbook<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    fn process_order(&mut self, order: OrderRequest<Asset>) -> OrderProcessingResult<Asset> {
+        Orderbook::process_order(self, order)
+    }
+}
+
+/// Request after which the two implementations produced different events
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    /// position of the request in the stream, starting from 0
+    pub request_index: u64,
+    pub primary: Vec<String>,
+    pub shadow: Vec<String>,
+}
+
+/// Runs a shadow implementation next to the primary one on the same requests.
+///
+/// Callers only ever see the primary results. Events of both are compared
+/// with timestamps stripped, and every mismatch is recorded as a divergence.
+pub struct ShadowBook<Asset, P, S>
+where
+    Asset: Debug + Clone,
+    P: MatchingBook<Asset>,
+    S: MatchingBook<Asset>,
+{
+    primary: P,
+    shadow: S,
+    processed: u64,
+    divergences: Vec<Divergence>,
+    _asset: std::marker::PhantomData<Asset>,
+}
+
+impl<Asset, P, S> ShadowBook<Asset, P, S>
+where
+    Asset: Debug + Clone,
+    P: MatchingBook<Asset>,
+    S: MatchingBook<Asset>,
+{
+    pub fn new(primary: P, shadow: S) -> Self {
+        ShadowBook {
+            primary,
+            shadow,
+            processed: 0,
+            divergences: vec![],
+            _asset: std::marker::PhantomData,
+        }
+    }
+
+    pub fn primary(&self) -> &P {
+        &self.primary
+    }
+
+    pub fn shadow(&self) -> &S {
+        &self.shadow
+    }
+
+    /// Divergences found so far
+    pub fn divergences(&self) -> &[Divergence] {
+        &self.divergences
+    }
+
+    /// Take divergences found so far, e.g. to report them periodically
+    pub fn drain_divergences(&mut self) -> Vec<Divergence> {
+        std::mem::take(&mut self.divergences)
+    }
+
+    /// Split back into the primary and shadow books
+    pub fn into_inner(self) -> (P, S) {
+        (self.primary, self.shadow)
+    }
+}
+
+impl<Asset, P, S> MatchingBook<Asset> for ShadowBook<Asset, P, S>
+where
+    Asset: Debug + Clone,
+    P: MatchingBook<Asset>,
+    S: MatchingBook<Asset>,
+{
+    fn process_order(&mut self, order: OrderRequest<Asset>) -> OrderProcessingResult<Asset> {
+        let shadow_results = self.shadow.process_order(order.clone());
+        let primary_results = self.primary.process_order(order);
+
+        let primary: Vec<String> = primary_results.iter().map(fingerprint).collect();
+        let shadow: Vec<String> = shadow_results.iter().map(fingerprint).collect();
+        if primary != shadow {
+            self.divergences.push(Divergence {
+                request_index: self.processed,
+                primary,
+                shadow,
+            });
+        }
+        self.processed += 1;
+
+        primary_results
+    }
+}
+
+/// Textual event description without timestamps
+fn fingerprint<Asset>(result: &Result<Success<Asset>, Failed>) -> String
+where
+    Asset: Debug,
+{
+    match result {
+        Ok(Success::Accepted {
+            order_id,
+            order_asset,
+            order_type,
+            price_asset,
+            price,
+            collared_from,
+            qty,
+            side,
+            liquidation,
+            ..
+        }) => format!(
+            "Accepted {} {:?}/{:?} {} {} {:?} {:?} {} {}",
+            order_id,
+            order_asset,
+            price_asset,
+            order_type,
+            side,
+            price,
+            collared_from,
+            qty,
+            liquidation
+        ),
+        Ok(Success::Filled {
+            order_id,
+            counterparty,
+            side,
+            aggressor,
+            order_type,
+            price,
+            qty,
+            liquidation,
+            ..
+        }) => format!(
+            "Filled {} {} {} {} {} {} {} {}",
+            order_id, counterparty, side, aggressor, order_type, price, qty, liquidation
+        ),
+        Ok(Success::PartiallyFilled {
+            order_id,
+            counterparty,
+            side,
+            aggressor,
+            order_type,
+            price,
+            qty,
+            liquidation,
+            ..
+        }) => format!(
+            "PartiallyFilled {} {} {} {} {} {} {} {}",
+            order_id, counterparty, side, aggressor, order_type, price, qty, liquidation
+        ),
+        Ok(Success::Amended {
+            order_id,
+            price,
+            qty,
+            ..
+        }) => format!("Amended {} {} {}", order_id, price, qty),
+        Ok(Success::Cancelled { order_id, .. }) => format!("Cancelled {}", order_id),
+        Ok(Success::StopAccepted {
+            order_id,
+            side,
+            trigger_price,
+            price,
+            qty,
+            liquidation,
+            ..
+        }) => format!(
+            "StopAccepted {} {} {} {:?} {} {}",
+            order_id, side, trigger_price, price, qty, liquidation
+        ),
+        Ok(Success::Triggered {
+            order_id,
+            last_price,
+            ..
+        }) => format!("Triggered {} {}", order_id, last_price),
+        Ok(Success::PendingNew { order_id, .. }) => format!("PendingNew {}", order_id),
+        Ok(Success::Scheduled {
+            order_id,
+            activate_at,
+            ..
+        }) => format!("Scheduled {} {:?}", order_id, activate_at),
+        Ok(Success::Activated { order_id, .. }) => format!("Activated {}", order_id),
+        Ok(Success::Expired { order_id, .. }) => format!("Expired {}", order_id),
+        Ok(Success::CancelledRemainder {
+            order_id,
+            unfilled_qty,
+            ..
+        }) => format!("CancelledRemainder {} {}", order_id, unfilled_qty),
+        Ok(Success::SelfTradePrevented {
+            order_id,
+            owner,
+            qty,
+            remaining,
+            ..
+        }) => format!(
+            "SelfTradePrevented {} {} {} {}",
+            order_id, owner, qty, remaining
+        ),
+        Ok(Success::DustCancelled {
+            order_id, side, qty, ..
+        }) => format!("DustCancelled {} {} {}", order_id, side, qty),
+        Err(failed) => format!("{:?}", failed),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::domain::OrderSide;
+    use super::super::orders;
+    use super::*;
+    use bigdecimal::BigDecimal;
+    use std::str::FromStr;
+    use std::time::SystemTime;
+
+    #[derive(PartialEq, Eq, Debug, Copy, Clone)]
+    pub enum Asset {
+        USD,
+        BTC,
+    }
+
+    fn bigdec(num: &str) -> BigDecimal {
+        BigDecimal::from_str(num).unwrap()
+    }
+
+    fn ask(price: &str) -> OrderRequest<Asset> {
+        orders::new_limit_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Ask,
+            bigdec(price),
+            bigdec("1"),
+            SystemTime::now(),
+        )
+    }
+
+    fn market_bid() -> OrderRequest<Asset> {
+        orders::new_market_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Bid,
+            bigdec("1"),
+            SystemTime::now(),
+        )
+    }
+
+    #[test]
+    fn identical_books_agree() {
+        let mut book = ShadowBook::new(
+            Orderbook::new(Asset::BTC, Asset::USD),
+            Orderbook::new(Asset::BTC, Asset::USD),
+        );
+        book.process_order(ask("101"));
+        book.process_order(ask("100"));
+        let results = book.process_order(market_bid());
+
+        assert_eq!(results.len(), 3);
+        assert!(book.divergences().is_empty());
+    }
+
+    #[test]
+    fn divergence_reported() {
+        let mut shadow = Orderbook::new(Asset::BTC, Asset::USD);
+        shadow.set_liquidation_priority(true);
+        let mut book = ShadowBook::new(Orderbook::new(Asset::BTC, Asset::USD), shadow);
+
+        book.process_order(ask("100"));
+        book.process_order(ask("100").with_liquidation_flag());
+        book.process_order(market_bid());
+
+        let divergences = book.drain_divergences();
+        assert_eq!(divergences.len(), 1);
+        assert_eq!(divergences[0].request_index, 2);
+        assert_ne!(divergences[0].primary, divergences[0].shadow);
+        assert!(book.divergences().is_empty());
+    }
+}
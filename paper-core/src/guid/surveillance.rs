@@ -0,0 +1,107 @@
+use bigdecimal::BigDecimal;
+use std::time::SystemTime;
+use uuid::Uuid;
+
+use super::domain::{AccountId, OrderSide};
+use super::engine::Symbol;
+
+/// Caps on orders a single account may stack on one side of a book.
+///
+/// Counts include the new order, so a `max_per_level` of 3 lets an account
+/// rest three orders at the same price.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StackingLimits {
+    /// orders at the very same price
+    pub max_per_level: Option<usize>,
+    /// orders priced within `max_in_range.0` of the new order, either way
+    pub max_in_range: Option<(BigDecimal, usize)>,
+}
+
+/// Stacking limit an order ran into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackingRule {
+    PriceLevel,
+    PriceRange,
+}
+
+/// Record of a rejected order for compliance review
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComplianceEvent<Asset> {
+    pub account: AccountId,
+    pub symbol: Symbol<Asset>,
+    pub order_id: Uuid,
+    pub side: OrderSide,
+    pub price: BigDecimal,
+    pub rule: StackingRule,
+    /// orders the account would have had in the level or range
+    pub count: usize,
+    pub ts: SystemTime,
+}
+
+impl StackingLimits {
+    /// Check an order at `price` against the prices of the account's other
+    /// orders resting on the same side
+    pub fn check<'a, I>(&self, price: &BigDecimal, resting: I) -> Result<(), (StackingRule, usize)>
+    where
+        I: IntoIterator<Item = &'a BigDecimal>,
+    {
+        let (mut at_level, mut in_range) = (1, 1);
+        for other in resting {
+            if other == price {
+                at_level += 1;
+            }
+            if let Some((width, _)) = &self.max_in_range {
+                if (other - price).abs() <= *width {
+                    in_range += 1;
+                }
+            }
+        }
+
+        if self.max_per_level.is_some_and(|max| at_level > max) {
+            return Err((StackingRule::PriceLevel, at_level));
+        }
+        match &self.max_in_range {
+            Some((_, max)) if in_range > *max => Err((StackingRule::PriceRange, in_range)),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+
+    fn bigdec(num: &str) -> BigDecimal {
+        BigDecimal::from_str(num).unwrap()
+    }
+
+    #[test]
+    fn level_and_range_limits() {
+        let limits = StackingLimits {
+            max_per_level: Some(2),
+            max_in_range: Some((bigdec("1"), 3)),
+        };
+        let resting = [bigdec("100"), bigdec("101"), bigdec("103")];
+
+        assert_eq!(limits.check(&bigdec("100"), &resting[..1]), Ok(()));
+        assert_eq!(
+            limits.check(&bigdec("100"), &[bigdec("100"), bigdec("100")]),
+            Err((StackingRule::PriceLevel, 3))
+        );
+        // 100 and 101 within range, 103 is not
+        assert_eq!(limits.check(&bigdec("100.5"), &resting), Ok(()));
+        assert_eq!(limits.check(&bigdec("102"), &resting), Ok(()));
+        assert_eq!(
+            limits.check(
+                &bigdec("100"),
+                &[bigdec("99"), bigdec("100.5"), bigdec("101")]
+            ),
+            Err((StackingRule::PriceRange, 4))
+        );
+        assert_eq!(
+            StackingLimits::default().check(&bigdec("1"), &resting),
+            Ok(())
+        );
+    }
+}
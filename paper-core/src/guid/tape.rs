@@ -0,0 +1,170 @@
+use bigdecimal::BigDecimal;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::ops::Range;
+use std::time::SystemTime;
+use uuid::Uuid;
+
+use super::domain::{AccountId, OrderSide};
+use super::engine::Symbol;
+use super::fees::LiquidityRole;
+use super::orderbook::{OrderProcessingResult, Success};
+use super::trade_ids::TradeId;
+
+/// Fill kept on the tape
+#[derive(Debug, Clone, PartialEq)]
+pub struct TapeFill<Asset> {
+    pub trade_id: TradeId,
+    pub order_id: Uuid,
+    /// owner of the order, None for orders not submitted for an account
+    pub account: Option<AccountId>,
+    pub symbol: Symbol<Asset>,
+    pub side: OrderSide,
+    pub role: LiquidityRole,
+    pub price: BigDecimal,
+    pub qty: BigDecimal,
+    pub ts: SystemTime,
+}
+
+/// Which page of a query to return
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageRequest {
+    /// `next` of the previous page, None for the first page
+    pub cursor: Option<u64>,
+    pub limit: usize,
+}
+
+/// Single page of fills, oldest first
+#[derive(Debug, PartialEq)]
+pub struct TradePage<'a, Asset> {
+    pub fills: Vec<&'a TapeFill<Asset>>,
+    /// cursor of the following page, None once the query is exhausted
+    pub next: Option<u64>,
+}
+
+/// Fills of every market in processing order, indexed by account.
+///
+/// Both fills of a trade are kept, so an account trading with itself sees
+/// both sides. The tape grows for the life of the engine, gateways answering
+/// "my trades" queries over longer periods should archive it.
+#[derive(Debug)]
+pub struct TradeTape<Asset> {
+    fills: Vec<TapeFill<Asset>>,
+    /// positions in `fills` of every account's fills
+    by_account: HashMap<AccountId, Vec<usize>>,
+}
+
+impl<Asset> Default for TradeTape<Asset> {
+    fn default() -> Self {
+        TradeTape {
+            fills: vec![],
+            by_account: HashMap::new(),
+        }
+    }
+}
+
+impl<Asset> TradeTape<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq + Hash,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append fills found in processing results of the market, `owner`
+    /// attributes orders to accounts
+    pub fn record<F>(
+        &mut self,
+        symbol: Symbol<Asset>,
+        owner: F,
+        results: &OrderProcessingResult<Asset>,
+    ) where
+        F: Fn(Uuid) -> Option<AccountId>,
+    {
+        for result in results {
+            let (order_id, trade_id, side, role, price, qty, ts) = match result {
+                Ok(Success::Filled {
+                    order_id,
+                    trade_id,
+                    side,
+                    role,
+                    price,
+                    qty,
+                    ts,
+                    ..
+                })
+                | Ok(Success::PartiallyFilled {
+                    order_id,
+                    trade_id,
+                    side,
+                    role,
+                    price,
+                    qty,
+                    ts,
+                    ..
+                }) => (order_id, trade_id, side, role, price, qty, ts),
+                _ => continue,
+            };
+            let account = owner(*order_id);
+            if let Some(account) = account {
+                self.by_account
+                    .entry(account)
+                    .or_default()
+                    .push(self.fills.len());
+            }
+            self.fills.push(TapeFill {
+                trade_id: *trade_id,
+                order_id: *order_id,
+                account,
+                symbol,
+                side: *side,
+                role: *role,
+                price: price.clone(),
+                qty: qty.clone(),
+                ts: *ts,
+            });
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.fills.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fills.is_empty()
+    }
+
+    /// Fills of the account timed within `range`, a page at a time
+    pub fn trades_for_account(
+        &self,
+        account: AccountId,
+        range: Range<SystemTime>,
+        page: PageRequest,
+    ) -> TradePage<'_, Asset> {
+        let index = match self.by_account.get(&account) {
+            Some(index) => index,
+            None => {
+                return TradePage {
+                    fills: vec![],
+                    next: None,
+                }
+            }
+        };
+
+        let start = page.cursor.map_or(0, |cursor| cursor as usize);
+        let mut matching = index
+            .iter()
+            .enumerate()
+            .skip(start)
+            .filter(|(_, position)| range.contains(&self.fills[**position].ts));
+        let fills = matching
+            .by_ref()
+            .take(page.limit)
+            .map(|(_, position)| &self.fills[*position])
+            .collect();
+        // the cursor points at the first fill of the next page
+        let next = matching.next().map(|(cursor, _)| cursor as u64);
+        TradePage { fills, next }
+    }
+}
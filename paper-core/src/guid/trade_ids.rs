@@ -0,0 +1,200 @@
+use serde::de::{self, Deserializer};
+use serde::{Deserialize, Serialize, Serializer};
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, UNIX_EPOCH};
+use uuid::Uuid;
+
+use super::clock::{Clock, SystemClock};
+
+/// Milliseconds since the Unix epoch of 2020-01-01T00:00:00Z
+const SNOWFLAKE_EPOCH_MS: u64 = 1_577_836_800_000;
+const SNOWFLAKE_NODE_BITS: u32 = 10;
+const SNOWFLAKE_SEQ_BITS: u32 = 12;
+
+/// Execution identifier shared by both fills of a trade
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TradeId(pub u128);
+
+impl fmt::Display for TradeId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Serialized as a decimal string, 128-bit numbers are not portable
+impl Serialize for TradeId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for TradeId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let id = String::deserialize(deserializer)?;
+        id.parse().map(TradeId).map_err(de::Error::custom)
+    }
+}
+
+/// Source of trade IDs of a book
+pub trait TradeIdGenerator {
+    fn next_id(&mut self) -> TradeId;
+}
+
+/// Consecutive numbers, unique within a single book.
+///
+/// After a restart continue from the last ID found in the journal with
+/// `starting_after`.
+#[derive(Debug, Clone, Default)]
+pub struct SequentialTradeIds {
+    last: u128,
+}
+
+impl SequentialTradeIds {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn starting_after(last: TradeId) -> Self {
+        SequentialTradeIds { last: last.0 }
+    }
+}
+
+impl TradeIdGenerator for SequentialTradeIds {
+    fn next_id(&mut self) -> TradeId {
+        self.last += 1;
+        TradeId(self.last)
+    }
+}
+
+/// Time-ordered 63-bit IDs: milliseconds since 2020, node and sequence.
+///
+/// Unique across books and restarts as long as every engine instance has its
+/// own node and the clock does not go back by more than the IDs issued ahead.
+/// When a millisecond runs out of sequence numbers the next one is borrowed.
+pub struct SnowflakeTradeIds<C: Clock> {
+    clock: C,
+    node: u16,
+    last_ms: u64,
+    seq: u64,
+}
+
+impl SnowflakeTradeIds<SystemClock> {
+    /// Generator for `node`, only its lowest 10 bits are used
+    pub fn new(node: u16) -> Self {
+        Self::with_clock(node, SystemClock)
+    }
+}
+
+impl<C: Clock> SnowflakeTradeIds<C> {
+    pub fn with_clock(node: u16, clock: C) -> Self {
+        SnowflakeTradeIds {
+            clock,
+            node: node & ((1 << SNOWFLAKE_NODE_BITS) - 1),
+            last_ms: 0,
+            seq: 0,
+        }
+    }
+}
+
+impl<C: Clock> TradeIdGenerator for SnowflakeTradeIds<C> {
+    fn next_id(&mut self) -> TradeId {
+        let now_ms = self
+            .clock
+            .now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or(Duration::ZERO)
+            .as_millis() as u64;
+        let now_ms = now_ms.saturating_sub(SNOWFLAKE_EPOCH_MS);
+
+        if now_ms > self.last_ms {
+            self.last_ms = now_ms;
+            self.seq = 0;
+        } else {
+            self.seq += 1;
+            if self.seq >> SNOWFLAKE_SEQ_BITS != 0 {
+                self.last_ms += 1;
+                self.seq = 0;
+            }
+        }
+
+        let id = (self.last_ms << (SNOWFLAKE_NODE_BITS + SNOWFLAKE_SEQ_BITS))
+            | ((self.node as u64) << SNOWFLAKE_SEQ_BITS)
+            | self.seq;
+        TradeId(id as u128)
+    }
+}
+
+/// Random v4 UUIDs, unique without any coordination
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UuidTradeIds;
+
+impl TradeIdGenerator for UuidTradeIds {
+    fn next_id(&mut self) -> TradeId {
+        TradeId(Uuid::new_v4().as_u128())
+    }
+}
+
+/// Generator shared by several books, e.g. all books of an engine
+impl<G: TradeIdGenerator + ?Sized> TradeIdGenerator for Rc<RefCell<G>> {
+    fn next_id(&mut self) -> TradeId {
+        self.borrow_mut().next_id()
+    }
+}
+
+/// Generator shared by books on several threads
+impl<G: TradeIdGenerator + ?Sized> TradeIdGenerator for Arc<Mutex<G>> {
+    fn next_id(&mut self) -> TradeId {
+        self.lock().unwrap().next_id()
+    }
+}
+
+/// How an engine issues trade IDs to its books
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TradeIdScheme {
+    /// consecutive numbers per symbol
+    #[default]
+    Sequential,
+    /// snowflake IDs of the given node, unique engine-wide
+    Snowflake { node: u16 },
+    Uuid,
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::clock::ManualClock;
+    use super::*;
+    use std::collections::HashSet;
+    use std::time::SystemTime;
+
+    #[test]
+    fn sequential_resumes() {
+        let mut ids = SequentialTradeIds::new();
+        assert_eq!(ids.next_id(), TradeId(1));
+        assert_eq!(ids.next_id(), TradeId(2));
+
+        let mut resumed = SequentialTradeIds::starting_after(TradeId(2));
+        assert_eq!(resumed.next_id(), TradeId(3));
+    }
+
+    #[test]
+    fn snowflake_unique_and_ordered() {
+        let clock = ManualClock::new(SystemTime::now());
+        let mut first = SnowflakeTradeIds::with_clock(1, &clock);
+        let mut second = SnowflakeTradeIds::with_clock(2, &clock);
+
+        // more IDs than a millisecond holds
+        let mut issued = vec![];
+        for _ in 0..5000 {
+            issued.push(first.next_id());
+            issued.push(second.next_id());
+        }
+        let unique: HashSet<TradeId> = issued.iter().copied().collect();
+        assert_eq!(unique.len(), issued.len());
+
+        let mine: Vec<TradeId> = issued.iter().step_by(2).copied().collect();
+        assert!(mine.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+}
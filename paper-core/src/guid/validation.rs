@@ -0,0 +1,295 @@
+
+use std::fmt::Debug;
+use std::time::SystemTime;
+use bigdecimal::{BigDecimal, Zero};
+use uuid::Uuid;
+
+use super::domain::TimeInForce;
+use super::orderbook::QtyLimits;
+use super::orders::{MarketProtection, OrderRequest};
+use super::price_key::PriceScale;
+
+/// Validation errors
+pub(super) const ERR_BAD_ORDER_ASSET: &str = "bad order asset";
+pub(super) const ERR_BAD_PRICE_ASSET: &str = "bad price asset";
+pub(super) const ERR_BAD_PRICE_VALUE: &str = "price must be non-negative";
+pub(super) const ERR_BAD_QUANTITY_VALUE: &str = "quantity must be non-negative";
+pub(super) const ERR_BAD_ORDER_ID: &str = "order ID invalid";
+pub(super) const ERR_EMPTY_AMEND: &str = "nothing to amend";
+pub(super) const ERR_OFF_TICK: &str = "price not on tick size";
+pub(super) const ERR_BAD_EXPIRY: &str = "expiry must be after order time";
+pub(super) const ERR_BAD_PROTECTION: &str = "bad market order protection";
+pub(super) const ERR_OFF_LOT: &str = "quantity not a multiple of the lot size";
+pub(super) const ERR_QTY_BELOW_MIN: &str = "quantity below minimum";
+pub(super) const ERR_QTY_ABOVE_MAX: &str = "quantity above maximum";
+
+/* Validators */
+pub struct OrderRequestValidator<Asset> {
+    orderbook_order_asset: Asset,
+    orderbook_price_asset: Asset,
+    price_scale: Option<PriceScale>,
+    qty_limits: Option<QtyLimits>,
+}
+
+impl<Asset> OrderRequestValidator<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    pub fn new(
+        orderbook_order_asset: Asset,
+        orderbook_price_asset: Asset,
+    ) -> Self {
+        OrderRequestValidator {
+            orderbook_order_asset,
+            orderbook_price_asset,
+            price_scale: None,
+            qty_limits: None,
+        }
+    }
+
+    /// Also reject prices off the tick grid of the scale
+    pub fn with_price_scale(mut self, price_scale: PriceScale) -> Self {
+        self.price_scale = Some(price_scale);
+        self
+    }
+
+    pub fn set_price_scale(&mut self, price_scale: PriceScale) {
+        self.price_scale = Some(price_scale);
+    }
+
+    /// Also reject quantities off the lot size or outside the range
+    pub fn set_qty_limits(&mut self, qty_limits: Option<QtyLimits>) {
+        self.qty_limits = qty_limits;
+    }
+
+    pub fn qty_limits(&self) -> Option<&QtyLimits> {
+        self.qty_limits.as_ref()
+    }
+
+    pub fn validate(&self, request: &OrderRequest<Asset>) -> Result<(), &str> {
+        match request {
+            OrderRequest::NewMarketOrder {
+                order_id: _,
+                order_asset,
+                price_asset,
+                side: _side,
+                qty,
+                ts: _ts,
+                liquidation: _,
+                protection,
+                owner: _,
+            } => {
+                self.validate_protection(protection.as_ref())?;
+                self.validate_market(*order_asset, *price_asset, qty.clone())
+            }
+
+            OrderRequest::NewLimitOrder {
+                order_id: _,
+                order_asset,
+                price_asset,
+                side: _side,
+                price,
+                qty,
+                ts,
+                liquidation: _,
+                time_in_force,
+                owner: _,
+            } => {
+                self.validate_expiry(time_in_force, ts)?;
+                self.validate_limit(*order_asset, *price_asset, price.clone(), qty.clone())
+            }
+
+            OrderRequest::NewStopOrder {
+                order_asset,
+                price_asset,
+                trigger_price,
+                qty,
+                ..
+            } => {
+                self.validate_trigger(trigger_price)?;
+                self.validate_market(*order_asset, *price_asset, qty.clone())
+            }
+
+            OrderRequest::NewStopLimitOrder {
+                order_asset,
+                price_asset,
+                trigger_price,
+                price,
+                qty,
+                ts,
+                time_in_force,
+                ..
+            } => {
+                self.validate_trigger(trigger_price)?;
+                self.validate_expiry(time_in_force, ts)?;
+                self.validate_limit(*order_asset, *price_asset, price.clone(), qty.clone())
+            }
+
+            OrderRequest::AmendOrder {
+                id,
+                price,
+                side: _side,
+                qty,
+                ts: _ts,
+            } => self.validate_amend(*id, price.as_ref(), qty.as_ref()),
+
+            OrderRequest::CancelOrder { id, side: _side } => self.validate_cancel(*id),
+        }
+    }
+
+    /* Internal validators */
+
+    fn validate_market(
+        &self,
+        order_asset: Asset,
+        price_asset: Asset,
+        qty: BigDecimal,
+    ) -> Result<(), &str> {
+
+        if self.orderbook_order_asset != order_asset {
+            return Err(ERR_BAD_ORDER_ASSET);
+        }
+
+        if self.orderbook_price_asset != price_asset {
+            return Err(ERR_BAD_PRICE_ASSET);
+        }
+
+        if qty <= BigDecimal::zero() {
+            return Err(ERR_BAD_QUANTITY_VALUE);
+        }
+
+        self.within_limits(&qty)
+    }
+
+
+    fn validate_limit(
+        &self,
+        order_asset: Asset,
+        price_asset: Asset,
+        price: BigDecimal,
+        qty: BigDecimal,
+    ) -> Result<(), &str> {
+
+        if self.orderbook_order_asset != order_asset {
+            return Err(ERR_BAD_ORDER_ASSET);
+        }
+
+        if self.orderbook_price_asset != price_asset {
+            return Err(ERR_BAD_PRICE_ASSET);
+        }
+
+        if price <= BigDecimal::zero() {
+            return Err(ERR_BAD_PRICE_VALUE);
+        }
+
+        if !self.on_tick(&price) {
+            return Err(ERR_OFF_TICK);
+        }
+
+        if qty <= BigDecimal::zero() {
+            return Err(ERR_BAD_QUANTITY_VALUE);
+        }
+
+        self.within_limits(&qty)
+    }
+
+
+    pub fn validate_amend(
+        &self,
+        id: Uuid,
+        price: Option<&BigDecimal>,
+        qty: Option<&BigDecimal>,
+    ) -> Result<(), &str> {
+        if id == Uuid::nil() {
+            return Err(ERR_BAD_ORDER_ID);
+        }
+
+        if price.is_none() && qty.is_none() {
+            return Err(ERR_EMPTY_AMEND);
+        }
+
+        if price.is_some_and(|price| price <= &BigDecimal::zero()) {
+            return Err(ERR_BAD_PRICE_VALUE);
+        }
+
+        if price.is_some_and(|price| !self.on_tick(price)) {
+            return Err(ERR_OFF_TICK);
+        }
+
+        if qty.is_some_and(|qty| qty <= &BigDecimal::zero()) {
+            return Err(ERR_BAD_QUANTITY_VALUE);
+        }
+
+        match qty {
+            Some(qty) => self.within_limits(qty),
+            None => Ok(()),
+        }
+    }
+
+
+    fn validate_trigger(&self, trigger_price: &BigDecimal) -> Result<(), &str> {
+        if trigger_price <= &BigDecimal::zero() {
+            return Err(ERR_BAD_PRICE_VALUE);
+        }
+
+        Ok(())
+    }
+
+
+    fn validate_protection(&self, protection: Option<&MarketProtection>) -> Result<(), &str> {
+        match protection {
+            Some(MarketProtection::Slippage(slippage)) if slippage < &BigDecimal::zero() => {
+                Err(ERR_BAD_PROTECTION)
+            }
+            Some(MarketProtection::Price(price)) if price <= &BigDecimal::zero() => {
+                Err(ERR_BAD_PROTECTION)
+            }
+            _ => Ok(()),
+        }
+    }
+
+
+    fn validate_expiry(&self, time_in_force: &TimeInForce, ts: &SystemTime) -> Result<(), &str> {
+        match time_in_force {
+            TimeInForce::GTD(expiry) if expiry <= ts => Err(ERR_BAD_EXPIRY),
+            _ => Ok(()),
+        }
+    }
+
+
+    fn validate_cancel(&self, id: Uuid) -> Result<(), &str> {
+        if id == Uuid::nil() {
+            return Err(ERR_BAD_ORDER_ID);
+        }
+
+        Ok(())
+    }
+
+    fn within_limits(&self, qty: &BigDecimal) -> Result<(), &str> {
+        let limits = match &self.qty_limits {
+            Some(limits) => limits,
+            None => return Ok(()),
+        };
+
+        if limits.lot_size.as_ref().is_some_and(|lot| !(qty % lot).is_zero()) {
+            return Err(ERR_OFF_LOT);
+        }
+
+        if limits.min_qty.as_ref().is_some_and(|min| qty < min) {
+            return Err(ERR_QTY_BELOW_MIN);
+        }
+
+        if limits.max_qty.as_ref().is_some_and(|max| qty > max) {
+            return Err(ERR_QTY_ABOVE_MAX);
+        }
+
+        Ok(())
+    }
+
+    fn on_tick(&self, price: &BigDecimal) -> bool {
+        match &self.price_scale {
+            Some(price_scale) => price_scale.key(price).is_some(),
+            None => true,
+        }
+    }
+}
@@ -0,0 +1,8 @@
+// matching helpers pass the full order description around explicitly,
+// assets are spelled as their tickers
+#![allow(clippy::too_many_arguments, clippy::upper_case_acronyms)]
+
+pub mod guid;
+// kept as it was written, the guid book is where the work goes
+#[allow(clippy::double_comparisons, clippy::map_entry)]
+pub mod sequential;
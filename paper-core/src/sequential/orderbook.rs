@@ -220,16 +220,26 @@ where
         side: OrderSide,
         qty: f64,
     ) {
-        // get copy of the current limit order
-        let opposite_order_result = {
-            let opposite_queue = match side {
-                OrderSide::Bid => &mut self.ask_queue,
-                OrderSide::Ask => &mut self.bid_queue,
+        let mut qty = qty;
+        loop {
+            // get copy of the current limit order
+            let opposite_order_result = {
+                let opposite_queue = match side {
+                    OrderSide::Bid => &mut self.ask_queue,
+                    OrderSide::Ask => &mut self.bid_queue,
+                };
+                opposite_queue.peek().cloned()
+            };
+
+            let opposite_order = match opposite_order_result {
+                Some(opposite_order) => opposite_order,
+                None => {
+                    // no limit orders found
+                    results.push(Err(Failed::NoMatch(order_id)));
+                    return;
+                }
             };
-            opposite_queue.peek().cloned()
-        };
 
-        if let Some(opposite_order) = opposite_order_result {
             let matching_complete = self.order_matching(
                 results,
                 &opposite_order,
@@ -241,21 +251,11 @@ where
                 qty,
             );
 
-            if !matching_complete {
-                // match the rest
-                self.process_market_order(
-                    results,
-                    order_id,
-                    order_asset,
-                    price_asset,
-                    side,
-                    qty - opposite_order.qty,
-                );
+            if matching_complete {
+                return;
             }
-
-        } else {
-            // no limit orders found
-            results.push(Err(Failed::NoMatch(order_id)));
+            // match the rest
+            qty -= opposite_order.qty;
         }
     }
 
@@ -271,74 +271,58 @@ where
         qty: f64,
         ts: SystemTime,
     ) {
-        // take a look at current opposite limit order
-        let opposite_order_result = {
-            let opposite_queue = match side {
-                OrderSide::Bid => &mut self.ask_queue,
-                OrderSide::Ask => &mut self.bid_queue,
+        let mut qty = qty;
+        loop {
+            // take a look at current opposite limit order
+            let opposite_order_result = {
+                let opposite_queue = match side {
+                    OrderSide::Bid => &mut self.ask_queue,
+                    OrderSide::Ask => &mut self.bid_queue,
+                };
+                opposite_queue.peek().cloned()
             };
-            opposite_queue.peek().cloned()
-        };
 
-        if let Some(opposite_order) = opposite_order_result {
-            let could_be_matched = match side {
+            let matchable = opposite_order_result.filter(|opposite_order| match side {
                 // verify bid/ask price overlap
                 OrderSide::Bid => price >= opposite_order.price,
                 OrderSide::Ask => price <= opposite_order.price,
-            };
-
-            if could_be_matched {
-                // match immediately
-                let matching_complete = self.order_matching(
-                    results,
-                    &opposite_order,
-                    order_id,
-                    order_asset,
-                    price_asset,
-                    OrderType::Limit,
-                    side,
-                    qty,
-                );
+            });
 
-                if !matching_complete {
-                    // process the rest of new limit order
-                    self.process_limit_order(
+            let opposite_order = match matchable {
+                Some(opposite_order) => opposite_order,
+                None => {
+                    // just insert new order in queue
+                    self.store_new_limit_order(
                         results,
                         order_id,
                         order_asset,
                         price_asset,
                         side,
                         price,
-                        qty - opposite_order.qty,
+                        qty,
                         ts,
                     );
+                    return;
                 }
+            };
 
-            } else {
-                // just insert new order in queue
-                self.store_new_limit_order(
-                    results,
-                    order_id,
-                    order_asset,
-                    price_asset,
-                    side,
-                    price,
-                    qty,
-                    ts,
-                );
-            }
-
-        } else {
-            self.store_new_limit_order(
+            // match immediately
+            let matching_complete = self.order_matching(
                 results,
+                &opposite_order,
                 order_id,
                 order_asset,
                 price_asset,
+                OrderType::Limit,
                 side,
-                price,
                 qty,
-                ts,
             );
+
+            if matching_complete {
+                return;
+            }
+            // process the rest of new limit order
+            qty -= opposite_order.qty;
         }
     }
 
@@ -594,4 +578,33 @@ mod test {
             _ => panic!("unexpected events"),
         }
     }
+
+    #[test]
+    fn deep_sweep() {
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        // as deep as the order ID range allows, leaving one for the sweep
+        let depth = (MAX_SEQUENCE_ID - MIN_SEQUENCE_ID) as usize;
+        for _ in 0..depth {
+            orderbook.process_order(orders::new_limit_order_request(
+                Asset::BTC,
+                Asset::USD,
+                OrderSide::Ask,
+                1.0,
+                1.0,
+                SystemTime::now(),
+            ));
+        }
+
+        // one pass over every resting order
+        let result = orderbook.process_order(orders::new_market_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Bid,
+            depth as f64,
+            SystemTime::now(),
+        ));
+        assert_eq!(result.len(), 1 + 2 * depth);
+        assert!(result.iter().all(|event| event.is_ok()));
+        assert!(orderbook.ask_queue.peek().is_none());
+    }
 }
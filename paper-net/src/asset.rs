@@ -0,0 +1,85 @@
+use serde::de::{self, Deserializer};
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+const MAX_CODE_LEN: usize = 8;
+
+/// Ticker of an asset configured at runtime, e.g. "BTC".
+///
+/// Up to 8 ASCII letters or digits, stored inline so it is `Copy` as the
+/// engine requires of assets.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct AssetCode {
+    len: u8,
+    bytes: [u8; MAX_CODE_LEN],
+}
+
+impl AssetCode {
+    pub fn as_str(&self) -> &str {
+        // only ever built from ASCII
+        std::str::from_utf8(&self.bytes[..self.len as usize]).unwrap()
+    }
+}
+
+impl FromStr for AssetCode {
+    type Err = String;
+
+    fn from_str(code: &str) -> Result<Self, Self::Err> {
+        if code.is_empty()
+            || code.len() > MAX_CODE_LEN
+            || !code.bytes().all(|byte| byte.is_ascii_alphanumeric())
+        {
+            return Err(format!("bad asset code {:?}", code));
+        }
+        let mut bytes = [0; MAX_CODE_LEN];
+        bytes[..code.len()].copy_from_slice(code.as_bytes());
+        Ok(AssetCode {
+            len: code.len() as u8,
+            bytes,
+        })
+    }
+}
+
+impl fmt::Display for AssetCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for AssetCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for AssetCode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for AssetCode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let code = String::deserialize(deserializer)?;
+        code.parse().map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_codes() {
+        let btc: AssetCode = "BTC".parse().unwrap();
+        assert_eq!(btc.as_str(), "BTC");
+        assert_eq!(format!("{:?}", btc), "BTC");
+        assert_eq!(serde_json::to_string(&btc).unwrap(), "\"BTC\"");
+        assert_ne!(btc, "BTCX".parse().unwrap());
+
+        assert!("".parse::<AssetCode>().is_err());
+        assert!("TOOLONGCODE".parse::<AssetCode>().is_err());
+        assert!("BTC/USD".parse::<AssetCode>().is_err());
+    }
+}
@@ -0,0 +1,26 @@
+//! Paper trading venue: `exchange [config.toml]`, see `ExchangeConfig` for
+//! the format.
+
+use std::env;
+use std::error::Error;
+
+use paper_net::config::ExchangeConfig;
+use paper_net::exchange::Exchange;
+use paper_net::server::Server;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let path = env::args()
+        .nth(1)
+        .unwrap_or_else(|| String::from("exchange.toml"));
+    let config = ExchangeConfig::load(&path)?;
+
+    let server = Server::bind(&config.server)?;
+    let exchange = Exchange::new(&config)?;
+    eprintln!(
+        "order entry on http://{}, market data on ws://{}",
+        server.rest_addr()?,
+        server.feed_addr()?
+    );
+    server.run(exchange)?;
+    Ok(())
+}
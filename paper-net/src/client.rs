@@ -0,0 +1,504 @@
+//! Typed clients of the exchange servers, for integrators and tests alike.
+//!
+//! Async over tokio sockets, any tokio runtime drives them: one connection
+//! per REST call, one for the lifetime of a feed subscription.
+
+use bigdecimal::BigDecimal;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::time;
+use uuid::Uuid;
+
+use paper_core::guid::completed::OrderStatus;
+use paper_core::guid::domain::OrderSide;
+use paper_core::guid::orderbook::{OrderProcessingResult, TradingStatus};
+use paper_serde::ladder::{Ladder, LadderMessage};
+use paper_serde::replica::{FeedMessage, ReplicaBook, ReplicaError};
+
+use crate::asset::AssetCode;
+use crate::exchange::{market_name, parse_market, Market, NewOrder, OrderBatch};
+use crate::websocket::{self, Frame, MAX_PAYLOAD, OP_CLOSE, OP_TEXT};
+
+/// Why a call to the exchange failed
+#[derive(Debug)]
+pub enum ClientError {
+    Io(io::Error),
+    /// answered with an error status and its reason
+    Rejected {
+        status: u16,
+        reason: String,
+    },
+    /// answer or message the client could not read
+    Malformed(String),
+    /// feed message a local book could not apply
+    Replica(ReplicaError),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClientError::Io(err) => write!(f, "{}", err),
+            ClientError::Rejected { status, reason } => write!(f, "{}: {}", status, reason),
+            ClientError::Malformed(reason) => write!(f, "malformed answer: {}", reason),
+            ClientError::Replica(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<io::Error> for ClientError {
+    fn from(err: io::Error) -> Self {
+        ClientError::Io(err)
+    }
+}
+
+/// Market as listed by `GET /markets`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct MarketInfo {
+    pub market: String,
+    pub tick: BigDecimal,
+    pub status: TradingStatus,
+}
+
+/// Answer to `POST /orders/batch`
+#[derive(Debug, Deserialize)]
+pub struct BatchResult {
+    pub accepted: bool,
+    pub cancels: Vec<OrderProcessingResult<AssetCode>>,
+    pub orders: Vec<OrderProcessingResult<AssetCode>>,
+}
+
+#[derive(Deserialize)]
+struct OrderStatusBody {
+    status: OrderStatus,
+}
+
+/// Client of the REST server, see `Exchange` for the routes
+#[derive(Debug, Clone)]
+pub struct RestClient {
+    addr: SocketAddr,
+    timeout: Option<Duration>,
+}
+
+impl RestClient {
+    pub fn new(addr: SocketAddr) -> Self {
+        RestClient {
+            addr,
+            timeout: None,
+        }
+    }
+
+    /// Give up on calls not answered within the timeout
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub async fn markets(&self) -> Result<Vec<MarketInfo>, ClientError> {
+        self.call("GET", "/markets", None).await
+    }
+
+    pub async fn submit(
+        &self,
+        order: &NewOrder,
+    ) -> Result<OrderProcessingResult<AssetCode>, ClientError> {
+        self.call("POST", "/orders", Some(encode(order)?)).await
+    }
+
+    pub async fn submit_batch(&self, batch: &OrderBatch) -> Result<BatchResult, ClientError> {
+        self.call("POST", "/orders/batch", Some(encode(batch)?))
+            .await
+    }
+
+    /// Cancel the order, looked up on both sides without `side`
+    pub async fn cancel(
+        &self,
+        order_id: Uuid,
+        market: Market,
+        side: Option<OrderSide>,
+    ) -> Result<OrderProcessingResult<AssetCode>, ClientError> {
+        let mut target = format!("/orders/{}?market={}", order_id, market_name(market));
+        match side {
+            Some(OrderSide::Bid) => target.push_str("&side=bid"),
+            Some(OrderSide::Ask) => target.push_str("&side=ask"),
+            None => (),
+        }
+        self.call("DELETE", &target, None).await
+    }
+
+    pub async fn order_status(
+        &self,
+        order_id: Uuid,
+        market: Market,
+    ) -> Result<OrderStatus, ClientError> {
+        let target = format!("/orders/{}?market={}", order_id, market_name(market));
+        let body: OrderStatusBody = self.call("GET", &target, None).await?;
+        Ok(body.status)
+    }
+
+    /// Copy of the book as on the feed, the copy does not follow the book
+    pub async fn book(&self, market: Market) -> Result<ReplicaBook, ClientError> {
+        let target = format!("/markets/{}/book", market_name(market));
+        let snapshot: FeedMessage = self.call("GET", &target, None).await?;
+        let mut book = ReplicaBook::awaiting_snapshot();
+        book.apply(&snapshot).map_err(ClientError::Replica)?;
+        Ok(book)
+    }
+
+    /// State of every market for operations dashboards, as served on
+    /// `GET /status`
+    pub async fn status(&self) -> Result<Value, ClientError> {
+        self.call("GET", "/status", None).await
+    }
+
+    /* Helpers */
+
+    async fn call<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        target: &str,
+        body: Option<String>,
+    ) -> Result<T, ClientError> {
+        let exchange = self.exchange(method, target, body.unwrap_or_default());
+        let response = match self.timeout {
+            Some(timeout) => time::timeout(timeout, exchange)
+                .await
+                .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))??,
+            None => exchange.await?,
+        };
+        let (head, body) = response
+            .split_once("\r\n\r\n")
+            .ok_or_else(|| ClientError::Malformed(String::from("no end of headers")))?;
+        let status: u16 = head
+            .split_whitespace()
+            .nth(1)
+            .and_then(|status| status.parse().ok())
+            .ok_or_else(|| ClientError::Malformed(String::from("no status")))?;
+
+        if status != 200 {
+            // failures of the engine shape carry the reason as their detail
+            let reason = serde_json::from_str::<Value>(body)
+                .ok()
+                .and_then(|error| {
+                    error["detail"]
+                        .as_str()
+                        .or_else(|| error["error"].as_str())
+                        .map(String::from)
+                })
+                .unwrap_or_else(|| body.to_string());
+            return Err(ClientError::Rejected { status, reason });
+        }
+        serde_json::from_str(body).map_err(|err| ClientError::Malformed(err.to_string()))
+    }
+
+    /// Send the request and read the whole response
+    async fn exchange(&self, method: &str, target: &str, body: String) -> io::Result<String> {
+        let mut stream = TcpStream::connect(self.addr).await?;
+        let request = format!(
+            "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            method,
+            target,
+            self.addr,
+            body.len(),
+            body
+        );
+        stream.write_all(request.as_bytes()).await?;
+        stream.flush().await?;
+
+        // the server closes the connection after its response
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await?;
+        Ok(response)
+    }
+}
+
+/// Subscription to the feed server keeping a local copy of every market.
+///
+/// The full feed maintains a `ReplicaBook` per market, checked against the
+/// sequence numbers and checksums of the feed. A ladder subscription keeps
+/// the best levels of every market as a `Ladder` instead.
+pub struct FeedClient {
+    stream: BufReader<TcpStream>,
+    books: HashMap<Market, ReplicaBook>,
+    ladders: HashMap<Market, Ladder>,
+}
+
+impl FeedClient {
+    /// Every depth change and trade of every market
+    pub async fn subscribe(addr: SocketAddr) -> Result<Self, ClientError> {
+        Self::connect(addr, "/").await
+    }
+
+    /// Best `depth` levels of every market
+    pub async fn subscribe_ladder(addr: SocketAddr, depth: usize) -> Result<Self, ClientError> {
+        Self::connect(addr, &format!("/?ladder={}", depth)).await
+    }
+
+    /// Wait for the next message and apply it to its market, returned.
+    ///
+    /// A book missing or failing a check stays out of sync until the next
+    /// snapshot, the error is returned all the same.
+    pub async fn next_update(&mut self) -> Result<Market, ClientError> {
+        let frame = loop {
+            let frame = read_frame(&mut self.stream).await?;
+            match frame.opcode {
+                OP_TEXT => break frame,
+                OP_CLOSE => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+                _ => continue,
+            }
+        };
+
+        let message: Value = serde_json::from_slice(&frame.payload)
+            .map_err(|err| ClientError::Malformed(err.to_string()))?;
+        let market = message["market"]
+            .as_str()
+            .and_then(parse_market)
+            .ok_or_else(|| ClientError::Malformed(String::from("no market")))?;
+
+        if message.get("rows").is_some() {
+            let message: LadderMessage = serde_json::from_value(message)
+                .map_err(|err| ClientError::Malformed(err.to_string()))?;
+            self.ladders.entry(market).or_default().apply(&message);
+        } else {
+            let message: FeedMessage = serde_json::from_value(message)
+                .map_err(|err| ClientError::Malformed(err.to_string()))?;
+            self.books
+                .entry(market)
+                .or_insert_with(ReplicaBook::awaiting_snapshot)
+                .apply(&message)
+                .map_err(ClientError::Replica)?;
+        }
+        Ok(market)
+    }
+
+    /// Local copy of the market, full feed only
+    pub fn book(&self, market: Market) -> Option<&ReplicaBook> {
+        self.books.get(&market)
+    }
+
+    /// Best levels of the market, ladder subscriptions only
+    pub fn ladder(&self, market: Market) -> Option<&Ladder> {
+        self.ladders.get(&market)
+    }
+
+    /* Helpers */
+
+    async fn connect(addr: SocketAddr, target: &str) -> Result<Self, ClientError> {
+        let mut stream = TcpStream::connect(addr).await?;
+        let handshake = format!(
+            "GET {} HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: {}\r\nSec-WebSocket-Version: 13\r\n\r\n",
+            target,
+            addr,
+            HANDSHAKE_KEY
+        );
+        stream.write_all(handshake.as_bytes()).await?;
+        stream.flush().await?;
+
+        let mut stream = BufReader::new(stream);
+        let mut line = String::new();
+        stream.read_line(&mut line).await?;
+        if !line.starts_with("HTTP/1.1 101") {
+            return Err(ClientError::Malformed(format!(
+                "handshake answered {}",
+                line.trim()
+            )));
+        }
+        let mut accepted = false;
+        while line != "\r\n" {
+            line.clear();
+            if stream.read_line(&mut line).await? == 0 {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                accepted |= name.eq_ignore_ascii_case("Sec-WebSocket-Accept")
+                    && value.trim() == websocket::accept_key(HANDSHAKE_KEY);
+            }
+        }
+        if !accepted {
+            return Err(ClientError::Malformed(String::from(
+                "handshake not accepted",
+            )));
+        }
+
+        Ok(FeedClient {
+            stream,
+            books: HashMap::new(),
+            ladders: HashMap::new(),
+        })
+    }
+}
+
+/// Nonce of the opening handshake, the server only echoes it back hashed
+const HANDSHAKE_KEY: &str = "cGFwZXItZmVlZC1jbGllbnQ=";
+
+/// Read the next frame, as `websocket::read_frame` does on blocking streams
+async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<Frame> {
+    let mut head = [0; 2];
+    reader.read_exact(&mut head).await?;
+    let opcode = head[0] & 0x0F;
+    let masked = head[1] & 0x80 != 0;
+
+    let len = match head[1] & 0x7F {
+        126 => reader.read_u16().await? as u64,
+        127 => reader.read_u64().await?,
+        len => len as u64,
+    };
+    if len > MAX_PAYLOAD {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "frame too large",
+        ));
+    }
+
+    let mut mask = [0; 4];
+    if masked {
+        reader.read_exact(&mut mask).await?;
+    }
+    let mut payload = vec![0; len as usize];
+    reader.read_exact(&mut payload).await?;
+    if masked {
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+    }
+
+    Ok(Frame { opcode, payload })
+}
+
+fn encode<T: serde::Serialize>(body: &T) -> Result<String, ClientError> {
+    serde_json::to_string(body).map_err(|err| ClientError::Malformed(err.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::ExchangeConfig;
+    use crate::exchange::{BatchCancel, BatchOrder, Exchange};
+    use crate::server::Server;
+    use paper_core::guid::orderbook::Success;
+    use std::str::FromStr;
+    use std::thread;
+
+    fn bigdec(num: &str) -> BigDecimal {
+        BigDecimal::from_str(num).unwrap()
+    }
+
+    fn order(account: u64, side: OrderSide, price: &str, qty: &str) -> NewOrder {
+        NewOrder {
+            account,
+            market: String::from("BTC-USD"),
+            side,
+            price: Some(bigdec(price)),
+            qty: bigdec(qty),
+            protection: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn rest_and_feed() {
+        let config = ExchangeConfig::from_toml(
+            r#"
+            [server]
+            rest = "127.0.0.1:0"
+            feed = "127.0.0.1:0"
+
+            [[markets]]
+            base = "BTC"
+            quote = "USD"
+            tick = "0.5"
+            "#,
+        )
+        .unwrap();
+        let server = Server::bind(&config.server).unwrap();
+        let (rest, feed) = (server.rest_addr().unwrap(), server.feed_addr().unwrap());
+        thread::spawn(move || {
+            let exchange = Exchange::new(&config).unwrap();
+            server.run(exchange)
+        });
+        let btc = parse_market("BTC-USD").unwrap();
+        let client = RestClient::new(rest).with_timeout(Duration::from_secs(5));
+        let mut feed = FeedClient::subscribe(feed).await.unwrap();
+        assert_eq!(feed.next_update().await.unwrap(), btc);
+
+        let markets = client.markets().await.unwrap();
+        assert_eq!(markets[0].market, "BTC-USD");
+        assert_eq!(markets[0].tick, bigdec("0.5"));
+        assert_eq!(markets[0].status, TradingStatus::Trading);
+
+        let results = client
+            .submit(&order(1, OrderSide::Ask, "100", "2"))
+            .await
+            .unwrap();
+        let resting = match &results[0] {
+            Ok(Success::Accepted { order_id, .. }) => *order_id,
+            other => panic!("unexpected {:?}", other),
+        };
+        let results = client
+            .submit(&order(2, OrderSide::Bid, "100", "1"))
+            .await
+            .unwrap();
+        assert!(results
+            .iter()
+            .any(|result| matches!(result, Ok(Success::Filled { .. }))));
+        assert_eq!(
+            client.order_status(resting, btc).await.unwrap(),
+            OrderStatus::Resting
+        );
+
+        let rejected = client.submit(&order(1, OrderSide::Ask, "100.2", "1")).await;
+        match rejected {
+            Err(ClientError::Rejected { status, reason }) => {
+                assert_eq!((status, reason.as_str()), (400, "price not on tick size"))
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let batch = OrderBatch {
+            account: 1,
+            cancels: vec![BatchCancel {
+                order_id: resting,
+                market: String::from("BTC-USD"),
+                side: Some(OrderSide::Ask),
+            }],
+            orders: vec![BatchOrder {
+                market: String::from("BTC-USD"),
+                side: OrderSide::Ask,
+                price: Some(bigdec("101")),
+                qty: bigdec("1"),
+                protection: None,
+            }],
+            all_or_nothing: true,
+        };
+        let result = client.submit_batch(&batch).await.unwrap();
+        assert!(result.accepted);
+        assert_eq!((result.cancels.len(), result.orders.len()), (1, 1));
+
+        // the local copy follows every request
+        for _ in 0..4 {
+            assert_eq!(feed.next_update().await.unwrap(), btc);
+        }
+        let local = feed.book(btc).unwrap();
+        assert!(local.is_in_sync());
+        assert_eq!(
+            local.best(OrderSide::Ask),
+            Some((&bigdec("101"), &bigdec("1")))
+        );
+        let served = client.book(btc).await.unwrap();
+        assert_eq!(
+            served.depth(OrderSide::Ask, 10),
+            local.depth(OrderSide::Ask, 10)
+        );
+
+        let cancelled = client.cancel(Uuid::new_v4(), btc, None).await.unwrap();
+        assert!(cancelled[0].is_err());
+    }
+}
@@ -0,0 +1,296 @@
+use bigdecimal::BigDecimal;
+use rand::Rng;
+use serde::Deserialize;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use paper_core::guid::fees::FeeRates;
+use paper_core::guid::matching::MatchAlgorithm;
+use paper_core::guid::orderbook::{BandMode, QtyLimits, TradingStatus};
+
+use crate::asset::AssetCode;
+use crate::precision::PrecisionPolicy;
+
+/// Settings of the demo exchange, read from TOML.
+///
+/// ```toml
+/// [server]
+/// rest = "127.0.0.1:8080"
+/// feed = "127.0.0.1:8081"
+///
+/// # optional, for testing clients against a slow venue
+/// [server.latency]
+/// response_ms = 20
+/// feed_ms = 5
+/// jitter_ms = 10
+///
+/// [fees]
+/// maker = "0.001"
+/// taker = "0.002"
+///
+/// [[markets]]
+/// base = "BTC"
+/// quote = "USD"
+/// tick = "0.01"
+/// qty_decimals = 8
+/// lot = "0.001"
+/// min_qty = "0.001"
+/// max_qty = "100"
+/// precision = "round"
+/// band = "0.1"
+/// collar = true
+/// status = "post_only"
+/// matching = "pro_rata"
+/// ```
+///
+/// Decimals are best written as strings, TOML floats are binary.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExchangeConfig {
+    pub server: ServerConfig,
+    /// no fees are charged without it
+    pub fees: Option<FeeRates>,
+    pub markets: Vec<MarketSpec>,
+}
+
+/// Addresses to listen on
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServerConfig {
+    /// HTTP order entry
+    pub rest: String,
+    /// websocket market data
+    pub feed: String,
+    /// delays injected into responses and feed messages, none by default
+    pub latency: Option<LatencyConfig>,
+}
+
+/// Simulated venue latency, so clients can test their timeouts, reconnects
+/// and sequencing. Feed messages keep their order whatever the jitter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LatencyConfig {
+    /// added to every REST response
+    #[serde(default)]
+    pub response_ms: u64,
+    /// added to every feed message, snapshots included
+    #[serde(default)]
+    pub feed_ms: u64,
+    /// random extra delay of up to this on every response and message
+    #[serde(default)]
+    pub jitter_ms: u64,
+}
+
+impl LatencyConfig {
+    pub fn response_delay(&self) -> Duration {
+        self.delay(self.response_ms)
+    }
+
+    pub fn feed_delay(&self) -> Duration {
+        self.delay(self.feed_ms)
+    }
+
+    fn delay(&self, base_ms: u64) -> Duration {
+        let jitter = match self.jitter_ms {
+            0 => 0,
+            jitter_ms => rand::thread_rng().gen_range(0..=jitter_ms),
+        };
+        Duration::from_millis(base_ms + jitter)
+    }
+}
+
+/// Market to open on start
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MarketSpec {
+    pub base: AssetCode,
+    pub quote: AssetCode,
+    /// tick size, the engine default when missing
+    pub tick: Option<BigDecimal>,
+    /// decimals of order quantities, any when missing
+    pub qty_decimals: Option<u32>,
+    /// for prices off the tick and quantities with more decimals, rejected
+    /// by default
+    #[serde(default)]
+    pub precision: PrecisionPolicy,
+    /// step order quantities must be a multiple of, always rejected
+    pub lot: Option<BigDecimal>,
+    pub min_qty: Option<BigDecimal>,
+    pub max_qty: Option<BigDecimal>,
+    /// price band width as a fraction of the last trade price
+    pub band: Option<BigDecimal>,
+    /// re-price orders through the band instead of rejecting them
+    #[serde(default)]
+    pub collar: bool,
+    /// phase the market opens in, trading by default
+    #[serde(default)]
+    pub status: TradingStatus,
+    /// allocation within a price level, price-time by default
+    #[serde(default)]
+    pub matching: MatchAlgorithm,
+}
+
+impl MarketSpec {
+    /// Quantity limits of the market, None without any
+    pub fn qty_limits(&self) -> Option<QtyLimits> {
+        if self.lot.is_none() && self.min_qty.is_none() && self.max_qty.is_none() {
+            return None;
+        }
+        Some(QtyLimits {
+            lot_size: self.lot.clone(),
+            min_qty: self.min_qty.clone(),
+            max_qty: self.max_qty.clone(),
+        })
+    }
+
+    pub fn band_mode(&self) -> BandMode {
+        if self.collar {
+            BandMode::Collar
+        } else {
+            BandMode::Reject
+        }
+    }
+}
+
+/// Why the config could not be loaded
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(err) => write!(f, "cannot read config: {}", err),
+            ConfigError::Parse(reason) => write!(f, "bad config: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl ExchangeConfig {
+    pub fn from_toml(text: &str) -> Result<Self, ConfigError> {
+        let config: Self =
+            toml::from_str(text).map_err(|err| ConfigError::Parse(err.to_string()))?;
+        for market in &config.markets {
+            if let Some(limits) = market.qty_limits() {
+                limits.validate().map_err(|reason| {
+                    ConfigError::Parse(format!(
+                        "market {}/{}: {}",
+                        market.base.as_str(),
+                        market.quote.as_str(),
+                        reason
+                    ))
+                })?;
+            }
+        }
+        Ok(config)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        let text = fs::read_to_string(path).map_err(ConfigError::Io)?;
+        Self::from_toml(&text)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn parse_config() {
+        let config = ExchangeConfig::from_toml(
+            r#"
+            [server]
+            rest = "127.0.0.1:0"
+            feed = "127.0.0.1:0"
+
+            [server.latency]
+            response_ms = 20
+            jitter_ms = 5
+
+            [fees]
+            maker = "-0.0001"
+            taker = "0.002"
+
+            [[markets]]
+            base = "BTC"
+            quote = "USD"
+            tick = "0.5"
+            qty_decimals = 4
+            lot = "0.01"
+            min_qty = "0.1"
+            precision = "round"
+            band = "0.1"
+            collar = true
+            status = "post_only"
+            matching = "pro_rata"
+
+            [[markets]]
+            base = "ETH"
+            quote = "USD"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.markets.len(), 2);
+        let btc = &config.markets[0];
+        assert_eq!(btc.base.as_str(), "BTC");
+        assert_eq!(btc.tick, Some(BigDecimal::from_str("0.5").unwrap()));
+        assert_eq!(btc.band_mode(), BandMode::Collar);
+        assert_eq!(btc.qty_decimals, Some(4));
+        assert_eq!(btc.precision, PrecisionPolicy::Round);
+        assert_eq!(config.markets[1].precision, PrecisionPolicy::Reject);
+        assert_eq!(config.markets[1].tick, None);
+        assert_eq!(config.markets[1].band_mode(), BandMode::Reject);
+        assert_eq!(btc.status, TradingStatus::PostOnly);
+        let limits = btc.qty_limits().unwrap();
+        assert_eq!(limits.lot_size, Some(BigDecimal::from_str("0.01").unwrap()));
+        assert_eq!(limits.max_qty, None);
+        assert_eq!(config.markets[1].qty_limits(), None);
+        assert_eq!(config.markets[1].status, TradingStatus::Trading);
+        assert_eq!(btc.matching, MatchAlgorithm::ProRata);
+        assert_eq!(config.markets[1].matching, MatchAlgorithm::PriceTime);
+        assert_eq!(
+            config.fees.unwrap().maker,
+            BigDecimal::from_str("-0.0001").unwrap()
+        );
+
+        let latency = config.server.latency.unwrap();
+        assert_eq!(latency.feed_ms, 0);
+        let delay = latency.response_delay();
+        assert!(delay >= Duration::from_millis(20) && delay <= Duration::from_millis(25));
+
+        assert!(ExchangeConfig::from_toml("[server]\nrest = 1").is_err());
+    }
+
+    #[test]
+    fn reject_qty_limits() {
+        let market = |limits: &str| {
+            ExchangeConfig::from_toml(&format!(
+                "[server]\nrest = \"127.0.0.1:0\"\nfeed = \"127.0.0.1:0\"\n\n\
+                 [[markets]]\nbase = \"BTC\"\nquote = \"USD\"\n{}",
+                limits
+            ))
+        };
+        assert!(market("lot = \"0.01\"").is_ok());
+        for limits in &[
+            "lot = \"0\"",
+            "lot = \"-0.01\"",
+            "min_qty = \"0\"",
+            "max_qty = \"-1\"",
+        ] {
+            match market(limits) {
+                Err(ConfigError::Parse(reason)) => assert!(reason.starts_with("market BTC/USD")),
+                other => panic!("{} accepted: {:?}", limits, other),
+            }
+        }
+        assert!(market("min_qty = \"2\"\nmax_qty = \"1\"").is_err());
+    }
+}
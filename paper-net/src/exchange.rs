@@ -0,0 +1,788 @@
+use bigdecimal::{BigDecimal, Zero};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::time::SystemTime;
+use uuid::Uuid;
+
+use paper_core::guid::config::MarketConfig;
+use paper_core::guid::dashboard::{Dashboard, SideSummary};
+use paper_core::guid::domain::{AccountId, OrderSide};
+use paper_core::guid::engine::{MatchingEngine, Symbol};
+use paper_core::guid::fees::FeeEngine;
+use paper_core::guid::orderbook::{
+    BandReference, BookConfig, Failed, OrderProcessingResult, PriceBand,
+};
+use paper_core::guid::orders::{self, MarketProtection, OrderRequest};
+use paper_core::guid::price_key::PriceScale;
+use paper_serde::ladder::LadderPublisher;
+use paper_serde::replica::FeedPublisher;
+
+use crate::asset::AssetCode;
+use crate::config::ExchangeConfig;
+use crate::http::{Request, Response};
+use crate::precision::Precision;
+use crate::schema::openapi;
+
+/// Market of the exchange, spelled "BTC-USD" on the wire
+pub type Market = Symbol<AssetCode>;
+
+/// Body of `POST /orders`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NewOrder {
+    pub account: AccountId,
+    pub market: String,
+    pub side: OrderSide,
+    /// market order without it
+    pub price: Option<BigDecimal>,
+    pub qty: BigDecimal,
+    /// how far a market order may walk the book
+    #[serde(default)]
+    pub protection: Option<MarketProtection>,
+}
+
+/// Cancel of `OrderBatch`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BatchCancel {
+    pub order_id: Uuid,
+    pub market: String,
+    #[serde(default)]
+    pub side: Option<OrderSide>,
+}
+
+/// New order of `OrderBatch`, a `NewOrder` without the account
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BatchOrder {
+    pub market: String,
+    pub side: OrderSide,
+    pub price: Option<BigDecimal>,
+    pub qty: BigDecimal,
+    #[serde(default)]
+    pub protection: Option<MarketProtection>,
+}
+
+/// Body of `POST /orders/batch`: cancels and new orders of one account,
+/// e.g. a market maker replacing its quotes. Cancels go first.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OrderBatch {
+    pub account: AccountId,
+    #[serde(default)]
+    pub cancels: Vec<BatchCancel>,
+    #[serde(default)]
+    pub orders: Vec<BatchOrder>,
+    /// nothing is processed if any cancel or order fails validation
+    #[serde(default)]
+    pub all_or_nothing: bool,
+}
+
+/// Cancels and new orders a single batch may carry
+pub const MAX_BATCH_LEN: usize = 100;
+
+/// Feed or ladder message tagged with its market
+#[derive(Serialize)]
+struct MarketFeedMessage<'a, M> {
+    market: String,
+    #[serde(flatten)]
+    message: &'a M,
+}
+
+/// Engine, accounts, fees and market data of the demo exchange, served
+/// over REST:
+///
+/// * `GET /markets`
+/// * `GET /markets/BTC-USD/book`, snapshot of the book as on the feed
+/// * `POST /orders` with a `NewOrder` body, processing results come back
+/// * `POST /orders/batch` with an `OrderBatch` body, the processing results
+///   of every cancel and order come back grouped
+/// * `GET /orders/<id>?market=BTC-USD`, status of a live or recently
+///   completed order
+/// * `DELETE /orders/<id>?market=BTC-USD`, `&side=bid` may be added
+/// * `GET /accounts/<id>`, open orders, positions and fees paid
+/// * `GET /schema`, OpenAPI components of the bodies above
+/// * `GET /status`, state of every market for operations dashboards
+///
+/// There is no authentication, anyone may act for any account.
+pub struct Exchange {
+    engine: MatchingEngine<AssetCode>,
+    fees: Option<FeeEngine<AssetCode>>,
+    /// fees charged so far, by account and asset
+    fees_paid: HashMap<(AccountId, AssetCode), BigDecimal>,
+    feeds: HashMap<Market, FeedPublisher>,
+    precision: HashMap<Market, Precision>,
+    /// top-of-book views by market and depth, made for the first subscriber
+    /// of a depth
+    ladders: HashMap<(Market, usize), LadderPublisher>,
+    /// in config order
+    markets: Vec<Market>,
+    dashboard: Dashboard<AssetCode>,
+}
+
+impl Exchange {
+    /// Open the configured markets
+    pub fn new(config: &ExchangeConfig) -> Result<Self, String> {
+        let mut engine = MatchingEngine::new();
+        let mut feeds = HashMap::new();
+        let mut precision = HashMap::new();
+        let mut markets = vec![];
+
+        for spec in &config.markets {
+            let market = (spec.base, spec.quote);
+            let book_config = BookConfig {
+                match_algorithm: spec.matching,
+                ..BookConfig::default()
+            };
+            if !engine.add_book_with_config(spec.base, spec.quote, book_config) {
+                return Err(format!("market {} listed twice", market_name(market)));
+            }
+            let price_scale = match &spec.tick {
+                Some(tick) => PriceScale::from_tick(tick)
+                    .ok_or_else(|| format!("bad tick size of {}", market_name(market)))?,
+                None => PriceScale::default(),
+            };
+            let band = spec.band.clone().map(|width| PriceBand {
+                width,
+                mode: spec.band_mode(),
+                reference: BandReference::LastTrade,
+            });
+            let market_config = MarketConfig {
+                price_scale,
+                price_band: band,
+                qty_limits: spec.qty_limits(),
+                trading_status: spec.status,
+                ..MarketConfig::default()
+            };
+            engine
+                .update_market_config(market, market_config)
+                .map_err(|err| format!("{}: {}", market_name(market), err))?;
+
+            feeds.insert(market, FeedPublisher::new());
+            precision.insert(
+                market,
+                Precision {
+                    price_scale,
+                    qty_decimals: spec.qty_decimals,
+                    policy: spec.precision,
+                },
+            );
+            markets.push(market);
+        }
+
+        Ok(Exchange {
+            engine,
+            fees: config.fees.clone().map(FeeEngine::flat),
+            fees_paid: HashMap::new(),
+            feeds,
+            precision,
+            ladders: HashMap::new(),
+            markets,
+            dashboard: Dashboard::new(),
+        })
+    }
+
+    pub fn engine(&self) -> &MatchingEngine<AssetCode> {
+        &self.engine
+    }
+
+    /// Serve a REST request, also returning the feed messages it caused
+    pub fn handle(&mut self, request: &Request) -> (Response, Vec<String>) {
+        let path = request.path.trim_matches('/');
+        let segments: Vec<&str> = path.split('/').collect();
+
+        let outcome = match (request.method.as_str(), segments.as_slice()) {
+            ("GET", ["schema"]) => Ok((Response::json(200, openapi().to_string()), vec![])),
+            ("GET", ["status"]) => Ok((self.status_summary(), vec![])),
+            ("GET", ["markets"]) => Ok((self.list_markets(), vec![])),
+            ("GET", ["markets", market, "book"]) => self.book(market).map(|book| (book, vec![])),
+            ("POST", ["orders"]) => self.submit(&request.body),
+            ("POST", ["orders", "batch"]) => self.submit_batch(&request.body),
+            ("GET", ["orders", order_id]) => self
+                .status(order_id, request)
+                .map(|status| (status, vec![])),
+            ("DELETE", ["orders", order_id]) => self.cancel(order_id, request),
+            ("GET", ["accounts", account]) => {
+                self.account(account).map(|account| (account, vec![]))
+            }
+            _ => Err(Response::error(404, "no such resource")),
+        };
+        outcome.unwrap_or_else(|response| (response, vec![]))
+    }
+
+    /// Snapshot of every market, for a new feed subscriber
+    pub fn snapshots(&self) -> Vec<String> {
+        let ts = SystemTime::now();
+        self.markets
+            .iter()
+            .map(|market| feed_json(*market, &self.feeds[market].snapshot(ts)))
+            .collect()
+    }
+
+    /// Top `depth` levels of every market, for a new ladder subscriber
+    pub fn ladder_snapshots(&mut self, depth: usize) -> Vec<String> {
+        // catch up a view nobody followed lately, its changes reach no one
+        self.ladder_updates(depth);
+        self.markets
+            .iter()
+            .map(|market| feed_json(*market, &self.ladders[&(*market, depth)].snapshot()))
+            .collect()
+    }
+
+    /// Row diffs of every market whose top `depth` levels changed since the
+    /// previous call
+    pub fn ladder_updates(&mut self, depth: usize) -> Vec<String> {
+        let mut messages = vec![];
+        for market in &self.markets {
+            let book = self.engine.book(*market).unwrap();
+            let message = self
+                .ladders
+                .entry((*market, depth))
+                .or_insert_with(|| LadderPublisher::new(depth))
+                .publish(book);
+            if let Some(message) = message {
+                messages.push(feed_json(*market, &message));
+            }
+        }
+        messages
+    }
+
+    /* Helpers */
+
+    fn list_markets(&self) -> Response {
+        let markets: Vec<Value> = self
+            .markets
+            .iter()
+            .filter_map(|market| {
+                let config = self.engine.market_config(*market)?;
+                Some(json!({
+                    "market": market_name(*market),
+                    "tick": config.price_scale.tick().to_string(),
+                    "status": config.trading_status,
+                }))
+            })
+            .collect();
+        Response::json(200, Value::from(markets).to_string())
+    }
+
+    fn status_summary(&mut self) -> Response {
+        let status = self.dashboard.refresh(&self.engine, SystemTime::now());
+        let decimal = |price: &Option<BigDecimal>| price.as_ref().map(BigDecimal::to_string);
+        let markets: Vec<Value> = status
+            .markets
+            .iter()
+            .map(|market| {
+                json!({
+                    "market": market_name(market.symbol),
+                    "halted": market.halted,
+                    "status": market.trading_status,
+                    "best_bid": decimal(&market.best_bid),
+                    "best_ask": decimal(&market.best_ask),
+                    "bids": side_summary(&market.bids),
+                    "asks": side_summary(&market.asks),
+                    "last_trade_price": decimal(&market.last_trade_price),
+                    "stops": market.stops,
+                    "seq": market.change_seq,
+                    "updates_per_sec": market.update_rate,
+                })
+            })
+            .collect();
+
+        let body = json!({
+            "markets": markets,
+            "queued": status.queued,
+            "shed": status.shed,
+            "shut_down": status.shut_down,
+        });
+        Response::json(200, body.to_string())
+    }
+
+    fn book(&self, market: &str) -> Result<Response, Response> {
+        let market = self.market(market)?;
+        let snapshot = self.feeds[&market].snapshot(SystemTime::now());
+        Ok(Response::json(200, feed_json(market, &snapshot)))
+    }
+
+    fn submit(&mut self, body: &[u8]) -> Result<(Response, Vec<String>), Response> {
+        let order: NewOrder =
+            serde_json::from_slice(body).map_err(|err| Response::error(400, &err.to_string()))?;
+        let market = self.market(&order.market)?;
+        let normalized = self.normalize(
+            market,
+            BatchOrder {
+                market: order.market,
+                side: order.side,
+                price: order.price,
+                qty: order.qty,
+                protection: order.protection,
+            },
+        )?;
+        let request = self.order_request(order.account, market, normalized);
+
+        let results = self.engine.submit_for(order.account, market, request);
+        Ok(self.respond(market, results))
+    }
+
+    fn submit_batch(&mut self, body: &[u8]) -> Result<(Response, Vec<String>), Response> {
+        let batch: OrderBatch =
+            serde_json::from_slice(body).map_err(|err| Response::error(400, &err.to_string()))?;
+        if batch.cancels.len() + batch.orders.len() > MAX_BATCH_LEN {
+            return Err(Response::error(400, "batch too large"));
+        }
+
+        let mut basket = vec![];
+        for cancel in &batch.cancels {
+            let market = self.market(&cancel.market)?;
+            let request = match cancel.side {
+                Some(side) => orders::limit_order_cancel_request(cancel.order_id, side),
+                None => orders::cancel_order_request(cancel.order_id),
+            };
+            basket.push((market, request));
+        }
+        // every order checked before any is registered
+        let orders = batch
+            .orders
+            .into_iter()
+            .map(|order| {
+                let market = self.market(&order.market)?;
+                Ok((market, self.normalize(market, order)?))
+            })
+            .collect::<Result<Vec<(Market, BatchOrder)>, Response>>()?;
+        for (market, order) in orders {
+            basket.push((market, self.order_request(batch.account, market, order)));
+        }
+
+        let markets: Vec<Market> = basket.iter().map(|(market, _)| *market).collect();
+        let result = self
+            .engine
+            .submit_basket_for(batch.account, basket, batch.all_or_nothing);
+        let mut feed = vec![];
+        if result.accepted {
+            for (market, results) in markets.iter().zip(&result.legs) {
+                feed.push(self.publish(*market, results));
+            }
+        }
+
+        let mut legs = result
+            .legs
+            .iter()
+            .map(|results| serde_json::to_value(results).unwrap());
+        let body = json!({
+            "accepted": result.accepted,
+            "cancels": legs.by_ref().take(batch.cancels.len()).collect::<Vec<Value>>(),
+            "orders": legs.collect::<Vec<Value>>(),
+        });
+        Ok((Response::json(200, body.to_string()), feed))
+    }
+
+    fn cancel(
+        &mut self,
+        order_id: &str,
+        request: &Request,
+    ) -> Result<(Response, Vec<String>), Response> {
+        let order_id =
+            Uuid::parse_str(order_id).map_err(|_| Response::error(400, "bad order ID"))?;
+        let market = self.market(request.query_param("market").unwrap_or_default())?;
+        let cancel = match request.query_param("side") {
+            Some(side) => OrderSide::from_string(side)
+                .map(|side| orders::limit_order_cancel_request(order_id, side))
+                .ok_or_else(|| Response::error(400, "side must be bid or ask"))?,
+            None => orders::cancel_order_request(order_id),
+        };
+
+        let results = self.engine.submit(market, cancel);
+        Ok(self.respond(market, results))
+    }
+
+    fn status(&self, order_id: &str, request: &Request) -> Result<Response, Response> {
+        let order_id =
+            Uuid::parse_str(order_id).map_err(|_| Response::error(400, "bad order ID"))?;
+        let market = self.market(request.query_param("market").unwrap_or_default())?;
+        let status = self
+            .engine
+            .book(market)
+            .and_then(|book| book.order_status(order_id))
+            .ok_or_else(|| Response::error(404, "unknown order"))?;
+
+        let body = json!({
+            "order_id": order_id.to_string(),
+            "market": market_name(market),
+            "status": status,
+        });
+        Ok(Response::json(200, body.to_string()))
+    }
+
+    fn account(&mut self, account: &str) -> Result<Response, Response> {
+        let account: AccountId = account
+            .parse()
+            .map_err(|_| Response::error(400, "bad account"))?;
+        let summary = self.engine.account_summary(account);
+
+        let open_orders: Vec<Value> = summary
+            .open_orders
+            .iter()
+            .map(|order| {
+                json!({
+                    "order_id": order.order_id.to_string(),
+                    "market": market_name(order.symbol),
+                    "side": order.side,
+                    "price": order.price.to_string(),
+                    "qty": order.qty.to_string(),
+                })
+            })
+            .collect();
+        let fees = self
+            .fees_paid
+            .iter()
+            .filter(|((owner, _), _)| *owner == account)
+            .map(|((_, asset), fee)| (*asset, fee));
+
+        let body = json!({
+            "account": account,
+            "open_orders": open_orders,
+            "positions": by_asset(summary.positions.iter().map(|(asset, qty)| (*asset, qty))),
+            "fees": by_asset(fees),
+        });
+        Ok(Response::json(200, body.to_string()))
+    }
+
+    /// Order with price and quantity to the precision of the market
+    fn normalize(&self, market: Market, order: BatchOrder) -> Result<BatchOrder, Response> {
+        let precision = &self.precision[&market];
+        let price = order
+            .price
+            .as_ref()
+            .map(|price| precision.price(order.side, price))
+            .transpose()
+            .map_err(rejected)?;
+        let qty = precision.qty(&order.qty).map_err(rejected)?;
+        Ok(BatchOrder {
+            price,
+            qty,
+            ..order
+        })
+    }
+
+    /// Engine request for the order of the account, known to the fees
+    fn order_request(
+        &mut self,
+        account: AccountId,
+        market: Market,
+        order: BatchOrder,
+    ) -> OrderRequest<AssetCode> {
+        let ts = SystemTime::now();
+        let request = match order.price {
+            Some(price) => orders::new_limit_order_request(
+                market.0, market.1, order.side, price, order.qty, ts,
+            ),
+            None => {
+                let request =
+                    orders::new_market_order_request(market.0, market.1, order.side, order.qty, ts);
+                match order.protection {
+                    Some(protection) => request.with_protection(protection),
+                    None => request,
+                }
+            }
+        };
+        if let Some(fees) = self.fees.as_mut() {
+            fees.register_order(request.order_id(), account, market);
+        }
+        request
+    }
+
+    /// Charge fees and publish what the request did to the book
+    fn respond(
+        &mut self,
+        market: Market,
+        results: OrderProcessingResult<AssetCode>,
+    ) -> (Response, Vec<String>) {
+        let message = self.publish(market, &results);
+        let body = serde_json::to_string(&results).unwrap();
+        (Response::json(200, body), vec![message])
+    }
+
+    /// Charge fees of the request and return its feed message
+    fn publish(&mut self, market: Market, results: &OrderProcessingResult<AssetCode>) -> String {
+        let ts = SystemTime::now();
+        if let Some(fees) = self.fees.as_mut() {
+            for record in fees.on_results(results, ts) {
+                *self
+                    .fees_paid
+                    .entry((record.account, record.asset))
+                    .or_insert_with(BigDecimal::zero) += record.fee;
+            }
+        }
+
+        let book = self.engine.book(market).unwrap();
+        let message = self
+            .feeds
+            .get_mut(&market)
+            .unwrap()
+            .publish(book, results, ts);
+        feed_json(market, &message)
+    }
+
+    fn market(&self, name: &str) -> Result<Market, Response> {
+        parse_market(name)
+            .filter(|market| self.feeds.contains_key(market))
+            .ok_or_else(|| Response::error(404, "unknown market"))
+    }
+}
+
+pub fn market_name((base, quote): Market) -> String {
+    format!("{}-{}", base, quote)
+}
+
+pub fn parse_market(name: &str) -> Option<Market> {
+    let (base, quote) = name.split_once('-')?;
+    Some((base.parse().ok()?, quote.parse().ok()?))
+}
+
+/// Request refused before the engine, with the code and shape of a failure
+/// out of it
+fn rejected(reason: &str) -> Response {
+    let failed = Failed::ValidationFailed(String::from(reason));
+    Response::json(400, serde_json::to_string(&failed).unwrap())
+}
+
+fn feed_json<M: Serialize>(market: Market, message: &M) -> String {
+    serde_json::to_string(&MarketFeedMessage {
+        market: market_name(market),
+        message,
+    })
+    .unwrap()
+}
+
+fn side_summary(summary: &SideSummary) -> Value {
+    json!({
+        "levels": summary.levels,
+        "orders": summary.orders,
+        "qty": summary.qty.to_string(),
+    })
+}
+
+/// Decimals keyed by asset, as strings to keep them exact
+fn by_asset<'a, I>(amounts: I) -> Value
+where
+    I: Iterator<Item = (AssetCode, &'a BigDecimal)>,
+{
+    let map: Map<String, Value> = amounts
+        .map(|(asset, amount)| (asset.to_string(), Value::from(amount.to_string())))
+        .collect();
+    Value::Object(map)
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use crate::precision::PrecisionPolicy;
+    use paper_core::guid::errors::{CODE_OFF_TICK, CODE_QTY_ROUNDS_TO_ZERO, CODE_QTY_TOO_PRECISE};
+
+    pub(crate) fn demo_config() -> ExchangeConfig {
+        ExchangeConfig::from_toml(
+            r#"
+            [server]
+            rest = "127.0.0.1:0"
+            feed = "127.0.0.1:0"
+
+            [fees]
+            maker = "0"
+            taker = "0.001"
+
+            [[markets]]
+            base = "BTC"
+            quote = "USD"
+            tick = "0.5"
+            "#,
+        )
+        .unwrap()
+    }
+
+    pub(crate) fn request(method: &str, path: &str, body: &str) -> Request {
+        let (path, query) = path.split_once('?').unwrap_or((path, ""));
+        Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            query: query
+                .split('&')
+                .filter_map(|pair| pair.split_once('='))
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+            headers: vec![],
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn order_entry_and_feed() {
+        let mut exchange = Exchange::new(&demo_config()).unwrap();
+
+        let (response, feed) = exchange.handle(&request(
+            "POST",
+            "/orders",
+            r#"{"account": 1, "market": "BTC-USD", "side": "ask", "price": "100", "qty": "2"}"#,
+        ));
+        assert_eq!(response.status, 200);
+        assert!(response.body.contains("Accepted"));
+        assert_eq!(feed.len(), 1);
+        assert!(feed[0].starts_with(r#"{"market":"BTC-USD","seq":1"#));
+
+        let (response, feed) = exchange.handle(&request(
+            "POST",
+            "/orders",
+            r#"{"account": 2, "market": "BTC-USD", "side": "bid", "qty": "1"}"#,
+        ));
+        assert!(response.body.contains("Filled"));
+        assert!(feed[0].contains(r#""type":"trade""#));
+
+        let (response, _) = exchange.handle(&request("GET", "/accounts/2", ""));
+        let account: Value = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(account["positions"]["BTC"], "1");
+        assert_eq!(account["fees"]["USD"], "0.1");
+
+        let (response, _) = exchange.handle(&request("GET", "/status", ""));
+        let status: Value = serde_json::from_str(&response.body).unwrap();
+        let market = &status["markets"][0];
+        assert_eq!(market["market"], "BTC-USD");
+        assert_eq!(market["best_bid"], Value::Null);
+        assert_eq!(market["best_ask"], "100");
+        assert_eq!(market["asks"]["qty"], "1");
+        assert_eq!(market["last_trade_price"], "100");
+        assert_eq!(market["halted"], false);
+        assert_eq!(market["status"], "trading");
+
+        // the resting remainder of account 1
+        let (response, _) = exchange.handle(&request("GET", "/accounts/1", ""));
+        let account: Value = serde_json::from_str(&response.body).unwrap();
+        let order_id = account["open_orders"][0]["order_id"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        let (response, feed) = exchange.handle(&request(
+            "DELETE",
+            &format!("/orders/{}?market=BTC-USD&side=ask", order_id),
+            "",
+        ));
+        assert!(response.body.contains("Cancelled"));
+        assert!(feed[0].contains(r#""qty":"0""#));
+        let (response, _) = exchange.handle(&request(
+            "GET",
+            &format!("/orders/{}?market=BTC-USD", order_id),
+            "",
+        ));
+        assert!(response.body.contains(r#""status":"cancelled""#));
+
+        let (response, _) = exchange.handle(&request("GET", "/markets/ETH-USD/book", ""));
+        assert_eq!(response.status, 404);
+        let (response, _) = exchange.handle(&request("POST", "/orders", "{}"));
+        assert_eq!(response.status, 400);
+        let (response, _) = exchange.handle(&request("GET", "/markets", ""));
+        assert_eq!(
+            response.body,
+            r#"[{"market":"BTC-USD","status":"trading","tick":"0.5"}]"#
+        );
+    }
+
+    #[test]
+    fn order_batch() {
+        let mut exchange = Exchange::new(&demo_config()).unwrap();
+        let (response, _) = exchange.handle(&request(
+            "POST",
+            "/orders",
+            r#"{"account": 1, "market": "BTC-USD", "side": "ask", "price": "100", "qty": "1"}"#,
+        ));
+        let results: Value = serde_json::from_str(&response.body).unwrap();
+        let quote = results[0]["Ok"]["Accepted"]["order_id"].as_str().unwrap();
+
+        // replace the quote
+        let body = json!({
+            "account": 1,
+            "cancels": [{"order_id": quote, "market": "BTC-USD"}],
+            "orders": [
+                {"market": "BTC-USD", "side": "ask", "price": "101", "qty": "1"},
+                {"market": "BTC-USD", "side": "bid", "price": "99", "qty": "1"},
+            ],
+        });
+        let (response, feed) =
+            exchange.handle(&request("POST", "/orders/batch", &body.to_string()));
+        assert_eq!(response.status, 200);
+        let batch: Value = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(batch["accepted"], true);
+        assert!(batch["cancels"][0][0]["Ok"]["Cancelled"].is_object());
+        assert_eq!(batch["orders"].as_array().unwrap().len(), 2);
+        assert_eq!(feed.len(), 3);
+        let summary = exchange.engine().account_summary(1);
+        assert_eq!(summary.open_orders.len(), 2);
+
+        // one bad order spoils the batch
+        let body = json!({
+            "account": 1,
+            "orders": [
+                {"market": "BTC-USD", "side": "ask", "price": "102", "qty": "1"},
+                {"market": "BTC-USD", "side": "ask", "price": "102", "qty": "0"},
+            ],
+            "all_or_nothing": true,
+        });
+        let (response, feed) =
+            exchange.handle(&request("POST", "/orders/batch", &body.to_string()));
+        let batch: Value = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(batch["accepted"], false);
+        assert!(batch["orders"][0][0]["Err"].is_object());
+        assert!(feed.is_empty());
+
+        let body = json!({"account": 1, "cancels": [{"order_id": quote, "market": "ETH-USD"}]});
+        let (response, _) = exchange.handle(&request("POST", "/orders/batch", &body.to_string()));
+        assert_eq!(response.status, 404);
+    }
+
+    #[test]
+    fn precision_normalized() {
+        let order = |price: &str, qty: &str| {
+            json!({"account": 1, "market": "BTC-USD", "side": "bid", "price": price, "qty": qty})
+                .to_string()
+        };
+        let mut exchange = Exchange::new(&demo_config()).unwrap();
+        let (response, _) = exchange.handle(&request("POST", "/orders", &order("99.2", "1")));
+        assert_eq!(response.status, 400);
+        exchange.handle(&request("POST", "/orders", &order("99.50", "1.0")));
+        let (response, _) = exchange.handle(&request("GET", "/accounts/1", ""));
+        let account: Value = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(account["open_orders"][0]["price"], "99.5");
+        assert_eq!(account["open_orders"][0]["qty"], "1");
+
+        let mut config = demo_config();
+        config.markets[0].qty_decimals = Some(2);
+        config.markets[0].precision = PrecisionPolicy::Round;
+        let mut exchange = Exchange::new(&config).unwrap();
+        exchange.handle(&request("POST", "/orders", &order("99.2", "1.239")));
+        let (response, _) = exchange.handle(&request("GET", "/accounts/1", ""));
+        let account: Value = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(account["open_orders"][0]["price"], "99");
+        assert_eq!(account["open_orders"][0]["qty"], "1.23");
+        let (response, _) = exchange.handle(&request("POST", "/orders", &order("99", "0.001")));
+        assert_eq!(response.status, 400);
+        let failed: Value = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(failed["code"], CODE_QTY_ROUNDS_TO_ZERO);
+        assert_eq!(failed["error"], "ValidationFailed");
+        assert_eq!(failed["detail"], "quantity rounds to zero");
+
+        config.markets[0].precision = PrecisionPolicy::Reject;
+        let mut exchange = Exchange::new(&config).unwrap();
+        let (response, _) = exchange.handle(&request("POST", "/orders", &order("99", "1.239")));
+        let failed: Value = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(failed["code"], CODE_QTY_TOO_PRECISE);
+        // the same code as the engine gives prices off the tick
+        let (response, _) = exchange.handle(&request("POST", "/orders", &order("99.2", "1")));
+        let failed: Value = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(failed["code"], CODE_OFF_TICK);
+        let batch = json!({
+            "account": 1,
+            "orders": [{"market": "BTC-USD", "side": "bid", "price": "99", "qty": "1.239"}],
+        });
+        let (response, _) = exchange.handle(&request("POST", "/orders/batch", &batch.to_string()));
+        assert_eq!(response.status, 400);
+        let failed: Value = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(failed["code"], CODE_QTY_TOO_PRECISE);
+    }
+}
@@ -0,0 +1,989 @@
+//! FIX 4.4 order entry on top of the engine's requests and events.
+//!
+//! Only application messages are translated: NewOrderSingle,
+//! OrderCancelRequest and OrderCancelReplaceRequest come in as
+//! `OrderRequest`s, the results of processing them go out as
+//! ExecutionReports and OrderCancelRejects. Logon, heartbeats and resends
+//! belong to the session layer of the gateway carrying the messages.
+
+use bigdecimal::{BigDecimal, Zero};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+use paper_core::guid::domain::{OrderSide, TimeInForce};
+use paper_core::guid::engine::Symbol;
+use paper_core::guid::errors::{
+    CODE_BAD_QUANTITY_VALUE, CODE_CANCEL_ONLY, CODE_DUPLICATE_ORDER_ID, CODE_MARKET_HALTED,
+    CODE_MARKET_SUSPENDED, CODE_UNKNOWN_MARKET,
+};
+use paper_core::guid::orderbook::{Failed, OrderProcessingResult, Success};
+use paper_core::guid::orders::{
+    amend_order_request, limit_order_cancel_request, new_limit_order_request,
+    new_market_order_request, new_stop_limit_order_request, new_stop_order_request, OrderRequest,
+};
+
+use crate::precision::trimmed;
+
+pub const BEGIN_STRING: &str = "FIX.4.4";
+pub const SOH: u8 = 0x01;
+
+/// Tags the adapter reads or writes
+pub mod tag {
+    pub const ACCOUNT: u32 = 1;
+    pub const AVG_PX: u32 = 6;
+    pub const BEGIN_STRING: u32 = 8;
+    pub const BODY_LENGTH: u32 = 9;
+    pub const CHECK_SUM: u32 = 10;
+    pub const CL_ORD_ID: u32 = 11;
+    pub const CUM_QTY: u32 = 14;
+    pub const EXEC_ID: u32 = 17;
+    pub const LAST_PX: u32 = 31;
+    pub const LAST_QTY: u32 = 32;
+    pub const MSG_SEQ_NUM: u32 = 34;
+    pub const MSG_TYPE: u32 = 35;
+    pub const ORDER_ID: u32 = 37;
+    pub const ORDER_QTY: u32 = 38;
+    pub const ORD_STATUS: u32 = 39;
+    pub const ORD_TYPE: u32 = 40;
+    pub const ORIG_CL_ORD_ID: u32 = 41;
+    pub const PRICE: u32 = 44;
+    pub const REF_SEQ_NUM: u32 = 45;
+    pub const SENDER_COMP_ID: u32 = 49;
+    pub const SENDING_TIME: u32 = 52;
+    pub const SIDE: u32 = 54;
+    pub const SYMBOL: u32 = 55;
+    pub const TARGET_COMP_ID: u32 = 56;
+    pub const TEXT: u32 = 58;
+    pub const TIME_IN_FORCE: u32 = 59;
+    pub const TRANSACT_TIME: u32 = 60;
+    pub const STOP_PX: u32 = 99;
+    pub const CXL_REJ_REASON: u32 = 102;
+    pub const ORD_REJ_REASON: u32 = 103;
+    pub const EXPIRE_TIME: u32 = 126;
+    pub const EXEC_TYPE: u32 = 150;
+    pub const LEAVES_QTY: u32 = 151;
+    pub const REF_MSG_TYPE: u32 = 372;
+    pub const BUSINESS_REJECT_REASON: u32 = 380;
+    pub const CXL_REJ_RESPONSE_TO: u32 = 434;
+    pub const SECONDARY_EXEC_ID: u32 = 527;
+}
+
+pub const MSG_NEW_ORDER_SINGLE: &str = "D";
+pub const MSG_ORDER_CANCEL_REQUEST: &str = "F";
+pub const MSG_ORDER_CANCEL_REPLACE_REQUEST: &str = "G";
+pub const MSG_EXECUTION_REPORT: &str = "8";
+pub const MSG_ORDER_CANCEL_REJECT: &str = "9";
+pub const MSG_BUSINESS_MESSAGE_REJECT: &str = "j";
+
+/// Why a message could not be read or turned into a request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FixError {
+    /// framing broken: begin string, body length, checksum or a field
+    Malformed(String),
+    /// required tag absent
+    Missing(u32),
+    /// tag present with a value out of its range
+    Invalid(u32),
+    /// OrigClOrdID of an order this session does not know, or no longer
+    UnknownOrder(String),
+    /// ClOrdID already in use by an open order
+    DuplicateClOrdID(String),
+    /// message type the adapter does not handle
+    Unsupported(String),
+}
+
+impl fmt::Display for FixError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FixError::Malformed(reason) => write!(f, "malformed message: {}", reason),
+            FixError::Missing(tag) => write!(f, "required tag {} missing", tag),
+            FixError::Invalid(tag) => write!(f, "value of tag {} is incorrect", tag),
+            FixError::UnknownOrder(id) => write!(f, "unknown order {}", id),
+            FixError::DuplicateClOrdID(id) => write!(f, "duplicate ClOrdID {}", id),
+            FixError::Unsupported(msg_type) => write!(f, "unsupported message type {}", msg_type),
+        }
+    }
+}
+
+impl std::error::Error for FixError {}
+
+/// Message as tag=value fields in wire order, without the BeginString,
+/// BodyLength and CheckSum framing which `encode` adds and `parse` checks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FixMessage {
+    fields: Vec<(u32, String)>,
+}
+
+impl FixMessage {
+    pub fn new(msg_type: &str) -> Self {
+        FixMessage {
+            fields: vec![(tag::MSG_TYPE, msg_type.to_string())],
+        }
+    }
+
+    /// Same message with one more field
+    pub fn with<V: fmt::Display>(mut self, tag: u32, value: V) -> Self {
+        self.fields.push((tag, value.to_string()));
+        self
+    }
+
+    pub fn msg_type(&self) -> &str {
+        self.get(tag::MSG_TYPE).unwrap_or_default()
+    }
+
+    /// Value of the first field with the tag
+    pub fn get(&self, tag: u32) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(known, _)| *known == tag)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Read a framed message, checking its body length and checksum
+    pub fn parse(bytes: &[u8]) -> Result<Self, FixError> {
+        let malformed = |reason: &str| FixError::Malformed(reason.to_string());
+        if bytes.last() != Some(&SOH) {
+            return Err(malformed("not terminated by SOH"));
+        }
+        let mut fields = Vec::new();
+        let mut offsets = Vec::new();
+        let mut start = 0;
+        for field in bytes[..bytes.len() - 1].split(|byte| *byte == SOH) {
+            let text = std::str::from_utf8(field).map_err(|_| malformed("not UTF-8"))?;
+            let (tag, value) = text
+                .split_once('=')
+                .ok_or_else(|| malformed("field without '='"))?;
+            let tag = tag.parse().map_err(|_| malformed("bad tag"))?;
+            if value.is_empty() {
+                return Err(FixError::Invalid(tag));
+            }
+            fields.push((tag, value.to_string()));
+            offsets.push(start);
+            start += field.len() + 1;
+        }
+        if fields.len() < 4 || fields[0] != (tag::BEGIN_STRING, BEGIN_STRING.to_string()) {
+            return Err(malformed("does not start with BeginString FIX.4.4"));
+        }
+        if fields[1].0 != tag::BODY_LENGTH || fields[2].0 != tag::MSG_TYPE {
+            return Err(malformed("BodyLength and MsgType must follow BeginString"));
+        }
+        let last = fields.len() - 1;
+        if fields[last].0 != tag::CHECK_SUM {
+            return Err(malformed("does not end with CheckSum"));
+        }
+        let body_length = offsets[last] - offsets[2];
+        if fields[1].1.parse() != Ok(body_length) {
+            return Err(malformed("wrong BodyLength"));
+        }
+        if fields[last].1 != format!("{:03}", checksum(&bytes[..offsets[last]])) {
+            return Err(malformed("wrong CheckSum"));
+        }
+        fields.truncate(last);
+        fields.drain(..2);
+        Ok(FixMessage { fields })
+    }
+
+    /// Message framed for the wire
+    pub fn encode(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        for (tag, value) in &self.fields {
+            body.extend_from_slice(format!("{}={}", tag, value).as_bytes());
+            body.push(SOH);
+        }
+        let mut bytes = format!(
+            "{}={}\x01{}={}\x01",
+            tag::BEGIN_STRING,
+            BEGIN_STRING,
+            tag::BODY_LENGTH,
+            body.len()
+        )
+        .into_bytes();
+        bytes.append(&mut body);
+        let trailer = format!("{}={:03}\x01", tag::CHECK_SUM, checksum(&bytes));
+        bytes.extend_from_slice(trailer.as_bytes());
+        bytes
+    }
+}
+
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+/// Order entered through the session, kept to fill in the quantities and
+/// IDs of its reports
+#[derive(Debug, Clone)]
+struct FixOrder<Asset> {
+    cl_ord_id: String,
+    /// ClOrdID of a cancel or replace request still waiting for its result
+    pending: Option<String>,
+    market: Symbol<Asset>,
+    symbol: String,
+    side: OrderSide,
+    /// total quantity, filled part included
+    qty: BigDecimal,
+    cum_qty: BigDecimal,
+    notional: BigDecimal,
+}
+
+/// Application side of one FIX session, translating in both directions.
+///
+/// Orders are known by their ClOrdID on the FIX side and by engine IDs on
+/// the other, the session maps between them until an order is done. Events
+/// of orders entered through other sessions are skipped, so every session
+/// can be handed all the results of the engine.
+pub struct FixSession<Asset> {
+    sender_comp_id: String,
+    target_comp_id: String,
+    next_seq: u64,
+    next_exec_id: u64,
+    orders: HashMap<Uuid, FixOrder<Asset>>,
+    cl_ord_ids: HashMap<String, Uuid>,
+}
+
+impl<Asset> FixSession<Asset>
+where
+    Asset: fmt::Debug + Copy + FromStr,
+{
+    /// Session sending as `sender_comp_id` to `target_comp_id`
+    pub fn new(sender_comp_id: &str, target_comp_id: &str) -> Self {
+        FixSession {
+            sender_comp_id: sender_comp_id.to_string(),
+            target_comp_id: target_comp_id.to_string(),
+            next_seq: 1,
+            next_exec_id: 1,
+            orders: HashMap::new(),
+            cl_ord_ids: HashMap::new(),
+        }
+    }
+
+    /// Engine request for an incoming message and the market it is for
+    pub fn to_request(
+        &mut self,
+        message: &FixMessage,
+    ) -> Result<(Symbol<Asset>, OrderRequest<Asset>), FixError> {
+        let ts = match message.get(tag::TRANSACT_TIME) {
+            Some(text) => parse_utc_timestamp(text).ok_or(FixError::Invalid(tag::TRANSACT_TIME))?,
+            None => SystemTime::now(),
+        };
+        match message.msg_type() {
+            MSG_NEW_ORDER_SINGLE => self.new_order(message, ts),
+            MSG_ORDER_CANCEL_REQUEST => {
+                let (id, side) = self.open_order(message)?;
+                let market = self.orders[&id].market;
+                Ok((market, limit_order_cancel_request(id, side)))
+            }
+            MSG_ORDER_CANCEL_REPLACE_REQUEST => {
+                let price = decimal(message, tag::PRICE)?;
+                let qty = decimal(message, tag::ORDER_QTY)?;
+                let (id, side) = self.open_order(message)?;
+                let market = self.orders[&id].market;
+                Ok((market, amend_order_request(id, side, price, qty, ts)))
+            }
+            other => Err(FixError::Unsupported(other.to_string())),
+        }
+    }
+
+    /// Reports of the results of processing `request`.
+    ///
+    /// Failures answer the request itself: ExecutionReports rejecting new
+    /// orders, OrderCancelRejects for cancels and replaces.
+    pub fn reports(
+        &mut self,
+        request: &OrderRequest<Asset>,
+        results: &OrderProcessingResult<Asset>,
+    ) -> Vec<FixMessage> {
+        let mut reports = Vec::new();
+        for result in results {
+            let report = match result {
+                Ok(event) => self.event_report(event),
+                Err(failed) if request.is_new_order() => self.rejected(request.order_id(), failed),
+                Err(failed) => self.cancel_rejected(request, failed),
+            };
+            reports.extend(report);
+        }
+        reports
+    }
+
+    /// Answer to a message `to_request` refused
+    pub fn reject(&mut self, message: &FixMessage, error: &FixError) -> FixMessage {
+        let text = error.to_string();
+        match message.msg_type() {
+            MSG_NEW_ORDER_SINGLE => {
+                let reason = match error {
+                    FixError::DuplicateClOrdID(_) => 6,
+                    FixError::Invalid(tag::SYMBOL) => 1,
+                    FixError::Invalid(tag::ORDER_QTY) => 13,
+                    _ => 99,
+                };
+                let exec_id = self.exec_id();
+                self.header(MSG_EXECUTION_REPORT)
+                    .with(tag::ORDER_ID, "NONE")
+                    .with(
+                        tag::CL_ORD_ID,
+                        message.get(tag::CL_ORD_ID).unwrap_or("NONE"),
+                    )
+                    .with(tag::EXEC_ID, exec_id)
+                    .with(tag::EXEC_TYPE, "8")
+                    .with(tag::ORD_STATUS, "8")
+                    .with(tag::SYMBOL, message.get(tag::SYMBOL).unwrap_or("NONE"))
+                    .with(tag::SIDE, message.get(tag::SIDE).unwrap_or("1"))
+                    .with(tag::LEAVES_QTY, 0)
+                    .with(tag::CUM_QTY, 0)
+                    .with(tag::AVG_PX, 0)
+                    .with(tag::ORD_REJ_REASON, reason)
+                    .with(tag::TEXT, text)
+            }
+            MSG_ORDER_CANCEL_REQUEST | MSG_ORDER_CANCEL_REPLACE_REQUEST => {
+                let reason = match error {
+                    FixError::UnknownOrder(_) => 1,
+                    FixError::DuplicateClOrdID(_) => 6,
+                    _ => 99,
+                };
+                let orig = message.get(tag::ORIG_CL_ORD_ID).unwrap_or("NONE");
+                let open = self.cl_ord_ids.get(orig).copied();
+                let order_id = open.map_or_else(|| String::from("NONE"), |id| id.to_string());
+                let status = open.map_or("8", |id| ord_status(&self.orders[&id]));
+                self.header(MSG_ORDER_CANCEL_REJECT)
+                    .with(tag::ORDER_ID, order_id)
+                    .with(
+                        tag::CL_ORD_ID,
+                        message.get(tag::CL_ORD_ID).unwrap_or("NONE"),
+                    )
+                    .with(tag::ORIG_CL_ORD_ID, orig)
+                    .with(tag::ORD_STATUS, status)
+                    .with(tag::CXL_REJ_RESPONSE_TO, response_to(message.msg_type()))
+                    .with(tag::CXL_REJ_REASON, reason)
+                    .with(tag::TEXT, text)
+            }
+            other => {
+                let mut reject = self
+                    .header(MSG_BUSINESS_MESSAGE_REJECT)
+                    .with(tag::REF_MSG_TYPE, other);
+                if let Some(seq) = message.get(tag::MSG_SEQ_NUM) {
+                    reject = reject.with(tag::REF_SEQ_NUM, seq);
+                }
+                // 3 = unsupported message type
+                reject
+                    .with(tag::BUSINESS_REJECT_REASON, 3)
+                    .with(tag::TEXT, text)
+            }
+        }
+    }
+
+    fn new_order(
+        &mut self,
+        message: &FixMessage,
+        ts: SystemTime,
+    ) -> Result<(Symbol<Asset>, OrderRequest<Asset>), FixError> {
+        let cl_ord_id = required(message, tag::CL_ORD_ID)?;
+        if self.cl_ord_ids.contains_key(cl_ord_id) {
+            return Err(FixError::DuplicateClOrdID(cl_ord_id.to_string()));
+        }
+        let symbol = required(message, tag::SYMBOL)?;
+        let market: Symbol<Asset> = parse_symbol(symbol).ok_or(FixError::Invalid(tag::SYMBOL))?;
+        let side = side(message)?;
+        let qty = decimal(message, tag::ORDER_QTY)?;
+        let (base, quote) = market;
+        let request = match required(message, tag::ORD_TYPE)? {
+            "1" => new_market_order_request(base, quote, side, qty.clone(), ts),
+            "2" => {
+                let price = decimal(message, tag::PRICE)?;
+                new_limit_order_request(base, quote, side, price, qty.clone(), ts)
+                    .with_time_in_force(time_in_force(message)?)
+            }
+            "3" => {
+                let stop_px = decimal(message, tag::STOP_PX)?;
+                new_stop_order_request(base, quote, side, stop_px, qty.clone(), ts)
+            }
+            "4" => {
+                let stop_px = decimal(message, tag::STOP_PX)?;
+                let price = decimal(message, tag::PRICE)?;
+                new_stop_limit_order_request(base, quote, side, stop_px, price, qty.clone(), ts)
+                    .with_time_in_force(time_in_force(message)?)
+            }
+            _ => return Err(FixError::Invalid(tag::ORD_TYPE)),
+        };
+        let request = match message.get(tag::ACCOUNT) {
+            Some(account) => {
+                let account = account
+                    .parse()
+                    .map_err(|_| FixError::Invalid(tag::ACCOUNT))?;
+                request.with_owner(account)
+            }
+            None => request,
+        };
+        let order_id = request.order_id();
+        self.cl_ord_ids.insert(cl_ord_id.to_string(), order_id);
+        self.orders.insert(
+            order_id,
+            FixOrder {
+                cl_ord_id: cl_ord_id.to_string(),
+                pending: None,
+                market,
+                symbol: symbol.to_string(),
+                side,
+                qty,
+                cum_qty: BigDecimal::zero(),
+                notional: BigDecimal::zero(),
+            },
+        );
+        Ok((market, request))
+    }
+
+    /// Open order a cancel or replace request is about, marked as waiting
+    /// for it
+    fn open_order(&mut self, message: &FixMessage) -> Result<(Uuid, OrderSide), FixError> {
+        let cl_ord_id = required(message, tag::CL_ORD_ID)?;
+        let orig = required(message, tag::ORIG_CL_ORD_ID)?;
+        let side = side(message)?;
+        let id = *self
+            .cl_ord_ids
+            .get(orig)
+            .ok_or_else(|| FixError::UnknownOrder(orig.to_string()))?;
+        if self.cl_ord_ids.contains_key(cl_ord_id) {
+            return Err(FixError::DuplicateClOrdID(cl_ord_id.to_string()));
+        }
+        self.orders.get_mut(&id).unwrap().pending = Some(cl_ord_id.to_string());
+        Ok((id, side))
+    }
+
+    fn event_report(&mut self, event: &Success<Asset>) -> Option<FixMessage> {
+        let (id, exec_type, ts) = match event {
+            Success::Accepted { order_id, ts, .. }
+            | Success::StopAccepted { order_id, ts, .. }
+            | Success::Activated { order_id, ts } => (*order_id, "0", ts),
+            Success::Filled {
+                order_id,
+                price,
+                qty,
+                ts,
+                ..
+            }
+            | Success::PartiallyFilled {
+                order_id,
+                price,
+                qty,
+                ts,
+                ..
+            } => {
+                let order = self.orders.get_mut(order_id)?;
+                order.cum_qty += qty;
+                order.notional += price * qty;
+                (*order_id, "F", ts)
+            }
+            Success::Amended {
+                order_id, qty, ts, ..
+            } => {
+                let order = self.orders.get_mut(order_id)?;
+                order.qty = &order.cum_qty + qty;
+                (*order_id, "5", ts)
+            }
+            Success::Cancelled { order_id, ts }
+            | Success::CancelledRemainder { order_id, ts, .. }
+            | Success::DustCancelled { order_id, ts, .. } => (*order_id, "4", ts),
+            Success::SelfTradePrevented {
+                order_id,
+                remaining,
+                ts,
+                ..
+            } => {
+                let order = self.orders.get_mut(order_id)?;
+                if remaining.is_zero() {
+                    (*order_id, "4", ts)
+                } else {
+                    order.qty = &order.cum_qty + remaining;
+                    (*order_id, "D", ts)
+                }
+            }
+            Success::Expired { order_id, ts } => (*order_id, "C", ts),
+            Success::Triggered { order_id, ts, .. } => (*order_id, "L", ts),
+            Success::PendingNew { order_id, ts } | Success::Scheduled { order_id, ts, .. } => {
+                (*order_id, "A", ts)
+            }
+        };
+        let order = self.orders.get(&id)?.clone();
+        let status = match exec_type {
+            "4" | "C" | "A" => exec_type,
+            _ if order.cum_qty >= order.qty => "2",
+            _ => ord_status(&order),
+        };
+        let done = matches!(status, "2" | "4" | "C");
+        let leaves = if done {
+            BigDecimal::zero()
+        } else {
+            &order.qty - &order.cum_qty
+        };
+        let exec_id = self.exec_id();
+        let mut report = self.header(MSG_EXECUTION_REPORT).with(tag::ORDER_ID, id);
+        // a cancel or replace answered by this event renames the order
+        let answered = matches!(event, Success::Cancelled { .. } | Success::Amended { .. });
+        report = match order.pending.as_ref().filter(|_| answered) {
+            Some(cl_ord_id) => report
+                .with(tag::CL_ORD_ID, cl_ord_id)
+                .with(tag::ORIG_CL_ORD_ID, &order.cl_ord_id),
+            None => report.with(tag::CL_ORD_ID, &order.cl_ord_id),
+        };
+        report = report
+            .with(tag::EXEC_ID, exec_id)
+            .with(tag::EXEC_TYPE, exec_type)
+            .with(tag::ORD_STATUS, status)
+            .with(tag::SYMBOL, &order.symbol)
+            .with(tag::SIDE, side_code(order.side))
+            .with(tag::ORDER_QTY, trimmed(order.qty.clone()))
+            .with(tag::LEAVES_QTY, trimmed(leaves))
+            .with(tag::CUM_QTY, trimmed(order.cum_qty.clone()))
+            .with(tag::AVG_PX, avg_px(&order));
+        match event {
+            Success::Filled {
+                trade_id,
+                price,
+                qty,
+                ..
+            }
+            | Success::PartiallyFilled {
+                trade_id,
+                price,
+                qty,
+                ..
+            } => {
+                report = report
+                    .with(tag::LAST_QTY, trimmed(qty.clone()))
+                    .with(tag::LAST_PX, trimmed(price.clone()))
+                    .with(tag::SECONDARY_EXEC_ID, trade_id);
+            }
+            Success::Amended { price, .. } => {
+                report = report.with(tag::PRICE, trimmed(price.clone()));
+            }
+            _ => (),
+        }
+        report = report.with(tag::TRANSACT_TIME, utc_timestamp(*ts));
+
+        if answered {
+            let order = self.orders.get_mut(&id).unwrap();
+            if let Some(cl_ord_id) = order.pending.take() {
+                self.cl_ord_ids.remove(&order.cl_ord_id);
+                self.cl_ord_ids.insert(cl_ord_id.clone(), id);
+                order.cl_ord_id = cl_ord_id;
+            }
+        }
+        if done {
+            self.forget(id);
+        }
+        Some(report)
+    }
+
+    fn rejected(&mut self, id: Uuid, failed: &Failed) -> Option<FixMessage> {
+        let order = self.orders.get(&id)?.clone();
+        // OrdRejReason: unknown symbol, exchange closed, incorrect quantity,
+        // duplicate order, other
+        let reason = match failed.code() {
+            CODE_UNKNOWN_MARKET => 1,
+            CODE_MARKET_HALTED | CODE_CANCEL_ONLY | CODE_MARKET_SUSPENDED => 2,
+            CODE_BAD_QUANTITY_VALUE => 13,
+            CODE_DUPLICATE_ORDER_ID => 6,
+            _ => 99,
+        };
+        let exec_id = self.exec_id();
+        let report = self
+            .header(MSG_EXECUTION_REPORT)
+            .with(tag::ORDER_ID, id)
+            .with(tag::CL_ORD_ID, &order.cl_ord_id)
+            .with(tag::EXEC_ID, exec_id)
+            .with(tag::EXEC_TYPE, "8")
+            .with(tag::ORD_STATUS, "8")
+            .with(tag::SYMBOL, &order.symbol)
+            .with(tag::SIDE, side_code(order.side))
+            .with(tag::ORDER_QTY, trimmed(order.qty.clone()))
+            .with(tag::LEAVES_QTY, 0)
+            .with(tag::CUM_QTY, trimmed(order.cum_qty.clone()))
+            .with(tag::AVG_PX, avg_px(&order))
+            .with(tag::ORD_REJ_REASON, reason)
+            .with(tag::TEXT, failure_text(failed));
+        self.forget(id);
+        Some(report)
+    }
+
+    fn cancel_rejected(
+        &mut self,
+        request: &OrderRequest<Asset>,
+        failed: &Failed,
+    ) -> Option<FixMessage> {
+        let (id, msg_type) = match request {
+            OrderRequest::CancelOrder { id, .. } => (*id, MSG_ORDER_CANCEL_REQUEST),
+            OrderRequest::AmendOrder { id, .. } => (*id, MSG_ORDER_CANCEL_REPLACE_REQUEST),
+            _ => return None,
+        };
+        let order = self.orders.get_mut(&id)?;
+        let cl_ord_id = order.pending.take()?;
+        let reason = match failed {
+            Failed::TooLateToCancel(_) | Failed::TooLateToAmend(_) => 0,
+            Failed::OrderNotFound(_) => 1,
+            _ => 99,
+        };
+        let order = order.clone();
+        Some(
+            self.header(MSG_ORDER_CANCEL_REJECT)
+                .with(tag::ORDER_ID, id)
+                .with(tag::CL_ORD_ID, cl_ord_id)
+                .with(tag::ORIG_CL_ORD_ID, &order.cl_ord_id)
+                .with(tag::ORD_STATUS, ord_status(&order))
+                .with(tag::CXL_REJ_RESPONSE_TO, response_to(msg_type))
+                .with(tag::CXL_REJ_REASON, reason)
+                .with(tag::TEXT, failure_text(failed)),
+        )
+    }
+
+    fn forget(&mut self, id: Uuid) {
+        if let Some(order) = self.orders.remove(&id) {
+            self.cl_ord_ids.remove(&order.cl_ord_id);
+        }
+    }
+
+    fn header(&mut self, msg_type: &str) -> FixMessage {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        FixMessage::new(msg_type)
+            .with(tag::SENDER_COMP_ID, &self.sender_comp_id)
+            .with(tag::TARGET_COMP_ID, &self.target_comp_id)
+            .with(tag::MSG_SEQ_NUM, seq)
+            .with(tag::SENDING_TIME, utc_timestamp(SystemTime::now()))
+    }
+
+    fn exec_id(&mut self) -> u64 {
+        let exec_id = self.next_exec_id;
+        self.next_exec_id += 1;
+        exec_id
+    }
+}
+
+fn required(message: &FixMessage, tag: u32) -> Result<&str, FixError> {
+    message.get(tag).ok_or(FixError::Missing(tag))
+}
+
+/// Positive decimal value of a tag
+fn decimal(message: &FixMessage, tag: u32) -> Result<BigDecimal, FixError> {
+    BigDecimal::from_str(required(message, tag)?)
+        .ok()
+        .filter(|value| *value > BigDecimal::zero())
+        .ok_or(FixError::Invalid(tag))
+}
+
+fn side(message: &FixMessage) -> Result<OrderSide, FixError> {
+    match required(message, tag::SIDE)? {
+        "1" => Ok(OrderSide::Bid),
+        "2" => Ok(OrderSide::Ask),
+        _ => Err(FixError::Invalid(tag::SIDE)),
+    }
+}
+
+fn side_code(side: OrderSide) -> &'static str {
+    match side {
+        OrderSide::Bid => "1",
+        OrderSide::Ask => "2",
+    }
+}
+
+/// Day when absent, as FIX defaults it
+fn time_in_force(message: &FixMessage) -> Result<TimeInForce, FixError> {
+    match message.get(tag::TIME_IN_FORCE).unwrap_or("0") {
+        "0" => Ok(TimeInForce::Day),
+        "1" => Ok(TimeInForce::GTC),
+        "3" => Ok(TimeInForce::IOC),
+        "4" => Ok(TimeInForce::FOK),
+        "6" => {
+            let expire_time = required(message, tag::EXPIRE_TIME)?;
+            parse_utc_timestamp(expire_time)
+                .map(TimeInForce::GTD)
+                .ok_or(FixError::Invalid(tag::EXPIRE_TIME))
+        }
+        _ => Err(FixError::Invalid(tag::TIME_IN_FORCE)),
+    }
+}
+
+/// Market of a symbol written "BTC/USD" or "BTC-USD"
+fn parse_symbol<Asset: FromStr>(symbol: &str) -> Option<Symbol<Asset>> {
+    let (base, quote) = symbol.split_once(['/', '-'])?;
+    Some((base.parse().ok()?, quote.parse().ok()?))
+}
+
+/// OrdStatus of an order still open
+fn ord_status<Asset>(order: &FixOrder<Asset>) -> &'static str {
+    if order.cum_qty.is_zero() {
+        "0"
+    } else {
+        "1"
+    }
+}
+
+fn response_to(msg_type: &str) -> &'static str {
+    if msg_type == MSG_ORDER_CANCEL_REQUEST {
+        "1"
+    } else {
+        "2"
+    }
+}
+
+fn avg_px<Asset>(order: &FixOrder<Asset>) -> BigDecimal {
+    if order.cum_qty.is_zero() {
+        BigDecimal::zero()
+    } else {
+        trimmed((&order.notional / &order.cum_qty).round(8))
+    }
+}
+
+fn failure_text(failed: &Failed) -> String {
+    format!("{} {}: {}", failed.code(), failed.name(), failed.detail())
+}
+
+/// UTCTimestamp with milliseconds, e.g. "20240131-09:30:00.000"
+pub fn utc_timestamp(ts: SystemTime) -> String {
+    let since_epoch = ts.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs() as i64;
+    let (year, month, day) = civil_from_days(secs.div_euclid(86_400));
+    let secs_of_day = secs.rem_euclid(86_400);
+    format!(
+        "{:04}{:02}{:02}-{:02}:{:02}:{:02}.{:03}",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// UTCTimestamp with or without milliseconds
+pub fn parse_utc_timestamp(text: &str) -> Option<SystemTime> {
+    let (date, time) = text.split_once('-')?;
+    let (time, millis) = match time.split_once('.') {
+        Some((time, millis)) if millis.len() == 3 => (time, millis.parse::<u64>().ok()?),
+        Some(_) => return None,
+        None => (time, 0),
+    };
+    if date.len() != 8 || time.len() != 8 || !date.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    let year: i64 = date[..4].parse().ok()?;
+    let month: u32 = date[4..6].parse().ok()?;
+    let day: u32 = date[6..].parse().ok()?;
+    let mut parts = time.split(':').map(|part| part.parse::<u64>().ok());
+    let (hour, minute, second) = (parts.next()??, parts.next()??, parts.next()??);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 {
+        return None;
+    }
+    // 60 is a leap second
+    if second > 60 {
+        return None;
+    }
+    let days = u64::try_from(days_from_civil(year, month, day)).ok()?;
+    let secs = days * 86_400 + hour * 3600 + minute * 60 + second;
+    Some(UNIX_EPOCH + Duration::from_secs(secs) + Duration::from_millis(millis))
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = i64::from(month);
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::asset::AssetCode;
+    use paper_core::guid::orderbook::Orderbook;
+
+    fn new_order_single(cl_ord_id: &str, side: &str, ord_type: &str, qty: &str) -> FixMessage {
+        order_for("BTC/USD", cl_ord_id, side, ord_type, qty)
+    }
+
+    fn order_for(
+        symbol: &str,
+        cl_ord_id: &str,
+        side: &str,
+        ord_type: &str,
+        qty: &str,
+    ) -> FixMessage {
+        FixMessage::new(MSG_NEW_ORDER_SINGLE)
+            .with(tag::CL_ORD_ID, cl_ord_id)
+            .with(tag::SYMBOL, symbol)
+            .with(tag::SIDE, side)
+            .with(tag::ORDER_QTY, qty)
+            .with(tag::ORD_TYPE, ord_type)
+            .with(tag::TRANSACT_TIME, "20240131-09:30:00.000")
+    }
+
+    /// Run a message through the session and the book, as the wire would
+    fn send(
+        session: &mut FixSession<AssetCode>,
+        book: &mut Orderbook<AssetCode>,
+        message: FixMessage,
+    ) -> Vec<FixMessage> {
+        let message = FixMessage::parse(&message.encode()).unwrap();
+        match session.to_request(&message) {
+            Ok((_, request)) => {
+                let results = book.process_order(request.clone());
+                session.reports(&request, &results)
+            }
+            Err(err) => vec![session.reject(&message, &err)],
+        }
+    }
+
+    fn fields<'a>(report: &'a FixMessage, tags: &[u32]) -> Vec<&'a str> {
+        tags.iter().map(|tag| report.get(*tag).unwrap()).collect()
+    }
+
+    #[test]
+    fn framing() {
+        let message = new_order_single("a", "1", "1", "2");
+        let bytes = message.encode();
+        assert!(bytes.starts_with(b"8=FIX.4.4\x019="));
+        assert_eq!(FixMessage::parse(&bytes), Ok(message));
+
+        let mut corrupted = bytes.clone();
+        let at = corrupted.iter().position(|byte| *byte == b'B').unwrap();
+        corrupted[at] = b'E';
+        assert_eq!(
+            FixMessage::parse(&corrupted),
+            Err(FixError::Malformed(String::from("wrong CheckSum")))
+        );
+        assert!(FixMessage::parse(&bytes[..bytes.len() - 1]).is_err());
+
+        let ts = parse_utc_timestamp("20240229-23:59:58.250").unwrap();
+        assert_eq!(utc_timestamp(ts), "20240229-23:59:58.250");
+        assert_eq!(utc_timestamp(UNIX_EPOCH), "19700101-00:00:00.000");
+        assert_eq!(parse_utc_timestamp("20240230-25:00:00"), None);
+    }
+
+    #[test]
+    fn order_entry() {
+        let btc = AssetCode::from_str("BTC").unwrap();
+        let usd = AssetCode::from_str("USD").unwrap();
+        let mut book = Orderbook::new(btc, usd);
+        let mut session = FixSession::new("PAPER", "CLIENT");
+        let report_tags = [
+            tag::CL_ORD_ID,
+            tag::EXEC_TYPE,
+            tag::ORD_STATUS,
+            tag::LEAVES_QTY,
+            tag::CUM_QTY,
+        ];
+
+        let sell = new_order_single("s1", "2", "2", "10").with(tag::PRICE, "100");
+        let reports = send(&mut session, &mut book, sell);
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].msg_type(), MSG_EXECUTION_REPORT);
+        assert_eq!(
+            fields(&reports[0], &report_tags),
+            ["s1", "0", "0", "10", "0"]
+        );
+        assert_eq!(reports[0].get(tag::MSG_SEQ_NUM), Some("1"));
+
+        // both sides of the trade were entered here, both get a report
+        let buy = new_order_single("b1", "1", "1", "4");
+        let reports = send(&mut session, &mut book, buy);
+        let fills: Vec<_> = reports
+            .iter()
+            .filter(|report| report.get(tag::EXEC_TYPE) == Some("F"))
+            .collect();
+        assert_eq!(fills.len(), 2);
+        let resting = fills
+            .iter()
+            .find(|report| report.get(tag::CL_ORD_ID) == Some("s1"))
+            .unwrap();
+        assert_eq!(fields(resting, &report_tags), ["s1", "F", "1", "6", "4"]);
+        assert_eq!(
+            fields(resting, &[tag::LAST_QTY, tag::LAST_PX, tag::AVG_PX]),
+            ["4", "100", "100"]
+        );
+
+        // replace asks for the total quantity, fills included
+        let replace = FixMessage::new(MSG_ORDER_CANCEL_REPLACE_REQUEST)
+            .with(tag::ORIG_CL_ORD_ID, "s1")
+            .with(tag::CL_ORD_ID, "s2")
+            .with(tag::SYMBOL, "BTC/USD")
+            .with(tag::SIDE, "2")
+            .with(tag::ORDER_QTY, "8")
+            .with(tag::ORD_TYPE, "2")
+            .with(tag::PRICE, "101");
+        let reports = send(&mut session, &mut book, replace);
+        assert_eq!(reports.len(), 1);
+        assert_eq!(
+            fields(&reports[0], &report_tags),
+            ["s2", "5", "1", "4", "4"]
+        );
+        assert_eq!(reports[0].get(tag::ORIG_CL_ORD_ID), Some("s1"));
+        assert_eq!(book.best_ask(), Some(&BigDecimal::from(101)));
+
+        let cancel = |orig: &str, cl_ord_id: &str| {
+            FixMessage::new(MSG_ORDER_CANCEL_REQUEST)
+                .with(tag::ORIG_CL_ORD_ID, orig)
+                .with(tag::CL_ORD_ID, cl_ord_id)
+                .with(tag::SYMBOL, "BTC/USD")
+                .with(tag::SIDE, "2")
+        };
+        // the order is known by its new ClOrdID only
+        let reports = send(&mut session, &mut book, cancel("s1", "s3"));
+        assert_eq!(reports[0].msg_type(), MSG_ORDER_CANCEL_REJECT);
+        assert_eq!(
+            fields(
+                &reports[0],
+                &[tag::CXL_REJ_REASON, tag::CXL_REJ_RESPONSE_TO]
+            ),
+            ["1", "1"]
+        );
+
+        let reports = send(&mut session, &mut book, cancel("s2", "s3"));
+        assert_eq!(
+            fields(&reports[0], &report_tags),
+            ["s3", "4", "4", "0", "4"]
+        );
+        assert_eq!(book.best_ask(), None);
+
+        // failed engine checks come back as rejected executions
+        let bad_market = order_for("ETH/USD", "x1", "1", "2", "1").with(tag::PRICE, "100");
+        let reports = send(&mut session, &mut book, bad_market);
+        assert_eq!(
+            fields(&reports[0], &report_tags),
+            ["x1", "8", "8", "0", "0"]
+        );
+        assert!(reports[0].get(tag::TEXT).unwrap().starts_with("1001 "));
+
+        let no_price = new_order_single("x2", "1", "2", "1");
+        let reports = send(&mut session, &mut book, no_price);
+        assert_eq!(reports[0].get(tag::TEXT), Some("required tag 44 missing"));
+
+        let logon = FixMessage::new("A").with(tag::MSG_SEQ_NUM, 7);
+        let reports = send(&mut session, &mut book, logon);
+        assert_eq!(reports[0].msg_type(), MSG_BUSINESS_MESSAGE_REJECT);
+        assert_eq!(reports[0].get(tag::REF_SEQ_NUM), Some("7"));
+    }
+}
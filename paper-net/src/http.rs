@@ -0,0 +1,181 @@
+//! Just enough HTTP/1.1 for JSON order entry: one request per connection,
+//! bodies sized by `Content-Length`.
+
+use std::io::{self, BufRead, Write};
+
+/// Requests larger than this are refused
+const MAX_BODY_LEN: usize = 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    pub method: String,
+    /// path without the query
+    pub path: String,
+    pub query: Vec<(String, String)>,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// Value of the header, names compared case-insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn query_param(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Response with a JSON body
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub body: String,
+}
+
+impl Response {
+    pub fn json(status: u16, body: String) -> Self {
+        Response { status, body }
+    }
+
+    /// `{"error": reason}`
+    pub fn error(status: u16, reason: &str) -> Self {
+        Response {
+            status,
+            body: serde_json::json!({ "error": reason }).to_string(),
+        }
+    }
+}
+
+/// Read the next request, None if the peer closed the connection first
+pub fn read_request<R: BufRead>(reader: &mut R) -> io::Result<Option<Request>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    let mut parts = line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target.to_string()),
+        _ => return Err(invalid("malformed request line")),
+    };
+
+    let mut headers = vec![];
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid("headers cut short"));
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        match header.split_once(':') {
+            Some((name, value)) => {
+                headers.push((name.trim().to_string(), value.trim().to_string()))
+            }
+            None => return Err(invalid("malformed header")),
+        }
+    }
+
+    let (path, query) = split_target(&target);
+    let mut request = Request {
+        method,
+        path,
+        query,
+        headers,
+        body: vec![],
+    };
+
+    let len = match request.header("Content-Length") {
+        Some(len) => len.parse().map_err(|_| invalid("bad content length"))?,
+        None => 0,
+    };
+    if len > MAX_BODY_LEN {
+        return Err(invalid("body too large"));
+    }
+    request.body.resize(len, 0);
+    reader.read_exact(&mut request.body)?;
+
+    Ok(Some(request))
+}
+
+/// Write the response and announce the connection closes after it
+pub fn write_response<W: Write>(writer: &mut W, response: &Response) -> io::Result<()> {
+    write!(
+        writer,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        reason_phrase(response.status),
+        response.body.len(),
+        response.body
+    )?;
+    writer.flush()
+}
+
+/* Helpers */
+
+/// Path and query of a request target
+pub(crate) fn split_target(target: &str) -> (String, Vec<(String, String)>) {
+    match target.split_once('?') {
+        Some((path, query)) => (path.to_string(), parse_query(query)),
+        None => (target.to_string(), vec![]),
+    }
+}
+
+fn parse_query(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((key, value)) => (key.to_string(), value.to_string()),
+            None => (pair.to_string(), String::new()),
+        })
+        .collect()
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        101 => "Switching Protocols",
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        503 => "Service Unavailable",
+        _ => "Unknown",
+    }
+}
+
+fn invalid(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn request_round() {
+        let raw = "DELETE /orders/42?market=BTC-USD&side=bid HTTP/1.1\r\nHost: x\r\ncontent-length: 2\r\n\r\n{}";
+        let request = read_request(&mut raw.as_bytes()).unwrap().unwrap();
+        assert_eq!(request.method, "DELETE");
+        assert_eq!(request.path, "/orders/42");
+        assert_eq!(request.query_param("side"), Some("bid"));
+        assert_eq!(request.header("Content-Length"), Some("2"));
+        assert_eq!(request.body, b"{}");
+        assert!(read_request(&mut "".as_bytes()).unwrap().is_none());
+        assert!(read_request(&mut "GET\r\n\r\n".as_bytes()).is_err());
+
+        let mut written = vec![];
+        write_response(&mut written, &Response::error(404, "no route")).unwrap();
+        let written = String::from_utf8(written).unwrap();
+        assert!(written.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        assert!(written.ends_with("\r\n\r\n{\"error\":\"no route\"}"));
+    }
+}
@@ -0,0 +1,18 @@
+//! Network servers and gateways on top of the matching engine.
+//!
+//! Transports live here so that `paper-core` stays free of I/O.
+
+pub use paper_core as core;
+pub use paper_serde as serde;
+
+pub mod asset;
+pub mod client;
+pub mod config;
+pub mod exchange;
+#[cfg(feature = "fix")]
+pub mod fix;
+pub mod http;
+pub mod precision;
+pub mod schema;
+pub mod server;
+pub mod websocket;
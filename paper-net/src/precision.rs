@@ -0,0 +1,126 @@
+use bigdecimal::{BigDecimal, RoundingMode, Zero};
+use serde::Deserialize;
+
+use paper_core::guid::domain::OrderSide;
+use paper_core::guid::errors::{ERR_QTY_ROUNDS_TO_ZERO, ERR_QTY_TOO_PRECISE};
+use paper_core::guid::price_key::PriceScale;
+
+/// What to do with a price or quantity finer than its market supports
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PrecisionPolicy {
+    #[default]
+    Reject,
+    /// prices to the passive side of the tick, quantities down
+    Round,
+}
+
+/// Precision a market accepts, applied to requests before they reach the
+/// engine.
+///
+/// Normalized values are written without trailing zeros, so "1.50" and "1.5"
+/// come back the same in responses and on the feed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Precision {
+    pub price_scale: PriceScale,
+    /// decimals of quantities, any when None
+    pub qty_decimals: Option<u32>,
+    pub policy: PrecisionPolicy,
+}
+
+impl Precision {
+    /// Limit price on the tick grid, bids rounded down and asks up
+    pub fn price(&self, side: OrderSide, price: &BigDecimal) -> Result<BigDecimal, &'static str> {
+        let mode = match side {
+            OrderSide::Bid => RoundingMode::Floor,
+            OrderSide::Ask => RoundingMode::Ceiling,
+        };
+        let rounded = self.price_scale.round(price, mode);
+        if rounded != *price && self.policy == PrecisionPolicy::Reject {
+            return Err("price not on tick size");
+        }
+        Ok(trimmed(rounded))
+    }
+
+    /// Quantity to the decimals of the market, rounded down
+    pub fn qty(&self, qty: &BigDecimal) -> Result<BigDecimal, &'static str> {
+        let decimals = match self.qty_decimals {
+            Some(decimals) => decimals,
+            None => return Ok(trimmed(qty.clone())),
+        };
+        let rounded = qty.with_scale_round(decimals as i64, RoundingMode::Down);
+        if rounded != *qty {
+            if self.policy == PrecisionPolicy::Reject {
+                return Err(ERR_QTY_TOO_PRECISE);
+            }
+            if rounded.is_zero() {
+                return Err(ERR_QTY_ROUNDS_TO_ZERO);
+            }
+        }
+        Ok(trimmed(rounded))
+    }
+}
+
+/// Same value without trailing zeros, never in exponent form
+pub(crate) fn trimmed(value: BigDecimal) -> BigDecimal {
+    let normalized = value.normalized();
+    if normalized.as_bigint_and_exponent().1 < 0 {
+        normalized.with_scale(0)
+    } else {
+        normalized
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+
+    fn bigdec(num: &str) -> BigDecimal {
+        BigDecimal::from_str(num).unwrap()
+    }
+
+    #[test]
+    fn reject_or_round() {
+        let mut precision = Precision {
+            price_scale: PriceScale::from_tick(&bigdec("0.5")).unwrap(),
+            qty_decimals: Some(2),
+            policy: PrecisionPolicy::Reject,
+        };
+        assert_eq!(
+            precision
+                .price(OrderSide::Bid, &bigdec("100.50"))
+                .unwrap()
+                .to_string(),
+            "100.5"
+        );
+        assert_eq!(
+            precision
+                .price(OrderSide::Ask, &bigdec("1000"))
+                .unwrap()
+                .to_string(),
+            "1000"
+        );
+        assert!(precision.price(OrderSide::Bid, &bigdec("100.2")).is_err());
+        assert_eq!(precision.qty(&bigdec("1.250")).unwrap().to_string(), "1.25");
+        assert!(precision.qty(&bigdec("1.255")).is_err());
+
+        precision.policy = PrecisionPolicy::Round;
+        assert_eq!(
+            precision.price(OrderSide::Bid, &bigdec("100.2")).unwrap(),
+            bigdec("100")
+        );
+        assert_eq!(
+            precision.price(OrderSide::Ask, &bigdec("100.2")).unwrap(),
+            bigdec("100.5")
+        );
+        assert_eq!(precision.qty(&bigdec("1.259")).unwrap(), bigdec("1.25"));
+        assert!(precision.qty(&bigdec("0.001")).is_err());
+
+        precision.qty_decimals = None;
+        assert_eq!(
+            precision.qty(&bigdec("0.0010")).unwrap().to_string(),
+            "0.001"
+        );
+    }
+}
@@ -0,0 +1,396 @@
+use serde_json::{json, Map, Value};
+
+/// Fields of every `Success` event as (name, schema), in declaration order.
+///
+/// Kept by hand next to the engine types, the test below checks it against
+/// what the exchange really sends.
+fn success_variants() -> Vec<(&'static str, Vec<(&'static str, Value)>)> {
+    vec![
+        (
+            "Accepted",
+            vec![
+                ("order_id", uuid()),
+                ("order_asset", asset()),
+                ("order_type", order_type()),
+                ("price_asset", asset()),
+                ("price", nullable(number())),
+                ("collared_from", nullable(number())),
+                ("qty", number()),
+                ("side", side()),
+                ("ts", timestamp()),
+                ("liquidation", boolean()),
+            ],
+        ),
+        ("Filled", fill()),
+        ("PartiallyFilled", fill()),
+        (
+            "Amended",
+            vec![
+                ("order_id", uuid()),
+                ("price", number()),
+                ("qty", number()),
+                ("prev_price", nullable(number())),
+                ("prev_qty", number()),
+                ("priority_kept", boolean()),
+                ("ts", timestamp()),
+            ],
+        ),
+        ("Cancelled", vec![("order_id", uuid()), ("ts", timestamp())]),
+        (
+            "StopAccepted",
+            vec![
+                ("order_id", uuid()),
+                ("order_asset", asset()),
+                ("price_asset", asset()),
+                ("side", side()),
+                ("trigger_price", number()),
+                ("price", nullable(number())),
+                ("qty", number()),
+                ("ts", timestamp()),
+                ("liquidation", boolean()),
+            ],
+        ),
+        (
+            "Triggered",
+            vec![
+                ("order_id", uuid()),
+                ("trigger_price", number()),
+                ("last_price", number()),
+                ("ts", timestamp()),
+            ],
+        ),
+        (
+            "PendingNew",
+            vec![("order_id", uuid()), ("ts", timestamp())],
+        ),
+        (
+            "Scheduled",
+            vec![
+                ("order_id", uuid()),
+                ("activate_at", timestamp()),
+                ("ts", timestamp()),
+            ],
+        ),
+        ("Activated", vec![("order_id", uuid()), ("ts", timestamp())]),
+        ("Expired", vec![("order_id", uuid()), ("ts", timestamp())]),
+        (
+            "CancelledRemainder",
+            vec![
+                ("order_id", uuid()),
+                ("unfilled_qty", number()),
+                ("ts", timestamp()),
+            ],
+        ),
+        (
+            "SelfTradePrevented",
+            vec![
+                ("order_id", uuid()),
+                ("owner", json!({"type": "integer", "minimum": 0})),
+                ("qty", number()),
+                ("remaining", number()),
+                ("ts", timestamp()),
+            ],
+        ),
+        (
+            "DustCancelled",
+            vec![
+                ("order_id", uuid()),
+                ("side", side()),
+                ("qty", number()),
+                ("ts", timestamp()),
+            ],
+        ),
+    ]
+}
+
+const FAILURES: &[&str] = &[
+    "ValidationFailed",
+    "DuplicateOrderID",
+    "NoMatch",
+    "OrderNotFound",
+    "BasketRejected",
+    "DepthLimitExceeded",
+    "Overloaded",
+    "RiskRejected",
+    "TooLateToCancel",
+    "TooLateToAmend",
+    "SideMismatch",
+    "QtyBelowFilled",
+    "JournalFailed",
+];
+
+/// OpenAPI 3.1 components describing the bodies of the REST API, served on
+/// `GET /schema` so clients can generate their models.
+///
+/// * `NewOrder`, body of `POST /orders`
+/// * `OrderBatch`, body of `POST /orders/batch`, answered by `BatchResult`
+/// * `OrderProcessingResult`, answer to order entry and cancels, a list of
+///   `{"Ok": Success}` and `{"Err": Failed}`
+///
+/// Decimals are read from strings or numbers, engine events write them as
+/// numbers.
+pub fn openapi() -> Value {
+    let success: Vec<Value> = success_variants()
+        .into_iter()
+        .map(|(name, fields)| tagged(name, object(fields)))
+        .collect();
+
+    json!({
+        "openapi": "3.1.0",
+        "info": {"title": "paper exchange", "version": env!("CARGO_PKG_VERSION")},
+        "components": {
+            "schemas": {
+                "Decimal": {
+                    "type": ["string", "number"],
+                    "description": "exact when sent as a string",
+                },
+                "Timestamp": object(vec![
+                    ("secs_since_epoch", json!({"type": "integer", "minimum": 0})),
+                    ("nanos_since_epoch", json!({"type": "integer", "minimum": 0})),
+                ]),
+                "MarketProtection": {
+                    "oneOf": [
+                        tagged("Slippage", reference("Decimal")),
+                        tagged("Price", reference("Decimal")),
+                    ],
+                },
+                "NewOrder": {
+                    "type": "object",
+                    "properties": {
+                        "account": {"type": "integer", "minimum": 0},
+                        "market": {"type": "string", "examples": ["BTC-USD"]},
+                        "side": side(),
+                        "price": {
+                            "$ref": "#/components/schemas/Decimal",
+                            "description": "market order without it",
+                        },
+                        "qty": reference("Decimal"),
+                        "protection": reference("MarketProtection"),
+                    },
+                    "required": ["account", "market", "side", "qty"],
+                    "additionalProperties": false,
+                },
+                "OrderBatch": {
+                    "type": "object",
+                    "properties": {
+                        "account": {"type": "integer", "minimum": 0},
+                        "cancels": {
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "properties": {
+                                    "order_id": uuid(),
+                                    "market": {"type": "string"},
+                                    "side": side(),
+                                },
+                                "required": ["order_id", "market"],
+                                "additionalProperties": false,
+                            },
+                        },
+                        "orders": {
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "properties": {
+                                    "market": {"type": "string"},
+                                    "side": side(),
+                                    "price": reference("Decimal"),
+                                    "qty": reference("Decimal"),
+                                    "protection": reference("MarketProtection"),
+                                },
+                                "required": ["market", "side", "qty"],
+                                "additionalProperties": false,
+                            },
+                        },
+                        "all_or_nothing": boolean(),
+                    },
+                    "required": ["account"],
+                    "additionalProperties": false,
+                },
+                "BatchResult": object(vec![
+                    ("accepted", boolean()),
+                    ("cancels", json!({"type": "array", "items": reference("OrderProcessingResult")})),
+                    ("orders", json!({"type": "array", "items": reference("OrderProcessingResult")})),
+                ]),
+                "Success": {"oneOf": success},
+                "Failed": {
+                    "type": "object",
+                    "properties": {
+                        "code": {"type": "integer"},
+                        "error": {"enum": FAILURES},
+                        "detail": {
+                            "type": "string",
+                            "description": "validation reason or order ID",
+                        },
+                    },
+                    "required": ["code", "error", "detail"],
+                    "additionalProperties": false,
+                },
+                "OrderProcessingResult": {
+                    "type": "array",
+                    "items": {
+                        "oneOf": [
+                            tagged("Ok", reference("Success")),
+                            tagged("Err", reference("Failed")),
+                        ],
+                    },
+                },
+            },
+        },
+    })
+}
+
+/* Helpers */
+
+fn fill() -> Vec<(&'static str, Value)> {
+    vec![
+        ("order_id", uuid()),
+        ("trade_id", json!({"type": "string"})),
+        ("counterparty", uuid()),
+        ("side", side()),
+        ("aggressor", side()),
+        ("role", json!({"enum": ["maker", "taker"]})),
+        ("order_type", order_type()),
+        ("price", number()),
+        ("qty", number()),
+        ("ts", timestamp()),
+        ("liquidation", boolean()),
+        ("fee", nullable(number())),
+        ("fee_asset", nullable(asset())),
+    ]
+}
+
+/// Object with every field required and no others
+fn object(fields: Vec<(&'static str, Value)>) -> Value {
+    let required: Vec<&str> = fields.iter().map(|(name, _)| *name).collect();
+    let properties: Map<String, Value> = fields
+        .into_iter()
+        .map(|(name, schema)| (name.to_string(), schema))
+        .collect();
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+        "additionalProperties": false,
+    })
+}
+
+/// Externally tagged enum variant, serde's default
+fn tagged(name: &str, schema: Value) -> Value {
+    let mut properties = Map::new();
+    properties.insert(name.to_string(), schema);
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": [name],
+        "additionalProperties": false,
+    })
+}
+
+fn reference(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{}", name) })
+}
+
+fn nullable(schema: Value) -> Value {
+    json!({"oneOf": [schema, {"type": "null"}]})
+}
+
+fn uuid() -> Value {
+    json!({"type": "string", "format": "uuid"})
+}
+
+fn asset() -> Value {
+    json!({"type": "string"})
+}
+
+fn number() -> Value {
+    json!({"type": "number"})
+}
+
+fn boolean() -> Value {
+    json!({"type": "boolean"})
+}
+
+fn side() -> Value {
+    json!({"enum": ["bid", "ask"]})
+}
+
+fn order_type() -> Value {
+    json!({"enum": ["market", "limit"]})
+}
+
+fn timestamp() -> Value {
+    reference("Timestamp")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::exchange::test::{demo_config, request};
+    use crate::exchange::Exchange;
+
+    /// Event object conforms to its schema: known variant, every required
+    /// field present and no others
+    fn check(schema: &Value, event: &Value) {
+        let schemas = &schema["components"]["schemas"];
+        let (tag, body) = event.as_object().unwrap().iter().next().unwrap();
+        let object = match tag.as_str() {
+            "Ok" => {
+                let (name, body) = body.as_object().unwrap().iter().next().unwrap();
+                let variant = schemas["Success"]["oneOf"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .find_map(|variant| variant["properties"].get(name))
+                    .unwrap_or_else(|| panic!("{} missing from the schema", name));
+                return check_object(variant, body);
+            }
+            "Err" => &schemas["Failed"],
+            other => panic!("unexpected tag {}", other),
+        };
+        check_object(object, body);
+    }
+
+    fn check_object(schema: &Value, body: &Value) {
+        let body = body.as_object().unwrap();
+        for field in schema["required"].as_array().unwrap() {
+            assert!(
+                body.contains_key(field.as_str().unwrap()),
+                "{} missing",
+                field
+            );
+        }
+        for field in body.keys() {
+            assert!(
+                schema["properties"].get(field).is_some(),
+                "{} not in the schema",
+                field
+            );
+        }
+    }
+
+    #[test]
+    fn schema_matches_responses() {
+        let mut exchange = Exchange::new(&demo_config()).unwrap();
+        let (response, _) = exchange.handle(&request("GET", "/schema", ""));
+        assert_eq!(response.status, 200);
+        let schema: Value = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(schema, openapi());
+
+        let mut events = vec![];
+        for body in [
+            r#"{"account": 1, "market": "BTC-USD", "side": "ask", "price": "100", "qty": "1"}"#,
+            r#"{"account": 2, "market": "BTC-USD", "side": "bid", "qty": "2"}"#,
+            r#"{"account": 2, "market": "BTC-USD", "side": "bid", "price": "-1", "qty": "1"}"#,
+        ] {
+            let (response, _) = exchange.handle(&request("POST", "/orders", body));
+            let results: Vec<Value> = serde_json::from_str(&response.body).unwrap();
+            events.extend(results);
+        }
+        // two accepted, both fills, the unfilled remainder and a failure
+        assert_eq!(events.len(), 6);
+        for event in &events {
+            check(&schema, event);
+        }
+    }
+}
@@ -0,0 +1,621 @@
+use std::io::{self, BufReader};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::config::{LatencyConfig, ServerConfig};
+use crate::exchange::Exchange;
+use crate::http::{self, Request, Response};
+use crate::websocket;
+
+/// Feed subscribers too slow to take a message within it are dropped
+const FEED_WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Deepest top-of-book view a subscriber may ask for
+pub const MAX_LADDER_DEPTH: usize = 50;
+
+enum Event {
+    Request(Request, Sender<Response>),
+    Subscribe(usize, TcpStream, FeedView),
+    /// text frame of a subscriber, answered on its own socket
+    Command(usize, String),
+}
+
+/// Request sent over the feed socket, its reply echoes the `id`
+#[derive(Deserialize)]
+struct Command {
+    #[serde(default)]
+    id: Value,
+    method: String,
+    /// path and query, as in a request line
+    path: String,
+    #[serde(default)]
+    body: Option<Value>,
+}
+
+impl Command {
+    fn parse(text: &str) -> Result<(Value, Request), Response> {
+        let command: Command =
+            serde_json::from_str(text).map_err(|err| Response::error(400, &err.to_string()))?;
+        let (path, query) = http::split_target(&command.path);
+        let body = command
+            .body
+            .map_or_else(Vec::new, |body| body.to_string().into_bytes());
+        let request = Request {
+            method: command.method.to_ascii_uppercase(),
+            path,
+            query,
+            headers: vec![],
+            body,
+        };
+        Ok((command.id, request))
+    }
+}
+
+/// What a subscriber gets, picked by the query of its handshake
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum FeedView {
+    /// every depth change and trade, `GET /`
+    Full,
+    /// row diffs of the best levels, `GET /?ladder=10`
+    Ladder(usize),
+}
+
+impl FeedView {
+    fn of(handshake: &Request) -> Self {
+        match handshake
+            .query_param("ladder")
+            .and_then(|depth| depth.parse::<usize>().ok())
+        {
+            Some(depth) => FeedView::Ladder(depth.clamp(1, MAX_LADDER_DEPTH)),
+            None => FeedView::Full,
+        }
+    }
+}
+
+enum Subscriber {
+    Direct(TcpStream),
+    /// written by a thread of its own holding each message until it is due
+    Delayed(Sender<(Instant, String)>),
+}
+
+impl Subscriber {
+    fn join(stream: TcpStream, latency: Option<LatencyConfig>) -> Self {
+        if latency.is_none_or(|latency| latency.feed_ms == 0 && latency.jitter_ms == 0) {
+            return Subscriber::Direct(stream);
+        }
+
+        let (messages, outbox) = mpsc::channel::<(Instant, String)>();
+        thread::spawn(move || {
+            let mut stream = stream;
+            // one message after the other, so jitter never reorders them
+            for (due, message) in outbox {
+                thread::sleep(due.saturating_duration_since(Instant::now()));
+                if websocket::write_text(&mut stream, &message).is_err() {
+                    return;
+                }
+            }
+        });
+        Subscriber::Delayed(messages)
+    }
+
+    /// false once the subscriber is gone
+    fn send(&mut self, message: &str, latency: Option<LatencyConfig>) -> bool {
+        match self {
+            Subscriber::Direct(stream) => websocket::write_text(stream, message).is_ok(),
+            Subscriber::Delayed(messages) => {
+                let delay = latency.map_or(Duration::ZERO, |latency| latency.feed_delay());
+                messages
+                    .send((Instant::now() + delay, message.to_string()))
+                    .is_ok()
+            }
+        }
+    }
+}
+
+/// Listeners of the exchange, bound but not serving yet
+pub struct Server {
+    rest: TcpListener,
+    feed: TcpListener,
+    latency: Option<LatencyConfig>,
+}
+
+impl Server {
+    pub fn bind(config: &ServerConfig) -> io::Result<Self> {
+        Ok(Server {
+            rest: TcpListener::bind(&config.rest)?,
+            feed: TcpListener::bind(&config.feed)?,
+            latency: config.latency,
+        })
+    }
+
+    pub fn rest_addr(&self) -> io::Result<SocketAddr> {
+        self.rest.local_addr()
+    }
+
+    pub fn feed_addr(&self) -> io::Result<SocketAddr> {
+        self.feed.local_addr()
+    }
+
+    /// Serve the exchange until the listeners fail.
+    ///
+    /// Connections are handled on threads of their own, the exchange stays on
+    /// the calling thread and takes requests one at a time, so every request
+    /// sees the books as left by the previous one. Feed subscribers get a
+    /// snapshot of every market, then the messages of every request.
+    /// Subscribers connecting with `?ladder=N` get the best N levels of every
+    /// market instead, as row diffs sent only when those levels change.
+    ///
+    /// Subscribers may also send requests as text frames, e.g.
+    /// `{"id": 1, "method": "POST", "path": "/orders", "body": {..}}`, routed
+    /// like the REST ones. The reply, `{"reply": 1, "status": 200, "body": ..}`,
+    /// comes ahead of the feed messages of the request.
+    ///
+    /// Configured latency holds back responses and feed messages without
+    /// slowing down the exchange itself.
+    pub fn run(self, mut exchange: Exchange) -> io::Result<()> {
+        let (events, inbox) = mpsc::channel();
+        let latency = self.latency;
+
+        let rest = self.rest;
+        let rest_events = events.clone();
+        thread::spawn(move || {
+            for stream in rest.incoming().flatten() {
+                let events = rest_events.clone();
+                thread::spawn(move || serve_rest(stream, events, latency));
+            }
+        });
+
+        let feed = self.feed;
+        thread::spawn(move || {
+            for (id, stream) in feed.incoming().flatten().enumerate() {
+                let events = events.clone();
+                thread::spawn(move || serve_feed(id, stream, events));
+            }
+        });
+
+        dispatch(&mut exchange, inbox, latency);
+        Err(io::Error::other("listeners stopped"))
+    }
+}
+
+/* Helpers */
+
+fn dispatch(exchange: &mut Exchange, inbox: Receiver<Event>, latency: Option<LatencyConfig>) {
+    let mut subscribers: Vec<(usize, FeedView, Subscriber)> = vec![];
+    for event in inbox {
+        match event {
+            Event::Request(request, reply) => {
+                let (response, feed) = exchange.handle(&request);
+                let _ = reply.send(response);
+                broadcast(exchange, feed, &mut subscribers, latency);
+            }
+            Event::Command(id, text) => {
+                let (tag, response, feed) = match Command::parse(&text) {
+                    Ok((tag, request)) => {
+                        let (response, feed) = exchange.handle(&request);
+                        (tag, response, feed)
+                    }
+                    Err(response) => (Value::Null, response, vec![]),
+                };
+                let body =
+                    serde_json::from_str(&response.body).unwrap_or(Value::String(response.body));
+                let reply = json!({ "reply": tag, "status": response.status, "body": body });
+                let reply = reply.to_string();
+                subscribers.retain_mut(|(subscriber_id, _, subscriber)| {
+                    *subscriber_id != id || subscriber.send(&reply, latency)
+                });
+                broadcast(exchange, feed, &mut subscribers, latency);
+            }
+            Event::Subscribe(id, stream, view) => {
+                if stream.set_write_timeout(Some(FEED_WRITE_TIMEOUT)).is_err() {
+                    continue;
+                }
+                let mut subscriber = Subscriber::join(stream, latency);
+                let snapshots = match view {
+                    FeedView::Full => exchange.snapshots(),
+                    FeedView::Ladder(depth) => exchange.ladder_snapshots(depth),
+                };
+                let joined = snapshots
+                    .iter()
+                    .all(|snapshot| subscriber.send(snapshot, latency));
+                if joined {
+                    subscribers.push((id, view, subscriber));
+                }
+            }
+        }
+    }
+}
+
+/// Send the feed messages of a request, and the ladder diffs it caused
+fn broadcast(
+    exchange: &mut Exchange,
+    feed: Vec<String>,
+    subscribers: &mut Vec<(usize, FeedView, Subscriber)>,
+    latency: Option<LatencyConfig>,
+) {
+    let mut depths: Vec<usize> = subscribers
+        .iter()
+        .filter_map(|(_, view, _)| match view {
+            FeedView::Ladder(depth) => Some(*depth),
+            FeedView::Full => None,
+        })
+        .collect();
+    depths.sort_unstable();
+    depths.dedup();
+    let mut messages: Vec<(FeedView, String)> = feed
+        .into_iter()
+        .map(|message| (FeedView::Full, message))
+        .collect();
+    for depth in depths {
+        messages.extend(
+            exchange
+                .ladder_updates(depth)
+                .into_iter()
+                .map(|message| (FeedView::Ladder(depth), message)),
+        );
+    }
+
+    for (view, message) in messages {
+        subscribers.retain_mut(|(_, subscribed, subscriber)| {
+            *subscribed != view || subscriber.send(&message, latency)
+        });
+    }
+}
+
+/// Subscribe the connection, then forward its text frames until it closes
+fn serve_feed(id: usize, mut stream: TcpStream, events: Sender<Event>) {
+    let handshake = match websocket::accept(&mut stream) {
+        Ok(handshake) => handshake,
+        Err(_) => return,
+    };
+    // the dispatch thread writes, this one only reads
+    let mut reader = match stream.try_clone() {
+        Ok(reader) => BufReader::new(reader),
+        Err(_) => return,
+    };
+    let view = FeedView::of(&handshake);
+    if events.send(Event::Subscribe(id, stream, view)).is_err() {
+        return;
+    }
+
+    while let Ok(frame) = websocket::read_frame(&mut reader) {
+        match frame.opcode {
+            websocket::OP_TEXT => {
+                let text = String::from_utf8_lossy(&frame.payload).into_owned();
+                if events.send(Event::Command(id, text)).is_err() {
+                    break;
+                }
+            }
+            websocket::OP_CLOSE => break,
+            _ => {}
+        }
+    }
+    // fails the next write, which drops the subscriber
+    let _ = reader.get_ref().shutdown(Shutdown::Both);
+}
+
+fn serve_rest(mut stream: TcpStream, events: Sender<Event>, latency: Option<LatencyConfig>) {
+    let request = match http::read_request(&mut BufReader::new(&mut stream)) {
+        Ok(Some(request)) => request,
+        Ok(None) => return,
+        Err(err) => {
+            let _ = http::write_response(&mut stream, &Response::error(400, &err.to_string()));
+            return;
+        }
+    };
+
+    let (reply, response) = mpsc::channel();
+    let response = match events.send(Event::Request(request, reply)) {
+        Ok(()) => response.recv().ok(),
+        Err(_) => None,
+    };
+    let response = response.unwrap_or_else(|| Response::error(503, "exchange stopped"));
+    if let Some(latency) = latency {
+        thread::sleep(latency.response_delay());
+    }
+    let _ = http::write_response(&mut stream, &response);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::client::{FeedClient, RestClient};
+    use crate::config::ExchangeConfig;
+    use crate::exchange::{parse_market, NewOrder};
+    use bigdecimal::BigDecimal;
+    use paper_core::guid::domain::OrderSide;
+    use paper_core::guid::orderbook::Success;
+    use paper_serde::capture::CaptureRecord;
+    use serde_json::Value;
+    use std::io::{BufRead, Read, Write};
+    use std::str::FromStr;
+
+    fn post(addr: SocketAddr, body: &str) -> (u16, String) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "POST /orders HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let status = response[9..12].parse().unwrap();
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        (status, body.to_string())
+    }
+
+    fn subscribe(addr: SocketAddr) -> BufReader<TcpStream> {
+        subscribe_to(addr, "/")
+    }
+
+    fn subscribe_to(addr: SocketAddr, target: &str) -> BufReader<TcpStream> {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "GET {} HTTP/1.1\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n",
+            target
+        )
+        .unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert!(line.starts_with("HTTP/1.1 101"));
+        while line != "\r\n" {
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+        }
+        reader
+    }
+
+    fn next_message(feed: &mut BufReader<TcpStream>) -> Value {
+        let frame = websocket::read_frame(feed).unwrap();
+        serde_json::from_slice(&frame.payload).unwrap()
+    }
+
+    #[tokio::test]
+    async fn end_to_end() {
+        let config = ExchangeConfig::from_toml(
+            r#"
+            [server]
+            rest = "127.0.0.1:0"
+            feed = "127.0.0.1:0"
+
+            [[markets]]
+            base = "BTC"
+            quote = "USD"
+            "#,
+        )
+        .unwrap();
+        let server = Server::bind(&config.server).unwrap();
+        let (rest, feed) = (server.rest_addr().unwrap(), server.feed_addr().unwrap());
+        thread::spawn(move || {
+            let exchange = Exchange::new(&config).unwrap();
+            server.run(exchange)
+        });
+
+        let btc = parse_market("BTC-USD").unwrap();
+        let mut feed = FeedClient::subscribe(feed).await.unwrap();
+        assert_eq!(feed.next_update().await.unwrap(), btc);
+        assert_eq!(feed.book(btc).unwrap().seq(), 0);
+
+        let client = RestClient::new(rest);
+        let order = |account, side, price: &str| NewOrder {
+            account,
+            market: String::from("BTC-USD"),
+            side,
+            price: Some(BigDecimal::from_str(price).unwrap()),
+            qty: BigDecimal::from(1),
+            protection: None,
+        };
+        client
+            .submit(&order(1, OrderSide::Ask, "100"))
+            .await
+            .unwrap();
+        let results = client
+            .submit(&order(2, OrderSide::Bid, "100"))
+            .await
+            .unwrap();
+        assert!(results
+            .iter()
+            .any(|result| matches!(result, Ok(Success::Filled { .. }))));
+
+        feed.next_update().await.unwrap();
+        let book = feed.book(btc).unwrap();
+        assert_eq!(
+            book.best(OrderSide::Ask)
+                .map(|(price, _)| price.to_string()),
+            Some(String::from("100"))
+        );
+        feed.next_update().await.unwrap();
+        let book = feed.book(btc).unwrap();
+        assert_eq!(book.seq(), 2);
+        assert!(book.best(OrderSide::Ask).is_none());
+        match book.last_trade() {
+            Some(CaptureRecord::Trade { aggressor, .. }) => assert_eq!(*aggressor, OrderSide::Bid),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let (status, _) = post(rest, "not json");
+        assert_eq!(status, 400);
+    }
+
+    #[test]
+    fn ladder_subscriber() {
+        let config = ExchangeConfig::from_toml(
+            r#"
+            [server]
+            rest = "127.0.0.1:0"
+            feed = "127.0.0.1:0"
+
+            [[markets]]
+            base = "BTC"
+            quote = "USD"
+            "#,
+        )
+        .unwrap();
+        let server = Server::bind(&config.server).unwrap();
+        let (rest, feed) = (server.rest_addr().unwrap(), server.feed_addr().unwrap());
+        thread::spawn(move || {
+            let exchange = Exchange::new(&config).unwrap();
+            server.run(exchange)
+        });
+
+        let mut ladder = subscribe_to(feed, "/?ladder=1");
+        let mut full = subscribe(feed);
+        let snapshot = next_message(&mut ladder);
+        assert_eq!(snapshot["market"], "BTC-USD");
+        assert_eq!(snapshot["snapshot"], true);
+        assert_eq!(snapshot["rows"], Value::Array(vec![]));
+        next_message(&mut full);
+
+        for price in ["100", "101", "99"] {
+            let (status, _) = post(
+                rest,
+                &format!(
+                    r#"{{"account": 1, "market": "BTC-USD", "side": "ask", "price": "{}", "qty": "1"}}"#,
+                    price
+                ),
+            );
+            assert_eq!(status, 200);
+        }
+
+        let first = next_message(&mut ladder);
+        assert_eq!(first["seq"], 1);
+        assert_eq!(first["rows"][0]["op"], "add");
+        assert_eq!(first["rows"][0]["price"], "100");
+        // 101 stayed below the view, the next message is about 99
+        let second = next_message(&mut ladder);
+        assert_eq!(second["seq"], 2);
+        let ops: Vec<&Value> = second["rows"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|row| &row["op"])
+            .collect();
+        assert_eq!(ops, vec!["remove", "add"]);
+        assert_eq!(second["rows"][1]["price"], "99");
+        assert_eq!(second["rows"][1]["rank"], 0);
+
+        // the full feed still gets every request
+        let seqs: Vec<Value> = (0..3)
+            .map(|_| next_message(&mut full)["seq"].clone())
+            .collect();
+        assert_eq!(seqs, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn order_entry_over_feed() {
+        let config = ExchangeConfig::from_toml(
+            r#"
+            [server]
+            rest = "127.0.0.1:0"
+            feed = "127.0.0.1:0"
+
+            [[markets]]
+            base = "BTC"
+            quote = "USD"
+            "#,
+        )
+        .unwrap();
+        let server = Server::bind(&config.server).unwrap();
+        let feed = server.feed_addr().unwrap();
+        thread::spawn(move || {
+            let exchange = Exchange::new(&config).unwrap();
+            server.run(exchange)
+        });
+
+        let mut trader = subscribe(feed);
+        let mut watcher = subscribe(feed);
+        next_message(&mut trader);
+        next_message(&mut watcher);
+
+        let mut orders = trader.get_ref().try_clone().unwrap();
+        let mut send = |command: &str| websocket::write_text(&mut orders, command).unwrap();
+        send(
+            r#"{"id": 1, "method": "POST", "path": "/orders", "body": {"account": 1, "market": "BTC-USD", "side": "ask", "price": "100", "qty": "1"}}"#,
+        );
+        let reply = next_message(&mut trader);
+        assert_eq!(reply["reply"], 1);
+        assert_eq!(reply["status"], 200);
+        let order_id = reply["body"][0]["Ok"]["Accepted"]["order_id"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        // the reply comes ahead of the feed message of the order
+        assert_eq!(next_message(&mut trader)["seq"], 1);
+        assert_eq!(next_message(&mut watcher)["seq"], 1);
+
+        send(&format!(
+            r#"{{"id": "two", "method": "delete", "path": "/orders/{}?market=BTC-USD&side=ask"}}"#,
+            order_id
+        ));
+        let reply = next_message(&mut trader);
+        assert_eq!(reply["reply"], "two");
+        assert_eq!(reply["status"], 200);
+        assert_eq!(next_message(&mut trader)["seq"], 2);
+        assert_eq!(next_message(&mut watcher)["seq"], 2);
+
+        send("not json");
+        let reply = next_message(&mut trader);
+        assert_eq!(reply["reply"], Value::Null);
+        assert_eq!(reply["status"], 400);
+        send(r#"{"id": 3, "method": "GET", "path": "/nowhere"}"#);
+        assert_eq!(next_message(&mut trader)["status"], 404);
+    }
+
+    #[test]
+    fn injected_latency() {
+        let config = ExchangeConfig::from_toml(
+            r#"
+            [server]
+            rest = "127.0.0.1:0"
+            feed = "127.0.0.1:0"
+
+            [server.latency]
+            response_ms = 100
+            feed_ms = 20
+            jitter_ms = 30
+
+            [[markets]]
+            base = "BTC"
+            quote = "USD"
+            "#,
+        )
+        .unwrap();
+        let server = Server::bind(&config.server).unwrap();
+        let (rest, feed) = (server.rest_addr().unwrap(), server.feed_addr().unwrap());
+        thread::spawn(move || {
+            let exchange = Exchange::new(&config).unwrap();
+            server.run(exchange)
+        });
+
+        let mut feed = subscribe(feed);
+        assert_eq!(next_message(&mut feed)["snapshot"], true);
+
+        let sent = Instant::now();
+        for price in ["100", "101", "102"] {
+            let (status, _) = post(
+                rest,
+                &format!(
+                    r#"{{"account": 1, "market": "BTC-USD", "side": "ask", "price": "{}", "qty": "1"}}"#,
+                    price
+                ),
+            );
+            assert_eq!(status, 200);
+        }
+        assert!(sent.elapsed() >= Duration::from_millis(300));
+
+        // jitter delays messages but never reorders them
+        let seqs: Vec<Value> = (0..3)
+            .map(|_| next_message(&mut feed)["seq"].clone())
+            .collect();
+        assert_eq!(seqs, vec![1, 2, 3]);
+    }
+}
@@ -0,0 +1,232 @@
+//! Server side of RFC 6455 websockets, enough to push text messages.
+
+use std::io::{self, BufReader, Read, Write};
+
+use crate::http;
+
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+pub const OP_TEXT: u8 = 0x1;
+pub const OP_CLOSE: u8 = 0x8;
+pub const OP_PING: u8 = 0x9;
+pub const OP_PONG: u8 = 0xA;
+
+/// Largest payload read, bigger frames are refused
+pub const MAX_PAYLOAD: u64 = 16 * 1024 * 1024;
+
+/// Single unfragmented frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub opcode: u8,
+    /// unmasked
+    pub payload: Vec<u8>,
+}
+
+/// Answer the opening handshake of a client, the stream then carries frames.
+///
+/// Returns the handshake request, its query may pick what to subscribe to.
+pub fn accept<S: Read + Write>(stream: &mut S) -> io::Result<http::Request> {
+    let request = http::read_request(&mut BufReader::new(&mut *stream))?
+        .ok_or_else(|| invalid("no handshake"))?;
+    let upgrade = request
+        .header("Upgrade")
+        .is_some_and(|upgrade| upgrade.eq_ignore_ascii_case("websocket"));
+    let key = match request.header("Sec-WebSocket-Key") {
+        Some(key) if upgrade && request.method == "GET" => key,
+        _ => {
+            http::write_response(stream, &http::Response::error(400, "websocket expected"))?;
+            return Err(invalid("not a websocket handshake"));
+        }
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        accept_key(key)
+    )?;
+    stream.flush()?;
+    Ok(request)
+}
+
+/// `Sec-WebSocket-Accept` answering the client key
+pub fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{}{}", key.trim(), ACCEPT_GUID).as_bytes()))
+}
+
+/// Send text as a single unmasked frame, as servers do
+pub fn write_text<W: Write>(writer: &mut W, text: &str) -> io::Result<()> {
+    write_frame(writer, OP_TEXT, text.as_bytes())
+}
+
+pub fn write_frame<W: Write>(writer: &mut W, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut header = vec![0x80 | opcode];
+    match payload.len() {
+        len if len < 126 => header.push(len as u8),
+        len if len <= u16::MAX as usize => {
+            header.push(126);
+            header.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            header.push(127);
+            header.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    writer.write_all(&header)?;
+    writer.write_all(payload)?;
+    writer.flush()
+}
+
+/// Read the next frame, unmasking client payloads
+pub fn read_frame<R: Read>(reader: &mut R) -> io::Result<Frame> {
+    let mut head = [0; 2];
+    reader.read_exact(&mut head)?;
+    let opcode = head[0] & 0x0F;
+    let masked = head[1] & 0x80 != 0;
+
+    let len = match head[1] & 0x7F {
+        126 => {
+            let mut len = [0; 2];
+            reader.read_exact(&mut len)?;
+            u16::from_be_bytes(len) as u64
+        }
+        127 => {
+            let mut len = [0; 8];
+            reader.read_exact(&mut len)?;
+            u64::from_be_bytes(len)
+        }
+        len => len as u64,
+    };
+    if len > MAX_PAYLOAD {
+        return Err(invalid("frame too large"));
+    }
+
+    let mut mask = [0; 4];
+    if masked {
+        reader.read_exact(&mut mask)?;
+    }
+    let mut payload = vec![0; len as usize];
+    reader.read_exact(&mut payload)?;
+    if masked {
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+    }
+
+    Ok(Frame { opcode, payload })
+}
+
+/* Helpers */
+
+fn invalid(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (state, value) in h.iter_mut().zip([a, b, c, d, e]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+
+    let mut digest = [0; 20];
+    for (chunk, word) in digest.chunks_mut(4).zip(h) {
+        chunk.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let group = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(group >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn handshake_key() {
+        // example of RFC 6455
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+        assert_eq!(base64(b"ab"), "YWI=");
+        assert_eq!(base64(b"a"), "YQ==");
+    }
+
+    #[test]
+    fn frames() {
+        let mut written = vec![];
+        write_text(&mut written, "hi").unwrap();
+        assert_eq!(written, [0x81, 2, b'h', b'i']);
+        let long = "x".repeat(300);
+        let mut written = vec![];
+        write_text(&mut written, &long).unwrap();
+        assert_eq!(&written[..4], &[0x81, 126, 1, 44]);
+        let frame = read_frame(&mut Cursor::new(written)).unwrap();
+        assert_eq!(frame.payload, long.as_bytes());
+
+        // masked "Hello" of RFC 6455
+        let masked = [
+            0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58,
+        ];
+        let frame = read_frame(&mut Cursor::new(masked)).unwrap();
+        assert_eq!(frame.opcode, OP_TEXT);
+        assert_eq!(frame.payload, b"Hello");
+    }
+}
@@ -0,0 +1,58 @@
+use bigdecimal::BigDecimal;
+use criterion::{criterion_group, criterion_main, Criterion};
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+
+use paper_core::guid::domain::OrderSide;
+use paper_core::guid::orderbook::Orderbook;
+use paper_core::guid::orders;
+use paper_serde::snapshot::{open_mapped, write_snapshot, LazyBook};
+
+const LEVELS: u64 = 2_000;
+const ORDERS_PER_LEVEL: u64 = 50;
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Serialize, Deserialize)]
+enum Asset {
+    Usd,
+    Btc,
+}
+
+fn large_book() -> Orderbook<Asset> {
+    let mut book = Orderbook::new(Asset::Btc, Asset::Usd);
+    for level in 0..LEVELS {
+        for _ in 0..ORDERS_PER_LEVEL {
+            book.process_order(orders::new_limit_order_request(
+                Asset::Btc,
+                Asset::Usd,
+                OrderSide::Ask,
+                BigDecimal::from(10_000 + level),
+                BigDecimal::from(1),
+                SystemTime::now(),
+            ));
+        }
+    }
+    book
+}
+
+fn cold_start(c: &mut Criterion) {
+    let file = tempfile::NamedTempFile::new().unwrap();
+    write_snapshot(&large_book(), file.as_file()).unwrap();
+
+    let mut group = c.benchmark_group("cold_start");
+    group.sample_size(10);
+    group.bench_function("eager", |b| {
+        b.iter(|| {
+            open_mapped::<Asset, _>(file.path())
+                .unwrap()
+                .load()
+                .unwrap()
+        })
+    });
+    group.bench_function("lazy_top_10_levels", |b| {
+        b.iter(|| LazyBook::new(open_mapped::<Asset, _>(file.path()).unwrap(), 10).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, cold_start);
+criterion_main!(benches);
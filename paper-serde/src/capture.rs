@@ -0,0 +1,510 @@
+use bigdecimal::{BigDecimal, Zero};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fmt::Debug;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::time::SystemTime;
+use uuid::Uuid;
+
+use paper_core::guid::domain::{Order, OrderSide};
+use paper_core::guid::order_queues::OrderQueue;
+use paper_core::guid::orderbook::{OrderProcessingResult, Orderbook, Success};
+use paper_core::guid::trade_ids::TradeId;
+
+const COMPRESSION_LEVEL: i32 = 3;
+
+/// Market data captured from a book
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum CaptureRecord {
+    /// new total quantity of the price level, zero when the level is gone
+    Depth {
+        side: OrderSide,
+        price: BigDecimal,
+        qty: BigDecimal,
+        ts: SystemTime,
+    },
+    Trade {
+        trade_id: TradeId,
+        /// side that took liquidity, buyer initiated for bids
+        aggressor: OrderSide,
+        price: BigDecimal,
+        qty: BigDecimal,
+        ts: SystemTime,
+    },
+}
+
+impl CaptureRecord {
+    pub fn ts(&self) -> SystemTime {
+        match self {
+            CaptureRecord::Depth { ts, .. } | CaptureRecord::Trade { ts, .. } => *ts,
+        }
+    }
+}
+
+/// Turns processing results into trades and depth deltas.
+///
+/// Only the levels of orders the results name are looked at, so a request
+/// costs the same however deep the book. The recorder has to see every
+/// request from an empty book on.
+#[derive(Debug, Default)]
+pub struct DepthRecorder {
+    bids: BTreeMap<BigDecimal, BigDecimal>,
+    asks: BTreeMap<BigDecimal, BigDecimal>,
+    /// side, price and quantity of resting orders as of the last recording
+    resting: HashMap<Uuid, (OrderSide, BigDecimal, BigDecimal)>,
+}
+
+impl DepthRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records describing what the request behind `results` did to the book
+    pub fn record<Asset>(
+        &mut self,
+        book: &Orderbook<Asset>,
+        results: &OrderProcessingResult<Asset>,
+        ts: SystemTime,
+    ) -> Vec<CaptureRecord>
+    where
+        Asset: Debug + Clone + Copy + Eq,
+    {
+        let fills = results.iter().filter_map(|result| match result {
+            Ok(Success::Filled {
+                trade_id,
+                aggressor,
+                price,
+                qty,
+                ..
+            })
+            | Ok(Success::PartiallyFilled {
+                trade_id,
+                aggressor,
+                price,
+                qty,
+                ..
+            }) => Some((trade_id, aggressor, price, qty)),
+            _ => None,
+        });
+        let mut seen: HashSet<TradeId> = HashSet::new();
+        let mut records: Vec<CaptureRecord> = fills
+            .filter(|(trade_id, ..)| seen.insert(**trade_id))
+            .map(|(trade_id, aggressor, price, qty)| CaptureRecord::Trade {
+                trade_id: *trade_id,
+                aggressor: *aggressor,
+                price: price.clone(),
+                qty: qty.clone(),
+                ts,
+            })
+            .collect();
+
+        // quantity of the levels the request touched, as they were before it
+        let mut touched_bids = BTreeMap::new();
+        let mut touched_asks = BTreeMap::new();
+        let mut seen: HashSet<Uuid> = HashSet::new();
+        let order_ids = results
+            .iter()
+            .filter_map(|result| result.as_ref().ok().map(Success::order_id));
+        for order_id in order_ids.filter(|order_id| seen.insert(*order_id)) {
+            let now = book
+                .bid_queue
+                .get(order_id)
+                .or_else(|| book.ask_queue.get(order_id))
+                .map(|order| (order.side, order.price.clone(), order.qty.clone()));
+            let before = self.resting.remove(&order_id);
+            // the order leaves its old level and joins the new one
+            let changes = before
+                .iter()
+                .map(|(side, price, qty)| (side, price, -qty))
+                .chain(
+                    now.iter()
+                        .map(|(side, price, qty)| (side, price, qty.clone())),
+                );
+            for (side, price, delta) in changes {
+                let (levels, touched) = match side {
+                    OrderSide::Bid => (&mut self.bids, &mut touched_bids),
+                    OrderSide::Ask => (&mut self.asks, &mut touched_asks),
+                };
+                adjust_level(levels, touched, price, delta);
+            }
+            if let Some(now) = now {
+                self.resting.insert(order_id, now);
+            }
+        }
+
+        level_records(OrderSide::Bid, &self.bids, touched_bids, ts, &mut records);
+        level_records(OrderSide::Ask, &self.asks, touched_asks, ts, &mut records);
+        records
+    }
+
+    /// Aggregated levels of the side as of the last recording
+    pub fn levels(&self, side: OrderSide) -> &BTreeMap<BigDecimal, BigDecimal> {
+        match side {
+            OrderSide::Bid => &self.bids,
+            OrderSide::Ask => &self.asks,
+        }
+    }
+}
+
+/// Start of a compressed frame within the capture
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    /// time of the first record in the frame
+    pub ts: SystemTime,
+    /// byte offset of the frame
+    pub offset: u64,
+    pub records: u32,
+}
+
+/// Writes records as zstd compressed frames.
+///
+/// Each frame is a little-endian u32 length followed by the compressed JSON
+/// lines of its records. Records have to be appended in time order for the
+/// index to allow seeking.
+pub struct CaptureWriter<W: Write> {
+    inner: W,
+    offset: u64,
+    frame_records: usize,
+    pending: Vec<CaptureRecord>,
+    index: Vec<IndexEntry>,
+}
+
+impl<W: Write> CaptureWriter<W> {
+    /// Create writer closing a frame every `frame_records` records
+    pub fn new(inner: W, frame_records: usize) -> Self {
+        CaptureWriter {
+            inner,
+            offset: 0,
+            frame_records: frame_records.max(1),
+            pending: vec![],
+            index: vec![],
+        }
+    }
+
+    pub fn append(&mut self, record: CaptureRecord) -> io::Result<()> {
+        self.pending.push(record);
+        if self.pending.len() >= self.frame_records {
+            self.flush_frame()?;
+        }
+        Ok(())
+    }
+
+    /// Write out buffered records as a frame, even if not full
+    pub fn flush_frame(&mut self) -> io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+
+        let mut payload = vec![];
+        for record in &self.pending {
+            serde_json::to_writer(&mut payload, record)?;
+            payload.push(b'\n');
+        }
+        let compressed = zstd::encode_all(payload.as_slice(), COMPRESSION_LEVEL)?;
+
+        self.inner
+            .write_all(&(compressed.len() as u32).to_le_bytes())?;
+        self.inner.write_all(&compressed)?;
+        self.index.push(IndexEntry {
+            ts: self.pending[0].ts(),
+            offset: self.offset,
+            records: self.pending.len() as u32,
+        });
+        self.offset += 4 + compressed.len() as u64;
+        self.pending.clear();
+        Ok(())
+    }
+
+    pub fn index(&self) -> &[IndexEntry] {
+        &self.index
+    }
+
+    /// Flush the last frame, returns the underlying writer and the index
+    pub fn finish(mut self) -> io::Result<(W, Vec<IndexEntry>)> {
+        self.flush_frame()?;
+        self.inner.flush()?;
+        Ok((self.inner, self.index))
+    }
+}
+
+/// Reads records back, optionally starting from a point in time
+pub struct CaptureReader<R: Read + Seek> {
+    inner: R,
+    index: Vec<IndexEntry>,
+    buffered: VecDeque<CaptureRecord>,
+    from: Option<SystemTime>,
+}
+
+impl<R: Read + Seek> CaptureReader<R> {
+    pub fn new(inner: R, index: Vec<IndexEntry>) -> Self {
+        CaptureReader {
+            inner,
+            index,
+            buffered: VecDeque::new(),
+            from: None,
+        }
+    }
+
+    /// Continue reading from the first record at or after `ts`
+    pub fn seek(&mut self, ts: SystemTime) -> io::Result<()> {
+        // the last frame starting before ts may still hold records at ts
+        let frame = self.index.partition_point(|entry| entry.ts < ts);
+        let offset = match frame {
+            0 => 0,
+            frame => self.index[frame - 1].offset,
+        };
+        self.inner.seek(SeekFrom::Start(offset))?;
+        self.buffered.clear();
+        self.from = Some(ts);
+        Ok(())
+    }
+
+    /// Read next frame, returns false at the end of the capture
+    fn read_frame(&mut self) -> io::Result<bool> {
+        let mut len = [0u8; 4];
+        match self.inner.read_exact(&mut len) {
+            Ok(()) => (),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(false),
+            Err(err) => return Err(err),
+        }
+        let mut compressed = vec![0u8; u32::from_le_bytes(len) as usize];
+        self.inner.read_exact(&mut compressed)?;
+
+        let payload = zstd::decode_all(compressed.as_slice())?;
+        for line in payload.split(|byte| *byte == b'\n') {
+            if line.is_empty() {
+                continue;
+            }
+            let record: CaptureRecord = serde_json::from_slice(line)?;
+            if self.from.is_some_and(|from| record.ts() < from) {
+                continue;
+            }
+            self.buffered.push_back(record);
+        }
+        Ok(true)
+    }
+}
+
+impl<R: Read + Seek> Iterator for CaptureReader<R> {
+    type Item = io::Result<CaptureRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.buffered.is_empty() {
+            match self.read_frame() {
+                Ok(true) => (),
+                Ok(false) => return None,
+                Err(err) => return Some(Err(err)),
+            }
+        }
+        self.buffered.pop_front().map(Ok)
+    }
+}
+
+/// Store index next to the capture, as JSON
+pub fn write_index<W: Write>(writer: W, index: &[IndexEntry]) -> io::Result<()> {
+    serde_json::to_writer(writer, index)?;
+    Ok(())
+}
+
+pub fn read_index<R: Read>(reader: R) -> io::Result<Vec<IndexEntry>> {
+    Ok(serde_json::from_reader(reader)?)
+}
+
+/* Helpers */
+
+pub(crate) fn aggregate<Asset>(queue: &OrderQueue<Order<Asset>>) -> BTreeMap<BigDecimal, BigDecimal>
+where
+    Asset: Debug + Clone,
+{
+    let mut levels = BTreeMap::new();
+    for order in queue.iter() {
+        *levels
+            .entry(order.price.clone())
+            .or_insert_with(BigDecimal::zero) += &order.qty;
+    }
+    levels
+}
+
+/// Add `delta` to the level, noting its quantity before the first change
+fn adjust_level(
+    levels: &mut BTreeMap<BigDecimal, BigDecimal>,
+    touched: &mut BTreeMap<BigDecimal, BigDecimal>,
+    price: &BigDecimal,
+    delta: BigDecimal,
+) {
+    if !touched.contains_key(price) {
+        let before = levels.get(price).cloned().unwrap_or_else(BigDecimal::zero);
+        touched.insert(price.clone(), before);
+    }
+    let qty = levels.entry(price.clone()).or_insert_with(BigDecimal::zero);
+    *qty += delta;
+    if qty.is_zero() {
+        levels.remove(price);
+    }
+}
+
+/// Depth records of the touched levels that ended up changed, by price
+fn level_records(
+    side: OrderSide,
+    levels: &BTreeMap<BigDecimal, BigDecimal>,
+    touched: BTreeMap<BigDecimal, BigDecimal>,
+    ts: SystemTime,
+    records: &mut Vec<CaptureRecord>,
+) {
+    for (price, before) in touched {
+        let qty = levels.get(&price).cloned().unwrap_or_else(BigDecimal::zero);
+        if qty != before {
+            records.push(CaptureRecord::Depth {
+                side,
+                price,
+                qty,
+                ts,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use paper_core::guid::orders;
+    use std::io::Cursor;
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    #[derive(PartialEq, Eq, Debug, Copy, Clone)]
+    pub enum Asset {
+        USD,
+        BTC,
+    }
+
+    fn bigdec(num: &str) -> BigDecimal {
+        BigDecimal::from_str(num).unwrap()
+    }
+
+    fn limit(side: OrderSide, price: &str, qty: &str) -> orders::OrderRequest<Asset> {
+        orders::new_limit_order_request(
+            Asset::BTC,
+            Asset::USD,
+            side,
+            bigdec(price),
+            bigdec(qty),
+            SystemTime::now(),
+        )
+    }
+
+    #[test]
+    fn depth_deltas_and_trades() {
+        let mut book = Orderbook::new(Asset::BTC, Asset::USD);
+        let mut recorder = DepthRecorder::new();
+        let ts = SystemTime::now();
+
+        let results = book.process_order(limit(OrderSide::Ask, "101", "2"));
+        assert_eq!(
+            recorder.record(&book, &results, ts),
+            vec![CaptureRecord::Depth {
+                side: OrderSide::Ask,
+                price: bigdec("101"),
+                qty: bigdec("2"),
+                ts,
+            }]
+        );
+
+        let results = book.process_order(limit(OrderSide::Bid, "101", "3"));
+        assert_eq!(
+            recorder.record(&book, &results, ts),
+            vec![
+                CaptureRecord::Trade {
+                    trade_id: TradeId(1),
+                    aggressor: OrderSide::Bid,
+                    price: bigdec("101"),
+                    qty: bigdec("2"),
+                    ts,
+                },
+                CaptureRecord::Depth {
+                    side: OrderSide::Bid,
+                    price: bigdec("101"),
+                    qty: bigdec("1"),
+                    ts,
+                },
+                CaptureRecord::Depth {
+                    side: OrderSide::Ask,
+                    price: bigdec("101"),
+                    qty: bigdec("0"),
+                    ts,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn levels_follow_the_book() {
+        let mut book = Orderbook::new(Asset::BTC, Asset::USD);
+        let mut recorder = DepthRecorder::new();
+        let ts = SystemTime::now();
+
+        let asks: Vec<orders::OrderRequest<Asset>> = ["101", "101.0", "102", "103"]
+            .iter()
+            .map(|price| limit(OrderSide::Ask, price, "1"))
+            .collect();
+        let ids: Vec<Uuid> = asks.iter().map(|ask| ask.order_id()).collect();
+        let mut requests = asks;
+        requests.extend(vec![
+            limit(OrderSide::Bid, "100", "2"),
+            orders::amend_order_request(ids[2], OrderSide::Ask, bigdec("103"), bigdec("3"), ts),
+            orders::limit_order_cancel_request(ids[3], OrderSide::Ask),
+            limit(OrderSide::Bid, "101", "1.5"),
+            orders::new_market_order_request(
+                Asset::BTC,
+                Asset::USD,
+                OrderSide::Bid,
+                bigdec("4"),
+                ts,
+            ),
+        ]);
+        for request in requests {
+            let results = book.process_order(request);
+            recorder.record(&book, &results, ts);
+            assert_eq!(recorder.levels(OrderSide::Bid), &aggregate(&book.bid_queue));
+            assert_eq!(recorder.levels(OrderSide::Ask), &aggregate(&book.ask_queue));
+        }
+        assert_eq!(recorder.levels(OrderSide::Bid).len(), 1);
+        assert!(recorder.levels(OrderSide::Ask).is_empty());
+    }
+
+    #[test]
+    fn write_and_seek() {
+        let start = SystemTime::now();
+        let records: Vec<CaptureRecord> = (0..10u64)
+            .map(|i| CaptureRecord::Trade {
+                trade_id: TradeId(i as u128 + 1),
+                aggressor: OrderSide::Ask,
+                price: BigDecimal::from(100 + i),
+                qty: bigdec("1"),
+                ts: start + Duration::from_secs(i),
+            })
+            .collect();
+
+        let mut writer = CaptureWriter::new(Cursor::new(vec![]), 4);
+        for record in records.clone() {
+            writer.append(record).unwrap();
+        }
+        assert_eq!(writer.index().len(), 2);
+        let (capture, index) = writer.finish().unwrap();
+        assert_eq!(index.len(), 3);
+
+        let mut stored_index = vec![];
+        write_index(&mut stored_index, &index).unwrap();
+        let index = read_index(stored_index.as_slice()).unwrap();
+
+        let capture = Cursor::new(capture.into_inner());
+        let mut reader = CaptureReader::new(capture, index);
+        let all: Vec<CaptureRecord> = reader.by_ref().map(Result::unwrap).collect();
+        assert_eq!(all, records);
+
+        reader.seek(start + Duration::from_secs(5)).unwrap();
+        let tail: Vec<CaptureRecord> = reader.map(Result::unwrap).collect();
+        assert_eq!(tail, records[5..].to_vec());
+    }
+}
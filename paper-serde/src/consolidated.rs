@@ -0,0 +1,467 @@
+use bigdecimal::{BigDecimal, Zero};
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::time::{Duration, SystemTime};
+
+use paper_core::guid::domain::OrderSide;
+use paper_core::guid::orderbook::Orderbook;
+
+use crate::capture::aggregate;
+use crate::replica::ReplicaBook;
+
+/// Book whose aggregated depth can be merged into a consolidated view
+pub trait DepthSource {
+    /// Price levels of the side as (price, qty), best first
+    fn levels(&self, side: OrderSide) -> Vec<(BigDecimal, BigDecimal)>;
+
+    /// Whether the levels can be trusted, stale books are left out
+    fn is_live(&self) -> bool {
+        true
+    }
+}
+
+impl<Asset> DepthSource for Orderbook<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    fn levels(&self, side: OrderSide) -> Vec<(BigDecimal, BigDecimal)> {
+        match side {
+            OrderSide::Bid => aggregate(&self.bid_queue).into_iter().rev().collect(),
+            OrderSide::Ask => aggregate(&self.ask_queue).into_iter().collect(),
+        }
+    }
+}
+
+impl DepthSource for ReplicaBook {
+    fn levels(&self, side: OrderSide) -> Vec<(BigDecimal, BigDecimal)> {
+        self.depth(side, usize::MAX)
+    }
+
+    /// a replica waiting for a snapshot may serve a wrong book
+    fn is_live(&self) -> bool {
+        self.is_in_sync()
+    }
+}
+
+/// Price level across venues
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConsolidatedLevel<V> {
+    pub price: BigDecimal,
+    /// total over all venues
+    pub qty: BigDecimal,
+    /// quantity of each venue quoting the price, in venue order
+    pub venues: Vec<(V, BigDecimal)>,
+}
+
+/// Best bid and offer over all venues
+#[derive(Debug, Clone, PartialEq)]
+pub struct Nbbo<V> {
+    pub bid: Option<ConsolidatedLevel<V>>,
+    pub ask: Option<ConsolidatedLevel<V>>,
+}
+
+impl<V> Nbbo<V> {
+    /// Best bid at or above the best ask, a router can trade the venues
+    /// against each other
+    pub fn is_crossed(&self) -> bool {
+        self.condition() != MarketCondition::Normal
+    }
+
+    pub fn condition(&self) -> MarketCondition {
+        match (&self.bid, &self.ask) {
+            (Some(bid), Some(ask)) if bid.price > ask.price => MarketCondition::Crossed,
+            (Some(bid), Some(ask)) if bid.price == ask.price => MarketCondition::Locked,
+            _ => MarketCondition::Normal,
+        }
+    }
+}
+
+/// How the best bid and ask over all venues relate
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum MarketCondition {
+    /// bid below ask, or a side is empty
+    Normal,
+    /// bid equal to ask
+    Locked,
+    /// bid above ask
+    Crossed,
+}
+
+/// Change of the merged view reported by `CrossMonitor`
+#[derive(Debug, Clone, PartialEq)]
+pub enum CrossEvent<V> {
+    /// the view became locked or crossed
+    Started {
+        condition: MarketCondition,
+        bid: ConsolidatedLevel<V>,
+        ask: ConsolidatedLevel<V>,
+        ts: SystemTime,
+    },
+    /// the locked or crossed condition is over
+    Ended {
+        condition: MarketCondition,
+        started: SystemTime,
+        duration: Duration,
+        ts: SystemTime,
+    },
+}
+
+/// Watches a consolidated view for locked and crossed markets.
+///
+/// Call `observe` after updating the venues, every change of condition is
+/// reported once. A locked view turning crossed ends the locked condition
+/// and starts the crossed one. Total time spent in each condition is kept
+/// for market quality statistics.
+#[derive(Debug, Clone)]
+pub struct CrossMonitor {
+    condition: MarketCondition,
+    since: Option<SystemTime>,
+    locked: Duration,
+    crossed: Duration,
+}
+
+impl Default for CrossMonitor {
+    fn default() -> Self {
+        CrossMonitor {
+            condition: MarketCondition::Normal,
+            since: None,
+            locked: Duration::ZERO,
+            crossed: Duration::ZERO,
+        }
+    }
+}
+
+impl CrossMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Events for the view as of `ts`
+    pub fn observe<V>(&mut self, book: &ConsolidatedBook<V>, ts: SystemTime) -> Vec<CrossEvent<V>>
+    where
+        V: Clone + Ord,
+    {
+        let nbbo = book.nbbo();
+        let condition = nbbo.condition();
+        if condition == self.condition {
+            return vec![];
+        }
+
+        let mut events = vec![];
+        if let Some(started) = self.since.take() {
+            let duration = ts.duration_since(started).unwrap_or_default();
+            match self.condition {
+                MarketCondition::Locked => self.locked += duration,
+                MarketCondition::Crossed => self.crossed += duration,
+                MarketCondition::Normal => (),
+            }
+            events.push(CrossEvent::Ended {
+                condition: self.condition,
+                started,
+                duration,
+                ts,
+            });
+        }
+        if let (Some(bid), Some(ask)) = (nbbo.bid, nbbo.ask) {
+            if condition != MarketCondition::Normal {
+                self.since = Some(ts);
+                events.push(CrossEvent::Started {
+                    condition,
+                    bid,
+                    ask,
+                    ts,
+                });
+            }
+        }
+        self.condition = condition;
+        events
+    }
+
+    /// Condition at the last observation and since when, None when normal
+    pub fn condition(&self) -> (MarketCondition, Option<SystemTime>) {
+        (self.condition, self.since)
+    }
+
+    /// Time spent in the condition up to the last change, the current
+    /// condition not included
+    pub fn time_in(&self, condition: MarketCondition) -> Duration {
+        match condition {
+            MarketCondition::Locked => self.locked,
+            MarketCondition::Crossed => self.crossed,
+            MarketCondition::Normal => Duration::ZERO,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct VenueDepth {
+    bids: Vec<(BigDecimal, BigDecimal)>,
+    asks: Vec<(BigDecimal, BigDecimal)>,
+}
+
+/// Depth of one instrument merged from the books of several venues.
+///
+/// Each venue is refreshed on its own with `update`, from a local
+/// `Orderbook` or a `ReplicaBook` following a remote one. Every merged level
+/// keeps the quantity each venue contributes, so a router can split orders
+/// and analytics can attribute the quote. Venues whose book is not live are
+/// dropped until they are updated from a live book again.
+#[derive(Debug, Clone)]
+pub struct ConsolidatedBook<V>
+where
+    V: Clone + Ord,
+{
+    venues: BTreeMap<V, VenueDepth>,
+}
+
+impl<V> Default for ConsolidatedBook<V>
+where
+    V: Clone + Ord,
+{
+    fn default() -> Self {
+        ConsolidatedBook {
+            venues: BTreeMap::new(),
+        }
+    }
+}
+
+impl<V> ConsolidatedBook<V>
+where
+    V: Clone + Ord,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take the current depth of the venue's book
+    pub fn update<B: DepthSource + ?Sized>(&mut self, venue: V, book: &B) {
+        if !book.is_live() {
+            self.venues.remove(&venue);
+            return;
+        }
+        self.venues.insert(
+            venue,
+            VenueDepth {
+                bids: book.levels(OrderSide::Bid),
+                asks: book.levels(OrderSide::Ask),
+            },
+        );
+    }
+
+    /// Stop consolidating the venue, returns false if it was not included
+    pub fn remove(&mut self, venue: &V) -> bool {
+        self.venues.remove(venue).is_some()
+    }
+
+    /// Venues currently included
+    pub fn venues(&self) -> impl Iterator<Item = &V> {
+        self.venues.keys()
+    }
+
+    /// Up to `levels` best merged price levels of the side
+    pub fn depth(&self, side: OrderSide, levels: usize) -> Vec<ConsolidatedLevel<V>> {
+        let mut merged: BTreeMap<&BigDecimal, Vec<(V, BigDecimal)>> = BTreeMap::new();
+        for (venue, depth) in &self.venues {
+            let venue_levels = match side {
+                OrderSide::Bid => &depth.bids,
+                OrderSide::Ask => &depth.asks,
+            };
+            // only the best `levels` of a venue can make it into the result
+            for (price, qty) in venue_levels.iter().take(levels) {
+                merged
+                    .entry(price)
+                    .or_default()
+                    .push((venue.clone(), qty.clone()));
+            }
+        }
+
+        let level = |(price, venues): (&BigDecimal, Vec<(V, BigDecimal)>)| ConsolidatedLevel {
+            price: price.clone(),
+            qty: venues
+                .iter()
+                .fold(BigDecimal::zero(), |total, (_, qty)| total + qty),
+            venues,
+        };
+        match side {
+            OrderSide::Bid => merged.into_iter().rev().take(levels).map(level).collect(),
+            OrderSide::Ask => merged.into_iter().take(levels).map(level).collect(),
+        }
+    }
+
+    pub fn nbbo(&self) -> Nbbo<V> {
+        Nbbo {
+            bid: self.depth(OrderSide::Bid, 1).pop(),
+            ask: self.depth(OrderSide::Ask, 1).pop(),
+        }
+    }
+
+    /// Quantity available up to the limit price on the side, per venue
+    pub fn available(&self, side: OrderSide, limit: &BigDecimal) -> Vec<(V, BigDecimal)> {
+        self.venues
+            .iter()
+            .map(|(venue, depth)| {
+                let qty = match side {
+                    OrderSide::Bid => depth
+                        .bids
+                        .iter()
+                        .take_while(|(price, _)| price >= limit)
+                        .fold(BigDecimal::zero(), |total, (_, qty)| total + qty),
+                    OrderSide::Ask => depth
+                        .asks
+                        .iter()
+                        .take_while(|(price, _)| price <= limit)
+                        .fold(BigDecimal::zero(), |total, (_, qty)| total + qty),
+                };
+                (venue.clone(), qty)
+            })
+            .filter(|(_, qty)| !qty.is_zero())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::replica::FeedPublisher;
+    use paper_core::guid::orders;
+    use std::str::FromStr;
+    use std::time::SystemTime;
+
+    #[derive(PartialEq, Eq, Debug, Copy, Clone)]
+    pub enum Asset {
+        USD,
+        BTC,
+    }
+
+    fn bigdec(num: &str) -> BigDecimal {
+        BigDecimal::from_str(num).unwrap()
+    }
+
+    fn limit(side: OrderSide, price: &str, qty: &str) -> orders::OrderRequest<Asset> {
+        orders::new_limit_order_request(
+            Asset::BTC,
+            Asset::USD,
+            side,
+            bigdec(price),
+            bigdec(qty),
+            SystemTime::now(),
+        )
+    }
+
+    #[test]
+    fn merged_depth_with_attribution() {
+        let mut local = Orderbook::new(Asset::BTC, Asset::USD);
+        for order in [
+            limit(OrderSide::Bid, "99", "1"),
+            limit(OrderSide::Bid, "98", "2"),
+            limit(OrderSide::Ask, "101", "1"),
+        ] {
+            local.process_order(order);
+        }
+
+        // remote venue known through its feed
+        let mut remote = Orderbook::new(Asset::BTC, Asset::USD);
+        let mut publisher = FeedPublisher::new();
+        let mut replica = ReplicaBook::new();
+        for order in [
+            limit(OrderSide::Bid, "99", "3"),
+            limit(OrderSide::Ask, "100", "2"),
+            limit(OrderSide::Ask, "101", "4"),
+        ] {
+            let results = remote.process_order(order);
+            replica
+                .apply(&publisher.publish(&remote, &results, SystemTime::now()))
+                .unwrap();
+        }
+
+        let mut book = ConsolidatedBook::new();
+        book.update("local", &local);
+        book.update("remote", &replica);
+
+        let nbbo = book.nbbo();
+        assert_eq!(
+            nbbo.bid,
+            Some(ConsolidatedLevel {
+                price: bigdec("99"),
+                qty: bigdec("4"),
+                venues: vec![("local", bigdec("1")), ("remote", bigdec("3"))],
+            })
+        );
+        assert_eq!(nbbo.ask.unwrap().venues, vec![("remote", bigdec("2"))]);
+        assert!(!book.nbbo().is_crossed());
+
+        let asks = book.depth(OrderSide::Ask, 5);
+        assert_eq!(asks.len(), 2);
+        assert_eq!(asks[1].qty, bigdec("5"));
+        assert_eq!(
+            book.available(OrderSide::Ask, &bigdec("101")),
+            vec![("local", bigdec("1")), ("remote", bigdec("6"))]
+        );
+
+        // a replica out of sync no longer counts
+        let stale = ReplicaBook::awaiting_snapshot();
+        book.update("remote", &stale);
+        assert_eq!(book.venues().collect::<Vec<_>>(), vec![&"local"]);
+        assert_eq!(book.nbbo().ask.unwrap().price, bigdec("101"));
+    }
+
+    #[test]
+    fn locked_and_crossed_views() {
+        let start = SystemTime::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let mut north = Orderbook::new(Asset::BTC, Asset::USD);
+        let mut south = Orderbook::new(Asset::BTC, Asset::USD);
+        north.process_order(limit(OrderSide::Bid, "99", "1"));
+        south.process_order(limit(OrderSide::Ask, "101", "1"));
+
+        let mut book = ConsolidatedBook::new();
+        let mut monitor = CrossMonitor::new();
+        book.update("north", &north);
+        book.update("south", &south);
+        assert!(monitor.observe(&book, at(0)).is_empty());
+
+        south.process_order(limit(OrderSide::Ask, "99", "1"));
+        north.process_order(limit(OrderSide::Bid, "99.5", "1"));
+        book.update("south", &south);
+        match monitor.observe(&book, at(10)).as_slice() {
+            [CrossEvent::Started {
+                condition: MarketCondition::Locked,
+                bid,
+                ask,
+                ..
+            }] => {
+                assert_eq!(bid.venues, vec![("north", bigdec("1"))]);
+                assert_eq!(ask.venues, vec![("south", bigdec("1"))]);
+            }
+            _ => panic!("unexpected events"),
+        }
+        assert!(monitor.observe(&book, at(20)).is_empty());
+
+        // the bid moved up on the other venue
+        book.update("north", &north);
+        let events = monitor.observe(&book, at(30));
+        assert_eq!(events.len(), 2);
+        assert!(matches!(
+            events[1],
+            CrossEvent::Started {
+                condition: MarketCondition::Crossed,
+                ..
+            }
+        ));
+        assert_eq!(
+            monitor.time_in(MarketCondition::Locked),
+            Duration::from_millis(20)
+        );
+
+        book.remove(&"south");
+        match monitor.observe(&book, at(45)).as_slice() {
+            [CrossEvent::Ended {
+                condition: MarketCondition::Crossed,
+                duration,
+                ..
+            }] => assert_eq!(*duration, Duration::from_millis(15)),
+            _ => panic!("unexpected events"),
+        }
+        assert_eq!(monitor.condition(), (MarketCondition::Normal, None));
+    }
+}
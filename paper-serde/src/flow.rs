@@ -0,0 +1,378 @@
+use bigdecimal::{BigDecimal, Signed, ToPrimitive, Zero};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+use paper_core::guid::domain::OrderSide;
+use paper_core::guid::orderbook::{OrderProcessingResult, Success};
+
+const BASIS_POINTS: u32 = 10_000;
+
+/// Cell of the order flow summary
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FlowKey {
+    /// start of the time bucket
+    pub bucket: SystemTime,
+    /// distance from the mid in bands of the configured width, nearest is 0.
+    /// None for events without a price, such as market orders, or seen
+    /// while one side of the book was empty
+    pub band: Option<u32>,
+}
+
+/// Events counted in a cell, quantities in the order asset
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlowCounts {
+    pub new_orders: u64,
+    pub new_qty: BigDecimal,
+    /// cancellations and expiries, with the quantity they removed
+    pub cancels: u64,
+    pub cancel_qty: BigDecimal,
+    /// trades, each counted once
+    pub trades: u64,
+    pub trade_qty: BigDecimal,
+}
+
+impl Default for FlowCounts {
+    fn default() -> Self {
+        FlowCounts {
+            new_orders: 0,
+            new_qty: BigDecimal::zero(),
+            cancels: 0,
+            cancel_qty: BigDecimal::zero(),
+            trades: 0,
+            trade_qty: BigDecimal::zero(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct RestingOrder {
+    side: OrderSide,
+    price: BigDecimal,
+    qty: BigDecimal,
+}
+
+/// Order flow of a book summarised by distance from the mid and time.
+///
+/// Fed with the processing results of every request of the book, in order.
+/// Distances are measured from the mid the book had before the request, as
+/// rebuilt from the results seen so far, so the consumer needs nothing but
+/// the engine output. Times are bucketed from the Unix epoch.
+#[derive(Debug)]
+pub struct OrderFlowStats {
+    bucket: Duration,
+    band_bps: u32,
+    cells: BTreeMap<FlowKey, FlowCounts>,
+    resting: HashMap<Uuid, RestingOrder>,
+    bids: BTreeMap<BigDecimal, BigDecimal>,
+    asks: BTreeMap<BigDecimal, BigDecimal>,
+}
+
+impl OrderFlowStats {
+    /// Summarise in time buckets of `bucket` and price bands `band_bps`
+    /// basis points of the mid wide
+    pub fn new(bucket: Duration, band_bps: u32) -> Self {
+        OrderFlowStats {
+            bucket: bucket.max(Duration::from_nanos(1)),
+            band_bps: band_bps.max(1),
+            cells: BTreeMap::new(),
+            resting: HashMap::new(),
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+        }
+    }
+
+    /// Count the events of a single request
+    pub fn record<Asset>(&mut self, results: &OrderProcessingResult<Asset>) {
+        let mid = self.mid();
+
+        for result in results {
+            match result {
+                Ok(Success::Accepted {
+                    order_id,
+                    side,
+                    price,
+                    qty,
+                    ts,
+                    ..
+                }) => {
+                    let counts = self.cell(*ts, price.as_ref(), mid.as_ref());
+                    counts.new_orders += 1;
+                    counts.new_qty += qty;
+                    if let Some(price) = price {
+                        self.add(*order_id, *side, price.clone(), qty.clone());
+                    }
+                }
+                Ok(Success::Filled {
+                    order_id,
+                    side,
+                    aggressor,
+                    price,
+                    qty,
+                    ts,
+                    ..
+                })
+                | Ok(Success::PartiallyFilled {
+                    order_id,
+                    side,
+                    aggressor,
+                    price,
+                    qty,
+                    ts,
+                    ..
+                }) => {
+                    // both fills of a trade are reported, the taker's counts
+                    if side == aggressor {
+                        let counts = self.cell(*ts, Some(price), mid.as_ref());
+                        counts.trades += 1;
+                        counts.trade_qty += qty;
+                    }
+                    self.reduce(*order_id, qty);
+                }
+                Ok(Success::Amended {
+                    order_id,
+                    price,
+                    qty,
+                    ..
+                }) => {
+                    if let Some(order) = self.remove(*order_id) {
+                        self.add(*order_id, order.side, price.clone(), qty.clone());
+                    }
+                }
+                Ok(Success::Cancelled { order_id, ts })
+                | Ok(Success::Expired { order_id, ts })
+                | Ok(Success::DustCancelled { order_id, ts, .. }) => {
+                    if let Some(order) = self.remove(*order_id) {
+                        let counts = self.cell(*ts, Some(&order.price), mid.as_ref());
+                        counts.cancels += 1;
+                        counts.cancel_qty += &order.qty;
+                    }
+                }
+                Ok(Success::SelfTradePrevented {
+                    order_id, qty, ts, ..
+                }) => {
+                    // market orders never rest and go unbanded
+                    let price = self.resting.get(order_id).map(|order| order.price.clone());
+                    let counts = self.cell(*ts, price.as_ref(), mid.as_ref());
+                    counts.cancels += 1;
+                    counts.cancel_qty += qty;
+                    self.reduce(*order_id, qty);
+                }
+                Ok(Success::CancelledRemainder {
+                    unfilled_qty, ts, ..
+                }) => {
+                    let counts = self.cell(*ts, None, mid.as_ref());
+                    counts.cancels += 1;
+                    counts.cancel_qty += unfilled_qty;
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Every cell with events, by time bucket then band
+    pub fn cells(&self) -> impl Iterator<Item = (&FlowKey, &FlowCounts)> {
+        self.cells.iter()
+    }
+
+    pub fn get(&self, key: &FlowKey) -> Option<&FlowCounts> {
+        self.cells.get(key)
+    }
+
+    /// Start of the bucket `ts` falls into
+    pub fn bucket_of(&self, ts: SystemTime) -> SystemTime {
+        let since = ts.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+        let bucket = self.bucket.as_nanos();
+        let start = since - since % bucket;
+        UNIX_EPOCH + Duration::from_nanos(start as u64)
+    }
+
+    /// Write every cell as CSV, by time bucket then band.
+    ///
+    /// Bucket starts are nanoseconds since the Unix epoch, the band is left
+    /// empty for unpriced events.
+    pub fn export_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(
+            writer,
+            "bucket,band,new_orders,new_qty,cancels,cancel_qty,trades,trade_qty"
+        )?;
+        for (key, counts) in &self.cells {
+            let bucket = key
+                .bucket
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_nanos());
+            let band = key.band.map_or(String::new(), |band| band.to_string());
+            writeln!(
+                writer,
+                "{},{},{},{},{},{},{},{}",
+                bucket,
+                band,
+                counts.new_orders,
+                counts.new_qty.normalized(),
+                counts.cancels,
+                counts.cancel_qty.normalized(),
+                counts.trades,
+                counts.trade_qty.normalized()
+            )?;
+        }
+        writer.flush()
+    }
+
+    /* Helpers */
+
+    fn mid(&self) -> Option<BigDecimal> {
+        let (bid, _) = self.bids.iter().next_back()?;
+        let (ask, _) = self.asks.iter().next()?;
+        Some((bid + ask) / BigDecimal::from(2))
+    }
+
+    fn cell(
+        &mut self,
+        ts: SystemTime,
+        price: Option<&BigDecimal>,
+        mid: Option<&BigDecimal>,
+    ) -> &mut FlowCounts {
+        let band = match (price, mid) {
+            (Some(price), Some(mid)) if mid.is_positive() => {
+                let bps = (price - mid).abs() * BigDecimal::from(BASIS_POINTS) / mid;
+                (bps / BigDecimal::from(self.band_bps))
+                    .with_scale(0)
+                    .to_u32()
+            }
+            _ => None,
+        };
+        let key = FlowKey {
+            bucket: self.bucket_of(ts),
+            band,
+        };
+        self.cells.entry(key).or_default()
+    }
+
+    fn add(&mut self, order_id: Uuid, side: OrderSide, price: BigDecimal, qty: BigDecimal) {
+        *self.levels_mut(side).entry(price.clone()).or_default() += &qty;
+        self.resting
+            .insert(order_id, RestingOrder { side, price, qty });
+    }
+
+    fn reduce(&mut self, order_id: Uuid, qty: &BigDecimal) {
+        let order = match self.resting.get_mut(&order_id) {
+            Some(order) => order,
+            None => return,
+        };
+        order.qty -= qty;
+        let (side, price) = (order.side, order.price.clone());
+        if !order.qty.is_positive() {
+            self.resting.remove(&order_id);
+        }
+        self.take_from_level(side, &price, qty);
+    }
+
+    fn remove(&mut self, order_id: Uuid) -> Option<RestingOrder> {
+        let order = self.resting.remove(&order_id)?;
+        self.take_from_level(order.side, &order.price, &order.qty);
+        Some(order)
+    }
+
+    fn take_from_level(&mut self, side: OrderSide, price: &BigDecimal, qty: &BigDecimal) {
+        let levels = self.levels_mut(side);
+        if let Some(level) = levels.get_mut(price) {
+            *level -= qty;
+            if !level.is_positive() {
+                levels.remove(price);
+            }
+        }
+    }
+
+    fn levels_mut(&mut self, side: OrderSide) -> &mut BTreeMap<BigDecimal, BigDecimal> {
+        match side {
+            OrderSide::Bid => &mut self.bids,
+            OrderSide::Ask => &mut self.asks,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use paper_core::guid::clock::{Clock, ManualClock};
+    use paper_core::guid::orderbook::Orderbook;
+    use paper_core::guid::orders;
+    use std::sync::Arc;
+    use std::str::FromStr;
+
+    #[derive(PartialEq, Eq, Debug, Copy, Clone)]
+    pub enum Asset {
+        USD,
+        BTC,
+    }
+
+    fn bigdec(num: &str) -> BigDecimal {
+        BigDecimal::from_str(num).unwrap()
+    }
+
+    #[test]
+    fn flow_by_band_and_bucket() {
+        let start = UNIX_EPOCH + Duration::from_secs(1_700_000_040);
+        let clock = Arc::new(ManualClock::new(start));
+        let mut book = Orderbook::new(Asset::BTC, Asset::USD);
+        book.set_clock(Box::new(clock.clone()));
+        let mut stats = OrderFlowStats::new(Duration::from_secs(60), 10);
+        let mut process = |request| {
+            let results = book.process_order(request);
+            stats.record(&results);
+            results
+        };
+        let limit = |side, price: &str| {
+            orders::new_limit_order_request(
+                Asset::BTC,
+                Asset::USD,
+                side,
+                bigdec(price),
+                bigdec("1"),
+                clock.now(),
+            )
+        };
+
+        // no mid yet, both quotes are unbanded
+        process(limit(OrderSide::Bid, "99"));
+        process(limit(OrderSide::Ask, "101"));
+        // 0.5% off the mid of 100 is band 5, then 1.75 off 100.25 band 17
+        process(limit(OrderSide::Bid, "99.5"));
+        let far = limit(OrderSide::Ask, "102");
+        let far_id = far.order_id();
+        process(far);
+
+        // next minute, mid 100.25: a buyer lifts the ask, the far one is pulled
+        clock.advance(Duration::from_secs(61));
+        process(orders::new_market_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Bid,
+            bigdec("0.5"),
+            clock.now(),
+        ));
+        process(orders::limit_order_cancel_request(far_id, OrderSide::Ask));
+
+        let first = stats.bucket_of(start);
+        let second = stats.bucket_of(clock.now());
+        let cell = |bucket, band| stats.get(&FlowKey { bucket, band }).unwrap();
+        assert_eq!(cell(first, None).new_orders, 2);
+        assert_eq!(cell(first, Some(5)).new_qty, bigdec("1"));
+        assert_eq!(cell(first, Some(17)).new_orders, 1);
+        // the market order itself has no price
+        assert_eq!(cell(second, None).new_qty, bigdec("0.5"));
+        assert_eq!(cell(second, Some(7)).trades, 1);
+        assert_eq!(cell(second, Some(7)).trade_qty, bigdec("0.5"));
+        // the ask at 101 is not used up, the mid stays
+        assert_eq!(cell(second, Some(17)).cancel_qty, bigdec("1"));
+        assert_eq!(stats.cells().count(), 6);
+
+        let mut csv = vec![];
+        stats.export_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 7);
+        assert_eq!(lines[1], "1700000040000000000,,2,2,0,0,0,0");
+    }
+}
@@ -0,0 +1,717 @@
+use bigdecimal::{BigDecimal, Signed, ToPrimitive};
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+use paper_core::guid::domain::OrderSide;
+use paper_core::guid::orderbook::{Failed, OrderProcessingResult, Success};
+
+use crate::capture::CaptureRecord;
+
+/// Prices are sent in units of 1/10000, as in ITCH
+pub const DEFAULT_PRICE_SCALE: u64 = 10_000;
+/// Quantities are sent in units of 1e-8, fine enough for crypto lots
+pub const DEFAULT_QTY_SCALE: u64 = 100_000_000;
+
+/// Type, sequence number and nanoseconds since the epoch
+const HEADER_LEN: usize = 17;
+
+/// Message of the binary feed.
+///
+/// Every message has a fixed layout chosen by its type byte, integers are
+/// big-endian:
+///
+/// | type | body after the 17-byte header (type u8, seq u64, ts u64) | bytes |
+/// |------|-----------------------------------------------------------|-------|
+/// | `A`  | order ref u64, side u8, qty u64, price i64                | 42    |
+/// | `M`  | order ref u64, qty u64, price i64, priority kept u8       | 42    |
+/// | `D`  | order ref u64                                             | 25    |
+/// | `E`  | order ref u64, qty u64, price i64, match number u128      | 57    |
+/// | `P`  | aggressor u8, qty u64, price i64, match number u128       | 50    |
+/// | `L`  | side u8, qty u64, price i64                               | 34    |
+///
+/// Sides are `B` and `S`, prices and quantities integers in units of the
+/// encoder scales.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ItchMessage {
+    /// consecutive from 1 for each encoder
+    pub seq: u64,
+    /// nanoseconds since the Unix epoch
+    pub ts: u64,
+    pub body: ItchBody,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ItchBody {
+    /// order now resting in the book with its displayed quantity
+    AddOrder {
+        order_ref: u64,
+        side: OrderSide,
+        qty: u64,
+        price: i64,
+    },
+    /// new quantity and price of a resting order
+    ModifyOrder {
+        order_ref: u64,
+        qty: u64,
+        price: i64,
+        /// false when the order went to the back of its level
+        priority_kept: bool,
+    },
+    DeleteOrder {
+        order_ref: u64,
+    },
+    /// resting order hit by a trade, once per trade
+    OrderExecuted {
+        order_ref: u64,
+        qty: u64,
+        price: i64,
+        match_number: u128,
+    },
+    /// trade of a price-level feed, without order references
+    Trade {
+        aggressor: OrderSide,
+        qty: u64,
+        price: i64,
+        match_number: u128,
+    },
+    /// new total quantity of a price level, zero when the level is gone
+    PriceLevel {
+        side: OrderSide,
+        qty: u64,
+        price: i64,
+    },
+}
+
+impl ItchBody {
+    pub fn type_byte(&self) -> u8 {
+        match self {
+            ItchBody::AddOrder { .. } => b'A',
+            ItchBody::ModifyOrder { .. } => b'M',
+            ItchBody::DeleteOrder { .. } => b'D',
+            ItchBody::OrderExecuted { .. } => b'E',
+            ItchBody::Trade { .. } => b'P',
+            ItchBody::PriceLevel { .. } => b'L',
+        }
+    }
+}
+
+/// Length of a message of the type, header included
+pub fn message_len(type_byte: u8) -> Option<usize> {
+    let body = match type_byte {
+        b'A' | b'M' => 25,
+        b'D' => 8,
+        b'E' => 40,
+        b'P' => 33,
+        b'L' => 17,
+        _ => return None,
+    };
+    Some(HEADER_LEN + body)
+}
+
+impl ItchMessage {
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let mut bytes = Vec::with_capacity(message_len(self.body.type_byte()).unwrap());
+        bytes.push(self.body.type_byte());
+        bytes.extend_from_slice(&self.seq.to_be_bytes());
+        bytes.extend_from_slice(&self.ts.to_be_bytes());
+        match &self.body {
+            ItchBody::AddOrder {
+                order_ref,
+                side,
+                qty,
+                price,
+            } => {
+                bytes.extend_from_slice(&order_ref.to_be_bytes());
+                bytes.push(side_byte(*side));
+                bytes.extend_from_slice(&qty.to_be_bytes());
+                bytes.extend_from_slice(&price.to_be_bytes());
+            }
+            ItchBody::ModifyOrder {
+                order_ref,
+                qty,
+                price,
+                priority_kept,
+            } => {
+                bytes.extend_from_slice(&order_ref.to_be_bytes());
+                bytes.extend_from_slice(&qty.to_be_bytes());
+                bytes.extend_from_slice(&price.to_be_bytes());
+                bytes.push(*priority_kept as u8);
+            }
+            ItchBody::DeleteOrder { order_ref } => {
+                bytes.extend_from_slice(&order_ref.to_be_bytes());
+            }
+            ItchBody::OrderExecuted {
+                order_ref,
+                qty,
+                price,
+                match_number,
+            } => {
+                bytes.extend_from_slice(&order_ref.to_be_bytes());
+                bytes.extend_from_slice(&qty.to_be_bytes());
+                bytes.extend_from_slice(&price.to_be_bytes());
+                bytes.extend_from_slice(&match_number.to_be_bytes());
+            }
+            ItchBody::Trade {
+                aggressor,
+                qty,
+                price,
+                match_number,
+            } => {
+                bytes.push(side_byte(*aggressor));
+                bytes.extend_from_slice(&qty.to_be_bytes());
+                bytes.extend_from_slice(&price.to_be_bytes());
+                bytes.extend_from_slice(&match_number.to_be_bytes());
+            }
+            ItchBody::PriceLevel { side, qty, price } => {
+                bytes.push(side_byte(*side));
+                bytes.extend_from_slice(&qty.to_be_bytes());
+                bytes.extend_from_slice(&price.to_be_bytes());
+            }
+        }
+        out.write_all(&bytes)
+    }
+
+    /// Read the message at the start of `bytes`, along with its length.
+    ///
+    /// Messages are self-delimiting, so a datagram holding several of them
+    /// is read by decoding from the end of the previous one.
+    pub fn decode(bytes: &[u8]) -> io::Result<(ItchMessage, usize)> {
+        let type_byte = *bytes.first().ok_or_else(|| invalid("empty message"))?;
+        let len = message_len(type_byte).ok_or_else(|| invalid("unknown message type"))?;
+        if bytes.len() < len {
+            return Err(invalid("truncated message"));
+        }
+        let mut reader = Reader {
+            bytes: &bytes[1..len],
+        };
+        let seq = reader.u64();
+        let ts = reader.u64();
+        let body = match type_byte {
+            b'A' => ItchBody::AddOrder {
+                order_ref: reader.u64(),
+                side: reader.side()?,
+                qty: reader.u64(),
+                price: reader.i64(),
+            },
+            b'M' => ItchBody::ModifyOrder {
+                order_ref: reader.u64(),
+                qty: reader.u64(),
+                price: reader.i64(),
+                priority_kept: reader.u8() != 0,
+            },
+            b'D' => ItchBody::DeleteOrder {
+                order_ref: reader.u64(),
+            },
+            b'E' => ItchBody::OrderExecuted {
+                order_ref: reader.u64(),
+                qty: reader.u64(),
+                price: reader.i64(),
+                match_number: reader.u128(),
+            },
+            b'P' => ItchBody::Trade {
+                aggressor: reader.side()?,
+                qty: reader.u64(),
+                price: reader.i64(),
+                match_number: reader.u128(),
+            },
+            _ => ItchBody::PriceLevel {
+                side: reader.side()?,
+                qty: reader.u64(),
+                price: reader.i64(),
+            },
+        };
+        Ok((ItchMessage { seq, ts, body }, len))
+    }
+}
+
+/// Reads the fields of a message already checked to be long enough
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> [u8; N] {
+        let (head, rest) = self.bytes.split_at(N);
+        self.bytes = rest;
+        head.try_into().unwrap()
+    }
+
+    fn u8(&mut self) -> u8 {
+        self.take::<1>()[0]
+    }
+
+    fn u64(&mut self) -> u64 {
+        u64::from_be_bytes(self.take())
+    }
+
+    fn i64(&mut self) -> i64 {
+        i64::from_be_bytes(self.take())
+    }
+
+    fn u128(&mut self) -> u128 {
+        u128::from_be_bytes(self.take())
+    }
+
+    fn side(&mut self) -> io::Result<OrderSide> {
+        match self.u8() {
+            b'B' => Ok(OrderSide::Bid),
+            b'S' => Ok(OrderSide::Ask),
+            _ => Err(invalid("bad side")),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct RestingOrder {
+    order_ref: u64,
+    price: BigDecimal,
+    qty: BigDecimal,
+}
+
+/// Limit order of the request being encoded, added once it is known to rest
+#[derive(Debug, Clone)]
+struct Incoming {
+    order_id: Uuid,
+    side: OrderSide,
+    price: BigDecimal,
+    qty: BigDecimal,
+    ts: SystemTime,
+    rests: bool,
+}
+
+/// Turns the events of a book into messages of a compact binary feed in the
+/// spirit of NASDAQ ITCH, a fraction of the size of the JSON feed.
+///
+/// `encode_results` gives an order-by-order feed: orders are added once
+/// they rest, with only what is left of them after matching, executions are
+/// reported against the resting order, amends and cancellations modify and
+/// delete it. Order UUIDs are numbered from 1 in order of appearance.
+/// `encode_records` gives a price-level feed from the depth deltas and
+/// trades of a `DepthRecorder`. Both share the sequence numbers of the
+/// encoder.
+///
+/// Prices and quantities must be whole multiples of 1/scale and fit the
+/// integers of the layout, others are refused as invalid data rather than
+/// rounded.
+#[derive(Debug)]
+pub struct ItchEncoder {
+    price_scale: BigDecimal,
+    qty_scale: BigDecimal,
+    seq: u64,
+    refs: HashMap<Uuid, u64>,
+    resting: HashMap<Uuid, RestingOrder>,
+}
+
+impl Default for ItchEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ItchEncoder {
+    pub fn new() -> Self {
+        ItchEncoder {
+            price_scale: BigDecimal::from(DEFAULT_PRICE_SCALE),
+            qty_scale: BigDecimal::from(DEFAULT_QTY_SCALE),
+            seq: 0,
+            refs: HashMap::new(),
+            resting: HashMap::new(),
+        }
+    }
+
+    pub fn with_price_scale(mut self, scale: u64) -> Self {
+        self.price_scale = BigDecimal::from(scale);
+        self
+    }
+
+    pub fn with_qty_scale(mut self, scale: u64) -> Self {
+        self.qty_scale = BigDecimal::from(scale);
+        self
+    }
+
+    /// Sequence number of the last message
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// Reference the order got in the feed
+    pub fn order_ref(&self, order_id: Uuid) -> Option<u64> {
+        self.refs.get(&order_id).copied()
+    }
+
+    /// Order-by-order messages for the results of a single request
+    pub fn encode_results<Asset>(
+        &mut self,
+        results: &OrderProcessingResult<Asset>,
+    ) -> io::Result<Vec<ItchMessage>> {
+        let mut messages = vec![];
+        let mut incoming: Option<Incoming> = None;
+
+        for result in results {
+            match result {
+                // stops released by the request come after it is done
+                Ok(Success::Accepted {
+                    order_id,
+                    side,
+                    price: Some(price),
+                    qty,
+                    ts,
+                    ..
+                }) => {
+                    if let Some(done) = incoming.take() {
+                        self.add(&mut messages, done)?;
+                    }
+                    incoming = Some(Incoming {
+                        order_id: *order_id,
+                        side: *side,
+                        price: price.clone(),
+                        qty: qty.clone(),
+                        ts: *ts,
+                        rests: true,
+                    });
+                }
+                Ok(Success::Filled {
+                    order_id,
+                    trade_id,
+                    price,
+                    qty,
+                    ts,
+                    ..
+                })
+                | Ok(Success::PartiallyFilled {
+                    order_id,
+                    trade_id,
+                    price,
+                    qty,
+                    ts,
+                    ..
+                }) => match &mut incoming {
+                    Some(taker) if taker.order_id == *order_id => taker.qty -= qty,
+                    _ => {
+                        let order_ref = match self.reduce(*order_id, qty) {
+                            Some(order) => order.order_ref,
+                            None => continue,
+                        };
+                        let body = ItchBody::OrderExecuted {
+                            order_ref,
+                            qty: self.qty(qty)?,
+                            price: self.price(price)?,
+                            match_number: trade_id.0,
+                        };
+                        self.push(&mut messages, *ts, body);
+                    }
+                },
+                Ok(Success::Amended {
+                    order_id,
+                    price,
+                    qty,
+                    priority_kept,
+                    ts,
+                    ..
+                }) => {
+                    let order = match self.resting.get_mut(order_id) {
+                        Some(order) => order,
+                        None => continue,
+                    };
+                    order.price = price.clone();
+                    order.qty = qty.clone();
+                    let body = ItchBody::ModifyOrder {
+                        order_ref: order.order_ref,
+                        qty: self.qty(qty)?,
+                        price: self.price(price)?,
+                        priority_kept: *priority_kept,
+                    };
+                    self.push(&mut messages, *ts, body);
+                }
+                Ok(Success::Cancelled { order_id, ts })
+                | Ok(Success::Expired { order_id, ts })
+                | Ok(Success::DustCancelled { order_id, ts, .. }) => match &mut incoming {
+                    Some(taker) if taker.order_id == *order_id => taker.rests = false,
+                    _ => self.delete(&mut messages, *order_id, *ts),
+                },
+                Ok(Success::SelfTradePrevented {
+                    order_id,
+                    qty,
+                    remaining,
+                    ts,
+                    ..
+                }) => match &mut incoming {
+                    Some(taker) if taker.order_id == *order_id => {
+                        taker.qty -= qty;
+                        taker.rests = taker.rests && remaining.is_positive();
+                    }
+                    _ if remaining.is_positive() => {
+                        let order = match self.reduce(*order_id, qty) {
+                            Some(order) => order,
+                            None => continue,
+                        };
+                        let body = ItchBody::ModifyOrder {
+                            order_ref: order.order_ref,
+                            qty: self.qty(&order.qty)?,
+                            price: self.price(&order.price)?,
+                            priority_kept: true,
+                        };
+                        self.push(&mut messages, *ts, body);
+                    }
+                    _ => self.delete(&mut messages, *order_id, *ts),
+                },
+                // refused by the depth limit, or evicted to make room
+                Err(Failed::DepthLimitExceeded(order_id)) => match &mut incoming {
+                    Some(taker) if taker.order_id == *order_id => taker.rests = false,
+                    _ => {
+                        let ts = incoming.as_ref().map_or_else(SystemTime::now, |new| new.ts);
+                        self.delete(&mut messages, *order_id, ts)
+                    }
+                },
+                _ => (),
+            }
+        }
+
+        if let Some(done) = incoming {
+            self.add(&mut messages, done)?;
+        }
+        Ok(messages)
+    }
+
+    /// Price-level messages for depth deltas and trades
+    pub fn encode_records(&mut self, records: &[CaptureRecord]) -> io::Result<Vec<ItchMessage>> {
+        let mut messages = Vec::with_capacity(records.len());
+        for record in records {
+            let body = match record {
+                CaptureRecord::Depth {
+                    side, price, qty, ..
+                } => ItchBody::PriceLevel {
+                    side: *side,
+                    qty: self.qty(qty)?,
+                    price: self.price(price)?,
+                },
+                CaptureRecord::Trade {
+                    trade_id,
+                    aggressor,
+                    price,
+                    qty,
+                    ..
+                } => ItchBody::Trade {
+                    aggressor: *aggressor,
+                    qty: self.qty(qty)?,
+                    price: self.price(price)?,
+                    match_number: trade_id.0,
+                },
+            };
+            self.push(&mut messages, record.ts(), body);
+        }
+        Ok(messages)
+    }
+
+    /* Helpers */
+
+    fn add(&mut self, messages: &mut Vec<ItchMessage>, incoming: Incoming) -> io::Result<()> {
+        if !incoming.rests || !incoming.qty.is_positive() {
+            return Ok(());
+        }
+        let next = self.refs.len() as u64 + 1;
+        let order_ref = *self.refs.entry(incoming.order_id).or_insert(next);
+        let body = ItchBody::AddOrder {
+            order_ref,
+            side: incoming.side,
+            qty: self.qty(&incoming.qty)?,
+            price: self.price(&incoming.price)?,
+        };
+        self.resting.insert(
+            incoming.order_id,
+            RestingOrder {
+                order_ref,
+                price: incoming.price,
+                qty: incoming.qty,
+            },
+        );
+        self.push(messages, incoming.ts, body);
+        Ok(())
+    }
+
+    /// Take `qty` off a resting order, forgetting it once nothing is left
+    fn reduce(&mut self, order_id: Uuid, qty: &BigDecimal) -> Option<RestingOrder> {
+        let order = self.resting.get_mut(&order_id)?;
+        order.qty -= qty;
+        let order = order.clone();
+        if !order.qty.is_positive() {
+            self.resting.remove(&order_id);
+        }
+        Some(order)
+    }
+
+    fn delete(&mut self, messages: &mut Vec<ItchMessage>, order_id: Uuid, ts: SystemTime) {
+        if let Some(order) = self.resting.remove(&order_id) {
+            let body = ItchBody::DeleteOrder {
+                order_ref: order.order_ref,
+            };
+            self.push(messages, ts, body);
+        }
+    }
+
+    fn push(&mut self, messages: &mut Vec<ItchMessage>, ts: SystemTime, body: ItchBody) {
+        self.seq += 1;
+        let since_epoch = ts.duration_since(UNIX_EPOCH).unwrap_or_default();
+        messages.push(ItchMessage {
+            seq: self.seq,
+            ts: since_epoch.as_nanos() as u64,
+            body,
+        });
+    }
+
+    fn price(&self, price: &BigDecimal) -> io::Result<i64> {
+        let scaled = price * &self.price_scale;
+        if !scaled.is_integer() {
+            return Err(invalid("price finer than the price scale"));
+        }
+        scaled.to_i64().ok_or_else(|| invalid("price out of range"))
+    }
+
+    fn qty(&self, qty: &BigDecimal) -> io::Result<u64> {
+        let scaled = qty * &self.qty_scale;
+        if !scaled.is_integer() {
+            return Err(invalid("quantity finer than the quantity scale"));
+        }
+        scaled
+            .to_u64()
+            .ok_or_else(|| invalid("quantity out of range"))
+    }
+}
+
+fn side_byte(side: OrderSide) -> u8 {
+    match side {
+        OrderSide::Bid => b'B',
+        OrderSide::Ask => b'S',
+    }
+}
+
+fn invalid(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::capture::DepthRecorder;
+    use paper_core::guid::orderbook::Orderbook;
+    use paper_core::guid::orders;
+    use std::str::FromStr;
+
+    #[derive(PartialEq, Eq, Debug, Copy, Clone)]
+    pub enum Asset {
+        USD,
+        BTC,
+    }
+
+    fn bigdec(num: &str) -> BigDecimal {
+        BigDecimal::from_str(num).unwrap()
+    }
+
+    fn limit(side: OrderSide, price: &str, qty: &str) -> orders::OrderRequest<Asset> {
+        orders::new_limit_order_request(
+            Asset::BTC,
+            Asset::USD,
+            side,
+            bigdec(price),
+            bigdec(qty),
+            SystemTime::now(),
+        )
+    }
+
+    #[test]
+    fn order_and_level_feeds() {
+        let mut book = Orderbook::new(Asset::BTC, Asset::USD);
+        let mut encoder = ItchEncoder::new().with_qty_scale(100);
+        let mut recorder = DepthRecorder::new();
+        let mut wire = vec![];
+        let mut bodies = vec![];
+        let mut process = |request| {
+            let results = book.process_order(request);
+            let mut messages = encoder.encode_results(&results).unwrap();
+            let records = recorder.record(&book, &results, SystemTime::now());
+            messages.extend(encoder.encode_records(&records).unwrap());
+            for message in messages {
+                message.write_to(&mut wire).unwrap();
+                bodies.push(message.body);
+            }
+        };
+
+        let ask = limit(OrderSide::Ask, "101.5", "2");
+        let ask_id = ask.order_id();
+        process(ask);
+        process(orders::amend_order_qty_request(
+            ask_id,
+            OrderSide::Ask,
+            bigdec("1.5"),
+            SystemTime::now(),
+        ));
+        // takes the whole ask and rests the remainder
+        process(limit(OrderSide::Bid, "102", "2"));
+
+        let level = |side, qty, price| ItchBody::PriceLevel { side, qty, price };
+        let expected = [
+            ItchBody::AddOrder {
+                order_ref: 1,
+                side: OrderSide::Ask,
+                qty: 200,
+                price: 1_015_000,
+            },
+            level(OrderSide::Ask, 200, 1_015_000),
+            ItchBody::ModifyOrder {
+                order_ref: 1,
+                qty: 150,
+                price: 1_015_000,
+                priority_kept: true,
+            },
+            level(OrderSide::Ask, 150, 1_015_000),
+            ItchBody::OrderExecuted {
+                order_ref: 1,
+                qty: 150,
+                price: 1_015_000,
+                match_number: 1,
+            },
+            ItchBody::AddOrder {
+                order_ref: 2,
+                side: OrderSide::Bid,
+                qty: 50,
+                price: 1_020_000,
+            },
+        ];
+        assert_eq!(bodies[..expected.len()], expected[..]);
+        assert!(bodies[expected.len()..].contains(&ItchBody::Trade {
+            aggressor: OrderSide::Bid,
+            qty: 150,
+            price: 1_015_000,
+            match_number: 1,
+        }));
+        assert!(bodies[expected.len()..].contains(&level(OrderSide::Bid, 50, 1_020_000)));
+        assert!(bodies[expected.len()..].contains(&level(OrderSide::Ask, 0, 1_015_000)));
+        assert_eq!(encoder.order_ref(ask_id), Some(1));
+
+        // fixed layouts, read back one after the other
+        let mut read = vec![];
+        let mut offset = 0;
+        while offset < wire.len() {
+            let (message, len) = ItchMessage::decode(&wire[offset..]).unwrap();
+            assert_eq!(Some(len), message_len(message.body.type_byte()));
+            read.push(message);
+            offset += len;
+        }
+        assert_eq!(read.len(), bodies.len());
+        assert_eq!(read.last().unwrap().seq, encoder.seq());
+        assert!(read.windows(2).all(|pair| pair[1].seq == pair[0].seq + 1));
+        assert_eq!(
+            read.iter().map(|message| &message.body).collect::<Vec<_>>(),
+            bodies.iter().collect::<Vec<_>>()
+        );
+        assert!(ItchMessage::decode(&wire[..10]).is_err());
+
+        // quantities finer than the scale are refused, not rounded
+        let results = book.process_order(limit(OrderSide::Bid, "100", "0.001"));
+        let err = encoder.encode_results(&results).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
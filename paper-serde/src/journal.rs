@@ -0,0 +1,807 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+use paper_core::guid::engine::Symbol;
+use paper_core::guid::journal::Journal;
+use paper_core::guid::orders::OrderRequest;
+
+const COMPRESSION_LEVEL: i32 = 3;
+
+/// Request as stored in the journal
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry<Asset>
+where
+    Asset: Debug + Clone,
+{
+    /// consecutive from 1 over the whole journal
+    pub seq: u64,
+    pub symbol: Symbol<Asset>,
+    /// time of the request, cancels carry the one of the entry before
+    pub ts: SystemTime,
+    pub request: OrderRequest<Asset>,
+}
+
+/// Frame of the journal and what it holds, enough to skip it unread
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalIndexEntry<Asset> {
+    /// byte offset of the frame
+    pub offset: u64,
+    pub first_seq: u64,
+    pub entries: u32,
+    /// earliest and latest request time in the frame, requests of different
+    /// clients need not arrive in time order
+    pub min_ts: SystemTime,
+    pub max_ts: SystemTime,
+    /// markets with requests in the frame
+    pub symbols: Vec<Symbol<Asset>>,
+}
+
+impl<Asset> JournalIndexEntry<Asset> {
+    pub fn seqs(&self) -> Range<u64> {
+        self.first_seq..self.first_seq + self.entries as u64
+    }
+}
+
+/// Part of a journal to replay, everything by default
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayFilter<Asset> {
+    pub symbol: Option<Symbol<Asset>>,
+    /// inclusive
+    pub from: Option<SystemTime>,
+    /// exclusive
+    pub until: Option<SystemTime>,
+    pub seqs: Option<Range<u64>>,
+}
+
+impl<Asset> Default for ReplayFilter<Asset> {
+    fn default() -> Self {
+        ReplayFilter {
+            symbol: None,
+            from: None,
+            until: None,
+            seqs: None,
+        }
+    }
+}
+
+impl<Asset> ReplayFilter<Asset>
+where
+    Asset: Debug + Clone + PartialEq,
+{
+    pub fn matches(&self, entry: &JournalEntry<Asset>) -> bool {
+        self.symbol
+            .as_ref()
+            .is_none_or(|symbol| *symbol == entry.symbol)
+            && self.from.is_none_or(|from| entry.ts >= from)
+            && self.until.is_none_or(|until| entry.ts < until)
+            && self
+                .seqs
+                .as_ref()
+                .is_none_or(|seqs| seqs.contains(&entry.seq))
+    }
+
+    /// Whether the frame can hold a matching entry
+    pub fn may_match(&self, frame: &JournalIndexEntry<Asset>) -> bool {
+        let seqs = frame.seqs();
+        self.symbol
+            .as_ref()
+            .is_none_or(|symbol| frame.symbols.contains(symbol))
+            && self.from.is_none_or(|from| frame.max_ts >= from)
+            && self.until.is_none_or(|until| frame.min_ts < until)
+            && self
+                .seqs
+                .as_ref()
+                .is_none_or(|wanted| wanted.start < seqs.end && seqs.start < wanted.end)
+    }
+}
+
+/// Writes journaled requests as zstd compressed frames.
+///
+/// Frames are laid out as in `capture`: a little-endian u32 length followed
+/// by the compressed JSON lines of the entries. The index records which
+/// markets, times and sequence numbers every frame covers, so a filtered
+/// replay only decompresses the frames it needs.
+pub struct JournalWriter<W, Asset>
+where
+    W: Write,
+    Asset: Debug + Clone,
+{
+    inner: W,
+    offset: u64,
+    seq: u64,
+    last_ts: SystemTime,
+    frame_entries: usize,
+    pending: Vec<JournalEntry<Asset>>,
+    index: Vec<JournalIndexEntry<Asset>>,
+}
+
+impl<W, Asset> JournalWriter<W, Asset>
+where
+    W: Write,
+    Asset: Debug + Clone + PartialEq + Serialize,
+{
+    /// Create writer closing a frame every `frame_entries` requests
+    pub fn new(inner: W, frame_entries: usize) -> Self {
+        JournalWriter {
+            inner,
+            offset: 0,
+            seq: 0,
+            last_ts: SystemTime::UNIX_EPOCH,
+            frame_entries: frame_entries.max(1),
+            pending: vec![],
+            index: vec![],
+        }
+    }
+
+    /// Write out buffered requests as a frame, even if not full
+    pub fn flush_frame(&mut self) -> io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+
+        let mut payload = vec![];
+        let mut symbols = vec![];
+        for entry in &self.pending {
+            serde_json::to_writer(&mut payload, entry)?;
+            payload.push(b'\n');
+            if !symbols.contains(&entry.symbol) {
+                symbols.push(entry.symbol.clone());
+            }
+        }
+        let compressed = zstd::encode_all(payload.as_slice(), COMPRESSION_LEVEL)?;
+
+        self.inner
+            .write_all(&(compressed.len() as u32).to_le_bytes())?;
+        self.inner.write_all(&compressed)?;
+        let times = self.pending.iter().map(|entry| entry.ts);
+        self.index.push(JournalIndexEntry {
+            offset: self.offset,
+            first_seq: self.pending[0].seq,
+            entries: self.pending.len() as u32,
+            min_ts: times.clone().min().unwrap(),
+            max_ts: times.max().unwrap(),
+            symbols,
+        });
+        self.offset += 4 + compressed.len() as u64;
+        self.pending.clear();
+        Ok(())
+    }
+
+    pub fn index(&self) -> &[JournalIndexEntry<Asset>] {
+        &self.index
+    }
+
+    /// Sequence number of the last appended request, 0 before the first
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// Flush the last frame, returns the underlying writer and the index
+    pub fn finish(mut self) -> io::Result<(W, Vec<JournalIndexEntry<Asset>>)> {
+        self.flush_frame()?;
+        self.inner.flush()?;
+        Ok((self.inner, self.index))
+    }
+}
+
+impl<W, Asset> Journal<Asset> for JournalWriter<W, Asset>
+where
+    W: Write,
+    Asset: Debug + Clone + PartialEq + Serialize,
+{
+    fn append(&mut self, symbol: Symbol<Asset>, request: &OrderRequest<Asset>) -> io::Result<()> {
+        self.seq += 1;
+        self.last_ts = request.ts().unwrap_or(self.last_ts);
+        self.pending.push(JournalEntry {
+            seq: self.seq,
+            symbol,
+            ts: self.last_ts,
+            request: request.clone(),
+        });
+        if self.pending.len() >= self.frame_entries {
+            self.flush_frame()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_frame()?;
+        self.inner.flush()
+    }
+}
+
+/// When a write-behind journal makes its entries durable
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct GroupCommit {
+    /// entries the ring holds before appends fail
+    pub capacity: usize,
+    /// commit once this many entries wait
+    pub max_entries: Option<usize>,
+    /// commit entries waiting this long
+    pub max_delay: Option<Duration>,
+}
+
+impl Default for GroupCommit {
+    fn default() -> Self {
+        GroupCommit {
+            capacity: 65_536,
+            max_entries: Some(1_000),
+            max_delay: Some(Duration::from_millis(10)),
+        }
+    }
+}
+
+enum WriteBehindMessage<Asset>
+where
+    Asset: Debug + Clone,
+{
+    Append(Symbol<Asset>, OrderRequest<Asset>),
+    /// commit now and answer once done
+    Flush(mpsc::Sender<io::Result<()>>),
+}
+
+/// Progress of the background thread, read by the matching thread
+#[derive(Debug, Default)]
+struct CommitState {
+    committed: AtomicU64,
+    /// first failure, the journal takes nothing after it
+    failure: Mutex<Option<(io::ErrorKind, String)>>,
+}
+
+impl CommitState {
+    fn fail(&self, err: &io::Error) {
+        let mut failure = self.failure.lock().unwrap();
+        if failure.is_none() {
+            *failure = Some((err.kind(), err.to_string()));
+        }
+    }
+
+    fn check(&self) -> io::Result<()> {
+        match &*self.failure.lock().unwrap() {
+            Some((kind, reason)) => Err(io::Error::new(*kind, reason.clone())),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Journal handing entries to a background thread, so the matching thread
+/// never waits on disk.
+///
+/// `append` only puts the request into a bounded ring and fails when the
+/// ring is full, the engine then rejects the request. The thread appends to
+/// the inner journal and flushes it as a group, by `GroupCommit`. `flush`
+/// commits on demand and waits for it.
+///
+/// Requests are processed before they are durable. A write failing later
+/// fails the next append or flush, and the journal takes nothing after it.
+pub struct WriteBehindJournal<J, Asset>
+where
+    Asset: Debug + Clone,
+{
+    ring: Option<SyncSender<WriteBehindMessage<Asset>>>,
+    worker: Option<JoinHandle<J>>,
+    state: Arc<CommitState>,
+}
+
+impl<J, Asset> WriteBehindJournal<J, Asset>
+where
+    J: Journal<Asset> + Send + 'static,
+    Asset: Debug + Clone + Send + 'static,
+{
+    pub fn new(journal: J, policy: GroupCommit) -> Self {
+        let (ring, entries) = mpsc::sync_channel(policy.capacity.max(1));
+        let state = Arc::new(CommitState::default());
+        let worker_state = state.clone();
+        let worker = thread::spawn(move || write_behind(journal, entries, policy, &worker_state));
+        WriteBehindJournal {
+            ring: Some(ring),
+            worker: Some(worker),
+            state,
+        }
+    }
+
+    /// Entries made durable so far
+    pub fn committed(&self) -> u64 {
+        self.state.committed.load(Ordering::Acquire)
+    }
+
+    /// Commit what is left, stop the thread and return the inner journal
+    pub fn finish(mut self) -> io::Result<J> {
+        drop(self.ring.take());
+        let journal = self
+            .worker
+            .take()
+            .unwrap()
+            .join()
+            .map_err(|_| io::Error::other("journal thread panicked"))?;
+        self.state.check()?;
+        Ok(journal)
+    }
+}
+
+impl<J, Asset> Journal<Asset> for WriteBehindJournal<J, Asset>
+where
+    Asset: Debug + Clone,
+{
+    fn append(&mut self, symbol: Symbol<Asset>, request: &OrderRequest<Asset>) -> io::Result<()> {
+        self.state.check()?;
+        let ring = self.ring.as_ref().unwrap();
+        match ring.try_send(WriteBehindMessage::Append(symbol, request.clone())) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                "journal ring full",
+            )),
+            Err(TrySendError::Disconnected(_)) => Err(stopped()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let (reply, done) = mpsc::channel();
+        let ring = self.ring.as_ref().unwrap();
+        ring.send(WriteBehindMessage::Flush(reply))
+            .map_err(|_| stopped())?;
+        done.recv().map_err(|_| stopped())?
+    }
+}
+
+impl<J, Asset> Drop for WriteBehindJournal<J, Asset>
+where
+    Asset: Debug + Clone,
+{
+    /// Commit what is left before the thread stops
+    fn drop(&mut self) {
+        drop(self.ring.take());
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Reads back the entries of a journal passing a filter, in journal order
+pub struct JournalReader<R, Asset>
+where
+    R: Read + Seek,
+    Asset: Debug + Clone,
+{
+    inner: R,
+    index: Vec<JournalIndexEntry<Asset>>,
+    filter: ReplayFilter<Asset>,
+    next_frame: usize,
+    buffered: VecDeque<JournalEntry<Asset>>,
+}
+
+impl<R, Asset> JournalReader<R, Asset>
+where
+    R: Read + Seek,
+    Asset: Debug + Clone + PartialEq + DeserializeOwned,
+{
+    pub fn new(
+        inner: R,
+        index: Vec<JournalIndexEntry<Asset>>,
+        filter: ReplayFilter<Asset>,
+    ) -> Self {
+        JournalReader {
+            inner,
+            index,
+            filter,
+            next_frame: 0,
+            buffered: VecDeque::new(),
+        }
+    }
+
+    /// Requests of the matching entries, ready for a `Replayer`
+    pub fn requests(self) -> impl Iterator<Item = io::Result<OrderRequest<Asset>>> {
+        self.map(|entry| entry.map(|entry| entry.request))
+    }
+
+    /// Read next frame that may match, returns false past the last one
+    fn read_frame(&mut self) -> io::Result<bool> {
+        let frame = match self.index[self.next_frame..]
+            .iter()
+            .position(|frame| self.filter.may_match(frame))
+        {
+            Some(skipped) => self.next_frame + skipped,
+            None => {
+                self.next_frame = self.index.len();
+                return Ok(false);
+            }
+        };
+        self.next_frame = frame + 1;
+
+        self.inner.seek(SeekFrom::Start(self.index[frame].offset))?;
+        let mut len = [0u8; 4];
+        self.inner.read_exact(&mut len)?;
+        let mut compressed = vec![0u8; u32::from_le_bytes(len) as usize];
+        self.inner.read_exact(&mut compressed)?;
+
+        let payload = zstd::decode_all(compressed.as_slice())?;
+        for line in payload.split(|byte| *byte == b'\n') {
+            if line.is_empty() {
+                continue;
+            }
+            let entry: JournalEntry<Asset> = serde_json::from_slice(line)?;
+            if self.filter.matches(&entry) {
+                self.buffered.push_back(entry);
+            }
+        }
+        Ok(true)
+    }
+}
+
+impl<R, Asset> Iterator for JournalReader<R, Asset>
+where
+    R: Read + Seek,
+    Asset: Debug + Clone + PartialEq + DeserializeOwned,
+{
+    type Item = io::Result<JournalEntry<Asset>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.buffered.is_empty() {
+            match self.read_frame() {
+                Ok(true) => (),
+                Ok(false) => return None,
+                Err(err) => return Some(Err(err)),
+            }
+        }
+        self.buffered.pop_front().map(Ok)
+    }
+}
+
+/// Body of the write-behind thread, returns the journal once the ring closes
+fn write_behind<J, Asset>(
+    mut journal: J,
+    entries: Receiver<WriteBehindMessage<Asset>>,
+    policy: GroupCommit,
+    state: &CommitState,
+) -> J
+where
+    J: Journal<Asset>,
+    Asset: Debug + Clone,
+{
+    let mut uncommitted = 0;
+    // arrival of the oldest uncommitted entry
+    let mut oldest: Option<Instant> = None;
+    let commit = |journal: &mut J, uncommitted: &mut u64| {
+        let committed = journal.flush();
+        match &committed {
+            Ok(()) => {
+                state.committed.fetch_add(*uncommitted, Ordering::Release);
+                *uncommitted = 0;
+            }
+            Err(err) => state.fail(err),
+        }
+        committed
+    };
+
+    loop {
+        let deadline = oldest.zip(policy.max_delay).map(|(at, delay)| at + delay);
+        let message = match deadline {
+            Some(deadline) => {
+                match entries.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                    Ok(message) => Some(message),
+                    Err(RecvTimeoutError::Timeout) => None,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+            None => match entries.recv() {
+                Ok(message) => Some(message),
+                Err(_) => break,
+            },
+        };
+
+        match message {
+            Some(WriteBehindMessage::Append(symbol, request)) => {
+                if let Err(err) = journal.append(symbol, &request) {
+                    state.fail(&err);
+                }
+                uncommitted += 1;
+                oldest.get_or_insert_with(Instant::now);
+                if policy
+                    .max_entries
+                    .is_some_and(|max_entries| uncommitted >= max_entries as u64)
+                {
+                    let _ = commit(&mut journal, &mut uncommitted);
+                    oldest = None;
+                }
+            }
+            Some(WriteBehindMessage::Flush(reply)) => {
+                let _ = reply.send(commit(&mut journal, &mut uncommitted));
+                oldest = None;
+            }
+            // the oldest entry waited long enough
+            None => {
+                let _ = commit(&mut journal, &mut uncommitted);
+                oldest = None;
+            }
+        }
+    }
+
+    if uncommitted > 0 {
+        let _ = commit(&mut journal, &mut uncommitted);
+    }
+    journal
+}
+
+fn stopped() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "journal thread stopped")
+}
+
+/// Store index next to the journal, as JSON
+pub fn write_index<W, Asset>(writer: W, index: &[JournalIndexEntry<Asset>]) -> io::Result<()>
+where
+    W: Write,
+    Asset: Serialize,
+{
+    serde_json::to_writer(writer, index)?;
+    Ok(())
+}
+
+pub fn read_index<R, Asset>(reader: R) -> io::Result<Vec<JournalIndexEntry<Asset>>>
+where
+    R: Read,
+    Asset: DeserializeOwned,
+{
+    Ok(serde_json::from_reader(reader)?)
+}
+
+/// Rebuild the index from the frames of a journal, e.g. after a crash left
+/// no index behind.
+///
+/// A frame cut short at the end, the one being written when the writer
+/// stopped, is left out with everything after it.
+pub fn scan_index<R, Asset>(mut reader: R) -> io::Result<Vec<JournalIndexEntry<Asset>>>
+where
+    R: Read,
+    Asset: Debug + Clone + PartialEq + DeserializeOwned,
+{
+    let mut index: Vec<JournalIndexEntry<Asset>> = vec![];
+    let mut offset = 0u64;
+    loop {
+        let mut len = [0u8; 4];
+        if !read_whole(&mut reader, &mut len)? {
+            break;
+        }
+        let len = u32::from_le_bytes(len) as u64;
+        // read through `take`, a torn length allocates no more than is there
+        let mut compressed = vec![];
+        reader.by_ref().take(len).read_to_end(&mut compressed)?;
+        if (compressed.len() as u64) < len {
+            break;
+        }
+
+        let payload = zstd::decode_all(compressed.as_slice())?;
+        let mut frame: Option<JournalIndexEntry<Asset>> = None;
+        for line in payload.split(|byte| *byte == b'\n') {
+            if line.is_empty() {
+                continue;
+            }
+            let entry: JournalEntry<Asset> = serde_json::from_slice(line)?;
+            match frame.as_mut() {
+                Some(frame) => {
+                    frame.entries += 1;
+                    frame.min_ts = frame.min_ts.min(entry.ts);
+                    frame.max_ts = frame.max_ts.max(entry.ts);
+                    if !frame.symbols.contains(&entry.symbol) {
+                        frame.symbols.push(entry.symbol);
+                    }
+                }
+                None => {
+                    frame = Some(JournalIndexEntry {
+                        offset,
+                        first_seq: entry.seq,
+                        entries: 1,
+                        min_ts: entry.ts,
+                        max_ts: entry.ts,
+                        symbols: vec![entry.symbol],
+                    })
+                }
+            }
+        }
+        index.extend(frame);
+        offset += 4 + len;
+    }
+    Ok(index)
+}
+
+/// Fill `buf`, false if the reader ends first
+fn read_whole<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<bool> {
+    match reader.read_exact(buf) {
+        Ok(()) => Ok(true),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bigdecimal::BigDecimal;
+    use paper_core::guid::domain::OrderSide;
+    use paper_core::guid::engine::MatchingEngine;
+    use paper_core::guid::orderbook::Orderbook;
+    use paper_core::guid::orders;
+    use paper_core::guid::replay::Replayer;
+    use std::io::Cursor;
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    #[derive(PartialEq, Eq, Hash, Debug, Copy, Clone, Serialize, Deserialize)]
+    pub enum Asset {
+        USD,
+        BTC,
+        ETH,
+    }
+
+    fn bigdec(num: &str) -> BigDecimal {
+        BigDecimal::from_str(num).unwrap()
+    }
+
+    #[test]
+    fn filtered_replay() {
+        let btc = (Asset::BTC, Asset::USD);
+        let eth = (Asset::ETH, Asset::USD);
+        let start = SystemTime::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        let writer = Arc::new(Mutex::new(JournalWriter::new(Cursor::new(vec![]), 3)));
+        let mut engine = MatchingEngine::new();
+        engine.add_book(btc.0, btc.1);
+        engine.add_book(eth.0, eth.1);
+        engine.set_journal(writer.clone());
+
+        // BTC in the first half only, ETH in the second
+        let mut btc_ids = vec![];
+        for i in 0..6u64 {
+            let (symbol, side) = match i {
+                0..=2 => (btc, OrderSide::Ask),
+                _ => (eth, OrderSide::Bid),
+            };
+            let request = orders::new_limit_order_request(
+                symbol.0,
+                symbol.1,
+                side,
+                BigDecimal::from(100 + i),
+                bigdec("1"),
+                at(i),
+            );
+            if symbol == btc {
+                btc_ids.push(request.order_id());
+            }
+            engine.submit(symbol, request);
+        }
+        let cancel = orders::limit_order_cancel_request(btc_ids[0], OrderSide::Ask);
+        engine.submit(btc, cancel);
+        drop(engine);
+
+        let writer = Arc::try_unwrap(writer).ok().unwrap().into_inner().unwrap();
+        let (journal, index) = writer.finish().unwrap();
+        assert_eq!(index.len(), 3);
+        assert_eq!(index[0].symbols, vec![btc]);
+        assert_eq!(index[2].seqs(), 7..8);
+        assert_eq!(index[2].min_ts, at(5));
+
+        let mut stored_index = vec![];
+        write_index(&mut stored_index, &index).unwrap();
+        let index: Vec<JournalIndexEntry<Asset>> = read_index(stored_index.as_slice()).unwrap();
+
+        // the frames alone give the same index, less a torn last frame
+        let journal = journal.into_inner();
+        let scanned: Vec<JournalIndexEntry<Asset>> = scan_index(journal.as_slice()).unwrap();
+        assert_eq!(scanned, index);
+        for torn in [1, journal.len() - index[2].offset as usize - 2] {
+            let scanned: Vec<JournalIndexEntry<Asset>> =
+                scan_index(&journal[..journal.len() - torn]).unwrap();
+            assert_eq!(scanned, index[..2]);
+        }
+
+        let read = |filter: ReplayFilter<Asset>| -> Vec<u64> {
+            JournalReader::new(Cursor::new(journal.clone()), index.clone(), filter)
+                .map(|entry| entry.unwrap().seq)
+                .collect()
+        };
+        assert_eq!(read(ReplayFilter::default()), (1..8).collect::<Vec<u64>>());
+
+        let only_btc = ReplayFilter {
+            symbol: Some(btc),
+            ..ReplayFilter::default()
+        };
+        assert!(!only_btc.may_match(&index[1]));
+        assert_eq!(read(only_btc.clone()), vec![1, 2, 3, 7]);
+
+        let window = ReplayFilter {
+            from: Some(at(2)),
+            until: Some(at(4)),
+            ..ReplayFilter::default()
+        };
+        assert_eq!(read(window), vec![3, 4]);
+        let seqs = ReplayFilter {
+            seqs: Some(4..6),
+            ..ReplayFilter::default()
+        };
+        assert!(!seqs.may_match(&index[0]));
+        assert_eq!(read(seqs), vec![4, 5]);
+
+        // a single market rebuilt from the journal
+        let requests = JournalReader::new(Cursor::new(journal.clone()), index, only_btc)
+            .requests()
+            .map(Result::unwrap);
+        let mut replayer = Replayer::new(Orderbook::new(btc.0, btc.1), requests);
+        replayer.run();
+        let book = replayer.into_book();
+        assert_eq!(book.ask_queue.len(), 2);
+        assert_eq!(book.bid_queue.len(), 0);
+    }
+
+    /// Entries committed once `committed` are or a few seconds passed
+    fn wait_for<J>(journal: &WriteBehindJournal<J, Asset>, committed: u64) -> u64
+    where
+        J: Journal<Asset> + Send + 'static,
+    {
+        let start = Instant::now();
+        while journal.committed() < committed && start.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(1));
+        }
+        journal.committed()
+    }
+
+    #[test]
+    fn group_commit() {
+        let btc = (Asset::BTC, Asset::USD);
+        let limit = |price: u64| {
+            orders::new_limit_order_request(
+                btc.0,
+                btc.1,
+                OrderSide::Ask,
+                BigDecimal::from(price),
+                bigdec("1"),
+                SystemTime::now(),
+            )
+        };
+        // by count, the third entry waits for an explicit flush
+        let policy = GroupCommit {
+            capacity: 16,
+            max_entries: Some(2),
+            max_delay: None,
+        };
+        let journal = Arc::new(Mutex::new(WriteBehindJournal::new(
+            JournalWriter::new(Cursor::new(vec![]), 10),
+            policy,
+        )));
+        let mut engine = MatchingEngine::new();
+        engine.add_book(btc.0, btc.1);
+        engine.set_journal(journal.clone());
+        for price in 100..103 {
+            assert!(engine.submit(btc, limit(price))[0].is_ok());
+        }
+        assert_eq!(wait_for(&journal.lock().unwrap(), 2), 2);
+        journal.lock().unwrap().flush().unwrap();
+        assert_eq!(journal.lock().unwrap().committed(), 3);
+        drop(engine);
+
+        let journal = Arc::try_unwrap(journal).ok().unwrap().into_inner().unwrap();
+        let (_, index) = journal.finish().unwrap().finish().unwrap();
+        let entries: u32 = index.iter().map(|frame| frame.entries).sum();
+        assert_eq!(entries, 3);
+
+        // by time
+        let policy = GroupCommit {
+            capacity: 16,
+            max_entries: None,
+            max_delay: Some(Duration::from_millis(5)),
+        };
+        let mut journal = WriteBehindJournal::new(vec![], policy);
+        journal.append(btc, &limit(100)).unwrap();
+        assert_eq!(wait_for(&journal, 1), 1);
+        assert_eq!(journal.finish().unwrap().len(), 1);
+    }
+}
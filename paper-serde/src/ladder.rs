@@ -0,0 +1,324 @@
+use bigdecimal::BigDecimal;
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+
+use paper_core::guid::domain::OrderSide;
+use paper_core::guid::orderbook::{DepthLevel, Orderbook};
+
+/// Change to one row of a ladder, rows ranked from 0 at the touch
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum LadderRow {
+    /// new price in the view, rows from `rank` on move one down
+    Add {
+        side: OrderSide,
+        rank: usize,
+        price: BigDecimal,
+        qty: BigDecimal,
+        orders: usize,
+    },
+    /// quantity or order count of the price at `rank` changed
+    Update {
+        side: OrderSide,
+        rank: usize,
+        price: BigDecimal,
+        qty: BigDecimal,
+        orders: usize,
+    },
+    /// price left the view, rows below `rank` move one up
+    Remove {
+        side: OrderSide,
+        rank: usize,
+        price: BigDecimal,
+    },
+}
+
+/// Rows of a top-N ladder that changed since the previous message.
+///
+/// Applied in order, removals come first from the bottom up, then additions
+/// and updates from the top down, so every rank is right at the time its
+/// row is applied.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LadderMessage {
+    /// consecutive from 1, a snapshot repeats the number of the last update
+    pub seq: u64,
+    #[serde(default)]
+    pub snapshot: bool,
+    pub rows: Vec<LadderRow>,
+}
+
+/// Best `depth` price levels of a book, sent as row diffs for UI clients
+/// that only draw the top of the book.
+///
+/// Changes below the view, and requests that leave the view as it was,
+/// produce no message at all.
+#[derive(Debug)]
+pub struct LadderPublisher {
+    depth: usize,
+    bids: Vec<DepthLevel>,
+    asks: Vec<DepthLevel>,
+    seq: u64,
+}
+
+impl LadderPublisher {
+    pub fn new(depth: usize) -> Self {
+        LadderPublisher {
+            depth,
+            bids: vec![],
+            asks: vec![],
+            seq: 0,
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Rows that changed since the last call, None if the view is the same
+    pub fn publish<Asset>(&mut self, book: &Orderbook<Asset>) -> Option<LadderMessage>
+    where
+        Asset: Debug + Clone + Copy + Eq,
+    {
+        let depth = book.depth(self.depth);
+        let mut rows = diff(OrderSide::Bid, &self.bids, &depth.bids);
+        rows.extend(diff(OrderSide::Ask, &self.asks, &depth.asks));
+        self.bids = depth.bids;
+        self.asks = depth.asks;
+        if rows.is_empty() {
+            return None;
+        }
+
+        self.seq += 1;
+        Some(LadderMessage {
+            seq: self.seq,
+            snapshot: false,
+            rows,
+        })
+    }
+
+    /// Whole view as of the last `publish`, every row added
+    pub fn snapshot(&self) -> LadderMessage {
+        let mut rows = diff(OrderSide::Bid, &[], &self.bids);
+        rows.extend(diff(OrderSide::Ask, &[], &self.asks));
+        LadderMessage {
+            seq: self.seq,
+            snapshot: true,
+            rows,
+        }
+    }
+}
+
+/// Top-N view of a book kept by a client from ladder messages
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Ladder {
+    bids: Vec<DepthLevel>,
+    asks: Vec<DepthLevel>,
+    seq: u64,
+}
+
+impl Ladder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Apply a message, rows out of range are ignored
+    pub fn apply(&mut self, message: &LadderMessage) {
+        if message.snapshot {
+            self.bids.clear();
+            self.asks.clear();
+        }
+        for row in &message.rows {
+            match row {
+                LadderRow::Add {
+                    side,
+                    rank,
+                    price,
+                    qty,
+                    orders,
+                } => {
+                    let rows = self.side_mut(*side);
+                    if *rank <= rows.len() {
+                        rows.insert(*rank, level(price, qty, *orders));
+                    }
+                }
+                LadderRow::Update {
+                    side,
+                    rank,
+                    price,
+                    qty,
+                    orders,
+                } => {
+                    if let Some(row) = self.side_mut(*side).get_mut(*rank) {
+                        *row = level(price, qty, *orders);
+                    }
+                }
+                LadderRow::Remove { side, rank, .. } => {
+                    let rows = self.side_mut(*side);
+                    if *rank < rows.len() {
+                        rows.remove(*rank);
+                    }
+                }
+            }
+        }
+        self.seq = message.seq;
+    }
+
+    /// Rows of the side, best first
+    pub fn side(&self, side: OrderSide) -> &[DepthLevel] {
+        match side {
+            OrderSide::Bid => &self.bids,
+            OrderSide::Ask => &self.asks,
+        }
+    }
+
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    fn side_mut(&mut self, side: OrderSide) -> &mut Vec<DepthLevel> {
+        match side {
+            OrderSide::Bid => &mut self.bids,
+            OrderSide::Ask => &mut self.asks,
+        }
+    }
+}
+
+/* Helpers */
+
+/// Rows turning `old` into `new`, both best first
+fn diff(side: OrderSide, old: &[DepthLevel], new: &[DepthLevel]) -> Vec<LadderRow> {
+    let mut rows: Vec<LadderRow> = old
+        .iter()
+        .enumerate()
+        .rev()
+        .filter(|(_, level)| !new.iter().any(|kept| kept.price == level.price))
+        .map(|(rank, level)| LadderRow::Remove {
+            side,
+            rank,
+            price: level.price.clone(),
+        })
+        .collect();
+
+    for (rank, level) in new.iter().enumerate() {
+        let price = level.price.clone();
+        let (qty, orders) = (level.qty.clone(), level.orders);
+        match old.iter().find(|before| before.price == level.price) {
+            None => rows.push(LadderRow::Add {
+                side,
+                rank,
+                price,
+                qty,
+                orders,
+            }),
+            Some(before) if before != level => rows.push(LadderRow::Update {
+                side,
+                rank,
+                price,
+                qty,
+                orders,
+            }),
+            Some(_) => {}
+        }
+    }
+    rows
+}
+
+fn level(price: &BigDecimal, qty: &BigDecimal, orders: usize) -> DepthLevel {
+    DepthLevel {
+        price: price.clone(),
+        qty: qty.clone(),
+        orders,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use paper_core::guid::orders;
+    use std::str::FromStr;
+    use std::time::SystemTime;
+
+    #[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
+    pub enum Asset {
+        USD,
+        BTC,
+    }
+
+    fn bigdec(num: &str) -> BigDecimal {
+        BigDecimal::from_str(num).unwrap()
+    }
+
+    fn limit(side: OrderSide, price: &str, qty: &str) -> orders::OrderRequest<Asset> {
+        orders::new_limit_order_request(
+            Asset::BTC,
+            Asset::USD,
+            side,
+            bigdec(price),
+            bigdec(qty),
+            SystemTime::now(),
+        )
+    }
+
+    #[test]
+    fn top_levels_as_row_diffs() {
+        let mut book = Orderbook::new(Asset::BTC, Asset::USD);
+        let mut publisher = LadderPublisher::new(2);
+        let mut ladder = Ladder::new();
+        assert!(publisher.publish(&book).is_none());
+
+        for price in ["100", "99", "98"] {
+            book.process_order(limit(OrderSide::Bid, price, "1"));
+        }
+        let message = publisher.publish(&book).unwrap();
+        assert_eq!(message.seq, 1);
+        // 98 is below the view
+        assert_eq!(message.rows.len(), 2);
+        ladder.apply(&message);
+
+        // below the view, nothing to send
+        book.process_order(limit(OrderSide::Bid, "97", "1"));
+        assert!(publisher.publish(&book).is_none());
+
+        // more at 99 and a new best bid pushing 99 down and 100 to the bottom
+        book.process_order(limit(OrderSide::Bid, "99", "2"));
+        book.process_order(limit(OrderSide::Bid, "101", "1"));
+        let message = publisher.publish(&book).unwrap();
+        assert_eq!(
+            message.rows,
+            vec![
+                LadderRow::Remove {
+                    side: OrderSide::Bid,
+                    rank: 1,
+                    price: bigdec("99"),
+                },
+                LadderRow::Add {
+                    side: OrderSide::Bid,
+                    rank: 0,
+                    price: bigdec("101"),
+                    qty: bigdec("1"),
+                    orders: 1,
+                },
+            ]
+        );
+        ladder.apply(&message);
+
+        // sell into the bids, taking 101 and part of 100
+        book.process_order(limit(OrderSide::Ask, "100", "1.5"));
+        let message = publisher.publish(&book).unwrap();
+        assert_eq!(message.seq, 3);
+        ladder.apply(&message);
+        let expected = book.depth(2);
+        assert_eq!(ladder.side(OrderSide::Bid), expected.bids.as_slice());
+        assert_eq!(ladder.side(OrderSide::Ask), expected.asks.as_slice());
+
+        // a late joiner starts from the snapshot
+        let mut late = Ladder::new();
+        late.apply(&publisher.snapshot());
+        assert_eq!(late, ladder);
+
+        let json = serde_json::to_string(&message).unwrap();
+        assert!(json.contains(r#""op":"update""#));
+        let decoded: LadderMessage = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, message);
+    }
+}
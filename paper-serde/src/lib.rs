@@ -0,0 +1,18 @@
+//! Wire and storage encodings of matching engine events.
+//!
+//! Kept apart from `paper-core` so embedders of the matching engine do not
+//! pull in JSON and compression dependencies.
+
+// assets are spelled as their tickers
+#![allow(clippy::upper_case_acronyms)]
+
+pub mod capture;
+pub mod consolidated;
+pub mod flow;
+pub mod itch;
+pub mod journal;
+pub mod ladder;
+pub mod lobster;
+pub mod recovery;
+pub mod replica;
+pub mod snapshot;
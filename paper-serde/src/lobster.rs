@@ -0,0 +1,448 @@
+use bigdecimal::{BigDecimal, Signed};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Debug;
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+use paper_core::guid::domain::OrderSide;
+use paper_core::guid::orderbook::{Failed, OrderProcessingResult, Orderbook, Success};
+use paper_core::guid::orders::OrderRequest;
+
+/// Prices are written in units of 1/10000, like the original LOBSTER data
+pub const DEFAULT_PRICE_SCALE: u64 = 10_000;
+
+/// Placeholder prices of missing levels in the orderbook file
+const EMPTY_ASK_PRICE: i64 = 9_999_999_999;
+const EMPTY_BID_PRICE: i64 = -9_999_999_999;
+
+const SECS_PER_DAY: u64 = 86_400;
+
+/// Event types of the LOBSTER message file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageType {
+    Submission = 1,
+    PartialCancellation = 2,
+    Deletion = 3,
+    Execution = 4,
+}
+
+#[derive(Debug, Clone)]
+struct RestingOrder {
+    number: u64,
+    side: OrderSide,
+    price: BigDecimal,
+    qty: BigDecimal,
+}
+
+/// Order entering the book with the request being converted
+#[derive(Debug, Clone)]
+struct Incoming {
+    order_id: Uuid,
+    side: OrderSide,
+    /// None for market orders, which never rest
+    price: Option<BigDecimal>,
+    qty: BigDecimal,
+    ts: SystemTime,
+    rests: bool,
+}
+
+/// Converts the event tape of a book into LOBSTER message and orderbook CSVs.
+///
+/// Every message row gets an orderbook row with the `levels` best levels
+/// after it, as ask price, ask size, bid price, bid size per level. Times are
+/// seconds after midnight UTC, prices integers scaled by `price_scale`.
+/// Order UUIDs are numbered from 1 in order of appearance. Sizes are written
+/// as decimals, so fractional quantities survive.
+///
+/// Aggressive orders appear as executions of the resting orders they hit,
+/// followed by the submission of whatever rests, as in the original data.
+/// Amends reducing size in place are partial cancellations, other amends a
+/// deletion and a new submission.
+pub struct LobsterWriter<M: Write, B: Write> {
+    messages: M,
+    orderbook: B,
+    levels: usize,
+    price_scale: BigDecimal,
+    numbers: HashMap<Uuid, u64>,
+    resting: HashMap<Uuid, RestingOrder>,
+    bids: BTreeMap<BigDecimal, BigDecimal>,
+    asks: BTreeMap<BigDecimal, BigDecimal>,
+}
+
+impl<M: Write, B: Write> LobsterWriter<M, B> {
+    pub fn new(messages: M, orderbook: B, levels: usize) -> Self {
+        LobsterWriter {
+            messages,
+            orderbook,
+            levels,
+            price_scale: BigDecimal::from(DEFAULT_PRICE_SCALE),
+            numbers: HashMap::new(),
+            resting: HashMap::new(),
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_price_scale(mut self, scale: u64) -> Self {
+        self.price_scale = BigDecimal::from(scale);
+        self
+    }
+
+    /// Number the order got in the output
+    pub fn order_number(&self, order_id: Uuid) -> Option<u64> {
+        self.numbers.get(&order_id).copied()
+    }
+
+    /// Write the messages for the results of a single request
+    pub fn convert<Asset>(&mut self, results: &OrderProcessingResult<Asset>) -> io::Result<()> {
+        let mut incoming: Option<Incoming> = None;
+
+        for result in results {
+            match result {
+                Ok(Success::Accepted {
+                    order_id,
+                    side,
+                    price,
+                    qty,
+                    ts,
+                    ..
+                }) => {
+                    incoming = Some(Incoming {
+                        order_id: *order_id,
+                        side: *side,
+                        price: price.clone(),
+                        qty: qty.clone(),
+                        ts: *ts,
+                        rests: price.is_some(),
+                    })
+                }
+                Ok(Success::Filled {
+                    order_id, qty, ts, ..
+                })
+                | Ok(Success::PartiallyFilled {
+                    order_id, qty, ts, ..
+                }) => match &mut incoming {
+                    Some(taker) if taker.order_id == *order_id => taker.qty -= qty,
+                    _ => self.reduce(*order_id, qty, MessageType::Execution, *ts)?,
+                },
+                Ok(Success::Amended {
+                    order_id,
+                    price,
+                    qty,
+                    priority_kept,
+                    ts,
+                    ..
+                }) => {
+                    let resting = match self.resting.get(order_id) {
+                        Some(resting) => resting.clone(),
+                        None => continue,
+                    };
+                    if *priority_kept && *price == resting.price {
+                        let reduced = &resting.qty - qty;
+                        if reduced.is_positive() {
+                            self.reduce(
+                                *order_id,
+                                &reduced,
+                                MessageType::PartialCancellation,
+                                *ts,
+                            )?;
+                        }
+                    } else {
+                        self.remove(*order_id, *ts)?;
+                        incoming = Some(Incoming {
+                            order_id: *order_id,
+                            side: resting.side,
+                            price: Some(price.clone()),
+                            qty: qty.clone(),
+                            ts: *ts,
+                            rests: true,
+                        });
+                    }
+                }
+                Ok(Success::Cancelled { order_id, ts })
+                | Ok(Success::Expired { order_id, ts })
+                | Ok(Success::DustCancelled { order_id, ts, .. }) => match &mut incoming {
+                    Some(taker) if taker.order_id == *order_id => taker.rests = false,
+                    _ => self.remove(*order_id, *ts)?,
+                },
+                Ok(Success::SelfTradePrevented {
+                    order_id,
+                    qty,
+                    remaining,
+                    ts,
+                    ..
+                }) => match &mut incoming {
+                    Some(taker) if taker.order_id == *order_id => {
+                        taker.qty -= qty;
+                        taker.rests = taker.rests && remaining.is_positive();
+                    }
+                    _ if remaining.is_positive() => {
+                        self.reduce(*order_id, qty, MessageType::PartialCancellation, *ts)?
+                    }
+                    _ => self.remove(*order_id, *ts)?,
+                },
+                Err(Failed::DepthLimitExceeded(order_id)) => {
+                    if let Some(taker) = &mut incoming {
+                        if taker.order_id == *order_id {
+                            taker.rests = false;
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        match incoming {
+            Some(Incoming {
+                order_id,
+                side,
+                price: Some(price),
+                qty,
+                ts,
+                rests: true,
+            }) if qty.is_positive() => self.submit(order_id, side, price, qty, ts),
+            _ => Ok(()),
+        }
+    }
+
+    /// Flush and return the message and orderbook writers
+    pub fn finish(mut self) -> io::Result<(M, B)> {
+        self.messages.flush()?;
+        self.orderbook.flush()?;
+        Ok((self.messages, self.orderbook))
+    }
+
+    /* Helpers */
+
+    fn submit(
+        &mut self,
+        order_id: Uuid,
+        side: OrderSide,
+        price: BigDecimal,
+        qty: BigDecimal,
+        ts: SystemTime,
+    ) -> io::Result<()> {
+        let next = self.numbers.len() as u64 + 1;
+        let number = *self.numbers.entry(order_id).or_insert(next);
+        *self.levels_mut(side).entry(price.clone()).or_default() += &qty;
+        let order = RestingOrder {
+            number,
+            side,
+            price,
+            qty: qty.clone(),
+        };
+        self.write(MessageType::Submission, &order, &qty, ts)?;
+        self.resting.insert(order_id, order);
+        Ok(())
+    }
+
+    fn reduce(
+        &mut self,
+        order_id: Uuid,
+        qty: &BigDecimal,
+        kind: MessageType,
+        ts: SystemTime,
+    ) -> io::Result<()> {
+        let order = match self.resting.get_mut(&order_id) {
+            Some(order) => {
+                order.qty -= qty;
+                order.clone()
+            }
+            None => return Ok(()),
+        };
+        if !order.qty.is_positive() {
+            self.resting.remove(&order_id);
+        }
+        self.take_from_level(order.side, &order.price, qty);
+        self.write(kind, &order, qty, ts)
+    }
+
+    fn remove(&mut self, order_id: Uuid, ts: SystemTime) -> io::Result<()> {
+        let order = match self.resting.remove(&order_id) {
+            Some(order) => order,
+            None => return Ok(()),
+        };
+        self.take_from_level(order.side, &order.price, &order.qty);
+        self.write(MessageType::Deletion, &order, &order.qty, ts)
+    }
+
+    fn take_from_level(&mut self, side: OrderSide, price: &BigDecimal, qty: &BigDecimal) {
+        let levels = self.levels_mut(side);
+        if let Some(level) = levels.get_mut(price) {
+            *level -= qty;
+            if !level.is_positive() {
+                levels.remove(price);
+            }
+        }
+    }
+
+    fn levels_mut(&mut self, side: OrderSide) -> &mut BTreeMap<BigDecimal, BigDecimal> {
+        match side {
+            OrderSide::Bid => &mut self.bids,
+            OrderSide::Ask => &mut self.asks,
+        }
+    }
+
+    fn write(
+        &mut self,
+        kind: MessageType,
+        order: &RestingOrder,
+        qty: &BigDecimal,
+        ts: SystemTime,
+    ) -> io::Result<()> {
+        let direction = match order.side {
+            OrderSide::Bid => 1,
+            OrderSide::Ask => -1,
+        };
+        writeln!(
+            self.messages,
+            "{},{},{},{},{},{}",
+            seconds_after_midnight(ts),
+            kind as u8,
+            order.number,
+            qty.normalized(),
+            self.scaled(&order.price),
+            direction
+        )?;
+
+        let mut row = vec![];
+        let mut asks = self.asks.iter();
+        let mut bids = self.bids.iter().rev();
+        for _ in 0..self.levels {
+            match asks.next() {
+                Some((price, qty)) => {
+                    row.push(self.scaled(price));
+                    row.push(qty.normalized().to_string());
+                }
+                None => row.extend([EMPTY_ASK_PRICE.to_string(), String::from("0")]),
+            }
+            match bids.next() {
+                Some((price, qty)) => {
+                    row.push(self.scaled(price));
+                    row.push(qty.normalized().to_string());
+                }
+                None => row.extend([EMPTY_BID_PRICE.to_string(), String::from("0")]),
+            }
+        }
+        writeln!(self.orderbook, "{}", row.join(","))
+    }
+
+    fn scaled(&self, price: &BigDecimal) -> String {
+        (price * &self.price_scale).with_scale(0).to_string()
+    }
+}
+
+/// Replay journaled requests into `book` and convert what it reports
+pub fn convert_journal<Asset, I, M, B>(
+    book: &mut Orderbook<Asset>,
+    journal: I,
+    writer: &mut LobsterWriter<M, B>,
+) -> io::Result<()>
+where
+    Asset: Debug + Clone + Copy + Eq,
+    I: IntoIterator<Item = OrderRequest<Asset>>,
+    M: Write,
+    B: Write,
+{
+    for request in journal {
+        let results = book.process_order(request);
+        writer.convert(&results)?;
+    }
+    Ok(())
+}
+
+fn seconds_after_midnight(ts: SystemTime) -> String {
+    let since_epoch = ts.duration_since(UNIX_EPOCH).unwrap_or_default();
+    format!(
+        "{}.{:09}",
+        since_epoch.as_secs() % SECS_PER_DAY,
+        since_epoch.subsec_nanos()
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use paper_core::guid::clock::ManualClock;
+    use paper_core::guid::orders;
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    #[derive(PartialEq, Eq, Debug, Copy, Clone)]
+    pub enum Asset {
+        USD,
+        BTC,
+    }
+
+    fn bigdec(num: &str) -> BigDecimal {
+        BigDecimal::from_str(num).unwrap()
+    }
+
+    #[test]
+    fn messages_and_orderbook() {
+        // 09:30 on some day
+        let start = UNIX_EPOCH + Duration::from_secs(19_000 * SECS_PER_DAY + 34_200);
+        let limit = |side, price: &str, qty: &str| {
+            orders::new_limit_order_request(
+                Asset::BTC,
+                Asset::USD,
+                side,
+                bigdec(price),
+                bigdec(qty),
+                start,
+            )
+        };
+        let ask = limit(OrderSide::Ask, "101.5", "2");
+        let ask_id = ask.order_id();
+        let journal = vec![
+            ask,
+            limit(OrderSide::Bid, "100", "1"),
+            limit(OrderSide::Bid, "102", "1.5"),
+            orders::amend_order_qty_request(ask_id, OrderSide::Ask, bigdec("1.75"), start),
+            // takes the rest of the ask and rests the remainder
+            limit(OrderSide::Bid, "102", "1"),
+        ];
+
+        let mut book = Orderbook::new(Asset::BTC, Asset::USD);
+        book.set_clock(Box::new(ManualClock::new(start)));
+        let mut writer = LobsterWriter::new(vec![], vec![], 2);
+        convert_journal(&mut book, journal, &mut writer).unwrap();
+        let (messages, orderbook) = writer.finish().unwrap();
+
+        let messages: Vec<String> = String::from_utf8(messages)
+            .unwrap()
+            .lines()
+            .map(|line| {
+                // events are a few nanoseconds apart
+                let (time, rest) = line.split_once(',').unwrap();
+                assert!(time.starts_with("34200.0000000"));
+                rest.to_string()
+            })
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "1,1,2,1015000,-1",
+                "1,2,1,1000000,1",
+                "4,1,1.5,1015000,-1",
+                "2,1,0.25,1015000,-1",
+                "4,1,0.25,1015000,-1",
+                "1,3,0.75,1020000,1",
+            ]
+        );
+
+        let orderbook = String::from_utf8(orderbook).unwrap();
+        assert_eq!(
+            orderbook.lines().collect::<Vec<_>>(),
+            vec![
+                "1015000,2,-9999999999,0,9999999999,0,-9999999999,0",
+                "1015000,2,1000000,1,9999999999,0,-9999999999,0",
+                "1015000,0.5,1000000,1,9999999999,0,-9999999999,0",
+                "1015000,0.25,1000000,1,9999999999,0,-9999999999,0",
+                "9999999999,0,1000000,1,9999999999,0,-9999999999,0",
+                "9999999999,0,1020000,0.75,9999999999,0,1000000,1",
+            ]
+        );
+    }
+}
@@ -0,0 +1,424 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use paper_core::guid::engine::Symbol;
+use paper_core::guid::orderbook::Orderbook;
+
+use crate::journal::{scan_index, JournalIndexEntry, JournalReader, ReplayFilter};
+use crate::replica::crc32_update;
+
+const EXTENSION: &str = "checkpoint";
+
+/// Whole book as of a journal sequence number
+pub struct Checkpoint<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    /// last journal entry the book reflects, 0 before the first
+    pub seq: u64,
+    /// `book_checksum` of the book when it was taken
+    pub checksum: u32,
+    pub book: Orderbook<Asset>,
+}
+
+#[derive(Serialize)]
+#[serde(bound = "Asset: Debug + Clone + Copy + Eq + Serialize")]
+struct StoredRef<'a, Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    seq: u64,
+    checksum: u32,
+    book: &'a Orderbook<Asset>,
+}
+
+#[derive(Deserialize)]
+#[serde(bound = "Asset: Debug + Clone + Copy + Eq + DeserializeOwned")]
+struct Stored<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    seq: u64,
+    checksum: u32,
+    book: Orderbook<Asset>,
+}
+
+/// Checkpoints of one book, a file per journal sequence number in a
+/// directory of their own.
+///
+/// Files are written under a temporary name and renamed once complete, so a
+/// crash while saving leaves the earlier checkpoints as they were.
+#[derive(Debug, Clone)]
+pub struct CheckpointStore {
+    dir: PathBuf,
+}
+
+impl CheckpointStore {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        CheckpointStore {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Store the book as reflecting the journal up to `seq`, e.g.
+    /// `JournalWriter::seq` after the last request it processed
+    pub fn save<Asset>(&self, book: &Orderbook<Asset>, seq: u64) -> io::Result<PathBuf>
+    where
+        Asset: Debug + Clone + Copy + Eq + Serialize,
+    {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(format!("{:020}.{}", seq, EXTENSION));
+        let partial = path.with_extension("partial");
+
+        let mut writer = BufWriter::new(File::create(&partial)?);
+        let stored = StoredRef {
+            seq,
+            checksum: book_checksum(book),
+            book,
+        };
+        serde_json::to_writer(&mut writer, &stored)?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        drop(writer);
+
+        fs::rename(&partial, &path)?;
+        Ok(path)
+    }
+
+    /// Sequence numbers of the stored checkpoints, oldest first
+    pub fn seqs(&self) -> io::Result<Vec<u64>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err),
+        };
+        let mut seqs = vec![];
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(EXTENSION) {
+                continue;
+            }
+            if let Some(seq) = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse().ok())
+            {
+                seqs.push(seq);
+            }
+        }
+        seqs.sort_unstable();
+        Ok(seqs)
+    }
+
+    /// Checkpoint of the sequence number, failing unless the book read back
+    /// has the checksum it was stored with
+    pub fn load<Asset>(&self, seq: u64) -> io::Result<Checkpoint<Asset>>
+    where
+        Asset: Debug + Clone + Copy + Eq + DeserializeOwned,
+    {
+        let path = self.dir.join(format!("{:020}.{}", seq, EXTENSION));
+        let stored: Stored<Asset> = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        let actual = book_checksum(&stored.book);
+        if stored.seq != seq || actual != stored.checksum {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "checkpoint {} has checksum {:08x}, book has {:08x}",
+                    seq, stored.checksum, actual
+                ),
+            ));
+        }
+        Ok(Checkpoint {
+            seq: stored.seq,
+            checksum: stored.checksum,
+            book: stored.book,
+        })
+    }
+
+    /// Newest checkpoint that loads, older ones are tried when a newer one
+    /// is damaged. None without any usable checkpoint.
+    pub fn latest<Asset>(&self) -> io::Result<Option<Checkpoint<Asset>>>
+    where
+        Asset: Debug + Clone + Copy + Eq + DeserializeOwned,
+    {
+        for seq in self.seqs()?.into_iter().rev() {
+            if let Ok(checkpoint) = self.load(seq) {
+                return Ok(Some(checkpoint));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Remove checkpoints older than the newest `keep`
+    pub fn prune(&self, keep: usize) -> io::Result<usize> {
+        let seqs = self.seqs()?;
+        let stale = seqs.len().saturating_sub(keep);
+        for seq in &seqs[..stale] {
+            fs::remove_file(self.dir.join(format!("{:020}.{}", seq, EXTENSION)))?;
+        }
+        Ok(stale)
+    }
+}
+
+/// Book rebuilt by `Recovery`
+pub struct Recovered<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    pub book: Orderbook<Asset>,
+    /// checkpoint started from, None for an empty book
+    pub checkpoint: Option<u64>,
+    /// last journal entry applied
+    pub seq: u64,
+    /// journal entries replayed after the checkpoint
+    pub replayed: usize,
+    pub checksum: u32,
+}
+
+/// Crash recovery of one book: its latest checkpoint, then the journal
+/// entries of its market after the checkpoint.
+///
+/// Checkpoints hold the whole book, settings and queue priorities included,
+/// and the tail is replayed with `Orderbook::replay`, so the recovered book
+/// is the one journaled. Without a checkpoint the whole journal is replayed
+/// into a book with default settings.
+///
+/// A journal without its index, as a crash leaves it, goes through
+/// `run_scanned`.
+pub struct Recovery<Asset> {
+    symbol: Symbol<Asset>,
+    store: CheckpointStore,
+    expected: Option<u32>,
+}
+
+impl<Asset> Recovery<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq + PartialEq + DeserializeOwned,
+{
+    pub fn new(symbol: Symbol<Asset>, store: CheckpointStore) -> Self {
+        Recovery {
+            symbol,
+            store,
+            expected: None,
+        }
+    }
+
+    /// Fail unless the recovered book has the checksum, e.g. one logged at
+    /// the last request before the crash
+    pub fn expect_checksum(mut self, checksum: u32) -> Self {
+        self.expected = Some(checksum);
+        self
+    }
+
+    /// Recover from a journal left without its index, as after a crash: the
+    /// index is rebuilt with `scan_index`, a torn last frame is not replayed
+    pub fn run_scanned<R>(&self, mut journal: R) -> io::Result<Recovered<Asset>>
+    where
+        R: Read + Seek,
+    {
+        journal.seek(SeekFrom::Start(0))?;
+        let index = scan_index(&mut journal)?;
+        self.run(journal, index)
+    }
+
+    pub fn run<R>(
+        &self,
+        journal: R,
+        index: Vec<JournalIndexEntry<Asset>>,
+    ) -> io::Result<Recovered<Asset>>
+    where
+        R: Read + Seek,
+    {
+        let (mut book, checkpoint) = match self.store.latest()? {
+            Some(Checkpoint { seq, book, .. }) => (book, Some(seq)),
+            None => (Orderbook::new(self.symbol.0, self.symbol.1), None),
+        };
+        let from = checkpoint.unwrap_or(0);
+
+        let filter = ReplayFilter {
+            symbol: Some(self.symbol),
+            seqs: Some(from + 1..u64::MAX),
+            ..ReplayFilter::default()
+        };
+        let mut seq = from;
+        let mut tail = vec![];
+        for entry in JournalReader::new(journal, index, filter) {
+            let entry = entry?;
+            seq = entry.seq;
+            tail.push(entry.request);
+        }
+        let replayed = book.replay(tail);
+
+        let checksum = book_checksum(&book);
+        if let Some(expected) = self.expected {
+            if checksum != expected {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "recovered book has checksum {:08x}, expected {:08x}",
+                        checksum, expected
+                    ),
+                ));
+            }
+        }
+        Ok(Recovered {
+            book,
+            checkpoint,
+            seq,
+            replayed,
+            checksum,
+        })
+    }
+}
+
+/// CRC-32 of every resting order in queue priority, bids then asks.
+///
+/// Orders are written as `id:price:qty` with decimals normalized, each
+/// followed by a newline, then the number of stop orders, the change
+/// sequence and the last trade price.
+pub fn book_checksum<Asset>(book: &Orderbook<Asset>) -> u32
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    let mut crc = !0u32;
+    for queue in [&book.bid_queue, &book.ask_queue] {
+        for (order, _, _) in queue.ranked() {
+            let line = format!(
+                "{}:{}:{}\n",
+                order.order_id,
+                order.price.normalized(),
+                order.qty.normalized()
+            );
+            crc = crc32_update(crc, line.as_bytes());
+        }
+    }
+    let trailer = format!(
+        "{}:{}:{}\n",
+        book.stop_count(),
+        book.change_seq(),
+        book.last_trade_price()
+            .map(|price| price.normalized().to_string())
+            .unwrap_or_default()
+    );
+    !crc32_update(crc, trailer.as_bytes())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::journal::JournalWriter;
+    use bigdecimal::BigDecimal;
+    use paper_core::guid::domain::OrderSide;
+    use paper_core::guid::orders;
+    use std::io::Cursor;
+    use std::str::FromStr;
+    use std::sync::{Arc, Mutex};
+    use std::time::SystemTime;
+
+    #[derive(PartialEq, Eq, Hash, Debug, Copy, Clone, Serialize, Deserialize)]
+    pub enum Asset {
+        USD,
+        BTC,
+    }
+
+    fn bigdec(num: &str) -> BigDecimal {
+        BigDecimal::from_str(num).unwrap()
+    }
+
+    fn limit(side: OrderSide, price: &str, qty: &str) -> orders::OrderRequest<Asset> {
+        orders::new_limit_order_request(
+            Asset::BTC,
+            Asset::USD,
+            side,
+            bigdec(price),
+            bigdec(qty),
+            SystemTime::now(),
+        )
+    }
+
+    #[test]
+    fn checkpoint_and_journal_tail() {
+        let btc = (Asset::BTC, Asset::USD);
+        let dir = tempfile::tempdir().unwrap();
+        let store = CheckpointStore::new(dir.path());
+        let writer = Arc::new(Mutex::new(JournalWriter::new(Cursor::new(vec![]), 2)));
+        let mut book = Orderbook::new(btc.0, btc.1);
+        book.set_journal(writer.clone());
+
+        for (price, qty) in [("101", "1"), ("101", "2"), ("102", "1")] {
+            book.process_order(limit(OrderSide::Ask, price, qty));
+        }
+        store.save(&book, writer.lock().unwrap().seq()).unwrap();
+        book.process_order(limit(OrderSide::Bid, "101", "1.5"));
+        book.process_order(limit(OrderSide::Bid, "99", "1"));
+        store.save(&book, writer.lock().unwrap().seq()).unwrap();
+        book.process_order(limit(OrderSide::Ask, "100", "2"));
+        book.process_order(limit(OrderSide::Bid, "98", "1"));
+        let expected = book_checksum(&book);
+
+        // the newest checkpoint is damaged, recovery falls back to the first
+        assert_eq!(store.seqs().unwrap(), vec![3, 5]);
+        fs::write(dir.path().join(format!("{:020}.checkpoint", 5)), b"{").unwrap();
+
+        drop(book);
+        let writer = Arc::try_unwrap(writer).ok().unwrap().into_inner().unwrap();
+        let (journal, index) = writer.finish().unwrap();
+        let recovery = Recovery::new(btc, store.clone()).expect_checksum(expected);
+        let recovered = recovery.run(journal.clone(), index.clone()).unwrap();
+        assert_eq!(recovered.checkpoint, Some(3));
+        assert_eq!((recovered.seq, recovered.replayed), (7, 4));
+        assert_eq!(recovered.checksum, expected);
+        assert_eq!(recovered.book.best_bid(), Some(&bigdec("99")));
+        assert_eq!(recovered.book.best_ask(), Some(&bigdec("100")));
+
+        // a book diverging from the expected one is reported
+        let wrong = Recovery::new(btc, store.clone()).expect_checksum(!expected);
+        let err = wrong.run(journal.clone(), index.clone()).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // without checkpoints the whole journal is replayed
+        assert_eq!(store.prune(0).unwrap(), 2);
+        let recovered = Recovery::new(btc, store).run(journal, index).unwrap();
+        assert_eq!(recovered.checkpoint, None);
+        assert_eq!(recovered.replayed, 7);
+        assert_eq!(recovered.checksum, expected);
+    }
+    #[test]
+    fn journal_without_index() {
+        let btc = (Asset::BTC, Asset::USD);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("journal");
+        let writer = Arc::new(Mutex::new(JournalWriter::new(
+            File::create(&path).unwrap(),
+            2,
+        )));
+        let mut book = Orderbook::new(btc.0, btc.1);
+        book.set_journal(writer.clone());
+
+        let mut checksums = vec![];
+        for price in ["101", "102", "103", "104", "105"] {
+            book.process_order(limit(OrderSide::Ask, price, "1"));
+            checksums.push(book_checksum(&book));
+        }
+        // crashed: no index, the last request never left the frame buffer
+        drop(book);
+        drop(writer);
+
+        let recovery = Recovery::new(btc, CheckpointStore::new(dir.path().join("checkpoints")));
+        let recovered = recovery.run_scanned(File::open(&path).unwrap()).unwrap();
+        assert_eq!((recovered.seq, recovered.replayed), (4, 4));
+        assert_eq!(recovered.checksum, checksums[3]);
+
+        // the second frame torn by the crash
+        let len = fs::metadata(&path).unwrap().len();
+        let journal = fs::OpenOptions::new().write(true).open(&path).unwrap();
+        journal.set_len(len - 3).unwrap();
+        let recovered = recovery.run_scanned(File::open(&path).unwrap()).unwrap();
+        assert_eq!((recovered.seq, recovered.replayed), (2, 2));
+        assert_eq!(recovered.checksum, checksums[1]);
+    }
+}
@@ -0,0 +1,565 @@
+use bigdecimal::{BigDecimal, Zero};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::{self, Debug};
+use std::time::SystemTime;
+
+use paper_core::guid::dark_pool::MidpointBook;
+use paper_core::guid::domain::OrderSide;
+use paper_core::guid::orderbook::{OrderProcessingResult, Orderbook};
+
+use crate::capture::{CaptureRecord, DepthRecorder};
+
+/// Levels per side covered by the checksum
+pub const CHECKSUM_DEPTH: usize = 10;
+
+/// Single message of the market data feed.
+///
+/// Carries what one request did to the book, or with `snapshot` set every
+/// level of the book, followed by a checksum of the book afterwards.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeedMessage {
+    /// consecutive from 1, a snapshot repeats the number of the last update
+    pub seq: u64,
+    #[serde(default)]
+    pub snapshot: bool,
+    pub records: Vec<CaptureRecord>,
+    /// CRC-32 of the top levels, see `checksum`
+    pub checksum: u32,
+    /// visible and hidden volume of each side, internal snapshots only
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub liquidity: Vec<SideLiquidity>,
+    /// number of the last book event the message covers, see
+    /// `Orderbook::event_seq`; a snapshot repeats the one of the last update
+    #[serde(default)]
+    pub event_seq: u64,
+    /// book events the message covers, none for a snapshot
+    #[serde(default)]
+    pub events: u64,
+}
+
+impl FeedMessage {
+    /// Number the first event of the message would have. It follows the
+    /// `event_seq` of the previous message unless the book emitted events
+    /// that never made it to the feed. None for a malformed message covering
+    /// more events than were numbered.
+    pub fn first_event_seq(&self) -> Option<u64> {
+        (self.event_seq + 1).checked_sub(self.events)
+    }
+}
+
+/// Displayed versus total volume of one side of the book
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SideLiquidity {
+    pub side: OrderSide,
+    /// sum of the published levels
+    pub visible: BigDecimal,
+    /// resting without being displayed, e.g. in the dark book
+    pub hidden: BigDecimal,
+}
+
+/// Turns processing results of a book into feed messages
+#[derive(Debug, Default)]
+pub struct FeedPublisher {
+    recorder: DepthRecorder,
+    seq: u64,
+    event_seq: u64,
+}
+
+impl FeedPublisher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Message for the request behind `results`, even if it left no trace
+    pub fn publish<Asset>(
+        &mut self,
+        book: &Orderbook<Asset>,
+        results: &OrderProcessingResult<Asset>,
+        ts: SystemTime,
+    ) -> FeedMessage
+    where
+        Asset: Debug + Clone + Copy + Eq,
+    {
+        self.seq += 1;
+        self.event_seq = book.event_seq();
+        FeedMessage {
+            seq: self.seq,
+            snapshot: false,
+            records: self.recorder.record(book, results, ts),
+            checksum: checksum(
+                self.recorder.levels(OrderSide::Bid),
+                self.recorder.levels(OrderSide::Ask),
+            ),
+            liquidity: vec![],
+            event_seq: self.event_seq,
+            events: results.len() as u64,
+        }
+    }
+
+    /// Full book as of the last message, for replicas joining or resyncing
+    pub fn snapshot(&self, ts: SystemTime) -> FeedMessage {
+        let mut records = vec![];
+        for side in [OrderSide::Bid, OrderSide::Ask] {
+            for (price, qty) in self.recorder.levels(side) {
+                records.push(CaptureRecord::Depth {
+                    side,
+                    price: price.clone(),
+                    qty: qty.clone(),
+                    ts,
+                });
+            }
+        }
+
+        FeedMessage {
+            seq: self.seq,
+            snapshot: true,
+            records,
+            checksum: checksum(
+                self.recorder.levels(OrderSide::Bid),
+                self.recorder.levels(OrderSide::Ask),
+            ),
+            liquidity: vec![],
+            event_seq: self.event_seq,
+            events: 0,
+        }
+    }
+
+    /// Snapshot for internal and surveillance subscribers, breaking down the
+    /// volume of each side into visible and hidden in the dark book. Levels
+    /// stay display-only, the public feed must keep using `snapshot`.
+    pub fn internal_snapshot<Asset>(
+        &self,
+        dark: &MidpointBook<Asset>,
+        ts: SystemTime,
+    ) -> FeedMessage
+    where
+        Asset: Debug + Clone + Copy + Eq,
+    {
+        let mut message = self.snapshot(ts);
+        message.liquidity = [OrderSide::Bid, OrderSide::Ask]
+            .iter()
+            .map(|&side| SideLiquidity {
+                side,
+                visible: self
+                    .recorder
+                    .levels(side)
+                    .values()
+                    .fold(BigDecimal::zero(), |total, qty| total + qty),
+                hidden: dark.hidden_qty(side),
+            })
+            .collect();
+        message
+    }
+}
+
+/// Why a replica could not apply a message
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplicaError {
+    Malformed(String),
+    /// messages got lost, the replica needs a snapshot
+    SequenceGap {
+        expected: u64,
+        got: u64,
+    },
+    /// replica diverged from the matcher, it needs a snapshot
+    ChecksumMismatch {
+        seq: u64,
+        expected: u32,
+        actual: u32,
+    },
+    /// updates are ignored after an error until a snapshot arrives
+    OutOfSync {
+        seq: u64,
+    },
+}
+
+impl fmt::Display for ReplicaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplicaError::Malformed(reason) => write!(f, "malformed message: {}", reason),
+            ReplicaError::SequenceGap { expected, got } => {
+                write!(f, "expected message {}, got {}", expected, got)
+            }
+            ReplicaError::ChecksumMismatch {
+                seq,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "checksum of message {} is {:08x}, book has {:08x}",
+                seq, expected, actual
+            ),
+            ReplicaError::OutOfSync { seq } => {
+                write!(f, "message {} skipped, waiting for snapshot", seq)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReplicaError {}
+
+/// Read-only copy of a book maintained from the feed alone.
+///
+/// Meant for API servers running apart from the matcher. Every message is
+/// checked to follow the previous one and to leave the book with the
+/// checksum the matcher computed. After a failed check the replica stops
+/// applying updates until it gets a snapshot, so it never serves a book
+/// known to be wrong. Duplicates of already applied messages are ignored.
+#[derive(Debug, Clone, Default)]
+pub struct ReplicaBook {
+    bids: BTreeMap<BigDecimal, BigDecimal>,
+    asks: BTreeMap<BigDecimal, BigDecimal>,
+    seq: u64,
+    in_sync: bool,
+    last_trade: Option<CaptureRecord>,
+}
+
+impl ReplicaBook {
+    /// Empty replica expecting the feed from its first message
+    pub fn new() -> Self {
+        ReplicaBook {
+            in_sync: true,
+            ..Default::default()
+        }
+    }
+
+    /// Replica joining a running feed, waiting for a snapshot
+    pub fn awaiting_snapshot() -> Self {
+        Self::default()
+    }
+
+    /// Apply a JSON encoded message
+    pub fn apply_json(&mut self, message: &[u8]) -> Result<(), ReplicaError> {
+        let message: FeedMessage = serde_json::from_slice(message)
+            .map_err(|err| ReplicaError::Malformed(err.to_string()))?;
+        self.apply(&message)
+    }
+
+    pub fn apply(&mut self, message: &FeedMessage) -> Result<(), ReplicaError> {
+        if message.first_event_seq().is_none() {
+            return Err(ReplicaError::Malformed(format!(
+                "{} events up to event {}",
+                message.events, message.event_seq
+            )));
+        }
+        if message.snapshot {
+            self.bids.clear();
+            self.asks.clear();
+        } else if !self.in_sync {
+            return Err(ReplicaError::OutOfSync { seq: message.seq });
+        } else if message.seq <= self.seq {
+            return Ok(());
+        } else if message.seq != self.seq + 1 {
+            self.in_sync = false;
+            return Err(ReplicaError::SequenceGap {
+                expected: self.seq + 1,
+                got: message.seq,
+            });
+        }
+
+        for record in &message.records {
+            match record {
+                CaptureRecord::Depth {
+                    side, price, qty, ..
+                } => {
+                    let levels = match side {
+                        OrderSide::Bid => &mut self.bids,
+                        OrderSide::Ask => &mut self.asks,
+                    };
+                    if qty.is_zero() {
+                        levels.remove(price);
+                    } else {
+                        levels.insert(price.clone(), qty.clone());
+                    }
+                }
+                CaptureRecord::Trade { .. } => self.last_trade = Some(record.clone()),
+            }
+        }
+        self.seq = message.seq;
+
+        let actual = checksum(&self.bids, &self.asks);
+        if actual != message.checksum {
+            self.in_sync = false;
+            return Err(ReplicaError::ChecksumMismatch {
+                seq: message.seq,
+                expected: message.checksum,
+                actual,
+            });
+        }
+        self.in_sync = true;
+        Ok(())
+    }
+
+    /// Sequence number of the last applied message
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    pub fn is_in_sync(&self) -> bool {
+        self.in_sync
+    }
+
+    /// Best price and its total quantity
+    pub fn best(&self, side: OrderSide) -> Option<(&BigDecimal, &BigDecimal)> {
+        match side {
+            OrderSide::Bid => self.bids.iter().next_back(),
+            OrderSide::Ask => self.asks.iter().next(),
+        }
+    }
+
+    /// Get current spread as a tuple: (bid, ask)
+    pub fn spread(&self) -> Option<(BigDecimal, BigDecimal)> {
+        let (bid, _) = self.best(OrderSide::Bid)?;
+        let (ask, _) = self.best(OrderSide::Ask)?;
+        Some((bid.clone(), ask.clone()))
+    }
+
+    /// Up to `levels` best price levels of the side as (price, qty)
+    pub fn depth(&self, side: OrderSide, levels: usize) -> Vec<(BigDecimal, BigDecimal)> {
+        let level = |(price, qty): (&BigDecimal, &BigDecimal)| (price.clone(), qty.clone());
+        match side {
+            OrderSide::Bid => self.bids.iter().rev().take(levels).map(level).collect(),
+            OrderSide::Ask => self.asks.iter().take(levels).map(level).collect(),
+        }
+    }
+
+    /// Total quantity resting at the price
+    pub fn level(&self, side: OrderSide, price: &BigDecimal) -> Option<&BigDecimal> {
+        match side {
+            OrderSide::Bid => self.bids.get(price),
+            OrderSide::Ask => self.asks.get(price),
+        }
+    }
+
+    pub fn last_trade(&self) -> Option<&CaptureRecord> {
+        self.last_trade.as_ref()
+    }
+}
+
+/// CRC-32 of the best `CHECKSUM_DEPTH` levels of both sides.
+///
+/// Levels are written best first as `price:qty` with decimals normalized,
+/// bids then asks, each level followed by a newline.
+pub fn checksum(
+    bids: &BTreeMap<BigDecimal, BigDecimal>,
+    asks: &BTreeMap<BigDecimal, BigDecimal>,
+) -> u32 {
+    let levels = bids
+        .iter()
+        .rev()
+        .take(CHECKSUM_DEPTH)
+        .chain(asks.iter().take(CHECKSUM_DEPTH));
+
+    let mut crc = !0u32;
+    for (price, qty) in levels {
+        let line = format!("{}:{}\n", price.normalized(), qty.normalized());
+        crc = crc32_update(crc, line.as_bytes());
+    }
+    !crc
+}
+
+/* Helpers */
+
+pub(crate) fn crc32_update(mut crc: u32, bytes: &[u8]) -> u32 {
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use paper_core::guid::orders::{self, OrderRequest};
+    use std::str::FromStr;
+
+    #[derive(PartialEq, Eq, Debug, Copy, Clone)]
+    pub enum Asset {
+        USD,
+        BTC,
+    }
+
+    fn bigdec(num: &str) -> BigDecimal {
+        BigDecimal::from_str(num).unwrap()
+    }
+
+    fn limit(side: OrderSide, price: &str, qty: &str) -> orders::OrderRequest<Asset> {
+        orders::new_limit_order_request(
+            Asset::BTC,
+            Asset::USD,
+            side,
+            bigdec(price),
+            bigdec(qty),
+            SystemTime::now(),
+        )
+    }
+
+    #[test]
+    fn internal_snapshot_hidden_volume() {
+        let mut book = Orderbook::new(Asset::BTC, Asset::USD);
+        let mut dark = MidpointBook::new(Asset::BTC, Asset::USD);
+        let mut publisher = FeedPublisher::new();
+        for order in [
+            limit(OrderSide::Ask, "101", "2"),
+            limit(OrderSide::Bid, "99", "4"),
+        ] {
+            let results = book.process_order(order);
+            publisher.publish(&book, &results, SystemTime::now());
+        }
+        let pegged = |qty: &str| -> OrderRequest<Asset> {
+            orders::new_market_order_request(
+                Asset::BTC,
+                Asset::USD,
+                OrderSide::Bid,
+                bigdec(qty),
+                SystemTime::now(),
+            )
+        };
+        dark.process_order(pegged("3"), &mut book);
+        dark.process_order(pegged("1.5"), &mut book);
+
+        let internal = publisher.internal_snapshot(&dark, SystemTime::now());
+        assert_eq!(
+            internal.liquidity,
+            vec![
+                SideLiquidity {
+                    side: OrderSide::Bid,
+                    visible: bigdec("4"),
+                    hidden: bigdec("4.5"),
+                },
+                SideLiquidity {
+                    side: OrderSide::Ask,
+                    visible: bigdec("2"),
+                    hidden: bigdec("0"),
+                },
+            ]
+        );
+
+        // the public snapshot carries the same levels and nothing hidden
+        let public = publisher.snapshot(SystemTime::now());
+        assert_eq!(public.records.len(), internal.records.len());
+        let json = serde_json::to_string(&public).unwrap();
+        assert!(!json.contains("liquidity"));
+        let mut replica = ReplicaBook::awaiting_snapshot();
+        replica.apply(&internal).unwrap();
+        assert_eq!(replica.spread(), Some((bigdec("99"), bigdec("101"))));
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(!crc32_update(!0, b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn replica_follows_feed() {
+        let mut book = Orderbook::new(Asset::BTC, Asset::USD);
+        let mut publisher = FeedPublisher::new();
+        let mut feed = vec![];
+        for order in [
+            limit(OrderSide::Ask, "101", "2"),
+            limit(OrderSide::Ask, "102", "1"),
+            limit(OrderSide::Bid, "99", "4"),
+            limit(OrderSide::Bid, "101", "3"),
+        ] {
+            let results = book.process_order(order);
+            let message = publisher.publish(&book, &results, SystemTime::now());
+            feed.push(serde_json::to_vec(&message).unwrap());
+        }
+
+        let mut replica = ReplicaBook::new();
+        for message in &feed {
+            replica.apply_json(message).unwrap();
+        }
+        // duplicates are harmless
+        replica.apply_json(&feed[3]).unwrap();
+        assert_eq!(replica.seq(), 4);
+        assert_eq!(replica.spread(), Some((bigdec("101"), bigdec("102"))));
+        assert_eq!(
+            replica.depth(OrderSide::Bid, 5),
+            vec![(bigdec("101"), bigdec("1")), (bigdec("99"), bigdec("4"))]
+        );
+        match replica.last_trade() {
+            Some(CaptureRecord::Trade { qty, .. }) => assert_eq!(*qty, bigdec("2")),
+            _ => panic!("unexpected trade"),
+        }
+
+        // a replica missing a message waits for the snapshot
+        let mut late = ReplicaBook::new();
+        late.apply_json(&feed[0]).unwrap();
+        assert_eq!(
+            late.apply_json(&feed[2]),
+            Err(ReplicaError::SequenceGap {
+                expected: 2,
+                got: 3
+            })
+        );
+        assert!(matches!(
+            late.apply_json(&feed[3]),
+            Err(ReplicaError::OutOfSync { .. })
+        ));
+        late.apply(&publisher.snapshot(SystemTime::now())).unwrap();
+        assert!(late.is_in_sync());
+        assert_eq!(
+            late.depth(OrderSide::Bid, 5),
+            replica.depth(OrderSide::Bid, 5)
+        );
+
+        // tampered levels fail the checksum
+        let mut message: FeedMessage = serde_json::from_slice(&feed[0]).unwrap();
+        if let CaptureRecord::Depth { qty, .. } = &mut message.records[0] {
+            *qty = bigdec("3");
+        }
+        assert!(matches!(
+            ReplicaBook::new().apply(&message),
+            Err(ReplicaError::ChecksumMismatch { seq: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn event_gap_on_feed() {
+        let mut book = Orderbook::new(Asset::BTC, Asset::USD);
+        let mut publisher = FeedPublisher::new();
+        let mut feed = vec![];
+        let mut publish = |book: &mut Orderbook<Asset>, order| {
+            let results = book.process_order(order);
+            let message = publisher.publish(book, &results, SystemTime::now());
+            feed.push(serde_json::to_vec(&message).unwrap());
+        };
+        publish(&mut book, limit(OrderSide::Ask, "101", "2"));
+        publish(&mut book, limit(OrderSide::Bid, "101", "1"));
+        // events the book emits outside of the feed
+        book.process_order(limit(OrderSide::Bid, "-1", "1"));
+        publish(&mut book, limit(OrderSide::Bid, "99", "1"));
+
+        // a consumer follows the event numbers across messages
+        let mut last_event = 0;
+        let mut missed = vec![];
+        for message in &feed {
+            let message: FeedMessage = serde_json::from_slice(message).unwrap();
+            let first = message.first_event_seq().unwrap();
+            if first != last_event + 1 {
+                missed.push(last_event + 1..first);
+            }
+            last_event = message.event_seq;
+        }
+        assert_eq!(missed, vec![5..6]);
+        assert_eq!(last_event, book.event_seq());
+        // the snapshot picks up where the last update left off
+        let snapshot = publisher.snapshot(SystemTime::now());
+        assert_eq!((snapshot.event_seq, snapshot.events), (last_event, 0));
+
+        // more events than event numbers is refused, not computed
+        let mut message: FeedMessage = serde_json::from_slice(&feed[0]).unwrap();
+        message.events = message.event_seq + 2;
+        let message = serde_json::to_vec(&message).unwrap();
+        assert!(matches!(
+            ReplicaBook::new().apply_json(&message),
+            Err(ReplicaError::Malformed(_))
+        ));
+    }
+}
@@ -0,0 +1,555 @@
+use bigdecimal::{BigDecimal, Zero};
+use memmap2::Mmap;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::convert::{TryFrom, TryInto};
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::SystemTime;
+
+use paper_core::guid::domain::{Order, OrderSide};
+use paper_core::guid::order_queues::OrderQueue;
+use paper_core::guid::orderbook::{OrderProcessingResult, Orderbook};
+use paper_core::guid::orders::OrderRequest;
+
+const MAGIC: &[u8; 4] = b"PSNP";
+const VERSION: u32 = 1;
+/// magic, version and directory length
+const HEADER_LEN: usize = 16;
+
+/// Resting order together with its place in the queue
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SnapshotOrder<Asset>
+where
+    Asset: Debug + Clone,
+{
+    order: Order<Asset>,
+    ts: SystemTime,
+    priority: bool,
+}
+
+/// Location of a price level within the snapshot
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LevelEntry {
+    pub price: BigDecimal,
+    pub orders: u32,
+    pub qty: BigDecimal,
+    /// byte offset of the level, relative to the end of the directory
+    pub offset: u64,
+    pub len: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Directory<Asset> {
+    order_asset: Asset,
+    price_asset: Asset,
+    /// levels of each side, best first
+    bids: Vec<LevelEntry>,
+    asks: Vec<LevelEntry>,
+}
+
+/// Write resting orders of the book as a snapshot.
+///
+/// Layout is a 16 byte header (`PSNP`, u32 version, u64 directory length,
+/// all little-endian), the JSON directory of price levels and the levels
+/// themselves, each a JSON array of orders in queue priority. The directory
+/// lets readers materialize levels one by one, nearest to the touch first.
+///
+/// Only resting orders are stored, book settings are left to the caller.
+pub fn write_snapshot<Asset, W>(book: &Orderbook<Asset>, mut writer: W) -> io::Result<()>
+where
+    Asset: Debug + Clone + Copy + Eq + Serialize,
+    W: Write,
+{
+    let mut payload = vec![];
+    let bids = write_levels(&book.bid_queue, &mut payload)?;
+    let asks = write_levels(&book.ask_queue, &mut payload)?;
+    let directory = serde_json::to_vec(&Directory {
+        order_asset: book.order_asset,
+        price_asset: book.price_asset,
+        bids,
+        asks,
+    })?;
+
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&(directory.len() as u64).to_le_bytes())?;
+    writer.write_all(&directory)?;
+    writer.write_all(&payload)?;
+    writer.flush()
+}
+
+/// Snapshot parsed up to its directory, levels are decoded on demand
+pub struct SnapshotReader<B, Asset>
+where
+    B: AsRef<[u8]>,
+{
+    data: B,
+    payload_start: usize,
+    directory: Directory<Asset>,
+}
+
+/// Memory-map a snapshot file, only the directory is read up front
+pub fn open_mapped<Asset, P>(path: P) -> io::Result<SnapshotReader<Mmap, Asset>>
+where
+    Asset: Debug + Clone + Copy + Eq + DeserializeOwned,
+    P: AsRef<Path>,
+{
+    let file = File::open(path)?;
+    // SAFETY: snapshots are written once and never modified in place
+    let data = unsafe { Mmap::map(&file)? };
+    SnapshotReader::new(data)
+}
+
+impl<B, Asset> SnapshotReader<B, Asset>
+where
+    B: AsRef<[u8]>,
+    Asset: Debug + Clone + Copy + Eq + DeserializeOwned,
+{
+    pub fn new(data: B) -> io::Result<Self> {
+        let bytes = data.as_ref();
+        if bytes.len() < HEADER_LEN || &bytes[..4] != MAGIC {
+            return Err(invalid("not a snapshot"));
+        }
+        let version = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+        if version != VERSION {
+            return Err(invalid("unsupported snapshot version"));
+        }
+        let directory_len = u64::from_le_bytes(bytes[8..16].try_into().unwrap());
+        // lengths come from the file, any of them may be garbage
+        let payload_start = usize::try_from(directory_len)
+            .ok()
+            .and_then(|len| HEADER_LEN.checked_add(len))
+            .filter(|start| *start <= bytes.len())
+            .ok_or_else(|| invalid("truncated snapshot"))?;
+        let directory = serde_json::from_slice(&bytes[HEADER_LEN..payload_start])?;
+
+        Ok(SnapshotReader {
+            data,
+            payload_start,
+            directory,
+        })
+    }
+
+    /// Price levels of the side, best first
+    pub fn levels(&self, side: OrderSide) -> &[LevelEntry] {
+        match side {
+            OrderSide::Bid => &self.directory.bids,
+            OrderSide::Ask => &self.directory.asks,
+        }
+    }
+
+    /// Build the whole book at once
+    pub fn load(&self) -> io::Result<Orderbook<Asset>> {
+        let mut book = Orderbook::new(self.directory.order_asset, self.directory.price_asset);
+        for side in [OrderSide::Bid, OrderSide::Ask] {
+            for level in 0..self.levels(side).len() {
+                self.materialize(&mut book, side, level)?;
+            }
+        }
+        Ok(book)
+    }
+
+    /// Insert orders of a single level into the book
+    fn materialize(
+        &self,
+        book: &mut Orderbook<Asset>,
+        side: OrderSide,
+        level: usize,
+    ) -> io::Result<()> {
+        let entry = &self.levels(side)[level];
+        let start = usize::try_from(entry.offset)
+            .ok()
+            .and_then(|offset| self.payload_start.checked_add(offset));
+        let end = start
+            .zip(usize::try_from(entry.len).ok())
+            .and_then(|(start, len)| start.checked_add(len));
+        let bytes = start
+            .zip(end)
+            .and_then(|(start, end)| self.data.as_ref().get(start..end))
+            .ok_or_else(|| invalid("truncated snapshot"))?;
+
+        let orders: Vec<SnapshotOrder<Asset>> = serde_json::from_slice(bytes)?;
+        let queue = match side {
+            OrderSide::Bid => &mut book.bid_queue,
+            OrderSide::Ask => &mut book.ask_queue,
+        };
+        for stored in orders {
+            queue.insert_with_priority(
+                stored.order.order_id,
+                stored.order.price.clone(),
+                stored.ts,
+                stored.priority,
+                stored.order,
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Book restored from a snapshot, with far levels materialized lazily.
+///
+/// Only the `eager_levels` nearest to the touch are decoded at start. Before
+/// each request, levels it could reach are brought in: opposite levels up to
+/// a limit price, enough opposite quantity for a market order, or the whole
+/// side when cancelling or amending an order not found yet. Once stop orders
+/// are involved the whole book is brought in, triggered stops can reach any
+/// level. Depth statistics of the inner book cover materialized levels only.
+pub struct LazyBook<B, Asset>
+where
+    B: AsRef<[u8]>,
+    Asset: Debug + Clone + Copy + Eq,
+{
+    book: Orderbook<Asset>,
+    snapshot: SnapshotReader<B, Asset>,
+    /// next level of each side to materialize
+    next_bid: usize,
+    next_ask: usize,
+}
+
+impl<B, Asset> LazyBook<B, Asset>
+where
+    B: AsRef<[u8]>,
+    Asset: Debug + Clone + Copy + Eq + DeserializeOwned,
+{
+    pub fn new(snapshot: SnapshotReader<B, Asset>, eager_levels: usize) -> io::Result<Self> {
+        let book = Orderbook::new(
+            snapshot.directory.order_asset,
+            snapshot.directory.price_asset,
+        );
+        let mut lazy = LazyBook {
+            book,
+            snapshot,
+            next_bid: 0,
+            next_ask: 0,
+        };
+        lazy.materialize_levels(OrderSide::Bid, eager_levels)?;
+        lazy.materialize_levels(OrderSide::Ask, eager_levels)?;
+        Ok(lazy)
+    }
+
+    pub fn book(&self) -> &Orderbook<Asset> {
+        &self.book
+    }
+
+    /// Levels of the side still waiting in the snapshot
+    pub fn pending_levels(&self, side: OrderSide) -> usize {
+        self.snapshot.levels(side).len() - self.next_level(side)
+    }
+
+    pub fn process_order(
+        &mut self,
+        order: OrderRequest<Asset>,
+    ) -> io::Result<OrderProcessingResult<Asset>> {
+        if self.book.stop_count() > 0 {
+            return self.process_materialized(order);
+        }
+        match &order {
+            OrderRequest::NewStopOrder { .. } | OrderRequest::NewStopLimitOrder { .. } => {
+                return self.process_materialized(order);
+            }
+            OrderRequest::NewMarketOrder { side, qty, .. } => {
+                let opposite = side.opposite();
+                let mut available = queue_qty(self.queue(opposite));
+                while available < *qty && self.pending_levels(opposite) > 0 {
+                    available += &self.snapshot.levels(opposite)[self.next_level(opposite)].qty;
+                    self.materialize_levels(opposite, 1)?;
+                }
+            }
+            OrderRequest::NewLimitOrder { side, price, .. } => {
+                let opposite = side.opposite();
+                while self.pending_levels(opposite) > 0 {
+                    let level = &self.snapshot.levels(opposite)[self.next_level(opposite)];
+                    let crosses = match side {
+                        OrderSide::Bid => level.price <= *price,
+                        OrderSide::Ask => level.price >= *price,
+                    };
+                    if !crosses {
+                        break;
+                    }
+                    self.materialize_levels(opposite, 1)?;
+                }
+            }
+            OrderRequest::AmendOrder { id, side, .. }
+            | OrderRequest::CancelOrder {
+                id,
+                side: Some(side),
+            } => {
+                if self.queue(*side).get(*id).is_none() {
+                    self.materialize_levels(*side, self.pending_levels(*side))?;
+                }
+            }
+            OrderRequest::CancelOrder { id, side: None } => {
+                // the order may be on a level of either side still pending
+                for side in [OrderSide::Bid, OrderSide::Ask] {
+                    if self.queue(OrderSide::Bid).get(*id).is_some()
+                        || self.queue(OrderSide::Ask).get(*id).is_some()
+                    {
+                        break;
+                    }
+                    self.materialize_levels(side, self.pending_levels(side))?;
+                }
+            }
+        }
+        Ok(self.book.process_order(order))
+    }
+
+    /// Materialize everything left and hand over the plain book
+    pub fn into_book(mut self) -> io::Result<Orderbook<Asset>> {
+        for side in [OrderSide::Bid, OrderSide::Ask] {
+            self.materialize_levels(side, self.pending_levels(side))?;
+        }
+        Ok(self.book)
+    }
+
+    /* Helpers */
+
+    /// Materialize everything left, then process the request
+    fn process_materialized(
+        &mut self,
+        order: OrderRequest<Asset>,
+    ) -> io::Result<OrderProcessingResult<Asset>> {
+        for side in [OrderSide::Bid, OrderSide::Ask] {
+            self.materialize_levels(side, self.pending_levels(side))?;
+        }
+        Ok(self.book.process_order(order))
+    }
+
+    fn next_level(&self, side: OrderSide) -> usize {
+        match side {
+            OrderSide::Bid => self.next_bid,
+            OrderSide::Ask => self.next_ask,
+        }
+    }
+
+    fn queue(&self, side: OrderSide) -> &OrderQueue<Order<Asset>> {
+        match side {
+            OrderSide::Bid => &self.book.bid_queue,
+            OrderSide::Ask => &self.book.ask_queue,
+        }
+    }
+
+    fn materialize_levels(&mut self, side: OrderSide, count: usize) -> io::Result<()> {
+        let first = self.next_level(side);
+        let last = (first + count).min(self.snapshot.levels(side).len());
+        for level in first..last {
+            self.snapshot.materialize(&mut self.book, side, level)?;
+        }
+        match side {
+            OrderSide::Bid => self.next_bid = last,
+            OrderSide::Ask => self.next_ask = last,
+        }
+        Ok(())
+    }
+}
+
+/* Helpers */
+
+fn write_levels<Asset>(
+    queue: &OrderQueue<Order<Asset>>,
+    payload: &mut Vec<u8>,
+) -> io::Result<Vec<LevelEntry>>
+where
+    Asset: Debug + Clone + Copy + Eq + Serialize,
+{
+    let mut levels: Vec<(BigDecimal, Vec<SnapshotOrder<Asset>>)> = vec![];
+    for (order, ts, priority) in queue.ranked() {
+        let stored = SnapshotOrder {
+            order: order.clone(),
+            ts,
+            priority,
+        };
+        match levels.last_mut() {
+            Some((price, orders)) if *price == order.price => orders.push(stored),
+            _ => levels.push((order.price.clone(), vec![stored])),
+        }
+    }
+
+    let mut entries = vec![];
+    for (price, orders) in levels {
+        let offset = payload.len() as u64;
+        serde_json::to_writer(&mut *payload, &orders)?;
+        entries.push(LevelEntry {
+            price,
+            orders: orders.len() as u32,
+            qty: orders
+                .iter()
+                .fold(BigDecimal::zero(), |qty, stored| qty + &stored.order.qty),
+            offset,
+            len: payload.len() as u64 - offset,
+        });
+    }
+    Ok(entries)
+}
+
+fn queue_qty<Asset>(queue: &OrderQueue<Order<Asset>>) -> BigDecimal
+where
+    Asset: Debug + Clone,
+{
+    queue
+        .iter()
+        .fold(BigDecimal::zero(), |qty, order| qty + &order.qty)
+}
+
+fn invalid(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use paper_core::guid::orderbook::Success;
+    use paper_core::guid::orders;
+    use std::str::FromStr;
+
+    #[derive(PartialEq, Eq, Debug, Copy, Clone, Serialize, Deserialize)]
+    pub enum Asset {
+        USD,
+        BTC,
+    }
+
+    fn bigdec(num: &str) -> BigDecimal {
+        BigDecimal::from_str(num).unwrap()
+    }
+
+    fn limit(side: OrderSide, price: &str, qty: &str) -> OrderRequest<Asset> {
+        orders::new_limit_order_request(
+            Asset::BTC,
+            Asset::USD,
+            side,
+            bigdec(price),
+            bigdec(qty),
+            SystemTime::now(),
+        )
+    }
+
+    fn sample_book() -> Orderbook<Asset> {
+        let mut book = Orderbook::new(Asset::BTC, Asset::USD);
+        for price in ["101", "102", "103", "104"] {
+            book.process_order(limit(OrderSide::Ask, price, "1"));
+        }
+        for price in ["99", "98", "97"] {
+            book.process_order(limit(OrderSide::Bid, price, "1"));
+        }
+        book
+    }
+
+    #[test]
+    fn eager_load() {
+        let mut book = sample_book();
+        let first = limit(OrderSide::Ask, "101", "2");
+        let first_id = first.order_id();
+        book.process_order(first);
+
+        let mut snapshot = vec![];
+        write_snapshot(&book, &mut snapshot).unwrap();
+        let reader = SnapshotReader::<_, Asset>::new(snapshot).unwrap();
+        assert_eq!(reader.levels(OrderSide::Ask).len(), 4);
+        assert_eq!(reader.levels(OrderSide::Ask)[0].qty, bigdec("3"));
+        assert_eq!(reader.levels(OrderSide::Bid)[0].price, bigdec("99"));
+
+        let restored = reader.load().unwrap();
+        assert_eq!(restored.ask_queue.len(), 5);
+        assert_eq!(restored.bid_queue.len(), 3);
+        // time priority survives the round trip
+        assert_ne!(restored.ask_queue.peek().unwrap().order_id, first_id);
+        assert_eq!(restored.ask_queue.peek().unwrap().price, bigdec("101"));
+    }
+
+    #[test]
+    fn corrupt_lengths() {
+        let mut header = MAGIC.to_vec();
+        header.extend_from_slice(&VERSION.to_le_bytes());
+        header.extend_from_slice(&u64::MAX.to_le_bytes());
+        header.extend_from_slice(b"{}");
+        let err = SnapshotReader::<_, Asset>::new(header).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // a level said to lie past the end of the snapshot
+        let directory = Directory {
+            order_asset: Asset::BTC,
+            price_asset: Asset::USD,
+            bids: vec![LevelEntry {
+                price: bigdec("99"),
+                orders: 1,
+                qty: bigdec("1"),
+                offset: u64::MAX,
+                len: 2,
+            }],
+            asks: vec![],
+        };
+        let directory = serde_json::to_vec(&directory).unwrap();
+        let mut snapshot = MAGIC.to_vec();
+        snapshot.extend_from_slice(&VERSION.to_le_bytes());
+        snapshot.extend_from_slice(&(directory.len() as u64).to_le_bytes());
+        snapshot.extend_from_slice(&directory);
+        let reader = SnapshotReader::<_, Asset>::new(snapshot).unwrap();
+        assert_eq!(
+            reader.load().err().unwrap().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn lazy_levels() {
+        let book = sample_book();
+        let far = book
+            .ask_queue
+            .iter()
+            .find(|order| order.price == bigdec("104"))
+            .unwrap()
+            .order_id;
+        let file = tempfile::NamedTempFile::new().unwrap();
+        write_snapshot(&book, file.as_file()).unwrap();
+
+        let reader = open_mapped::<Asset, _>(file.path()).unwrap();
+        let mut lazy = LazyBook::new(reader, 1).unwrap();
+        assert_eq!(lazy.book().ask_queue.len(), 1);
+        assert_eq!(lazy.pending_levels(OrderSide::Ask), 3);
+
+        // limit bid crossing two levels brings in the second one
+        let results = lazy
+            .process_order(limit(OrderSide::Bid, "102", "2"))
+            .unwrap();
+        let fills = results
+            .iter()
+            .filter(|result| {
+                matches!(
+                    result,
+                    Ok(Success::Filled { .. }) | Ok(Success::PartiallyFilled { .. })
+                )
+            })
+            .count();
+        assert_eq!(fills, 4);
+        assert!(lazy.book().ask_queue.is_empty());
+        assert_eq!(lazy.pending_levels(OrderSide::Ask), 2);
+
+        // market order takes just enough levels
+        lazy.process_order(orders::new_market_order_request(
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Bid,
+            bigdec("0.5"),
+            SystemTime::now(),
+        ))
+        .unwrap();
+        assert_eq!(lazy.pending_levels(OrderSide::Ask), 1);
+
+        // cancelling a far order materializes its side
+        match lazy
+            .process_order(orders::limit_order_cancel_request(far, OrderSide::Ask))
+            .unwrap()
+            .as_slice()
+        {
+            [Ok(Success::Cancelled { order_id, .. })] => assert_eq!(*order_id, far),
+            _ => panic!("unexpected events"),
+        }
+        assert_eq!(lazy.pending_levels(OrderSide::Ask), 0);
+        assert_eq!(lazy.pending_levels(OrderSide::Bid), 2);
+
+        let book = lazy.into_book().unwrap();
+        assert_eq!(book.bid_queue.len(), 3);
+    }
+}